@@ -0,0 +1,484 @@
+use crate::{
+    config::AccountingConfig,
+    entity::{
+        accounting_exports, bills, customers, disputes, order_items, orders,
+        prelude::{
+            AccountingExports, Bills, Customers, Disputes, OrderItems, Orders, Products,
+            RefundItems, Refunds,
+        },
+        products, refund_items, refunds,
+    },
+    error::AppError,
+    ids::TenantId,
+    jobs::JobTable,
+    money::round_amount,
+    payment_gateway::{DisputeStatus, RefundStatus, SETTLED_PAYMENT_STATUSES},
+    storage::Storage,
+    tenancy::TenantScope,
+};
+use async_graphql::Enum;
+use chrono::{Days, NaiveDate};
+use sea_orm::{
+    prelude::Decimal, ActiveValue::Set, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter,
+    QueryOrder,
+};
+use std::{collections::HashMap, sync::Arc};
+
+const EXPORT_POLL_SECONDS: u64 = 10;
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+pub enum AccountingFormat {
+    Datev,
+    Quickbooks,
+}
+
+impl AccountingFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AccountingFormat::Datev => "DATEV",
+            AccountingFormat::Quickbooks => "QUICKBOOKS",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "DATEV" => Some(AccountingFormat::Datev),
+            "QUICKBOOKS" => Some(AccountingFormat::Quickbooks),
+            _ => None,
+        }
+    }
+}
+
+enum EntryKind {
+    Sale,
+    Refund,
+}
+
+struct AccountingEntry {
+    date: NaiveDate,
+    kind: EntryKind,
+    document: String,
+    counterparty: String,
+    net: Decimal,
+    tax: Decimal,
+    gross: Decimal,
+    // the order's, an order priced with regional overrides isn't in the store currency
+    currency: String,
+}
+
+// part of an order that belongs to the supplier, by line value
+async fn supplier_shares(
+    db: &DatabaseConnection,
+    supplier_id: i32,
+    order_ids: Vec<i32>,
+) -> Result<HashMap<i32, Decimal>, AppError> {
+    let items = OrderItems::find()
+        .filter(order_items::Column::OrderId.is_in(order_ids))
+        .all(db)
+        .await?;
+    let supplier_products: Vec<i32> = Products::find()
+        .filter(products::Column::SupplierId.eq(supplier_id))
+        .filter(products::Column::ProductId.is_in(items.iter().map(|item| item.product_id)))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|product| product.product_id)
+        .collect();
+
+    let mut totals: HashMap<i32, (Decimal, Decimal)> = HashMap::new();
+    for item in items {
+        let value = item.unit_price * Decimal::from(item.quantity) - item.discount_amount;
+        let (supplier_value, order_value) = totals.entry(item.order_id).or_default();
+        *order_value += value;
+        if supplier_products.contains(&item.product_id) {
+            *supplier_value += value;
+        }
+    }
+
+    Ok(totals
+        .into_iter()
+        .filter(|(_, (_, order_value))| !order_value.is_zero())
+        .map(|(order_id, (supplier_value, order_value))| (order_id, supplier_value / order_value))
+        .collect())
+}
+
+// settled orders of the tenant placed in the period plus refunds paid out and chargebacks lost in
+// it, scoped to the supplier's lines
+async fn collect_entries(
+    db: &DatabaseConnection,
+    tenant: TenantId,
+    supplier_id: Option<i32>,
+    period_start: NaiveDate,
+    period_end: NaiveDate,
+) -> Result<Vec<AccountingEntry>, AppError> {
+    let from = period_start
+        .and_hms_opt(0, 0, 0)
+        .unwrap()
+        .and_utc()
+        .fixed_offset();
+    let until = (period_end + Days::new(1))
+        .and_hms_opt(0, 0, 0)
+        .unwrap()
+        .and_utc()
+        .fixed_offset();
+
+    let placed = Orders::find()
+        .for_tenant(tenant)
+        .filter(orders::Column::Status.ne("CANCELLED"))
+        .filter(orders::Column::OrderDate.gte(from))
+        .filter(orders::Column::OrderDate.lt(until))
+        .order_by_asc(orders::Column::OrderDate)
+        .all(db)
+        .await?;
+    let settled: Vec<i32> = Bills::find()
+        .filter(bills::Column::OrderId.is_in(placed.iter().map(|order| order.order_id)))
+        // the money was taken, later chargebacks show up as refunds
+        .filter(bills::Column::PaymentStatus.is_in(SETTLED_PAYMENT_STATUSES))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|bill| bill.order_id)
+        .collect();
+    let sold: Vec<orders::Model> = placed
+        .into_iter()
+        .filter(|order| settled.contains(&order.order_id))
+        .collect();
+
+    let paid_back = Refunds::find()
+        .filter(refunds::Column::Status.eq(RefundStatus::Succeeded.as_str()))
+        .filter(refunds::Column::RefundedAt.gte(from))
+        .filter(refunds::Column::RefundedAt.lt(until))
+        .order_by_asc(refunds::Column::RefundedAt)
+        .all(db)
+        .await?;
+    let charged_back = Disputes::find()
+        .filter(disputes::Column::Status.eq(DisputeStatus::Lost.as_str()))
+        .filter(disputes::Column::OrderId.is_not_null())
+        .filter(disputes::Column::UpdatedAt.gte(from))
+        .filter(disputes::Column::UpdatedAt.lt(until))
+        .order_by_asc(disputes::Column::UpdatedAt)
+        .all(db)
+        .await?;
+    // refunds and chargebacks of another tenant's orders find no order here and are skipped
+    let refunded_orders = Orders::find()
+        .for_tenant(tenant)
+        .filter(
+            orders::Column::OrderId.is_in(
+                charged_back
+                    .iter()
+                    .filter_map(|dispute| dispute.order_id)
+                    .chain(paid_back.iter().map(|refund| refund.order_id)),
+            ),
+        )
+        .all(db)
+        .await?;
+
+    let mut order_ids: Vec<i32> = sold.iter().map(|order| order.order_id).collect();
+    order_ids.extend(refunded_orders.iter().map(|order| order.order_id));
+    let shares = match supplier_id {
+        Some(supplier_id) => Some(supplier_shares(db, supplier_id, order_ids).await?),
+        None => None,
+    };
+    let share_of = |order_id: i32| match &shares {
+        Some(shares) => shares.get(&order_id).copied().unwrap_or_default(),
+        None => Decimal::ONE,
+    };
+    // refunded lines count in full for the supplier whose product they are
+    let supplier_refunded: HashMap<i32, Decimal> = match supplier_id {
+        Some(supplier_id) => {
+            let lines = RefundItems::find()
+                .filter(
+                    refund_items::Column::RefundId
+                        .is_in(paid_back.iter().map(|refund| refund.refund_id)),
+                )
+                .find_also_related(OrderItems)
+                .all(db)
+                .await?;
+            let supplier_products: Vec<i32> = Products::find()
+                .filter(products::Column::SupplierId.eq(supplier_id))
+                .filter(
+                    products::Column::ProductId.is_in(
+                        lines
+                            .iter()
+                            .filter_map(|(_, item)| item.as_ref().map(|item| item.product_id)),
+                    ),
+                )
+                .all(db)
+                .await?
+                .into_iter()
+                .map(|product| product.product_id)
+                .collect();
+            let mut refunded = HashMap::new();
+            for (line, item) in lines {
+                if item.is_some_and(|item| supplier_products.contains(&item.product_id)) {
+                    *refunded.entry(line.refund_id).or_default() += line.amount;
+                }
+            }
+            refunded
+        }
+        None => HashMap::new(),
+    };
+
+    let customers: HashMap<i32, customers::Model> = Customers::find()
+        .filter(
+            customers::Column::CustomerId.is_in(
+                sold.iter()
+                    .chain(refunded_orders.iter())
+                    .filter_map(|order| order.customer_id),
+            ),
+        )
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|customer| (customer.customer_id, customer))
+        .collect();
+    // DATEV and QuickBooks book against the counterparty by name, a hash can't be reconciled
+    let counterparty = |order: &orders::Model| match order
+        .customer_id
+        .and_then(|customer_id| customers.get(&customer_id))
+    {
+        Some(customer) => format!("{} {}", customer.first_name, customer.last_name),
+        None => order.guest_email.clone().unwrap_or_default(),
+    };
+
+    let mut entries = Vec::new();
+    for order in &sold {
+        let share = share_of(order.order_id);
+        if share.is_zero() {
+            continue;
+        }
+        let currency = &order.currency;
+        let gross = round_amount(order.total_amount * share, currency);
+        let tax = round_amount(order.tax_amount * share, currency);
+        entries.push(AccountingEntry {
+            date: order
+                .order_date
+                .map(|date| date.date_naive())
+                .unwrap_or(period_start),
+            kind: EntryKind::Sale,
+            document: format!("ORD-{}", order.order_id),
+            counterparty: counterparty(order),
+            net: gross - tax,
+            tax,
+            gross,
+            currency: currency.clone(),
+        });
+    }
+
+    for refund in paid_back {
+        let Some(order) = refunded_orders
+            .iter()
+            .find(|order| order.order_id == refund.order_id)
+        else {
+            continue;
+        };
+        if order.total_amount.is_zero() {
+            continue;
+        }
+        let currency = &order.currency;
+        let gross = match supplier_id {
+            Some(_) => round_amount(
+                supplier_refunded
+                    .get(&refund.refund_id)
+                    .copied()
+                    .unwrap_or_default()
+                    + refund.extra_amount * share_of(order.order_id),
+                currency,
+            ),
+            None => refund.amount,
+        };
+        if gross.is_zero() {
+            continue;
+        }
+        let tax = round_amount(gross * order.tax_amount / order.total_amount, currency);
+        entries.push(AccountingEntry {
+            date: refund
+                .refunded_at
+                .map(|date| date.date_naive())
+                .unwrap_or(period_start),
+            kind: EntryKind::Refund,
+            document: format!("RF-{}", refund.refund_id),
+            counterparty: counterparty(order),
+            net: gross - tax,
+            tax,
+            gross,
+            currency: currency.clone(),
+        });
+    }
+
+    for dispute in charged_back {
+        let Some(order) = refunded_orders
+            .iter()
+            .find(|order| Some(order.order_id) == dispute.order_id)
+        else {
+            continue;
+        };
+        let share = share_of(order.order_id);
+        if share.is_zero() || order.total_amount.is_zero() {
+            continue;
+        }
+        let currency = &order.currency;
+        let gross = round_amount(dispute.amount * share, currency);
+        let tax = round_amount(gross * order.tax_amount / order.total_amount, currency);
+        entries.push(AccountingEntry {
+            date: dispute.updated_at.date_naive(),
+            kind: EntryKind::Refund,
+            document: format!("CB-{}", dispute.dispute_id),
+            counterparty: counterparty(order),
+            net: gross - tax,
+            tax,
+            gross,
+            currency: currency.clone(),
+        });
+    }
+
+    Ok(entries)
+}
+
+fn write_csv(
+    format: AccountingFormat,
+    config: &AccountingConfig,
+    entries: &[AccountingEntry],
+) -> Result<Vec<u8>, AppError> {
+    let csv_error = |e: csv::Error| AppError::Internal(format!("Failed to write export: {}", e));
+
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(match format {
+            AccountingFormat::Datev => b';',
+            AccountingFormat::Quickbooks => b',',
+        })
+        .from_writer(Vec::new());
+
+    match format {
+        // booking batch columns of the DATEV format, decimal comma and DDMM dates
+        AccountingFormat::Datev => {
+            writer
+                .write_record([
+                    "Umsatz (ohne Soll/Haben-Kz)",
+                    "Soll/Haben-Kennzeichen",
+                    "WKZ Umsatz",
+                    "Konto",
+                    "Gegenkonto (ohne BU-Schlüssel)",
+                    "Belegdatum",
+                    "Belegfeld 1",
+                    "Buchungstext",
+                ])
+                .map_err(csv_error)?;
+            for entry in entries {
+                writer
+                    .write_record([
+                        entry.gross.to_string().replace('.', ","),
+                        match entry.kind {
+                            EntryKind::Sale => "S".to_string(),
+                            EntryKind::Refund => "H".to_string(),
+                        },
+                        entry.currency.clone(),
+                        config.datev_debtor_account.clone(),
+                        config.datev_revenue_account.clone(),
+                        entry.date.format("%d%m").to_string(),
+                        entry.document.clone(),
+                        entry.counterparty.chars().take(60).collect(),
+                    ])
+                    .map_err(csv_error)?;
+            }
+        }
+        // QuickBooks sales/refund receipt import layout
+        AccountingFormat::Quickbooks => {
+            writer
+                .write_record([
+                    "Date",
+                    "Transaction Type",
+                    "Num",
+                    "Customer",
+                    "Currency",
+                    "Net Amount",
+                    "Tax Amount",
+                    "Total Amount",
+                ])
+                .map_err(csv_error)?;
+            for entry in entries {
+                writer
+                    .write_record([
+                        entry.date.format("%m/%d/%Y").to_string(),
+                        match entry.kind {
+                            EntryKind::Sale => "Sales Receipt".to_string(),
+                            EntryKind::Refund => "Refund Receipt".to_string(),
+                        },
+                        entry.document.clone(),
+                        entry.counterparty.clone(),
+                        entry.currency.clone(),
+                        entry.net.to_string(),
+                        entry.tax.to_string(),
+                        entry.gross.to_string(),
+                    ])
+                    .map_err(csv_error)?;
+            }
+        }
+    }
+
+    writer
+        .into_inner()
+        .map_err(|e| AppError::Internal(format!("Failed to write export: {}", e)))
+}
+
+// writes the export's file, a rerun after a lost lease overwrites what an earlier run left
+async fn generate_export(
+    db: &DatabaseConnection,
+    storage: &dyn Storage,
+    config: &AccountingConfig,
+    accounting_export_id: i32,
+) -> Result<(), AppError> {
+    let export = AccountingExports::find_by_id(accounting_export_id)
+        .one(db)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Accounting export"))?;
+    let format = AccountingFormat::parse(&export.format)
+        .ok_or_else(|| AppError::Internal(format!("Unknown export format {}", export.format)))?;
+
+    let entries = collect_entries(
+        db,
+        export.tenant_id.into(),
+        export.supplier_id,
+        export.period_start,
+        export.period_end,
+    )
+    .await?;
+    let bytes = write_csv(format, config, &entries)?;
+
+    let key = format!(
+        "exports/{}/{}-{}-{}.csv",
+        export.accounting_export_id,
+        export.format.to_lowercase(),
+        export.period_start,
+        export.period_end
+    );
+    storage.put_private(&key, bytes, "text/csv").await?;
+
+    AccountingExports::update(accounting_exports::ActiveModel {
+        accounting_export_id: Set(export.accounting_export_id),
+        file_key: Set(Some(key)),
+        row_count: Set(Some(entries.len() as i32)),
+        ..Default::default()
+    })
+    .exec(db)
+    .await?;
+    Ok(())
+}
+
+const EXPORT_JOBS: JobTable = JobTable {
+    table: "accounting_exports",
+    id_column: "accounting_export_id",
+    label: "Accounting export",
+};
+
+pub fn spawn_accounting_export_worker(
+    db: DatabaseConnection,
+    storage: Arc<dyn Storage>,
+    config: AccountingConfig,
+) {
+    let worker_db = db.clone();
+    EXPORT_JOBS.spawn_worker(db, EXPORT_POLL_SECONDS, move |accounting_export_id| {
+        let (db, storage, config) = (worker_db.clone(), storage.clone(), config.clone());
+        async move { generate_export(&db, storage.as_ref(), &config, accounting_export_id).await }
+    });
+}
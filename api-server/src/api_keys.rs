@@ -0,0 +1,222 @@
+use crate::{
+    auth::{Auth, ROLE_CUSTOMER, ROLE_SUPPLIER},
+    config::JwtPolicy,
+    entity::{
+        api_keys,
+        prelude::{ApiKeys as ApiKeysEntity, Customers, Suppliers, Users},
+        users,
+    },
+    error::{AppError, AuthErrorCode},
+    ids::TenantId,
+    retry::retry_db,
+};
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use async_graphql::Context;
+use chrono::Duration;
+use sea_orm::{
+    ColumnTrait, ConnectionTrait, DatabaseConnection, DbBackend, EntityTrait, QueryFilter,
+    Statement,
+};
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, Mutex},
+    time::{Duration as StdDuration, Instant},
+};
+
+pub const API_KEY_HEADER: &str = "x-api-key";
+const API_KEY_PREFIX: &str = "sdb_";
+
+// Attached to the request context when the caller authenticated with an API key
+#[derive(Clone)]
+pub struct ApiKeyContext {
+    pub api_key_id: i32,
+    pub token: String,
+    // requests run against the sandbox schema and a gateway that never moves money
+    pub sandbox: bool,
+    // a key works in its owner's marketplace, whatever host the request came in on
+    pub tenant_id: TenantId,
+}
+
+// true when the request came in with a sandbox key and reads the sandbox copies
+pub fn in_sandbox(ctx: &Context<'_>) -> bool {
+    ctx.data_opt::<ApiKeyContext>()
+        .is_some_and(|api_key| api_key.sandbox)
+}
+
+pub enum UsageOutcome {
+    Success,
+    Error,
+    RateLimited,
+}
+
+pub struct GeneratedApiKey {
+    pub key: String,
+    pub prefix: String,
+    pub hash: String,
+}
+
+pub fn generate_api_key() -> GeneratedApiKey {
+    let mut bytes = [0u8; 24];
+    OsRng.fill_bytes(&mut bytes);
+    let key = format!(
+        "{}{}",
+        API_KEY_PREFIX,
+        bytes
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<String>()
+    );
+
+    GeneratedApiKey {
+        prefix: key[..12].to_string(),
+        hash: hash_api_key(&key),
+        key,
+    }
+}
+
+pub fn hash_api_key(key: &str) -> String {
+    Auth::hash_secret(key)
+}
+
+// how long a resolved key and its token are reused, a revocation on another instance takes
+// effect within this. The token itself lives five minutes
+const RESOLVED_KEY_SECONDS: u64 = 60;
+
+struct ResolvedKey {
+    resolved_at: Instant,
+    user_id: i32,
+    context: ApiKeyContext,
+}
+
+// resolved keys by hash, so a busy key isn't looked up and signed for on every request
+static RESOLVED_KEYS: LazyLock<Mutex<HashMap<String, ResolvedKey>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+// Looks up an active key and mints a short lived token for its owner, so the existing
+// role guards work unchanged for API key callers. Keys of disabled accounts don't resolve
+pub async fn resolve_api_key(
+    db: &DatabaseConnection,
+    jwt_policy: &JwtPolicy,
+    key: &str,
+) -> Result<ApiKeyContext, AppError> {
+    let invalid_key = || AppError::Auth {
+        message: "Invalid API key".to_string(),
+        code: AuthErrorCode::InvalidCredentials,
+        user_id: None,
+    };
+
+    let key_hash = hash_api_key(key);
+    if let Some(resolved) = RESOLVED_KEYS.lock().unwrap().get(&key_hash) {
+        if resolved.resolved_at.elapsed() < StdDuration::from_secs(RESOLVED_KEY_SECONDS) {
+            return Ok(resolved.context.clone());
+        }
+    }
+
+    let api_key = retry_db("resolve_api_key", || {
+        ApiKeysEntity::find()
+            .filter(api_keys::Column::KeyHash.eq(key_hash.as_str()))
+            .filter(api_keys::Column::RevokedAt.is_null())
+            .one(db)
+    })
+    .await?
+    .ok_or_else(invalid_key)?;
+
+    // supplier keys drive the catalog, customer keys are used by punchout buyers
+    let (user_id, role) = match (api_key.supplier_id, api_key.customer_id) {
+        (Some(supplier_id), _) => Suppliers::find_by_id(supplier_id)
+            .one(db)
+            .await?
+            .map(|supplier| (supplier.user_id, ROLE_SUPPLIER)),
+        (None, Some(customer_id)) => Customers::find_by_id(customer_id)
+            .one(db)
+            .await?
+            .map(|customer| (customer.user_id, ROLE_CUSTOMER)),
+        (None, None) => None,
+    }
+    .ok_or_else(invalid_key)?;
+    let tenant_id = Users::find_by_id(user_id)
+        .filter(users::Column::DisabledAt.is_null())
+        .one(db)
+        .await?
+        .ok_or_else(invalid_key)?
+        .tenant_id
+        .into();
+
+    let context = ApiKeyContext {
+        api_key_id: api_key.api_key_id,
+        sandbox: api_key.sandbox,
+        tenant_id,
+        token: Auth::create_token(
+            jwt_policy,
+            user_id.into(),
+            tenant_id,
+            role.to_string(),
+            Duration::minutes(5),
+        )?,
+    };
+    RESOLVED_KEYS.lock().unwrap().insert(
+        key_hash,
+        ResolvedKey {
+            resolved_at: Instant::now(),
+            user_id,
+            context: context.clone(),
+        },
+    );
+    Ok(context)
+}
+
+// drops a revoked key from this instance's resolved keys
+pub fn forget_api_key(api_key_id: i32) {
+    RESOLVED_KEYS
+        .lock()
+        .unwrap()
+        .retain(|_, resolved| resolved.context.api_key_id != api_key_id);
+}
+
+// revokes every key of a disabled account, whether it was issued to its supplier or customer
+pub async fn revoke_user_api_keys<C: ConnectionTrait>(
+    db: &C,
+    user_id: i32,
+) -> Result<(), AppError> {
+    db.execute(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        "UPDATE api_keys SET revoked_at = CURRENT_TIMESTAMP
+            WHERE revoked_at IS NULL
+              AND (supplier_id IN (SELECT supplier_id FROM suppliers WHERE user_id = $1)
+                OR customer_id IN (SELECT customer_id FROM customers WHERE user_id = $1))",
+        vec![user_id.into()],
+    ))
+    .await?;
+    RESOLVED_KEYS
+        .lock()
+        .unwrap()
+        .retain(|_, resolved| resolved.user_id != user_id);
+    Ok(())
+}
+
+pub async fn record_usage(
+    db: &DatabaseConnection,
+    api_key_id: i32,
+    outcome: UsageOutcome,
+) -> Result<(), AppError> {
+    let (errors, rate_limited) = match outcome {
+        UsageOutcome::Success => (0, 0),
+        UsageOutcome::Error => (1, 0),
+        UsageOutcome::RateLimited => (0, 1),
+    };
+
+    db.execute(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        "INSERT INTO api_key_usage (api_key_id, usage_date, request_count, error_count, rate_limited_count)
+            VALUES ($1, CURRENT_DATE, 1, $2, $3)
+            ON CONFLICT (api_key_id, usage_date) DO UPDATE SET
+                request_count = api_key_usage.request_count + 1,
+                error_count = api_key_usage.error_count + EXCLUDED.error_count,
+                rate_limited_count = api_key_usage.rate_limited_count + EXCLUDED.rate_limited_count;
+            ",
+        vec![api_key_id.into(), errors.into(), rate_limited.into()],
+    ))
+    .await?;
+
+    Ok(())
+}
@@ -1,25 +1,97 @@
 // This file contains few comments which may feel out of place, but they are here only to explain the concepts of OOP in Rust.
 
+use crate::breached_passwords::BreachedPasswordCheck;
+use crate::config::{EmailVerificationPolicy, JwtPolicy, PasswordPolicy};
+use crate::entity::{prelude::Users as UsersEntity, users};
 use crate::error::{AppError, AuthErrorCode};
+use crate::ids::{TenantId, UserId};
+use crate::notifications::{send_templated, TemplateKey};
+use crate::permissions::caller_permissions;
+use crate::sessions::issue_email_verification_token;
+use crate::tenancy::default_tenant_id;
 use argon2::{
-    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    password_hash::{
+        rand_core::{OsRng, RngCore},
+        PasswordHash, PasswordHasher, PasswordVerifier, SaltString,
+    },
     Algorithm, Argon2, Params, Version,
 };
 use async_graphql::*;
-use chrono::{Duration, TimeDelta, Utc};
+use chrono::{TimeDelta, Utc};
 use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
 use lazy_regex::regex;
-use mail_send::mail_builder::MessageBuilder;
-use mail_send::SmtpClientBuilder;
+use sea_orm::{DatabaseConnection, DbErr, EntityTrait};
 use serde::{Deserialize, Serialize};
-use std::env;
+use serde_json::Value as JsonValue;
+use sha2::{Digest, Sha256};
+use std::{collections::BTreeMap, env};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Claims {
     pub user_id: String,
+    // the role the account signed up with, resolvers that act as one identity go by this one
     pub role: String,
+    // every role the account holds, tokens from before roles could be added don't carry it
+    #[serde(default)]
+    pub roles: Vec<String>,
+    // the marketplace the account lives in, a token is only honoured on that tenant's requests
+    #[serde(default = "default_tenant_id")]
+    pub tenant_id: i32,
     pub exp: i64,
     pub iat: i64,
+    pub iss: String,
+    pub aud: Vec<String>,
+    // unique per token, so services consuming it can tell tokens apart and deny a single one
+    pub jti: String,
+    // the tenant and permission claims configured in JwtPolicy, under the names given there
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, JsonValue>,
+}
+
+impl Claims {
+    pub fn has_role(&self, role: &str) -> bool {
+        self.role == role || self.roles.iter().any(|held| held == role)
+    }
+}
+
+// the caller behind a request's bearer token, verified once by the handler before any resolver runs
+#[derive(Debug, Clone)]
+pub struct AuthenticatedUser(pub Claims);
+
+impl AuthenticatedUser {
+    // the caller the handler verified, resolvers go through this instead of verifying the token again
+    pub fn from_ctx(ctx: &Context<'_>) -> Result<Claims, AppError> {
+        if let Some(user) = ctx.data_opt::<AuthenticatedUser>() {
+            return Ok(user.0.clone());
+        }
+        match ctx.data_opt::<RejectedToken>() {
+            Some(rejected) => Err(AppError::Auth {
+                message: rejected.message.clone(),
+                code: rejected.code.clone(),
+                user_id: None,
+            }),
+            None => Err(AppError::Unauthenticated),
+        }
+    }
+}
+
+// why the handler turned the request's token down, public queries still run without a caller
+#[derive(Debug, Clone)]
+pub struct RejectedToken {
+    pub message: String,
+    pub code: AuthErrorCode,
+}
+
+impl From<AppError> for RejectedToken {
+    fn from(e: AppError) -> Self {
+        match e {
+            AppError::Auth { message, code, .. } => RejectedToken { message, code },
+            e => RejectedToken {
+                message: e.to_string(),
+                code: AuthErrorCode::InvalidCredentials,
+            },
+        }
+    }
 }
 
 pub struct Auth;
@@ -42,6 +114,14 @@ impl Auth {
             .map_err(|e| AppError::Internal(format!("Password hashing failed: {}", e)))
     }
 
+    // fast digest for high entropy secrets (API keys, one time codes), passwords use argon2 above
+    pub fn hash_secret(secret: &str) -> String {
+        Sha256::digest(secret.as_bytes())
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
     pub fn verify_password(password: &str, hash: &str) -> Result<bool, AppError> {
         let parsed_hash = PasswordHash::new(hash)
             .map_err(|e| AppError::Internal(format!("Invalid password hash: {}", e)))?;
@@ -58,16 +138,45 @@ impl Auth {
     }
 
     pub fn create_token(
-        user_id: i32,
+        policy: &JwtPolicy,
+        user_id: UserId,
+        tenant_id: TenantId,
         role: String,
         duration: TimeDelta,
+    ) -> Result<String, AppError> {
+        let roles = vec![role.clone()];
+        Self::create_token_with_roles(policy, user_id, tenant_id, role, roles, duration)
+    }
+
+    pub fn create_token_with_roles(
+        policy: &JwtPolicy,
+        user_id: UserId,
+        tenant_id: TenantId,
+        role: String,
+        roles: Vec<String>,
+        duration: TimeDelta,
     ) -> Result<String, AppError> {
         let now = Utc::now();
+        let mut extra = BTreeMap::new();
+        if let Some(name) = &policy.tenant_claim {
+            extra.insert(name.clone(), tenant_id.0.into());
+        }
+        if let Some(name) = &policy.permissions_claim {
+            extra.insert(name.clone(), roles.clone().into());
+        }
+        let mut jti = [0u8; 16];
+        OsRng.fill_bytes(&mut jti);
         let claims = Claims {
             user_id: user_id.to_string(),
             role,
+            roles,
+            tenant_id: tenant_id.0,
             exp: (now + duration).timestamp(), // 30 days might be unconventional, but we need it because refresh tokens implementation is limited due to OS limitations. also revoke token will prevent misuse (maybe, idk)
             iat: now.timestamp(),
+            iss: policy.issuer.clone(),
+            aud: policy.audience.clone(),
+            jti: jti.iter().map(|byte| format!("{:02x}", byte)).collect(),
+            extra,
         };
 
         let secret = env::var("TOKEN_SECRET").map_err(|_| {
@@ -82,15 +191,21 @@ impl Auth {
         .map_err(|e| AppError::Internal(format!("Token creation failed: {}", e)))
     }
 
-    pub fn verify_token(token: &str) -> Result<Claims, AppError> {
+    pub fn verify_token(policy: &JwtPolicy, token: &str) -> Result<Claims, AppError> {
         let secret = env::var("TOKEN_SECRET").map_err(|_| {
             AppError::Internal("TOKEN_SECRET environment variable not set".to_string())
         })?;
 
+        // issuer and audience are checked on every token, jti has to be there for Claims to parse
+        let mut validation = Validation::default();
+        validation.set_issuer(&[&policy.issuer]);
+        validation.set_audience(&policy.audience);
+        validation.set_required_spec_claims(&["exp", "iss", "aud"]);
+
         decode::<Claims>(
             token,
             &DecodingKey::from_secret(secret.as_ref()),
-            &Validation::default(),
+            &validation,
         )
         .map(|token_data| token_data.claims)
         .map_err(|e| match e.kind() {
@@ -107,36 +222,65 @@ impl Auth {
         })
     }
 
-    pub fn refresh_token(token: &str) -> Result<String, AppError> {
-        let claims = Auth::verify_token(token)?;
-        let now = Utc::now();
-        let new_claims = Claims {
-            user_id: claims.user_id,
-            role: claims.role,
-            exp: (now + Duration::days(30)).timestamp(),
-            iat: now.timestamp(),
-        };
+    pub fn check_password_strength(password: &str, policy: &PasswordPolicy) -> Vec<PasswordRule> {
+        let checks = [
+            (
+                PasswordRule::MinLength,
+                password.chars().count() >= policy.min_length,
+            ),
+            (
+                PasswordRule::Uppercase,
+                !policy.require_uppercase || regex!(r"[A-Z]").is_match(password),
+            ),
+            (
+                PasswordRule::Lowercase,
+                !policy.require_lowercase || regex!(r"[a-z]").is_match(password),
+            ),
+            (
+                PasswordRule::Digit,
+                !policy.require_digit || regex!(r"[0-9]").is_match(password),
+            ),
+            (
+                PasswordRule::Symbol,
+                !policy.require_symbol || password.chars().any(|c| c.is_ascii_punctuation()),
+            ),
+        ];
 
-        let secret = env::var("TOKEN_SECRET").expect("TOKEN_SECRET must be set");
-        encode(
-            &Header::default(),
-            &new_claims,
-            &EncodingKey::from_secret(secret.as_ref()),
-        )
-        .map_err(|e| AppError::Internal(format!("Token creation failed: {}", e)))
+        checks
+            .into_iter()
+            .filter(|(_, passed)| !passed)
+            .map(|(rule, _)| rule)
+            .collect()
     }
 
-    pub fn check_password_strength(password: &str) -> Result<(), &'static str> {
-        if password.len() < 8
-            || !regex!(r"[A-Z]").is_match(password)
-            || !regex!(r"[0-9]").is_match(password)
-            || !regex!(r"[a-z]").is_match(password)
-            || !regex!(r"[!@#$%^&*]").is_match(password)
-        {
-            return Err("You're not the only person who knows about the developer tools in the browser. Nice try bro");
+    // runs the local rules first and only asks the breach checker when everything else passed
+    pub async fn enforce_password_policy(
+        password: &str,
+        policy: &PasswordPolicy,
+        breach_check: &dyn BreachedPasswordCheck,
+    ) -> Result<(), AppError> {
+        let mut failed_rules = Self::check_password_strength(password, policy);
+
+        if failed_rules.is_empty() && policy.check_breached {
+            match breach_check.is_breached(password).await {
+                Ok(true) => failed_rules.push(PasswordRule::Breached),
+                Ok(false) => {}
+                // the lookup is best effort, an unreachable API should not block sign ups
+                Err(e) => eprintln!("Breached password lookup failed, skipping the check: {}", e),
+            }
         }
 
-        Ok(())
+        if failed_rules.is_empty() {
+            return Ok(());
+        }
+
+        Err(AppError::Validation {
+            message: "Password does not satisfy the password policy".to_string(),
+            failed_rules: failed_rules
+                .into_iter()
+                .map(|rule| rule.to_string())
+                .collect(),
+        })
     }
 
     pub fn check_email(email: &str) -> Result<(), &'static str> {
@@ -147,51 +291,53 @@ impl Auth {
         Ok(())
     }
 
+    // a single use link for verifyEmail, asking again voids the link sent before
     pub async fn send_email_verification(
-        email: String,
-        id: i32,
-        role: String,
-    ) -> Result<String, &'static str> {
-        let token = Self::create_token(id, role, Duration::minutes(15))
-            .map_err(|_| "Failed to create token")?;
-
-        let port = env::var("PORT").map_err(|_| "PORT must be set")?;
-
-        // Build a simple multipart message
-        let message = MessageBuilder::new()
-            .from((
-                "Verify Mail Id Bitte",
-                "postmaster@testing.giripriyadarshan.com",
-            ))
-            .to(email)
-            .subject("Nine11 email verification")
-            .html_body(
-                "<a href=\"http://localhost:".to_string()
-                    + port.as_str()
-                    + "/verify/"
-                    + token.as_str()
-                    + "\">Click here to verify your email</a>",
-            );
-
-        // Connect to the SMTP submissions port, upgrade to TLS and
-        // authenticate using the provided credentials.
-        let smtp_username = env::var("SMTP_USERNAME").expect("SMTP_USERNAME must be set");
-        let smtp_password = env::var("SMTP_PASSWORD").expect("SMTP_PASSWORD must be set");
-        SmtpClientBuilder::new("smtp.mailgun.org", 587)
-            .implicit_tls(false)
-            .credentials((smtp_username.as_str(), smtp_password.as_str()))
-            .connect()
-            .await
-            .expect("Failed to connect to SMTP server")
-            .send(message)
-            .await
-            .expect("Failed to send email");
-        Ok("Email verification sent".to_string())
+        db: &DatabaseConnection,
+        redis: &redis::Client,
+        policy: &EmailVerificationPolicy,
+        user: &users::Model,
+    ) -> Result<(), AppError> {
+        let token = issue_email_verification_token(redis, user.user_id, policy.token_hours).await?;
+
+        send_templated(
+            db,
+            TenantId(user.tenant_id),
+            TemplateKey::EmailVerification,
+            user.email.clone(),
+            user.locale.as_deref(),
+            &[("verification_url", format!("{}/{}", policy.url, token))],
+        )
+        .await
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PasswordRule {
+    MinLength,
+    Uppercase,
+    Lowercase,
+    Digit,
+    Symbol,
+    Breached,
+}
+
+impl std::fmt::Display for PasswordRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MinLength => write!(f, "MIN_LENGTH"),
+            Self::Uppercase => write!(f, "UPPERCASE"),
+            Self::Lowercase => write!(f, "LOWERCASE"),
+            Self::Digit => write!(f, "DIGIT"),
+            Self::Symbol => write!(f, "SYMBOL"),
+            Self::Breached => write!(f, "BREACHED"),
+        }
     }
 }
 
 pub const ROLE_SUPPLIER: &str = "supplier";
 pub const ROLE_CUSTOMER: &str = "customer";
+pub const ROLE_ADMIN: &str = "admin";
 
 // struct name is equivalent to a class name in OOP
 // it consists of data members
@@ -213,24 +359,55 @@ impl RoleGuard {
 impl Guard for RoleGuard {
     // Polymorphism
     async fn check(&self, ctx: &Context<'_>) -> Result<()> {
-        let token = ctx.data_opt::<String>().ok_or(AppError::Auth {
-            message: "No authorization token found".to_string(),
-            code: AuthErrorCode::InvalidCredentials,
-            user_id: None,
-        })?;
-
-        let claims = Auth::verify_token(token)?;
+        let permissions = caller_permissions(ctx).await?;
 
         // Open recursion using 'self' keyword
-        if self.allowed_roles.contains(&claims.role) {
+        if self
+            .allowed_roles
+            .iter()
+            .any(|role| permissions.has_role(role))
+        {
             Ok(())
         } else {
             Err(AppError::Auth {
                 message: "Insufficient permissions".to_string(),
                 code: AuthErrorCode::InsufficientPermissions,
-                user_id: Some(claims.user_id),
+                user_id: Some(permissions.user_id.to_string()),
             }
-            .into())
+            .extend())
+        }
+    }
+}
+
+pub async fn is_email_verified(db: &DatabaseConnection, user_id: i32) -> Result<bool, DbErr> {
+    Ok(UsersEntity::find_by_id(user_id)
+        .one(db)
+        .await?
+        .is_some_and(|user| user.email_verified))
+}
+
+// Put in front of purchases together with the role guard, it lets everyone through unless
+// REQUIRE_VERIFIED_EMAIL_FOR_PURCHASES is on
+pub struct VerifiedEmailGuard;
+
+impl Guard for VerifiedEmailGuard {
+    async fn check(&self, ctx: &Context<'_>) -> Result<()> {
+        if !ctx
+            .data::<EmailVerificationPolicy>()?
+            .required_for_purchases
+        {
+            return Ok(());
+        }
+        let permissions = caller_permissions(ctx).await?;
+
+        if permissions.email_verified {
+            return Ok(());
+        }
+        Err(AppError::Auth {
+            message: "Verify your email address before purchasing".to_string(),
+            code: AuthErrorCode::EmailNotVerified,
+            user_id: Some(permissions.user_id.to_string()),
         }
+        .extend())
     }
 }
@@ -0,0 +1,44 @@
+use crate::error::AppError;
+use async_trait::async_trait;
+use sha1::{Digest, Sha1};
+
+#[async_trait]
+pub trait BreachedPasswordCheck: Send + Sync {
+    async fn is_breached(&self, password: &str) -> Result<bool, AppError>;
+}
+
+// Have I Been Pwned range API, only the first five characters of the SHA-1 hash leave the server
+#[derive(Default)]
+pub struct PwnedPasswords {
+    client: reqwest::Client,
+}
+
+#[async_trait]
+impl BreachedPasswordCheck for PwnedPasswords {
+    async fn is_breached(&self, password: &str) -> Result<bool, AppError> {
+        let hash: String = Sha1::digest(password.as_bytes())
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect();
+        let (prefix, suffix) = hash.split_at(5);
+
+        let body = self
+            .client
+            .get(format!("https://api.pwnedpasswords.com/range/{}", prefix))
+            .header("Add-Padding", "true")
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| AppError::Internal(format!("Breached password lookup failed: {}", e)))?
+            .text()
+            .await
+            .map_err(|e| AppError::Internal(format!("Breached password lookup failed: {}", e)))?;
+
+        // padded entries carry a count of 0 and must not be treated as hits
+        Ok(body.lines().any(|line| {
+            line.split_once(':').is_some_and(|(candidate, count)| {
+                candidate.eq_ignore_ascii_case(suffix) && count.trim() != "0"
+            })
+        }))
+    }
+}
@@ -0,0 +1,33 @@
+use futures_util::{stream, Stream};
+use tokio::sync::broadcast;
+
+// in-process fan-out for subscription events, subscribers filter down to what they may see
+#[derive(Clone)]
+pub struct Broker<T: Clone> {
+    sender: broadcast::Sender<T>,
+}
+
+impl<T: Clone + Send + 'static> Broker<T> {
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        Self { sender }
+    }
+
+    pub fn publish(&self, event: T) {
+        // an error only means nobody is subscribed right now
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> impl Stream<Item = T> {
+        stream::unfold(self.sender.subscribe(), |mut receiver| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(event) => return Some((event, receiver)),
+                    // a slow subscriber skips what it missed instead of ending the stream
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,215 @@
+use crate::{
+    api_keys::revoke_user_api_keys,
+    auth::{ROLE_ADMIN, ROLE_CUSTOMER, ROLE_SUPPLIER},
+    config::{env_or, SessionPolicy},
+    entity::{
+        bulk_user_job_results,
+        bulk_user_jobs::{self, Model as BulkUserJobsModel},
+        prelude::{BulkUserJobResults, BulkUserJobs, UserRoles, Users},
+        sea_orm_active_enums::UserRole,
+        user_roles, users,
+    },
+    error::AppError,
+    ids::TenantId,
+    jobs::JobTable,
+    models::{bulk_user_jobs::BulkUserAction, user::held_roles},
+    notifications::{send_templated, TemplateKey},
+    sessions::revoke_user_sessions,
+};
+use chrono::Utc;
+use sea_orm::{
+    sea_query::{Expr, OnConflict},
+    ActiveEnum,
+    ActiveValue::Set,
+    ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, QuerySelect,
+};
+use std::sync::Arc;
+
+const BULK_USER_POLL_SECONDS: u64 = 10;
+
+// no password hashes to this, so logging in answers "please reset password" until one is set again
+const RESET_PASSWORD: &str = "!reset";
+
+// the role a bulk job assigns, None for anything that isn't a role accounts can hold
+pub fn parse_role(role: &str) -> Option<UserRole> {
+    match role {
+        ROLE_CUSTOMER => Some(UserRole::Customer),
+        ROLE_SUPPLIER => Some(UserRole::Supplier),
+        ROLE_ADMIN => Some(UserRole::Admin),
+        _ => None,
+    }
+}
+
+// what happened to one account, failures are recorded and the job moves on to the next one
+async fn apply(
+    db: &DatabaseConnection,
+    redis: &redis::Client,
+    policy: &SessionPolicy,
+    job: &BulkUserJobsModel,
+    action: BulkUserAction,
+    user_id: i32,
+) -> Result<(&'static str, Option<String>), AppError> {
+    let Some(user) = Users::find_by_id(user_id)
+        .filter(users::Column::TenantId.eq(job.tenant_id))
+        .one(db)
+        .await?
+    else {
+        return Ok(("FAILED", Some("User not found".to_string())));
+    };
+    if user.user_id == job.requested_by && action != BulkUserAction::AssignRole {
+        return Ok(("SKIPPED", Some("Requested by this account".to_string())));
+    }
+
+    match action {
+        BulkUserAction::Disable => {
+            if user.disabled_at.is_some() {
+                return Ok(("SKIPPED", Some("Already disabled".to_string())));
+            }
+            Users::update_many()
+                .col_expr(
+                    users::Column::DisabledAt,
+                    Expr::value(Utc::now().fixed_offset()),
+                )
+                .filter(users::Column::UserId.eq(user_id))
+                .exec(db)
+                .await?;
+            revoke_user_api_keys(db, user_id).await?;
+            revoke_user_sessions(redis, policy, user_id).await?;
+            Ok(("DONE", None))
+        }
+        BulkUserAction::ResetPassword => {
+            Users::update_many()
+                .col_expr(users::Column::Password, Expr::value(RESET_PASSWORD))
+                .filter(users::Column::UserId.eq(user_id))
+                .exec(db)
+                .await?;
+            revoke_user_sessions(redis, policy, user_id).await?;
+            // the reset already happened, a bounced notice doesn't undo it
+            let notice = send_templated(
+                db,
+                TenantId(user.tenant_id),
+                TemplateKey::PasswordResetByAdmin,
+                user.email,
+                user.locale.as_deref(),
+                &[],
+            )
+            .await
+            .err()
+            .map(|e| format!("Notice not sent: {}", e));
+            Ok(("DONE", notice))
+        }
+        BulkUserAction::AssignRole => {
+            let role = job.role.clone().ok_or_else(|| {
+                AppError::Internal(format!("Job {} has no role to assign", job.job_id))
+            })?;
+            // held by sign up or through an earlier addRole
+            if held_roles(db, &user)
+                .await
+                .map_err(|e| AppError::Internal(e.message))?
+                .contains(&role.to_value())
+            {
+                return Ok(("SKIPPED", Some("Already holds the role".to_string())));
+            }
+            let granted = UserRoles::insert(user_roles::ActiveModel {
+                user_id: Set(user_id),
+                role: Set(role),
+                ..Default::default()
+            })
+            .on_conflict(
+                OnConflict::columns([user_roles::Column::UserId, user_roles::Column::Role])
+                    .do_nothing()
+                    .to_owned(),
+            )
+            .exec_without_returning(db)
+            .await?;
+            if granted == 0 {
+                return Ok(("SKIPPED", Some("Already holds the role".to_string())));
+            }
+            Ok(("DONE", None))
+        }
+    }
+}
+
+async fn run_batch(
+    db: &DatabaseConnection,
+    redis: &redis::Client,
+    policy: &SessionPolicy,
+    job: &BulkUserJobsModel,
+    action: BulkUserAction,
+    batch_size: u64,
+) -> Result<u64, AppError> {
+    let user_ids: Vec<i32> = BulkUserJobResults::find()
+        .select_only()
+        .column(bulk_user_job_results::Column::UserId)
+        .filter(bulk_user_job_results::Column::JobId.eq(job.job_id))
+        .filter(bulk_user_job_results::Column::Outcome.eq("PENDING"))
+        .order_by_asc(bulk_user_job_results::Column::UserId)
+        .limit(batch_size)
+        .into_tuple()
+        .all(db)
+        .await?;
+
+    for &user_id in &user_ids {
+        let (outcome, message) = match apply(db, redis, policy, job, action, user_id).await {
+            Ok(result) => result,
+            Err(e) => ("FAILED", Some(e.to_string())),
+        };
+        BulkUserJobResults::update(bulk_user_job_results::ActiveModel {
+            job_id: Set(job.job_id),
+            user_id: Set(user_id),
+            outcome: Set(outcome.to_string()),
+            message: Set(message),
+            processed_at: Set(Some(Utc::now().fixed_offset())),
+        })
+        .exec(db)
+        .await?;
+        BulkUserJobs::update_many()
+            .col_expr(
+                bulk_user_jobs::Column::ProcessedUsers,
+                Expr::col(bulk_user_jobs::Column::ProcessedUsers).add(1),
+            )
+            .col_expr(
+                bulk_user_jobs::Column::FailedUsers,
+                Expr::col(bulk_user_jobs::Column::FailedUsers).add(i32::from(outcome == "FAILED")),
+            )
+            .filter(bulk_user_jobs::Column::JobId.eq(job.job_id))
+            .exec(db)
+            .await?;
+    }
+
+    Ok(user_ids.len() as u64)
+}
+
+// a job picked up again after its worker stopped continues with the accounts still PENDING
+async fn run_job(
+    db: &DatabaseConnection,
+    redis: &redis::Client,
+    policy: &SessionPolicy,
+    job_id: i32,
+    batch_size: u64,
+) -> Result<(), AppError> {
+    let job = BulkUserJobs::find_by_id(job_id)
+        .one(db)
+        .await?
+        .ok_or(AppError::NotFound("Bulk user job"))?;
+    let action = BulkUserAction::parse(&job.action)
+        .ok_or_else(|| AppError::Internal(format!("Unknown bulk action {}", job.action)))?;
+    while run_batch(db, redis, policy, &job, action, batch_size).await? > 0 {}
+    Ok(())
+}
+
+const BULK_USER_JOBS: JobTable = JobTable {
+    table: "bulk_user_jobs",
+    id_column: "job_id",
+    label: "Bulk user job",
+};
+
+pub fn spawn_bulk_user_worker(db: DatabaseConnection, redis: redis::Client, policy: SessionPolicy) {
+    let batch_size = env_or("BULK_USER_BATCH_SIZE", 100u64).max(1);
+    let policy = Arc::new(policy);
+    let worker_db = db.clone();
+    BULK_USER_JOBS.spawn_worker(db, BULK_USER_POLL_SECONDS, move |job_id| {
+        let (db, redis, policy) = (worker_db.clone(), redis.clone(), policy.clone());
+        async move { run_job(&db, &redis, &policy, job_id, batch_size).await }
+    });
+}
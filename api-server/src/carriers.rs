@@ -0,0 +1,154 @@
+use crate::{entity::order_shipments::Model as OrderShipmentsModel, error::AppError};
+use async_graphql::{Enum, SimpleObject};
+use lazy_regex::regex;
+use sea_orm::prelude::DateTimeWithTimeZone;
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Carrier {
+    Dhl,
+    Dpd,
+    Fedex,
+    Gls,
+    Ups,
+    Usps,
+}
+
+impl Carrier {
+    pub const ALL: [Carrier; 6] = [
+        Carrier::Dhl,
+        Carrier::Dpd,
+        Carrier::Fedex,
+        Carrier::Gls,
+        Carrier::Ups,
+        Carrier::Usps,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Carrier::Dhl => "DHL",
+            Carrier::Dpd => "DPD",
+            Carrier::Fedex => "FEDEX",
+            Carrier::Gls => "GLS",
+            Carrier::Ups => "UPS",
+            Carrier::Usps => "USPS",
+        }
+    }
+
+    pub fn from_code(code: &str) -> Option<Carrier> {
+        Self::ALL
+            .into_iter()
+            .find(|carrier| carrier.as_str() == code)
+    }
+
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            Carrier::Dhl => "DHL",
+            Carrier::Dpd => "DPD",
+            Carrier::Fedex => "FedEx",
+            Carrier::Gls => "GLS",
+            Carrier::Ups => "UPS",
+            Carrier::Usps => "USPS",
+        }
+    }
+
+    // formats the carriers print on their labels, checked after normalize_tracking_number
+    fn is_valid_tracking_number(&self, tracking_number: &str) -> bool {
+        match self {
+            Carrier::Dhl => regex!(r"^(\d{10}|\d{12}|\d{20}|JJD\d{18,20}|JVGL\d{16})$"),
+            Carrier::Dpd => regex!(r"^(\d{14}|\d{14}[A-Z])$"),
+            Carrier::Fedex => regex!(r"^(\d{12}|\d{15}|\d{20}|\d{22})$"),
+            Carrier::Gls => regex!(r"^(\d{11,12}|[A-Z0-9]{8})$"),
+            Carrier::Ups => regex!(r"^1Z[0-9A-Z]{16}$"),
+            Carrier::Usps => regex!(r"^(\d{20}|\d{22}|\d{26}|[A-Z]{2}\d{9}US)$"),
+        }
+        .is_match(tracking_number)
+    }
+
+    pub fn tracking_url(&self, tracking_number: &str) -> String {
+        match self {
+            Carrier::Dhl => format!(
+                "https://www.dhl.com/global-en/home/tracking/tracking-parcel.html?submit=1&tracking-id={}",
+                tracking_number
+            ),
+            Carrier::Dpd => format!(
+                "https://tracking.dpd.de/status/en_US/parcel/{}",
+                tracking_number
+            ),
+            Carrier::Fedex => format!(
+                "https://www.fedex.com/fedextrack/?trknbr={}",
+                tracking_number
+            ),
+            Carrier::Gls => format!("https://gls-group.eu/track/{}", tracking_number),
+            Carrier::Ups => format!("https://www.ups.com/track?tracknum={}", tracking_number),
+            Carrier::Usps => format!(
+                "https://tools.usps.com/go/TrackConfirmAction?tLabels={}",
+                tracking_number
+            ),
+        }
+    }
+}
+
+// suppliers paste numbers the way the label prints them, with spaces and in lower case
+fn normalize_tracking_number(tracking_number: &str) -> String {
+    tracking_number
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .collect::<String>()
+        .to_uppercase()
+}
+
+pub fn validate_tracking_number(
+    carrier: Carrier,
+    tracking_number: &str,
+) -> Result<String, AppError> {
+    let tracking_number = normalize_tracking_number(tracking_number);
+    if !carrier.is_valid_tracking_number(&tracking_number) {
+        return Err(AppError::Validation {
+            message: format!(
+                "{} is not a valid {} tracking number",
+                tracking_number,
+                carrier.display_name()
+            ),
+            failed_rules: vec![format!("TRACKING_NUMBER_{}", carrier.as_str())],
+        });
+    }
+    Ok(tracking_number)
+}
+
+#[derive(SimpleObject)]
+pub struct Shipment {
+    pub carrier: Carrier,
+    pub tracking_number: String,
+    pub tracking_url: String,
+}
+
+impl Shipment {
+    // None until the order was handed to a carrier
+    pub fn from_order(carrier: Option<&str>, tracking_number: Option<String>) -> Option<Shipment> {
+        let carrier = Carrier::from_code(carrier?)?;
+        let tracking_number = tracking_number?;
+        Some(Shipment {
+            carrier,
+            tracking_url: carrier.tracking_url(&tracking_number),
+            tracking_number,
+        })
+    }
+}
+
+// one supplier's lines of an order handed to a carrier
+#[derive(SimpleObject)]
+pub struct SupplierShipment {
+    pub supplier_id: i32,
+    pub shipment: Shipment,
+    pub shipped_at: DateTimeWithTimeZone,
+}
+
+impl SupplierShipment {
+    pub fn from_model(val: OrderShipmentsModel) -> Option<SupplierShipment> {
+        Some(SupplierShipment {
+            supplier_id: val.supplier_id,
+            shipment: Shipment::from_order(Some(&val.carrier), Some(val.tracking_number))?,
+            shipped_at: val.shipped_at,
+        })
+    }
+}
@@ -0,0 +1,180 @@
+use crate::{
+    config::CartExpiryPolicy,
+    entity::{
+        cart_items, expired_cart_items, expired_carts,
+        prelude::{CartItems, ExpiredCartItems, ExpiredCarts, Products, ShoppingCarts},
+        shopping_carts,
+    },
+    error::AppError,
+};
+use chrono::{Duration, Utc};
+use sea_orm::{
+    prelude::{DateTimeWithTimeZone, Decimal},
+    sea_query::{LockBehavior, LockType},
+    ActiveValue::Set,
+    ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, QuerySelect,
+    TransactionTrait,
+};
+use std::{
+    collections::BTreeMap,
+    sync::{LazyLock, Mutex},
+};
+
+const CART_EXPIRY_BATCH_SIZE: u64 = 100;
+
+// since startup, read by the cartExpiryMetrics admin query
+static COUNTS: LazyLock<Mutex<CartExpiryCounts>> = LazyLock::new(Default::default);
+
+#[derive(Clone, Copy, Default)]
+pub struct CartExpiryCounts {
+    // guest carts included, though only customers' ones can be restored
+    pub carts_expired: u64,
+    // units, not lines
+    pub items_expired: u64,
+    // what the expired carts held at base prices
+    pub value_expired: Decimal,
+    pub carts_restored: u64,
+}
+
+impl CartExpiryCounts {
+    fn add(&mut self, other: &CartExpiryCounts) {
+        self.carts_expired += other.carts_expired;
+        self.items_expired += other.items_expired;
+        self.value_expired += other.value_expired;
+        self.carts_restored += other.carts_restored;
+    }
+}
+
+pub fn cart_expiry_counts() -> CartExpiryCounts {
+    COUNTS.lock().map(|counts| *counts).unwrap_or_default()
+}
+
+pub fn count_restored_cart() {
+    if let Ok(mut counts) = COUNTS.lock() {
+        counts.carts_restored += 1;
+    }
+}
+
+// one transaction per batch, carts another instance is already expiring are skipped
+async fn expire_batch(
+    db: &DatabaseConnection,
+    cutoff: DateTimeWithTimeZone,
+) -> Result<(CartExpiryCounts, usize), AppError> {
+    let txn = db.begin().await?;
+
+    let carts = ShoppingCarts::find()
+        .filter(shopping_carts::Column::UpdatedAt.lt(cutoff))
+        .order_by_asc(shopping_carts::Column::UpdatedAt)
+        .limit(CART_EXPIRY_BATCH_SIZE)
+        .lock_with_behavior(LockType::Update, LockBehavior::SkipLocked)
+        .all(&txn)
+        .await?;
+    if carts.is_empty() {
+        return Ok((CartExpiryCounts::default(), 0));
+    }
+    let cart_ids: Vec<i32> = carts.iter().map(|cart| cart.cart_id).collect();
+
+    // products added twice show up as two rows, the snapshot keeps one line per product
+    let mut lines: BTreeMap<i32, BTreeMap<i32, (i32, Decimal)>> = BTreeMap::new();
+    for (item, product) in CartItems::find()
+        .filter(cart_items::Column::CartId.is_in(cart_ids.clone()))
+        .find_also_related(Products)
+        .all(&txn)
+        .await?
+    {
+        let Some(product) = product else {
+            continue;
+        };
+        let line = lines
+            .entry(item.cart_id)
+            .or_default()
+            .entry(item.product_id)
+            .or_insert((0, product.base_price));
+        line.0 += item.quantity;
+    }
+
+    let mut counts = CartExpiryCounts::default();
+    for cart in &carts {
+        let lines = lines.remove(&cart.cart_id).unwrap_or_default();
+        let units: i32 = lines.values().map(|(quantity, _)| quantity).sum();
+        let value: Decimal = lines
+            .values()
+            .map(|(quantity, unit_price)| Decimal::from(*quantity) * unit_price)
+            .sum();
+        counts.carts_expired += 1;
+        counts.items_expired += units.max(0) as u64;
+        counts.value_expired += value;
+
+        let Some(customer_id) = cart.customer_id.filter(|_| !lines.is_empty()) else {
+            continue;
+        };
+        let expired = ExpiredCarts::insert(expired_carts::ActiveModel {
+            customer_id: Set(customer_id),
+            item_count: Set(units),
+            total_value: Set(value),
+            last_active_at: Set(cart.updated_at),
+            ..Default::default()
+        })
+        .exec_with_returning(&txn)
+        .await?;
+        ExpiredCartItems::insert_many(lines.into_iter().map(
+            |(product_id, (quantity, unit_price))| expired_cart_items::ActiveModel {
+                expired_cart_id: Set(expired.expired_cart_id),
+                product_id: Set(product_id),
+                quantity: Set(quantity),
+                unit_price: Set(unit_price),
+            },
+        ))
+        .exec_without_returning(&txn)
+        .await?;
+    }
+
+    // cart_items go with their cart through the cascade
+    ShoppingCarts::delete_many()
+        .filter(shopping_carts::Column::CartId.is_in(cart_ids))
+        .exec(&txn)
+        .await?;
+    txn.commit().await?;
+
+    Ok((counts, carts.len()))
+}
+
+pub async fn expire_carts(
+    db: &DatabaseConnection,
+    policy: &CartExpiryPolicy,
+) -> Result<CartExpiryCounts, AppError> {
+    let cutoff = Utc::now().fixed_offset() - Duration::days(policy.ttl_days);
+
+    let mut run = CartExpiryCounts::default();
+    loop {
+        let (batch, carts) = expire_batch(db, cutoff).await?;
+        run.add(&batch);
+        if let Ok(mut counts) = COUNTS.lock() {
+            counts.add(&batch);
+        }
+        if carts < CART_EXPIRY_BATCH_SIZE as usize {
+            break;
+        }
+    }
+
+    Ok(run)
+}
+
+pub fn spawn_cart_expiry_job(db: DatabaseConnection, policy: CartExpiryPolicy) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(
+            policy.interval_minutes.max(1) * 60,
+        ));
+        loop {
+            interval.tick().await;
+            match expire_carts(&db, &policy).await {
+                Ok(run) if run.carts_expired > 0 => println!(
+                    "Expired {} carts holding {} items worth {}",
+                    run.carts_expired, run.items_expired, run.value_expired
+                ),
+                Ok(_) => {}
+                Err(e) => eprintln!("Cart expiry failed: {}", e),
+            }
+        }
+    });
+}
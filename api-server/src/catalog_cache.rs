@@ -0,0 +1,102 @@
+use crate::{
+    config::CatalogCachePolicy, error::AppError, hot_cache::HotCache, ids::TenantId,
+    retry::retry_redis,
+};
+use redis::AsyncCommands;
+use serde::{de::DeserializeOwned, Serialize};
+use std::future::Future;
+
+const VERSION_KEY_PREFIX: &str = "catalog_version:";
+
+// Answers of the public catalog queries, kept in the hot cache under the tenant's catalog version.
+// Changing a product bumps the version, which retires every cached answer of the tenant at once
+// instead of hunting down each key a product could show up under
+#[derive(Clone)]
+pub struct CatalogCache {
+    cache: HotCache,
+    redis: redis::Client,
+    policy: CatalogCachePolicy,
+}
+
+impl CatalogCache {
+    pub fn new(cache: HotCache, redis: redis::Client, policy: CatalogCachePolicy) -> Self {
+        Self {
+            cache,
+            redis,
+            policy,
+        }
+    }
+
+    // None while Redis is away, an answer cached under a version that can't be read might be stale
+    async fn version(&self, tenant: TenantId) -> Option<u64> {
+        let key = format!("{}{}", VERSION_KEY_PREFIX, tenant.0);
+        retry_redis("catalog_version_get", || async {
+            let mut connection = self.redis.get_multiplexed_async_connection().await?;
+            connection.get::<_, Option<u64>>(&key).await
+        })
+        .await
+        .map(|version| version.unwrap_or(0))
+        .map_err(|e| eprintln!("Catalog version of tenant {} unreadable: {}", tenant, e))
+        .ok()
+    }
+
+    // the query's answer for these arguments, loaded when nothing current is cached. Sandbox
+    // requests read their own copy of the catalog and get answers of their own. A TTL of 0 turns
+    // the cache off
+    pub async fn get_or_load<T, A, F, Fut>(
+        &self,
+        tenant: TenantId,
+        sandbox: bool,
+        query: &str,
+        args: &A,
+        load: F,
+    ) -> Result<T, AppError>
+    where
+        T: Serialize + DeserializeOwned,
+        A: Serialize,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, AppError>>,
+    {
+        if self.policy.ttl_secs == 0 {
+            return load().await;
+        }
+        let Some(version) = self.version(tenant).await else {
+            return load().await;
+        };
+        let args = serde_json::to_string(args)
+            .map_err(|e| AppError::Internal(format!("Failed to key {}: {}", query, e)))?;
+
+        self.cache
+            .get_or_load_for(
+                &format!(
+                    "catalog:{}:{}:{}:{}:{}",
+                    tenant.0,
+                    if sandbox { "sandbox" } else { "live" },
+                    version,
+                    query,
+                    args
+                ),
+                self.policy.ttl_secs,
+                load,
+            )
+            .await
+    }
+
+    // the change is already stored, a failed bump is logged and the answers age out with the TTL
+    pub async fn invalidate(&self, tenant: TenantId) {
+        bump_catalog_version(&self.redis, tenant).await;
+    }
+}
+
+// for the workers that change products without a CatalogCache at hand
+pub async fn bump_catalog_version(redis: &redis::Client, tenant: TenantId) {
+    let key = format!("{}{}", VERSION_KEY_PREFIX, tenant.0);
+    if let Err(e) = retry_redis("catalog_version_bump", || async {
+        let mut connection = redis.get_multiplexed_async_connection().await?;
+        connection.incr::<_, _, ()>(&key, 1).await
+    })
+    .await
+    {
+        eprintln!("Catalog cache of tenant {} not invalidated: {}", tenant, e);
+    }
+}
@@ -0,0 +1,698 @@
+use crate::error::AppError;
+use sea_orm::prelude::Decimal;
+use std::{env, str::FromStr};
+
+// reads an optional setting from the environment, falling back when it is missing or unparsable
+pub fn env_or<T: FromStr>(key: &str, default: T) -> T {
+    env::var(key)
+        .ok()
+        .and_then(|value| value.parse::<T>().ok())
+        .unwrap_or(default)
+}
+
+// development conveniences (resolver timings and the like) that must stay off in production
+pub fn dev_mode() -> bool {
+    env_or("DEV_MODE", false)
+}
+
+// lets the GraphiQL page sign in as the seeded GRAPHIQL_<ROLE>_EMAIL accounts, kept apart from
+// DEV_MODE so turning on resolver timings never hands out tokens
+pub fn graphiql_sign_in() -> bool {
+    env_or("GRAPHIQL_SIGN_IN", false)
+}
+
+// the key @pii values are hashed with in request logs, read once so a missing one stops startup
+#[derive(Clone)]
+pub struct PiiPolicy {
+    pub hash_secret: String,
+}
+
+impl PiiPolicy {
+    pub fn from_env() -> Result<Self, AppError> {
+        let hash_secret = env::var("PII_HASH_SECRET")
+            .or_else(|_| env::var("TOKEN_SECRET"))
+            .map_err(|_| {
+                AppError::Internal("PII_HASH_SECRET or TOKEN_SECRET must be set".to_string())
+            })?;
+        Ok(Self { hash_secret })
+    }
+}
+
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    pub require_uppercase: bool,
+    pub require_lowercase: bool,
+    pub require_digit: bool,
+    pub require_symbol: bool,
+    pub check_breached: bool,
+}
+
+impl PasswordPolicy {
+    pub fn from_env() -> Self {
+        Self {
+            min_length: env_or("PASSWORD_MIN_LENGTH", 8),
+            require_uppercase: env_or("PASSWORD_REQUIRE_UPPERCASE", true),
+            require_lowercase: env_or("PASSWORD_REQUIRE_LOWERCASE", true),
+            require_digit: env_or("PASSWORD_REQUIRE_DIGIT", true),
+            require_symbol: env_or("PASSWORD_REQUIRE_SYMBOL", true),
+            check_breached: env_or("PASSWORD_CHECK_BREACHED", false),
+        }
+    }
+}
+
+pub struct StepUpPolicy {
+    // distinct emails tried from one IP inside the window before that IP counts as suspicious
+    pub distinct_emails: i64,
+    pub window_minutes: i64,
+    pub flag_hours: i64,
+    pub otp_ttl_minutes: i64,
+    pub otp_max_attempts: i32,
+}
+
+impl StepUpPolicy {
+    pub fn from_env() -> Self {
+        Self {
+            distinct_emails: env_or("STEP_UP_DISTINCT_EMAILS", 5),
+            window_minutes: env_or("STEP_UP_WINDOW_MINUTES", 15),
+            flag_hours: env_or("STEP_UP_FLAG_HOURS", 24),
+            otp_ttl_minutes: env_or("STEP_UP_OTP_TTL_MINUTES", 10),
+            otp_max_attempts: env_or("STEP_UP_OTP_MAX_ATTEMPTS", 5),
+        }
+    }
+}
+
+pub struct SessionPolicy {
+    // access tokens are only recalled through the revocation list, so they live briefly
+    pub access_token_minutes: i64,
+    // refresh tokens are single use, every refresh hands out a new one with a fresh lifetime
+    pub refresh_token_days: i64,
+}
+
+impl SessionPolicy {
+    pub fn from_env() -> Self {
+        Self {
+            access_token_minutes: env_or("ACCESS_TOKEN_MINUTES", 15),
+            refresh_token_days: env_or("REFRESH_TOKEN_DAYS", 30),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct JwtPolicy {
+    // who signs the tokens, a token from anyone else is rejected
+    pub issuer: String,
+    // services the tokens are meant for, a token has to name at least one of them
+    pub audience: Vec<String>,
+    // when set the tenant id is also published under this claim, for services with their own naming
+    pub tenant_claim: Option<String>,
+    // when set the held roles are also published under this claim
+    pub permissions_claim: Option<String>,
+}
+
+impl JwtPolicy {
+    pub fn from_env() -> Self {
+        let claim_name = |key: &str| {
+            env::var(key)
+                .ok()
+                .map(|name| name.trim().to_string())
+                .filter(|name| !name.is_empty())
+        };
+        let audience: Vec<String> = env_or("JWT_AUDIENCE", String::new())
+            .split(',')
+            .map(|audience| audience.trim().to_string())
+            .filter(|audience| !audience.is_empty())
+            .collect();
+
+        Self {
+            issuer: env_or("JWT_ISSUER", "sdb-api-server".to_string()),
+            audience: if audience.is_empty() {
+                vec!["sdb-api-server".to_string()]
+            } else {
+                audience
+            },
+            tenant_claim: claim_name("JWT_TENANT_CLAIM"),
+            permissions_claim: claim_name("JWT_PERMISSIONS_CLAIM"),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RegionConfig {
+    // when set this deployment only serves (and records orders for) the one region
+    pub pinned_region: Option<String>,
+    // regions a product's price overrides have to cover once a supplier sets any
+    pub required_price_regions: Vec<String>,
+}
+
+impl RegionConfig {
+    pub fn from_env() -> Self {
+        Self {
+            pinned_region: env::var("DEPLOYMENT_REGION")
+                .ok()
+                .filter(|region| !region.is_empty()),
+            required_price_regions: env_or("PRICE_REQUIRED_REGIONS", String::new())
+                .split(',')
+                .map(|region| region.trim().to_string())
+                .filter(|region| !region.is_empty())
+                .collect(),
+        }
+    }
+
+    pub fn effective_region(&self, requested: Option<String>) -> Option<String> {
+        self.pinned_region.clone().or(requested)
+    }
+}
+
+#[derive(Clone)]
+pub struct UploadPolicy {
+    pub max_image_bytes: usize,
+    pub max_csv_bytes: usize,
+    // longest edge an uploaded image may have, in pixels
+    pub max_image_dimension: u32,
+    pub allowed_image_types: Vec<String>,
+    pub max_video_bytes: usize,
+    pub max_zip_bytes: usize,
+    // files one ZIP may hold, directories not counted
+    pub max_zip_entries: usize,
+    // what all files of one ZIP may add up to once unpacked
+    pub max_zip_unpacked_bytes: usize,
+    pub max_pdf_bytes: usize,
+}
+
+impl UploadPolicy {
+    pub fn from_env() -> Self {
+        Self {
+            max_image_bytes: env_or("UPLOAD_MAX_IMAGE_BYTES", 5 * 1024 * 1024),
+            max_csv_bytes: env_or("UPLOAD_MAX_CSV_BYTES", 2 * 1024 * 1024),
+            max_image_dimension: env_or("UPLOAD_MAX_IMAGE_DIMENSION", 4096),
+            allowed_image_types: env_or(
+                "UPLOAD_IMAGE_TYPES",
+                "image/png,image/jpeg,image/webp".to_string(),
+            )
+            .split(',')
+            .map(|mime| mime.trim().to_string())
+            .filter(|mime| !mime.is_empty())
+            .collect(),
+            max_video_bytes: env_or("UPLOAD_MAX_VIDEO_BYTES", 100 * 1024 * 1024),
+            max_zip_bytes: env_or("UPLOAD_MAX_ZIP_BYTES", 50 * 1024 * 1024),
+            max_zip_entries: env_or("UPLOAD_MAX_ZIP_ENTRIES", 500),
+            max_zip_unpacked_bytes: env_or("UPLOAD_MAX_ZIP_UNPACKED_BYTES", 200 * 1024 * 1024),
+            max_pdf_bytes: env_or("UPLOAD_MAX_PDF_BYTES", 10 * 1024 * 1024),
+        }
+    }
+
+    // body cap for multipart GraphQL requests, the largest upload plus room for the envelope
+    pub fn body_limit(&self) -> usize {
+        self.max_image_bytes
+            .max(self.max_csv_bytes)
+            .max(self.max_video_bytes)
+            .max(self.max_zip_bytes)
+            .max(self.max_pdf_bytes)
+            + 64 * 1024
+    }
+}
+
+pub struct ReviewPolicy {
+    // days after the most recent purchase of a product during which it can be reviewed
+    pub window_days: i64,
+}
+
+impl ReviewPolicy {
+    pub fn from_env() -> Self {
+        Self {
+            window_days: env_or("REVIEW_WINDOW_DAYS", 90),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct TaxPolicy {
+    // percentage added at checkout for customers without a validated VAT ID
+    pub vat_rate: Decimal,
+}
+
+impl TaxPolicy {
+    pub fn from_env() -> Self {
+        Self {
+            vat_rate: env_or("VAT_RATE", Decimal::ZERO),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct EmailVerificationPolicy {
+    pub token_hours: i64,
+    // the page that takes the token, the link in the email is <url>/<token>
+    pub url: String,
+    // orders and checkout fail for customers who haven't verified their address yet
+    pub required_for_purchases: bool,
+}
+
+impl EmailVerificationPolicy {
+    pub fn from_env() -> Self {
+        Self {
+            token_hours: env_or("EMAIL_VERIFICATION_TOKEN_HOURS", 24),
+            url: env_or(
+                "EMAIL_VERIFICATION_URL",
+                format!("http://localhost:{}/verify", env_or("PORT", 8000)),
+            ),
+            required_for_purchases: env_or("REQUIRE_VERIFIED_EMAIL_FOR_PURCHASES", false),
+        }
+    }
+}
+
+pub struct PasswordResetPolicy {
+    pub token_minutes: i64,
+    // the page that takes the token, the link in the email is <url>/<token>
+    pub url: String,
+}
+
+impl PasswordResetPolicy {
+    pub fn from_env() -> Self {
+        Self {
+            token_minutes: env_or("PASSWORD_RESET_TOKEN_MINUTES", 30),
+            url: env_or(
+                "PASSWORD_RESET_URL",
+                "http://localhost:8000/reset-password".to_string(),
+            ),
+        }
+    }
+}
+
+pub struct GuestOrderPolicy {
+    // the page that takes the lookup token, the link mailed after a guest checkout is <url>/<token>
+    pub url: String,
+}
+
+impl GuestOrderPolicy {
+    pub fn from_env() -> Self {
+        Self {
+            url: env_or(
+                "GUEST_ORDER_URL",
+                format!("http://localhost:{}/guest-orders", env_or("PORT", 8000)),
+            ),
+        }
+    }
+}
+
+pub struct NewsletterPolicy {
+    // the page that takes the confirmation token, the link in the email is <url>/<token>
+    pub confirmation_url: String,
+}
+
+impl NewsletterPolicy {
+    pub fn from_env() -> Self {
+        Self {
+            confirmation_url: env_or(
+                "NEWSLETTER_CONFIRMATION_URL",
+                format!(
+                    "http://localhost:{}/newsletter/confirm",
+                    env_or("PORT", 8000)
+                ),
+            ),
+        }
+    }
+}
+
+pub struct WelcomePolicy {
+    // percentage taken off a customer's first order, 0 turns the welcome discount off
+    pub discount_percent: Decimal,
+}
+
+impl WelcomePolicy {
+    pub fn from_env() -> Self {
+        Self {
+            discount_percent: env_or("WELCOME_DISCOUNT_PERCENT", Decimal::ZERO)
+                .clamp(Decimal::ZERO, Decimal::ONE_HUNDRED),
+        }
+    }
+}
+
+pub struct SponsorshipPolicy {
+    // sponsored results shown above the first page of a search
+    pub slots: u64,
+    // repeated clicks from one IP inside this window are not billed again
+    pub click_dedup_minutes: i64,
+}
+
+impl SponsorshipPolicy {
+    pub fn from_env() -> Self {
+        Self {
+            slots: env_or("SPONSORED_SLOTS", 2),
+            click_dedup_minutes: env_or("SPONSORED_CLICK_DEDUP_MINUTES", 30),
+        }
+    }
+}
+
+pub struct DuplicatePolicy {
+    // name similarity (0 to 1) above which a supplier is warned about a likely duplicate
+    pub similarity_threshold: f64,
+}
+
+impl DuplicatePolicy {
+    pub fn from_env() -> Self {
+        Self {
+            similarity_threshold: env_or("PRODUCT_DUPLICATE_THRESHOLD", 0.6),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RetentionPolicy {
+    pub analytics_days: i64,
+    pub audit_log_days: i64,
+    // expired carts can be restored for this long, then what they held is dropped
+    pub cart_days: i64,
+    pub interval_hours: u64,
+    // scheduled runs only report what they would purge
+    pub dry_run: bool,
+}
+
+impl RetentionPolicy {
+    pub fn from_env() -> Self {
+        Self {
+            analytics_days: env_or("RETENTION_ANALYTICS_DAYS", 90),
+            audit_log_days: env_or("RETENTION_AUDIT_LOG_DAYS", 730),
+            cart_days: env_or("RETENTION_CART_DAYS", 14),
+            interval_hours: env_or("RETENTION_INTERVAL_HOURS", 24),
+            dry_run: env_or("RETENTION_DRY_RUN", false),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct CartExpiryPolicy {
+    // carts nobody has touched for this long are expired, customers' ones can be restored for a while
+    pub ttl_days: i64,
+    pub interval_minutes: u64,
+}
+
+impl CartExpiryPolicy {
+    pub fn from_env() -> Self {
+        Self {
+            ttl_days: env_or("CART_TTL_DAYS", 30),
+            interval_minutes: env_or("CART_EXPIRY_INTERVAL_MINUTES", 60),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct DigestPolicy {
+    // local hour (0 to 23) from which a supplier's daily digest goes out
+    pub send_hour: u32,
+    // products with this many units or fewer are listed as low on stock
+    pub low_stock_threshold: i32,
+}
+
+impl DigestPolicy {
+    pub fn from_env() -> Self {
+        Self {
+            send_hour: env_or("DIGEST_SEND_HOUR", 8u32).min(23),
+            low_stock_threshold: env_or("DIGEST_LOW_STOCK_THRESHOLD", 5),
+        }
+    }
+}
+
+// the currency base prices are in, orders are charged in it unless a regional override prices
+// them in another
+#[derive(Clone)]
+pub struct CurrencyPolicy {
+    pub currency: String,
+}
+
+impl CurrencyPolicy {
+    pub fn from_env() -> Self {
+        Self {
+            currency: env_or("STORE_CURRENCY", "EUR".to_string())
+                .trim()
+                .to_uppercase(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct AccountingConfig {
+    // DATEV SKR03 defaults, revenue booked against the collective debtor account
+    pub datev_revenue_account: String,
+    pub datev_debtor_account: String,
+}
+
+impl AccountingConfig {
+    pub fn from_env() -> Self {
+        Self {
+            datev_revenue_account: env_or("DATEV_REVENUE_ACCOUNT", "8400".to_string()),
+            datev_debtor_account: env_or("DATEV_DEBTOR_ACCOUNT", "10000".to_string()),
+        }
+    }
+}
+
+pub struct RetryPolicy {
+    // attempts including the first one, transient errors past this are returned to the caller
+    pub max_attempts: u32,
+    pub base_delay_ms: u64,
+    pub max_delay_ms: u64,
+}
+
+impl RetryPolicy {
+    pub fn from_env() -> Self {
+        Self {
+            max_attempts: env_or("RETRY_MAX_ATTEMPTS", 3),
+            base_delay_ms: env_or("RETRY_BASE_DELAY_MS", 50),
+            max_delay_ms: env_or("RETRY_MAX_DELAY_MS", 1000),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct HotCachePolicy {
+    // how long an instance serves its own copy before asking Redis again
+    pub local_ttl_secs: u64,
+    // how long the copy shared through Redis lives before the database is asked again
+    pub shared_ttl_secs: u64,
+    // entries per instance, the least recently read goes first
+    pub capacity: usize,
+}
+
+impl HotCachePolicy {
+    pub fn from_env() -> Self {
+        Self {
+            local_ttl_secs: env_or("HOT_CACHE_LOCAL_TTL_SECS", 5),
+            shared_ttl_secs: env_or("HOT_CACHE_SHARED_TTL_SECS", 60),
+            capacity: env_or("HOT_CACHE_CAPACITY", 1024),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct CatalogCachePolicy {
+    // how long a cached catalog answer is served, product changes retire it before that.
+    // Stock sold through orders isn't a product change, so counts may lag by up to this long
+    pub ttl_secs: u64,
+}
+
+impl CatalogCachePolicy {
+    pub fn from_env() -> Self {
+        Self {
+            ttl_secs: env_or("CATALOG_CACHE_TTL_SECS", 30),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct AnalyticsPolicy {
+    // how long supplierAnalytics answers are reused, 0 counts on every request
+    pub supplier_cache_secs: u64,
+}
+
+impl AnalyticsPolicy {
+    pub fn from_env() -> Self {
+        Self {
+            supplier_cache_secs: env_or("SUPPLIER_ANALYTICS_CACHE_SECS", 3600),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum StockLocking {
+    // read, then decrement only if nobody changed the stock in between, rereading on conflict
+    Optimistic,
+    // SELECT ... FOR UPDATE, concurrent orders for the same product wait for each other
+    Pessimistic,
+}
+
+impl FromStr for StockLocking {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "optimistic" => Ok(StockLocking::Optimistic),
+            "pessimistic" => Ok(StockLocking::Pessimistic),
+            _ => Err(()),
+        }
+    }
+}
+
+pub struct StockPolicy {
+    pub locking: StockLocking,
+    // optimistic only, conflicting reservations past this fail the order instead of trying again
+    pub optimistic_attempts: u32,
+}
+
+impl StockPolicy {
+    pub fn from_env() -> Self {
+        Self {
+            locking: env_or("STOCK_LOCKING", StockLocking::Optimistic),
+            optimistic_attempts: env_or("STOCK_OPTIMISTIC_ATTEMPTS", 5),
+        }
+    }
+}
+
+// how deep and how costly a single GraphQL operation may get, anything over is refused before it runs
+pub struct QueryLimits {
+    pub max_depth: usize,
+    pub max_complexity: usize,
+    // body cap for GraphQL requests that don't carry files
+    pub max_body_bytes: usize,
+}
+
+impl QueryLimits {
+    pub fn from_env() -> Self {
+        Self {
+            max_depth: env_or("GRAPHQL_MAX_DEPTH", 15),
+            max_complexity: env_or("GRAPHQL_MAX_COMPLEXITY", 5000),
+            max_body_bytes: env_or("GRAPHQL_MAX_BODY_BYTES", 1024 * 1024),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct HealthPolicy {
+    // how long /readyz waits on the database and on Redis before counting them as down
+    pub timeout_ms: u64,
+}
+
+impl HealthPolicy {
+    pub fn from_env() -> Self {
+        Self {
+            timeout_ms: env_or("HEALTH_CHECK_TIMEOUT_MS", 2000),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct MetricsPolicy {
+    // bearer token scrapers send to /metrics on the public port, which isn't served without one
+    pub token: Option<String>,
+    // a listener of its own serving /metrics without a token, e.g. 127.0.0.1:9100, for scrapers on
+    // the private network
+    pub internal_addr: Option<String>,
+}
+
+impl MetricsPolicy {
+    pub fn from_env() -> Self {
+        Self {
+            token: env::var("METRICS_TOKEN")
+                .ok()
+                .filter(|token| !token.is_empty()),
+            internal_addr: env::var("METRICS_ADDR")
+                .ok()
+                .filter(|addr| !addr.is_empty()),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct TelemetryPolicy {
+    // OTLP gRPC collector the spans go to, tracing stays off without one
+    pub otlp_endpoint: Option<String>,
+    pub service_name: String,
+}
+
+impl TelemetryPolicy {
+    pub fn from_env() -> Self {
+        Self {
+            otlp_endpoint: env::var("OTEL_EXPORTER_OTLP_ENDPOINT")
+                .ok()
+                .filter(|endpoint| !endpoint.is_empty()),
+            service_name: env_or("OTEL_SERVICE_NAME", "sdb-api-server".to_string()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum FilterAction {
+    Allow,
+    // the match is replaced and the text saved
+    Mask,
+    // the text is rejected with what was found
+    Block,
+}
+
+impl FromStr for FilterAction {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "allow" => Ok(FilterAction::Allow),
+            "mask" => Ok(FilterAction::Mask),
+            "block" => Ok(FilterAction::Block),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct FieldFilter {
+    // phone numbers and email addresses, kept off the platform's public text so deals stay on it
+    pub contact_details: FilterAction,
+    // words from flagged_words
+    pub language: FilterAction,
+}
+
+impl FieldFilter {
+    fn from_env(field: &str, contact_details: FilterAction, language: FilterAction) -> Self {
+        Self {
+            contact_details: env_or(
+                &format!("CONTENT_FILTER_{}_CONTACT", field),
+                contact_details,
+            ),
+            language: env_or(&format!("CONTENT_FILTER_{}_LANGUAGE", field), language),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ContentFilterPolicy {
+    pub reviews: FieldFilter,
+    pub order_messages: FieldFilter,
+    pub product_descriptions: FieldFilter,
+    // matched as whole words without regard to case
+    pub flagged_words: Vec<String>,
+}
+
+impl ContentFilterPolicy {
+    pub fn from_env() -> Self {
+        Self {
+            reviews: FieldFilter::from_env("REVIEWS", FilterAction::Mask, FilterAction::Mask),
+            order_messages: FieldFilter::from_env(
+                "ORDER_MESSAGES",
+                FilterAction::Mask,
+                FilterAction::Allow,
+            ),
+            product_descriptions: FieldFilter::from_env(
+                "PRODUCT_DESCRIPTIONS",
+                FilterAction::Mask,
+                FilterAction::Block,
+            ),
+            flagged_words: env_or(
+                "CONTENT_FILTER_WORDS",
+                "asshole,bastard,bitch,cunt,dickhead,fuck,fucking,motherfucker,shit,wanker"
+                    .to_string(),
+            )
+            .split(',')
+            .map(|word| word.trim().to_lowercase())
+            .filter(|word| !word.is_empty())
+            .collect(),
+        }
+    }
+}
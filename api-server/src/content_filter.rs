@@ -0,0 +1,220 @@
+use crate::{
+    config::{ContentFilterPolicy, FieldFilter, FilterAction},
+    error::AppError,
+};
+use lazy_regex::{regex, Captures};
+
+const MASKED_CONTACT: &str = "[contact removed]";
+
+// text customers and suppliers write that other people read
+#[derive(Clone, Copy)]
+pub enum ContentField {
+    Review,
+    OrderMessage,
+    ProductDescription,
+}
+
+impl ContentField {
+    fn rules(&self, policy: &ContentFilterPolicy) -> FieldFilter {
+        match self {
+            ContentField::Review => policy.reviews,
+            ContentField::OrderMessage => policy.order_messages,
+            ContentField::ProductDescription => policy.product_descriptions,
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            ContentField::Review => "Review",
+            ContentField::OrderMessage => "Message",
+            ContentField::ProductDescription => "Description",
+        }
+    }
+}
+
+// runs of 9 to 15 digits with the usual separators in between, shorter ones are too often prices,
+// dates or order numbers. Barcodes, ISBNs and tracking numbers are left alone
+fn mask_phone_numbers(text: &str, found: &mut bool) -> String {
+    regex!(r"\+?\(?\d[\d\s().\-/]{7,}\d")
+        .replace_all(text, |caps: &Captures| {
+            let matched = caps.get(0).unwrap();
+            let digits = matched
+                .as_str()
+                .chars()
+                .filter(char::is_ascii_digit)
+                .count();
+            if (9..=15).contains(&digits) && !is_product_or_parcel_code(text, matched) {
+                *found = true;
+                MASKED_CONTACT.to_string()
+            } else {
+                matched.as_str().to_string()
+            }
+        })
+        .into_owned()
+}
+
+fn is_product_or_parcel_code(text: &str, matched: lazy_regex::regex::Match) -> bool {
+    // part of a longer code with letters in it, like RR123456789DE or an ISBN ending in X
+    let before = text[..matched.start()].chars().next_back();
+    let after = text[matched.end()..].chars().next();
+    if before.is_some_and(char::is_alphanumeric) || after.is_some_and(char::is_alphanumeric) {
+        return true;
+    }
+
+    // labelled as a code, like "ISBN 978-3-16-148410-0" or "tracking no. 123456789012"
+    let label_start = text[..matched.start()]
+        .char_indices()
+        .rev()
+        .nth(30)
+        .map_or(0, |(i, _)| i);
+    if regex!(
+        r"(?i)\b(isbn(-1[03])?|ean(-?1[34])?|gtin(-?1[234])?|upc|barcode|tracking|track|sendung\w*|awb|waybill|consignment)\b[\s:#.]*(no\.?|nr\.?|number|code|id)?[\s:#.]*$"
+    )
+    .is_match(&text[label_start..matched.start()])
+    {
+        return true;
+    }
+
+    // barcodes and ISBNs are written without separators or with hyphens only, and carry a check
+    // digit, a phone number passes that by chance one time in ten
+    if matched
+        .as_str()
+        .chars()
+        .any(|c| !c.is_ascii_digit() && c != '-')
+    {
+        return false;
+    }
+    let digits: Vec<u32> = matched
+        .as_str()
+        .chars()
+        .filter_map(|c| c.to_digit(10))
+        .collect();
+    match digits.len() {
+        8 | 12 | 13 | 14 => has_gs1_check_digit(&digits),
+        10 => has_isbn10_check_digit(&digits),
+        _ => false,
+    }
+}
+
+// EAN-8, UPC-A, EAN-13 (and so ISBN-13) and GTIN-14 share the same check digit
+fn has_gs1_check_digit(digits: &[u32]) -> bool {
+    let (check, body) = digits.split_last().unwrap();
+    let sum: u32 = body
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, d)| if i % 2 == 0 { d * 3 } else { *d })
+        .sum();
+    (10 - sum % 10) % 10 == *check
+}
+
+fn has_isbn10_check_digit(digits: &[u32]) -> bool {
+    let sum: u32 = digits
+        .iter()
+        .enumerate()
+        .map(|(i, d)| (10 - i as u32) * d)
+        .sum();
+    sum.is_multiple_of(11)
+}
+
+fn mask_emails(text: &str, found: &mut bool) -> String {
+    regex!(r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}")
+        .replace_all(text, |_: &Captures| {
+            *found = true;
+            MASKED_CONTACT
+        })
+        .into_owned()
+}
+
+// the flagged word's first letter is kept, the rest starred out
+fn mask_flagged_words(text: &str, flagged_words: &[String], found: &mut bool) -> String {
+    regex!(r"[\p{L}\p{N}']+")
+        .replace_all(text, |caps: &Captures| {
+            let word = &caps[0];
+            if flagged_words.contains(&word.to_lowercase()) {
+                *found = true;
+                word.chars()
+                    .enumerate()
+                    .map(|(i, c)| if i == 0 { c } else { '*' })
+                    .collect()
+            } else {
+                word.to_string()
+            }
+        })
+        .into_owned()
+}
+
+// the text as it may be saved for the field, masked where the field's rules say so, or the
+// reason it can't be saved at all
+pub fn filter_content(
+    policy: &ContentFilterPolicy,
+    field: ContentField,
+    text: String,
+) -> Result<String, AppError> {
+    let rules = field.rules(policy);
+    let mut text = text;
+
+    if rules.contact_details != FilterAction::Allow {
+        let mut found = false;
+        let masked = mask_emails(&mask_phone_numbers(&text, &mut found), &mut found);
+        if found && rules.contact_details == FilterAction::Block {
+            return Err(AppError::Validation {
+                message: format!(
+                    "{} can't contain phone numbers or email addresses",
+                    field.label()
+                ),
+                failed_rules: vec!["contactDetails".to_string()],
+            });
+        }
+        text = masked;
+    }
+
+    if rules.language != FilterAction::Allow {
+        let mut found = false;
+        let masked = mask_flagged_words(&text, &policy.flagged_words, &mut found);
+        if found && rules.language == FilterAction::Block {
+            return Err(AppError::Validation {
+                message: format!("{} contains language that isn't allowed", field.label()),
+                failed_rules: vec!["language".to_string()],
+            });
+        }
+        text = masked;
+    }
+
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn masked(text: &str) -> bool {
+        let mut found = false;
+        mask_phone_numbers(text, &mut found);
+        found
+    }
+
+    #[test]
+    fn masks_phone_numbers() {
+        assert!(masked("call me on +44 20 7946 0958"));
+        assert!(masked("ring 0151-496-0123 after six"));
+        assert!(masked("(555) 010-4477"));
+    }
+
+    #[test]
+    fn leaves_barcodes_and_isbns_alone() {
+        assert!(!masked("EAN 4006381333931"));
+        assert!(!masked("barcode on the box: 4006381333931"));
+        assert!(!masked("the book 978-3-16-148410-0 is in stock"));
+        assert!(!masked("see 0-306-40615-2 for details"));
+        assert!(!masked("ISBN 0-8044-2957-X"));
+        assert!(!masked("gtin 00012345600012"));
+    }
+
+    #[test]
+    fn leaves_tracking_numbers_alone() {
+        assert!(!masked("tracking no. 123456789012"));
+        assert!(!masked("Sendungsnummer: 00340434161094042557"));
+        assert!(!masked("parcel RR123456789DE"));
+    }
+}
@@ -0,0 +1,90 @@
+use crate::{
+    entity::{prelude::Suppliers, products, suppliers},
+    error::AppError,
+};
+use sea_orm::{prelude::Decimal, ColumnTrait, ConnectionTrait, EntityTrait, QueryFilter};
+use std::collections::HashMap;
+
+// what customs needs about one order line, per unit
+pub struct CustomsLine {
+    pub hs_code: String,
+    pub customs_value: Decimal,
+    pub origin_country: String,
+}
+
+// countries are compared the way addresses store them, trimmed and upper case
+pub fn normalize_country(country: &str) -> String {
+    country.trim().to_uppercase()
+}
+
+// HS codes are 6 digits worldwide, countries extend them up to 10, dots and spaces are dropped
+pub fn normalize_hs_code(hs_code: &str) -> Result<String, AppError> {
+    let digits: String = hs_code
+        .chars()
+        .filter(|c| !matches!(c, '.' | ' '))
+        .collect();
+    if !(6..=10).contains(&digits.len()) || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return Err(AppError::Validation {
+            message: "HS codes are 6 to 10 digits".to_string(),
+            failed_rules: vec!["HS_CODE_FORMAT".to_string()],
+        });
+    }
+    Ok(digits)
+}
+
+// the customs data of the lines whose supplier ships from another country than the destination,
+// keyed by product. Suppliers that haven't set a ship-from country are taken as domestic
+pub async fn customs_lines<C: ConnectionTrait>(
+    db: &C,
+    destination_country: &str,
+    lines: &[(&products::Model, Decimal)],
+) -> Result<HashMap<i32, CustomsLine>, AppError> {
+    let destination = normalize_country(destination_country);
+    let origins: HashMap<i32, String> = Suppliers::find()
+        .filter(
+            suppliers::Column::SupplierId
+                .is_in(lines.iter().filter_map(|(product, _)| product.supplier_id)),
+        )
+        .filter(suppliers::Column::ShipFromCountry.is_not_null())
+        .all(db)
+        .await?
+        .into_iter()
+        .filter_map(|supplier| {
+            supplier
+                .ship_from_country
+                .map(|country| (supplier.supplier_id, normalize_country(&country)))
+        })
+        .collect();
+
+    let mut customs = HashMap::new();
+    let mut failed_rules = Vec::new();
+    for (product, unit_price) in lines {
+        let Some(origin) = product
+            .supplier_id
+            .and_then(|supplier_id| origins.get(&supplier_id))
+            .filter(|origin| **origin != destination)
+        else {
+            continue;
+        };
+        let Some(hs_code) = &product.hs_code else {
+            failed_rules.push(format!("{}: no HS code", product.name));
+            continue;
+        };
+        customs.insert(
+            product.product_id,
+            CustomsLine {
+                hs_code: hs_code.clone(),
+                customs_value: product.customs_value.unwrap_or(*unit_price),
+                origin_country: origin.clone(),
+            },
+        );
+    }
+
+    if !failed_rules.is_empty() {
+        return Err(AppError::Validation {
+            message: format!("Some products can't be shipped to {} yet", destination),
+            failed_rules,
+        });
+    }
+    Ok(customs)
+}
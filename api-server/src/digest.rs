@@ -0,0 +1,223 @@
+use crate::{
+    auth::ROLE_CUSTOMER,
+    config::DigestPolicy,
+    entity::{prelude::Suppliers, suppliers},
+    error::AppError,
+    ids::TenantId,
+    notifications::{send_templated, TemplateKey},
+};
+use sea_orm::{
+    prelude::DateTimeWithTimeZone, sea_query::Expr, ColumnTrait, Condition, DatabaseConnection,
+    DbBackend, EntityTrait, FromQueryResult, QueryFilter, Statement,
+};
+
+const DIGEST_POLL_SECONDS: u64 = 300;
+// how long an instance holds a digest while sending it, a claim left by an instance that
+// stopped mid-send runs out and the digest is sent by the next poll
+const DIGEST_CLAIM_MINUTES: u32 = 10;
+
+#[derive(FromQueryResult)]
+struct DueDigest {
+    supplier_id: i32,
+    tenant_id: i32,
+    name: String,
+    email: String,
+    locale: Option<String>,
+    last_digest_sent_at: Option<DateTimeWithTimeZone>,
+    // the supplier's calendar day the digest is sent on
+    local_date: String,
+}
+
+#[derive(FromQueryResult)]
+struct SupplierActivity {
+    new_orders: i64,
+    questions: i64,
+    reviews: i64,
+    low_stock: i64,
+    // the end of the window the counts cover, the next digest starts from here
+    window_end: DateTimeWithTimeZone,
+}
+
+// opted in suppliers past the send hour in their own time zone who haven't had today's digest yet
+async fn due_digests(
+    db: &DatabaseConnection,
+    policy: &DigestPolicy,
+) -> Result<Vec<DueDigest>, AppError> {
+    Ok(DueDigest::find_by_statement(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        r#"SELECT s.supplier_id, u.tenant_id, s.name, u.email, u.locale, s.last_digest_sent_at,
+                to_char(CURRENT_TIMESTAMP AT TIME ZONE s.timezone, 'YYYY-MM-DD') AS local_date
+            FROM suppliers s
+            JOIN users u ON u.user_id = s.user_id
+            WHERE s.digest_enabled
+                AND extract(hour FROM CURRENT_TIMESTAMP AT TIME ZONE s.timezone) >= $1
+                AND (s.last_digest_sent_at IS NULL
+                    OR (s.last_digest_sent_at AT TIME ZONE s.timezone)::date
+                        < (CURRENT_TIMESTAMP AT TIME ZONE s.timezone)::date)"#,
+        vec![(policy.send_hour as i32).into()],
+    ))
+    .all(db)
+    .await?)
+}
+
+// everything since the previous digest, the first one looks back a day
+async fn supplier_activity(
+    db: &DatabaseConnection,
+    supplier_id: i32,
+    since: Option<DateTimeWithTimeZone>,
+    low_stock_threshold: i32,
+) -> Result<SupplierActivity, AppError> {
+    SupplierActivity::find_by_statement(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        r#"WITH window_start AS (
+                SELECT COALESCE($2::timestamptz, CURRENT_TIMESTAMP - INTERVAL '1 day') AS since
+            )
+            SELECT
+                (SELECT count(DISTINCT o.order_id)
+                    FROM orders o
+                    JOIN order_items oi ON oi.order_id = o.order_id
+                    JOIN products p ON p.product_id = oi.product_id
+                    WHERE p.supplier_id = $1 AND o.order_date >= (SELECT since FROM window_start)
+                ) AS new_orders,
+                (SELECT count(*)
+                    FROM order_messages m
+                    WHERE m.sender_role = $3
+                        AND m.created_at >= (SELECT since FROM window_start)
+                        AND EXISTS (SELECT 1
+                            FROM order_items oi
+                            JOIN products p ON p.product_id = oi.product_id
+                            WHERE oi.order_id = m.order_id AND p.supplier_id = $1)
+                ) AS questions,
+                (SELECT count(*)
+                    FROM reviews r
+                    JOIN products p ON p.product_id = r.product_id
+                    WHERE p.supplier_id = $1 AND r.review_date >= (SELECT since FROM window_start)
+                ) AS reviews,
+                (SELECT count(*)
+                    FROM products p
+                    WHERE p.supplier_id = $1 AND p.stock_quantity <= $4
+                ) AS low_stock,
+                CURRENT_TIMESTAMP AS window_end"#,
+        vec![
+            supplier_id.into(),
+            since.into(),
+            ROLE_CUSTOMER.into(),
+            low_stock_threshold.into(),
+        ],
+    ))
+    .one(db)
+    .await?
+    .ok_or_else(|| AppError::Internal("Supplier activity query returned no row".to_string()))
+}
+
+// the claim keeps other instances from sending the same digest meanwhile. The digest only
+// counts as sent once the mail went out, a failed one is released and retried on the next poll
+async fn send_digest(
+    db: &DatabaseConnection,
+    policy: &DigestPolicy,
+    due: DueDigest,
+) -> Result<(), AppError> {
+    let claimed = Suppliers::update_many()
+        .col_expr(
+            suppliers::Column::DigestClaimedUntil,
+            Expr::cust(format!(
+                "CURRENT_TIMESTAMP + INTERVAL '{} minutes'",
+                DIGEST_CLAIM_MINUTES
+            )),
+        )
+        .filter(suppliers::Column::SupplierId.eq(due.supplier_id))
+        .filter(match due.last_digest_sent_at {
+            Some(sent_at) => suppliers::Column::LastDigestSentAt.eq(sent_at),
+            None => suppliers::Column::LastDigestSentAt.is_null(),
+        })
+        .filter(
+            Condition::any()
+                .add(suppliers::Column::DigestClaimedUntil.is_null())
+                .add(
+                    Expr::col(suppliers::Column::DigestClaimedUntil).lt(Expr::current_timestamp()),
+                ),
+        )
+        .exec(db)
+        .await?
+        .rows_affected;
+    if claimed == 0 {
+        return Ok(());
+    }
+
+    let supplier_id = due.supplier_id;
+    let result = deliver_digest(db, policy, due).await;
+    let mut release = Suppliers::update_many()
+        .col_expr(
+            suppliers::Column::DigestClaimedUntil,
+            Expr::value(Option::<DateTimeWithTimeZone>::None),
+        )
+        .filter(suppliers::Column::SupplierId.eq(supplier_id));
+    if let Ok(window_end) = &result {
+        release = release.col_expr(
+            suppliers::Column::LastDigestSentAt,
+            Expr::value(*window_end),
+        );
+    }
+    release.exec(db).await?;
+    result.map(|_| ())
+}
+
+// sends the digest, returning the end of the window it covered
+async fn deliver_digest(
+    db: &DatabaseConnection,
+    policy: &DigestPolicy,
+    due: DueDigest,
+) -> Result<DateTimeWithTimeZone, AppError> {
+    let activity = supplier_activity(
+        db,
+        due.supplier_id,
+        due.last_digest_sent_at,
+        policy.low_stock_threshold,
+    )
+    .await?;
+
+    send_templated(
+        db,
+        TenantId(due.tenant_id),
+        TemplateKey::SupplierDigest,
+        due.email,
+        due.locale.as_deref(),
+        &[
+            ("supplier_name", due.name),
+            ("date", due.local_date),
+            ("new_orders", activity.new_orders.to_string()),
+            ("questions", activity.questions.to_string()),
+            ("reviews", activity.reviews.to_string()),
+            ("low_stock", activity.low_stock.to_string()),
+        ],
+    )
+    .await?;
+    Ok(activity.window_end)
+}
+
+async fn run_supplier_digests(
+    db: &DatabaseConnection,
+    policy: &DigestPolicy,
+) -> Result<(), AppError> {
+    for due in due_digests(db, policy).await? {
+        let supplier_id = due.supplier_id;
+        // one supplier's mail failing shouldn't hold up the others
+        if let Err(e) = send_digest(db, policy, due).await {
+            eprintln!("Digest for supplier {} failed: {}", supplier_id, e);
+        }
+    }
+    Ok(())
+}
+
+pub fn spawn_supplier_digest_scheduler(db: DatabaseConnection, policy: DigestPolicy) {
+    tokio::spawn(async move {
+        let mut interval =
+            tokio::time::interval(std::time::Duration::from_secs(DIGEST_POLL_SECONDS));
+        loop {
+            interval.tick().await;
+            if let Err(e) = run_supplier_digests(&db, &policy).await {
+                eprintln!("Supplier digest run failed: {}", e);
+            }
+        }
+    });
+}
@@ -0,0 +1,164 @@
+use crate::{
+    entity::{
+        analytics_events, domain_events,
+        prelude::{AnalyticsEvents, DomainEvents},
+    },
+    error::AppError,
+    ids::TenantId,
+};
+use chrono::Utc;
+use sea_orm::{
+    sea_query::OnConflict, ActiveModelTrait, ActiveValue::Set, ColumnTrait, ConnectionTrait,
+    DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, QuerySelect,
+};
+use serde::Serialize;
+
+// events still failing after this many attempts are left for an admin to look at
+const MAX_EVENT_ATTEMPTS: i32 = 5;
+const EVENT_BATCH_SIZE: u64 = 50;
+const EVENT_POLL_SECONDS: u64 = 15;
+
+pub const FIRST_ORDER_PLACED: &str = "FIRST_ORDER_PLACED";
+
+// a customer placed their first order that wasn't cancelled, amounts as strings to keep the cents exact
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FirstOrderPlaced {
+    pub customer_id: i32,
+    pub order_id: i32,
+    pub total_amount: String,
+    pub welcome_discount: String,
+}
+
+pub const ORDER_PLACED: &str = "ORDER_PLACED";
+
+// every order a customer places, shown in their activity feed
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderPlaced {
+    pub customer_id: i32,
+    pub order_id: i32,
+    pub total_amount: String,
+}
+
+pub const REVIEW_POSTED: &str = "REVIEW_POSTED";
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReviewPosted {
+    pub customer_id: i32,
+    pub review_id: i32,
+    pub product_id: i32,
+}
+
+pub const PRODUCT_VIEWED: &str = "PRODUCT_VIEWED";
+pub const PRODUCT_ADDED_TO_CART: &str = "PRODUCT_ADDED_TO_CART";
+
+// reported by the storefront when a product page is shown, anonymous visitors have no customer
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProductViewed {
+    pub product_id: i32,
+    pub customer_id: Option<i32>,
+}
+
+// guest carts have no customer
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProductAddedToCart {
+    pub product_id: i32,
+    pub customer_id: Option<i32>,
+    pub quantity: i32,
+}
+
+// stores the event in the caller's transaction, so it is only handed on if that commits
+pub async fn record_event<C: ConnectionTrait, E: Serialize>(
+    db: &C,
+    tenant: TenantId,
+    event_type: &str,
+    event: &E,
+) -> Result<(), AppError> {
+    let payload = serde_json::to_value(event).map_err(|e| AppError::Internal(e.to_string()))?;
+    DomainEvents::insert(domain_events::ActiveModel {
+        event_type: Set(event_type.to_string()),
+        payload: Set(payload),
+        tenant_id: Set(tenant.0),
+        ..Default::default()
+    })
+    .exec_without_returning(db)
+    .await?;
+    Ok(())
+}
+
+pub fn spawn_domain_event_worker(db: DatabaseConnection) {
+    tokio::spawn(async move {
+        let mut interval =
+            tokio::time::interval(std::time::Duration::from_secs(EVENT_POLL_SECONDS));
+        loop {
+            interval.tick().await;
+            if let Err(e) = process_pending_events(&db).await {
+                eprintln!("Domain event processing failed: {}", e);
+            }
+        }
+    });
+}
+
+async fn process_pending_events(db: &DatabaseConnection) -> Result<(), AppError> {
+    let pending = DomainEvents::find()
+        .filter(domain_events::Column::ProcessedAt.is_null())
+        .filter(domain_events::Column::Attempts.lt(MAX_EVENT_ATTEMPTS))
+        .order_by_asc(domain_events::Column::OccurredAt)
+        .limit(EVENT_BATCH_SIZE)
+        .all(db)
+        .await?;
+
+    for event in pending {
+        let result = record_analytics_event(db, &event).await;
+
+        let attempts = event.attempts + 1;
+        let mut event: domain_events::ActiveModel = event.into();
+        event.attempts = Set(attempts);
+        match result {
+            Ok(()) => {
+                event.processed_at = Set(Some(Utc::now().fixed_offset()));
+                event.last_error = Set(None);
+            }
+            Err(e) => event.last_error = Set(Some(e.to_string())),
+        }
+        event.update(db).await?;
+    }
+
+    Ok(())
+}
+
+// every domain event lands in analytics_events, keyed by the event so a retry doesn't count it twice
+async fn record_analytics_event(
+    db: &DatabaseConnection,
+    event: &domain_events::Model,
+) -> Result<(), AppError> {
+    let id = |key: &str| {
+        event
+            .payload
+            .get(key)
+            .and_then(|value| value.as_i64())
+            .and_then(|value| i32::try_from(value).ok())
+    };
+    AnalyticsEvents::insert(analytics_events::ActiveModel {
+        event_type: Set(event.event_type.clone()),
+        customer_id: Set(id("customerId")),
+        order_id: Set(id("orderId")),
+        properties: Set(event.payload.clone()),
+        tenant_id: Set(event.tenant_id),
+        occurred_at: Set(event.occurred_at),
+        domain_event_id: Set(Some(event.event_id)),
+        ..Default::default()
+    })
+    .on_conflict(
+        OnConflict::column(analytics_events::Column::DomainEventId)
+            .do_nothing()
+            .to_owned(),
+    )
+    .exec_without_returning(db)
+    .await?;
+    Ok(())
+}
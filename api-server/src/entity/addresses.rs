@@ -7,7 +7,7 @@ use sea_orm::entity::prelude::*;
 pub struct Model {
     #[sea_orm(primary_key)]
     pub address_id: i32,
-    pub customer_id: i32,
+    pub customer_id: Option<i32>,
     pub street_address: String,
     pub city: String,
     pub state: Option<String>,
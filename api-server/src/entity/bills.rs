@@ -13,6 +13,11 @@ pub struct Model {
     #[sea_orm(column_type = "Decimal(Some((10, 2)))")]
     pub total_amount: Decimal,
     pub payment_status: String,
+    #[sea_orm(column_type = "Decimal(Some((10, 2)))")]
+    pub tax_amount: Decimal,
+    pub vat_id: Option<String>,
+    pub po_number: Option<String>,
+    pub provider_payment_id: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -9,10 +9,15 @@ pub struct Model {
     pub category_id: i32,
     pub name: String,
     pub parent_category_id: Option<i32>,
+    pub updated_at: DateTimeWithTimeZone,
+    pub tenant_id: i32,
+    pub sync_xid: i64,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
+    #[sea_orm(has_many = "super::coupons::Entity")]
+    Coupons,
     #[sea_orm(
         belongs_to = "Entity",
         from = "Column::ParentCategoryId",
@@ -21,10 +26,40 @@ pub enum Relation {
         on_delete = "SetNull"
     )]
     SelfRef,
+    #[sea_orm(has_many = "super::category_attributes::Entity")]
+    CategoryAttributes,
+    #[sea_orm(has_one = "super::category_product_counts::Entity")]
+    CategoryProductCounts,
     #[sea_orm(has_many = "super::discounts::Entity")]
     Discounts,
     #[sea_orm(has_many = "super::products::Entity")]
     Products,
+    #[sea_orm(
+        belongs_to = "super::tenants::Entity",
+        from = "Column::TenantId",
+        to = "super::tenants::Column::TenantId",
+        on_update = "NoAction",
+        on_delete = "NoAction"
+    )]
+    Tenants,
+}
+
+impl Related<super::category_attributes::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::CategoryAttributes.def()
+    }
+}
+
+impl Related<super::category_product_counts::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::CategoryProductCounts.def()
+    }
+}
+
+impl Related<super::coupons::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Coupons.def()
+    }
 }
 
 impl Related<super::discounts::Entity> for Entity {
@@ -39,4 +74,10 @@ impl Related<super::products::Entity> for Entity {
     }
 }
 
+impl Related<super::tenants::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Tenants.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
@@ -12,12 +12,20 @@ pub struct Model {
     pub registration_date: Option<DateTimeWithTimeZone>,
     #[sea_orm(unique)]
     pub user_id: i32,
+    pub vat_id: Option<String>,
+    pub vat_id_validated_at: Option<DateTimeWithTimeZone>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
     #[sea_orm(has_many = "super::addresses::Entity")]
     Addresses,
+    #[sea_orm(has_many = "super::api_keys::Entity")]
+    ApiKeys,
+    #[sea_orm(has_many = "super::coupon_redemptions::Entity")]
+    CouponRedemptions,
+    #[sea_orm(has_many = "super::expired_carts::Entity")]
+    ExpiredCarts,
     #[sea_orm(has_many = "super::orders::Entity")]
     Orders,
     #[sea_orm(has_one = "super::payment_methods::Entity")]
@@ -34,6 +42,8 @@ pub enum Relation {
         on_delete = "Cascade"
     )]
     Users,
+    #[sea_orm(has_many = "super::wishlist_items::Entity")]
+    WishlistItems,
 }
 
 impl Related<super::addresses::Entity> for Entity {
@@ -42,6 +52,24 @@ impl Related<super::addresses::Entity> for Entity {
     }
 }
 
+impl Related<super::api_keys::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ApiKeys.def()
+    }
+}
+
+impl Related<super::coupon_redemptions::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::CouponRedemptions.def()
+    }
+}
+
+impl Related<super::expired_carts::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ExpiredCarts.def()
+    }
+}
+
 impl Related<super::orders::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::Orders.def()
@@ -72,4 +100,10 @@ impl Related<super::users::Entity> for Entity {
     }
 }
 
+impl Related<super::wishlist_items::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::WishlistItems.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
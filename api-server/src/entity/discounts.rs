@@ -20,6 +20,7 @@ pub struct Model {
     pub product_id: Option<i32>,
     pub category_id: Option<i32>,
     pub min_quantity: Option<i32>,
+    pub tenant_id: i32,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -42,6 +43,14 @@ pub enum Relation {
         on_delete = "Cascade"
     )]
     Products,
+    #[sea_orm(
+        belongs_to = "super::tenants::Entity",
+        from = "Column::TenantId",
+        to = "super::tenants::Column::TenantId",
+        on_update = "NoAction",
+        on_delete = "NoAction"
+    )]
+    Tenants,
 }
 
 impl Related<super::categories::Entity> for Entity {
@@ -62,4 +71,10 @@ impl Related<super::products::Entity> for Entity {
     }
 }
 
+impl Related<super::tenants::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Tenants.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
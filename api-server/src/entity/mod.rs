@@ -2,20 +2,78 @@
 
 pub mod prelude;
 
+pub mod accounting_exports;
 pub mod address_types;
 pub mod addresses;
+pub mod analytics_events;
+pub mod announcements;
+pub mod api_key_usage;
+pub mod api_keys;
 pub mod bills;
+pub mod boost_rules;
+pub mod bulk_user_job_results;
+pub mod bulk_user_jobs;
+pub mod canned_responses;
 pub mod card_types;
+pub mod cart_coupons;
 pub mod cart_items;
 pub mod categories;
+pub mod category_attributes;
+pub mod category_product_counts;
+pub mod category_reassignments;
+pub mod coupon_redemptions;
+pub mod coupons;
 pub mod customers;
 pub mod discounts;
+pub mod disputes;
+pub mod domain_events;
+pub mod email_suppressions;
+pub mod email_templates;
+pub mod expired_cart_items;
+pub mod expired_carts;
+pub mod image_zip_job_results;
+pub mod image_zip_jobs;
+pub mod integrity_checks;
+pub mod integrity_findings;
+pub mod login_challenges;
+pub mod newsletter_subscriptions;
+pub mod order_address_changes;
 pub mod order_items;
+pub mod order_messages;
+pub mod order_shipments;
+pub mod order_status_history;
 pub mod orders;
+pub mod payment_events;
 pub mod payment_methods;
+pub mod policy_acceptances;
+pub mod policy_versions;
+pub mod product_attributes;
+pub mod product_availability;
+pub mod product_image_variants;
+pub mod product_images;
+pub mod product_price_history;
+pub mod product_region_prices;
+pub mod product_videos;
 pub mod products;
+pub mod rate_limit_tiers;
+pub mod refund_items;
+pub mod refunds;
+pub mod retention_runs;
+pub mod return_policies;
+pub mod return_requests;
+pub mod review_summaries;
 pub mod reviews;
 pub mod sea_orm_active_enums;
+pub mod security_events;
 pub mod shopping_carts;
+pub mod sponsored_campaigns;
+pub mod sponsored_clicks;
+pub mod supplier_agreements;
+pub mod supplier_payout_adjustments;
+pub mod supplier_payouts;
 pub mod suppliers;
+pub mod sync_tombstones;
+pub mod tenants;
+pub mod user_roles;
 pub mod users;
+pub mod wishlist_items;
@@ -14,6 +14,13 @@ pub struct Model {
     pub unit_price: Decimal,
     #[sea_orm(column_type = "Decimal(Some((10, 2)))")]
     pub discount_amount: Decimal,
+    #[sea_orm(column_type = "Decimal(Some((10, 2)))")]
+    pub tax_amount: Decimal,
+    pub booking_date: Option<Date>,
+    pub hs_code: Option<String>,
+    #[sea_orm(column_type = "Decimal(Some((10, 2)))", nullable)]
+    pub customs_value: Option<Decimal>,
+    pub origin_country: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -34,6 +41,10 @@ pub enum Relation {
         on_delete = "Cascade"
     )]
     Products,
+    #[sea_orm(has_many = "super::refund_items::Entity")]
+    RefundItems,
+    #[sea_orm(has_many = "super::return_requests::Entity")]
+    ReturnRequests,
 }
 
 impl Related<super::orders::Entity> for Entity {
@@ -48,4 +59,16 @@ impl Related<super::products::Entity> for Entity {
     }
 }
 
+impl Related<super::refund_items::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::RefundItems.def()
+    }
+}
+
+impl Related<super::return_requests::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ReturnRequests.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
@@ -7,14 +7,28 @@ use sea_orm::entity::prelude::*;
 pub struct Model {
     #[sea_orm(primary_key)]
     pub order_id: i32,
-    pub customer_id: i32,
+    pub customer_id: Option<i32>,
     pub order_date: Option<DateTimeWithTimeZone>,
     #[sea_orm(column_type = "Decimal(Some((10, 2)))")]
     pub total_amount: Decimal,
     pub status: String,
     pub shipping_address_id: i32,
-    pub payment_method_id: i32,
+    pub payment_method_id: Option<i32>,
     pub discount_id: Option<i32>,
+    pub region: Option<String>,
+    #[sea_orm(column_type = "Decimal(Some((10, 2)))")]
+    pub tax_amount: Decimal,
+    pub po_number: Option<String>,
+    pub payout_frozen: bool,
+    pub guest_email: Option<String>,
+    pub tenant_id: i32,
+    pub carrier: Option<String>,
+    pub tracking_number: Option<String>,
+    pub shipped_at: Option<DateTimeWithTimeZone>,
+    pub first_order: bool,
+    #[sea_orm(column_type = "Decimal(Some((10, 2)))")]
+    pub welcome_discount: Decimal,
+    pub currency: String,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -45,8 +59,20 @@ pub enum Relation {
         on_delete = "SetNull"
     )]
     Discounts,
+    #[sea_orm(has_many = "super::disputes::Entity")]
+    Disputes,
+    #[sea_orm(has_many = "super::order_address_changes::Entity")]
+    OrderAddressChanges,
     #[sea_orm(has_many = "super::order_items::Entity")]
     OrderItems,
+    #[sea_orm(has_many = "super::order_messages::Entity")]
+    OrderMessages,
+    #[sea_orm(has_many = "super::order_shipments::Entity")]
+    OrderShipments,
+    #[sea_orm(has_many = "super::order_status_history::Entity")]
+    OrderStatusHistory,
+    #[sea_orm(has_many = "super::payment_events::Entity")]
+    PaymentEvents,
     #[sea_orm(
         belongs_to = "super::payment_methods::Entity",
         from = "Column::PaymentMethodId",
@@ -55,6 +81,16 @@ pub enum Relation {
         on_delete = "Restrict"
     )]
     PaymentMethods,
+    #[sea_orm(has_many = "super::refunds::Entity")]
+    Refunds,
+    #[sea_orm(
+        belongs_to = "super::tenants::Entity",
+        from = "Column::TenantId",
+        to = "super::tenants::Column::TenantId",
+        on_update = "NoAction",
+        on_delete = "NoAction"
+    )]
+    Tenants,
 }
 
 impl Related<super::addresses::Entity> for Entity {
@@ -81,16 +117,64 @@ impl Related<super::discounts::Entity> for Entity {
     }
 }
 
+impl Related<super::disputes::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Disputes.def()
+    }
+}
+
+impl Related<super::order_address_changes::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::OrderAddressChanges.def()
+    }
+}
+
 impl Related<super::order_items::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::OrderItems.def()
     }
 }
 
+impl Related<super::order_messages::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::OrderMessages.def()
+    }
+}
+
+impl Related<super::order_shipments::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::OrderShipments.def()
+    }
+}
+
+impl Related<super::order_status_history::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::OrderStatusHistory.def()
+    }
+}
+
+impl Related<super::payment_events::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::PaymentEvents.def()
+    }
+}
+
 impl Related<super::payment_methods::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::PaymentMethods.def()
     }
 }
 
+impl Related<super::refunds::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Refunds.def()
+    }
+}
+
+impl Related<super::tenants::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Tenants.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
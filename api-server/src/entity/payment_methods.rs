@@ -21,6 +21,12 @@ pub struct Model {
     pub bank_account_number: Option<String>,
     pub ifsc_code: Option<String>,
     pub card_type_id: Option<i32>,
+    pub provider: Option<String>,
+    #[sea_orm(unique)]
+    pub provider_token: Option<String>,
+    pub card_brand: Option<String>,
+    pub card_last4: Option<String>,
+    pub provider_customer_id: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
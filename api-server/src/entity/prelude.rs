@@ -1,18 +1,76 @@
 //! `SeaORM` Entity, @generated by sea-orm-codegen 1.1.2
 
+pub use super::accounting_exports::Entity as AccountingExports;
 pub use super::address_types::Entity as AddressTypes;
 pub use super::addresses::Entity as Addresses;
+pub use super::analytics_events::Entity as AnalyticsEvents;
+pub use super::announcements::Entity as Announcements;
+pub use super::api_key_usage::Entity as ApiKeyUsage;
+pub use super::api_keys::Entity as ApiKeys;
 pub use super::bills::Entity as Bills;
+pub use super::boost_rules::Entity as BoostRules;
+pub use super::bulk_user_job_results::Entity as BulkUserJobResults;
+pub use super::bulk_user_jobs::Entity as BulkUserJobs;
+pub use super::canned_responses::Entity as CannedResponses;
 pub use super::card_types::Entity as CardTypes;
+pub use super::cart_coupons::Entity as CartCoupons;
 pub use super::cart_items::Entity as CartItems;
 pub use super::categories::Entity as Categories;
+pub use super::category_attributes::Entity as CategoryAttributes;
+pub use super::category_product_counts::Entity as CategoryProductCounts;
+pub use super::category_reassignments::Entity as CategoryReassignments;
+pub use super::coupon_redemptions::Entity as CouponRedemptions;
+pub use super::coupons::Entity as Coupons;
 pub use super::customers::Entity as Customers;
 pub use super::discounts::Entity as Discounts;
+pub use super::disputes::Entity as Disputes;
+pub use super::domain_events::Entity as DomainEvents;
+pub use super::email_suppressions::Entity as EmailSuppressions;
+pub use super::email_templates::Entity as EmailTemplates;
+pub use super::expired_cart_items::Entity as ExpiredCartItems;
+pub use super::expired_carts::Entity as ExpiredCarts;
+pub use super::image_zip_job_results::Entity as ImageZipJobResults;
+pub use super::image_zip_jobs::Entity as ImageZipJobs;
+pub use super::integrity_checks::Entity as IntegrityChecks;
+pub use super::integrity_findings::Entity as IntegrityFindings;
+pub use super::login_challenges::Entity as LoginChallenges;
+pub use super::newsletter_subscriptions::Entity as NewsletterSubscriptions;
+pub use super::order_address_changes::Entity as OrderAddressChanges;
 pub use super::order_items::Entity as OrderItems;
+pub use super::order_messages::Entity as OrderMessages;
+pub use super::order_shipments::Entity as OrderShipments;
+pub use super::order_status_history::Entity as OrderStatusHistory;
 pub use super::orders::Entity as Orders;
+pub use super::payment_events::Entity as PaymentEvents;
 pub use super::payment_methods::Entity as PaymentMethods;
+pub use super::policy_acceptances::Entity as PolicyAcceptances;
+pub use super::policy_versions::Entity as PolicyVersions;
+pub use super::product_attributes::Entity as ProductAttributes;
+pub use super::product_availability::Entity as ProductAvailability;
+pub use super::product_image_variants::Entity as ProductImageVariants;
+pub use super::product_images::Entity as ProductImages;
+pub use super::product_price_history::Entity as ProductPriceHistory;
+pub use super::product_region_prices::Entity as ProductRegionPrices;
+pub use super::product_videos::Entity as ProductVideos;
 pub use super::products::Entity as Products;
+pub use super::rate_limit_tiers::Entity as RateLimitTiers;
+pub use super::refund_items::Entity as RefundItems;
+pub use super::refunds::Entity as Refunds;
+pub use super::retention_runs::Entity as RetentionRuns;
+pub use super::return_policies::Entity as ReturnPolicies;
+pub use super::return_requests::Entity as ReturnRequests;
+pub use super::review_summaries::Entity as ReviewSummaries;
 pub use super::reviews::Entity as Reviews;
+pub use super::security_events::Entity as SecurityEvents;
 pub use super::shopping_carts::Entity as ShoppingCarts;
+pub use super::sponsored_campaigns::Entity as SponsoredCampaigns;
+pub use super::sponsored_clicks::Entity as SponsoredClicks;
+pub use super::supplier_agreements::Entity as SupplierAgreements;
+pub use super::supplier_payout_adjustments::Entity as SupplierPayoutAdjustments;
+pub use super::supplier_payouts::Entity as SupplierPayouts;
 pub use super::suppliers::Entity as Suppliers;
+pub use super::sync_tombstones::Entity as SyncTombstones;
+pub use super::tenants::Entity as Tenants;
+pub use super::user_roles::Entity as UserRoles;
 pub use super::users::Entity as Users;
+pub use super::wishlist_items::Entity as WishlistItems;
@@ -18,6 +18,24 @@ pub struct Model {
     pub base_product_id: Option<i32>,
     pub media_paths: Option<Vec<String>>,
     pub created_at: Option<DateTimeWithTimeZone>,
+    pub updated_at: DateTimeWithTimeZone,
+    pub price_updated_at: DateTimeWithTimeZone,
+    #[sea_orm(column_type = "Decimal(Some((10, 2)))", nullable)]
+    pub cost_price: Option<Decimal>,
+    pub sku: Option<String>,
+    pub tenant_id: i32,
+    pub max_per_customer: Option<i32>,
+    pub limit_window_days: Option<i32>,
+    pub date_bookable: bool,
+    pub archived_at: Option<DateTimeWithTimeZone>,
+    pub removed_at: Option<DateTimeWithTimeZone>,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub removal_reason: Option<String>,
+    pub hs_code: Option<String>,
+    #[sea_orm(column_type = "Decimal(Some((10, 2)))", nullable)]
+    pub customs_value: Option<Decimal>,
+    pub sync_xid: i64,
+    pub price_sync_xid: i64,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -34,8 +52,22 @@ pub enum Relation {
     Categories,
     #[sea_orm(has_many = "super::discounts::Entity")]
     Discounts,
+    #[sea_orm(has_many = "super::expired_cart_items::Entity")]
+    ExpiredCartItems,
+    #[sea_orm(has_many = "super::image_zip_job_results::Entity")]
+    ImageZipJobResults,
     #[sea_orm(has_many = "super::order_items::Entity")]
     OrderItems,
+    #[sea_orm(has_many = "super::product_attributes::Entity")]
+    ProductAttributes,
+    #[sea_orm(has_many = "super::product_availability::Entity")]
+    ProductAvailability,
+    #[sea_orm(has_many = "super::product_image_variants::Entity")]
+    ProductImageVariants,
+    #[sea_orm(has_many = "super::product_images::Entity")]
+    ProductImages,
+    #[sea_orm(has_many = "super::product_price_history::Entity")]
+    ProductPriceHistory,
     #[sea_orm(
         belongs_to = "Entity",
         from = "Column::BaseProductId",
@@ -44,8 +76,16 @@ pub enum Relation {
         on_delete = "SetNull"
     )]
     SelfRef,
+    #[sea_orm(has_many = "super::product_region_prices::Entity")]
+    ProductRegionPrices,
+    #[sea_orm(has_many = "super::product_videos::Entity")]
+    ProductVideos,
+    #[sea_orm(has_one = "super::review_summaries::Entity")]
+    ReviewSummaries,
     #[sea_orm(has_many = "super::reviews::Entity")]
     Reviews,
+    #[sea_orm(has_many = "super::sponsored_campaigns::Entity")]
+    SponsoredCampaigns,
     #[sea_orm(
         belongs_to = "super::suppliers::Entity",
         from = "Column::SupplierId",
@@ -54,6 +94,16 @@ pub enum Relation {
         on_delete = "SetNull"
     )]
     Suppliers,
+    #[sea_orm(
+        belongs_to = "super::tenants::Entity",
+        from = "Column::TenantId",
+        to = "super::tenants::Column::TenantId",
+        on_update = "NoAction",
+        on_delete = "NoAction"
+    )]
+    Tenants,
+    #[sea_orm(has_many = "super::wishlist_items::Entity")]
+    WishlistItems,
 }
 
 impl Related<super::cart_items::Entity> for Entity {
@@ -74,22 +124,100 @@ impl Related<super::discounts::Entity> for Entity {
     }
 }
 
+impl Related<super::expired_cart_items::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ExpiredCartItems.def()
+    }
+}
+
+impl Related<super::image_zip_job_results::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ImageZipJobResults.def()
+    }
+}
+
 impl Related<super::order_items::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::OrderItems.def()
     }
 }
 
+impl Related<super::product_attributes::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ProductAttributes.def()
+    }
+}
+
+impl Related<super::product_availability::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ProductAvailability.def()
+    }
+}
+
+impl Related<super::product_image_variants::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ProductImageVariants.def()
+    }
+}
+
+impl Related<super::product_images::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ProductImages.def()
+    }
+}
+
+impl Related<super::product_price_history::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ProductPriceHistory.def()
+    }
+}
+
+impl Related<super::product_region_prices::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ProductRegionPrices.def()
+    }
+}
+
+impl Related<super::product_videos::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ProductVideos.def()
+    }
+}
+
+impl Related<super::review_summaries::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ReviewSummaries.def()
+    }
+}
+
 impl Related<super::reviews::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::Reviews.def()
     }
 }
 
+impl Related<super::sponsored_campaigns::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::SponsoredCampaigns.def()
+    }
+}
+
 impl Related<super::suppliers::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::Suppliers.def()
     }
 }
 
+impl Related<super::tenants::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Tenants.def()
+    }
+}
+
+impl Related<super::wishlist_items::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::WishlistItems.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
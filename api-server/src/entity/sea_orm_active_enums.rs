@@ -21,6 +21,8 @@ pub enum PaymentMethodType {
 #[derive(Debug, Clone, PartialEq, Eq, EnumIter, DeriveActiveEnum)]
 #[sea_orm(rs_type = "String", db_type = "Enum", enum_name = "user_role")]
 pub enum UserRole {
+    #[sea_orm(string_value = "admin")]
+    Admin,
     #[sea_orm(string_value = "customer")]
     Customer,
     #[sea_orm(string_value = "supplier")]
@@ -7,12 +7,17 @@ use sea_orm::entity::prelude::*;
 pub struct Model {
     #[sea_orm(primary_key)]
     pub cart_id: i32,
-    pub customer_id: i32,
+    pub customer_id: Option<i32>,
     pub created_at: Option<DateTimeWithTimeZone>,
+    pub updated_at: DateTimeWithTimeZone,
+    #[sea_orm(unique)]
+    pub guest_token_hash: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
+    #[sea_orm(has_one = "super::cart_coupons::Entity")]
+    CartCoupons,
     #[sea_orm(has_many = "super::cart_items::Entity")]
     CartItems,
     #[sea_orm(
@@ -25,6 +30,12 @@ pub enum Relation {
     Customers,
 }
 
+impl Related<super::cart_coupons::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::CartCoupons.def()
+    }
+}
+
 impl Related<super::cart_items::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::CartItems.def()
@@ -12,12 +12,47 @@ pub struct Model {
     pub contact_phone: Option<String>,
     #[sea_orm(unique)]
     pub user_id: i32,
+    pub region: Option<String>,
+    pub payout_account_holder: Option<String>,
+    pub payout_iban: Option<String>,
+    pub digest_enabled: bool,
+    pub timezone: String,
+    pub last_digest_sent_at: Option<DateTimeWithTimeZone>,
+    pub digest_claimed_until: Option<DateTimeWithTimeZone>,
+    pub approved_at: Option<DateTimeWithTimeZone>,
+    pub ship_from_country: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
+    #[sea_orm(has_many = "super::accounting_exports::Entity")]
+    AccountingExports,
+    #[sea_orm(has_many = "super::api_keys::Entity")]
+    ApiKeys,
+    #[sea_orm(has_many = "super::boost_rules::Entity")]
+    BoostRules,
+    #[sea_orm(has_many = "super::canned_responses::Entity")]
+    CannedResponses,
+    #[sea_orm(has_many = "super::category_reassignments::Entity")]
+    CategoryReassignments,
+    #[sea_orm(has_many = "super::coupons::Entity")]
+    Coupons,
+    #[sea_orm(has_many = "super::image_zip_jobs::Entity")]
+    ImageZipJobs,
+    #[sea_orm(has_many = "super::order_shipments::Entity")]
+    OrderShipments,
     #[sea_orm(has_many = "super::products::Entity")]
     Products,
+    #[sea_orm(has_one = "super::return_policies::Entity")]
+    ReturnPolicies,
+    #[sea_orm(has_many = "super::sponsored_campaigns::Entity")]
+    SponsoredCampaigns,
+    #[sea_orm(has_many = "super::supplier_agreements::Entity")]
+    SupplierAgreements,
+    #[sea_orm(has_many = "super::supplier_payout_adjustments::Entity")]
+    SupplierPayoutAdjustments,
+    #[sea_orm(has_many = "super::supplier_payouts::Entity")]
+    SupplierPayouts,
     #[sea_orm(
         belongs_to = "super::users::Entity",
         from = "Column::UserId",
@@ -28,12 +63,90 @@ pub enum Relation {
     Users,
 }
 
+impl Related<super::accounting_exports::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::AccountingExports.def()
+    }
+}
+
+impl Related<super::api_keys::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ApiKeys.def()
+    }
+}
+
+impl Related<super::boost_rules::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::BoostRules.def()
+    }
+}
+
+impl Related<super::canned_responses::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::CannedResponses.def()
+    }
+}
+
+impl Related<super::category_reassignments::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::CategoryReassignments.def()
+    }
+}
+
+impl Related<super::coupons::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Coupons.def()
+    }
+}
+
+impl Related<super::image_zip_jobs::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ImageZipJobs.def()
+    }
+}
+
+impl Related<super::order_shipments::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::OrderShipments.def()
+    }
+}
+
 impl Related<super::products::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::Products.def()
     }
 }
 
+impl Related<super::return_policies::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ReturnPolicies.def()
+    }
+}
+
+impl Related<super::sponsored_campaigns::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::SponsoredCampaigns.def()
+    }
+}
+
+impl Related<super::supplier_agreements::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::SupplierAgreements.def()
+    }
+}
+
+impl Related<super::supplier_payout_adjustments::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::SupplierPayoutAdjustments.def()
+    }
+}
+
+impl Related<super::supplier_payouts::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::SupplierPayouts.def()
+    }
+}
+
 impl Related<super::users::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::Users.def()
@@ -8,20 +8,95 @@ use sea_orm::entity::prelude::*;
 pub struct Model {
     #[sea_orm(primary_key)]
     pub user_id: i32,
-    #[sea_orm(unique)]
     pub email: String,
     pub password: String,
     pub role: UserRole,
     pub created_at: Option<DateTimeWithTimeZone>,
-    pub email_verified: Option<bool>,
+    pub email_verified: bool,
+    pub step_up_until: Option<DateTimeWithTimeZone>,
+    pub locale: Option<String>,
+    pub tenant_id: i32,
+    pub disabled_at: Option<DateTimeWithTimeZone>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
+    #[sea_orm(has_many = "super::accounting_exports::Entity")]
+    AccountingExports,
+    #[sea_orm(has_many = "super::announcements::Entity")]
+    Announcements,
+    #[sea_orm(has_many = "super::bulk_user_jobs::Entity")]
+    BulkUserJobs,
+    #[sea_orm(has_many = "super::category_reassignments::Entity")]
+    CategoryReassignments,
+    #[sea_orm(has_many = "super::coupons::Entity")]
+    Coupons,
     #[sea_orm(has_one = "super::customers::Entity")]
     Customers,
+    #[sea_orm(has_many = "super::integrity_checks::Entity")]
+    IntegrityChecks,
+    #[sea_orm(has_many = "super::login_challenges::Entity")]
+    LoginChallenges,
+    #[sea_orm(has_many = "super::order_address_changes::Entity")]
+    OrderAddressChanges,
+    #[sea_orm(has_many = "super::order_messages::Entity")]
+    OrderMessages,
+    #[sea_orm(has_many = "super::order_shipments::Entity")]
+    OrderShipments,
+    #[sea_orm(has_many = "super::order_status_history::Entity")]
+    OrderStatusHistory,
+    #[sea_orm(has_many = "super::policy_acceptances::Entity")]
+    PolicyAcceptances,
+    #[sea_orm(has_many = "super::rate_limit_tiers::Entity")]
+    RateLimitTiers,
+    #[sea_orm(has_many = "super::refunds::Entity")]
+    Refunds,
+    #[sea_orm(has_many = "super::security_events::Entity")]
+    SecurityEvents,
+    #[sea_orm(has_many = "super::supplier_agreements::Entity")]
+    SupplierAgreements,
     #[sea_orm(has_one = "super::suppliers::Entity")]
     Suppliers,
+    #[sea_orm(
+        belongs_to = "super::tenants::Entity",
+        from = "Column::TenantId",
+        to = "super::tenants::Column::TenantId",
+        on_update = "NoAction",
+        on_delete = "NoAction"
+    )]
+    Tenants,
+    #[sea_orm(has_many = "super::user_roles::Entity")]
+    UserRoles,
+}
+
+impl Related<super::accounting_exports::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::AccountingExports.def()
+    }
+}
+
+impl Related<super::announcements::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Announcements.def()
+    }
+}
+
+impl Related<super::bulk_user_jobs::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::BulkUserJobs.def()
+    }
+}
+
+impl Related<super::category_reassignments::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::CategoryReassignments.def()
+    }
+}
+
+impl Related<super::coupons::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Coupons.def()
+    }
 }
 
 impl Related<super::customers::Entity> for Entity {
@@ -30,10 +105,88 @@ impl Related<super::customers::Entity> for Entity {
     }
 }
 
+impl Related<super::integrity_checks::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::IntegrityChecks.def()
+    }
+}
+
+impl Related<super::login_challenges::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::LoginChallenges.def()
+    }
+}
+
+impl Related<super::order_address_changes::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::OrderAddressChanges.def()
+    }
+}
+
+impl Related<super::order_messages::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::OrderMessages.def()
+    }
+}
+
+impl Related<super::order_shipments::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::OrderShipments.def()
+    }
+}
+
+impl Related<super::order_status_history::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::OrderStatusHistory.def()
+    }
+}
+
+impl Related<super::policy_acceptances::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::PolicyAcceptances.def()
+    }
+}
+
+impl Related<super::rate_limit_tiers::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::RateLimitTiers.def()
+    }
+}
+
+impl Related<super::refunds::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Refunds.def()
+    }
+}
+
+impl Related<super::security_events::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::SecurityEvents.def()
+    }
+}
+
+impl Related<super::supplier_agreements::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::SupplierAgreements.def()
+    }
+}
+
 impl Related<super::suppliers::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::Suppliers.def()
     }
 }
 
+impl Related<super::tenants::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Tenants.def()
+    }
+}
+
+impl Related<super::user_roles::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::UserRoles.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
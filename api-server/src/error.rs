@@ -1,3 +1,4 @@
+use crate::retry::is_transient_db;
 use axum::http::StatusCode;
 use axum::response::IntoResponse;
 use axum::BoxError;
@@ -21,6 +22,40 @@ pub enum AppError {
         user_id: Option<String>,
     },
 
+    #[error("Additional verification required")]
+    StepUpRequired { challenge_id: i32 },
+
+    #[error("No authorization token found")]
+    Unauthenticated,
+
+    // the resource is named in singular, e.g. "Product"
+    #[error("{0} not found")]
+    NotFound(&'static str),
+
+    #[error("Validation error: {message}")]
+    Validation {
+        message: String,
+        failed_rules: Vec<String>,
+    },
+
+    #[error("Upload rejected: {message}")]
+    UploadRejected {
+        message: String,
+        reason: UploadRejection,
+    },
+
+    #[error("Only {available} of product {product_id} in stock")]
+    InsufficientStock { product_id: i32, available: i32 },
+
+    #[error("Product {product_id} is limited to {limit} per customer")]
+    PurchaseLimitExceeded {
+        product_id: i32,
+        limit: i32,
+        already_purchased: i32,
+        // null when the limit applies to every purchase ever made
+        window_days: Option<i32>,
+    },
+
     #[error("Internal server error: {0}")]
     Internal(String),
 }
@@ -30,6 +65,9 @@ pub enum AuthErrorCode {
     InvalidCredentials,
     TokenExpired,
     InsufficientPermissions,
+    RateLimited,
+    TermsNotAccepted,
+    EmailNotVerified,
 }
 
 impl fmt::Display for AuthErrorCode {
@@ -38,6 +76,47 @@ impl fmt::Display for AuthErrorCode {
             Self::InvalidCredentials => write!(f, "INVALID_CREDENTIALS"),
             Self::TokenExpired => write!(f, "TOKEN_EXPIRED"),
             Self::InsufficientPermissions => write!(f, "INSUFFICIENT_PERMISSIONS"),
+            Self::RateLimited => write!(f, "RATE_LIMITED"),
+            Self::TermsNotAccepted => write!(f, "TERMS_NOT_ACCEPTED"),
+            Self::EmailNotVerified => write!(f, "EMAIL_NOT_VERIFIED"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum UploadRejection {
+    TooLarge,
+    UnsupportedType,
+    DimensionsExceeded,
+    Malformed,
+}
+
+impl fmt::Display for UploadRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooLarge => write!(f, "FILE_TOO_LARGE"),
+            Self::UnsupportedType => write!(f, "UNSUPPORTED_MEDIA_TYPE"),
+            Self::DimensionsExceeded => write!(f, "IMAGE_DIMENSIONS_EXCEEDED"),
+            Self::Malformed => write!(f, "MALFORMED_FILE"),
+        }
+    }
+}
+
+impl AppError {
+    // the caller is signed in, but what they asked for belongs to someone else
+    pub fn forbidden() -> Self {
+        Self::Auth {
+            message: "Not allowed for this account".to_string(),
+            code: AuthErrorCode::InsufficientPermissions,
+            user_id: None,
+        }
+    }
+
+    // input that breaks no named rule, the message says what to change
+    pub fn invalid(message: impl Into<String>) -> Self {
+        Self::Validation {
+            message: message.into(),
+            failed_rules: Vec::new(),
         }
     }
 }
@@ -52,19 +131,27 @@ impl From<DbErr> for AppError {
     }
 }
 
+// async-graphql converts anything printable with `?`, which would keep the message but drop the
+// code clients branch on, resolvers go through `.extend()` instead
 impl async_graphql::ErrorExtensions for AppError {
     fn extend(&self) -> async_graphql::Error {
         let error = async_graphql::Error::new(self.to_string());
 
         error.extend_with(|_, e| match self {
             AppError::Database {
-                message, context, ..
+                message,
+                source,
+                context,
             } => {
                 e.set("code", "DATABASE_ERROR");
                 e.set("message", message);
                 if let Some(ctx) = context {
                     e.set("context", ctx);
                 }
+                // the source is dropped here, retry_transaction goes by this instead
+                if is_transient_db(source) {
+                    e.set("retryable", true);
+                }
             }
             AppError::Auth {
                 message,
@@ -78,6 +165,53 @@ impl async_graphql::ErrorExtensions for AppError {
                     e.set("userId", uid);
                 }
             }
+            AppError::StepUpRequired { challenge_id } => {
+                e.set("code", "STEP_UP_REQUIRED");
+                e.set("challengeId", *challenge_id);
+            }
+            AppError::Unauthenticated => {
+                e.set("code", "UNAUTHENTICATED");
+            }
+            AppError::NotFound(resource) => {
+                e.set("code", "NOT_FOUND");
+                e.set("resource", *resource);
+            }
+            AppError::Validation {
+                message,
+                failed_rules,
+            } => {
+                e.set("code", "VALIDATION_FAILED");
+                e.set("message", message);
+                e.set("failedRules", failed_rules.clone());
+            }
+            AppError::UploadRejected { message, reason } => {
+                e.set("code", "UPLOAD_REJECTED");
+                e.set("reason", reason.to_string());
+                e.set("message", message);
+            }
+            AppError::InsufficientStock {
+                product_id,
+                available,
+            } => {
+                e.set("code", "INSUFFICIENT_STOCK");
+                e.set("productId", *product_id);
+                e.set("available", *available);
+            }
+            AppError::PurchaseLimitExceeded {
+                product_id,
+                limit,
+                already_purchased,
+                window_days,
+            } => {
+                e.set("code", "PURCHASE_LIMIT_EXCEEDED");
+                e.set("productId", *product_id);
+                e.set("limit", *limit);
+                e.set("alreadyPurchased", *already_purchased);
+                e.set("remaining", (limit - already_purchased).max(0));
+                if let Some(window_days) = window_days {
+                    e.set("windowDays", *window_days);
+                }
+            }
             AppError::Internal(message) => {
                 e.set("code", "INTERNAL_ERROR");
                 e.set("message", message);
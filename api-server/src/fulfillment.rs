@@ -0,0 +1,348 @@
+use crate::{
+    carriers::Carrier,
+    entity::{
+        addresses, customers, order_items, orders,
+        prelude::{Addresses, Customers, OrderItems, Orders, Products, Suppliers},
+        products, suppliers,
+    },
+    error::AppError,
+    labels::{LabelProvider, LabelRequest},
+    models::fulfillment::{CustomsDeclaration, CustomsItem},
+    money::{format_money, round_amount},
+    pdf::TextPdf,
+    storage::Storage,
+};
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use chrono::{Duration, NaiveDate};
+use sea_orm::{
+    prelude::Decimal, ColumnTrait, DatabaseConnection, EntityTrait, JoinType, QueryFilter,
+    QueryOrder, QuerySelect, RelationTrait,
+};
+use std::collections::HashMap;
+
+// a day with more orders than this is printed in several batches
+pub const MAX_BATCH_ORDERS: usize = 500;
+
+// how long a stored document's link works, generating it again gives a fresh one
+const DOCUMENT_URL_MINUTES: i64 = 60;
+
+// statuses whose parcels are already out, a day's batch leaves them out
+const DISPATCHED_STATUSES: [&str; 3] = ["SHIPPED", "OUT_FOR_DELIVERY", "DELIVERED"];
+
+// one supplier's part of an order, which is what goes into one parcel
+pub struct Shipment {
+    pub order: orders::Model,
+    pub items: Vec<(order_items::Model, products::Model)>,
+    pub recipient: String,
+    pub address: Option<addresses::Model>,
+}
+
+impl Shipment {
+    fn ship_to(&self) -> Vec<String> {
+        let mut lines = vec![self.recipient.clone()];
+        if let Some(address) = &self.address {
+            lines.push(address.street_address.clone());
+            lines.push(format!("{} {}", address.postal_code, address.city));
+            if let Some(state) = &address.state {
+                lines.push(state.clone());
+            }
+            lines.push(address.country.clone());
+        }
+        lines
+    }
+
+    // None for domestic parcels, checkout only keeps customs data on lines crossing a border.
+    // Customs values are the prices paid, in the order's currency
+    pub fn customs_declaration(&self) -> Option<CustomsDeclaration> {
+        let currency = self.order.currency.as_str();
+        let items: Vec<CustomsItem> = self
+            .items
+            .iter()
+            .filter_map(|(item, product)| {
+                let unit_value = round_amount(item.customs_value?, currency);
+                Some(CustomsItem {
+                    product_id: product.product_id,
+                    description: product.name.clone(),
+                    quantity: item.quantity,
+                    hs_code: item.hs_code.clone()?,
+                    origin_country: item.origin_country.clone()?,
+                    unit_value: unit_value.to_string(),
+                    total_value: round_amount(unit_value * Decimal::from(item.quantity), currency)
+                        .to_string(),
+                })
+            })
+            .collect();
+        if items.is_empty() {
+            return None;
+        }
+        let total_value: Decimal = self
+            .items
+            .iter()
+            .filter_map(|(item, _)| {
+                Some(round_amount(item.customs_value?, currency) * Decimal::from(item.quantity))
+            })
+            .sum();
+        Some(CustomsDeclaration {
+            order_id: self.order.order_id.into(),
+            destination_country: self
+                .address
+                .as_ref()
+                .map(|address| address.country.trim().to_string())
+                .unwrap_or_default(),
+            items,
+            total_value: round_amount(total_value, currency).to_string(),
+            currency: currency.to_string(),
+        })
+    }
+}
+
+// the supplier's shipments among the given orders, or among the orders placed on the given day
+// that haven't gone out yet
+pub async fn load_shipments(
+    db: &DatabaseConnection,
+    supplier_id: i32,
+    order_ids: Option<Vec<i32>>,
+    placed_on: Option<NaiveDate>,
+) -> Result<Vec<Shipment>, AppError> {
+    let mut query = Orders::find()
+        .join(JoinType::InnerJoin, orders::Relation::OrderItems.def())
+        .join(JoinType::InnerJoin, order_items::Relation::Products.def())
+        .filter(products::Column::SupplierId.eq(supplier_id))
+        .filter(orders::Column::Status.ne("CANCELLED"))
+        .distinct();
+    if let Some(order_ids) = order_ids {
+        query = query.filter(orders::Column::OrderId.is_in(order_ids));
+    }
+    if let Some(day) = placed_on {
+        let start = day.and_hms_opt(0, 0, 0).unwrap().and_utc().fixed_offset();
+        query = query
+            .filter(orders::Column::OrderDate.gte(start))
+            .filter(orders::Column::OrderDate.lt(start + chrono::Duration::days(1)))
+            .filter(orders::Column::Status.is_not_in(DISPATCHED_STATUSES));
+    }
+    let orders = query
+        .order_by_asc(orders::Column::OrderId)
+        .limit(MAX_BATCH_ORDERS as u64 + 1)
+        .all(db)
+        .await?;
+    if orders.len() > MAX_BATCH_ORDERS {
+        return Err(AppError::Validation {
+            message: format!("At most {} orders are printed at once", MAX_BATCH_ORDERS),
+            failed_rules: vec!["TOO_MANY_ORDERS".to_string()],
+        });
+    }
+
+    let order_ids: Vec<i32> = orders.iter().map(|order| order.order_id).collect();
+    let mut items: HashMap<i32, Vec<(order_items::Model, products::Model)>> = HashMap::new();
+    for (item, product) in OrderItems::find()
+        .find_also_related(Products)
+        .filter(order_items::Column::OrderId.is_in(order_ids))
+        .filter(products::Column::SupplierId.eq(supplier_id))
+        .order_by_asc(order_items::Column::OrderItemId)
+        .all(db)
+        .await?
+    {
+        if let Some(product) = product {
+            items
+                .entry(item.order_id)
+                .or_default()
+                .push((item, product));
+        }
+    }
+
+    let addresses: HashMap<i32, addresses::Model> = Addresses::find()
+        .filter(
+            addresses::Column::AddressId
+                .is_in(orders.iter().map(|order| order.shipping_address_id)),
+        )
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|address| (address.address_id, address))
+        .collect();
+    let customers: HashMap<i32, customers::Model> = Customers::find()
+        .filter(
+            customers::Column::CustomerId
+                .is_in(orders.iter().filter_map(|order| order.customer_id)),
+        )
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|customer| (customer.customer_id, customer))
+        .collect();
+
+    Ok(orders
+        .into_iter()
+        .map(|order| Shipment {
+            items: items.remove(&order.order_id).unwrap_or_default(),
+            // guest orders carry no name, the street address is enough for the carrier
+            recipient: order
+                .customer_id
+                .and_then(|customer_id| customers.get(&customer_id))
+                .map(|customer| format!("{} {}", customer.first_name, customer.last_name))
+                .unwrap_or_else(|| "Recipient".to_string()),
+            address: addresses.get(&order.shipping_address_id).cloned(),
+            order,
+        })
+        .collect())
+}
+
+// the supplier's name and region, printed as the sender
+pub async fn ship_from(db: &DatabaseConnection, supplier_id: i32) -> Result<Vec<String>, AppError> {
+    let supplier = Suppliers::find()
+        .filter(suppliers::Column::SupplierId.eq(supplier_id))
+        .one(db)
+        .await?
+        .ok_or_else(|| AppError::Internal(format!("Supplier {} not found", supplier_id)))?;
+    Ok(std::iter::once(supplier.name)
+        .chain(supplier.region)
+        .collect())
+}
+
+fn packing_slip_lines(shipment: &Shipment, ship_from: &[String]) -> Vec<String> {
+    let order = &shipment.order;
+    let mut lines = vec![
+        "PACKING SLIP".to_string(),
+        format!(
+            "Order #{}{}",
+            order.order_id,
+            order
+                .order_date
+                .map(|date| format!("    placed {}", date.date_naive()))
+                .unwrap_or_default()
+        ),
+    ];
+    if let Some(po_number) = &order.po_number {
+        lines.push(format!("PO number: {}", po_number));
+    }
+    lines.push(String::new());
+    lines.push("Ship to".to_string());
+    lines.extend(
+        shipment
+            .ship_to()
+            .into_iter()
+            .map(|line| format!("  {}", line)),
+    );
+    lines.push(String::new());
+    lines.push("Shipped by".to_string());
+    lines.extend(ship_from.iter().map(|line| format!("  {}", line)));
+    lines.push(String::new());
+    lines.push("Qty   SKU              Item".to_string());
+    for (item, product) in &shipment.items {
+        let mut name = product.name.clone();
+        if let Some(booking_date) = item.booking_date {
+            name.push_str(&format!(" (for {})", booking_date));
+        }
+        lines.push(format!(
+            "{:<5} {:<16} {}",
+            item.quantity,
+            product.sku.as_deref().unwrap_or("-"),
+            name
+        ));
+    }
+    lines
+}
+
+fn commercial_invoice_lines(
+    shipment: &Shipment,
+    declaration: &CustomsDeclaration,
+    ship_from: &[String],
+) -> Vec<String> {
+    // invoices are printed in English whatever the locale of the supplier
+    let money = |value: &str| {
+        Decimal::from_str_exact(value)
+            .map(|value| format_money(value, &declaration.currency, "en"))
+            .unwrap_or_else(|_| value.to_string())
+    };
+    let mut lines = vec![
+        "COMMERCIAL INVOICE".to_string(),
+        format!(
+            "Order #{}    destination {}",
+            declaration.order_id, declaration.destination_country
+        ),
+        String::new(),
+        "Exporter".to_string(),
+    ];
+    lines.extend(ship_from.iter().map(|line| format!("  {}", line)));
+    lines.push(String::new());
+    lines.push("Consignee".to_string());
+    lines.extend(
+        shipment
+            .ship_to()
+            .into_iter()
+            .map(|line| format!("  {}", line)),
+    );
+    lines.push(String::new());
+    lines.push("Qty   HS code     Origin  Unit value      Total           Description".to_string());
+    for item in &declaration.items {
+        lines.push(format!(
+            "{:<5} {:<11} {:<7} {:<15} {:<15} {}",
+            item.quantity,
+            item.hs_code,
+            item.origin_country,
+            money(&item.unit_value),
+            money(&item.total_value),
+            item.description
+        ));
+    }
+    lines.push(String::new());
+    lines.push(format!(
+        "Total declared value: {}",
+        money(&declaration.total_value)
+    ));
+    lines
+}
+
+// every shipment on its own page(s), in one PDF, parcels going abroad get their commercial
+// invoice right after the slip
+pub fn packing_slips(shipments: &[Shipment], ship_from: &[String]) -> Vec<u8> {
+    let mut pdf = TextPdf::default();
+    for shipment in shipments {
+        pdf.add_page(packing_slip_lines(shipment, ship_from));
+        if let Some(declaration) = shipment.customs_declaration() {
+            pdf.add_page(commercial_invoice_lines(shipment, &declaration, ship_from));
+        }
+    }
+    pdf.finish()
+}
+
+pub async fn shipping_label(
+    labels: &dyn LabelProvider,
+    shipment: &Shipment,
+    carrier: Carrier,
+    ship_from: &[String],
+) -> Result<Vec<u8>, AppError> {
+    labels
+        .create_label(&LabelRequest {
+            order_id: shipment.order.order_id,
+            carrier,
+            tracking_number: shipment.order.tracking_number.clone(),
+            ship_from: ship_from.to_vec(),
+            ship_to: shipment.ship_to(),
+            customs: shipment.customs_declaration(),
+        })
+        .await
+}
+
+// documents carry addresses and customs values, they go to private storage and the supplier gets
+// a link that expires. The random segment keeps a document from overwriting an earlier one
+pub async fn store_pdf(
+    storage: &dyn Storage,
+    supplier_id: i32,
+    name: &str,
+    bytes: Vec<u8>,
+) -> Result<String, AppError> {
+    let mut nonce = [0u8; 16];
+    OsRng.fill_bytes(&mut nonce);
+    let key = format!(
+        "fulfillment/{}/{}/{}.pdf",
+        supplier_id,
+        nonce
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<String>(),
+        name
+    );
+    storage.put_private(&key, bytes, "application/pdf").await?;
+    storage.signed_url(&key, Duration::minutes(DOCUMENT_URL_MINUTES))
+}
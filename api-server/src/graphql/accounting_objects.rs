@@ -0,0 +1,118 @@
+use crate::{
+    accounting::AccountingFormat,
+    auth::{AuthenticatedUser, RoleGuard, ROLE_ADMIN, ROLE_SUPPLIER},
+    error::AppError,
+    graphql::{complexity, macros::role_guard},
+    models::{
+        accounting::AccountingExports,
+        user::{get_customer_supplier_id, tenant_supplier},
+    },
+    permissions::caller_permissions,
+    tenancy::{current_tenant, TenantScope},
+};
+use async_graphql::{Context, ErrorExtensions, Object};
+use chrono::NaiveDate;
+use sea_orm::{
+    ActiveValue::Set, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder,
+};
+
+// longest period a single export may cover
+const MAX_EXPORT_DAYS: i64 = 366;
+
+#[derive(Default)]
+pub struct AccountingQuery;
+
+#[derive(Default)]
+pub struct AccountingMutation;
+
+#[Object]
+impl AccountingQuery {
+    // exports the caller requested, newest first
+    #[graphql(
+        guard = "role_guard!(ROLE_SUPPLIER, ROLE_ADMIN)",
+        complexity = "complexity::unbounded(child_complexity)"
+    )]
+    async fn accounting_exports(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<Vec<AccountingExports>, async_graphql::Error> {
+        use crate::entity::{
+            accounting_exports, prelude::AccountingExports as AccountingExportsEntity,
+        };
+        let db = ctx.data::<DatabaseConnection>()?;
+        let user_id = AuthenticatedUser::from_ctx(ctx)
+            .map_err(|e| e.extend())?
+            .user_id
+            .parse::<i32>()?;
+
+        let exports = AccountingExportsEntity::find()
+            .for_tenant(current_tenant(ctx))
+            .filter(accounting_exports::Column::RequestedBy.eq(user_id))
+            .order_by_desc(accounting_exports::Column::RequestedAt)
+            .all(db)
+            .await?;
+
+        Ok(exports.into_iter().map(|export| export.into()).collect())
+    }
+}
+
+#[Object]
+impl AccountingMutation {
+    // queued for the export worker, suppliers only get their own lines, admins their marketplace or
+    // one of its suppliers
+    #[graphql(guard = "role_guard!(ROLE_SUPPLIER, ROLE_ADMIN)")]
+    async fn request_accounting_export(
+        &self,
+        ctx: &Context<'_>,
+        format: AccountingFormat,
+        period_start: NaiveDate,
+        period_end: NaiveDate,
+        supplier_id: Option<i32>,
+    ) -> Result<AccountingExports, async_graphql::Error> {
+        use crate::entity::{
+            accounting_exports, prelude::AccountingExports as AccountingExportsEntity,
+        };
+        let db = ctx.data::<DatabaseConnection>()?;
+        let user_id = AuthenticatedUser::from_ctx(ctx)
+            .map_err(|e| e.extend())?
+            .user_id
+            .parse::<i32>()?;
+
+        if period_start > period_end {
+            return Err(AppError::invalid("periodStart must not be after periodEnd").extend());
+        }
+        if (period_end - period_start).num_days() >= MAX_EXPORT_DAYS {
+            return Err(AppError::invalid(format!(
+                "An export covers at most {} days",
+                MAX_EXPORT_DAYS
+            ))
+            .extend());
+        }
+
+        let supplier_id = if !caller_permissions(ctx).await?.has_role(ROLE_ADMIN) {
+            if supplier_id.is_some() {
+                return Err(AppError::forbidden().extend());
+            }
+            Some(get_customer_supplier_id(db, ctx, ROLE_SUPPLIER).await?)
+        } else {
+            if let Some(supplier_id) = supplier_id {
+                tenant_supplier(ctx, supplier_id).await?;
+            }
+            supplier_id
+        };
+
+        let export = AccountingExportsEntity::insert(accounting_exports::ActiveModel {
+            requested_by: Set(user_id),
+            tenant_id: Set(current_tenant(ctx).0),
+            supplier_id: Set(supplier_id),
+            format: Set(format.as_str().to_string()),
+            period_start: Set(period_start),
+            period_end: Set(period_end),
+            ..Default::default()
+        })
+        .exec_with_returning(db)
+        .await?;
+
+        Ok(export.into())
+    }
+}
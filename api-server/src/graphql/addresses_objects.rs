@@ -1,16 +1,19 @@
 use crate::models::addresses::AddressType;
 use crate::{
-    auth::{Auth, RoleGuard, ROLE_CUSTOMER},
-    graphql::macros::role_guard,
+    auth::{AuthenticatedUser, RoleGuard, ROLE_CUSTOMER},
+    error::AppError,
+    graphql::{complexity, macros::role_guard},
     models::{
-        addresses::{create_address, Addresses, RegisterAddress},
+        addresses::{
+            clear_default_address, create_address, AddressInput, Addresses, RegisterAddress,
+        },
         user::get_customer_supplier_id,
     },
 };
-use async_graphql::{Context, Object};
+use async_graphql::{Context, ErrorExtensions, Object};
 use sea_orm::{
     ActiveModelTrait, ActiveValue::Set, ColumnTrait, ConnectionTrait, DatabaseConnection,
-    DbBackend, EntityTrait, ModelTrait, QueryFilter, Statement, TransactionTrait,
+    DbBackend, EntityTrait, FromQueryResult, ModelTrait, QueryFilter, Statement, TransactionTrait,
 };
 
 #[derive(Default)]
@@ -21,15 +24,18 @@ pub struct AddressesMutation;
 
 #[Object]
 impl AddressesQuery {
-    #[graphql(guard = "role_guard!(ROLE_CUSTOMER)")]
+    #[graphql(
+        guard = "role_guard!(ROLE_CUSTOMER)",
+        complexity = "complexity::unbounded(child_complexity)"
+    )]
     async fn addresses(&self, ctx: &Context<'_>) -> Result<Vec<Addresses>, async_graphql::Error> {
         use crate::entity::addresses;
         let db = ctx.data::<DatabaseConnection>()?;
-        let token = ctx
-            .data_opt::<String>()
-            .ok_or("No authorization token found")?;
 
-        let user_id = Auth::verify_token(token)?.user_id.parse::<i32>()?;
+        let user_id = AuthenticatedUser::from_ctx(ctx)
+            .map_err(|e| e.extend())?
+            .user_id
+            .parse::<i32>()?;
         // this query includes inner join with users, customers and addresses tables
         // this query can be written entirely with SELECT and WHERE.
         // Basically, get customer_id using user_id in customer table and then insert it into addresses table.
@@ -48,38 +54,10 @@ impl AddressesQuery {
             ))
             .await?;
 
-        let addresses: Vec<Addresses> = address
-            .into_iter()
-            .map(|item| {
-                addresses::Model {
-                    address_id: item.try_get::<i32>("", "address_id").unwrap().to_owned(),
-                    address_type_id: item
-                        .try_get::<Option<i32>>("", "address_type_id")
-                        .unwrap()
-                        .to_owned(),
-                    city: item.try_get::<String>("", "city").unwrap().to_owned(),
-                    country: item.try_get::<String>("", "country").unwrap().to_owned(),
-                    customer_id: item.try_get::<i32>("", "customer_id").unwrap().to_owned(),
-                    is_default: item
-                        .try_get::<Option<bool>>("", "is_default")
-                        .unwrap()
-                        .to_owned(),
-                    postal_code: item
-                        .try_get::<String>("", "postal_code")
-                        .unwrap()
-                        .to_owned(),
-                    state: item
-                        .try_get::<Option<String>>("", "state")
-                        .unwrap()
-                        .to_owned(),
-                    street_address: item
-                        .try_get::<String>("", "street_address")
-                        .unwrap()
-                        .to_owned(),
-                }
-                .into()
-            })
-            .collect();
+        let addresses = address
+            .iter()
+            .map(|item| addresses::Model::from_query_result(item, "").map(Addresses::from))
+            .collect::<Result<Vec<_>, _>>()?;
 
         Ok(addresses)
     }
@@ -96,50 +74,100 @@ impl AddressesQuery {
         let address_type = AddressTypesEntity::find_by_id(address_type_id)
             .one(db)
             .await?
-            .ok_or("Address type not found")?;
+            .ok_or_else(|| AppError::NotFound("Address type").extend())?;
 
         Ok(address_type.into())
     }
 }
 
+// every address gets an address type of its own holding the customer's label for it
+async fn add_address(
+    ctx: &Context<'_>,
+    mut input: AddressInput,
+) -> Result<Addresses, async_graphql::Error> {
+    use crate::entity::{
+        address_types, addresses,
+        prelude::{AddressTypes as AddressTypesEntity, Addresses as AddressesEntity},
+    };
+    let db = ctx.data::<DatabaseConnection>()?;
+    let txn = db.begin().await?;
+    let customer_id = get_customer_supplier_id(db, ctx, ROLE_CUSTOMER).await?;
+
+    let has_addresses = AddressesEntity::find()
+        .filter(addresses::Column::CustomerId.eq(customer_id))
+        .one(&txn)
+        .await?
+        .is_some();
+    input.is_default |= !has_addresses;
+
+    let address_type_id = AddressTypesEntity::insert(address_types::ActiveModel {
+        name: Set(input.address_type.clone()),
+        ..Default::default()
+    })
+    .exec(&txn)
+    .await?
+    .last_insert_id;
+
+    let address = create_address(input, customer_id, address_type_id, &txn).await?;
+
+    let insert_address = AddressesEntity::insert(address)
+        .exec_with_returning(&txn)
+        .await?;
+
+    txn.commit().await?;
+
+    Ok(insert_address.into())
+}
+
 #[Object]
 impl AddressesMutation {
     #[graphql(guard = "role_guard!(ROLE_CUSTOMER)")]
+    async fn add_address(
+        &self,
+        ctx: &Context<'_>,
+        input: AddressInput,
+    ) -> Result<Addresses, async_graphql::Error> {
+        add_address(ctx, input).await
+    }
+
+    #[graphql(
+        guard = "role_guard!(ROLE_CUSTOMER)",
+        deprecation = "Use addAddress, the customerId given here is ignored"
+    )]
     async fn register_address(
         &self,
         ctx: &Context<'_>,
         input: RegisterAddress,
     ) -> Result<Addresses, async_graphql::Error> {
-        use crate::entity::{
-            address_types,
-            prelude::{AddressTypes as AddressTypesEntity, Addresses as AddressesEntity},
-        };
+        add_address(ctx, input.into()).await
+    }
+
+    // the address that was the default before stops being it
+    #[graphql(guard = "role_guard!(ROLE_CUSTOMER)")]
+    async fn set_default_address(
+        &self,
+        ctx: &Context<'_>,
+        address_id: i32,
+    ) -> Result<Addresses, async_graphql::Error> {
+        use crate::entity::{addresses, prelude::Addresses as AddressesEntity};
         let db = ctx.data::<DatabaseConnection>()?;
-        let token = ctx
-            .data_opt::<String>()
-            .ok_or("No authorization token found")?;
         let txn = db.begin().await?;
-        let customer_id = get_customer_supplier_id(db, token, ROLE_CUSTOMER).await?;
-
-        let address_type = address_types::ActiveModel {
-            name: Set(input.clone().address_type),
-            ..Default::default()
-        };
+        let customer_id = get_customer_supplier_id(db, ctx, ROLE_CUSTOMER).await?;
 
-        let address_type_id = AddressTypesEntity::insert(address_type)
-            .exec(&txn)
+        let address = AddressesEntity::find_by_id(address_id)
+            .one(&txn)
             .await?
-            .last_insert_id;
-
-        let address = create_address(input, customer_id, address_type_id, &txn).await?;
+            .filter(|address| address.customer_id == Some(customer_id))
+            .ok_or_else(|| AppError::NotFound("Address").extend())?;
 
-        let insert_address = AddressesEntity::insert(address)
-            .exec_with_returning(&txn)
-            .await?;
+        clear_default_address(customer_id, &txn).await?;
+        let mut address: addresses::ActiveModel = address.into();
+        address.is_default = Set(Some(true));
+        let address = address.update(&txn).await?;
 
         txn.commit().await?;
 
-        Ok(insert_address.into())
+        Ok(address.into())
     }
 
     #[graphql(guard = "role_guard!(ROLE_CUSTOMER)")]
@@ -152,13 +180,16 @@ impl AddressesMutation {
     ) -> Result<Addresses, async_graphql::Error> {
         use crate::entity::{addresses, prelude::Addresses as AddressesEntity};
         let db = ctx.data::<DatabaseConnection>()?;
-        let token = ctx
-            .data_opt::<String>()
-            .ok_or("No authorization token found")?;
         let txn = db.begin().await?;
-        let customer_id = get_customer_supplier_id(db, token, ROLE_CUSTOMER).await?;
+        let customer_id = get_customer_supplier_id(db, ctx, ROLE_CUSTOMER).await?;
+
+        AddressesEntity::find_by_id(address_id)
+            .one(&txn)
+            .await?
+            .filter(|address| address.customer_id == Some(customer_id))
+            .ok_or_else(|| AppError::NotFound("Address").extend())?;
 
-        let mut address = create_address(input, customer_id, address_type_id, &txn).await?;
+        let mut address = create_address(input.into(), customer_id, address_type_id, &txn).await?;
         address.address_id = Set(address_id);
 
         let update_address = AddressesEntity::update(address)
@@ -182,33 +213,37 @@ impl AddressesMutation {
             prelude::{AddressTypes as AddressTypesEntity, Addresses as AddressesEntity},
         };
         let db = ctx.data::<DatabaseConnection>()?;
-        let token = ctx
-            .data_opt::<String>()
-            .ok_or("No authorization token found")?;
         let txn = db.begin().await?;
-        let customer_id = get_customer_supplier_id(db, token, ROLE_CUSTOMER).await?;
+        let customer_id = get_customer_supplier_id(db, ctx, ROLE_CUSTOMER).await?;
 
         let address = AddressesEntity::find()
             .filter(addresses::Column::AddressId.eq(address_id))
             .one(&txn)
             .await?
-            .ok_or("Address not found")?;
+            .ok_or_else(|| AppError::NotFound("Address").extend())?;
 
-        if address.customer_id != customer_id {
-            return Err("Unauthorized".into());
+        if address.customer_id != Some(customer_id) {
+            return Err(AppError::forbidden().extend());
         }
 
         if address.is_default.unwrap_or(false) {
-            return Err("Cannot delete default address".into());
+            return Err(AppError::invalid("Cannot delete default address").extend());
         }
 
-        let address_type = AddressTypesEntity::find_by_id(address.address_type_id.unwrap())
-            .one(&txn)
-            .await?
-            .ok_or("Address type not found")?;
+        let address_type = match address.address_type_id {
+            Some(address_type_id) => Some(
+                AddressTypesEntity::find_by_id(address_type_id)
+                    .one(&txn)
+                    .await?
+                    .ok_or_else(|| AppError::NotFound("Address type").extend())?,
+            ),
+            None => None,
+        };
 
         address.delete(&txn).await?;
-        address_type.delete(&txn).await?;
+        if let Some(address_type) = address_type {
+            address_type.delete(&txn).await?;
+        }
 
         txn.commit().await?;
 
@@ -227,26 +262,23 @@ impl AddressesMutation {
             prelude::{AddressTypes as AddressTypesEntity, Addresses as AddressesEntity},
         };
         let db = ctx.data::<DatabaseConnection>()?;
-        let token = ctx
-            .data_opt::<String>()
-            .ok_or("No authorization token found")?;
         let txn = db.begin().await?;
-        let customer_id = get_customer_supplier_id(db, token, ROLE_CUSTOMER).await?;
+        let customer_id = get_customer_supplier_id(db, ctx, ROLE_CUSTOMER).await?;
 
         let address_type = AddressTypesEntity::find()
             .filter(address_types::Column::AddressTypeId.eq(address_type_id))
             .one(&txn)
             .await?
-            .ok_or("Address type not found")?;
+            .ok_or_else(|| AppError::NotFound("Address type").extend())?;
 
         let address = AddressesEntity::find()
             .filter(addresses::Column::AddressTypeId.eq(address_type_id))
             .one(&txn)
             .await?
-            .ok_or("Address not found")?;
+            .ok_or_else(|| AppError::NotFound("Address").extend())?;
 
-        if address.customer_id != customer_id {
-            return Err("Unauthorized".into());
+        if address.customer_id != Some(customer_id) {
+            return Err(AppError::forbidden().extend());
         }
 
         let mut address_type: address_types::ActiveModel = address_type.into();
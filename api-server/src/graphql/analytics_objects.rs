@@ -0,0 +1,181 @@
+use crate::{
+    api_keys::in_sandbox,
+    auth::{RoleGuard, ROLE_CUSTOMER, ROLE_SUPPLIER},
+    config::{AnalyticsPolicy, CurrencyPolicy},
+    domain_events::{record_event, ProductViewed, PRODUCT_VIEWED},
+    error::AppError,
+    graphql::{complexity, macros::role_guard},
+    hot_cache::HotCache,
+    ids::ProductId,
+    models::{
+        analytics::{
+            performance_csv, product_performance, supplier_sales, AnalyticsBucket,
+            PerformanceWindow, ProductPerformance, SupplierAnalytics,
+        },
+        products::{check_if_supplier_owns_product, on_sale},
+        user::get_customer_supplier_id,
+    },
+    notifications::DEFAULT_LOCALE,
+    storage::Storage,
+    tenancy::current_tenant,
+};
+use async_graphql::{Context, ErrorExtensions, Object};
+use chrono::Duration;
+use sea_orm::{DatabaseConnection, EntityTrait};
+use std::sync::Arc;
+
+// how long the download link an export returns keeps working
+const REPORT_URL_MINUTES: i64 = 15;
+
+#[derive(Default)]
+pub struct AnalyticsQuery;
+
+#[derive(Default)]
+pub struct AnalyticsMutation;
+
+async fn supplier_report(
+    ctx: &Context<'_>,
+    product_id: ProductId,
+    window: PerformanceWindow,
+    locale: Option<String>,
+) -> Result<ProductPerformance, async_graphql::Error> {
+    let db = ctx.data::<DatabaseConnection>()?;
+    let supplier_id = get_customer_supplier_id(db, ctx, ROLE_SUPPLIER).await?;
+    check_if_supplier_owns_product(db, supplier_id, product_id).await?;
+
+    product_performance(
+        db,
+        current_tenant(ctx),
+        product_id,
+        window,
+        &ctx.data::<CurrencyPolicy>()?.currency,
+        locale.as_deref().unwrap_or(DEFAULT_LOCALE),
+    )
+    .await
+    .map_err(|e| e.extend())
+}
+
+#[Object]
+impl AnalyticsQuery {
+    // how one of the supplier's products did over the window, day by day
+    #[graphql(guard = "role_guard!(ROLE_SUPPLIER)")]
+    async fn product_performance(
+        &self,
+        ctx: &Context<'_>,
+        product_id: ProductId,
+        #[graphql(default_with = "PerformanceWindow::Month")] window: PerformanceWindow,
+        // for the formatted revenue
+        locale: Option<String>,
+    ) -> Result<ProductPerformance, async_graphql::Error> {
+        supplier_report(ctx, product_id, window, locale).await
+    }
+
+    // sales of all the supplier's products over the window, counted at most once an hour
+    #[graphql(
+        guard = "role_guard!(ROLE_SUPPLIER)",
+        complexity = "complexity::unbounded(child_complexity)"
+    )]
+    async fn supplier_analytics(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default_with = "PerformanceWindow::Month")] window: PerformanceWindow,
+        #[graphql(default_with = "AnalyticsBucket::Day")] bucket: AnalyticsBucket,
+        // for the formatted amounts
+        locale: Option<String>,
+    ) -> Result<SupplierAnalytics, async_graphql::Error> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let supplier_id = get_customer_supplier_id(db, ctx, ROLE_SUPPLIER).await?;
+        let tenant = current_tenant(ctx);
+
+        let cache_secs = ctx.data::<AnalyticsPolicy>()?.supplier_cache_secs;
+        let sales = if cache_secs == 0 {
+            supplier_sales(db, tenant, supplier_id, window, bucket).await
+        } else {
+            ctx.data::<HotCache>()?
+                .get_or_load_for(
+                    &format!(
+                        "supplier_analytics:{}:{}:{}:{}:{}",
+                        tenant.0,
+                        if in_sandbox(ctx) { "sandbox" } else { "live" },
+                        supplier_id,
+                        window.days(),
+                        bucket.as_str()
+                    ),
+                    cache_secs,
+                    || supplier_sales(db, tenant, supplier_id, window, bucket),
+                )
+                .await
+        }
+        .map_err(|e| e.extend())?;
+
+        Ok(sales.into_analytics(
+            window,
+            bucket,
+            &ctx.data::<CurrencyPolicy>()?.currency,
+            locale.as_deref().unwrap_or(DEFAULT_LOCALE),
+        ))
+    }
+}
+
+#[Object]
+impl AnalyticsMutation {
+    // sent by the storefront when a product page is shown, signed in customers are attributed
+    async fn track_product_view(
+        &self,
+        ctx: &Context<'_>,
+        product_id: ProductId,
+    ) -> Result<bool, async_graphql::Error> {
+        use crate::entity::prelude::Products as ProductsEntity;
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        let product = on_sale(ProductsEntity::find_by_id(product_id))
+            .one(db)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Product").extend())?;
+        // an invalid or expired token counts as an anonymous view rather than failing the page
+        let customer_id = get_customer_supplier_id(db, ctx, ROLE_CUSTOMER).await.ok();
+
+        record_event(
+            db,
+            product.tenant_id.into(),
+            PRODUCT_VIEWED,
+            &ProductViewed {
+                product_id: product.product_id,
+                customer_id,
+            },
+        )
+        .await
+        .map_err(|e| e.extend())?;
+
+        Ok(true)
+    }
+
+    // the productPerformance days as CSV, returns a download link that expires after a few
+    // minutes, export again for a fresh one
+    #[graphql(guard = "role_guard!(ROLE_SUPPLIER)")]
+    async fn export_product_performance(
+        &self,
+        ctx: &Context<'_>,
+        product_id: ProductId,
+        #[graphql(default_with = "PerformanceWindow::Month")] window: PerformanceWindow,
+    ) -> Result<String, async_graphql::Error> {
+        let report = supplier_report(ctx, product_id, window, None).await?;
+        let bytes = performance_csv(&report, &ctx.data::<CurrencyPolicy>()?.currency)
+            .map_err(|e| e.extend())?;
+
+        // the supplier's sales figures, kept private and only handed out through a signed URL
+        let key = format!(
+            "reports/product-{}-{}-days.csv",
+            product_id.0,
+            window.days()
+        );
+        let storage = ctx.data::<Arc<dyn Storage>>()?;
+        storage
+            .put_private(&key, bytes, "text/csv")
+            .await
+            .map_err(|e| e.extend())?;
+        storage
+            .signed_url(&key, Duration::minutes(REPORT_URL_MINUTES))
+            .map_err(|e| e.extend())
+    }
+}
@@ -0,0 +1,239 @@
+use crate::{
+    auth::{AuthenticatedUser, RoleGuard, ROLE_ADMIN},
+    broker::Broker,
+    error::AppError,
+    graphql::{complexity, macros::role_guard},
+    models::announcements::{
+        active_announcements, audiences_for, next_schedule_change, AnnouncementInput,
+        Announcements, AnnouncementsChanged,
+    },
+    permissions::caller_permissions,
+    tenancy::{current_tenant, TenantScope},
+};
+use async_graphql::{Context, ErrorExtensions, Object, Subscription};
+use chrono::Utc;
+use futures_util::{stream, Stream, StreamExt};
+use sea_orm::{
+    prelude::DateTimeWithTimeZone, ActiveModelTrait, ActiveValue::Set, ColumnTrait,
+    DatabaseConnection, EntityTrait, QueryFilter, QueryOrder,
+};
+
+#[derive(Default)]
+pub struct AnnouncementsQuery;
+
+#[derive(Default)]
+pub struct AnnouncementsMutation;
+
+#[derive(Default)]
+pub struct AnnouncementsSubscription;
+
+fn check_announcement(
+    input: &AnnouncementInput,
+    starts_at: DateTimeWithTimeZone,
+) -> Result<(), async_graphql::Error> {
+    if input.title.trim().is_empty() || input.body.trim().is_empty() {
+        return Err(AppError::invalid("Announcements need a title and a body").extend());
+    }
+    if input.ends_at.is_some_and(|ends_at| ends_at <= starts_at) {
+        return Err(AppError::invalid("An announcement must end after it starts").extend());
+    }
+    Ok(())
+}
+
+// signed out callers get the announcements for everyone
+async fn caller_audiences(ctx: &Context<'_>) -> Result<Vec<&'static str>, async_graphql::Error> {
+    let permissions = match AuthenticatedUser::from_ctx(ctx) {
+        Ok(_) => Some(caller_permissions(ctx).await?),
+        Err(_) => None,
+    };
+    Ok(audiences_for(permissions.as_ref()))
+}
+
+#[Object]
+impl AnnouncementsQuery {
+    // open to anonymous visitors, a token adds the announcements meant for the caller's roles
+    #[graphql(complexity = "complexity::unbounded(child_complexity)")]
+    async fn active_announcements(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<Vec<Announcements>, async_graphql::Error> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let audiences = caller_audiences(ctx).await?;
+
+        Ok(active_announcements(db, current_tenant(ctx), &audiences)
+            .await?
+            .into_iter()
+            .map(|announcement| announcement.into())
+            .collect())
+    }
+
+    // past and scheduled ones included
+    #[graphql(
+        guard = "role_guard!(ROLE_ADMIN)",
+        complexity = "complexity::unbounded(child_complexity)"
+    )]
+    async fn announcements(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<Vec<Announcements>, async_graphql::Error> {
+        use crate::entity::{announcements, prelude::Announcements as AnnouncementsEntity};
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        Ok(AnnouncementsEntity::find()
+            .for_tenant(current_tenant(ctx))
+            .order_by_desc(announcements::Column::StartsAt)
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|announcement| announcement.into())
+            .collect())
+    }
+}
+
+#[Object]
+impl AnnouncementsMutation {
+    #[graphql(guard = "role_guard!(ROLE_ADMIN)")]
+    async fn create_announcement(
+        &self,
+        ctx: &Context<'_>,
+        input: AnnouncementInput,
+    ) -> Result<Announcements, async_graphql::Error> {
+        use crate::entity::{announcements, prelude::Announcements as AnnouncementsEntity};
+        let db = ctx.data::<DatabaseConnection>()?;
+        let user_id = AuthenticatedUser::from_ctx(ctx)
+            .map_err(|e| e.extend())?
+            .user_id
+            .parse::<i32>()?;
+        let starts_at = input.starts_at.unwrap_or_else(|| Utc::now().fixed_offset());
+        check_announcement(&input, starts_at)?;
+
+        let announcement = AnnouncementsEntity::insert(announcements::ActiveModel {
+            title: Set(input.title),
+            body: Set(input.body),
+            kind: Set(input.kind.as_str().to_string()),
+            audience: Set(input.audience.as_str().to_string()),
+            starts_at: Set(starts_at),
+            ends_at: Set(input.ends_at),
+            created_by: Set(Some(user_id)),
+            tenant_id: Set(current_tenant(ctx).0),
+            ..Default::default()
+        })
+        .exec_with_returning(db)
+        .await?;
+        ctx.data::<Broker<AnnouncementsChanged>>()?
+            .publish(AnnouncementsChanged);
+
+        Ok(announcement.into())
+    }
+
+    // replaces every field, a missing startsAt keeps the current start
+    #[graphql(guard = "role_guard!(ROLE_ADMIN)")]
+    async fn update_announcement(
+        &self,
+        ctx: &Context<'_>,
+        announcement_id: i32,
+        input: AnnouncementInput,
+    ) -> Result<Announcements, async_graphql::Error> {
+        use crate::entity::{announcements, prelude::Announcements as AnnouncementsEntity};
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        let announcement = AnnouncementsEntity::find_by_id(announcement_id)
+            .for_tenant(current_tenant(ctx))
+            .one(db)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Announcement").extend())?;
+        let starts_at = input.starts_at.unwrap_or(announcement.starts_at);
+        check_announcement(&input, starts_at)?;
+
+        let mut announcement: announcements::ActiveModel = announcement.into();
+        announcement.title = Set(input.title);
+        announcement.body = Set(input.body);
+        announcement.kind = Set(input.kind.as_str().to_string());
+        announcement.audience = Set(input.audience.as_str().to_string());
+        announcement.starts_at = Set(starts_at);
+        announcement.ends_at = Set(input.ends_at);
+        let announcement = announcement.update(db).await?;
+        ctx.data::<Broker<AnnouncementsChanged>>()?
+            .publish(AnnouncementsChanged);
+
+        Ok(announcement.into())
+    }
+
+    #[graphql(guard = "role_guard!(ROLE_ADMIN)")]
+    async fn delete_announcement(
+        &self,
+        ctx: &Context<'_>,
+        announcement_id: i32,
+    ) -> Result<String, async_graphql::Error> {
+        use crate::entity::{announcements, prelude::Announcements as AnnouncementsEntity};
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        let result = AnnouncementsEntity::delete_by_id(announcement_id)
+            .filter(announcements::Column::TenantId.eq(current_tenant(ctx).0))
+            .exec(db)
+            .await?;
+        if result.rows_affected == 0 {
+            return Err(AppError::NotFound("Announcement").extend());
+        }
+        ctx.data::<Broker<AnnouncementsChanged>>()?
+            .publish(AnnouncementsChanged);
+
+        Ok("Announcement deleted".to_string())
+    }
+}
+
+#[Subscription]
+impl AnnouncementsSubscription {
+    // the caller's active announcements, sent on subscribe and again whenever the list changes,
+    // either through an admin edit or because a scheduled one started or ended
+    async fn active_announcements_changed(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<impl Stream<Item = Vec<Announcements>>, async_graphql::Error> {
+        let db = ctx.data::<DatabaseConnection>()?.clone();
+        let tenant = current_tenant(ctx);
+        let audiences = caller_audiences(ctx).await?;
+        let changes = ctx
+            .data::<Broker<AnnouncementsChanged>>()?
+            .subscribe()
+            .boxed();
+
+        Ok(stream::unfold(
+            (db, audiences, changes, true),
+            |(db, audiences, mut changes, first)| async move {
+                if !first {
+                    // a failed lookup only costs the timed refresh, edits still come through
+                    match next_schedule_change(&db, tenant, &audiences)
+                        .await
+                        .ok()
+                        .flatten()
+                    {
+                        Some(at) => {
+                            let delay = (at.with_timezone(&Utc) - Utc::now())
+                                .to_std()
+                                .unwrap_or_default();
+                            tokio::select! {
+                                change = changes.next() => change?,
+                                _ = tokio::time::sleep(delay) => AnnouncementsChanged,
+                            };
+                        }
+                        None => {
+                            changes.next().await?;
+                        }
+                    }
+                }
+
+                let active = active_announcements(&db, tenant, &audiences)
+                    .await
+                    .map(|active| {
+                        active
+                            .into_iter()
+                            .map(|announcement| announcement.into())
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                Some((active, (db, audiences, changes, false)))
+            },
+        ))
+    }
+}
@@ -0,0 +1,165 @@
+use crate::{
+    api_keys::{forget_api_key, generate_api_key},
+    auth::{RoleGuard, ROLE_CUSTOMER, ROLE_SUPPLIER},
+    catalog_cache::CatalogCache,
+    error::AppError,
+    graphql::{complexity, macros::role_guard},
+    models::api_keys::{ApiKeyOwner, ApiKeys, ApiUsageDay, CreatedApiKey},
+    models::category_counts::rebuild_category_counts,
+    sandbox::{reset_customer_sandbox, reset_supplier_sandbox, SandboxDb},
+    tenancy::current_tenant,
+    terms::TermsGuard,
+};
+use async_graphql::{Context, ErrorExtensions, Object};
+use chrono::{Duration, Utc};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter,
+    QueryOrder, TransactionTrait,
+};
+
+#[derive(Default)]
+pub struct ApiKeysQuery;
+
+#[derive(Default)]
+pub struct ApiKeysMutation;
+
+#[Object]
+impl ApiKeysQuery {
+    #[graphql(
+        guard = "role_guard!(ROLE_SUPPLIER, ROLE_CUSTOMER)",
+        complexity = "complexity::unbounded(child_complexity)"
+    )]
+    async fn api_keys(&self, ctx: &Context<'_>) -> Result<Vec<ApiKeys>, async_graphql::Error> {
+        use crate::entity::{api_keys, prelude::ApiKeys as ApiKeysEntity};
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        let owner = ApiKeyOwner::from_ctx(db, ctx).await?;
+
+        let api_keys = ApiKeysEntity::find()
+            .filter(owner.filter())
+            .order_by_desc(api_keys::Column::CreatedAt)
+            .all(db)
+            .await?;
+
+        Ok(api_keys.into_iter().map(|api_key| api_key.into()).collect())
+    }
+
+    // per day counters for every key the caller owns, newest first
+    #[graphql(
+        guard = "role_guard!(ROLE_SUPPLIER, ROLE_CUSTOMER)",
+        complexity = "complexity::unbounded(child_complexity)"
+    )]
+    async fn my_api_usage(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 30)] days: i64,
+    ) -> Result<Vec<ApiUsageDay>, async_graphql::Error> {
+        use crate::entity::{
+            api_key_usage,
+            prelude::{ApiKeyUsage as ApiKeyUsageEntity, ApiKeys as ApiKeysEntity},
+        };
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        let owner = ApiKeyOwner::from_ctx(db, ctx).await?;
+        let since = (Utc::now() - Duration::days(days)).date_naive();
+
+        let usage = ApiKeyUsageEntity::find()
+            .find_also_related(ApiKeysEntity)
+            .filter(owner.filter())
+            .filter(api_key_usage::Column::UsageDate.gte(since))
+            .order_by_desc(api_key_usage::Column::UsageDate)
+            .all(db)
+            .await?;
+
+        Ok(usage
+            .into_iter()
+            .filter_map(|(usage, api_key)| api_key.map(|api_key| (usage, api_key).into()))
+            .collect())
+    }
+}
+
+#[Object]
+impl ApiKeysMutation {
+    #[graphql(guard = "role_guard!(ROLE_SUPPLIER, ROLE_CUSTOMER).and(TermsGuard)")]
+    async fn create_api_key(
+        &self,
+        ctx: &Context<'_>,
+        name: String,
+        #[graphql(default)] sandbox: bool,
+    ) -> Result<CreatedApiKey, async_graphql::Error> {
+        use crate::entity::{api_keys, prelude::ApiKeys as ApiKeysEntity};
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        let owner = ApiKeyOwner::from_ctx(db, ctx).await?;
+        let generated = generate_api_key();
+
+        let mut api_key = api_keys::ActiveModel {
+            name: Set(name),
+            key_prefix: Set(generated.prefix),
+            key_hash: Set(generated.hash),
+            sandbox: Set(sandbox),
+            ..Default::default()
+        };
+        owner.assign(&mut api_key);
+
+        let insert_api_key = ApiKeysEntity::insert(api_key)
+            .exec_with_returning(db)
+            .await?;
+
+        Ok(CreatedApiKey {
+            api_key: insert_api_key.into(),
+            key: generated.key,
+        })
+    }
+
+    #[graphql(guard = "role_guard!(ROLE_SUPPLIER, ROLE_CUSTOMER)")]
+    async fn revoke_api_key(
+        &self,
+        ctx: &Context<'_>,
+        api_key_id: i32,
+    ) -> Result<String, async_graphql::Error> {
+        use crate::entity::{api_keys, prelude::ApiKeys as ApiKeysEntity};
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        let owner = ApiKeyOwner::from_ctx(db, ctx).await?;
+
+        let api_key = ApiKeysEntity::find_by_id(api_key_id)
+            .one(db)
+            .await?
+            .ok_or_else(|| AppError::NotFound("API key").extend())?;
+
+        if !owner.owns(&api_key) {
+            return Err(AppError::forbidden().extend());
+        }
+
+        let mut api_key: api_keys::ActiveModel = api_key.into();
+        api_key.revoked_at = Set(Some(Utc::now().fixed_offset()));
+        let api_key = api_key.update(db).await?;
+        forget_api_key(api_key.api_key_id);
+
+        Ok("API key revoked".to_string())
+    }
+
+    // clears what the caller's sandbox keys created, suppliers get a fresh copy of their live catalog
+    #[graphql(guard = "role_guard!(ROLE_SUPPLIER, ROLE_CUSTOMER)")]
+    async fn reset_sandbox(&self, ctx: &Context<'_>) -> Result<String, async_graphql::Error> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let sandbox = ctx.data::<SandboxDb>()?;
+
+        let owner = ApiKeyOwner::from_ctx(db, ctx).await?;
+        let txn = sandbox.0.begin().await?;
+        match owner {
+            ApiKeyOwner::Supplier(supplier_id) => reset_supplier_sandbox(&txn, supplier_id).await?,
+            ApiKeyOwner::Customer(customer_id) => reset_customer_sandbox(&txn, customer_id).await?,
+        }
+        txn.commit().await?;
+        if let ApiKeyOwner::Supplier(_) = owner {
+            rebuild_category_counts(&sandbox.0).await?;
+            ctx.data::<CatalogCache>()?
+                .invalidate(current_tenant(ctx))
+                .await;
+        }
+
+        Ok("Sandbox reset".to_string())
+    }
+}
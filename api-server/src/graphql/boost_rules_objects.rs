@@ -0,0 +1,162 @@
+use crate::{
+    auth::{RoleGuard, ROLE_ADMIN},
+    catalog_cache::CatalogCache,
+    error::AppError,
+    graphql::{complexity, macros::role_guard},
+    models::boost_rules::{BoostRuleSet, BoostRules, RegisterBoostRule},
+    tenancy::{current_tenant, TenantScope},
+};
+use async_graphql::{Context, ErrorExtensions, Object};
+use sea_orm::{
+    prelude::Decimal, ActiveValue::Set, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter,
+    QueryOrder,
+};
+
+#[derive(Default)]
+pub struct BoostRulesQuery;
+
+#[derive(Default)]
+pub struct BoostRulesMutation;
+
+fn create_boost_rule_model(
+    input: RegisterBoostRule,
+) -> Result<crate::entity::boost_rules::ActiveModel, async_graphql::Error> {
+    use crate::models::boost_rules::BoostRuleType;
+
+    // each rule type needs its own parameter, the rest are ignored when scoring
+    match input.rule_type {
+        BoostRuleType::NewArrival if input.max_age_days.is_none() => {
+            return Err(AppError::invalid("NEW_ARRIVAL rules need maxAgeDays").extend())
+        }
+        BoostRuleType::Supplier if input.supplier_id.is_none() => {
+            return Err(AppError::invalid("SUPPLIER rules need supplierId").extend())
+        }
+        BoostRuleType::Margin if input.min_margin_percent.is_none() => {
+            return Err(AppError::invalid("MARGIN rules need minMarginPercent").extend())
+        }
+        _ => {}
+    }
+
+    Ok(crate::entity::boost_rules::ActiveModel {
+        name: Set(input.name),
+        rule_type: Set(input.rule_type.as_str().to_string()),
+        weight: Set(Decimal::from_str_exact(&input.weight)?),
+        supplier_id: Set(input.supplier_id),
+        max_age_days: Set(input.max_age_days),
+        min_margin_percent: Set(input
+            .min_margin_percent
+            .as_deref()
+            .map(Decimal::from_str_exact)
+            .transpose()?),
+        active: Set(input.active),
+        ..Default::default()
+    })
+}
+
+#[Object]
+impl BoostRulesQuery {
+    #[graphql(
+        guard = "role_guard!(ROLE_ADMIN)",
+        complexity = "complexity::unbounded(child_complexity)"
+    )]
+    async fn boost_rules(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<Vec<BoostRules>, async_graphql::Error> {
+        use crate::entity::{boost_rules, prelude::BoostRules as BoostRulesEntity};
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        let rules = BoostRulesEntity::find()
+            .for_tenant(current_tenant(ctx))
+            .order_by_asc(boost_rules::Column::BoostRuleId)
+            .all(db)
+            .await?;
+
+        Ok(rules.into_iter().map(|rule| rule.into()).collect())
+    }
+}
+
+#[Object]
+impl BoostRulesMutation {
+    #[graphql(guard = "role_guard!(ROLE_ADMIN)")]
+    async fn create_boost_rule(
+        &self,
+        ctx: &Context<'_>,
+        input: RegisterBoostRule,
+    ) -> Result<BoostRules, async_graphql::Error> {
+        use crate::entity::prelude::BoostRules as BoostRulesEntity;
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        let mut rule = create_boost_rule_model(input)?;
+        rule.tenant_id = Set(current_tenant(ctx).0);
+        let rule = BoostRulesEntity::insert(rule)
+            .exec_with_returning(db)
+            .await?;
+        ctx.data::<BoostRuleSet>()?
+            .announce_change(db, ctx.data::<redis::Client>()?)
+            .await
+            .map_err(|e| e.extend())?;
+        ctx.data::<CatalogCache>()?
+            .invalidate(current_tenant(ctx))
+            .await;
+
+        Ok(rule.into())
+    }
+
+    #[graphql(guard = "role_guard!(ROLE_ADMIN)")]
+    async fn update_boost_rule(
+        &self,
+        ctx: &Context<'_>,
+        boost_rule_id: i32,
+        input: RegisterBoostRule,
+    ) -> Result<BoostRules, async_graphql::Error> {
+        use crate::entity::prelude::BoostRules as BoostRulesEntity;
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        BoostRulesEntity::find_by_id(boost_rule_id)
+            .for_tenant(current_tenant(ctx))
+            .one(db)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Boost rule").extend())?;
+
+        let mut rule = create_boost_rule_model(input)?;
+        rule.boost_rule_id = Set(boost_rule_id);
+        let rule = BoostRulesEntity::update(rule).exec(db).await?;
+        ctx.data::<BoostRuleSet>()?
+            .announce_change(db, ctx.data::<redis::Client>()?)
+            .await
+            .map_err(|e| e.extend())?;
+        ctx.data::<CatalogCache>()?
+            .invalidate(current_tenant(ctx))
+            .await;
+
+        Ok(rule.into())
+    }
+
+    #[graphql(guard = "role_guard!(ROLE_ADMIN)")]
+    async fn delete_boost_rule(
+        &self,
+        ctx: &Context<'_>,
+        boost_rule_id: i32,
+    ) -> Result<String, async_graphql::Error> {
+        use crate::entity::{boost_rules, prelude::BoostRules as BoostRulesEntity};
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        let result = BoostRulesEntity::delete_by_id(boost_rule_id)
+            .filter(boost_rules::Column::TenantId.eq(current_tenant(ctx).0))
+            .exec(db)
+            .await?;
+        if result.rows_affected == 0 {
+            return Err(AppError::NotFound("Boost rule").extend());
+        }
+        ctx.data::<BoostRuleSet>()?
+            .announce_change(db, ctx.data::<redis::Client>()?)
+            .await
+            .map_err(|e| e.extend())?;
+        ctx.data::<CatalogCache>()?
+            .invalidate(current_tenant(ctx))
+            .await;
+
+        Ok("Boost rule deleted".to_string())
+    }
+}
@@ -0,0 +1,154 @@
+use crate::{
+    auth::{AuthenticatedUser, RoleGuard, ROLE_ADMIN},
+    bulk_users::parse_role,
+    entity::sea_orm_active_enums::UserRole,
+    error::AppError,
+    graphql::{complexity, macros::role_guard},
+    models::bulk_user_jobs::{BulkUserAction, BulkUserJobs, MAX_BULK_USERS},
+    tenancy::current_tenant,
+};
+use async_graphql::{Context, ErrorExtensions, Object};
+use sea_orm::{
+    ActiveValue::Set, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder,
+    QuerySelect, TransactionTrait,
+};
+use std::collections::BTreeSet;
+
+#[derive(Default)]
+pub struct BulkUsersQuery;
+
+#[derive(Default)]
+pub struct BulkUsersMutation;
+
+#[Object]
+impl BulkUsersQuery {
+    // progress of a bulk job, with the per account report under results
+    #[graphql(guard = "role_guard!(ROLE_ADMIN)")]
+    async fn bulk_user_job(
+        &self,
+        ctx: &Context<'_>,
+        job_id: i32,
+    ) -> Result<BulkUserJobs, async_graphql::Error> {
+        use crate::entity::{bulk_user_jobs, prelude::BulkUserJobs as BulkUserJobsEntity};
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        Ok(BulkUserJobsEntity::find_by_id(job_id)
+            .filter(bulk_user_jobs::Column::TenantId.eq(current_tenant(ctx).0))
+            .one(db)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Bulk job").extend())?
+            .into())
+    }
+
+    #[graphql(
+        guard = "role_guard!(ROLE_ADMIN)",
+        complexity = "complexity::list(limit.clamp(1, 100), child_complexity)"
+    )]
+    async fn bulk_user_jobs(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 20)] limit: u64,
+    ) -> Result<Vec<BulkUserJobs>, async_graphql::Error> {
+        use crate::entity::{bulk_user_jobs, prelude::BulkUserJobs as BulkUserJobsEntity};
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        Ok(BulkUserJobsEntity::find()
+            .filter(bulk_user_jobs::Column::TenantId.eq(current_tenant(ctx).0))
+            .order_by_desc(bulk_user_jobs::Column::RequestedAt)
+            .limit(limit.clamp(1, 100))
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|job| job.into())
+            .collect())
+    }
+}
+
+#[Object]
+impl BulkUsersMutation {
+    // the accounts can't sign in or refresh until re-enabled, their current sessions end right away
+    #[graphql(guard = "role_guard!(ROLE_ADMIN)")]
+    async fn bulk_disable_users(
+        &self,
+        ctx: &Context<'_>,
+        user_ids: Vec<i32>,
+    ) -> Result<BulkUserJobs, async_graphql::Error> {
+        queue_bulk_user_job(ctx, BulkUserAction::Disable, None, user_ids).await
+    }
+
+    // the passwords stop working, every session ends and the owners are told by email
+    #[graphql(guard = "role_guard!(ROLE_ADMIN)")]
+    async fn bulk_reset_passwords(
+        &self,
+        ctx: &Context<'_>,
+        user_ids: Vec<i32>,
+    ) -> Result<BulkUserJobs, async_graphql::Error> {
+        queue_bulk_user_job(ctx, BulkUserAction::ResetPassword, None, user_ids).await
+    }
+
+    // granted alongside the roles the accounts already hold, it shows up in their next token
+    #[graphql(guard = "role_guard!(ROLE_ADMIN)")]
+    async fn bulk_assign_role(
+        &self,
+        ctx: &Context<'_>,
+        user_ids: Vec<i32>,
+        role: String,
+    ) -> Result<BulkUserJobs, async_graphql::Error> {
+        let role = parse_role(&role).ok_or_else(|| AppError::invalid("Invalid role").extend())?;
+        queue_bulk_user_job(ctx, BulkUserAction::AssignRole, Some(role), user_ids).await
+    }
+}
+
+// queued for the bulk user worker, follow the progress through bulkUserJob with the returned id
+async fn queue_bulk_user_job(
+    ctx: &Context<'_>,
+    action: BulkUserAction,
+    role: Option<UserRole>,
+    user_ids: Vec<i32>,
+) -> Result<BulkUserJobs, async_graphql::Error> {
+    use crate::entity::{
+        bulk_user_job_results, bulk_user_jobs,
+        prelude::{
+            BulkUserJobResults as BulkUserJobResultsEntity, BulkUserJobs as BulkUserJobsEntity,
+        },
+    };
+    let db = ctx.data::<DatabaseConnection>()?;
+    let requested_by = AuthenticatedUser::from_ctx(ctx)
+        .map_err(|e| e.extend())?
+        .user_id
+        .parse::<i32>()?;
+
+    let user_ids: BTreeSet<i32> = user_ids.into_iter().collect();
+    if user_ids.is_empty() {
+        return Err(AppError::invalid("No users given").extend());
+    }
+    if user_ids.len() > MAX_BULK_USERS {
+        return Err(
+            AppError::invalid(format!("At most {} users per bulk job", MAX_BULK_USERS)).extend(),
+        );
+    }
+
+    let txn = db.begin().await?;
+    let job = BulkUserJobsEntity::insert(bulk_user_jobs::ActiveModel {
+        action: Set(action.as_str().to_string()),
+        role: Set(role),
+        requested_by: Set(requested_by),
+        total_users: Set(user_ids.len() as i32),
+        tenant_id: Set(current_tenant(ctx).0),
+        ..Default::default()
+    })
+    .exec_with_returning(&txn)
+    .await?;
+    BulkUserJobResultsEntity::insert_many(user_ids.into_iter().map(|user_id| {
+        bulk_user_job_results::ActiveModel {
+            job_id: Set(job.job_id),
+            user_id: Set(user_id),
+            ..Default::default()
+        }
+    }))
+    .exec_without_returning(&txn)
+    .await?;
+    txn.commit().await?;
+
+    Ok(job.into())
+}
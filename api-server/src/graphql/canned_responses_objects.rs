@@ -0,0 +1,164 @@
+use crate::{
+    auth::{RoleGuard, ROLE_ADMIN, ROLE_SUPPLIER},
+    error::AppError,
+    graphql::{complexity, macros::role_guard},
+    ids::OrderId,
+    models::{
+        canned_responses::{
+            canned_response_owner, resolve_canned_response, usable_by, validate_canned_response,
+            CannedResponses, RegisterCannedResponse,
+        },
+        order_messages::check_order_participant,
+    },
+    tenancy::{current_tenant, TenantScope},
+};
+use async_graphql::{Context, ErrorExtensions, Object};
+use sea_orm::{
+    ActiveValue::Set, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder,
+};
+
+#[derive(Default)]
+pub struct CannedResponsesQuery;
+
+#[derive(Default)]
+pub struct CannedResponsesMutation;
+
+#[Object]
+impl CannedResponsesQuery {
+    // suppliers see their own responses followed by the shared ones, admins the shared ones
+    #[graphql(
+        guard = "role_guard!(ROLE_SUPPLIER, ROLE_ADMIN)",
+        complexity = "complexity::unbounded(child_complexity)"
+    )]
+    async fn canned_responses(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<Vec<CannedResponses>, async_graphql::Error> {
+        use crate::entity::{canned_responses, prelude::CannedResponses as CannedResponsesEntity};
+        let db = ctx.data::<DatabaseConnection>()?;
+        let owner = canned_response_owner(db, ctx).await?;
+
+        let responses = CannedResponsesEntity::find()
+            .for_tenant(current_tenant(ctx))
+            .filter(usable_by(owner))
+            .order_by_asc(canned_responses::Column::SupplierId)
+            .order_by_asc(canned_responses::Column::Title)
+            .all(db)
+            .await?;
+
+        Ok(responses
+            .into_iter()
+            .map(|response| response.into())
+            .collect())
+    }
+
+    // what sendCannedResponse would post to the order's thread
+    #[graphql(guard = "role_guard!(ROLE_SUPPLIER, ROLE_ADMIN)")]
+    async fn preview_canned_response(
+        &self,
+        ctx: &Context<'_>,
+        order_id: OrderId,
+        canned_response_id: i32,
+    ) -> Result<String, async_graphql::Error> {
+        use crate::entity::prelude::CannedResponses as CannedResponsesEntity;
+        let db = ctx.data::<DatabaseConnection>()?;
+        check_order_participant(db, ctx, order_id).await?;
+        let owner = canned_response_owner(db, ctx).await?;
+
+        let response = CannedResponsesEntity::find_by_id(canned_response_id)
+            .for_tenant(current_tenant(ctx))
+            .filter(usable_by(owner))
+            .one(db)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Canned response").extend())?;
+
+        resolve_canned_response(db, order_id.into(), &response.body).await
+    }
+}
+
+#[Object]
+impl CannedResponsesMutation {
+    #[graphql(guard = "role_guard!(ROLE_SUPPLIER, ROLE_ADMIN)")]
+    async fn create_canned_response(
+        &self,
+        ctx: &Context<'_>,
+        input: RegisterCannedResponse,
+    ) -> Result<CannedResponses, async_graphql::Error> {
+        use crate::entity::{canned_responses, prelude::CannedResponses as CannedResponsesEntity};
+        let db = ctx.data::<DatabaseConnection>()?;
+        let owner = canned_response_owner(db, ctx).await?;
+        validate_canned_response(&input).map_err(|e| e.extend())?;
+
+        let response = CannedResponsesEntity::insert(canned_responses::ActiveModel {
+            supplier_id: Set(owner),
+            title: Set(input.title.trim().to_string()),
+            body: Set(input.body),
+            tenant_id: Set(current_tenant(ctx).0),
+            ..Default::default()
+        })
+        .exec_with_returning(db)
+        .await?;
+
+        Ok(response.into())
+    }
+
+    // only the owner edits a response, suppliers can use the shared ones but not change them
+    #[graphql(guard = "role_guard!(ROLE_SUPPLIER, ROLE_ADMIN)")]
+    async fn update_canned_response(
+        &self,
+        ctx: &Context<'_>,
+        canned_response_id: i32,
+        input: RegisterCannedResponse,
+    ) -> Result<CannedResponses, async_graphql::Error> {
+        use crate::entity::{canned_responses, prelude::CannedResponses as CannedResponsesEntity};
+        let db = ctx.data::<DatabaseConnection>()?;
+        let owner = canned_response_owner(db, ctx).await?;
+        validate_canned_response(&input).map_err(|e| e.extend())?;
+
+        let response = CannedResponsesEntity::find_by_id(canned_response_id)
+            .for_tenant(current_tenant(ctx))
+            .one(db)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Canned response").extend())?;
+        if response.supplier_id != owner {
+            return Err(AppError::forbidden().extend());
+        }
+
+        let response = CannedResponsesEntity::update(canned_responses::ActiveModel {
+            canned_response_id: Set(canned_response_id),
+            title: Set(input.title.trim().to_string()),
+            body: Set(input.body),
+            ..Default::default()
+        })
+        .exec(db)
+        .await?;
+
+        Ok(response.into())
+    }
+
+    #[graphql(guard = "role_guard!(ROLE_SUPPLIER, ROLE_ADMIN)")]
+    async fn delete_canned_response(
+        &self,
+        ctx: &Context<'_>,
+        canned_response_id: i32,
+    ) -> Result<String, async_graphql::Error> {
+        use crate::entity::{canned_responses, prelude::CannedResponses as CannedResponsesEntity};
+        let db = ctx.data::<DatabaseConnection>()?;
+        let owner = canned_response_owner(db, ctx).await?;
+
+        let owned = match owner {
+            Some(supplier_id) => canned_responses::Column::SupplierId.eq(supplier_id),
+            None => canned_responses::Column::SupplierId.is_null(),
+        };
+        let result = CannedResponsesEntity::delete_by_id(canned_response_id)
+            .filter(canned_responses::Column::TenantId.eq(current_tenant(ctx).0))
+            .filter(owned)
+            .exec(db)
+            .await?;
+        if result.rows_affected == 0 {
+            return Err(AppError::NotFound("Canned response").extend());
+        }
+
+        Ok("Canned response deleted".to_string())
+    }
+}
@@ -1,16 +1,26 @@
 use crate::{
     auth::{RoleGuard, ROLE_CUSTOMER},
-    graphql::macros::role_guard,
+    cart_expiry::count_restored_cart,
+    config::RetentionPolicy,
+    domain_events::{record_event, ProductAddedToCart, PRODUCT_ADDED_TO_CART},
+    error::AppError,
+    graphql::{complexity, macros::role_guard},
+    ids::ProductId,
     models::{
-        products::{check_product_exists, Products},
+        carts::{ExpiredCartItems, ExpiredCarts, RestoredCart},
+        orders::OrderOwner,
+        products::{check_product_exists, on_sale, Products},
+        purchase_limits::check_purchase_quantity,
         user::get_customer_supplier_id,
     },
 };
-use async_graphql::{Context, Object};
+use async_graphql::{Context, ErrorExtensions, Object};
+use chrono::{Duration, Utc};
 use sea_orm::{
     ActiveModelTrait, ActiveValue::Set, ColumnTrait, DatabaseConnection, EntityTrait, ModelTrait,
-    QueryFilter, TransactionTrait,
+    QueryFilter, QueryOrder, QuerySelect, TransactionTrait,
 };
+use std::collections::BTreeSet;
 
 #[derive(Default)]
 pub struct CartsQuery;
@@ -20,7 +30,10 @@ pub struct CartsMutation;
 
 #[Object]
 impl CartsQuery {
-    #[graphql(guard = "role_guard!(ROLE_CUSTOMER)")]
+    #[graphql(
+        guard = "role_guard!(ROLE_CUSTOMER)",
+        complexity = "complexity::unbounded(child_complexity)"
+    )]
     async fn cart_items(&self, ctx: &Context<'_>) -> Result<Vec<Products>, async_graphql::Error> {
         use crate::entity::{
             cart_items,
@@ -31,18 +44,18 @@ impl CartsQuery {
             shopping_carts,
         };
         let db = ctx.data::<DatabaseConnection>()?;
-        let token = ctx
-            .data_opt::<String>()
-            .ok_or("No authorization token found")?;
 
-        let customer_id = get_customer_supplier_id(db, token, ROLE_CUSTOMER).await?;
+        let customer_id = get_customer_supplier_id(db, ctx, ROLE_CUSTOMER).await?;
 
-        let cart_id = ShoppingCartsEntity::find()
+        // carts are created on the first add and removed when they expire
+        let Some(cart) = ShoppingCartsEntity::find()
             .filter(shopping_carts::Column::CustomerId.eq(customer_id))
             .one(db)
             .await?
-            .unwrap()
-            .cart_id;
+        else {
+            return Ok(Vec::new());
+        };
+        let cart_id = cart.cart_id;
 
         let cart_items = CartItemsEntity::find()
             .filter(cart_items::Column::CartId.eq(cart_id))
@@ -56,13 +69,69 @@ impl CartsQuery {
                 ProductsEntity::find_by_id(cart_item.product_id)
                     .one(db)
                     .await?
-                    .unwrap()
+                    .ok_or_else(|| AppError::NotFound("Product").extend())?
                     .into(),
             );
         }
 
         Ok(products_list)
     }
+
+    // the most recent cart of the customer that expired and can still be restored
+    #[graphql(guard = "role_guard!(ROLE_CUSTOMER)")]
+    async fn expired_cart(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<Option<ExpiredCarts>, async_graphql::Error> {
+        use crate::entity::{
+            expired_cart_items, prelude::ExpiredCartItems as ExpiredCartItemsEntity,
+        };
+        let db = ctx.data::<DatabaseConnection>()?;
+        let customer_id = get_customer_supplier_id(db, ctx, ROLE_CUSTOMER).await?;
+        let grace = Duration::days(ctx.data::<RetentionPolicy>()?.cart_days);
+
+        let Some(expired) = restorable_cart(db, customer_id, grace).await? else {
+            return Ok(None);
+        };
+        let items = ExpiredCartItemsEntity::find()
+            .filter(expired_cart_items::Column::ExpiredCartId.eq(expired.expired_cart_id))
+            .order_by_asc(expired_cart_items::Column::ProductId)
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|item| ExpiredCartItems {
+                product_id: ProductId(item.product_id),
+                quantity: item.quantity,
+                unit_price: item.unit_price.to_string(),
+            })
+            .collect();
+
+        Ok(Some(ExpiredCarts {
+            expired_cart_id: expired.expired_cart_id,
+            item_count: expired.item_count,
+            total_value: expired.total_value.to_string(),
+            expired_at: expired.expired_at,
+            restorable_until: expired.expired_at + grace,
+            items,
+        }))
+    }
+}
+
+// expired carts are kept as long as the retention policy keeps them, which is the grace period
+async fn restorable_cart<C: sea_orm::ConnectionTrait>(
+    db: &C,
+    customer_id: i32,
+    grace: Duration,
+) -> Result<Option<crate::entity::expired_carts::Model>, async_graphql::Error> {
+    use crate::entity::{expired_carts, prelude::ExpiredCarts as ExpiredCartsEntity};
+
+    Ok(ExpiredCartsEntity::find()
+        .filter(expired_carts::Column::CustomerId.eq(customer_id))
+        .filter(expired_carts::Column::RestoredAt.is_null())
+        .filter(expired_carts::Column::ExpiredAt.gt(Utc::now().fixed_offset() - grace))
+        .order_by_desc(expired_carts::Column::ExpiredAt)
+        .one(db)
+        .await?)
 }
 
 #[Object]
@@ -71,23 +140,29 @@ impl CartsMutation {
     async fn add_to_cart(
         &self,
         ctx: &Context<'_>,
-        product_id: i32,
+        product_id: ProductId,
         quantity: i32,
     ) -> Result<i32, async_graphql::Error> {
         use crate::entity::{
             cart_items,
-            prelude::{CartItems as CartItemsEntity, ShoppingCarts as ShoppingCartsEntity},
+            prelude::{
+                CartItems as CartItemsEntity, Products as ProductsEntity,
+                ShoppingCarts as ShoppingCartsEntity,
+            },
             shopping_carts,
         };
         let db = ctx.data::<DatabaseConnection>()?;
-        let token = ctx
-            .data_opt::<String>()
-            .ok_or("No authorization token found")?;
         let txn = db.begin().await?;
 
-        let customer_id = get_customer_supplier_id(db, token, ROLE_CUSTOMER).await?;
+        let customer_id = get_customer_supplier_id(db, ctx, ROLE_CUSTOMER).await?;
 
-        check_product_exists(&txn, product_id).await?;
+        if quantity <= 0 {
+            return Err(AppError::invalid("Quantity must be positive").extend());
+        }
+        let product = on_sale(ProductsEntity::find_by_id(product_id))
+            .one(&txn)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Product").extend())?;
 
         let cart = match ShoppingCartsEntity::find()
             .filter(shopping_carts::Column::CustomerId.eq(customer_id))
@@ -97,21 +172,51 @@ impl CartsMutation {
             Some(cart) => cart,
             None => {
                 let new_cart = shopping_carts::ActiveModel {
-                    customer_id: Set(customer_id),
+                    customer_id: Set(Some(customer_id)),
                     ..Default::default()
                 };
                 new_cart.insert(&txn).await?
             }
         };
 
+        // the limit covers what is already in the cart for the product plus what's being added
+        let in_cart: i32 = CartItemsEntity::find()
+            .filter(cart_items::Column::CartId.eq(cart.cart_id))
+            .filter(cart_items::Column::ProductId.eq(product_id))
+            .all(&txn)
+            .await?
+            .iter()
+            .map(|item| item.quantity)
+            .sum();
+        check_purchase_quantity(
+            &txn,
+            Some(&OrderOwner::Customer(customer_id)),
+            &product,
+            in_cart + quantity,
+        )
+        .await
+        .map_err(|e| e.extend())?;
+
         let cart_item = cart_items::ActiveModel {
             cart_id: Set(cart.cart_id),
-            product_id: Set(product_id),
+            product_id: Set(product_id.into()),
             quantity: Set(quantity),
             ..Default::default()
         };
 
         CartItemsEntity::insert(cart_item).exec(&txn).await?;
+        record_event(
+            &txn,
+            product.tenant_id.into(),
+            PRODUCT_ADDED_TO_CART,
+            &ProductAddedToCart {
+                product_id: product.product_id,
+                customer_id: Some(customer_id),
+                quantity,
+            },
+        )
+        .await
+        .map_err(|e| e.extend())?;
         txn.commit().await?;
 
         Ok(cart.cart_id)
@@ -121,38 +226,38 @@ impl CartsMutation {
     async fn update_cart_item_quantity(
         &self,
         ctx: &Context<'_>,
-        product_id: i32,
+        product_id: ProductId,
         quantity: i32,
         cart_id: i32,
     ) -> Result<String, async_graphql::Error> {
         use crate::entity::{
             cart_items,
-            prelude::{CartItems as CartItemsEntity, ShoppingCarts as ShoppingCartsEntity},
+            prelude::{
+                CartItems as CartItemsEntity, Products as ProductsEntity,
+                ShoppingCarts as ShoppingCartsEntity,
+            },
         };
         let db = ctx.data::<DatabaseConnection>()?;
-        let token = ctx
-            .data_opt::<String>()
-            .ok_or("No authorization token found")?;
         let txn = db.begin().await?;
 
-        let customer_id = get_customer_supplier_id(db, token, ROLE_CUSTOMER).await?;
+        let customer_id = get_customer_supplier_id(db, ctx, ROLE_CUSTOMER).await?;
 
         check_product_exists(&txn, product_id).await?;
 
         let cart = ShoppingCartsEntity::find_by_id(cart_id)
             .one(&txn)
             .await?
-            .ok_or("Cart not found")?;
+            .ok_or_else(|| AppError::NotFound("Cart").extend())?;
 
         let cart_item = CartItemsEntity::find()
             .filter(cart_items::Column::CartId.eq(cart.cart_id))
             .filter(cart_items::Column::ProductId.eq(product_id))
             .one(&txn)
             .await?
-            .ok_or("Product not found in cart")?;
+            .ok_or_else(|| AppError::NotFound("Cart item").extend())?;
 
-        if cart.customer_id != customer_id {
-            return Err("Unauthorized".into());
+        if cart.customer_id != Some(customer_id) {
+            return Err(AppError::forbidden().extend());
         }
 
         if quantity == 0 {
@@ -160,6 +265,19 @@ impl CartsMutation {
             txn.commit().await?;
             Ok("Product removed from cart".to_string())
         } else {
+            let product = on_sale(ProductsEntity::find_by_id(product_id))
+                .one(&txn)
+                .await?
+                .ok_or_else(|| AppError::NotFound("Product").extend())?;
+            check_purchase_quantity(
+                &txn,
+                Some(&OrderOwner::Customer(customer_id)),
+                &product,
+                quantity,
+            )
+            .await
+            .map_err(|e| e.extend())?;
+
             let mut cart_item: cart_items::ActiveModel = cart_item.into();
             cart_item.quantity = Set(quantity);
             cart_item.update(&txn).await?;
@@ -172,7 +290,7 @@ impl CartsMutation {
     async fn remove_from_cart(
         &self,
         ctx: &Context<'_>,
-        product_id: i32,
+        product_id: ProductId,
     ) -> Result<String, async_graphql::Error> {
         use crate::entity::{
             cart_items,
@@ -180,12 +298,9 @@ impl CartsMutation {
             shopping_carts,
         };
         let db = ctx.data::<DatabaseConnection>()?;
-        let token = ctx
-            .data_opt::<String>()
-            .ok_or("No authorization token found")?;
         let txn = db.begin().await?;
 
-        let customer_id = get_customer_supplier_id(db, token, ROLE_CUSTOMER).await?;
+        let customer_id = get_customer_supplier_id(db, ctx, ROLE_CUSTOMER).await?;
 
         check_product_exists(&txn, product_id).await?;
 
@@ -196,7 +311,7 @@ impl CartsMutation {
         {
             Some(cart) => cart,
             None => {
-                return Err("Cart not found".into());
+                return Err(AppError::NotFound("Cart").extend());
             }
         };
 
@@ -205,7 +320,7 @@ impl CartsMutation {
             .filter(cart_items::Column::ProductId.eq(product_id))
             .one(&txn)
             .await?
-            .ok_or("Product not found in cart")?;
+            .ok_or_else(|| AppError::NotFound("Cart item").extend())?;
 
         cart_item.delete(&txn).await?;
 
@@ -213,4 +328,110 @@ impl CartsMutation {
 
         Ok("Product removed from cart".to_string())
     }
+
+    // puts the items of the customer's most recently expired cart back into their cart, products
+    // already in it keep the quantity they have there
+    #[graphql(guard = "role_guard!(ROLE_CUSTOMER)")]
+    async fn restore_expired_cart(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<RestoredCart, async_graphql::Error> {
+        use crate::entity::{
+            cart_items, expired_cart_items, expired_carts,
+            prelude::{
+                CartItems as CartItemsEntity, ExpiredCartItems as ExpiredCartItemsEntity,
+                ExpiredCarts as ExpiredCartsEntity, Products as ProductsEntity,
+                ShoppingCarts as ShoppingCartsEntity,
+            },
+            shopping_carts,
+        };
+        let db = ctx.data::<DatabaseConnection>()?;
+        let customer_id = get_customer_supplier_id(db, ctx, ROLE_CUSTOMER).await?;
+        let grace = Duration::days(ctx.data::<RetentionPolicy>()?.cart_days);
+        let txn = db.begin().await?;
+
+        let expired = restorable_cart(&txn, customer_id, grace)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Expired cart").extend())?;
+        // two restores at once would both add the items
+        let expired = ExpiredCartsEntity::find_by_id(expired.expired_cart_id)
+            .filter(expired_carts::Column::RestoredAt.is_null())
+            .lock_exclusive()
+            .one(&txn)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Expired cart").extend())?;
+
+        let cart = match ShoppingCartsEntity::find()
+            .filter(shopping_carts::Column::CustomerId.eq(customer_id))
+            .one(&txn)
+            .await?
+        {
+            Some(cart) => cart,
+            None => {
+                shopping_carts::ActiveModel {
+                    customer_id: Set(Some(customer_id)),
+                    ..Default::default()
+                }
+                .insert(&txn)
+                .await?
+            }
+        };
+        let in_cart: BTreeSet<i32> = CartItemsEntity::find()
+            .filter(cart_items::Column::CartId.eq(cart.cart_id))
+            .all(&txn)
+            .await?
+            .into_iter()
+            .map(|item| item.product_id)
+            .collect();
+
+        let mut restored_items = Vec::new();
+        let mut skipped_items = Vec::new();
+        for item in ExpiredCartItemsEntity::find()
+            .filter(expired_cart_items::Column::ExpiredCartId.eq(expired.expired_cart_id))
+            .order_by_asc(expired_cart_items::Column::ProductId)
+            .all(&txn)
+            .await?
+        {
+            let product = on_sale(ProductsEntity::find_by_id(item.product_id))
+                .one(&txn)
+                .await?;
+            let restorable = match &product {
+                Some(product) if !in_cart.contains(&item.product_id) => check_purchase_quantity(
+                    &txn,
+                    Some(&OrderOwner::Customer(customer_id)),
+                    product,
+                    item.quantity,
+                )
+                .await
+                .is_ok(),
+                _ => false,
+            };
+            if !restorable {
+                skipped_items.push(ProductId(item.product_id));
+                continue;
+            }
+
+            CartItemsEntity::insert(cart_items::ActiveModel {
+                cart_id: Set(cart.cart_id),
+                product_id: Set(item.product_id),
+                quantity: Set(item.quantity),
+                ..Default::default()
+            })
+            .exec(&txn)
+            .await?;
+            restored_items.push(ProductId(item.product_id));
+        }
+
+        let mut expired: expired_carts::ActiveModel = expired.into();
+        expired.restored_at = Set(Some(Utc::now().fixed_offset()));
+        expired.update(&txn).await?;
+        txn.commit().await?;
+        count_restored_cart();
+
+        Ok(RestoredCart {
+            cart_id: cart.cart_id,
+            restored_items,
+            skipped_items,
+        })
+    }
 }
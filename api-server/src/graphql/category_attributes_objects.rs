@@ -0,0 +1,161 @@
+use crate::{
+    auth::{RoleGuard, ROLE_ADMIN},
+    error::AppError,
+    graphql::{complexity, macros::role_guard},
+    models::{
+        category_attributes::{
+            attribute_facets, attribute_template, category_subtree, normalize_attribute_name,
+            AttributeFacet, CategoryAttributeInput, CategoryAttributes,
+        },
+        products::category_tree,
+    },
+    tenancy::{current_tenant, TenantScope},
+};
+use async_graphql::{Context, ErrorExtensions, Object};
+use sea_orm::{
+    sea_query::OnConflict, ActiveValue::Set, ColumnTrait, DatabaseConnection, EntityTrait,
+    QueryFilter,
+};
+
+#[derive(Default)]
+pub struct CategoryAttributesQuery;
+
+#[derive(Default)]
+pub struct CategoryAttributesMutation;
+
+#[Object]
+impl CategoryAttributesQuery {
+    // what products of the category carry, inherited attributes included
+    #[graphql(complexity = "complexity::unbounded(child_complexity)")]
+    async fn category_attributes(
+        &self,
+        ctx: &Context<'_>,
+        category_id: i32,
+    ) -> Result<Vec<CategoryAttributes>, async_graphql::Error> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let tree = category_tree(ctx).await.map_err(|e| e.extend())?;
+
+        Ok(attribute_template(db, &tree, Some(category_id))
+            .await
+            .map_err(|e| e.extend())?
+            .into_iter()
+            .map(|attribute| attribute.into())
+            .collect())
+    }
+
+    // value counts for each attribute of the category, over its products and its subcategories'
+    #[graphql(complexity = "complexity::unbounded(child_complexity)")]
+    async fn category_facets(
+        &self,
+        ctx: &Context<'_>,
+        category_id: i32,
+    ) -> Result<Vec<AttributeFacet>, async_graphql::Error> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let tree = category_tree(ctx).await.map_err(|e| e.extend())?;
+        let template = attribute_template(db, &tree, Some(category_id))
+            .await
+            .map_err(|e| e.extend())?;
+
+        attribute_facets(
+            db,
+            current_tenant(ctx),
+            category_subtree(&tree, category_id),
+            &template,
+        )
+        .await
+        .map_err(|e| e.extend())
+    }
+}
+
+#[Object]
+impl CategoryAttributesMutation {
+    // adds the attribute to the category or redefines it, products already saved are checked
+    // against the new definition the next time they are updated
+    #[graphql(guard = "role_guard!(ROLE_ADMIN)")]
+    async fn set_category_attribute(
+        &self,
+        ctx: &Context<'_>,
+        category_id: i32,
+        input: CategoryAttributeInput,
+    ) -> Result<CategoryAttributes, async_graphql::Error> {
+        use crate::entity::{
+            category_attributes, prelude::Categories as CategoriesEntity,
+            prelude::CategoryAttributes as CategoryAttributesEntity,
+        };
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        let name = normalize_attribute_name(&input.name);
+        if name.is_empty() || name.chars().count() > 50 {
+            return Err(AppError::invalid("Attribute names are 1 to 50 characters").extend());
+        }
+        let allowed_values = input.allowed_values.map(|values| {
+            values
+                .into_iter()
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+                .collect::<Vec<_>>()
+        });
+        if allowed_values.as_ref().is_some_and(Vec::is_empty) {
+            return Err(
+                "allowedValues needs at least one value, leave it out for free text".into(),
+            );
+        }
+        CategoriesEntity::find_by_id(category_id)
+            .for_tenant(current_tenant(ctx))
+            .one(db)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Category").extend())?;
+
+        let attribute = CategoryAttributesEntity::insert(category_attributes::ActiveModel {
+            category_id: Set(category_id),
+            name: Set(name),
+            required: Set(input.required),
+            allowed_values: Set(allowed_values),
+            ..Default::default()
+        })
+        .on_conflict(
+            OnConflict::columns([
+                category_attributes::Column::CategoryId,
+                category_attributes::Column::Name,
+            ])
+            .update_columns([
+                category_attributes::Column::Required,
+                category_attributes::Column::AllowedValues,
+            ])
+            .to_owned(),
+        )
+        .exec_with_returning(db)
+        .await?;
+
+        Ok(attribute.into())
+    }
+
+    // values products already carry stay stored but no longer show up as facets
+    #[graphql(guard = "role_guard!(ROLE_ADMIN)")]
+    async fn remove_category_attribute(
+        &self,
+        ctx: &Context<'_>,
+        category_id: i32,
+        name: String,
+    ) -> Result<bool, async_graphql::Error> {
+        use crate::entity::{
+            category_attributes, prelude::Categories as CategoriesEntity,
+            prelude::CategoryAttributes as CategoryAttributesEntity,
+        };
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        CategoriesEntity::find_by_id(category_id)
+            .for_tenant(current_tenant(ctx))
+            .one(db)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Category").extend())?;
+
+        let removed = CategoryAttributesEntity::delete_many()
+            .filter(category_attributes::Column::CategoryId.eq(category_id))
+            .filter(category_attributes::Column::Name.eq(normalize_attribute_name(&name)))
+            .exec(db)
+            .await?
+            .rows_affected;
+        Ok(removed > 0)
+    }
+}
@@ -0,0 +1,309 @@
+use crate::{
+    auth::{AuthenticatedUser, RoleGuard, ROLE_ADMIN, ROLE_CUSTOMER},
+    config::{CurrencyPolicy, RegionConfig},
+    entity::{coupons::Model as CouponsModel, shopping_carts::Model as ShoppingCartsModel},
+    error::AppError,
+    graphql::{complexity, macros::role_guard},
+    models::{
+        coupons::{
+            cart_coupon, cart_totals, coupon_unusable_reason, CartTotals, Coupons, RegisterCoupon,
+        },
+        user::get_customer_supplier_id,
+    },
+    tenancy::{current_tenant, TenantScope},
+};
+use async_graphql::{Context, ErrorExtensions, Object};
+use chrono::Utc;
+use sea_orm::{
+    prelude::Decimal,
+    sea_query::{Expr, Func, OnConflict},
+    ActiveModelTrait,
+    ActiveValue::Set,
+    ColumnTrait, ConnectionTrait, DatabaseConnection, EntityTrait, JoinType, QueryFilter,
+    QueryOrder, QuerySelect, RelationTrait,
+};
+
+#[derive(Default)]
+pub struct CouponsQuery;
+
+#[derive(Default)]
+pub struct CouponsMutation;
+
+async fn customer_cart<C: ConnectionTrait>(
+    db: &C,
+    customer_id: i32,
+) -> Result<ShoppingCartsModel, async_graphql::Error> {
+    use crate::entity::{prelude::ShoppingCarts as ShoppingCartsEntity, shopping_carts};
+
+    ShoppingCartsEntity::find()
+        .filter(shopping_carts::Column::CustomerId.eq(customer_id))
+        .one(db)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Cart").extend())
+}
+
+// the coupon on the cart, as long as it can still be used
+async fn applied_coupon<C: ConnectionTrait>(
+    db: &C,
+    customer_id: i32,
+    cart_id: i32,
+) -> Result<Option<CouponsModel>, async_graphql::Error> {
+    let Some(coupon) = cart_coupon(db, cart_id).await? else {
+        return Ok(None);
+    };
+    Ok(coupon_unusable_reason(db, &coupon, Some(customer_id))
+        .await?
+        .is_none()
+        .then_some(coupon))
+}
+
+#[Object]
+impl CouponsQuery {
+    // newest first
+    #[graphql(
+        guard = "role_guard!(ROLE_ADMIN)",
+        complexity = "complexity::unbounded(child_complexity)"
+    )]
+    async fn coupons(&self, ctx: &Context<'_>) -> Result<Vec<Coupons>, async_graphql::Error> {
+        use crate::entity::{coupons, prelude::Coupons as CouponsEntity};
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        Ok(CouponsEntity::find()
+            .filter(coupons::Column::TenantId.eq(current_tenant(ctx).0))
+            .order_by_desc(coupons::Column::CreatedAt)
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|coupon| coupon.into())
+            .collect())
+    }
+
+    // the customer's cart priced with the coupon applied to it, in the region checkout is given
+    #[graphql(guard = "role_guard!(ROLE_CUSTOMER)")]
+    async fn cart_totals(
+        &self,
+        ctx: &Context<'_>,
+        region: Option<String>,
+    ) -> Result<CartTotals, async_graphql::Error> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let customer_id = get_customer_supplier_id(db, ctx, ROLE_CUSTOMER).await?;
+        let cart = customer_cart(db, customer_id).await?;
+        let coupon = applied_coupon(db, customer_id, cart.cart_id).await?;
+
+        Ok(cart_totals(
+            db,
+            cart.cart_id,
+            coupon.as_ref(),
+            ctx.data::<RegionConfig>()?
+                .effective_region(region)
+                .as_deref(),
+            &ctx.data::<CurrencyPolicy>()?.currency,
+        )
+        .await?)
+    }
+}
+
+#[Object]
+impl CouponsMutation {
+    #[graphql(guard = "role_guard!(ROLE_ADMIN)")]
+    async fn create_coupon(
+        &self,
+        ctx: &Context<'_>,
+        input: RegisterCoupon,
+    ) -> Result<Coupons, async_graphql::Error> {
+        use crate::entity::{
+            coupons,
+            prelude::{
+                Categories as CategoriesEntity, Coupons as CouponsEntity,
+                Suppliers as SuppliersEntity,
+            },
+            suppliers, users,
+        };
+        use crate::models::coupons::CouponType;
+        let db = ctx.data::<DatabaseConnection>()?;
+        let user_id = AuthenticatedUser::from_ctx(ctx)
+            .map_err(|e| e.extend())?
+            .user_id
+            .parse::<i32>()?;
+        let tenant = current_tenant(ctx);
+
+        let code = input.code.trim().to_string();
+        if code.is_empty() || code.len() > 50 || code.chars().any(char::is_whitespace) {
+            return Err(
+                AppError::invalid("Code must be 1 to 50 characters without spaces").extend(),
+            );
+        }
+        let value = Decimal::from_str_exact(input.value.trim())?;
+        if value <= Decimal::ZERO
+            || (input.coupon_type == CouponType::Percentage && value > Decimal::ONE_HUNDRED)
+        {
+            return Err(AppError::invalid(
+                "Value must be positive, and at most 100 for percentage coupons",
+            )
+            .extend());
+        }
+        if input.max_uses.is_some_and(|uses| uses <= 0)
+            || input.max_uses_per_customer.is_some_and(|uses| uses <= 0)
+        {
+            return Err(AppError::invalid("Usage limits must be positive").extend());
+        }
+        if let (Some(from), Some(until)) = (input.valid_from, input.valid_until) {
+            if until <= from {
+                return Err(AppError::invalid("Coupon can't expire before it starts").extend());
+            }
+        }
+        if let Some(category_id) = input.category_id {
+            CategoriesEntity::find_by_id(category_id)
+                .for_tenant(tenant)
+                .one(db)
+                .await?
+                .ok_or_else(|| AppError::NotFound("Category").extend())?;
+        }
+        if let Some(supplier_id) = input.supplier_id {
+            SuppliersEntity::find_by_id(supplier_id)
+                .join(JoinType::InnerJoin, suppliers::Relation::Users.def())
+                .filter(users::Column::TenantId.eq(tenant.0))
+                .one(db)
+                .await?
+                .ok_or_else(|| AppError::NotFound("Supplier").extend())?;
+        }
+        let taken = CouponsEntity::find()
+            .filter(coupons::Column::TenantId.eq(tenant.0))
+            .filter(
+                Expr::expr(Func::upper(Expr::col(coupons::Column::Code))).eq(code.to_uppercase()),
+            )
+            .one(db)
+            .await?
+            .is_some();
+        if taken {
+            return Err(
+                AppError::invalid(format!("Coupon code {} is already taken", code)).extend(),
+            );
+        }
+
+        Ok(CouponsEntity::insert(coupons::ActiveModel {
+            tenant_id: Set(tenant.0),
+            code: Set(code),
+            description: Set(input.description),
+            coupon_type: Set(input.coupon_type.as_str().to_string()),
+            value: Set(value),
+            max_uses: Set(input.max_uses),
+            max_uses_per_customer: Set(input.max_uses_per_customer),
+            valid_from: Set(input.valid_from),
+            valid_until: Set(input.valid_until),
+            category_id: Set(input.category_id),
+            supplier_id: Set(input.supplier_id),
+            created_by: Set(Some(user_id)),
+            ..Default::default()
+        })
+        .exec_with_returning(db)
+        .await?
+        .into())
+    }
+
+    // an inactive coupon can't be applied and drops off the carts it is on
+    #[graphql(guard = "role_guard!(ROLE_ADMIN)")]
+    async fn set_coupon_active(
+        &self,
+        ctx: &Context<'_>,
+        coupon_id: i32,
+        active: bool,
+    ) -> Result<Coupons, async_graphql::Error> {
+        use crate::entity::{coupons, prelude::Coupons as CouponsEntity};
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        let coupon = CouponsEntity::find_by_id(coupon_id)
+            .filter(coupons::Column::TenantId.eq(current_tenant(ctx).0))
+            .one(db)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Coupon").extend())?;
+        let mut coupon: coupons::ActiveModel = coupon.into();
+        coupon.active = Set(active);
+
+        Ok(coupon.update(db).await?.into())
+    }
+
+    // puts the coupon on the customer's cart in place of any other and returns the repriced cart.
+    // Nothing counts against the coupon's limits until the cart is ordered
+    #[graphql(guard = "role_guard!(ROLE_CUSTOMER)")]
+    async fn apply_coupon(
+        &self,
+        ctx: &Context<'_>,
+        code: String,
+    ) -> Result<CartTotals, async_graphql::Error> {
+        use crate::entity::{
+            cart_coupons, coupons,
+            prelude::{CartCoupons as CartCouponsEntity, Coupons as CouponsEntity},
+        };
+        let db = ctx.data::<DatabaseConnection>()?;
+        let customer_id = get_customer_supplier_id(db, ctx, ROLE_CUSTOMER).await?;
+
+        let cart = customer_cart(db, customer_id).await?;
+        let coupon = CouponsEntity::find()
+            .filter(coupons::Column::TenantId.eq(current_tenant(ctx).0))
+            .filter(
+                Expr::expr(Func::upper(Expr::col(coupons::Column::Code)))
+                    .eq(code.trim().to_uppercase()),
+            )
+            .one(db)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Coupon").extend())?;
+        if let Some(reason) = coupon_unusable_reason(db, &coupon, Some(customer_id)).await? {
+            return Err(AppError::invalid(reason).extend());
+        }
+
+        let totals = cart_totals(
+            db,
+            cart.cart_id,
+            Some(&coupon),
+            ctx.data::<RegionConfig>()?.pinned_region.as_deref(),
+            &ctx.data::<CurrencyPolicy>()?.currency,
+        )
+        .await?;
+        if Decimal::from_str_exact(&totals.discount)?.is_zero() {
+            return Err(
+                AppError::invalid("The coupon doesn't cover anything in the cart").extend(),
+            );
+        }
+
+        CartCouponsEntity::insert(cart_coupons::ActiveModel {
+            cart_id: Set(cart.cart_id),
+            coupon_id: Set(coupon.coupon_id),
+            applied_at: Set(Utc::now().fixed_offset()),
+        })
+        .on_conflict(
+            OnConflict::column(cart_coupons::Column::CartId)
+                .update_columns([
+                    cart_coupons::Column::CouponId,
+                    cart_coupons::Column::AppliedAt,
+                ])
+                .to_owned(),
+        )
+        .exec(db)
+        .await?;
+
+        Ok(totals)
+    }
+
+    // takes the coupon off the customer's cart
+    #[graphql(guard = "role_guard!(ROLE_CUSTOMER)")]
+    async fn remove_coupon(&self, ctx: &Context<'_>) -> Result<CartTotals, async_graphql::Error> {
+        use crate::entity::prelude::CartCoupons as CartCouponsEntity;
+        let db = ctx.data::<DatabaseConnection>()?;
+        let customer_id = get_customer_supplier_id(db, ctx, ROLE_CUSTOMER).await?;
+
+        let cart = customer_cart(db, customer_id).await?;
+        CartCouponsEntity::delete_by_id(cart.cart_id)
+            .exec(db)
+            .await?;
+
+        Ok(cart_totals(
+            db,
+            cart.cart_id,
+            None,
+            ctx.data::<RegionConfig>()?.pinned_region.as_deref(),
+            &ctx.data::<CurrencyPolicy>()?.currency,
+        )
+        .await?)
+    }
+}
@@ -0,0 +1,149 @@
+use crate::{
+    auth::{RoleGuard, ROLE_ADMIN},
+    error::AppError,
+    graphql::{complexity, macros::role_guard},
+    models::email_templates::{EmailTemplates, RegisterEmailTemplate, RenderedEmail},
+    notifications::{is_valid_locale, render_template, unknown_placeholders, TemplateKey},
+    tenancy::{current_tenant, TenantScope},
+};
+use async_graphql::{Context, ErrorExtensions, Object};
+use sea_orm::{
+    sea_query::OnConflict, ActiveValue::Set, ColumnTrait, DatabaseConnection, EntityTrait,
+    QueryFilter, QueryOrder,
+};
+
+#[derive(Default)]
+pub struct EmailTemplatesQuery;
+
+#[derive(Default)]
+pub struct EmailTemplatesMutation;
+
+#[Object]
+impl EmailTemplatesQuery {
+    #[graphql(
+        guard = "role_guard!(ROLE_ADMIN)",
+        complexity = "complexity::unbounded(child_complexity)"
+    )]
+    async fn email_templates(
+        &self,
+        ctx: &Context<'_>,
+        template_key: Option<TemplateKey>,
+    ) -> Result<Vec<EmailTemplates>, async_graphql::Error> {
+        use crate::entity::{email_templates, prelude::EmailTemplates as EmailTemplatesEntity};
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        let mut templates = EmailTemplatesEntity::find().for_tenant(current_tenant(ctx));
+        if let Some(template_key) = template_key {
+            templates =
+                templates.filter(email_templates::Column::TemplateKey.eq(template_key.as_str()));
+        }
+
+        let templates = templates
+            .order_by_asc(email_templates::Column::TemplateKey)
+            .order_by_asc(email_templates::Column::Locale)
+            .all(db)
+            .await?;
+
+        Ok(templates
+            .into_iter()
+            .map(|template| template.into())
+            .collect())
+    }
+
+    // renders what a recipient with the given locale would receive, filled with sample data
+    #[graphql(guard = "role_guard!(ROLE_ADMIN)")]
+    async fn preview_email_template(
+        &self,
+        ctx: &Context<'_>,
+        template_key: TemplateKey,
+        locale: Option<String>,
+    ) -> Result<RenderedEmail, async_graphql::Error> {
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        render_template(
+            db,
+            current_tenant(ctx),
+            template_key,
+            locale.as_deref(),
+            &template_key.sample_data(),
+        )
+        .await
+        .map_err(|e| e.extend())
+    }
+}
+
+#[Object]
+impl EmailTemplatesMutation {
+    #[graphql(guard = "role_guard!(ROLE_ADMIN)")]
+    async fn upsert_email_template(
+        &self,
+        ctx: &Context<'_>,
+        input: RegisterEmailTemplate,
+    ) -> Result<EmailTemplates, async_graphql::Error> {
+        use crate::entity::{email_templates, prelude::EmailTemplates as EmailTemplatesEntity};
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        let mut failed_rules = Vec::new();
+        if !is_valid_locale(&input.locale) {
+            failed_rules.push(format!("{} is not a valid locale", input.locale));
+        }
+        for placeholder in unknown_placeholders(
+            input.template_key,
+            &format!("{}{}", input.subject, input.html_body),
+        ) {
+            failed_rules.push(format!("unknown placeholder {{{{{}}}}}", placeholder));
+        }
+        if !failed_rules.is_empty() {
+            return Err(AppError::Validation {
+                message: "Email template is invalid".to_string(),
+                failed_rules,
+            }
+            .extend());
+        }
+
+        let template = EmailTemplatesEntity::insert(email_templates::ActiveModel {
+            template_key: Set(input.template_key.as_str().to_string()),
+            locale: Set(input.locale),
+            subject: Set(input.subject),
+            html_body: Set(input.html_body),
+            tenant_id: Set(current_tenant(ctx).0),
+            ..Default::default()
+        })
+        .on_conflict(
+            OnConflict::columns([
+                email_templates::Column::TenantId,
+                email_templates::Column::TemplateKey,
+                email_templates::Column::Locale,
+            ])
+            .update_columns([
+                email_templates::Column::Subject,
+                email_templates::Column::HtmlBody,
+            ])
+            .to_owned(),
+        )
+        .exec_with_returning(db)
+        .await?;
+
+        Ok(template.into())
+    }
+
+    #[graphql(guard = "role_guard!(ROLE_ADMIN)")]
+    async fn delete_email_template(
+        &self,
+        ctx: &Context<'_>,
+        email_template_id: i32,
+    ) -> Result<String, async_graphql::Error> {
+        use crate::entity::{email_templates, prelude::EmailTemplates as EmailTemplatesEntity};
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        let result = EmailTemplatesEntity::delete_by_id(email_template_id)
+            .filter(email_templates::Column::TenantId.eq(current_tenant(ctx).0))
+            .exec(db)
+            .await?;
+        if result.rows_affected == 0 {
+            return Err(AppError::NotFound("Email template").extend());
+        }
+
+        Ok("Email template deleted".to_string())
+    }
+}
@@ -0,0 +1,35 @@
+use crate::{
+    ids::{ProductId, UserId},
+    models::{
+        node::{own_user, visible_product},
+        products::Products,
+        user::Users,
+    },
+};
+use async_graphql::{Context, Object};
+
+#[derive(Default)]
+pub struct FederationQuery;
+
+// entity resolvers behind _entities, other subgraphs of the supergraph reference products and users
+// by these keys. They see exactly what node() would, anything else resolves to null
+#[Object]
+impl FederationQuery {
+    #[graphql(entity)]
+    async fn find_products_by_product_id(
+        &self,
+        ctx: &Context<'_>,
+        product_id: ProductId,
+    ) -> Result<Option<Products>, async_graphql::Error> {
+        visible_product(ctx, product_id).await
+    }
+
+    #[graphql(entity)]
+    async fn find_users_by_user_id(
+        &self,
+        ctx: &Context<'_>,
+        user_id: UserId,
+    ) -> Result<Option<Users>, async_graphql::Error> {
+        own_user(ctx, user_id).await
+    }
+}
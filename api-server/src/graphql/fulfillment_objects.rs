@@ -0,0 +1,203 @@
+use crate::{
+    auth::{RoleGuard, ROLE_SUPPLIER},
+    carriers::Carrier,
+    error::AppError,
+    fulfillment::{load_shipments, packing_slips, ship_from, shipping_label, store_pdf},
+    graphql::macros::role_guard,
+    ids::OrderId,
+    labels::LabelProvider,
+    models::{
+        fulfillment::{CustomsDeclaration, FulfillmentBatch, FulfillmentDocument},
+        user::get_customer_supplier_id,
+    },
+    storage::Storage,
+};
+use async_graphql::{Context, ErrorExtensions, Object};
+use chrono::NaiveDate;
+use sea_orm::DatabaseConnection;
+use std::sync::Arc;
+
+#[derive(Default)]
+pub struct FulfillmentQuery;
+
+#[derive(Default)]
+pub struct FulfillmentMutation;
+
+#[Object]
+impl FulfillmentQuery {
+    // the customs data of the caller's parcel of the order, null when it doesn't leave the country
+    #[graphql(guard = "role_guard!(ROLE_SUPPLIER)")]
+    async fn customs_declaration(
+        &self,
+        ctx: &Context<'_>,
+        order_id: OrderId,
+    ) -> Result<Option<CustomsDeclaration>, async_graphql::Error> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let supplier_id = get_customer_supplier_id(db, ctx, ROLE_SUPPLIER).await?;
+
+        let shipment = load_shipments(db, supplier_id, Some(vec![order_id.into()]), None)
+            .await
+            .map_err(|e| e.extend())?
+            .pop()
+            .ok_or_else(|| AppError::forbidden().extend())?;
+        Ok(shipment.customs_declaration())
+    }
+}
+
+#[Object]
+impl FulfillmentMutation {
+    // one PDF with a slip per order, listing only the caller's items in each
+    #[graphql(guard = "role_guard!(ROLE_SUPPLIER)")]
+    async fn generate_packing_slips(
+        &self,
+        ctx: &Context<'_>,
+        order_ids: Vec<OrderId>,
+    ) -> Result<FulfillmentDocument, async_graphql::Error> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let storage = ctx.data::<Arc<dyn Storage>>()?;
+        let supplier_id = get_customer_supplier_id(db, ctx, ROLE_SUPPLIER).await?;
+
+        let shipments = load_shipments(
+            db,
+            supplier_id,
+            Some(order_ids.into_iter().map(i32::from).collect()),
+            None,
+        )
+        .await
+        .map_err(|e| e.extend())?;
+        if shipments.is_empty() {
+            return Err(AppError::invalid("No orders to print").extend());
+        }
+        let ship_from = ship_from(db, supplier_id).await.map_err(|e| e.extend())?;
+
+        let url = store_pdf(
+            storage.as_ref(),
+            supplier_id,
+            "packing-slips",
+            packing_slips(&shipments, &ship_from),
+        )
+        .await
+        .map_err(|e| e.extend())?;
+        Ok(FulfillmentDocument {
+            url,
+            order_ids: shipments
+                .iter()
+                .map(|shipment| shipment.order.order_id.into())
+                .collect(),
+        })
+    }
+
+    // the carrier defaults to the one the order was marked shipped with
+    #[graphql(guard = "role_guard!(ROLE_SUPPLIER)")]
+    async fn generate_shipping_label(
+        &self,
+        ctx: &Context<'_>,
+        order_id: OrderId,
+        carrier: Option<Carrier>,
+    ) -> Result<FulfillmentDocument, async_graphql::Error> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let storage = ctx.data::<Arc<dyn Storage>>()?;
+        let labels = ctx.data::<Arc<dyn LabelProvider>>()?;
+        let supplier_id = get_customer_supplier_id(db, ctx, ROLE_SUPPLIER).await?;
+
+        let shipment = load_shipments(db, supplier_id, Some(vec![order_id.into()]), None)
+            .await
+            .map_err(|e| e.extend())?
+            .pop()
+            .ok_or_else(|| AppError::forbidden().extend())?;
+        let carrier = carrier
+            .or_else(|| {
+                shipment
+                    .order
+                    .carrier
+                    .as_deref()
+                    .and_then(Carrier::from_code)
+            })
+            .ok_or_else(|| AppError::invalid("No carrier given").extend())?;
+        let ship_from = ship_from(db, supplier_id).await.map_err(|e| e.extend())?;
+
+        let label = shipping_label(labels.as_ref(), &shipment, carrier, &ship_from)
+            .await
+            .map_err(|e| e.extend())?;
+        let url = store_pdf(
+            storage.as_ref(),
+            supplier_id,
+            &format!("{}-label-{}", labels.provider(), order_id),
+            label,
+        )
+        .await
+        .map_err(|e| e.extend())?;
+        Ok(FulfillmentDocument {
+            url,
+            order_ids: vec![order_id],
+        })
+    }
+
+    // the paperwork for every order placed on the day that hasn't gone out yet, labels too when a
+    // carrier is given
+    #[graphql(guard = "role_guard!(ROLE_SUPPLIER)")]
+    async fn generate_fulfillment_batch(
+        &self,
+        ctx: &Context<'_>,
+        date: NaiveDate,
+        carrier: Option<Carrier>,
+    ) -> Result<FulfillmentBatch, async_graphql::Error> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let storage = ctx.data::<Arc<dyn Storage>>()?;
+        let labels = ctx.data::<Arc<dyn LabelProvider>>()?;
+        let supplier_id = get_customer_supplier_id(db, ctx, ROLE_SUPPLIER).await?;
+
+        let shipments = load_shipments(db, supplier_id, None, Some(date))
+            .await
+            .map_err(|e| e.extend())?;
+        if shipments.is_empty() {
+            return Ok(FulfillmentBatch {
+                date,
+                packing_slips: None,
+                labels: Vec::new(),
+            });
+        }
+        let ship_from = ship_from(db, supplier_id).await.map_err(|e| e.extend())?;
+
+        let url = store_pdf(
+            storage.as_ref(),
+            supplier_id,
+            &format!("packing-slips-{}", date),
+            packing_slips(&shipments, &ship_from),
+        )
+        .await
+        .map_err(|e| e.extend())?;
+
+        let mut documents = Vec::new();
+        if let Some(carrier) = carrier {
+            for shipment in &shipments {
+                let label = shipping_label(labels.as_ref(), shipment, carrier, &ship_from)
+                    .await
+                    .map_err(|e| e.extend())?;
+                documents.push(FulfillmentDocument {
+                    url: store_pdf(
+                        storage.as_ref(),
+                        supplier_id,
+                        &format!("{}-label-{}", labels.provider(), shipment.order.order_id),
+                        label,
+                    )
+                    .await
+                    .map_err(|e| e.extend())?,
+                    order_ids: vec![shipment.order.order_id.into()],
+                });
+            }
+        }
+
+        Ok(FulfillmentBatch {
+            date,
+            packing_slips: Some(FulfillmentDocument {
+                url,
+                order_ids: shipments
+                    .iter()
+                    .map(|shipment| shipment.order.order_id.into())
+                    .collect(),
+            }),
+            labels: documents,
+        })
+    }
+}
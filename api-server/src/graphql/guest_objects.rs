@@ -0,0 +1,401 @@
+use crate::{
+    auth::{Auth, AuthenticatedUser, RoleGuard, ROLE_CUSTOMER},
+    config::{CurrencyPolicy, GuestOrderPolicy, RegionConfig, TaxPolicy},
+    domain_events::{record_event, ProductAddedToCart, PRODUCT_ADDED_TO_CART},
+    error::AppError,
+    graphql::{complexity, macros::role_guard},
+    guest::{generate_cart_token, order_link_url, sign_order_link, verify_order_link},
+    ids::{ProductId, TenantId},
+    models::{
+        availability::booking_date_for,
+        carts::CartItems,
+        guest::{GuestCart, GuestCheckout, GuestCheckoutResult, GuestOrder},
+        orders::{charge_order, place_order, OrderOwner, RegisterOrder, RegisterOrderItem},
+        products::on_sale,
+        purchase_limits::check_purchase_quantity,
+        user::get_customer_supplier_id,
+    },
+    money::format_money,
+    notifications::{send_templated, TemplateKey, DEFAULT_LOCALE},
+    payment_gateway::PaymentGateway,
+    pii::{pii, PiiKind},
+    tenancy::{current_tenant, TenantScope},
+};
+use async_graphql::{Context, ErrorExtensions, Object};
+use sea_orm::{
+    sea_query::{Expr, Func},
+    ActiveModelTrait,
+    ActiveValue::Set,
+    ColumnTrait, DatabaseConnection, EntityTrait, ModelTrait, QueryFilter, TransactionTrait,
+};
+use std::sync::Arc;
+
+#[derive(Default)]
+pub struct GuestQuery;
+
+#[derive(Default)]
+pub struct GuestMutation;
+
+async fn find_guest_cart<C: sea_orm::ConnectionTrait>(
+    db: &C,
+    guest_token: &str,
+) -> Result<crate::entity::shopping_carts::Model, async_graphql::Error> {
+    use crate::entity::{prelude::ShoppingCarts as ShoppingCartsEntity, shopping_carts};
+
+    ShoppingCartsEntity::find()
+        .filter(shopping_carts::Column::GuestTokenHash.eq(Auth::hash_secret(guest_token)))
+        .one(db)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Guest cart").extend())
+}
+
+#[Object]
+impl GuestQuery {
+    #[graphql(complexity = "complexity::unbounded(child_complexity)")]
+    async fn guest_cart_items(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(directive = pii::apply(PiiKind::Secret))] guest_token: String,
+    ) -> Result<Vec<CartItems>, async_graphql::Error> {
+        use crate::entity::{cart_items, prelude::CartItems as CartItemsEntity};
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        let cart = find_guest_cart(db, &guest_token).await?;
+        let cart_items = CartItemsEntity::find()
+            .filter(cart_items::Column::CartId.eq(cart.cart_id))
+            .all(db)
+            .await?;
+
+        Ok(cart_items
+            .into_iter()
+            .map(|item| CartItems {
+                cart_item_id: item.cart_item_id,
+                cart_id: item.cart_id,
+                product_id: ProductId(item.product_id),
+                quantity: item.quantity,
+            })
+            .collect())
+    }
+
+    // token comes from the guest checkout result or the mailed link
+    async fn guest_order(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(directive = pii::apply(PiiKind::Secret))] token: String,
+    ) -> Result<GuestOrder, async_graphql::Error> {
+        use crate::entity::prelude::Orders as OrdersEntity;
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        let order_id = verify_order_link(&token).map_err(|e| e.extend())?;
+        let order = OrdersEntity::find_by_id(order_id)
+            .one(db)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Order").extend())?;
+
+        Ok(order.into())
+    }
+
+    // guest orders the customer claimed, orders lists the ones placed with the account
+    #[graphql(
+        guard = "role_guard!(ROLE_CUSTOMER)",
+        complexity = "complexity::unbounded(child_complexity)"
+    )]
+    async fn claimed_guest_orders(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<Vec<GuestOrder>, async_graphql::Error> {
+        use crate::entity::{orders, prelude::Orders as OrdersEntity};
+        let db = ctx.data::<DatabaseConnection>()?;
+        let customer_id = get_customer_supplier_id(db, ctx, ROLE_CUSTOMER).await?;
+
+        Ok(OrdersEntity::find()
+            .filter(orders::Column::CustomerId.eq(customer_id))
+            .filter(orders::Column::PaymentMethodId.is_null())
+            .for_tenant(current_tenant(ctx))
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|order| order.into())
+            .collect())
+    }
+}
+
+#[Object]
+impl GuestMutation {
+    async fn create_guest_cart(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<GuestCart, async_graphql::Error> {
+        use crate::entity::{prelude::ShoppingCarts as ShoppingCartsEntity, shopping_carts};
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        let (guest_token, guest_token_hash) = generate_cart_token();
+        let cart = ShoppingCartsEntity::insert(shopping_carts::ActiveModel {
+            guest_token_hash: Set(Some(guest_token_hash)),
+            ..Default::default()
+        })
+        .exec_with_returning(db)
+        .await?;
+
+        Ok(GuestCart {
+            guest_token,
+            cart_id: cart.cart_id,
+        })
+    }
+
+    async fn add_to_guest_cart(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(directive = pii::apply(PiiKind::Secret))] guest_token: String,
+        product_id: ProductId,
+        quantity: i32,
+    ) -> Result<i32, async_graphql::Error> {
+        use crate::entity::{
+            cart_items,
+            prelude::{CartItems as CartItemsEntity, Products as ProductsEntity},
+        };
+        let db = ctx.data::<DatabaseConnection>()?;
+        let txn = db.begin().await?;
+
+        if quantity <= 0 {
+            return Err(AppError::invalid("Quantity must be positive").extend());
+        }
+        let product = on_sale(ProductsEntity::find_by_id(product_id))
+            .one(&txn)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Product").extend())?;
+        // the guest isn't known until checkout, where their earlier orders are counted too
+        check_purchase_quantity(&txn, None, &product, quantity)
+            .await
+            .map_err(|e| e.extend())?;
+        let cart = find_guest_cart(&txn, &guest_token).await?;
+
+        match CartItemsEntity::find()
+            .filter(cart_items::Column::CartId.eq(cart.cart_id))
+            .filter(cart_items::Column::ProductId.eq(product_id))
+            .one(&txn)
+            .await?
+        {
+            Some(cart_item) => {
+                let mut cart_item: cart_items::ActiveModel = cart_item.into();
+                cart_item.quantity = Set(quantity);
+                cart_item.update(&txn).await?;
+            }
+            None => {
+                CartItemsEntity::insert(cart_items::ActiveModel {
+                    cart_id: Set(cart.cart_id),
+                    product_id: Set(product_id.into()),
+                    quantity: Set(quantity),
+                    ..Default::default()
+                })
+                .exec(&txn)
+                .await?;
+            }
+        }
+        record_event(
+            &txn,
+            product.tenant_id.into(),
+            PRODUCT_ADDED_TO_CART,
+            &ProductAddedToCart {
+                product_id: product.product_id,
+                customer_id: None,
+                quantity,
+            },
+        )
+        .await
+        .map_err(|e| e.extend())?;
+        txn.commit().await?;
+
+        Ok(cart.cart_id)
+    }
+
+    async fn remove_from_guest_cart(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(directive = pii::apply(PiiKind::Secret))] guest_token: String,
+        product_id: ProductId,
+    ) -> Result<String, async_graphql::Error> {
+        use crate::entity::{cart_items, prelude::CartItems as CartItemsEntity};
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        let cart = find_guest_cart(db, &guest_token).await?;
+        let cart_item = CartItemsEntity::find()
+            .filter(cart_items::Column::CartId.eq(cart.cart_id))
+            .filter(cart_items::Column::ProductId.eq(product_id))
+            .one(db)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Cart item").extend())?;
+        cart_item.delete(db).await?;
+
+        Ok("Product removed from cart".to_string())
+    }
+
+    // orders the guest cart, the email is the only identity until the order is claimed
+    async fn guest_checkout(
+        &self,
+        ctx: &Context<'_>,
+        input: GuestCheckout,
+    ) -> Result<GuestCheckoutResult, async_graphql::Error> {
+        use crate::entity::{
+            addresses, cart_items,
+            prelude::{Addresses as AddressesEntity, CartItems as CartItemsEntity},
+        };
+        let db = ctx.data::<DatabaseConnection>()?;
+        let gateway = ctx.data::<Arc<dyn PaymentGateway>>()?;
+
+        let email = input.email.trim().to_lowercase();
+        Auth::check_email(&email)?;
+
+        let cart = find_guest_cart(db, &input.guest_token).await?;
+        let cart_items = CartItemsEntity::find()
+            .filter(cart_items::Column::CartId.eq(cart.cart_id))
+            .all(db)
+            .await?;
+        if cart_items.is_empty() {
+            return Err(AppError::invalid("Cart is empty").extend());
+        }
+
+        let address = input.shipping_address;
+        let address = AddressesEntity::insert(addresses::ActiveModel {
+            street_address: Set(address.street_address),
+            city: Set(address.city),
+            state: Set(address.state),
+            postal_code: Set(address.postal_code),
+            country: Set(address.country),
+            ..Default::default()
+        })
+        .exec_with_returning(db)
+        .await?;
+
+        let order = place_order(
+            db,
+            OrderOwner::Guest(email.clone()),
+            current_tenant(ctx),
+            RegisterOrder {
+                shipping_address_id: address.address_id,
+                payment_method_id: None,
+                discount_code: input.discount_code,
+                order_items: cart_items
+                    .iter()
+                    .map(|item| RegisterOrderItem {
+                        product_id: ProductId(item.product_id),
+                        quantity: item.quantity,
+                        booking_date: booking_date_for(&input.booking_dates, item.product_id),
+                    })
+                    .collect(),
+                po_number: None,
+                region: input.region,
+            },
+            Some(cart.cart_id),
+            ctx.data::<RegionConfig>()?,
+            ctx.data::<CurrencyPolicy>()?,
+            ctx.data::<TaxPolicy>()?,
+        )
+        .await?;
+
+        // the cart only goes once the charge went through, a declined one voids the order and
+        // the guest can try again with the same cart
+        let (order, charge) = charge_order(
+            db,
+            ctx.data::<redis::Client>()?,
+            gateway.as_ref(),
+            order,
+            &input.provider_token,
+            None,
+        )
+        .await?;
+
+        // the items and coupon go with the cart through the cascade
+        cart.delete(db).await?;
+
+        let lookup_token = sign_order_link(order.order_id).map_err(|e| e.extend())?;
+        let lookup_url = order_link_url(ctx.data::<GuestOrderPolicy>()?, &lookup_token);
+        // the order stands even if the confirmation can't be sent, the result carries the token too
+        if let Err(e) = send_templated(
+            db,
+            current_tenant(ctx),
+            TemplateKey::GuestOrderConfirmation,
+            email,
+            input.locale.as_deref(),
+            &[
+                ("order_id", order.order_id.to_string()),
+                (
+                    "total_amount",
+                    format_money(
+                        order.total_amount,
+                        &order.currency,
+                        input.locale.as_deref().unwrap_or(DEFAULT_LOCALE),
+                    ),
+                ),
+                ("lookup_url", lookup_url),
+            ],
+        )
+        .await
+        {
+            eprintln!(
+                "Guest order confirmation failed for order {}: {}",
+                order.order_id, e
+            );
+        }
+
+        Ok(GuestCheckoutResult {
+            order: order.into(),
+            payment_status: charge.status.as_str().to_string(),
+            client_secret: charge.client_secret,
+            lookup_token,
+        })
+    }
+
+    // attaches guest orders placed with the account's email, which has to be verified first
+    #[graphql(
+        guard = "role_guard!(ROLE_CUSTOMER)",
+        complexity = "complexity::unbounded(child_complexity)"
+    )]
+    async fn claim_guest_orders(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<Vec<GuestOrder>, async_graphql::Error> {
+        use crate::entity::{
+            orders,
+            prelude::{Orders as OrdersEntity, Users as UsersEntity},
+        };
+        let db = ctx.data::<DatabaseConnection>()?;
+        let customer_id = get_customer_supplier_id(db, ctx, ROLE_CUSTOMER).await?;
+        let user_id = AuthenticatedUser::from_ctx(ctx)
+            .map_err(|e| e.extend())?
+            .user_id
+            .parse::<i32>()?;
+
+        let user = UsersEntity::find_by_id(user_id)
+            .one(db)
+            .await?
+            .ok_or_else(|| AppError::NotFound("User").extend())?;
+        if !user.email_verified {
+            return Err(AppError::invalid(
+                "Verify your email address before claiming guest orders",
+            )
+            .extend());
+        }
+
+        let txn = db.begin().await?;
+        let guest_orders = OrdersEntity::find()
+            .filter(orders::Column::CustomerId.is_null())
+            .for_tenant(TenantId(user.tenant_id))
+            .filter(
+                Expr::expr(Func::lower(Expr::col(orders::Column::GuestEmail)))
+                    .eq(user.email.to_lowercase()),
+            )
+            .all(&txn)
+            .await?;
+
+        let mut claimed = Vec::with_capacity(guest_orders.len());
+        for order in guest_orders {
+            let mut order: orders::ActiveModel = order.into();
+            order.customer_id = Set(Some(customer_id));
+            claimed.push(order.update(&txn).await?.into());
+        }
+        txn.commit().await?;
+
+        Ok(claimed)
+    }
+}
@@ -0,0 +1,119 @@
+use crate::{
+    auth::{AuthenticatedUser, RoleGuard, ROLE_ADMIN},
+    error::AppError,
+    graphql::{complexity, macros::role_guard},
+    integrity::IntegrityFindingKind,
+    models::integrity::{IntegrityChecks, IntegrityFindings},
+    tenancy::current_tenant,
+};
+use async_graphql::{Context, ErrorExtensions, Object};
+use sea_orm::{
+    ActiveValue::Set, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder,
+    QuerySelect,
+};
+
+#[derive(Default)]
+pub struct IntegrityQuery;
+
+#[derive(Default)]
+pub struct IntegrityMutation;
+
+#[Object]
+impl IntegrityQuery {
+    // newest first
+    #[graphql(
+        guard = "role_guard!(ROLE_ADMIN)",
+        complexity = "complexity::list(limit, child_complexity)"
+    )]
+    async fn integrity_checks(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 20)] limit: u64,
+    ) -> Result<Vec<IntegrityChecks>, async_graphql::Error> {
+        use crate::entity::{integrity_checks, prelude::IntegrityChecks as IntegrityChecksEntity};
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        Ok(IntegrityChecksEntity::find()
+            .filter(integrity_checks::Column::TenantId.eq(current_tenant(ctx).0))
+            .order_by_desc(integrity_checks::Column::RequestedAt)
+            .limit(limit)
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|check| check.into())
+            .collect())
+    }
+
+    // the report of one check, optionally narrowed to one kind of finding
+    #[graphql(
+        guard = "role_guard!(ROLE_ADMIN)",
+        complexity = "complexity::list(limit, child_complexity)"
+    )]
+    async fn integrity_findings(
+        &self,
+        ctx: &Context<'_>,
+        integrity_check_id: i32,
+        kind: Option<IntegrityFindingKind>,
+        #[graphql(default = 100)] limit: u64,
+        #[graphql(default = 0)] offset: u64,
+    ) -> Result<Vec<IntegrityFindings>, async_graphql::Error> {
+        use crate::entity::{
+            integrity_checks, integrity_findings,
+            prelude::{
+                IntegrityChecks as IntegrityChecksEntity,
+                IntegrityFindings as IntegrityFindingsEntity,
+            },
+        };
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        IntegrityChecksEntity::find_by_id(integrity_check_id)
+            .filter(integrity_checks::Column::TenantId.eq(current_tenant(ctx).0))
+            .one(db)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Integrity check").extend())?;
+
+        let mut findings = IntegrityFindingsEntity::find()
+            .filter(integrity_findings::Column::IntegrityCheckId.eq(integrity_check_id));
+        if let Some(kind) = kind {
+            findings = findings.filter(integrity_findings::Column::Kind.eq(kind.as_str()));
+        }
+
+        Ok(findings
+            .order_by_asc(integrity_findings::Column::IntegrityFindingId)
+            .limit(limit)
+            .offset(offset)
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|finding| finding.into())
+            .collect())
+    }
+}
+
+#[Object]
+impl IntegrityMutation {
+    // queued for the integrity check worker, follow it through integrityChecks and read the
+    // report with integrityFindings once it is DONE
+    #[graphql(guard = "role_guard!(ROLE_ADMIN)")]
+    async fn run_integrity_check(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<IntegrityChecks, async_graphql::Error> {
+        use crate::entity::{integrity_checks, prelude::IntegrityChecks as IntegrityChecksEntity};
+        let db = ctx.data::<DatabaseConnection>()?;
+        let user_id = AuthenticatedUser::from_ctx(ctx)
+            .map_err(|e| e.extend())?
+            .user_id
+            .parse::<i32>()?;
+
+        let check = IntegrityChecksEntity::insert(integrity_checks::ActiveModel {
+            requested_by: Set(Some(user_id)),
+            tenant_id: Set(current_tenant(ctx).0),
+            ..Default::default()
+        })
+        .exec_with_returning(db)
+        .await?;
+
+        Ok(check.into())
+    }
+}
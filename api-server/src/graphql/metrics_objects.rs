@@ -0,0 +1,43 @@
+use crate::{
+    auth::{RoleGuard, ROLE_ADMIN},
+    cart_expiry::cart_expiry_counts,
+    graphql::{complexity, macros::role_guard},
+    models::metrics::{CartExpiryMetrics, RetryMetrics},
+    retry::retry_counts,
+};
+use async_graphql::Object;
+
+#[derive(Default)]
+pub struct MetricsQuery;
+
+#[Object]
+impl MetricsQuery {
+    // transient database and redis failures absorbed by retries since this instance started
+    #[graphql(
+        guard = "role_guard!(ROLE_ADMIN)",
+        complexity = "complexity::unbounded(child_complexity)"
+    )]
+    async fn retry_metrics(&self) -> Vec<RetryMetrics> {
+        retry_counts()
+            .into_iter()
+            .map(|(operation, counts)| RetryMetrics {
+                operation: operation.to_string(),
+                retries: counts.retries,
+                recovered: counts.recovered,
+                exhausted: counts.exhausted,
+            })
+            .collect()
+    }
+
+    // carts the expiry job dropped and restoreExpiredCart brought back since this instance started
+    #[graphql(guard = "role_guard!(ROLE_ADMIN)")]
+    async fn cart_expiry_metrics(&self) -> CartExpiryMetrics {
+        let counts = cart_expiry_counts();
+        CartExpiryMetrics {
+            carts_expired: counts.carts_expired,
+            items_expired: counts.items_expired,
+            value_expired: counts.value_expired.to_string(),
+            carts_restored: counts.carts_restored,
+        }
+    }
+}
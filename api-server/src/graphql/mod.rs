@@ -1,10 +1,57 @@
+mod accounting_objects;
 mod addresses_objects;
+mod analytics_objects;
+mod announcements_objects;
+mod api_keys_objects;
+mod boost_rules_objects;
+mod bulk_users_objects;
+mod canned_responses_objects;
 mod carts_objects;
+mod category_attributes_objects;
+mod coupons_objects;
+mod email_templates_objects;
+mod federation_objects;
+mod fulfillment_objects;
+mod guest_objects;
+mod integrity_objects;
+mod metrics_objects;
+mod moderation_objects;
+mod newsletter_objects;
+mod node_objects;
+mod order_messages_objects;
 mod orders_objects;
 mod payments_objects;
 mod products_objects;
+mod rate_limits_objects;
+mod refunds_objects;
+mod retention_objects;
+mod returns_objects;
 pub mod schema;
+#[cfg(test)]
+mod schema_snapshot;
+mod sponsorships_objects;
+mod supplier_agreements_objects;
+mod sync_objects;
+mod terms_objects;
 mod users_objects;
+mod wishlist_objects;
+
+// what list fields add to an operation's complexity, checked against the limit before it runs
+pub mod complexity {
+    // items a list without a limit argument is taken to hold
+    const UNBOUNDED_ITEMS: usize = 10;
+
+    // the children are counted once per item the field may return
+    pub fn list(items: u64, child_complexity: usize) -> usize {
+        usize::try_from(items)
+            .unwrap_or(usize::MAX)
+            .saturating_mul(child_complexity)
+    }
+
+    pub fn unbounded(child_complexity: usize) -> usize {
+        UNBOUNDED_ITEMS.saturating_mul(child_complexity)
+    }
+}
 
 pub mod macros {
     macro_rules! role_guard {
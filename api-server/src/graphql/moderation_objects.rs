@@ -0,0 +1,211 @@
+use crate::{
+    api_keys::revoke_user_api_keys,
+    auth::{AuthenticatedUser, RoleGuard, ROLE_ADMIN},
+    bulk_users::parse_role,
+    catalog_cache::CatalogCache,
+    config::SessionPolicy,
+    error::AppError,
+    graphql::{complexity, macros::role_guard},
+    ids::{ProductId, UserId},
+    models::{
+        category_counts::adjust_category_count,
+        products::Products,
+        user::{Suppliers, UserAccounts},
+    },
+    sessions::revoke_user_sessions,
+    tenancy::{current_tenant, TenantScope},
+};
+use async_graphql::{Context, ErrorExtensions, Object};
+use chrono::Utc;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, DatabaseConnection, EntityTrait, JoinType,
+    QueryFilter, QueryOrder, QuerySelect, RelationTrait, TransactionTrait,
+};
+
+#[derive(Default)]
+pub struct ModerationQuery;
+
+#[derive(Default)]
+pub struct ModerationMutation;
+
+#[Object]
+impl ModerationQuery {
+    // every account of the marketplace, oldest first, role narrows it to the accounts signed up as that
+    #[graphql(
+        guard = "role_guard!(ROLE_ADMIN)",
+        complexity = "complexity::list(limit.clamp(1, 200), child_complexity)"
+    )]
+    async fn all_users(
+        &self,
+        ctx: &Context<'_>,
+        role: Option<String>,
+        #[graphql(default = 50)] limit: u64,
+        #[graphql(default = 0)] offset: u64,
+    ) -> Result<Vec<UserAccounts>, async_graphql::Error> {
+        use crate::entity::{prelude::Users as UsersEntity, users};
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        let mut query = UsersEntity::find().for_tenant(current_tenant(ctx));
+        if let Some(role) = role {
+            query =
+                query.filter(users::Column::Role.eq(
+                    parse_role(&role).ok_or_else(|| AppError::invalid("Invalid role").extend())?,
+                ));
+        }
+
+        Ok(query
+            .order_by_asc(users::Column::UserId)
+            .limit(limit.clamp(1, 200))
+            .offset(offset)
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|user| user.into())
+            .collect())
+    }
+
+    // suppliers waiting for approveSupplier, in the order they signed up
+    #[graphql(
+        guard = "role_guard!(ROLE_ADMIN)",
+        complexity = "complexity::unbounded(child_complexity)"
+    )]
+    async fn pending_suppliers(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<Vec<Suppliers>, async_graphql::Error> {
+        use crate::entity::{prelude::Suppliers as SuppliersEntity, suppliers, users};
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        Ok(SuppliersEntity::find()
+            .join(JoinType::InnerJoin, suppliers::Relation::Users.def())
+            .filter(users::Column::TenantId.eq(current_tenant(ctx).0))
+            .filter(suppliers::Column::ApprovedAt.is_null())
+            .order_by_asc(suppliers::Column::SupplierId)
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|supplier| supplier.into())
+            .collect())
+    }
+}
+
+#[Object]
+impl ModerationMutation {
+    // the account can't sign in or refresh anymore, its current sessions end right away and its
+    // API keys are revoked
+    #[graphql(guard = "role_guard!(ROLE_ADMIN)")]
+    async fn suspend_user(
+        &self,
+        ctx: &Context<'_>,
+        user_id: UserId,
+    ) -> Result<UserAccounts, async_graphql::Error> {
+        use crate::entity::{prelude::Users as UsersEntity, users};
+        let db = ctx.data::<DatabaseConnection>()?;
+        if AuthenticatedUser::from_ctx(ctx)
+            .map_err(|e| e.extend())?
+            .user_id
+            .parse::<i32>()?
+            == user_id.0
+        {
+            return Err(AppError::invalid("Admins can't suspend themselves").extend());
+        }
+
+        let user = UsersEntity::find_by_id(user_id.0)
+            .for_tenant(current_tenant(ctx))
+            .one(db)
+            .await?
+            .ok_or_else(|| AppError::NotFound("User").extend())?;
+        if user.disabled_at.is_some() {
+            return Ok(user.into());
+        }
+        let user = users::ActiveModel {
+            disabled_at: Set(Some(Utc::now().fixed_offset())),
+            ..user.into()
+        }
+        .update(db)
+        .await?;
+        revoke_user_api_keys(db, user.user_id)
+            .await
+            .map_err(|e| e.extend())?;
+        revoke_user_sessions(
+            ctx.data::<redis::Client>()?,
+            ctx.data::<SessionPolicy>()?,
+            user.user_id,
+        )
+        .await
+        .map_err(|e| e.extend())?;
+
+        Ok(user.into())
+    }
+
+    #[graphql(guard = "role_guard!(ROLE_ADMIN)")]
+    async fn approve_supplier(
+        &self,
+        ctx: &Context<'_>,
+        supplier_id: i32,
+    ) -> Result<Suppliers, async_graphql::Error> {
+        use crate::entity::{prelude::Suppliers as SuppliersEntity, suppliers, users};
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        let supplier = SuppliersEntity::find_by_id(supplier_id)
+            .join(JoinType::InnerJoin, suppliers::Relation::Users.def())
+            .filter(users::Column::TenantId.eq(current_tenant(ctx).0))
+            .one(db)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Supplier").extend())?;
+        if supplier.approved_at.is_some() {
+            return Ok(supplier.into());
+        }
+
+        Ok(suppliers::ActiveModel {
+            approved_at: Set(Some(Utc::now().fixed_offset())),
+            ..supplier.into()
+        }
+        .update(db)
+        .await?
+        .into())
+    }
+
+    // takes the product off sale for good, its supplier sees the reason and can't unarchive it
+    #[graphql(guard = "role_guard!(ROLE_ADMIN)")]
+    async fn remove_product(
+        &self,
+        ctx: &Context<'_>,
+        product_id: ProductId,
+        reason: String,
+    ) -> Result<Products, async_graphql::Error> {
+        use crate::entity::{prelude::Products as ProductsEntity, products};
+        let db = ctx.data::<DatabaseConnection>()?;
+        let reason = reason.trim().to_string();
+        if reason.is_empty() {
+            return Err(AppError::invalid("A removal needs a reason").extend());
+        }
+
+        let txn = db.begin().await?;
+        let product = ProductsEntity::find_by_id(product_id)
+            .for_tenant(current_tenant(ctx))
+            .lock_exclusive()
+            .one(&txn)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Product").extend())?;
+        let (category_id, counted) = (product.category_id, product.archived_at.is_none());
+        let now = Utc::now().fixed_offset();
+        let product = products::ActiveModel {
+            archived_at: Set(Some(product.archived_at.unwrap_or(now))),
+            removed_at: Set(Some(now)),
+            removal_reason: Set(Some(reason)),
+            ..product.into()
+        }
+        .update(&txn)
+        .await?;
+        if counted {
+            adjust_category_count(&txn, category_id, -1).await?;
+        }
+        txn.commit().await?;
+        ctx.data::<CatalogCache>()?
+            .invalidate(current_tenant(ctx))
+            .await;
+
+        Ok(product.into())
+    }
+}
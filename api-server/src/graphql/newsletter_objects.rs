@@ -0,0 +1,197 @@
+use crate::{
+    auth::{RoleGuard, ROLE_ADMIN},
+    config::NewsletterPolicy,
+    error::AppError,
+    graphql::{complexity, macros::role_guard},
+    models::{
+        newsletter::{EmailSuppressions, NewsletterSubscriptionsSync, SuppressionReason},
+        sync::{decode_sync_cursor, encode_sync_cursor, MAX_SYNC_BATCH},
+    },
+    newsletter::{request_subscription, unsubscribe},
+    notifications::normalize_email,
+    pii::{pii, PiiKind},
+    tenancy::{current_tenant, TenantScope},
+};
+use async_graphql::{Context, ErrorExtensions, Object};
+use sea_orm::{
+    sea_query::OnConflict, ActiveValue::Set, ColumnTrait, Condition, DatabaseConnection,
+    EntityTrait, QueryFilter, QueryOrder, QuerySelect,
+};
+
+#[derive(Default)]
+pub struct NewsletterQuery;
+
+#[derive(Default)]
+pub struct NewsletterMutation;
+
+#[Object]
+impl NewsletterQuery {
+    // export hook for the ESP, every subscription in the order it last changed, unsubscribes included
+    #[graphql(
+        guard = "role_guard!(ROLE_ADMIN)",
+        complexity = "complexity::list(limit.clamp(1, MAX_SYNC_BATCH), child_complexity)"
+    )]
+    async fn newsletter_subscriptions_updated_since(
+        &self,
+        ctx: &Context<'_>,
+        cursor: Option<String>,
+        #[graphql(default = 100)] limit: u64,
+    ) -> Result<NewsletterSubscriptionsSync, async_graphql::Error> {
+        use crate::entity::{
+            newsletter_subscriptions, prelude::NewsletterSubscriptions as NewsletterEntity,
+        };
+        let db = ctx.data::<DatabaseConnection>()?;
+        let limit = limit.clamp(1, MAX_SYNC_BATCH);
+
+        let mut query = NewsletterEntity::find()
+            .filter(newsletter_subscriptions::Column::TenantId.eq(current_tenant(ctx).0));
+        if let Some(cursor) = cursor {
+            let (updated_at, subscription_id) = decode_sync_cursor(&cursor)?;
+            query = query.filter(
+                Condition::any()
+                    .add(newsletter_subscriptions::Column::UpdatedAt.gt(updated_at))
+                    .add(
+                        Condition::all()
+                            .add(newsletter_subscriptions::Column::UpdatedAt.eq(updated_at))
+                            .add(
+                                newsletter_subscriptions::Column::SubscriptionId
+                                    .gt(subscription_id),
+                            ),
+                    ),
+            );
+        }
+
+        let mut subscriptions = query
+            .order_by_asc(newsletter_subscriptions::Column::UpdatedAt)
+            .order_by_asc(newsletter_subscriptions::Column::SubscriptionId)
+            .limit(limit + 1)
+            .all(db)
+            .await?;
+
+        let has_more = subscriptions.len() as u64 > limit;
+        subscriptions.truncate(limit as usize);
+        let next_cursor = subscriptions.last().map(|subscription| {
+            encode_sync_cursor(subscription.updated_at, subscription.subscription_id)
+        });
+
+        Ok(NewsletterSubscriptionsSync {
+            subscriptions: subscriptions
+                .into_iter()
+                .map(|subscription| subscription.into())
+                .collect(),
+            next_cursor,
+            has_more,
+        })
+    }
+
+    #[graphql(
+        guard = "role_guard!(ROLE_ADMIN)",
+        complexity = "complexity::unbounded(child_complexity)"
+    )]
+    async fn email_suppressions(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<Vec<EmailSuppressions>, async_graphql::Error> {
+        use crate::entity::{email_suppressions, prelude::EmailSuppressions as SuppressionsEntity};
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        let suppressions = SuppressionsEntity::find()
+            .for_tenant(current_tenant(ctx))
+            .order_by_desc(email_suppressions::Column::CreatedAt)
+            .all(db)
+            .await?;
+
+        Ok(suppressions
+            .into_iter()
+            .map(|suppression| suppression.into())
+            .collect())
+    }
+}
+
+#[Object]
+impl NewsletterMutation {
+    // always answers the same, whether the address is new, pending or already subscribed
+    async fn subscribe_newsletter(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(directive = pii::apply(PiiKind::Email))] email: String,
+        locale: Option<String>,
+    ) -> Result<String, async_graphql::Error> {
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        request_subscription(
+            db,
+            ctx.data::<NewsletterPolicy>()?,
+            current_tenant(ctx),
+            &email,
+            locale,
+        )
+        .await
+        .map_err(|e| e.extend())?;
+
+        Ok("Check your inbox to confirm the subscription".to_string())
+    }
+
+    async fn unsubscribe_newsletter(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(directive = pii::apply(PiiKind::Secret))] token: String,
+    ) -> Result<String, async_graphql::Error> {
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        unsubscribe(db, &token).await.map_err(|e| e.extend())?;
+
+        Ok("Unsubscribed from the newsletter".to_string())
+    }
+
+    // bounces and complaints the ESP reports back, no templated mail goes to the address afterwards
+    #[graphql(guard = "role_guard!(ROLE_ADMIN)")]
+    async fn suppress_email(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(directive = pii::apply(PiiKind::Email))] email: String,
+        reason: SuppressionReason,
+    ) -> Result<EmailSuppressions, async_graphql::Error> {
+        use crate::entity::{email_suppressions, prelude::EmailSuppressions as SuppressionsEntity};
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        let suppression = SuppressionsEntity::insert(email_suppressions::ActiveModel {
+            tenant_id: Set(current_tenant(ctx).0),
+            email: Set(normalize_email(&email)),
+            reason: Set(reason.as_str().to_string()),
+            ..Default::default()
+        })
+        .on_conflict(
+            OnConflict::columns([
+                email_suppressions::Column::TenantId,
+                email_suppressions::Column::Email,
+            ])
+            .update_column(email_suppressions::Column::Reason)
+            .to_owned(),
+        )
+        .exec_with_returning(db)
+        .await?;
+
+        Ok(suppression.into())
+    }
+
+    #[graphql(guard = "role_guard!(ROLE_ADMIN)")]
+    async fn unsuppress_email(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(directive = pii::apply(PiiKind::Email))] email: String,
+    ) -> Result<String, async_graphql::Error> {
+        use crate::entity::prelude::EmailSuppressions as SuppressionsEntity;
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        let result =
+            SuppressionsEntity::delete_by_id((current_tenant(ctx).0, normalize_email(&email)))
+                .exec(db)
+                .await?;
+        if result.rows_affected == 0 {
+            return Err(AppError::invalid("Address is not suppressed").extend());
+        }
+
+        Ok("Address removed from the suppression list".to_string())
+    }
+}
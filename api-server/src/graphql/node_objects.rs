@@ -0,0 +1,13 @@
+use crate::models::node::{load_node, Node};
+use async_graphql::{Context, Object, ID};
+
+#[derive(Default)]
+pub struct NodeQuery;
+
+#[Object]
+impl NodeQuery {
+    // Relay object refetching, takes the id field of any Node
+    async fn node(&self, ctx: &Context<'_>, id: ID) -> Result<Option<Node>, async_graphql::Error> {
+        load_node(ctx, &id).await
+    }
+}
@@ -0,0 +1,183 @@
+use crate::{
+    auth::{Claims, RoleGuard, ROLE_ADMIN, ROLE_CUSTOMER, ROLE_SUPPLIER},
+    broker::Broker,
+    config::{ContentFilterPolicy, UploadPolicy},
+    content_filter::{filter_content, ContentField},
+    error::AppError,
+    graphql::{complexity, macros::role_guard},
+    ids::OrderId,
+    models::{
+        canned_responses::{canned_response_owner, resolve_canned_response, usable_by},
+        order_messages::{check_order_participant, check_order_participant_role, OrderMessages},
+    },
+    storage::Storage,
+    tenancy::{current_tenant, TenantScope},
+    uploads::{validate_upload, UploadKind},
+};
+use async_graphql::{Context, ErrorExtensions, Object, Subscription, Upload};
+use futures_util::{future, Stream, StreamExt};
+use sea_orm::{
+    ActiveValue::Set, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder,
+};
+use std::sync::Arc;
+
+#[derive(Default)]
+pub struct OrderMessagesQuery;
+
+#[derive(Default)]
+pub struct OrderMessagesMutation;
+
+#[derive(Default)]
+pub struct OrderMessagesSubscription;
+
+#[Object]
+impl OrderMessagesQuery {
+    #[graphql(
+        guard = "role_guard!(ROLE_CUSTOMER, ROLE_SUPPLIER, ROLE_ADMIN)",
+        complexity = "complexity::unbounded(child_complexity)"
+    )]
+    async fn order_messages(
+        &self,
+        ctx: &Context<'_>,
+        order_id: OrderId,
+    ) -> Result<Vec<OrderMessages>, async_graphql::Error> {
+        use crate::entity::{order_messages, prelude::OrderMessages as OrderMessagesEntity};
+        let db = ctx.data::<DatabaseConnection>()?;
+        check_order_participant(db, ctx, order_id).await?;
+
+        let messages = OrderMessagesEntity::find()
+            .filter(order_messages::Column::OrderId.eq(order_id))
+            .order_by_asc(order_messages::Column::CreatedAt)
+            .all(db)
+            .await?;
+
+        Ok(messages.into_iter().map(|message| message.into()).collect())
+    }
+}
+
+#[Object]
+impl OrderMessagesMutation {
+    #[graphql(guard = "role_guard!(ROLE_CUSTOMER, ROLE_SUPPLIER, ROLE_ADMIN)")]
+    async fn send_order_message(
+        &self,
+        ctx: &Context<'_>,
+        order_id: OrderId,
+        body: String,
+        attachments: Option<Vec<Upload>>,
+    ) -> Result<OrderMessages, async_graphql::Error> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let (claims, sender_role) = check_order_participant_role(db, ctx, order_id).await?;
+
+        let body = body.trim().to_string();
+        if body.is_empty() {
+            return Err(AppError::invalid("Message body cannot be empty").extend());
+        }
+        let body = filter_content(
+            ctx.data::<ContentFilterPolicy>()?,
+            ContentField::OrderMessage,
+            body,
+        )
+        .map_err(|e| e.extend())?;
+
+        // validate everything before storing anything so a bad file doesn't leave orphans behind
+        let policy = ctx.data::<UploadPolicy>()?;
+        let uploads = attachments
+            .unwrap_or_default()
+            .into_iter()
+            .map(|file| validate_upload(ctx, file, UploadKind::Image, policy))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| e.extend())?;
+
+        let storage = ctx.data::<Arc<dyn Storage>>()?;
+        let mut attachment_keys = Vec::with_capacity(uploads.len());
+        // only the order's participants may see them, so they're handed out through signed URLs
+        for upload in uploads {
+            let key = format!("orders/{}/messages/{}", order_id, upload.storage_name());
+            storage
+                .put_private(&key, upload.bytes, upload.content_type)
+                .await
+                .map_err(|e| e.extend())?;
+            attachment_keys.push(key);
+        }
+
+        post_order_message(ctx, order_id, claims, sender_role, body, attachment_keys).await
+    }
+
+    // posts a canned response with the order's details filled in, as if the sender had typed it
+    #[graphql(guard = "role_guard!(ROLE_SUPPLIER, ROLE_ADMIN)")]
+    async fn send_canned_response(
+        &self,
+        ctx: &Context<'_>,
+        order_id: OrderId,
+        canned_response_id: i32,
+    ) -> Result<OrderMessages, async_graphql::Error> {
+        use crate::entity::prelude::CannedResponses;
+        let db = ctx.data::<DatabaseConnection>()?;
+        let (claims, sender_role) = check_order_participant_role(db, ctx, order_id).await?;
+        let owner = canned_response_owner(db, ctx).await?;
+
+        let response = CannedResponses::find_by_id(canned_response_id)
+            .for_tenant(current_tenant(ctx))
+            .filter(usable_by(owner))
+            .one(db)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Canned response").extend())?;
+        let body = resolve_canned_response(db, order_id.into(), &response.body).await?;
+        let body = filter_content(
+            ctx.data::<ContentFilterPolicy>()?,
+            ContentField::OrderMessage,
+            body,
+        )
+        .map_err(|e| e.extend())?;
+
+        post_order_message(ctx, order_id, claims, sender_role, body, Vec::new()).await
+    }
+}
+
+async fn post_order_message(
+    ctx: &Context<'_>,
+    order_id: OrderId,
+    claims: Claims,
+    // the role the sender takes part in the order through
+    sender_role: &str,
+    body: String,
+    attachment_keys: Vec<String>,
+) -> Result<OrderMessages, async_graphql::Error> {
+    use crate::entity::{order_messages, prelude::OrderMessages as OrderMessagesEntity};
+    let db = ctx.data::<DatabaseConnection>()?;
+
+    let message = OrderMessagesEntity::insert(order_messages::ActiveModel {
+        order_id: Set(order_id.into()),
+        sender_user_id: Set(claims.user_id.parse::<i32>()?),
+        sender_role: Set(sender_role.to_string()),
+        body: Set(body),
+        attachment_keys: Set(attachment_keys),
+        ..Default::default()
+    })
+    .exec_with_returning(db)
+    .await?;
+
+    let message: OrderMessages = message.into();
+    ctx.data::<Broker<OrderMessages>>()?
+        .publish(message.clone());
+
+    Ok(message)
+}
+
+#[Subscription]
+impl OrderMessagesSubscription {
+    #[graphql(guard = "role_guard!(ROLE_CUSTOMER, ROLE_SUPPLIER, ROLE_ADMIN)")]
+    async fn order_message_sent(
+        &self,
+        ctx: &Context<'_>,
+        order_id: OrderId,
+    ) -> Result<impl Stream<Item = OrderMessages>, async_graphql::Error> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        check_order_participant(db, ctx, order_id).await?;
+
+        Ok(ctx
+            .data::<Broker<OrderMessages>>()?
+            .subscribe()
+            .filter(move |message| future::ready(message.order_id == order_id)))
+    }
+}
@@ -1,18 +1,41 @@
 use crate::{
-    auth::{RoleGuard, ROLE_CUSTOMER, ROLE_SUPPLIER},
-    graphql::macros::role_guard,
+    auth::{
+        AuthenticatedUser, RoleGuard, VerifiedEmailGuard, ROLE_ADMIN, ROLE_CUSTOMER, ROLE_SUPPLIER,
+    },
+    broker::Broker,
+    carriers::{validate_tracking_number, Carrier},
+    config::{CurrencyPolicy, RegionConfig, TaxPolicy},
+    error::AppError,
+    graphql::{complexity, macros::role_guard},
+    ids::{OrderId, ProductId},
     models::{
+        availability::{booking_date_for, BookingDate},
         bills::Bills,
-        orders::{Orders, RegisterOrder},
+        order_messages::check_order_participant,
+        order_status::{check_transition, OrderStatus},
+        order_timeline::{order_timeline, OrderTimelineEntry},
+        orders::{
+            change_shipping_address, charge_order, place_order, record_status_change,
+            send_shipping_notification, void_order, CheckoutResult, OrderOwner, OrderStatusChanged,
+            Orders, PlacedOrder, RegisterOrder, RegisterOrderItem,
+        },
         products::Products,
         user::get_customer_supplier_id,
     },
+    order_events::publish_status_change,
+    payment_gateway::{ChargeStatus, PaymentGateway},
+    tenancy::{current_tenant, TenantScope},
+    terms::TermsGuard,
 };
-use async_graphql::{Context, Object};
+use async_graphql::{Context, ErrorExtensions, Object, Subscription};
+use chrono::Utc;
+use futures_util::{future, Stream, StreamExt};
 use sea_orm::{
-    prelude::Decimal, ActiveModelTrait, ActiveValue::Set, ColumnTrait, DatabaseConnection,
-    EntityTrait, QueryFilter, TransactionTrait,
+    sea_query::Query, ActiveModelTrait, ActiveValue::Set, ColumnTrait, DatabaseConnection,
+    EntityTrait, JoinType, PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, QueryTrait,
+    RelationTrait, TransactionTrait,
 };
+use std::sync::Arc;
 
 #[derive(Default)]
 pub struct OrdersQuery;
@@ -20,33 +43,56 @@ pub struct OrdersQuery;
 #[derive(Default)]
 pub struct OrdersMutation;
 
+#[derive(Default)]
+pub struct OrdersSubscription;
+
 #[Object]
 impl OrdersQuery {
-    #[graphql(guard = "role_guard!(ROLE_CUSTOMER)")]
+    #[graphql(
+        guard = "role_guard!(ROLE_CUSTOMER)",
+        complexity = "complexity::unbounded(child_complexity)"
+    )]
     async fn orders(&self, ctx: &Context<'_>) -> Result<Vec<Orders>, async_graphql::Error> {
         use crate::entity::{orders, prelude::Orders as OrdersEntity};
         let db = ctx.data::<DatabaseConnection>()?;
-        let token = ctx
-            .data_opt::<String>()
-            .ok_or("No authorization token found")?;
 
-        let customer_id = get_customer_supplier_id(db, token, ROLE_CUSTOMER).await?;
+        let customer_id = get_customer_supplier_id(db, ctx, ROLE_CUSTOMER).await?;
 
+        // claimed guest orders are listed by claimedGuestOrders
         let orders = OrdersEntity::find()
             .filter(orders::Column::CustomerId.eq(customer_id))
+            .filter(orders::Column::PaymentMethodId.is_not_null())
+            .for_tenant(current_tenant(ctx))
             .all(db)
             .await?;
 
-        let orders: Vec<Orders> = orders.into_iter().map(|order| order.into()).collect();
+        orders.into_iter().map(Orders::customer_order).collect()
+    }
 
-        Ok(orders)
+    // status changes, payments, disputes, messages and returns of the order in one feed, oldest first
+    #[graphql(
+        guard = "role_guard!(ROLE_CUSTOMER, ROLE_SUPPLIER, ROLE_ADMIN)",
+        complexity = "complexity::unbounded(child_complexity)"
+    )]
+    async fn order_timeline(
+        &self,
+        ctx: &Context<'_>,
+        order_id: OrderId,
+    ) -> Result<Vec<OrderTimelineEntry>, async_graphql::Error> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        check_order_participant(db, ctx, order_id).await?;
+
+        order_timeline(db, order_id).await
     }
 
-    #[graphql(guard = "role_guard!(ROLE_CUSTOMER)")]
+    #[graphql(
+        guard = "role_guard!(ROLE_CUSTOMER)",
+        complexity = "complexity::unbounded(child_complexity)"
+    )]
     async fn order_items(
         &self,
         ctx: &Context<'_>,
-        order_id: i32,
+        order_id: OrderId,
     ) -> Result<Vec<Products>, async_graphql::Error> {
         use crate::entity::{
             order_items,
@@ -66,7 +112,7 @@ impl OrdersQuery {
                 ProductsEntity::find_by_id(order_item.product_id)
                     .one(db)
                     .await?
-                    .unwrap()
+                    .ok_or_else(|| AppError::NotFound("Product").extend())?
                     .into(),
             );
         }
@@ -74,20 +120,21 @@ impl OrdersQuery {
         Ok(products_list)
     }
 
-    #[graphql(guard = "role_guard!(ROLE_CUSTOMER)")]
+    #[graphql(
+        guard = "role_guard!(ROLE_CUSTOMER)",
+        complexity = "complexity::unbounded(child_complexity)"
+    )]
     async fn bills(&self, ctx: &Context<'_>) -> Result<Vec<Bills>, async_graphql::Error> {
         use crate::entity::{
             bills, orders, prelude::Bills as BillsEntity, prelude::Orders as OrdersEntity,
         };
         let db = ctx.data::<DatabaseConnection>()?;
-        let token = ctx
-            .data_opt::<String>()
-            .ok_or("No authorization token found")?;
 
-        let customer_id = get_customer_supplier_id(db, token, ROLE_CUSTOMER).await?;
+        let customer_id = get_customer_supplier_id(db, ctx, ROLE_CUSTOMER).await?;
 
         let orders = OrdersEntity::find()
             .filter(orders::Column::CustomerId.eq(customer_id))
+            .for_tenant(current_tenant(ctx))
             .all(db)
             .await?;
 
@@ -98,7 +145,10 @@ impl OrdersQuery {
                 .filter(bills::Column::OrderId.eq(order.order_id))
                 .one(db)
                 .await?;
-            bills_list.push(bill.unwrap().into());
+            // orders are billed when they're paid for
+            if let Some(bill) = bill {
+                bills_list.push(bill.into());
+            }
         }
 
         Ok(bills_list)
@@ -107,207 +157,454 @@ impl OrdersQuery {
 
 #[Object]
 impl OrdersMutation {
-    #[graphql(guard = "role_guard!(ROLE_CUSTOMER)")]
+    #[graphql(guard = "role_guard!(ROLE_CUSTOMER).and(TermsGuard).and(VerifiedEmailGuard)")]
     async fn register_order(
         &self,
         ctx: &Context<'_>,
         input: RegisterOrder,
     ) -> Result<Orders, async_graphql::Error> {
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        let customer_id = get_customer_supplier_id(db, ctx, ROLE_CUSTOMER).await?;
+
+        let order = place_order(
+            db,
+            OrderOwner::Customer(customer_id),
+            current_tenant(ctx),
+            input,
+            // the items are given rather than taken from the cart, so no cart coupon applies
+            None,
+            ctx.data::<RegionConfig>()?,
+            ctx.data::<CurrencyPolicy>()?,
+            ctx.data::<TaxPolicy>()?,
+        )
+        .await?;
+
+        Orders::customer_order(order)
+    }
+
+    // orders the cart with a stored payment method, defaults are used for whatever isn't given
+    #[graphql(guard = "role_guard!(ROLE_CUSTOMER).and(TermsGuard).and(VerifiedEmailGuard)")]
+    async fn checkout_with_saved_method(
+        &self,
+        ctx: &Context<'_>,
+        payment_method_id: Option<i32>,
+        shipping_address_id: Option<i32>,
+        discount_code: Option<String>,
+        // a date for every date bookable product in the cart
+        #[graphql(default)] booking_dates: Vec<BookingDate>,
+        // priced like the catalog for the same region, ignored by pinned deployments
+        region: Option<String>,
+    ) -> Result<CheckoutResult, async_graphql::Error> {
         use crate::entity::{
-            discounts, order_items, orders,
+            addresses, cart_items, payment_methods,
             prelude::{
-                Discounts as DiscountsEntity, OrderItems as OrderItemsEntity,
-                Orders as OrdersEntity, Products as ProductsEntity,
+                Addresses as AddressesEntity, CartCoupons as CartCouponsEntity,
+                CartItems as CartItemsEntity, PaymentMethods as PaymentMethodsEntity,
+                ShoppingCarts as ShoppingCartsEntity,
             },
-            products,
+            shopping_carts,
         };
         let db = ctx.data::<DatabaseConnection>()?;
-        let token = ctx
-            .data_opt::<String>()
-            .ok_or("No authorization token found")?;
-        let txn = db.begin().await?;
-
-        let customer_id = get_customer_supplier_id(db, token, ROLE_CUSTOMER).await?;
-
-        let discount_id = match &input.discount_code {
-            Some(discount_code) => ProductsEntity::find()
-                .filter(products::Column::Name.eq(discount_code))
-                .one(db)
-                .await
-                .map_err(|_| "Discount not found")?
-                .map(|product| product.product_id),
-            None => None,
-        };
-
-        let mut total_amount: f64 = 0.0;
-        for item in &input.order_items {
-            let product: products::Model = ProductsEntity::find_by_id(item.product_id)
-                .one(db)
-                .await
-                .map_err(|_| "Product not found")?
-                .unwrap();
-            total_amount += product.base_price.to_string().parse::<f64>()? * item.quantity as f64;
-        }
-
-        if let Some(discount_id) = discount_id {
-            let discount: discounts::Model = DiscountsEntity::find_by_id(discount_id)
-                .one(&txn)
-                .await?
-                .unwrap();
-
-            if discount.discount_type == "PERCENTAGE" {
-                total_amount -=
-                    total_amount * discount.discount_value.to_string().parse::<f64>()? / 100.0;
-            } else {
-                total_amount -= discount.discount_value.to_string().parse::<f64>()?;
-            }
-
-            // increment discount usage
-            let mut discount: discounts::ActiveModel = discount.into();
-            discount.times_used = Set(Some(discount.times_used.unwrap().unwrap() + 1));
-        }
-
-        let order = orders::ActiveModel {
-            customer_id: Set(customer_id),
-            shipping_address_id: Set(input.shipping_address_id),
-            payment_method_id: Set(input.payment_method_id),
-            discount_id: Set(discount_id),
-            total_amount: Set(Decimal::from_str_exact(total_amount.to_string().as_str())?),
-            status: Set("PENDING".to_string()),
-            ..Default::default()
-        };
-
-        let insert_order = OrdersEntity::insert(order)
-            .exec_with_returning(&txn)
+        let customer_id = get_customer_supplier_id(db, ctx, ROLE_CUSTOMER).await?;
+        let gateway = ctx.data::<Arc<dyn PaymentGateway>>()?;
+
+        let payment_method = PaymentMethodsEntity::find()
+            .filter(payment_methods::Column::CustomerId.eq(customer_id))
+            .apply_if(payment_method_id, |query, payment_method_id| {
+                query.filter(payment_methods::Column::PaymentMethodId.eq(payment_method_id))
+            })
+            .order_by_desc(payment_methods::Column::IsDefault)
+            .one(db)
+            .await?
+            .filter(|method| payment_method_id.is_some() || method.is_default == Some(true))
+            .ok_or_else(|| AppError::NotFound("Saved payment method").extend())?;
+        let provider_token = payment_method.provider_token.clone().ok_or_else(|| {
+            AppError::invalid("Payment method is not stored with the payment provider").extend()
+        })?;
+
+        let address = AddressesEntity::find()
+            .filter(addresses::Column::CustomerId.eq(customer_id))
+            .apply_if(shipping_address_id, |query, shipping_address_id| {
+                query.filter(addresses::Column::AddressId.eq(shipping_address_id))
+            })
+            .order_by_desc(addresses::Column::IsDefault)
+            .one(db)
+            .await?
+            .filter(|address| shipping_address_id.is_some() || address.is_default == Some(true))
+            .ok_or_else(|| AppError::NotFound("Shipping address").extend())?;
+
+        let cart = ShoppingCartsEntity::find()
+            .filter(shopping_carts::Column::CustomerId.eq(customer_id))
+            .one(db)
+            .await?
+            .ok_or_else(|| AppError::invalid("Cart is empty").extend())?;
+        let cart_items = CartItemsEntity::find()
+            .filter(cart_items::Column::CartId.eq(cart.cart_id))
+            .all(db)
             .await?;
-
-        for item in &input.order_items {
-            let product: products::Model = ProductsEntity::find_by_id(item.product_id)
-                .one(&txn)
-                .await?
-                .unwrap();
-
-            if product.stock_quantity < item.quantity {
-                return Err("Insufficient stock".into());
-            }
-
-            let product_base_price = product.base_price;
-
-            let product: products::ActiveModel = products::ActiveModel {
-                stock_quantity: Set(product.stock_quantity - item.quantity),
-                ..product.into()
-            };
-
-            ProductsEntity::update(product)
-                .filter(products::Column::ProductId.eq(item.product_id))
-                .exec(&txn)
-                .await?;
-
-            let order_item = order_items::ActiveModel {
-                order_id: Set(insert_order.order_id),
-                product_id: Set(item.product_id),
-                quantity: Set(item.quantity),
-                unit_price: Set(product_base_price),
-                ..Default::default()
-            };
-            OrderItemsEntity::insert(order_item).exec(&txn).await?;
+        if cart_items.is_empty() {
+            return Err(AppError::invalid("Cart is empty").extend());
         }
 
-        txn.commit().await?;
+        let order = place_order(
+            db,
+            OrderOwner::Customer(customer_id),
+            current_tenant(ctx),
+            RegisterOrder {
+                shipping_address_id: address.address_id,
+                payment_method_id: Some(payment_method.payment_method_id),
+                discount_code,
+                order_items: cart_items
+                    .iter()
+                    .map(|item| RegisterOrderItem {
+                        product_id: ProductId(item.product_id),
+                        quantity: item.quantity,
+                        booking_date: booking_date_for(&booking_dates, item.product_id),
+                    })
+                    .collect(),
+                po_number: None,
+                region,
+            },
+            Some(cart.cart_id),
+            ctx.data::<RegionConfig>()?,
+            ctx.data::<CurrencyPolicy>()?,
+            ctx.data::<TaxPolicy>()?,
+        )
+        .await?;
+
+        // the cart is only emptied once the charge went through, a declined one voids the order
+        // and the customer can try again with the same cart
+        let (order, charge) = charge_order(
+            db,
+            ctx.data::<redis::Client>()?,
+            gateway.as_ref(),
+            order,
+            &provider_token,
+            payment_method.provider_customer_id.as_deref(),
+        )
+        .await?;
+
+        CartItemsEntity::delete_many()
+            .filter(cart_items::Column::CartId.eq(cart.cart_id))
+            .exec(db)
+            .await?;
+        CartCouponsEntity::delete_by_id(cart.cart_id)
+            .exec(db)
+            .await?;
 
-        Ok(insert_order.into())
+        Ok(CheckoutResult {
+            order: Orders::customer_order(order)?,
+            payment_status: charge.status.as_str().to_string(),
+            client_secret: charge.client_secret,
+        })
     }
 
+    // moves an order of the supplier's along, shipping and cancelling have their own mutations
     #[graphql(guard = "role_guard!(ROLE_SUPPLIER)")]
     async fn update_order_status(
         &self,
         ctx: &Context<'_>,
-        order_id: i32,
+        order_id: OrderId,
         status: String,
     ) -> Result<String, async_graphql::Error> {
-        use crate::entity::{orders, prelude::Orders as OrdersEntity};
+        use crate::entity::{order_items, orders, prelude::Orders as OrdersEntity, products};
         let db = ctx.data::<DatabaseConnection>()?;
-        let txn = db.begin().await?;
+        let supplier_id = get_customer_supplier_id(db, ctx, ROLE_SUPPLIER).await?;
+        let user_id = AuthenticatedUser::from_ctx(ctx)
+            .map_err(|e| e.extend())?
+            .user_id
+            .parse::<i32>()?;
+
+        let status = status.parse::<OrderStatus>().map_err(|e| e.extend())?;
+        match status {
+            OrderStatus::Shipped => {
+                return Err(AppError::invalid(
+                    "Orders are shipped with markOrderShipped, which takes the tracking number",
+                )
+                .extend())
+            }
+            OrderStatus::Cancelled => {
+                return Err(AppError::invalid("Orders are cancelled with cancelOrder").extend())
+            }
+            OrderStatus::Paid => {
+                return Err(
+                    AppError::invalid("Orders become paid once their payment comes in").extend(),
+                )
+            }
+            _ => {}
+        }
 
+        let txn = db.begin().await?;
+        // only a supplier with a product in the order moves it along
         let order: orders::Model = OrdersEntity::find_by_id(order_id)
+            .join(JoinType::InnerJoin, orders::Relation::OrderItems.def())
+            .join(JoinType::InnerJoin, order_items::Relation::Products.def())
+            .filter(products::Column::SupplierId.eq(supplier_id))
+            .lock_exclusive()
             .one(&txn)
-            .await
-            .map_err(|_| "Order not found")?
-            .unwrap();
+            .await?
+            .ok_or_else(|| AppError::forbidden().extend())?;
+        check_transition(&order.status, status).map_err(|e| e.extend())?;
+        // an unpaid order is only put together when it's bought on account, against the buyer's PO
+        if order.status == OrderStatus::Pending.as_str() && order.po_number.is_none() {
+            return Err(AppError::Validation {
+                message: "The order hasn't been paid yet".to_string(),
+                failed_rules: vec!["orderStatusTransition".to_string()],
+            }
+            .extend());
+        }
+        let previous_status = order.status.clone();
 
         let mut update_order: orders::ActiveModel = order.into();
-        update_order.status = Set(status);
+        update_order.status = Set(status.as_str().to_string());
 
         update_order.update(&txn).await?;
+        let change = record_status_change(
+            &txn,
+            order_id.into(),
+            Some(&previous_status),
+            status.as_str(),
+            Some(user_id),
+        )
+        .await?;
 
         txn.commit().await?;
+        notify_status_change(ctx, &change).await;
 
         Ok("Order status updated".to_string())
     }
 
-    #[graphql(guard = "role_guard!(ROLE_CUSTOMER)")]
-    async fn cancel_order(
+    // hands the supplier's lines of the order to a carrier, the buyer gets the tracking link by
+    // email. The order is SHIPPED once every supplier with a line in it has shipped
+    #[graphql(guard = "role_guard!(ROLE_SUPPLIER)")]
+    async fn mark_order_shipped(
         &self,
         ctx: &Context<'_>,
-        order_id: i32,
-    ) -> Result<String, async_graphql::Error> {
+        order_id: OrderId,
+        carrier: Carrier,
+        tracking_number: String,
+    ) -> Result<PlacedOrder, async_graphql::Error> {
         use crate::entity::{
-            order_items, orders,
-            prelude::{Orders as OrdersEntity, Products as ProductsEntity},
+            order_items, order_shipments, orders,
+            prelude::{OrderShipments as OrderShipmentsEntity, Orders as OrdersEntity},
             products,
         };
         let db = ctx.data::<DatabaseConnection>()?;
-        let token = ctx
-            .data_opt::<String>()
-            .ok_or("No authorization token found")?;
-        let txn = db.begin().await?;
+        let supplier_id = get_customer_supplier_id(db, ctx, ROLE_SUPPLIER).await?;
+        let user_id = AuthenticatedUser::from_ctx(ctx)
+            .map_err(|e| e.extend())?
+            .user_id
+            .parse::<i32>()?;
 
-        let customer_id = get_customer_supplier_id(db, token, ROLE_CUSTOMER).await?;
+        let tracking_number =
+            validate_tracking_number(carrier, &tracking_number).map_err(|e| e.extend())?;
 
-        let order: orders::Model = OrdersEntity::find_by_id(order_id)
+        let txn = db.begin().await?;
+        // only a supplier with a product in the order ships it, the lock keeps two suppliers
+        // shipping at once from both missing that the other one completed the order
+        let order = OrdersEntity::find_by_id(order_id)
+            .join(JoinType::InnerJoin, orders::Relation::OrderItems.def())
+            .join(JoinType::InnerJoin, order_items::Relation::Products.def())
+            .filter(products::Column::SupplierId.eq(supplier_id))
+            .lock_exclusive()
             .one(&txn)
-            .await
-            .map_err(|_| "Order not found")?
-            .unwrap();
+            .await?
+            .ok_or_else(|| AppError::forbidden().extend())?;
+        check_transition(&order.status, OrderStatus::Shipped).map_err(|e| e.extend())?;
+        let already_shipped = OrderShipmentsEntity::find()
+            .filter(order_shipments::Column::OrderId.eq(order.order_id))
+            .filter(order_shipments::Column::SupplierId.eq(supplier_id))
+            .count(&txn)
+            .await?
+            > 0;
+        if already_shipped {
+            return Err(
+                AppError::invalid("Your items of this order were already shipped").extend(),
+            );
+        }
+        OrderShipmentsEntity::insert(order_shipments::ActiveModel {
+            order_id: Set(order.order_id),
+            supplier_id: Set(supplier_id),
+            carrier: Set(carrier.as_str().to_string()),
+            tracking_number: Set(tracking_number.clone()),
+            shipped_by: Set(Some(user_id)),
+            ..Default::default()
+        })
+        .exec(&txn)
+        .await?;
+
+        // suppliers with a line in the order that haven't shipped it yet
+        let waiting_on = order_items::Entity::find()
+            .join(JoinType::InnerJoin, order_items::Relation::Products.def())
+            .filter(order_items::Column::OrderId.eq(order.order_id))
+            .filter(
+                products::Column::SupplierId.not_in_subquery(
+                    Query::select()
+                        .column(order_shipments::Column::SupplierId)
+                        .from(OrderShipmentsEntity)
+                        .and_where(order_shipments::Column::OrderId.eq(order.order_id))
+                        .to_owned(),
+                ),
+            )
+            .count(&txn)
+            .await?;
 
-        if order.customer_id != customer_id {
-            return Err("Unauthorized".into());
+        let (order, change) = if waiting_on == 0 {
+            let previous_status = order.status.clone();
+            let mut update_order: orders::ActiveModel = order.into();
+            update_order.status = Set(OrderStatus::Shipped.as_str().to_string());
+            update_order.carrier = Set(Some(carrier.as_str().to_string()));
+            update_order.tracking_number = Set(Some(tracking_number.clone()));
+            update_order.shipped_at = Set(Some(Utc::now().fixed_offset()));
+            let order = update_order.update(&txn).await?;
+            let change = record_status_change(
+                &txn,
+                order.order_id,
+                Some(&previous_status),
+                OrderStatus::Shipped.as_str(),
+                Some(user_id),
+            )
+            .await?;
+            (order, Some(change))
+        } else {
+            (order, None)
+        };
+
+        txn.commit().await?;
+        if let Some(change) = change {
+            notify_status_change(ctx, &change).await;
         }
 
-        if order.status == "CANCELLED" {
-            return Err("Order already cancelled".into());
+        // the shipment stands even if the email can't be sent
+        if let Err(e) = send_shipping_notification(db, &order, carrier, &tracking_number).await {
+            eprintln!(
+                "Shipping notification failed for order {}: {}",
+                order.order_id, e
+            );
         }
 
-        let order_items_list = order_items::Entity::find()
-            .filter(order_items::Column::OrderId.eq(order_id))
-            .all(&txn)
-            .await?;
+        Ok(order.into())
+    }
 
-        for order_item in order_items_list {
-            let product: products::Model = ProductsEntity::find_by_id(order_item.product_id)
-                .one(&txn)
-                .await?
-                .unwrap();
+    #[graphql(guard = "role_guard!(ROLE_CUSTOMER)")]
+    async fn cancel_order(
+        &self,
+        ctx: &Context<'_>,
+        order_id: OrderId,
+    ) -> Result<String, async_graphql::Error> {
+        use crate::entity::{
+            bills, orders,
+            prelude::{Bills as BillsEntity, Orders as OrdersEntity},
+        };
+        let db = ctx.data::<DatabaseConnection>()?;
+        let txn = db.begin().await?;
 
-            let product: products::ActiveModel = products::ActiveModel {
-                stock_quantity: Set(product.stock_quantity + order_item.quantity),
-                ..product.into()
-            };
+        let customer_id = get_customer_supplier_id(db, ctx, ROLE_CUSTOMER).await?;
 
-            ProductsEntity::update(product)
-                .filter(products::Column::ProductId.eq(order_item.product_id))
-                .exec(&txn)
-                .await?;
-        }
+        let order: orders::Model = OrdersEntity::find_by_id(order_id)
+            .lock_exclusive()
+            .one(&txn)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Order").extend())?;
 
-        let mut order: orders::ActiveModel = order.into();
+        if order.customer_id != Some(customer_id) {
+            return Err(AppError::forbidden().extend());
+        }
 
-        order.status = Set("CANCELLED".to_string());
+        if order.status == OrderStatus::Cancelled.as_str() {
+            return Err(AppError::invalid("Order already cancelled").extend());
+        }
+        // once it is with the carrier the order can only be sent back as a return
+        check_transition(&order.status, OrderStatus::Cancelled).map_err(|e| e.extend())?;
+        // paid money goes back through refundOrder, so only orders nothing was taken for yet
+        // can be called off. Processing orders bought on account have an unpaid bill
+        let unpaid = BillsEntity::find()
+            .filter(bills::Column::OrderId.eq(order.order_id))
+            .lock_exclusive()
+            .one(&txn)
+            .await?
+            .is_none_or(|bill| {
+                [ChargeStatus::Failed.as_str(), "PENDING"].contains(&bill.payment_status.as_str())
+            });
+        if !unpaid {
+            return Err(AppError::invalid(
+                "Paid orders can't be cancelled, ask for a refund or return instead",
+            )
+            .extend());
+        }
 
-        order.update(&txn).await?;
+        // stock, booked dates and the coupon use go back the way a declined payment's do
+        let change = void_order(
+            &txn,
+            order,
+            Some(
+                AuthenticatedUser::from_ctx(ctx)
+                    .map_err(|e| e.extend())?
+                    .user_id
+                    .parse::<i32>()?,
+            ),
+        )
+        .await?;
 
         txn.commit().await?;
+        notify_status_change(ctx, &change).await;
 
         Ok("Order cancelled".to_string())
     }
+
+    // ships the order to another of the customer's addresses, possible until it is handed to a carrier
+    #[graphql(guard = "role_guard!(ROLE_CUSTOMER)")]
+    async fn change_order_shipping_address(
+        &self,
+        ctx: &Context<'_>,
+        order_id: OrderId,
+        address_id: i32,
+    ) -> Result<PlacedOrder, async_graphql::Error> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let customer_id = get_customer_supplier_id(db, ctx, ROLE_CUSTOMER).await?;
+        let user_id = AuthenticatedUser::from_ctx(ctx)
+            .map_err(|e| e.extend())?
+            .user_id
+            .parse::<i32>()?;
+
+        Ok(
+            change_shipping_address(db, customer_id, order_id, address_id, user_id)
+                .await?
+                .into(),
+        )
+    }
+}
+
+#[Subscription]
+impl OrdersSubscription {
+    // status changes made through any api-server instance, open to everyone who can see the order
+    #[graphql(guard = "role_guard!(ROLE_CUSTOMER, ROLE_SUPPLIER, ROLE_ADMIN)")]
+    async fn order_status_changed(
+        &self,
+        ctx: &Context<'_>,
+        order_id: OrderId,
+    ) -> Result<impl Stream<Item = OrderStatusChanged>, async_graphql::Error> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        check_order_participant(db, ctx, order_id).await?;
+
+        Ok(ctx
+            .data::<Broker<OrderStatusChanged>>()?
+            .subscribe()
+            .filter(move |change| future::ready(change.order_id == order_id)))
+    }
+}
+
+// the change is already committed, subscribers missing it is logged rather than failing the mutation
+async fn notify_status_change(ctx: &Context<'_>, change: &OrderStatusChanged) {
+    let result = match ctx.data::<redis::Client>() {
+        Ok(redis) => publish_status_change(redis, change).await,
+        Err(e) => Err(AppError::Internal(e.message)),
+    };
+    if let Err(e) = result {
+        eprintln!(
+            "Status change of order {} not published: {}",
+            change.order_id, e
+        );
+    }
 }
@@ -1,14 +1,29 @@
 use crate::{
-    auth::{Auth, RoleGuard, ROLE_CUSTOMER},
-    graphql::macros::role_guard,
+    auth::{AuthenticatedUser, RoleGuard, ROLE_ADMIN, ROLE_CUSTOMER},
+    error::AppError,
+    graphql::{complexity, macros::role_guard},
+    ids::OrderId,
     models::{
-        payments::{create_payment_method, CardTypes, PaymentMethods, RegisterPaymentMethod},
+        disputes::{reconcile_payments, Disputes, ReconciliationEntry},
+        orders::{CheckoutResult, Orders},
+        payments::{
+            card_type_for_brand, clear_default_payment_method, create_payment_method, CardTypes,
+            PaymentMethods, RegisterPaymentMethod,
+        },
         user::get_customer_supplier_id,
     },
+    payment_gateway::{ChargeStatus, PaymentGateway, ProviderPayment},
+    payment_webhooks::settle_payment,
+    pii::{pii, PiiKind},
+    terms::TermsGuard,
 };
-use async_graphql::{Context, Object};
+use async_graphql::{Context, ErrorExtensions, Object};
 use sea_orm::ActiveValue::Set;
-use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, TransactionTrait};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder,
+    QueryTrait, TransactionTrait,
+};
+use std::sync::Arc;
 
 #[derive(Default)]
 pub struct PaymentsQuery;
@@ -18,7 +33,10 @@ pub struct PaymentsMutation;
 
 #[Object]
 impl PaymentsQuery {
-    #[graphql(guard = "role_guard!(ROLE_CUSTOMER)")]
+    #[graphql(
+        guard = "role_guard!(ROLE_CUSTOMER)",
+        complexity = "complexity::unbounded(child_complexity)"
+    )]
     async fn payment_methods(
         &self,
         ctx: &Context<'_>,
@@ -28,11 +46,11 @@ impl PaymentsQuery {
             prelude::{Customers as CustomersEntity, PaymentMethods as PaymentMethodsEntity},
         };
         let db = ctx.data::<DatabaseConnection>()?;
-        let token = ctx
-            .data_opt::<String>()
-            .ok_or("No authorization token found")?;
 
-        let user_id = Auth::verify_token(token)?.user_id.parse::<i32>()?;
+        let user_id = AuthenticatedUser::from_ctx(ctx)
+            .map_err(|e| e.extend())?
+            .user_id
+            .parse::<i32>()?;
         let payment_method = PaymentMethodsEntity::find()
             .inner_join(CustomersEntity)
             .filter(customers::Column::UserId.eq(user_id))
@@ -47,6 +65,32 @@ impl PaymentsQuery {
         Ok(payment_methods)
     }
 
+    // default first, then in the order they were saved
+    #[graphql(
+        guard = "role_guard!(ROLE_CUSTOMER)",
+        complexity = "complexity::unbounded(child_complexity)"
+    )]
+    async fn my_payment_methods(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<Vec<PaymentMethods>, async_graphql::Error> {
+        use crate::entity::{payment_methods, prelude::PaymentMethods as PaymentMethodsEntity};
+        let db = ctx.data::<DatabaseConnection>()?;
+        let customer_id = get_customer_supplier_id(db, ctx, ROLE_CUSTOMER).await?;
+
+        let payment_methods = PaymentMethodsEntity::find()
+            .filter(payment_methods::Column::CustomerId.eq(customer_id))
+            .order_by_desc(payment_methods::Column::IsDefault)
+            .order_by_asc(payment_methods::Column::PaymentMethodId)
+            .all(db)
+            .await?;
+
+        Ok(payment_methods
+            .into_iter()
+            .map(|payment_method| payment_method.into())
+            .collect())
+    }
+
     async fn card_type(
         &self,
         ctx: &Context<'_>,
@@ -55,15 +99,63 @@ impl PaymentsQuery {
         use crate::entity::prelude::CardTypes as CardTypesEntity;
         let db = ctx.data::<DatabaseConnection>()?;
 
-        let card_type = CardTypesEntity::find_by_id(card_type_id).one(db).await?;
+        let card_type = CardTypesEntity::find_by_id(card_type_id)
+            .one(db)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Card type").extend())?;
 
-        Ok(card_type.unwrap().into())
+        Ok(card_type.into())
+    }
+
+    #[graphql(
+        guard = "role_guard!(ROLE_ADMIN)",
+        complexity = "complexity::unbounded(child_complexity)"
+    )]
+    async fn disputes(
+        &self,
+        ctx: &Context<'_>,
+        status: Option<String>,
+    ) -> Result<Vec<Disputes>, async_graphql::Error> {
+        use crate::entity::{disputes, prelude::Disputes as DisputesEntity};
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        let disputes = DisputesEntity::find()
+            .apply_if(status, |query, status| {
+                query.filter(disputes::Column::Status.eq(status))
+            })
+            .order_by_desc(disputes::Column::OpenedAt)
+            .all(db)
+            .await?;
+
+        Ok(disputes.into_iter().map(|dispute| dispute.into()).collect())
+    }
+
+    // compares the provider's payments of the last `days` days with the local bills
+    #[graphql(
+        guard = "role_guard!(ROLE_ADMIN)",
+        complexity = "complexity::unbounded(child_complexity)"
+    )]
+    async fn payment_reconciliation(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 30)] days: i64,
+    ) -> Result<Vec<ReconciliationEntry>, async_graphql::Error> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let gateway = ctx.data::<Arc<dyn PaymentGateway>>()?;
+
+        if !(1..=365).contains(&days) {
+            return Err(AppError::invalid("days must be between 1 and 365").extend());
+        }
+
+        reconcile_payments(db, gateway.as_ref(), days)
+            .await
+            .map_err(|e| e.extend())
     }
 }
 
 #[Object]
 impl PaymentsMutation {
-    #[graphql(guard = "role_guard!(ROLE_CUSTOMER)")]
+    #[graphql(guard = "role_guard!(ROLE_CUSTOMER).and(TermsGuard)")]
     async fn register_payment_method(
         &self,
         ctx: &Context<'_>,
@@ -71,12 +163,9 @@ impl PaymentsMutation {
     ) -> Result<PaymentMethods, async_graphql::Error> {
         use crate::entity::prelude::PaymentMethods as PaymentMethodsEntity;
         let db = ctx.data::<DatabaseConnection>()?;
-        let token = ctx
-            .data_opt::<String>()
-            .ok_or("No authorization token found")?;
         let txn = db.begin().await?;
 
-        let customer_id = get_customer_supplier_id(db, token, ROLE_CUSTOMER).await?;
+        let customer_id = get_customer_supplier_id(db, ctx, ROLE_CUSTOMER).await?;
         let is_default: Option<bool> = Some(input.is_default.unwrap_or(false));
 
         let payment_method = create_payment_method(customer_id, is_default, input, &txn).await?;
@@ -90,7 +179,99 @@ impl PaymentsMutation {
         Ok(insert_payment_method.into())
     }
 
+    // stores a token created client side by the provider's SDK, card details never reach the server
+    #[graphql(guard = "role_guard!(ROLE_CUSTOMER).and(TermsGuard)")]
+    async fn save_payment_method(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(directive = pii::apply(PiiKind::Secret))] provider_token: String,
+        #[graphql(default = false)] make_default: bool,
+    ) -> Result<PaymentMethods, async_graphql::Error> {
+        use crate::entity::{
+            payment_methods, prelude::PaymentMethods as PaymentMethodsEntity,
+            sea_orm_active_enums::PaymentMethodType,
+        };
+        let db = ctx.data::<DatabaseConnection>()?;
+        let customer_id = get_customer_supplier_id(db, ctx, ROLE_CUSTOMER).await?;
+        let gateway = ctx.data::<Arc<dyn PaymentGateway>>()?;
+
+        let method = gateway
+            .describe_method(&provider_token)
+            .await
+            .map_err(|e| e.extend())?;
+        // all of a customer's saved methods hang off one provider customer
+        let provider_customer_id = PaymentMethodsEntity::find()
+            .filter(payment_methods::Column::CustomerId.eq(customer_id))
+            .filter(payment_methods::Column::Provider.eq(gateway.provider()))
+            .filter(payment_methods::Column::ProviderCustomerId.is_not_null())
+            .one(db)
+            .await?
+            .and_then(|method| method.provider_customer_id);
+        let provider_customer_id = gateway
+            .attach_method(&provider_token, provider_customer_id.as_deref())
+            .await
+            .map_err(|e| e.extend())?;
+
+        let txn = db.begin().await?;
+        if make_default {
+            clear_default_payment_method(customer_id, &txn).await?;
+        }
+        let card_type_id = match &method.brand {
+            Some(brand) => Some(card_type_for_brand(brand, &txn).await?),
+            None => None,
+        };
+
+        let payment_method = PaymentMethodsEntity::insert(payment_methods::ActiveModel {
+            customer_id: Set(customer_id),
+            payment_type: Set(PaymentMethodType::Card),
+            is_default: Set(Some(make_default)),
+            card_expiration_date: Set(method.expiration),
+            card_type_id: Set(card_type_id),
+            provider: Set(Some(gateway.provider().to_string())),
+            provider_token: Set(Some(provider_token)),
+            card_brand: Set(method.brand),
+            card_last4: Set(method.last4),
+            provider_customer_id: Set(Some(provider_customer_id)),
+            ..Default::default()
+        })
+        .exec_with_returning(&txn)
+        .await?;
+
+        txn.commit().await?;
+
+        Ok(payment_method.into())
+    }
+
     #[graphql(guard = "role_guard!(ROLE_CUSTOMER)")]
+    async fn set_default_payment_method(
+        &self,
+        ctx: &Context<'_>,
+        payment_method_id: i32,
+    ) -> Result<PaymentMethods, async_graphql::Error> {
+        use crate::entity::{payment_methods, prelude::PaymentMethods as PaymentMethodsEntity};
+        let db = ctx.data::<DatabaseConnection>()?;
+        let customer_id = get_customer_supplier_id(db, ctx, ROLE_CUSTOMER).await?;
+        let txn = db.begin().await?;
+
+        let payment_method = PaymentMethodsEntity::find_by_id(payment_method_id)
+            .one(&txn)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Payment method").extend())?;
+        if payment_method.customer_id != customer_id {
+            return Err(AppError::forbidden().extend());
+        }
+
+        clear_default_payment_method(customer_id, &txn).await?;
+        let mut payment_method: payment_methods::ActiveModel = payment_method.into();
+        payment_method.is_default = Set(Some(true));
+        let payment_method = payment_method.update(&txn).await?;
+
+        txn.commit().await?;
+
+        Ok(payment_method.into())
+    }
+
+    #[graphql(guard = "role_guard!(ROLE_CUSTOMER).and(TermsGuard)")]
     async fn update_payment_method(
         &self,
         ctx: &Context<'_>,
@@ -99,12 +280,9 @@ impl PaymentsMutation {
     ) -> Result<PaymentMethods, async_graphql::Error> {
         use crate::entity::{payment_methods, prelude::PaymentMethods as PaymentMethodsEntity};
         let db = ctx.data::<DatabaseConnection>()?;
-        let token = ctx
-            .data_opt::<String>()
-            .ok_or("No authorization token found")?;
         let txn = db.begin().await?;
 
-        let customer_id = get_customer_supplier_id(db, token, ROLE_CUSTOMER).await?;
+        let customer_id = get_customer_supplier_id(db, ctx, ROLE_CUSTOMER).await?;
         let is_default: Option<bool> = Some(input.is_default.unwrap_or(false));
 
         let mut payment_method =
@@ -120,4 +298,84 @@ impl PaymentsMutation {
 
         Ok(update_payment_method.into())
     }
+
+    // for clients confirming the payment with the provider's SDK, the order moves to PAID once
+    // the provider's webhook reports the payment succeeded
+    #[graphql(guard = "role_guard!(ROLE_CUSTOMER).and(TermsGuard)")]
+    async fn create_payment_intent(
+        &self,
+        ctx: &Context<'_>,
+        order_id: OrderId,
+    ) -> Result<CheckoutResult, async_graphql::Error> {
+        use crate::entity::{
+            bills, prelude::Bills as BillsEntity, prelude::Orders as OrdersEntity,
+        };
+        let db = ctx.data::<DatabaseConnection>()?;
+        let customer_id = get_customer_supplier_id(db, ctx, ROLE_CUSTOMER).await?;
+        let gateway = ctx.data::<Arc<dyn PaymentGateway>>()?;
+
+        let order = OrdersEntity::find_by_id(order_id)
+            .one(db)
+            .await?
+            // guest orders are paid at guest checkout
+            .filter(|order| {
+                order.customer_id == Some(customer_id) && order.payment_method_id.is_some()
+            })
+            .ok_or_else(|| AppError::NotFound("Order").extend())?;
+        if order.status != "PENDING" {
+            return Err(AppError::invalid("Only pending orders can be paid").extend());
+        }
+        let bill = BillsEntity::find()
+            .filter(bills::Column::OrderId.eq(order.order_id))
+            .one(db)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Bill").extend())?;
+        if bill.payment_status == ChargeStatus::Succeeded.as_str() {
+            return Err(AppError::invalid("This order is already paid").extend());
+        }
+
+        // keyed on the order so asking again returns the same intent instead of a second one
+        let intent = gateway
+            .create_payment_intent(
+                order.total_amount,
+                &order.currency,
+                order.order_id,
+                &format!("order-{}-intent", order.order_id),
+            )
+            .await
+            .map_err(|e| e.extend())?;
+
+        let mut bill: bills::ActiveModel = bill.into();
+        bill.payment_status = Set(intent.status.as_str().to_string());
+        bill.provider_payment_id = Set(intent.provider_payment_id.clone());
+        bill.update(db).await?;
+
+        // the sandbox gateway has nothing to confirm and sends no webhook
+        if let (ChargeStatus::Succeeded, Some(provider_payment_id)) =
+            (intent.status, &intent.provider_payment_id)
+        {
+            settle_payment(
+                db,
+                ctx.data::<redis::Client>()?,
+                &ProviderPayment {
+                    provider_payment_id: provider_payment_id.clone(),
+                    amount: order.total_amount,
+                    status: intent.status,
+                    idempotency_key: None,
+                },
+            )
+            .await
+            .map_err(|e| e.extend())?;
+        }
+
+        let order = OrdersEntity::find_by_id(order.order_id)
+            .one(db)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Order").extend())?;
+        Ok(CheckoutResult {
+            order: Orders::customer_order(order)?,
+            payment_status: intent.status.as_str().to_string(),
+            client_secret: intent.client_secret,
+        })
+    }
 }
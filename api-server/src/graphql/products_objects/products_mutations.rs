@@ -1,20 +1,63 @@
 use crate::{
-    auth::{RoleGuard, ROLE_CUSTOMER, ROLE_SUPPLIER},
-    graphql::macros::role_guard,
+    api_keys::in_sandbox,
+    auth::{AuthenticatedUser, RoleGuard, ROLE_ADMIN, ROLE_CUSTOMER, ROLE_SUPPLIER},
+    catalog_cache::CatalogCache,
+    config::{ContentFilterPolicy, DuplicatePolicy, RegionConfig, ReviewPolicy, UploadPolicy},
+    content_filter::{filter_content, ContentField},
+    domain_events::{record_event, ReviewPosted, REVIEW_POSTED},
+    error::AppError,
+    graphql::{complexity, macros::role_guard},
+    hot_cache::HotCache,
+    ids::ProductId,
+    image_zips::{ImageZipQueue, ImageZipTask},
+    images::{attach_product_image, ImageQueue},
     models::{
+        availability::{AvailabilityDay, AvailabilityDayInput},
+        boost_rules::BoostRuleSet,
+        cache_warming::{best_sellers, product_page_paginator, WarmCacheScope, WarmedCache},
+        catalog_imports::{
+            catalog_diff, checked_row_attributes, store_catalog_import, take_catalog_import,
+            CatalogImportPreview, PendingCatalogImport,
+        },
+        category_attributes::{
+            attribute_template, checked_attributes, lacks_required_attribute, parse_csv_attributes,
+            product_attributes, replace_product_attributes, required_attribute_rules,
+        },
+        category_counts::{adjust_category_count, move_category_count, rebuild_category_counts},
+        category_reassignments::{CategoryReassignments, ReassignProductsFilter},
+        image_zip_jobs::ImageZipJobs,
+        order_und_pagination::OrderAndPagination,
         products::{
+            cached_products_with_id, category_tree, category_tree_key, check_duplicate_products,
             check_if_supplier_owns_product, create_discount_model, create_product_model,
-            create_review_model, Discounts, Products, RegisterDiscount, RegisterProduct,
+            create_review_model, parse_product_csv, review_ineligible_reason, Discounts,
+            ProductCsvRow, ProductLookup, Products, RegisterDiscount, RegisterProduct,
             RegisterReview, Reviews,
         },
-        user::get_customer_supplier_id,
+        regional_prices::{validate_regional_prices, RegionalPrice, RegionalPriceInput},
+        review_summaries::apply_review_to_summary,
+        user::{check_supplier_approved, get_customer_supplier_id},
+        videos::ProductVideos,
     },
+    reassignment::reassigned_products,
+    search::sanitize_search,
+    storage::Storage,
+    tenancy::{current_tenant, TenantScope},
+    terms::TermsGuard,
+    uploads::{validate_upload, UploadKind},
+    videos::{parse_video_url, VideoJob, VideoQueue},
 };
-use async_graphql::{Context, Object};
+use async_graphql::{Context, ErrorExtensions, Object, Upload};
+use chrono::Utc;
 use sea_orm::ActiveValue::Set;
 use sea_orm::{
-    ColumnTrait, DatabaseConnection, EntityTrait, ModelTrait, QueryFilter, TransactionTrait,
+    prelude::Decimal,
+    sea_query::{Expr, OnConflict},
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, DatabaseTransaction, EntityTrait,
+    ModelTrait, PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, TransactionTrait,
+    TryIntoModel,
 };
+use std::sync::Arc;
 
 #[derive(Default)]
 pub struct ProductsMutation;
@@ -22,105 +65,923 @@ pub struct ProductsMutation;
 #[Object]
 impl ProductsMutation {
     // needs a role_guard to check if the user is a supplier as even a customer has a valid token
-    #[graphql(guard = "role_guard!(ROLE_SUPPLIER)")]
+    #[graphql(guard = "role_guard!(ROLE_SUPPLIER).and(TermsGuard)")]
     async fn register_product(
         &self,
         ctx: &Context<'_>,
-        input: RegisterProduct,
+        mut input: RegisterProduct,
     ) -> Result<Products, async_graphql::Error> {
         use crate::entity::prelude::Products as ProductsEntity;
         let db = ctx.data::<DatabaseConnection>()?;
-        let token = ctx
-            .data_opt::<String>()
-            .ok_or("No authorization token found")?;
-        let supplier_id = get_customer_supplier_id(db, token, ROLE_SUPPLIER).await?;
-        let product = create_product_model(input, supplier_id)?;
+        let supplier_id = get_customer_supplier_id(db, ctx, ROLE_SUPPLIER).await?;
+        check_supplier_approved(db, supplier_id).await?;
+        input.description = input
+            .description
+            .map(|text| {
+                filter_content(
+                    ctx.data::<ContentFilterPolicy>()?,
+                    ContentField::ProductDescription,
+                    text,
+                )
+                .map_err(|e| e.extend())
+            })
+            .transpose()?;
+        let duplicate_warnings = check_duplicate_products(
+            db,
+            supplier_id,
+            &input.name,
+            input.category_id,
+            None,
+            ctx.data::<DuplicatePolicy>()?,
+        )
+        .await
+        .map_err(|e| e.extend())?;
+        let attributes = checked_attributes(ctx, input.category_id, &input.attributes)
+            .await
+            .map_err(|e| e.extend())?;
+        let mut product = create_product_model(input, supplier_id)?;
+        product.tenant_id = Set(current_tenant(ctx).0);
+        let txn = db.begin().await?;
         let insert_product = ProductsEntity::insert(product)
-            .exec_with_returning(db)
+            .exec_with_returning(&txn)
             .await?;
-        Ok(insert_product.into())
+        adjust_category_count(&txn, insert_product.category_id, 1).await?;
+        replace_product_attributes(&txn, insert_product.product_id, attributes)
+            .await
+            .map_err(|e| e.extend())?;
+        txn.commit().await?;
+        ctx.data::<CatalogCache>()?
+            .invalidate(current_tenant(ctx))
+            .await;
+        Ok(Products {
+            duplicate_warnings,
+            ..insert_product.into()
+        })
     }
 
     #[graphql(guard = "role_guard!(ROLE_SUPPLIER)")]
     async fn update_product(
         &self,
         ctx: &Context<'_>,
-        product_id: i32,
-        input: RegisterProduct,
+        product_id: ProductId,
+        mut input: RegisterProduct,
     ) -> Result<Products, async_graphql::Error> {
         use crate::entity::{prelude::Products as ProductsEntity, products};
         let db = ctx.data::<DatabaseConnection>()?;
-        let token = ctx
-            .data_opt::<String>()
-            .ok_or("No authorization token found")?;
-        let supplier_id = get_customer_supplier_id(db, token, ROLE_SUPPLIER).await?;
+        let supplier_id = get_customer_supplier_id(db, ctx, ROLE_SUPPLIER).await?;
         check_if_supplier_owns_product(db, supplier_id, product_id).await?;
+        input.description = input
+            .description
+            .map(|text| {
+                filter_content(
+                    ctx.data::<ContentFilterPolicy>()?,
+                    ContentField::ProductDescription,
+                    text,
+                )
+                .map_err(|e| e.extend())
+            })
+            .transpose()?;
+        let duplicate_warnings = check_duplicate_products(
+            db,
+            supplier_id,
+            &input.name,
+            input.category_id,
+            Some(product_id),
+            ctx.data::<DuplicatePolicy>()?,
+        )
+        .await
+        .map_err(|e| e.extend())?;
+        let attributes = checked_attributes(ctx, input.category_id, &input.attributes)
+            .await
+            .map_err(|e| e.extend())?;
         let mut product = create_product_model(input, supplier_id)?;
 
-        product.product_id = Set(product_id);
+        product.product_id = Set(product_id.into());
+        let txn = db.begin().await?;
+        let previous = ProductsEntity::find_by_id(product_id)
+            .lock_exclusive()
+            .one(&txn)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Product").extend())?;
         let update_product = ProductsEntity::update(product)
             .filter(products::Column::ProductId.eq(product_id))
-            .exec(db)
+            .exec(&txn)
+            .await?;
+        // archived products aren't counted, wherever they move
+        if previous.archived_at.is_none() {
+            move_category_count(&txn, previous.category_id, update_product.category_id).await?;
+        }
+        replace_product_attributes(&txn, update_product.product_id, attributes)
+            .await
+            .map_err(|e| e.extend())?;
+        txn.commit().await?;
+        ctx.data::<CatalogCache>()?
+            .invalidate(current_tenant(ctx))
+            .await;
+        Ok(Products {
+            duplicate_warnings,
+            ..update_product.into()
+        })
+    }
+
+    // restocks with a positive delta, writes off with a negative one, in one statement so it can't
+    // race checkouts, the stock never goes below zero
+    #[graphql(guard = "role_guard!(ROLE_SUPPLIER)")]
+    async fn adjust_stock(
+        &self,
+        ctx: &Context<'_>,
+        product_id: ProductId,
+        delta: i32,
+    ) -> Result<Products, async_graphql::Error> {
+        use crate::entity::{prelude::Products as ProductsEntity, products};
+        let db = ctx.data::<DatabaseConnection>()?;
+        let supplier_id = get_customer_supplier_id(db, ctx, ROLE_SUPPLIER).await?;
+        check_if_supplier_owns_product(db, supplier_id, product_id).await?;
+
+        let product = ProductsEntity::update_many()
+            .col_expr(
+                products::Column::StockQuantity,
+                Expr::col(products::Column::StockQuantity).add(delta),
+            )
+            .filter(products::Column::ProductId.eq(product_id))
+            .filter(products::Column::DateBookable.eq(false))
+            .filter(Expr::col(products::Column::StockQuantity).gte(-delta))
+            .exec_with_returning(db)
+            .await?
+            .pop();
+
+        match product {
+            Some(product) => {
+                ctx.data::<CatalogCache>()?
+                    .invalidate(current_tenant(ctx))
+                    .await;
+                Ok(product.into())
+            }
+            None => {
+                let product = ProductsEntity::find_by_id(product_id)
+                    .one(db)
+                    .await?
+                    .ok_or_else(|| AppError::NotFound("Product").extend())?;
+                if product.date_bookable {
+                    return Err(AppError::invalid(
+                        "Date bookable products are sold through their availability",
+                    )
+                    .extend());
+                }
+                Err(AppError::InsufficientStock {
+                    product_id: product.product_id,
+                    available: product.stock_quantity,
+                }
+                .extend())
+            }
+        }
+    }
+
+    // opens days of a date bookable product with the given capacity, days not listed stay as they are
+    #[graphql(
+        guard = "role_guard!(ROLE_SUPPLIER)",
+        complexity = "complexity::unbounded(child_complexity)"
+    )]
+    async fn set_product_availability(
+        &self,
+        ctx: &Context<'_>,
+        product_id: ProductId,
+        days: Vec<AvailabilityDayInput>,
+    ) -> Result<Vec<AvailabilityDay>, async_graphql::Error> {
+        use crate::entity::{
+            prelude::{
+                ProductAvailability as ProductAvailabilityEntity, Products as ProductsEntity,
+            },
+            product_availability,
+        };
+        let db = ctx.data::<DatabaseConnection>()?;
+        let supplier_id = get_customer_supplier_id(db, ctx, ROLE_SUPPLIER).await?;
+        check_if_supplier_owns_product(db, supplier_id, product_id).await?;
+
+        let product = ProductsEntity::find_by_id(product_id)
+            .one(db)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Product").extend())?;
+        if !product.date_bookable {
+            return Err(AppError::invalid("Product is not date bookable").extend());
+        }
+        if days.iter().any(|day| day.capacity < 0) {
+            return Err(AppError::invalid("Capacity can't be negative").extend());
+        }
+
+        let txn = db.begin().await?;
+        let booked = ProductAvailabilityEntity::find()
+            .filter(product_availability::Column::ProductId.eq(product_id))
+            .filter(
+                product_availability::Column::AvailableOn
+                    .is_in(days.iter().map(|day| day.date).collect::<Vec<_>>()),
+            )
+            .lock_exclusive()
+            .all(&txn)
+            .await?;
+        // a day can't shrink below what customers already booked on it
+        let mut failed_rules = Vec::new();
+        for day in &days {
+            if let Some(stored) = booked
+                .iter()
+                .find(|stored| stored.available_on == day.date && stored.booked > day.capacity)
+            {
+                failed_rules.push(format!("{}: {} already booked", day.date, stored.booked));
+            }
+        }
+        if !failed_rules.is_empty() {
+            return Err(AppError::Validation {
+                message: "Capacity is below existing bookings".to_string(),
+                failed_rules,
+            }
+            .extend());
+        }
+
+        for day in &days {
+            ProductAvailabilityEntity::insert(product_availability::ActiveModel {
+                product_id: Set(product_id.into()),
+                available_on: Set(day.date),
+                capacity: Set(day.capacity),
+                ..Default::default()
+            })
+            .on_conflict(
+                OnConflict::columns([
+                    product_availability::Column::ProductId,
+                    product_availability::Column::AvailableOn,
+                ])
+                .update_column(product_availability::Column::Capacity)
+                .to_owned(),
+            )
+            .exec_without_returning(&txn)
+            .await?;
+        }
+        txn.commit().await?;
+
+        Ok(ProductAvailabilityEntity::find()
+            .filter(product_availability::Column::ProductId.eq(product_id))
+            .filter(
+                product_availability::Column::AvailableOn
+                    .is_in(days.iter().map(|day| day.date).collect::<Vec<_>>()),
+            )
+            .order_by_asc(product_availability::Column::AvailableOn)
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|day| day.into())
+            .collect())
+    }
+
+    // replaces all of the product's price overrides, an empty list prices it at its base price everywhere
+    #[graphql(
+        guard = "role_guard!(ROLE_SUPPLIER)",
+        complexity = "complexity::unbounded(child_complexity)"
+    )]
+    async fn set_product_regional_prices(
+        &self,
+        ctx: &Context<'_>,
+        product_id: ProductId,
+        prices: Vec<RegionalPriceInput>,
+    ) -> Result<Vec<RegionalPrice>, async_graphql::Error> {
+        use crate::entity::{
+            prelude::ProductRegionPrices as ProductRegionPricesEntity, product_region_prices,
+        };
+        let db = ctx.data::<DatabaseConnection>()?;
+        let supplier_id = get_customer_supplier_id(db, ctx, ROLE_SUPPLIER).await?;
+        check_if_supplier_owns_product(db, supplier_id, product_id).await?;
+        let prices = validate_regional_prices(ctx.data::<RegionConfig>()?, &prices)
+            .map_err(|e| e.extend())?;
+
+        let txn = db.begin().await?;
+        ProductRegionPricesEntity::delete_many()
+            .filter(product_region_prices::Column::ProductId.eq(product_id))
+            .exec(&txn)
             .await?;
-        Ok(update_product.into())
+        if !prices.is_empty() {
+            ProductRegionPricesEntity::insert_many(prices.into_iter().map(
+                |(region, currency, price)| product_region_prices::ActiveModel {
+                    product_id: Set(product_id.into()),
+                    region: Set(region),
+                    currency: Set(currency),
+                    price: Set(price),
+                },
+            ))
+            .exec_without_returning(&txn)
+            .await?;
+        }
+        txn.commit().await?;
+        ctx.data::<CatalogCache>()?
+            .invalidate(current_tenant(ctx))
+            .await;
+
+        Ok(ProductRegionPricesEntity::find()
+            .filter(product_region_prices::Column::ProductId.eq(product_id))
+            .order_by_asc(product_region_prices::Column::Region)
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|price| price.into())
+            .collect())
     }
 
     #[graphql(guard = "role_guard!(ROLE_SUPPLIER)")]
     async fn delete_product(
         &self,
         ctx: &Context<'_>,
-        product_id: i32,
+        product_id: ProductId,
     ) -> Result<String, async_graphql::Error> {
         use crate::entity::prelude::Products as ProductsEntity;
         let db = ctx.data::<DatabaseConnection>()?;
-        let token = ctx
-            .data_opt::<String>()
-            .ok_or("No authorization token found")?;
 
-        let supplier_id = get_customer_supplier_id(db, token, ROLE_SUPPLIER).await?;
+        let supplier_id = get_customer_supplier_id(db, ctx, ROLE_SUPPLIER).await?;
 
         check_if_supplier_owns_product(db, supplier_id, product_id).await?;
-        ProductsEntity::delete_by_id(product_id).exec(db).await?;
+        let txn = db.begin().await?;
+        let product = ProductsEntity::find_by_id(product_id)
+            .lock_exclusive()
+            .one(&txn)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Product").extend())?;
+        let (category_id, counted) = (product.category_id, product.archived_at.is_none());
+        product.delete(&txn).await?;
+        if counted {
+            adjust_category_count(&txn, category_id, -1).await?;
+        }
+        txn.commit().await?;
+        ctx.data::<CatalogCache>()?
+            .invalidate(current_tenant(ctx))
+            .await;
         Ok("Product deleted".to_string())
     }
 
-    #[graphql(guard = "role_guard!(ROLE_CUSTOMER)")]
-    async fn register_review(
+    // takes the product off sale without losing it, past orders, returns and reviews keep pointing at it
+    #[graphql(guard = "role_guard!(ROLE_SUPPLIER)")]
+    async fn archive_product(
         &self,
         ctx: &Context<'_>,
-        input: RegisterReview,
-    ) -> Result<Reviews, async_graphql::Error> {
+        product_id: ProductId,
+    ) -> Result<Products, async_graphql::Error> {
+        set_product_archived(ctx, product_id, true).await
+    }
+
+    #[graphql(guard = "role_guard!(ROLE_SUPPLIER)")]
+    async fn unarchive_product(
+        &self,
+        ctx: &Context<'_>,
+        product_id: ProductId,
+    ) -> Result<Products, async_graphql::Error> {
+        set_product_archived(ctx, product_id, false).await
+    }
+
+    #[graphql(guard = "role_guard!(ROLE_SUPPLIER).and(TermsGuard)")]
+    async fn upload_product_image(
+        &self,
+        ctx: &Context<'_>,
+        product_id: ProductId,
+        file: Upload,
+    ) -> Result<Products, async_graphql::Error> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let supplier_id = get_customer_supplier_id(db, ctx, ROLE_SUPPLIER).await?;
+        check_if_supplier_owns_product(db, supplier_id, product_id).await?;
+
+        let upload = validate_upload(ctx, file, UploadKind::Image, ctx.data::<UploadPolicy>()?)
+            .map_err(|e| e.extend())?;
+        let storage = ctx.data::<Arc<dyn Storage>>()?;
+        let product = attach_product_image(
+            db,
+            storage.as_ref(),
+            ctx.data::<ImageQueue>()?,
+            product_id.into(),
+            upload,
+        )
+        .await
+        .map_err(|e| e.extend())?;
+        ctx.data::<CatalogCache>()?
+            .invalidate(current_tenant(ctx))
+            .await;
+
+        Ok(product.into())
+    }
+
+    // images named by SKU, e.g. ABC-123.jpg, are unpacked and attached to the supplier's products
+    // in the background, follow the report through imageZipJob with the returned id
+    #[graphql(guard = "role_guard!(ROLE_SUPPLIER)")]
+    async fn upload_product_images_zip(
+        &self,
+        ctx: &Context<'_>,
+        file: Upload,
+    ) -> Result<ImageZipJobs, async_graphql::Error> {
+        use crate::entity::{image_zip_jobs, prelude::ImageZipJobs as ImageZipJobsEntity};
+        let db = ctx.data::<DatabaseConnection>()?;
+        let supplier_id = get_customer_supplier_id(db, ctx, ROLE_SUPPLIER).await?;
+
+        let file_name = file.value(ctx)?.filename.chars().take(255).collect();
+        let upload = validate_upload(ctx, file, UploadKind::Zip, ctx.data::<UploadPolicy>()?)
+            .map_err(|e| e.extend())?;
+        let queued = ctx
+            .data::<ImageZipQueue>()?
+            .reserve()
+            .map_err(|e| e.extend())?;
+
+        let job = ImageZipJobsEntity::insert(image_zip_jobs::ActiveModel {
+            supplier_id: Set(supplier_id),
+            file_name: Set(file_name),
+            tenant_id: Set(current_tenant(ctx).0),
+            ..Default::default()
+        })
+        .exec_with_returning(db)
+        .await?;
+        queued.send(ImageZipTask {
+            job_id: job.job_id,
+            supplier_id,
+            bytes: upload.bytes,
+        });
+
+        Ok(job.into())
+    }
+
+    // the video is stored right away and listed on the product once the worker marks it READY
+    #[graphql(guard = "role_guard!(ROLE_SUPPLIER)")]
+    async fn upload_product_video(
+        &self,
+        ctx: &Context<'_>,
+        product_id: ProductId,
+        file: Upload,
+    ) -> Result<ProductVideos, async_graphql::Error> {
+        use crate::entity::{prelude::ProductVideos as ProductVideosEntity, product_videos};
+        let db = ctx.data::<DatabaseConnection>()?;
+        let supplier_id = get_customer_supplier_id(db, ctx, ROLE_SUPPLIER).await?;
+        check_if_supplier_owns_product(db, supplier_id, product_id).await?;
+
+        let upload = validate_upload(ctx, file, UploadKind::Video, ctx.data::<UploadPolicy>()?)
+            .map_err(|e| e.extend())?;
+        let queued = ctx
+            .data::<VideoQueue>()?
+            .reserve()
+            .map_err(|e| e.extend())?;
+        let key = format!("products/{}/videos/{}", product_id, upload.storage_name());
+        let url = ctx
+            .data::<Arc<dyn Storage>>()?
+            .put(&key, upload.bytes.clone(), upload.content_type)
+            .await
+            .map_err(|e| e.extend())?;
+
+        let video = ProductVideosEntity::insert(product_videos::ActiveModel {
+            product_id: Set(product_id.into()),
+            source: Set("UPLOAD".to_string()),
+            url: Set(url),
+            ..Default::default()
+        })
+        .exec_with_returning(db)
+        .await?;
+        queued.enqueue(
+            db,
+            VideoJob::Upload {
+                video_id: video.video_id,
+                bytes: upload.bytes,
+            },
+        );
+
+        Ok(video.into())
+    }
+
+    // only YouTube and Vimeo links are accepted, the worker checks the video can be embedded
+    #[graphql(guard = "role_guard!(ROLE_SUPPLIER)")]
+    async fn add_product_video_url(
+        &self,
+        ctx: &Context<'_>,
+        product_id: ProductId,
+        url: String,
+    ) -> Result<ProductVideos, async_graphql::Error> {
+        use crate::entity::{prelude::ProductVideos as ProductVideosEntity, product_videos};
+        let db = ctx.data::<DatabaseConnection>()?;
+        let supplier_id = get_customer_supplier_id(db, ctx, ROLE_SUPPLIER).await?;
+        check_if_supplier_owns_product(db, supplier_id, product_id).await?;
+
+        let (provider, url) = parse_video_url(&url).ok_or_else(|| {
+            AppError::invalid("Only YouTube and Vimeo video links are supported").extend()
+        })?;
+        let queued = ctx
+            .data::<VideoQueue>()?
+            .reserve()
+            .map_err(|e| e.extend())?;
+
+        let video = ProductVideosEntity::insert(product_videos::ActiveModel {
+            product_id: Set(product_id.into()),
+            source: Set("EXTERNAL".to_string()),
+            provider: Set(Some(provider.as_str().to_string())),
+            url: Set(url.clone()),
+            ..Default::default()
+        })
+        .exec_with_returning(db)
+        .await?;
+        queued.enqueue(
+            db,
+            VideoJob::External {
+                video_id: video.video_id,
+                provider,
+                url,
+            },
+        );
+
+        Ok(video.into())
+    }
+
+    #[graphql(guard = "role_guard!(ROLE_SUPPLIER)")]
+    async fn remove_product_video(
+        &self,
+        ctx: &Context<'_>,
+        video_id: i32,
+    ) -> Result<String, async_graphql::Error> {
+        use crate::entity::{prelude::ProductVideos as ProductVideosEntity, product_videos};
+        let db = ctx.data::<DatabaseConnection>()?;
+        let supplier_id = get_customer_supplier_id(db, ctx, ROLE_SUPPLIER).await?;
+
+        let video = ProductVideosEntity::find_by_id(video_id)
+            .one(db)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Video").extend())?;
+        check_if_supplier_owns_product(db, supplier_id, ProductId(video.product_id)).await?;
+
+        // the file goes first, a failed delete leaves the video listed so it can be removed again.
+        // Files are named by their content, the same upload twice shares one
+        let shared = ProductVideosEntity::find()
+            .filter(product_videos::Column::Url.eq(&video.url))
+            .filter(product_videos::Column::VideoId.ne(video.video_id))
+            .count(db)
+            .await?
+            > 0;
+        if video.source == "UPLOAD" && !shared {
+            ctx.data::<Arc<dyn Storage>>()?
+                .delete(&video.url)
+                .await
+                .map_err(|e| e.extend())?;
+        }
+        video.delete(db).await?;
+        Ok("Video removed".to_string())
+    }
+
+    // recomputes every category's product count, for backfilling or after the category tree was edited
+    #[graphql(guard = "role_guard!(ROLE_ADMIN)")]
+    async fn recount_category_products(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<String, async_graphql::Error> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        rebuild_category_counts(db).await?;
+        Ok("Category product counts rebuilt".to_string())
+    }
+
+    // categories are maintained in the database directly, call this after editing them so every
+    // instance drops its cached tree instead of waiting out the TTL
+    #[graphql(guard = "role_guard!(ROLE_ADMIN)")]
+    async fn invalidate_category_cache(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<String, async_graphql::Error> {
+        let cache = ctx.data::<HotCache>()?;
+
+        cache
+            .invalidate(&category_tree_key(current_tenant(ctx)))
+            .await
+            .map_err(|e| e.extend())?;
+
+        Ok("Category cache invalidated".to_string())
+    }
+
+    // fills the shared cache after a deploy so the first requests don't all go to the database,
+    // entries that are still cached are left as they are
+    #[graphql(guard = "role_guard!(ROLE_ADMIN)")]
+    async fn warm_cache(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default_with = "WarmCacheScope::All")] scope: WarmCacheScope,
+        // how many best sellers get their productsWithId answer cached
+        #[graphql(default = 50)] limit: u64,
+        // the paginator the storefront sends with productsWithId(productId), the first of one
+        // product by date when left out
+        paginator: Option<OrderAndPagination>,
+    ) -> Result<WarmedCache, async_graphql::Error> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let tenant = current_tenant(ctx);
+
+        let tree_warmed = scope.includes(WarmCacheScope::CategoryTree);
+        if tree_warmed {
+            category_tree(ctx).await.map_err(|e| e.extend())?;
+        }
+
+        let mut top_products = 0;
+        if scope.includes(WarmCacheScope::TopProducts) {
+            let catalog = ctx.data::<CatalogCache>()?;
+            let boost_rules = ctx.data::<BoostRuleSet>()?;
+            let region = ctx.data::<RegionConfig>()?.effective_region(None);
+            let paginator = paginator.unwrap_or_else(product_page_paginator);
+            for product_id in best_sellers(db, tenant, limit.clamp(1, 500))
+                .await
+                .map_err(|e| e.extend())?
+            {
+                cached_products_with_id(
+                    catalog,
+                    db,
+                    boost_rules,
+                    tenant,
+                    in_sandbox(ctx),
+                    ProductLookup {
+                        product_id: Some(product_id.into()),
+                        ..Default::default()
+                    },
+                    region.clone(),
+                    paginator.clone(),
+                )
+                .await
+                .map_err(|e| e.extend())?;
+                top_products += 1;
+            }
+        }
+
+        Ok(WarmedCache {
+            category_tree: tree_warmed,
+            top_products,
+        })
+    }
+
+    // queued for the reassignment worker, which moves the products in batches,
+    // follow the progress through categoryReassignment with the returned id
+    #[graphql(guard = "role_guard!(ROLE_ADMIN)")]
+    async fn reassign_products_category(
+        &self,
+        ctx: &Context<'_>,
+        from_category_id: i32,
+        to_category_id: i32,
+        filter: Option<ReassignProductsFilter>,
+    ) -> Result<CategoryReassignments, async_graphql::Error> {
         use crate::entity::{
-            orders,
+            categories, category_reassignments,
             prelude::{
-                OrderItems as OrderItemsEntity, Orders as OrdersEntity, Reviews as ReviewsEntity,
+                Categories as CategoriesEntity,
+                CategoryReassignments as CategoryReassignmentsEntity,
             },
         };
         let db = ctx.data::<DatabaseConnection>()?;
-        let token = ctx
-            .data_opt::<String>()
-            .ok_or("No authorization token found")?;
+        let user_id = AuthenticatedUser::from_ctx(ctx)
+            .map_err(|e| e.extend())?
+            .user_id
+            .parse::<i32>()?;
+        let tenant = current_tenant(ctx);
+
+        if from_category_id == to_category_id {
+            return Err(AppError::invalid("Source and target category must differ").extend());
+        }
+        let found = CategoriesEntity::find()
+            .filter(categories::Column::CategoryId.is_in([from_category_id, to_category_id]))
+            .for_tenant(tenant)
+            .count(db)
+            .await?;
+        if found != 2 {
+            return Err(AppError::NotFound("Category").extend());
+        }
+
+        let (name_contains, supplier_id) = match filter {
+            Some(filter) => (
+                filter
+                    .name_contains
+                    .map(|name| sanitize_search(&name))
+                    .transpose()
+                    .map_err(|e| e.extend())?
+                    .filter(|name| !name.is_empty()),
+                filter.supplier_id,
+            ),
+            None => (None, None),
+        };
+        let tree = category_tree(ctx).await.map_err(|e| e.extend())?;
+        let template = attribute_template(db, &tree, Some(to_category_id))
+            .await
+            .map_err(|e| e.extend())?;
+        if let Some(lacking) = lacks_required_attribute(&template) {
+            let missing =
+                reassigned_products(tenant, from_category_id, name_contains.clone(), supplier_id)
+                    .filter(lacking)
+                    .count(db)
+                    .await?;
+            if missing > 0 {
+                return Err(AppError::Validation {
+                    message: format!(
+                        "{} products lack attributes the target category requires",
+                        missing
+                    ),
+                    failed_rules: required_attribute_rules(&template),
+                }
+                .extend());
+            }
+        }
+        let reassignment =
+            CategoryReassignmentsEntity::insert(category_reassignments::ActiveModel {
+                requested_by: Set(user_id),
+                from_category_id: Set(from_category_id),
+                to_category_id: Set(to_category_id),
+                name_contains: Set(name_contains),
+                supplier_id: Set(supplier_id),
+                tenant_id: Set(tenant.0),
+                ..Default::default()
+            })
+            .exec_with_returning(db)
+            .await?;
+
+        Ok(reassignment.into())
+    }
+
+    // creates products from a CSV catalog, rows whose name matches an existing product of the supplier update it instead
+    #[graphql(
+        guard = "role_guard!(ROLE_SUPPLIER).and(TermsGuard)",
+        complexity = "complexity::unbounded(child_complexity)"
+    )]
+    async fn import_products_csv(
+        &self,
+        ctx: &Context<'_>,
+        file: Upload,
+    ) -> Result<Vec<Products>, async_graphql::Error> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let supplier_id = get_customer_supplier_id(db, ctx, ROLE_SUPPLIER).await?;
+        check_supplier_approved(db, supplier_id).await?;
+
+        let upload = validate_upload(ctx, file, UploadKind::Csv, ctx.data::<UploadPolicy>()?)
+            .map_err(|e| e.extend())?;
+        let rows = parse_product_csv(&upload.bytes).map_err(|e| e.extend())?;
+
         let txn = db.begin().await?;
+        let imported = import_product_rows(ctx, &txn, supplier_id, rows).await?;
+        txn.commit().await?;
+        ctx.data::<CatalogCache>()?
+            .invalidate(current_tenant(ctx))
+            .await;
 
-        let customer_id = get_customer_supplier_id(db, token, ROLE_CUSTOMER).await?;
+        Ok(imported)
+    }
 
-        // check if customer has ordered the product
-        if OrderItemsEntity::find()
-            .inner_join(OrdersEntity)
-            .filter(orders::Column::CustomerId.eq(customer_id))
-            .one(&txn)
-            .await?
-            .is_none()
+    // what importing the CSV as the supplier's whole catalog would change, nothing is written yet.
+    // Listed products missing from the CSV would be archived, confirm with applyProductsCsv
+    #[graphql(guard = "role_guard!(ROLE_SUPPLIER).and(TermsGuard)")]
+    async fn preview_products_csv(
+        &self,
+        ctx: &Context<'_>,
+        file: Upload,
+    ) -> Result<CatalogImportPreview, async_graphql::Error> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let supplier_id = get_customer_supplier_id(db, ctx, ROLE_SUPPLIER).await?;
+        check_supplier_approved(db, supplier_id).await?;
+
+        let upload = validate_upload(ctx, file, UploadKind::Csv, ctx.data::<UploadPolicy>()?)
+            .map_err(|e| e.extend())?;
+        let rows = parse_product_csv(&upload.bytes).map_err(|e| e.extend())?;
+        let csv = String::from_utf8(upload.bytes)
+            .map_err(|_| AppError::invalid("Product CSV must be UTF-8").extend())?;
+        let diff = catalog_diff(ctx, db, supplier_id, &rows)
+            .await
+            .map_err(|e| e.extend())?;
+
+        let pending = PendingCatalogImport {
+            supplier_id,
+            csv,
+            diff,
+        };
+        let (apply_token, expires_at) =
+            store_catalog_import(ctx.data::<redis::Client>()?, &pending)
+                .await
+                .map_err(|e| e.extend())?;
+
+        Ok(CatalogImportPreview {
+            apply_token,
+            expires_at,
+            diff: pending.diff,
+        })
+    }
+
+    // carries out a previewed import, refused when the catalog has changed since the preview so
+    // the supplier never applies a diff they haven't seen
+    #[graphql(
+        guard = "role_guard!(ROLE_SUPPLIER).and(TermsGuard)",
+        complexity = "complexity::unbounded(child_complexity)"
+    )]
+    async fn apply_products_csv(
+        &self,
+        ctx: &Context<'_>,
+        apply_token: String,
+    ) -> Result<Vec<Products>, async_graphql::Error> {
+        use crate::entity::{prelude::Products as ProductsEntity, products};
+        let db = ctx.data::<DatabaseConnection>()?;
+        let supplier_id = get_customer_supplier_id(db, ctx, ROLE_SUPPLIER).await?;
+        check_supplier_approved(db, supplier_id).await?;
+
+        let pending = take_catalog_import(ctx.data::<redis::Client>()?, &apply_token)
+            .await
+            .map_err(|e| e.extend())?;
+        if pending.supplier_id != supplier_id {
+            return Err(
+                AppError::invalid("Apply token is invalid, expired or already used").extend(),
+            );
+        }
+        let rows = parse_product_csv(pending.csv.as_bytes()).map_err(|e| e.extend())?;
+
+        let txn = db.begin().await?;
+        // the supplier's products are locked so nothing changes between the check and the import
+        ProductsEntity::find()
+            .filter(products::Column::SupplierId.eq(supplier_id))
+            .lock_exclusive()
+            .all(&txn)
+            .await?;
+        let diff = catalog_diff(ctx, &txn, supplier_id, &rows)
+            .await
+            .map_err(|e| e.extend())?;
+        if diff != pending.diff {
+            return Err(AppError::Validation {
+                message: "The catalog has changed since the preview, preview the CSV again"
+                    .to_string(),
+                failed_rules: vec!["catalogChanged".to_string()],
+            }
+            .extend());
+        }
+
+        let imported = import_product_rows(ctx, &txn, supplier_id, rows).await?;
+        let archived_at = Utc::now().fixed_offset();
+        for deactivation in diff.deactivations {
+            let product = ProductsEntity::find_by_id(deactivation.product_id)
+                .one(&txn)
+                .await?
+                .ok_or_else(|| AppError::NotFound("Product").extend())?;
+            let category_id = product.category_id;
+            products::ActiveModel {
+                archived_at: Set(Some(archived_at)),
+                ..product.into()
+            }
+            .update(&txn)
+            .await?;
+            adjust_category_count(&txn, category_id, -1).await?;
+        }
+        txn.commit().await?;
+        ctx.data::<CatalogCache>()?
+            .invalidate(current_tenant(ctx))
+            .await;
+
+        Ok(imported)
+    }
+
+    #[graphql(guard = "role_guard!(ROLE_CUSTOMER)")]
+    async fn register_review(
+        &self,
+        ctx: &Context<'_>,
+        mut input: RegisterReview,
+    ) -> Result<Reviews, async_graphql::Error> {
+        use crate::entity::prelude::Reviews as ReviewsEntity;
+        let db = ctx.data::<DatabaseConnection>()?;
+        let txn = db.begin().await?;
+
+        let customer_id = get_customer_supplier_id(db, ctx, ROLE_CUSTOMER).await?;
+
+        if let Some(reason) = review_ineligible_reason(
+            &txn,
+            customer_id,
+            input.product_id,
+            ctx.data::<ReviewPolicy>()?,
+        )
+        .await?
         {
-            return Err("Customer has not ordered the product".into());
+            return Err(AppError::Validation {
+                message: reason.message().to_string(),
+                failed_rules: vec![reason.code().to_string()],
+            }
+            .extend());
         }
 
+        input.review_text = input
+            .review_text
+            .map(|text| {
+                filter_content(
+                    ctx.data::<ContentFilterPolicy>()?,
+                    ContentField::Review,
+                    text,
+                )
+                .map_err(|e| e.extend())
+            })
+            .transpose()?;
         let review = create_review_model(input, customer_id)?;
 
         let insert_review = ReviewsEntity::insert(review)
             .exec_with_returning(&txn)
             .await?;
 
+        apply_review_to_summary(&txn, &insert_review, 1).await?;
+        record_event(
+            &txn,
+            current_tenant(ctx),
+            REVIEW_POSTED,
+            &ReviewPosted {
+                customer_id,
+                review_id: insert_review.review_id,
+                product_id: insert_review.product_id,
+            },
+        )
+        .await
+        .map_err(|e| e.extend())?;
+
         txn.commit().await?;
 
         Ok(insert_review.into())
@@ -131,24 +992,45 @@ impl ProductsMutation {
         &self,
         ctx: &Context<'_>,
         review_id: i32,
-        input: RegisterReview,
+        mut input: RegisterReview,
     ) -> Result<Reviews, async_graphql::Error> {
         use crate::entity::{prelude::Reviews as ReviewsEntity, reviews};
         let db = ctx.data::<DatabaseConnection>()?;
-        let token = ctx
-            .data_opt::<String>()
-            .ok_or("No authorization token found")?;
         let txn = db.begin().await?;
 
-        let customer_id = get_customer_supplier_id(db, token, ROLE_CUSTOMER).await?;
+        let customer_id = get_customer_supplier_id(db, ctx, ROLE_CUSTOMER).await?;
 
-        let review = create_review_model(input, customer_id)?;
+        let existing_review = ReviewsEntity::find_by_id(review_id)
+            .one(&txn)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Review").extend())?;
+
+        if existing_review.customer_id != customer_id {
+            return Err(AppError::forbidden().extend());
+        }
+
+        input.review_text = input
+            .review_text
+            .map(|text| {
+                filter_content(
+                    ctx.data::<ContentFilterPolicy>()?,
+                    ContentField::Review,
+                    text,
+                )
+                .map_err(|e| e.extend())
+            })
+            .transpose()?;
+        let mut review = create_review_model(input, customer_id)?;
+        review.review_id = Set(review_id);
 
         let update_review = ReviewsEntity::update(review)
             .filter(reviews::Column::ReviewId.eq(review_id))
             .exec(&txn)
             .await?;
 
+        apply_review_to_summary(&txn, &existing_review, -1).await?;
+        apply_review_to_summary(&txn, &update_review, 1).await?;
+
         txn.commit().await?;
 
         Ok(update_review.into())
@@ -162,23 +1044,21 @@ impl ProductsMutation {
     ) -> Result<String, async_graphql::Error> {
         use crate::entity::{prelude::Reviews as ReviewsEntity, reviews};
         let db = ctx.data::<DatabaseConnection>()?;
-        let token = ctx
-            .data_opt::<String>()
-            .ok_or("No authorization token found")?;
         let txn = db.begin().await?;
 
-        let customer_id = get_customer_supplier_id(db, token, ROLE_CUSTOMER).await?;
+        let customer_id = get_customer_supplier_id(db, ctx, ROLE_CUSTOMER).await?;
 
         let review = ReviewsEntity::find()
             .filter(reviews::Column::ReviewId.eq(review_id))
             .one(&txn)
             .await?
-            .ok_or("Review not found")?;
+            .ok_or_else(|| AppError::NotFound("Review").extend())?;
 
         if review.customer_id != customer_id {
-            return Err("Unauthorized".into());
+            return Err(AppError::forbidden().extend());
         }
 
+        apply_review_to_summary(&txn, &review, -1).await?;
         review.delete(&txn).await?;
 
         txn.commit().await?;
@@ -194,14 +1074,12 @@ impl ProductsMutation {
     ) -> Result<Discounts, async_graphql::Error> {
         use crate::entity::prelude::Discounts as DiscountsEntity;
         let db = ctx.data::<DatabaseConnection>()?;
-        let token = ctx
-            .data_opt::<String>()
-            .ok_or("No authorization token found")?;
-        let supplier_id = get_customer_supplier_id(db, token, ROLE_SUPPLIER).await?;
+        let supplier_id = get_customer_supplier_id(db, ctx, ROLE_SUPPLIER).await?;
 
         check_if_supplier_owns_product(db, supplier_id, input.product_id).await?;
 
-        let discount = create_discount_model(input)?;
+        let mut discount = create_discount_model(input)?;
+        discount.tenant_id = Set(current_tenant(ctx).0);
         let insert_discount = DiscountsEntity::insert(discount)
             .exec_with_returning(db)
             .await?;
@@ -217,10 +1095,7 @@ impl ProductsMutation {
     ) -> Result<Discounts, async_graphql::Error> {
         use crate::entity::{discounts, prelude::Discounts as DiscountsEntity};
         let db = ctx.data::<DatabaseConnection>()?;
-        let token = ctx
-            .data_opt::<String>()
-            .ok_or("No authorization token found")?;
-        let supplier_id = get_customer_supplier_id(db, token, ROLE_SUPPLIER).await?;
+        let supplier_id = get_customer_supplier_id(db, ctx, ROLE_SUPPLIER).await?;
 
         check_if_supplier_owns_product(db, supplier_id, input.product_id).await?;
 
@@ -239,14 +1114,11 @@ impl ProductsMutation {
         &self,
         ctx: &Context<'_>,
         discount_id: i32,
-        product_id: i32,
+        product_id: ProductId,
     ) -> Result<String, async_graphql::Error> {
         use crate::entity::prelude::Discounts as DiscountsEntity;
         let db = ctx.data::<DatabaseConnection>()?;
-        let token = ctx
-            .data_opt::<String>()
-            .ok_or("No authorization token found")?;
-        let supplier_id = get_customer_supplier_id(db, token, ROLE_SUPPLIER).await?;
+        let supplier_id = get_customer_supplier_id(db, ctx, ROLE_SUPPLIER).await?;
 
         check_if_supplier_owns_product(db, supplier_id, product_id).await?;
 
@@ -254,3 +1126,134 @@ impl ProductsMutation {
         Ok("Discount deleted".to_string())
     }
 }
+
+async fn set_product_archived(
+    ctx: &Context<'_>,
+    product_id: ProductId,
+    archived: bool,
+) -> Result<Products, async_graphql::Error> {
+    use crate::entity::{prelude::Products as ProductsEntity, products};
+    let db = ctx.data::<DatabaseConnection>()?;
+    let supplier_id = get_customer_supplier_id(db, ctx, ROLE_SUPPLIER).await?;
+    check_if_supplier_owns_product(db, supplier_id, product_id).await?;
+
+    let txn = db.begin().await?;
+    let product = ProductsEntity::find_by_id(product_id)
+        .lock_exclusive()
+        .one(&txn)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Product").extend())?;
+    if product.archived_at.is_some() == archived {
+        return Ok(product.into());
+    }
+    if !archived {
+        if let Some(reason) = &product.removal_reason {
+            return Err(
+                AppError::invalid(format!("Product was removed by an admin: {}", reason)).extend(),
+            );
+        }
+        check_supplier_approved(db, supplier_id).await?;
+    }
+    let category_id = product.category_id;
+    let product = products::ActiveModel {
+        archived_at: Set(archived.then(|| Utc::now().fixed_offset())),
+        ..product.into()
+    }
+    .update(&txn)
+    .await?;
+    adjust_category_count(&txn, category_id, if archived { -1 } else { 1 }).await?;
+    txn.commit().await?;
+    ctx.data::<CatalogCache>()?
+        .invalidate(current_tenant(ctx))
+        .await;
+
+    Ok(product.into())
+}
+
+// creates or updates the supplier's products from parsed catalog rows, rows whose name matches an
+// existing product of the supplier update it instead
+async fn import_product_rows(
+    ctx: &Context<'_>,
+    txn: &DatabaseTransaction,
+    supplier_id: i32,
+    rows: Vec<(ProductCsvRow, Decimal)>,
+) -> Result<Vec<Products>, async_graphql::Error> {
+    use crate::entity::{prelude::Products as ProductsEntity, products};
+    let duplicate_policy = ctx.data::<DuplicatePolicy>()?;
+    let content_policy = ctx.data::<ContentFilterPolicy>()?;
+    let mut imported = Vec::with_capacity(rows.len());
+    for (row, base_price) in rows {
+        let existing = ProductsEntity::find()
+            .filter(products::Column::SupplierId.eq(supplier_id))
+            .filter(products::Column::Name.eq(row.name.as_str()))
+            .one(txn)
+            .await?;
+
+        // rows matching a product by name update it, only new rows are checked for duplicates
+        let previous = existing
+            .as_ref()
+            .map(|product| (product.category_id, product.archived_at.is_some()));
+        let (mut product, duplicate_warnings) = match existing {
+            Some(product) => (product.into(), Vec::new()),
+            None => (
+                products::ActiveModel {
+                    name: Set(row.name.clone()),
+                    supplier_id: Set(Some(supplier_id)),
+                    tenant_id: Set(current_tenant(ctx).0),
+                    ..Default::default()
+                },
+                check_duplicate_products(
+                    txn,
+                    supplier_id,
+                    &row.name,
+                    row.category_id,
+                    None,
+                    duplicate_policy,
+                )
+                .await
+                .map_err(|e| e.extend())?,
+            ),
+        };
+        product.description = Set(row
+            .description
+            .map(|text| {
+                filter_content(content_policy, ContentField::ProductDescription, text)
+                    .map_err(|e| e.extend())
+            })
+            .transpose()?);
+        product.base_price = Set(base_price);
+        product.category_id = Set(row.category_id);
+        product.stock_quantity = Set(row.stock_quantity);
+        if row.sku.is_some() {
+            product.sku = Set(row.sku);
+        }
+
+        let product = product.save(txn).await?.try_into_model()?;
+        let attributes = match row.attributes.as_deref().map(str::trim) {
+            Some(column) if !column.is_empty() => parse_csv_attributes(column),
+            _ if previous.is_some() => product_attributes(txn, product.product_id)
+                .await
+                .map_err(|e| e.extend())?,
+            _ => Vec::new(),
+        };
+        let attributes =
+            checked_row_attributes(ctx, &product.name, product.category_id, &attributes)
+                .await
+                .map_err(|e| e.extend())?;
+        replace_product_attributes(txn, product.product_id, attributes)
+            .await
+            .map_err(|e| e.extend())?;
+        match previous {
+            Some((_, true)) => {}
+            Some((previous_category_id, false)) => {
+                move_category_count(txn, previous_category_id, product.category_id).await?
+            }
+            None => adjust_category_count(txn, product.category_id, 1).await?,
+        }
+        imported.push(Products {
+            duplicate_warnings,
+            ..product.into()
+        });
+    }
+    Ok(imported)
+}
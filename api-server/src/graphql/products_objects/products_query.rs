@@ -1,13 +1,35 @@
-use crate::models::{
-    order_und_pagination::{OrderAndPagination, PageInfo},
-    products::{
-        paginate_products, Categories, Discounts, Products, ProductsPaginate, Reviews,
-        ReviewsPaginate,
+use crate::{
+    api_keys::in_sandbox,
+    auth::{RoleGuard, ROLE_ADMIN, ROLE_CUSTOMER, ROLE_SUPPLIER},
+    catalog_cache::CatalogCache,
+    config::{RegionConfig, ReviewPolicy, SponsorshipPolicy},
+    error::AppError,
+    graphql::{complexity, macros::role_guard},
+    ids::ProductId,
+    models::{
+        availability::{month_availability, AvailabilityDay},
+        boost_rules::BoostRuleSet,
+        category_attributes::{has_attribute, ProductAttributeInput},
+        category_reassignments::CategoryReassignments,
+        image_zip_jobs::ImageZipJobs,
+        order_und_pagination::{OrderAndPagination, PageInfo},
+        products::{
+            cached_products_with_id, category_children, category_tree,
+            check_if_supplier_owns_product, filter_region, on_sale, paginate_products,
+            review_ineligible_reason, Categories, Discounts, ProductLookup, Products,
+            ProductsPaginate, ReviewEligibility, Reviews, ReviewsPaginate,
+        },
+        sponsorships::sponsored_products,
+        user::get_customer_supplier_id,
+        videos::ProductVideos,
     },
+    search::{full_text_search, like_contains, prefix_tsquery, sanitize_search},
+    tenancy::{current_tenant, TenantScope},
 };
-use async_graphql::{Context, Object};
+use async_graphql::{Context, ErrorExtensions, Object};
 use sea_orm::{
     ColumnTrait, DatabaseConnection, EntityTrait, PaginatorTrait, QueryFilter, QueryOrder,
+    QuerySelect,
 };
 
 #[derive(Default)]
@@ -15,38 +37,72 @@ pub struct ProductsQuery;
 
 #[Object]
 impl ProductsQuery {
+    #[allow(clippy::too_many_arguments)]
+    #[graphql(complexity = "complexity::list(paginator.pagination.page_size, child_complexity)")]
     async fn products_with_id(
         &self,
         ctx: &Context<'_>,
         category_id: Option<i32>,
         supplier_id: Option<i32>,
-        base_product_id: Option<i32>,
-        product_id: Option<i32>,
+        base_product_id: Option<ProductId>,
+        product_id: Option<ProductId>,
+        region: Option<String>,
         paginator: OrderAndPagination,
+    ) -> Result<ProductsPaginate, async_graphql::Error> {
+        let region = ctx.data::<RegionConfig>()?.effective_region(region);
+
+        cached_products_with_id(
+            ctx.data::<CatalogCache>()?,
+            ctx.data::<DatabaseConnection>()?,
+            ctx.data::<BoostRuleSet>()?,
+            current_tenant(ctx),
+            in_sandbox(ctx),
+            ProductLookup {
+                category_id,
+                supplier_id,
+                base_product_id,
+                product_id,
+            },
+            region,
+            paginator,
+        )
+        .await
+        .map_err(|e| e.extend())
+    }
+
+    #[graphql(complexity = "complexity::list(paginator.pagination.page_size, child_complexity)")]
+    async fn products_with_name(
+        &self,
+        ctx: &Context<'_>,
+        name: String,
+        region: Option<String>,
+        paginator: OrderAndPagination,
+        // facet values from categoryFacets, a product has to carry all of them
+        #[graphql(default)] attributes: Vec<ProductAttributeInput>,
     ) -> Result<ProductsPaginate, async_graphql::Error> {
         use crate::entity::{prelude::Products as ProductsEntity, products};
         let db = ctx.data::<DatabaseConnection>()?;
 
         let page = paginator.pagination.page - 1;
         let page_size = paginator.pagination.page_size;
-
-        let products = ProductsEntity::find().filter(
-            match (category_id, supplier_id, base_product_id, product_id) {
-                (Some(category_id), None, None, None) => {
-                    products::Column::CategoryId.eq(category_id)
-                }
-                (None, Some(supplier_id), None, None) => {
-                    products::Column::SupplierId.eq(supplier_id)
-                }
-                (None, None, Some(base_product_id), None) => {
-                    products::Column::BaseProductId.eq(base_product_id)
-                }
-                (None, None, None, Some(product_id)) => products::Column::ProductId.eq(product_id),
-                _ => Err("Only one of category_id, supplier_id, base_product_id or product_id can be used")?,
-            },
+        let name = sanitize_search(&name).map_err(|e| e.extend())?;
+
+        let mut products = ProductsEntity::find()
+            .filter(like_contains(products::Column::Name, &name))
+            .for_tenant(current_tenant(ctx));
+        for attribute in &attributes {
+            products = products.filter(has_attribute(&attribute.name, &attribute.value));
+        }
+        let products = filter_region(
+            on_sale(products),
+            ctx.data::<RegionConfig>()?.effective_region(region),
         );
 
+        let products = ctx
+            .data::<BoostRuleSet>()?
+            .apply(current_tenant(ctx), products);
         let products = paginate_products(paginator, products).await?;
+
         let products = products.paginate(db, page_size);
         let items = PageInfo {
             total_pages: products.num_pages().await?,
@@ -55,7 +111,23 @@ impl ProductsQuery {
 
         let products = products.fetch_page(page).await?;
 
-        let products: Vec<Products> = products.into_iter().map(|product| product.into()).collect();
+        let mut products: Vec<Products> =
+            products.into_iter().map(|product| product.into()).collect();
+
+        // sponsored placements sit on top of the first page only, flagged with isSponsored. They
+        // aren't matched against facets, so a faceted search goes without them. A sponsored
+        // product isn't listed a second time among the organic results
+        if page == 0 && attributes.is_empty() {
+            let slots = ctx.data::<SponsorshipPolicy>()?.slots;
+            let mut sponsored = sponsored_products(db, current_tenant(ctx), &name, slots).await?;
+            products.retain(|product| {
+                !sponsored
+                    .iter()
+                    .any(|placed| placed.product_id == product.product_id)
+            });
+            sponsored.append(&mut products);
+            products = sponsored;
+        }
 
         Ok(ProductsPaginate {
             products,
@@ -63,56 +135,158 @@ impl ProductsQuery {
         })
     }
 
-    async fn products_with_name(
+    // ranked full text search over names and descriptions, every word counts as a prefix so
+    // results show up while the customer is still typing
+    #[graphql(complexity = "complexity::list(limit.clamp(1, 100), child_complexity)")]
+    async fn search_products(
         &self,
         ctx: &Context<'_>,
-        name: String,
-        paginator: OrderAndPagination,
-    ) -> Result<ProductsPaginate, async_graphql::Error> {
-        use crate::entity::{prelude::Products as ProductsEntity, products};
+        query: String,
+        #[graphql(default = 20)] limit: u64,
+    ) -> Result<Vec<Products>, async_graphql::Error> {
+        use crate::entity::prelude::Products as ProductsEntity;
         let db = ctx.data::<DatabaseConnection>()?;
 
-        let page = paginator.pagination.page - 1;
-        let page_size = paginator.pagination.page_size;
+        let query = sanitize_search(&query).map_err(|e| e.extend())?;
+        let Some(tsquery) = prefix_tsquery(&query) else {
+            return Ok(Vec::new());
+        };
 
-        let products = ProductsEntity::find().filter(products::Column::Name.contains(name));
+        let tenant = current_tenant(ctx);
+        let region = ctx.data::<RegionConfig>()?.effective_region(None);
+        let limit = limit.clamp(1, 100);
+
+        ctx.data::<CatalogCache>()?
+            .get_or_load(
+                tenant,
+                in_sandbox(ctx),
+                "search_products",
+                &(&tsquery, limit, region.clone()),
+                || async {
+                    let products =
+                        filter_region(on_sale(ProductsEntity::find().for_tenant(tenant)), region);
+                    Ok(full_text_search(products, &tsquery)
+                        .limit(limit)
+                        .all(db)
+                        .await?
+                        .into_iter()
+                        .map(|product| product.into())
+                        .collect())
+                },
+            )
+            .await
+            .map_err(|e| e.extend())
+    }
 
-        let products = paginate_products(paginator, products).await?;
+    // every video of the supplier's product whatever its processing status, newest first
+    #[graphql(
+        guard = "role_guard!(ROLE_SUPPLIER)",
+        complexity = "complexity::unbounded(child_complexity)"
+    )]
+    async fn product_videos(
+        &self,
+        ctx: &Context<'_>,
+        product_id: ProductId,
+    ) -> Result<Vec<ProductVideos>, async_graphql::Error> {
+        use crate::entity::{prelude::ProductVideos as ProductVideosEntity, product_videos};
+        let db = ctx.data::<DatabaseConnection>()?;
+        let supplier_id = get_customer_supplier_id(db, ctx, ROLE_SUPPLIER).await?;
+        check_if_supplier_owns_product(db, supplier_id, product_id).await?;
 
-        let products = products.paginate(db, page_size);
-        let items = PageInfo {
-            total_pages: products.num_pages().await?,
-            total_items: products.num_items().await?,
-        };
+        Ok(ProductVideosEntity::find()
+            .filter(product_videos::Column::ProductId.eq(product_id.0))
+            .order_by_desc(product_videos::Column::CreatedAt)
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|video| video.into())
+            .collect())
+    }
 
-        let products = products.fetch_page(page).await?;
+    // progress of an uploadProductImagesZip job, with the per file report under results
+    #[graphql(guard = "role_guard!(ROLE_SUPPLIER)")]
+    async fn image_zip_job(
+        &self,
+        ctx: &Context<'_>,
+        job_id: i32,
+    ) -> Result<ImageZipJobs, async_graphql::Error> {
+        use crate::entity::{image_zip_jobs, prelude::ImageZipJobs as ImageZipJobsEntity};
+        let db = ctx.data::<DatabaseConnection>()?;
+        let supplier_id = get_customer_supplier_id(db, ctx, ROLE_SUPPLIER).await?;
+
+        Ok(ImageZipJobsEntity::find_by_id(job_id)
+            .filter(image_zip_jobs::Column::SupplierId.eq(supplier_id))
+            .one(db)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Image ZIP job").extend())?
+            .into())
+    }
 
-        let products: Vec<Products> = products.into_iter().map(|product| product.into()).collect();
+    #[graphql(
+        guard = "role_guard!(ROLE_SUPPLIER)",
+        complexity = "complexity::list(limit.clamp(1, 100), child_complexity)"
+    )]
+    async fn image_zip_jobs(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 20)] limit: u64,
+    ) -> Result<Vec<ImageZipJobs>, async_graphql::Error> {
+        use crate::entity::{image_zip_jobs, prelude::ImageZipJobs as ImageZipJobsEntity};
+        let db = ctx.data::<DatabaseConnection>()?;
+        let supplier_id = get_customer_supplier_id(db, ctx, ROLE_SUPPLIER).await?;
 
-        Ok(ProductsPaginate {
-            products,
-            page_info: items,
-        })
+        Ok(ImageZipJobsEntity::find()
+            .filter(image_zip_jobs::Column::SupplierId.eq(supplier_id))
+            .order_by_desc(image_zip_jobs::Column::RequestedAt)
+            .limit(limit.clamp(1, 100))
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|job| job.into())
+            .collect())
     }
 
+    #[graphql(complexity = "complexity::unbounded(child_complexity)")]
     async fn categories(&self, ctx: &Context<'_>) -> Result<Vec<Categories>, async_graphql::Error> {
-        use crate::entity::prelude::Categories as CategoriesEntity;
-        let db = ctx.data::<DatabaseConnection>()?;
+        category_tree(ctx).await.map_err(|e| e.extend())
+    }
 
-        let categories = CategoriesEntity::find().all(db).await?;
+    // the top level categories, nest `children` as deep as the menu goes
+    #[graphql(complexity = "complexity::unbounded(child_complexity)")]
+    async fn category_tree(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<Vec<Categories>, async_graphql::Error> {
+        let tree = category_tree(ctx).await.map_err(|e| e.extend())?;
 
-        let categories: Vec<Categories> = categories
-            .into_iter()
-            .map(|category| category.into())
-            .collect();
+        Ok(category_children(tree, None))
+    }
+
+    // progress of a reassignProductsCategory job
+    #[graphql(guard = "role_guard!(ROLE_ADMIN)")]
+    async fn category_reassignment(
+        &self,
+        ctx: &Context<'_>,
+        reassignment_id: i32,
+    ) -> Result<CategoryReassignments, async_graphql::Error> {
+        use crate::entity::{
+            category_reassignments, prelude::CategoryReassignments as CategoryReassignmentsEntity,
+        };
+        let db = ctx.data::<DatabaseConnection>()?;
 
-        Ok(categories)
+        Ok(CategoryReassignmentsEntity::find_by_id(reassignment_id)
+            .filter(category_reassignments::Column::TenantId.eq(current_tenant(ctx).0))
+            .one(db)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Category reassignment").extend())?
+            .into())
     }
 
+    #[graphql(complexity = "complexity::list(paginator.pagination.page_size, child_complexity)")]
     async fn reviews_for_product(
         &self,
         ctx: &Context<'_>,
-        product_id: i32,
+        product_id: ProductId,
         paginator: OrderAndPagination,
     ) -> Result<ReviewsPaginate, async_graphql::Error> {
         use crate::entity::{prelude::Reviews as ReviewsEntity, reviews};
@@ -142,11 +316,34 @@ impl ProductsQuery {
         })
     }
 
+    #[graphql(guard = "role_guard!(ROLE_CUSTOMER)")]
+    async fn can_review(
+        &self,
+        ctx: &Context<'_>,
+        product_id: ProductId,
+    ) -> Result<ReviewEligibility, async_graphql::Error> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let customer_id = get_customer_supplier_id(db, ctx, ROLE_CUSTOMER).await?;
+
+        let reason =
+            review_ineligible_reason(db, customer_id, product_id, ctx.data::<ReviewPolicy>()?)
+                .await?;
+
+        Ok(ReviewEligibility {
+            eligible: reason.is_none(),
+            reason,
+        })
+    }
+
+    #[graphql(complexity = "complexity::unbounded(child_complexity)")]
     async fn discounts(&self, ctx: &Context<'_>) -> Result<Vec<Discounts>, async_graphql::Error> {
         use crate::entity::prelude::Discounts as DiscountsEntity;
         let db = ctx.data::<DatabaseConnection>()?;
 
-        let discounts = DiscountsEntity::find().all(db).await?;
+        let discounts = DiscountsEntity::find()
+            .for_tenant(current_tenant(ctx))
+            .all(db)
+            .await?;
 
         let discounts: Vec<Discounts> = discounts
             .into_iter()
@@ -156,16 +353,18 @@ impl ProductsQuery {
         Ok(discounts)
     }
 
+    #[graphql(complexity = "complexity::unbounded(child_complexity)")]
     async fn discounts_on_product(
         &self,
         ctx: &Context<'_>,
-        product_id: i32,
+        product_id: ProductId,
     ) -> Result<Vec<Discounts>, async_graphql::Error> {
         use crate::entity::{discounts, prelude::Discounts as DiscountsEntity};
         let db = ctx.data::<DatabaseConnection>()?;
 
         let discounts = DiscountsEntity::find()
             .filter(discounts::Column::ProductId.eq(product_id))
+            .for_tenant(current_tenant(ctx))
             .all(db)
             .await?;
 
@@ -176,4 +375,29 @@ impl ProductsQuery {
 
         Ok(discounts)
     }
+
+    // bookable units per day of a date bookable product, month as YYYY-MM
+    #[graphql(complexity = "complexity::unbounded(child_complexity)")]
+    async fn availability(
+        &self,
+        ctx: &Context<'_>,
+        product_id: ProductId,
+        month: String,
+    ) -> Result<Vec<AvailabilityDay>, async_graphql::Error> {
+        use crate::entity::prelude::Products as ProductsEntity;
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        let product = ProductsEntity::find_by_id(product_id)
+            .for_tenant(current_tenant(ctx))
+            .one(db)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Product").extend())?;
+        if !product.date_bookable {
+            return Err(AppError::invalid("Product is not date bookable").extend());
+        }
+
+        month_availability(db, product_id, &month)
+            .await
+            .map_err(|e| e.extend())
+    }
 }
@@ -0,0 +1,151 @@
+use crate::{
+    auth::{AuthenticatedUser, RoleGuard, ROLE_ADMIN},
+    error::AppError,
+    graphql::{complexity, macros::role_guard},
+    ids::TenantId,
+    models::rate_limits::{RateLimitTier, RateLimitTierSetting},
+    rate_limit::RateLimiter,
+    tenancy::current_tenant,
+};
+use async_graphql::{Context, ErrorExtensions, Object};
+use chrono::Utc;
+use sea_orm::{
+    sea_query::OnConflict, ActiveValue::Set, ColumnTrait, DatabaseConnection, EntityTrait,
+    QueryFilter,
+};
+
+#[derive(Default)]
+pub struct RateLimitsQuery;
+
+#[derive(Default)]
+pub struct RateLimitsMutation;
+
+// every tier of the marketplace, those without an override at their default
+async fn tier_settings(
+    db: &DatabaseConnection,
+    limiter: &RateLimiter,
+    tenant: TenantId,
+) -> Result<Vec<RateLimitTierSetting>, AppError> {
+    use crate::entity::{prelude::RateLimitTiers, rate_limit_tiers};
+
+    let overrides = RateLimitTiers::find()
+        .filter(rate_limit_tiers::Column::TenantId.eq(tenant.0))
+        .all(db)
+        .await?;
+
+    Ok(RateLimitTier::ALL
+        .into_iter()
+        .map(|tier| {
+            let row = overrides.iter().find(|row| row.tier == tier.as_str());
+            let default = limiter.default_limit(tier).min(i32::MAX as u32) as i32;
+            RateLimitTierSetting {
+                tier,
+                requests_per_minute: row.map_or(default, |row| row.requests_per_minute),
+                default_requests_per_minute: default,
+                overridden: row.is_some(),
+                updated_at: row.map(|row| row.updated_at),
+                updated_by: row.and_then(|row| row.updated_by),
+            }
+        })
+        .collect())
+}
+
+#[Object]
+impl RateLimitsQuery {
+    #[graphql(
+        guard = "role_guard!(ROLE_ADMIN)",
+        complexity = "complexity::unbounded(child_complexity)"
+    )]
+    async fn rate_limit_tiers(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<Vec<RateLimitTierSetting>, async_graphql::Error> {
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        tier_settings(db, ctx.data::<RateLimiter>()?, current_tenant(ctx))
+            .await
+            .map_err(|e| e.extend())
+    }
+}
+
+#[Object]
+impl RateLimitsMutation {
+    // takes effect on every instance without a redeploy, windows already running keep their count
+    #[graphql(guard = "role_guard!(ROLE_ADMIN)")]
+    async fn set_rate_limit_tier(
+        &self,
+        ctx: &Context<'_>,
+        tier: RateLimitTier,
+        requests_per_minute: i32,
+    ) -> Result<Vec<RateLimitTierSetting>, async_graphql::Error> {
+        use crate::entity::{prelude::RateLimitTiers, rate_limit_tiers};
+        let db = ctx.data::<DatabaseConnection>()?;
+        let limiter = ctx.data::<RateLimiter>()?;
+        let tenant = current_tenant(ctx);
+
+        if requests_per_minute < 1 {
+            return Err(AppError::invalid("requestsPerMinute must be positive").extend());
+        }
+        let admin_id = AuthenticatedUser::from_ctx(ctx)
+            .map_err(|e| e.extend())?
+            .user_id
+            .parse::<i32>()?;
+
+        RateLimitTiers::insert(rate_limit_tiers::ActiveModel {
+            tenant_id: Set(tenant.0),
+            tier: Set(tier.as_str().to_string()),
+            requests_per_minute: Set(requests_per_minute),
+            updated_at: Set(Utc::now().fixed_offset()),
+            updated_by: Set(Some(admin_id)),
+        })
+        .on_conflict(
+            OnConflict::columns([
+                rate_limit_tiers::Column::TenantId,
+                rate_limit_tiers::Column::Tier,
+            ])
+            .update_columns([
+                rate_limit_tiers::Column::RequestsPerMinute,
+                rate_limit_tiers::Column::UpdatedAt,
+                rate_limit_tiers::Column::UpdatedBy,
+            ])
+            .to_owned(),
+        )
+        .exec_without_returning(db)
+        .await?;
+        limiter
+            .announce_change(db, ctx.data::<redis::Client>()?)
+            .await
+            .map_err(|e| e.extend())?;
+
+        tier_settings(db, limiter, tenant)
+            .await
+            .map_err(|e| e.extend())
+    }
+
+    // back to the default from the environment
+    #[graphql(guard = "role_guard!(ROLE_ADMIN)")]
+    async fn reset_rate_limit_tier(
+        &self,
+        ctx: &Context<'_>,
+        tier: RateLimitTier,
+    ) -> Result<Vec<RateLimitTierSetting>, async_graphql::Error> {
+        use crate::entity::{prelude::RateLimitTiers, rate_limit_tiers};
+        let db = ctx.data::<DatabaseConnection>()?;
+        let limiter = ctx.data::<RateLimiter>()?;
+        let tenant = current_tenant(ctx);
+
+        RateLimitTiers::delete_many()
+            .filter(rate_limit_tiers::Column::TenantId.eq(tenant.0))
+            .filter(rate_limit_tiers::Column::Tier.eq(tier.as_str()))
+            .exec(db)
+            .await?;
+        limiter
+            .announce_change(db, ctx.data::<redis::Client>()?)
+            .await
+            .map_err(|e| e.extend())?;
+
+        tier_settings(db, limiter, tenant)
+            .await
+            .map_err(|e| e.extend())
+    }
+}
@@ -0,0 +1,185 @@
+use crate::{
+    auth::{AuthenticatedUser, RoleGuard, ROLE_ADMIN, ROLE_CUSTOMER, ROLE_SUPPLIER},
+    error::AppError,
+    graphql::{complexity, macros::role_guard},
+    ids::OrderId,
+    models::{
+        order_messages::check_order_participant,
+        refunds::{plan_refund, send_refund, RefundOrderInput, Refunds},
+        user::get_customer_supplier_id,
+    },
+    payment_gateway::{ChargeStatus, PaymentGateway, RefundStatus},
+    permissions::caller_permissions,
+    tenancy::current_tenant,
+};
+use async_graphql::{Context, ErrorExtensions, Object};
+use sea_orm::{
+    ActiveValue::Set, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder,
+    QuerySelect, TransactionTrait,
+};
+use std::sync::Arc;
+
+#[derive(Default)]
+pub struct RefundsQuery;
+
+#[derive(Default)]
+pub struct RefundsMutation;
+
+#[Object]
+impl RefundsQuery {
+    // oldest first, for everyone taking part in the order
+    #[graphql(
+        guard = "role_guard!(ROLE_CUSTOMER, ROLE_SUPPLIER, ROLE_ADMIN)",
+        complexity = "complexity::unbounded(child_complexity)"
+    )]
+    async fn order_refunds(
+        &self,
+        ctx: &Context<'_>,
+        order_id: OrderId,
+    ) -> Result<Vec<Refunds>, async_graphql::Error> {
+        use crate::entity::{prelude::Refunds as RefundsEntity, refunds};
+        let db = ctx.data::<DatabaseConnection>()?;
+        check_order_participant(db, ctx, order_id).await?;
+
+        Ok(RefundsEntity::find()
+            .filter(refunds::Column::OrderId.eq(order_id.0))
+            .order_by_asc(refunds::Column::CreatedAt)
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|refund| refund.into())
+            .collect())
+    }
+}
+
+#[Object]
+impl RefundsMutation {
+    // pays back units of the order's lines and, for admins, an amount on top such as shipping.
+    // Suppliers can only refund lines of their own products
+    #[graphql(guard = "role_guard!(ROLE_ADMIN, ROLE_SUPPLIER)")]
+    async fn refund_order(
+        &self,
+        ctx: &Context<'_>,
+        order_id: OrderId,
+        input: RefundOrderInput,
+    ) -> Result<Refunds, async_graphql::Error> {
+        use crate::entity::{
+            bills, order_items,
+            prelude::{
+                Bills as BillsEntity, OrderItems as OrderItemsEntity, Orders as OrdersEntity,
+                Products as ProductsEntity, RefundItems as RefundItemsEntity,
+                Refunds as RefundsEntity,
+            },
+            refund_items, refunds,
+        };
+        let db = ctx.data::<DatabaseConnection>()?;
+        let claims = AuthenticatedUser::from_ctx(ctx).map_err(|e| e.extend())?;
+        let user_id = claims.user_id.parse::<i32>()?;
+        let gateway = ctx.data::<Arc<dyn PaymentGateway>>()?;
+        // the roles the account holds now, not the ones the token was issued with
+        let is_admin = caller_permissions(ctx).await?.has_role(ROLE_ADMIN);
+
+        let txn = db.begin().await?;
+        // refunds of one order wait for each other, so two can't both take what is left
+        let order = OrdersEntity::find_by_id(order_id)
+            .lock_exclusive()
+            .one(&txn)
+            .await?
+            .filter(|order| order.tenant_id == current_tenant(ctx).0)
+            .ok_or_else(|| AppError::NotFound("Order").extend())?;
+        let items = OrderItemsEntity::find()
+            .filter(order_items::Column::OrderId.eq(order.order_id))
+            .find_also_related(ProductsEntity)
+            .all(&txn)
+            .await?;
+
+        if !is_admin {
+            let supplier_id = get_customer_supplier_id(db, ctx, ROLE_SUPPLIER).await?;
+            if input.extra_amount.is_some() {
+                return Err(AppError::forbidden().extend());
+            }
+            let foreign_line = input.lines.iter().any(|line| {
+                !items.iter().any(|(item, product)| {
+                    item.order_item_id == line.order_item_id
+                        && product
+                            .as_ref()
+                            .is_some_and(|product| product.supplier_id == Some(supplier_id))
+                })
+            });
+            if input.lines.is_empty() || foreign_line {
+                return Err(AppError::forbidden().extend());
+            }
+        }
+
+        let bill = BillsEntity::find()
+            .filter(bills::Column::OrderId.eq(order.order_id))
+            .one(&txn)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Bill").extend())?;
+        let provider_payment_id = match &bill.provider_payment_id {
+            Some(provider_payment_id)
+                if bill.payment_status == ChargeStatus::Succeeded.as_str() =>
+            {
+                provider_payment_id.clone()
+            }
+            _ => {
+                return Err(AppError::invalid(
+                    "Only orders paid through the payment provider can be refunded",
+                )
+                .extend())
+            }
+        };
+
+        let items: Vec<order_items::Model> = items.into_iter().map(|(item, _)| item).collect();
+        let plan = plan_refund(
+            &txn,
+            order.order_id,
+            bill.total_amount,
+            &items,
+            &input,
+            &order.currency,
+        )
+        .await
+        .map_err(|e| e.extend())?;
+
+        let refund = RefundsEntity::insert(refunds::ActiveModel {
+            order_id: Set(order.order_id),
+            amount: Set(plan.amount),
+            extra_amount: Set(plan.extra_amount),
+            reason: Set(input.reason),
+            requested_by: Set(Some(user_id)),
+            ..Default::default()
+        })
+        .exec_with_returning(&txn)
+        .await?;
+        if !plan.lines.is_empty() {
+            RefundItemsEntity::insert_many(plan.lines.into_iter().map(
+                |(order_item_id, quantity, amount)| refund_items::ActiveModel {
+                    refund_id: Set(refund.refund_id),
+                    order_item_id: Set(order_item_id),
+                    quantity: Set(quantity),
+                    amount: Set(amount),
+                },
+            ))
+            .exec(&txn)
+            .await?;
+        }
+        // stored before the provider is asked, a refund the provider took but we lost can't be
+        // repeated by accident, the idempotency key ties the request to this row
+        txn.commit().await?;
+
+        // a refund the provider couldn't be reached for stays PENDING and is asked for again
+        let refund =
+            match send_refund(db, gateway.as_ref(), refund.clone(), &provider_payment_id).await {
+                Ok(refund) => refund,
+                Err(e) => {
+                    eprintln!("Refund {} is pending a retry: {}", refund.refund_id, e);
+                    return Ok(refund.into());
+                }
+            };
+        if refund.status == RefundStatus::Failed.as_str() {
+            return Err(AppError::invalid("The payment provider declined the refund").extend());
+        }
+        Ok(refund.into())
+    }
+}
@@ -0,0 +1,99 @@
+use crate::{
+    auth::{RoleGuard, ROLE_ADMIN},
+    config::RetentionPolicy,
+    graphql::{complexity, macros::role_guard},
+    models::retention::{RetentionPolicies, RetentionRuns},
+    retention::{run_retention, RetentionTarget},
+};
+use async_graphql::{Context, ErrorExtensions, Object};
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, QuerySelect};
+
+#[derive(Default)]
+pub struct RetentionQuery;
+
+#[derive(Default)]
+pub struct RetentionMutation;
+
+#[Object]
+impl RetentionQuery {
+    // configured retention per target together with its most recent run
+    #[graphql(
+        guard = "role_guard!(ROLE_ADMIN)",
+        complexity = "complexity::unbounded(child_complexity)"
+    )]
+    async fn retention_policies(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<Vec<RetentionPolicies>, async_graphql::Error> {
+        use crate::entity::{prelude::RetentionRuns as RetentionRunsEntity, retention_runs};
+        let db = ctx.data::<DatabaseConnection>()?;
+        let policy = ctx.data::<RetentionPolicy>()?;
+
+        let mut policies = Vec::with_capacity(RetentionTarget::ALL.len());
+        for target in RetentionTarget::ALL {
+            let last_run = RetentionRunsEntity::find()
+                .filter(retention_runs::Column::Target.eq(target.as_str()))
+                .order_by_desc(retention_runs::Column::StartedAt)
+                .one(db)
+                .await?;
+
+            policies.push(RetentionPolicies {
+                target: target.as_str().to_string(),
+                retention_days: target.retention_days(policy),
+                last_run: last_run.map(|run| run.into()),
+            });
+        }
+
+        Ok(policies)
+    }
+
+    #[graphql(
+        guard = "role_guard!(ROLE_ADMIN)",
+        complexity = "complexity::list(limit, child_complexity)"
+    )]
+    async fn retention_runs(
+        &self,
+        ctx: &Context<'_>,
+        target: Option<RetentionTarget>,
+        #[graphql(default = 50)] limit: u64,
+    ) -> Result<Vec<RetentionRuns>, async_graphql::Error> {
+        use crate::entity::{prelude::RetentionRuns as RetentionRunsEntity, retention_runs};
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        let mut runs = RetentionRunsEntity::find();
+        if let Some(target) = target {
+            runs = runs.filter(retention_runs::Column::Target.eq(target.as_str()));
+        }
+
+        let runs = runs
+            .order_by_desc(retention_runs::Column::StartedAt)
+            .limit(limit)
+            .all(db)
+            .await?;
+
+        Ok(runs.into_iter().map(|run| run.into()).collect())
+    }
+}
+
+#[Object]
+impl RetentionMutation {
+    // on demand run, dry by default so admins can see what a purge would remove first
+    #[graphql(
+        guard = "role_guard!(ROLE_ADMIN)",
+        complexity = "complexity::unbounded(child_complexity)"
+    )]
+    async fn run_retention(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = true)] dry_run: bool,
+    ) -> Result<Vec<RetentionRuns>, async_graphql::Error> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let policy = ctx.data::<RetentionPolicy>()?;
+
+        let runs = run_retention(db, policy, dry_run)
+            .await
+            .map_err(|e| e.extend())?;
+
+        Ok(runs.into_iter().map(|run| run.into()).collect())
+    }
+}
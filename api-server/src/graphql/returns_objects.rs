@@ -0,0 +1,297 @@
+use crate::{
+    auth::{RoleGuard, ROLE_CUSTOMER, ROLE_SUPPLIER},
+    error::AppError,
+    graphql::{complexity, macros::role_guard},
+    models::{
+        order_status::OrderStatus,
+        refunds::line_paid,
+        returns::{
+            effective_return_policy, is_returnable, ReturnPolicies, ReturnPolicyInput,
+            ReturnRequests,
+        },
+        user::get_customer_supplier_id,
+    },
+    money::round_amount,
+    permissions::caller_permissions,
+};
+use async_graphql::{Context, ErrorExtensions, Object};
+use chrono::{Duration, Utc};
+use sea_orm::{
+    prelude::Decimal, sea_query::OnConflict, ActiveModelTrait, ActiveValue::Set, ColumnTrait,
+    Condition, DatabaseConnection, EntityTrait, JoinType, QueryFilter, QueryOrder, QuerySelect,
+    RelationTrait, TransactionTrait,
+};
+
+#[derive(Default)]
+pub struct ReturnsQuery;
+
+#[derive(Default)]
+pub struct ReturnsMutation;
+
+#[Object]
+impl ReturnsQuery {
+    #[graphql(guard = "role_guard!(ROLE_SUPPLIER)")]
+    async fn return_policy(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<ReturnPolicies, async_graphql::Error> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let supplier_id = get_customer_supplier_id(db, ctx, ROLE_SUPPLIER).await?;
+
+        Ok(effective_return_policy(db, supplier_id).await?.into())
+    }
+
+    // customers see the returns they requested, suppliers the ones for their products, accounts
+    // holding both roles see both
+    #[graphql(
+        guard = "role_guard!(ROLE_CUSTOMER, ROLE_SUPPLIER)",
+        complexity = "complexity::unbounded(child_complexity)"
+    )]
+    async fn return_requests(
+        &self,
+        ctx: &Context<'_>,
+        status: Option<String>,
+    ) -> Result<Vec<ReturnRequests>, async_graphql::Error> {
+        use crate::entity::{
+            order_items, orders, prelude::ReturnRequests as ReturnRequestsEntity, products,
+            return_requests,
+        };
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        let permissions = caller_permissions(ctx).await?;
+        let mut visible = Condition::any();
+        if permissions.has_role(ROLE_SUPPLIER) {
+            let supplier_id = get_customer_supplier_id(db, ctx, ROLE_SUPPLIER).await?;
+            visible = visible.add(products::Column::SupplierId.eq(supplier_id));
+        }
+        if permissions.has_role(ROLE_CUSTOMER) {
+            let customer_id = get_customer_supplier_id(db, ctx, ROLE_CUSTOMER).await?;
+            visible = visible.add(orders::Column::CustomerId.eq(customer_id));
+        }
+        let mut requests = ReturnRequestsEntity::find()
+            .join(
+                JoinType::InnerJoin,
+                return_requests::Relation::OrderItems.def(),
+            )
+            .join(JoinType::InnerJoin, order_items::Relation::Products.def())
+            .join(JoinType::InnerJoin, order_items::Relation::Orders.def())
+            .filter(visible)
+            .order_by_desc(return_requests::Column::RequestedAt);
+        if let Some(status) = status {
+            requests = requests.filter(return_requests::Column::Status.eq(status));
+        }
+
+        Ok(requests
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|request| request.into())
+            .collect())
+    }
+}
+
+#[Object]
+impl ReturnsMutation {
+    #[graphql(guard = "role_guard!(ROLE_SUPPLIER)")]
+    async fn set_return_policy(
+        &self,
+        ctx: &Context<'_>,
+        input: ReturnPolicyInput,
+    ) -> Result<ReturnPolicies, async_graphql::Error> {
+        use crate::entity::{prelude::ReturnPolicies as ReturnPoliciesEntity, return_policies};
+        let db = ctx.data::<DatabaseConnection>()?;
+        let supplier_id = get_customer_supplier_id(db, ctx, ROLE_SUPPLIER).await?;
+
+        let restocking_fee_percent = Decimal::from_str_exact(&input.restocking_fee_percent)?;
+        if input.return_window_days < 0 {
+            return Err(AppError::invalid("Return window cannot be negative").extend());
+        }
+        if restocking_fee_percent < Decimal::ZERO || restocking_fee_percent > Decimal::ONE_HUNDRED {
+            return Err(
+                AppError::invalid("Restocking fee must be between 0 and 100 percent").extend(),
+            );
+        }
+
+        let policy = ReturnPoliciesEntity::insert(return_policies::ActiveModel {
+            supplier_id: Set(supplier_id),
+            return_window_days: Set(input.return_window_days),
+            restocking_fee_percent: Set(restocking_fee_percent),
+            non_returnable_category_ids: Set(input.non_returnable_category_ids),
+            ..Default::default()
+        })
+        .on_conflict(
+            OnConflict::column(return_policies::Column::SupplierId)
+                .update_columns([
+                    return_policies::Column::ReturnWindowDays,
+                    return_policies::Column::RestockingFeePercent,
+                    return_policies::Column::NonReturnableCategoryIds,
+                ])
+                .to_owned(),
+        )
+        .exec_with_returning(db)
+        .await?;
+
+        Ok(policy.into())
+    }
+
+    // checked against the supplier's policy, the fee and refund are fixed at this point
+    #[graphql(guard = "role_guard!(ROLE_CUSTOMER)")]
+    async fn request_return(
+        &self,
+        ctx: &Context<'_>,
+        order_item_id: i32,
+        quantity: i32,
+        reason: Option<String>,
+    ) -> Result<ReturnRequests, async_graphql::Error> {
+        use crate::entity::{
+            prelude::{
+                OrderItems as OrderItemsEntity, Orders as OrdersEntity, Products as ProductsEntity,
+                ReturnRequests as ReturnRequestsEntity,
+            },
+            return_requests,
+        };
+        let db = ctx.data::<DatabaseConnection>()?;
+        let customer_id = get_customer_supplier_id(db, ctx, ROLE_CUSTOMER).await?;
+        let txn = db.begin().await?;
+
+        // the line is locked so two requests for it can't both count the returns before either
+        let (item, order) = OrderItemsEntity::find_by_id(order_item_id)
+            .find_also_related(OrdersEntity)
+            .lock_exclusive()
+            .one(&txn)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Order item").extend())?;
+        let order = order.ok_or_else(|| AppError::NotFound("Order").extend())?;
+        if order.customer_id != Some(customer_id) {
+            return Err(AppError::forbidden().extend());
+        }
+        // nothing can be sent back before it was sent out
+        if ![
+            OrderStatus::Shipped,
+            OrderStatus::OutForDelivery,
+            OrderStatus::Delivered,
+        ]
+        .iter()
+        .any(|status| status.as_str() == order.status)
+        {
+            return Err(
+                AppError::invalid("Only shipped or delivered orders can be returned").extend(),
+            );
+        }
+
+        let product = ProductsEntity::find_by_id(item.product_id)
+            .one(&txn)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Product").extend())?;
+        let supplier_id = product
+            .supplier_id
+            .ok_or_else(|| AppError::invalid("This product can't be returned").extend())?;
+        let policy = effective_return_policy(&txn, supplier_id).await?;
+
+        if !is_returnable(ctx, &policy, product.category_id)
+            .await
+            .map_err(|e| e.extend())?
+        {
+            return Err(AppError::invalid("This product can't be returned").extend());
+        }
+        let ordered_at = order
+            .order_date
+            .ok_or_else(|| AppError::Internal("Order date unknown".to_string()).extend())?;
+        if ordered_at + Duration::days(policy.return_window_days.into()) < Utc::now() {
+            return Err(AppError::invalid("The return window for this order has closed").extend());
+        }
+
+        let already_returned: i32 = ReturnRequestsEntity::find()
+            .filter(return_requests::Column::OrderItemId.eq(order_item_id))
+            .filter(return_requests::Column::Status.ne("REJECTED"))
+            .all(&txn)
+            .await?
+            .iter()
+            .map(|request| request.quantity)
+            .sum();
+        if quantity <= 0 || quantity > item.quantity - already_returned {
+            return Err(AppError::invalid(
+                "Quantity exceeds what can still be returned for this item",
+            )
+            .extend());
+        }
+
+        // what was paid for the line, its discount and VAT included, spread evenly over its units
+        let currency = &order.currency;
+        let value = round_amount(
+            line_paid(&item) * Decimal::from(quantity) / Decimal::from(item.quantity),
+            currency,
+        );
+        let restocking_fee = round_amount(
+            value * policy.restocking_fee_percent / Decimal::ONE_HUNDRED,
+            currency,
+        );
+
+        let request = ReturnRequestsEntity::insert(return_requests::ActiveModel {
+            order_item_id: Set(order_item_id),
+            quantity: Set(quantity),
+            reason: Set(reason),
+            restocking_fee: Set(restocking_fee),
+            refund_amount: Set(value - restocking_fee),
+            ..Default::default()
+        })
+        .exec_with_returning(&txn)
+        .await?;
+        txn.commit().await?;
+
+        Ok(request.into())
+    }
+
+    // approved returns go back into stock
+    #[graphql(guard = "role_guard!(ROLE_SUPPLIER)")]
+    async fn resolve_return_request(
+        &self,
+        ctx: &Context<'_>,
+        return_request_id: i32,
+        approve: bool,
+    ) -> Result<ReturnRequests, async_graphql::Error> {
+        use crate::entity::{
+            prelude::{
+                OrderItems as OrderItemsEntity, Products as ProductsEntity,
+                ReturnRequests as ReturnRequestsEntity,
+            },
+            products, return_requests,
+        };
+        let db = ctx.data::<DatabaseConnection>()?;
+        let supplier_id = get_customer_supplier_id(db, ctx, ROLE_SUPPLIER).await?;
+        let txn = db.begin().await?;
+
+        let (request, item) = ReturnRequestsEntity::find_by_id(return_request_id)
+            .find_also_related(OrderItemsEntity)
+            .lock_exclusive()
+            .one(&txn)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Return request").extend())?;
+        let item = item.ok_or_else(|| AppError::NotFound("Order item").extend())?;
+        let product = ProductsEntity::find_by_id(item.product_id)
+            .one(&txn)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Product").extend())?;
+        if product.supplier_id != Some(supplier_id) {
+            return Err(AppError::forbidden().extend());
+        }
+        if request.status != "REQUESTED" {
+            return Err(AppError::invalid("Return request already resolved").extend());
+        }
+
+        if approve {
+            let stock_quantity = product.stock_quantity + request.quantity;
+            let mut product: products::ActiveModel = product.into();
+            product.stock_quantity = Set(stock_quantity);
+            product.update(&txn).await?;
+        }
+
+        let mut request: return_requests::ActiveModel = request.into();
+        request.status = Set(if approve { "APPROVED" } else { "REJECTED" }.to_string());
+        request.resolved_at = Set(Some(Utc::now().fixed_offset()));
+        let request = request.update(&txn).await?;
+        txn.commit().await?;
+
+        Ok(request.into())
+    }
+}
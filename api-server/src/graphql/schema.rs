@@ -1,58 +1,519 @@
-use crate::graphql::{
-    addresses_objects::{AddressesMutation, AddressesQuery},
-    carts_objects::{CartsMutation, CartsQuery},
-    orders_objects::{OrdersMutation, OrdersQuery},
-    payments_objects::{PaymentsMutation, PaymentsQuery},
-    products_objects::{products_mutations::ProductsMutation, products_query::ProductsQuery},
-    users_objects::{UsersMutation, UsersQuery},
+use crate::{
+    accounting::spawn_accounting_export_worker,
+    api_keys::{record_usage, resolve_api_key, UsageOutcome, API_KEY_HEADER},
+    auth::{Auth, AuthenticatedUser, RejectedToken, ROLE_ADMIN, ROLE_CUSTOMER, ROLE_SUPPLIER},
+    breached_passwords::{BreachedPasswordCheck, PwnedPasswords},
+    broker::Broker,
+    bulk_users::spawn_bulk_user_worker,
+    cart_expiry::spawn_cart_expiry_job,
+    catalog_cache::CatalogCache,
+    config::{
+        dev_mode, env_or, graphiql_sign_in, AccountingConfig, AnalyticsPolicy, CartExpiryPolicy,
+        CatalogCachePolicy, ContentFilterPolicy, CurrencyPolicy, DigestPolicy, DuplicatePolicy,
+        EmailVerificationPolicy, GuestOrderPolicy, HotCachePolicy, JwtPolicy, NewsletterPolicy,
+        PasswordPolicy, PasswordResetPolicy, PiiPolicy, QueryLimits, RegionConfig, RetentionPolicy,
+        ReviewPolicy, SessionPolicy, SponsorshipPolicy, StepUpPolicy, TaxPolicy, TelemetryPolicy,
+        UploadPolicy,
+    },
+    digest::spawn_supplier_digest_scheduler,
+    domain_events::spawn_domain_event_worker,
+    entity::sea_orm_active_enums::UserRole,
+    error::{AppError, AuthErrorCode},
+    graphql::{
+        accounting_objects::{AccountingMutation, AccountingQuery},
+        addresses_objects::{AddressesMutation, AddressesQuery},
+        analytics_objects::{AnalyticsMutation, AnalyticsQuery},
+        announcements_objects::{
+            AnnouncementsMutation, AnnouncementsQuery, AnnouncementsSubscription,
+        },
+        api_keys_objects::{ApiKeysMutation, ApiKeysQuery},
+        boost_rules_objects::{BoostRulesMutation, BoostRulesQuery},
+        bulk_users_objects::{BulkUsersMutation, BulkUsersQuery},
+        canned_responses_objects::{CannedResponsesMutation, CannedResponsesQuery},
+        carts_objects::{CartsMutation, CartsQuery},
+        category_attributes_objects::{CategoryAttributesMutation, CategoryAttributesQuery},
+        coupons_objects::{CouponsMutation, CouponsQuery},
+        email_templates_objects::{EmailTemplatesMutation, EmailTemplatesQuery},
+        federation_objects::FederationQuery,
+        fulfillment_objects::{FulfillmentMutation, FulfillmentQuery},
+        guest_objects::{GuestMutation, GuestQuery},
+        integrity_objects::{IntegrityMutation, IntegrityQuery},
+        metrics_objects::MetricsQuery,
+        moderation_objects::{ModerationMutation, ModerationQuery},
+        newsletter_objects::{NewsletterMutation, NewsletterQuery},
+        node_objects::NodeQuery,
+        order_messages_objects::{
+            OrderMessagesMutation, OrderMessagesQuery, OrderMessagesSubscription,
+        },
+        orders_objects::{OrdersMutation, OrdersQuery, OrdersSubscription},
+        payments_objects::{PaymentsMutation, PaymentsQuery},
+        products_objects::{products_mutations::ProductsMutation, products_query::ProductsQuery},
+        rate_limits_objects::{RateLimitsMutation, RateLimitsQuery},
+        refunds_objects::{RefundsMutation, RefundsQuery},
+        retention_objects::{RetentionMutation, RetentionQuery},
+        returns_objects::{ReturnsMutation, ReturnsQuery},
+        sponsorships_objects::{SponsorshipsMutation, SponsorshipsQuery},
+        supplier_agreements_objects::{SupplierAgreementsMutation, SupplierAgreementsQuery},
+        sync_objects::SyncQuery,
+        terms_objects::{TermsMutation, TermsQuery},
+        users_objects::{UsersMutation, UsersQuery},
+        wishlist_objects::{WishlistMutation, WishlistQuery},
+    },
+    hot_cache::HotCache,
+    ids::TenantId,
+    image_zips::spawn_image_zip_worker,
+    images::spawn_image_worker,
+    integrity::spawn_integrity_check_worker,
+    labels::{LabelProvider, PrintedLabels},
+    loaders::{
+        CategoryLoader, ProductAttributesLoader, ReferencePriceLoader, SupplierLoader,
+        WishlistLoader,
+    },
+    metrics::OperationMetrics,
+    models::{
+        announcements::AnnouncementsChanged, boost_rules::BoostRuleSet,
+        order_messages::OrderMessages, orders::OrderStatusChanged, rate_limits::RateLimitTier,
+        user::held_roles,
+    },
+    order_events::spawn_status_relay,
+    payment_gateway::PaymentGateway,
+    payment_webhooks::spawn_payment_event_worker,
+    permissions::PermissionCache,
+    rate_limit::RateLimiter,
+    reassignment::spawn_category_reassignment_worker,
+    request_log::RequestLog,
+    retention::spawn_retention_scheduler,
+    sandbox::{SandboxDb, SandboxGateway},
+    sessions::is_access_token_revoked,
+    storage::Storage,
+    tenancy::TenantDirectory,
+    vat::{VatIdValidator, ViesValidator},
+    videos::spawn_video_worker,
 };
-use async_graphql::{http::GraphiQLSource, EmptySubscription, MergedObject, Schema};
-use async_graphql_axum::GraphQLRequest;
+use async_graphql::{
+    dataloader::DataLoader,
+    extensions::{ApolloTracing, Tracing},
+    http::{GraphiQLSource, ALL_WEBSOCKET_PROTOCOLS},
+    Data, ErrorExtensions, MergedObject, MergedSubscription, Pos, Response, Schema,
+};
+use async_graphql_axum::{GraphQLProtocol, GraphQLRequest, GraphQLWebSocket};
 use axum::{
+    extract::{ConnectInfo, Query, WebSocketUpgrade},
     http::HeaderMap,
     response::{self, IntoResponse},
     Extension, Json,
 };
-use sea_orm::DatabaseConnection;
+use chrono::Duration;
+use sea_orm::{ActiveEnum, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
+use serde::Deserialize;
+use std::{net::SocketAddr, sync::Arc};
+
+pub type AppSchema = Schema<QueryRoot, MutationRoot, SubscriptionRoot>;
 
-pub type AppSchema = Schema<QueryRoot, MutationRoot, EmptySubscription>;
+// address of the caller, taken from X-Forwarded-For when running behind a trusted proxy
+pub struct ClientIp(pub String);
 
 #[derive(MergedObject, Default)]
 pub struct QueryRoot(
+    AccountingQuery,
     AddressesQuery,
+    AnalyticsQuery,
+    AnnouncementsQuery,
+    ApiKeysQuery,
+    BoostRulesQuery,
+    BulkUsersQuery,
+    CannedResponsesQuery,
     CartsQuery,
+    CategoryAttributesQuery,
+    CouponsQuery,
+    EmailTemplatesQuery,
+    FederationQuery,
+    FulfillmentQuery,
+    GuestQuery,
+    IntegrityQuery,
+    MetricsQuery,
+    ModerationQuery,
+    NewsletterQuery,
+    NodeQuery,
+    OrderMessagesQuery,
     OrdersQuery,
     PaymentsQuery,
     ProductsQuery,
+    RateLimitsQuery,
+    RefundsQuery,
+    RetentionQuery,
+    ReturnsQuery,
+    SponsorshipsQuery,
+    SupplierAgreementsQuery,
+    SyncQuery,
+    TermsQuery,
     UsersQuery,
+    WishlistQuery,
 );
 
 #[derive(MergedObject, Default)]
 pub struct MutationRoot(
+    AccountingMutation,
     AddressesMutation,
+    AnalyticsMutation,
+    AnnouncementsMutation,
+    ApiKeysMutation,
+    BoostRulesMutation,
+    BulkUsersMutation,
+    CannedResponsesMutation,
     CartsMutation,
+    CategoryAttributesMutation,
+    CouponsMutation,
+    EmailTemplatesMutation,
+    FulfillmentMutation,
+    GuestMutation,
+    IntegrityMutation,
+    ModerationMutation,
+    NewsletterMutation,
+    OrderMessagesMutation,
     OrdersMutation,
     PaymentsMutation,
     ProductsMutation,
+    RateLimitsMutation,
+    RefundsMutation,
+    RetentionMutation,
+    ReturnsMutation,
+    SponsorshipsMutation,
+    SupplierAgreementsMutation,
+    TermsMutation,
     UsersMutation,
+    WishlistMutation,
+);
+
+#[derive(MergedSubscription, Default)]
+pub struct SubscriptionRoot(
+    AnnouncementsSubscription,
+    OrderMessagesSubscription,
+    OrdersSubscription,
 );
 
-pub fn create_schema(db: DatabaseConnection) -> AppSchema {
-    Schema::build(
+pub fn create_schema(
+    db: DatabaseConnection,
+    sandbox: SandboxDb,
+    redis: redis::Client,
+    rate_limiter: RateLimiter,
+    pii_policy: PiiPolicy,
+    jwt_policy: JwtPolicy,
+    storage: Arc<dyn Storage>,
+    payment_gateway: Arc<dyn PaymentGateway>,
+) -> AppSchema {
+    let image_queue = spawn_image_worker(storage.clone());
+    let image_zip_queue = spawn_image_zip_worker(
+        db.clone(),
+        storage.clone(),
+        image_queue.clone(),
+        UploadPolicy::from_env(),
+    );
+    let video_queue = spawn_video_worker();
+    let boost_rules = BoostRuleSet::default();
+    boost_rules.spawn_reloader(db.clone(), redis.clone());
+    let hot_cache = HotCache::new(redis.clone(), HotCachePolicy::from_env());
+    hot_cache.spawn_invalidation_listener();
+    let catalog_cache = CatalogCache::new(
+        hot_cache.clone(),
+        redis.clone(),
+        CatalogCachePolicy::from_env(),
+    );
+    let retention_policy = RetentionPolicy::from_env();
+    spawn_retention_scheduler(db.clone(), retention_policy.clone());
+    spawn_payment_event_worker(db.clone(), payment_gateway.clone(), redis.clone());
+    spawn_domain_event_worker(db.clone());
+    spawn_accounting_export_worker(db.clone(), storage.clone(), AccountingConfig::from_env());
+    spawn_category_reassignment_worker(db.clone(), redis.clone());
+    spawn_integrity_check_worker(db.clone(), storage.clone());
+    spawn_bulk_user_worker(db.clone(), redis.clone(), SessionPolicy::from_env());
+    spawn_cart_expiry_job(db.clone(), CartExpiryPolicy::from_env());
+    spawn_supplier_digest_scheduler(db.clone(), DigestPolicy::from_env());
+    let status_changes = Broker::<OrderStatusChanged>::new(256);
+    spawn_status_relay(redis.clone(), status_changes.clone());
+
+    let category_loader = DataLoader::new(CategoryLoader(db.clone()), tokio::spawn);
+    let supplier_loader = DataLoader::new(SupplierLoader(db.clone()), tokio::spawn);
+    let wishlist_loader = DataLoader::new(WishlistLoader(db.clone()), tokio::spawn);
+    let attributes_loader = DataLoader::new(ProductAttributesLoader(db.clone()), tokio::spawn);
+    let reference_price_loader = DataLoader::new(ReferencePriceLoader(db.clone()), tokio::spawn);
+
+    let limits = QueryLimits::from_env();
+    let mut schema = Schema::build(
         QueryRoot::default(),
         MutationRoot::default(),
-        EmptySubscription,
+        SubscriptionRoot::default(),
     )
+    // served as a subgraph, _service and _entities let a federation router compose it
+    .enable_federation()
+    .limit_depth(limits.max_depth)
+    .limit_complexity(limits.max_complexity)
     .data(db)
-    .finish()
+    .data(AnalyticsPolicy::from_env())
+    .data(CurrencyPolicy::from_env())
+    .data(ContentFilterPolicy::from_env())
+    .data(DuplicatePolicy::from_env())
+    .data(PasswordPolicy::from_env())
+    .data(EmailVerificationPolicy::from_env())
+    .data(GuestOrderPolicy::from_env())
+    .data(NewsletterPolicy::from_env())
+    .data(PasswordResetPolicy::from_env())
+    .data(StepUpPolicy::from_env())
+    .data(RegionConfig::from_env())
+    .data(SessionPolicy::from_env())
+    .data(retention_policy)
+    .data(ReviewPolicy::from_env())
+    .data(SponsorshipPolicy::from_env())
+    .data(TaxPolicy::from_env())
+    .data(UploadPolicy::from_env())
+    .data(storage)
+    .data(jwt_policy)
+    .data(image_queue)
+    .data(image_zip_queue)
+    .data(video_queue)
+    .data(sandbox)
+    .data(boost_rules)
+    .data(rate_limiter)
+    .data(hot_cache)
+    .data(catalog_cache)
+    .data(category_loader)
+    .data(supplier_loader)
+    .data(wishlist_loader)
+    .data(attributes_loader)
+    .data(reference_price_loader)
+    .data(redis)
+    .data(Broker::<OrderMessages>::new(256))
+    .data(Broker::<AnnouncementsChanged>::new(16))
+    .data(status_changes)
+    .data(Arc::new(PwnedPasswords::default()) as Arc<dyn BreachedPasswordCheck>)
+    .data(payment_gateway)
+    .data(Arc::new(ViesValidator::default()) as Arc<dyn VatIdValidator>)
+    .data(Arc::new(PrintedLabels) as Arc<dyn LabelProvider>);
+
+    // operation counts and latencies for /metrics
+    schema = schema.extension(OperationMetrics);
+
+    // operations and their arguments on stdout, values marked with @pii are hashed or redacted
+    if env_or("LOG_REQUESTS", false) {
+        schema = schema.extension(RequestLog(pii_policy));
+    }
+
+    // a span per resolver for the OTLP exporter, nested under the request's
+    if TelemetryPolicy::from_env().otlp_endpoint.is_some() {
+        schema = schema.extension(Tracing);
+    }
+
+    // per resolver timings under `extensions.tracing`, in the Apollo tracing format
+    if dev_mode() {
+        schema = schema.extension(ApolloTracing);
+    }
+
+    schema.finish()
 }
 
-pub async fn graphiql() -> impl IntoResponse {
-    response::Html(GraphiQLSource::build().endpoint("/").finish())
+#[derive(Deserialize)]
+pub struct GraphiqlParams {
+    // role of the seeded user GraphiQL signs in as, only honoured with GRAPHIQL_SIGN_IN
+    #[serde(rename = "as")]
+    role: Option<String>,
 }
 
+// the seeded account GRAPHIQL_<ROLE>_EMAIL names, None for a role without one. Signed in for
+// an hour
+fn graphiql_email(role: &str) -> Option<String> {
+    Some(env_or(
+        &format!("GRAPHIQL_{}_EMAIL", role.to_uppercase()),
+        String::new(),
+    ))
+    .filter(|email| !email.is_empty())
+}
+
+async fn graphiql_token(
+    db: &DatabaseConnection,
+    jwt_policy: &JwtPolicy,
+    tenant: TenantId,
+    role: UserRole,
+) -> Result<Option<String>, AppError> {
+    use crate::entity::{prelude::Users, users};
+
+    let Some(email) = graphiql_email(&role.to_value()) else {
+        return Ok(None);
+    };
+    let Some(user) = Users::find()
+        .filter(users::Column::Role.eq(role))
+        .filter(users::Column::TenantId.eq(tenant.0))
+        .filter(users::Column::DisabledAt.is_null())
+        .filter(users::Column::Email.eq(email))
+        .one(db)
+        .await?
+    else {
+        return Ok(None);
+    };
+
+    let roles = held_roles(db, &user)
+        .await
+        .map_err(|e| AppError::Internal(e.message))?;
+    Auth::create_token_with_roles(
+        jwt_policy,
+        user.user_id.into(),
+        tenant,
+        user.role.to_value(),
+        roles,
+        Duration::hours(1),
+    )
+    .map(Some)
+}
+
+// switches the page between the seeded roles by reloading it with another ?as=
+fn graphiql_role_picker(selected: Option<&str>) -> String {
+    let options: String = ["", ROLE_CUSTOMER, ROLE_SUPPLIER, ROLE_ADMIN]
+        .into_iter()
+        .filter(|role| role.is_empty() || graphiql_email(role).is_some())
+        .map(|role| {
+            format!(
+                r#"<option value="{}"{}>{}</option>"#,
+                role,
+                if selected.unwrap_or_default() == role {
+                    " selected"
+                } else {
+                    ""
+                },
+                if role.is_empty() { "signed out" } else { role }
+            )
+        })
+        .collect();
+    format!(
+        r#"<form method="get" style="position:fixed;right:12px;bottom:12px;z-index:10">
+      <select name="as" onchange="this.form.submit()">{}</select>
+    </form>
+  "#,
+        options
+    )
+}
+
+// with GRAPHIQL_SIGN_IN the page can be signed in as a seeded customer, supplier or admin, whose
+// token is sent with every request and the subscription's connection_init
+pub async fn graphiql(
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(jwt_policy): Extension<JwtPolicy>,
+    Extension(tenants): Extension<TenantDirectory>,
+    headers: HeaderMap,
+    Query(params): Query<GraphiqlParams>,
+) -> impl IntoResponse {
+    let role = params
+        .role
+        .filter(|_| graphiql_sign_in())
+        .and_then(|role| UserRole::try_from_value(&role).ok());
+
+    let mut authorization = None;
+    if let Some(role) = role.clone() {
+        match tenants.resolve(&headers) {
+            Ok(tenant) => match graphiql_token(&db, &jwt_policy, tenant, role.clone()).await {
+                Ok(Some(token)) => authorization = Some(format!("Bearer {}", token)),
+                Ok(None) => eprintln!("No {} to sign GraphiQL in as", role.to_value()),
+                Err(e) => eprintln!("Signing GraphiQL in failed: {}", e),
+            },
+            Err(e) => eprintln!("Signing GraphiQL in failed: {}", e),
+        }
+    }
+
+    let mut source = GraphiQLSource::build()
+        .endpoint("/")
+        .subscription_endpoint("/ws");
+    if let Some(authorization) = &authorization {
+        source = source
+            .header("Authorization", authorization)
+            .ws_connection_param("Authorization", authorization);
+    }
+    let mut page = source.finish();
+    if graphiql_sign_in() {
+        let selected = role.map(|role| role.to_value());
+        page = page.replacen(
+            "</body>",
+            &format!("{}</body>", graphiql_role_picker(selected.as_deref())),
+            1,
+        );
+    }
+
+    response::Html(page)
+}
+
+// subscriptions over graphql-ws, the bearer token travels in the connection_init payload
+pub async fn graphql_ws_handler(
+    Extension(schema): Extension<AppSchema>,
+    Extension(tenants): Extension<TenantDirectory>,
+    Extension(redis): Extension<redis::Client>,
+    Extension(jwt_policy): Extension<JwtPolicy>,
+    headers: HeaderMap,
+    protocol: GraphQLProtocol,
+    upgrade: WebSocketUpgrade,
+) -> response::Response {
+    // the connection belongs to the marketplace whose host it was opened on
+    let tenant = match tenants.resolve(&headers) {
+        Ok(tenant) => tenant,
+        Err(e) => return Json(error_response(e)).into_response(),
+    };
+
+    upgrade
+        .protocols(ALL_WEBSOCKET_PROTOCOLS)
+        .on_upgrade(move |stream| {
+            GraphQLWebSocket::new(stream, schema, protocol)
+                .on_connection_init(move |payload| async move {
+                    let mut data = Data::default();
+                    data.insert(tenant);
+                    data.insert(PermissionCache::default());
+                    let token = payload
+                        .get("Authorization")
+                        .or_else(|| payload.get("authorization"))
+                        .and_then(|value| value.as_str())
+                        .map(|auth| auth.strip_prefix("Bearer ").unwrap_or(auth).to_string());
+                    if let Some(token) = token {
+                        match Auth::verify_token(&jwt_policy, &token) {
+                            Err(e) => data.insert(RejectedToken::from(e)),
+                            Ok(claims) => {
+                                // a session from one marketplace is no good on another's host
+                                if claims.tenant_id != tenant.0 {
+                                    return Err(AppError::Auth {
+                                        message: "Token was issued for another marketplace"
+                                            .to_string(),
+                                        code: AuthErrorCode::InsufficientPermissions,
+                                        user_id: Some(claims.user_id),
+                                    }
+                                    .extend());
+                                }
+                                match is_access_token_revoked(&redis, &token, &claims).await {
+                                    Ok(false) => {}
+                                    Ok(true) => {
+                                        return Err(AppError::Auth {
+                                            message: "Token was revoked".to_string(),
+                                            code: AuthErrorCode::TokenExpired,
+                                            user_id: None,
+                                        }
+                                        .extend())
+                                    }
+                                    Err(e) => return Err(e.extend()),
+                                }
+                                data.insert(AuthenticatedUser(claims));
+                            }
+                        }
+                        data.insert(token);
+                    }
+                    Ok(data)
+                })
+                .serve()
+        })
+}
+
+#[allow(clippy::too_many_arguments)]
 pub async fn graphql_handler(
     schema: Extension<AppSchema>,
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(sandbox): Extension<SandboxDb>,
+    Extension(rate_limiter): Extension<RateLimiter>,
+    Extension(tenants): Extension<TenantDirectory>,
+    Extension(redis): Extension<redis::Client>,
+    Extension(jwt_policy): Extension<JwtPolicy>,
+    ConnectInfo(remote_addr): ConnectInfo<SocketAddr>,
     headers: HeaderMap,
     req: GraphQLRequest,
 ) -> impl IntoResponse {
@@ -61,14 +522,162 @@ pub async fn graphql_handler(
         .and_then(|value| value.to_str().ok())
         .and_then(|auth| auth.strip_prefix("Bearer "))
         .map(String::from);
+    let api_key = headers
+        .get(API_KEY_HEADER)
+        .and_then(|value| value.to_str().ok());
+
+    // a request acts as one caller, with two credentials it would be unclear which one
+    if token.is_some() && api_key.is_some() {
+        return Json(error_response(AppError::Auth {
+            message: format!(
+                "Send either a bearer token or an {} header, not both",
+                API_KEY_HEADER
+            ),
+            code: AuthErrorCode::InvalidCredentials,
+            user_id: None,
+        }));
+    }
 
-    let mut request = req.into_inner();
+    let client_ip = headers
+        .get("x-forwarded-for")
+        .filter(|_| env_or("TRUST_PROXY_HEADERS", false))
+        .and_then(|value| value.to_str().ok())
+        .and_then(|forwarded| forwarded.split(',').next())
+        .map(|ip| ip.trim().to_string())
+        .unwrap_or_else(|| remote_addr.ip().to_string());
+
+    let tenant = match tenants.resolve(&headers) {
+        Ok(tenant) => tenant,
+        Err(e) => return Json(error_response(e)),
+    };
+
+    // signed out callers share a budget per IP, admins aren't limited so they can always raise one
+    let mut rate_limit = Some((RateLimitTier::Anonymous, format!("ip:{}", client_ip)));
+
+    let mut request = req.into_inner().data(ClientIp(client_ip)).data(tenant);
+    let permissions = PermissionCache::default();
 
-    // Add the token to the request context
+    // Add the token to the request context, verified once here for every resolver
     if let Some(token) = token {
+        // a session from one marketplace is no good on another's host
+        match Auth::verify_token(&jwt_policy, &token) {
+            Err(e) => request = request.data(RejectedToken::from(e)),
+            Ok(claims) => {
+                if claims.tenant_id != tenant.0 {
+                    return Json(error_response(AppError::Auth {
+                        message: "Token was issued for another marketplace".to_string(),
+                        code: AuthErrorCode::InsufficientPermissions,
+                        user_id: Some(claims.user_id),
+                    }));
+                }
+                // logged out tokens, and those of accounts signed out everywhere, stay valid until
+                // they expire unless they are turned away here. A token that can't be checked is
+                // turned away too, a session store outage mustn't bring revoked sessions back
+                match is_access_token_revoked(&redis, &token, &claims).await {
+                    Ok(false) => {}
+                    Ok(true) => {
+                        return Json(error_response(AppError::Auth {
+                            message: "Token was revoked".to_string(),
+                            code: AuthErrorCode::InvalidCredentials,
+                            user_id: Some(claims.user_id),
+                        }))
+                    }
+                    Err(e) => return Json(error_response(e)),
+                }
+                // the tier follows the roles the account holds now, a revoked admin role stops
+                // exempting the token it was issued with. A failed lookup gets the customer tier
+                let user_key = format!("user:{}", claims.user_id);
+                let held = match claims.user_id.parse::<i32>() {
+                    Ok(user_id) => permissions.load(&db, user_id).await.ok(),
+                    Err(_) => None,
+                };
+                rate_limit = match held {
+                    Some(held) if held.has_role(ROLE_ADMIN) => None,
+                    Some(held) if held.has_role(ROLE_SUPPLIER) => {
+                        Some((RateLimitTier::Supplier, user_key))
+                    }
+                    _ => Some((RateLimitTier::Customer, user_key)),
+                };
+                request = request.data(AuthenticatedUser(claims));
+            }
+        }
         request = request.data(token);
     }
+    request = request.data(permissions);
 
+    // API key callers are rate limited and metered per key, everyone else per account or IP
+    let Some(api_key) = api_key else {
+        if let Some((tier, key)) = rate_limit {
+            if !rate_limiter.check(tenant, tier, &key) {
+                return Json(error_response(AppError::Auth {
+                    message: "Rate limit exceeded".to_string(),
+                    code: AuthErrorCode::RateLimited,
+                    user_id: None,
+                }));
+            }
+        }
+        return Json(schema.execute(request).await);
+    };
+
+    let api_key = match resolve_api_key(&db, &jwt_policy, api_key).await {
+        Ok(api_key) => api_key,
+        Err(e) => return Json(error_response(e)),
+    };
+
+    if !rate_limiter.check(
+        api_key.tenant_id,
+        RateLimitTier::PartnerKey,
+        &format!("api_key:{}", api_key.api_key_id),
+    ) {
+        let _ = record_usage(&db, api_key.api_key_id, UsageOutcome::RateLimited).await;
+        return Json(error_response(AppError::Auth {
+            message: "API key rate limit exceeded".to_string(),
+            code: AuthErrorCode::RateLimited,
+            user_id: None,
+        }));
+    }
+
+    let api_key_id = api_key.api_key_id;
+    // request data is looked up before schema data, so these shadow the production connection, gateway
+    // and the loaders reading wishlists, attributes and prices, which have a sandbox copy
+    if api_key.sandbox {
+        request = request
+            .data(DataLoader::new(
+                WishlistLoader(sandbox.0.clone()),
+                tokio::spawn,
+            ))
+            .data(DataLoader::new(
+                ProductAttributesLoader(sandbox.0.clone()),
+                tokio::spawn,
+            ))
+            .data(DataLoader::new(
+                ReferencePriceLoader(sandbox.0.clone()),
+                tokio::spawn,
+            ))
+            .data(sandbox.0)
+            .data(Arc::new(SandboxGateway) as Arc<dyn PaymentGateway>);
+    }
+    // the key's token was minted by resolve_api_key and can't fail here unless the secret changed
+    request = match Auth::verify_token(&jwt_policy, &api_key.token) {
+        Ok(claims) => request.data(AuthenticatedUser(claims)),
+        Err(e) => request.data(RejectedToken::from(e)),
+    };
+    request = request
+        .data(api_key.tenant_id)
+        .data(api_key.token.clone())
+        .data(api_key);
     let response = schema.execute(request).await;
+
+    let outcome = if response.is_err() {
+        UsageOutcome::Error
+    } else {
+        UsageOutcome::Success
+    };
+    let _ = record_usage(&db, api_key_id, outcome).await;
+
     Json(response)
 }
+
+fn error_response(error: AppError) -> Response {
+    Response::from_errors(vec![error.extend().into_server_error(Pos::default())])
+}
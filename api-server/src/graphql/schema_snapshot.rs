@@ -0,0 +1,283 @@
+// The SDL of the schema is checked in at the repository root. schema.graphql has to match what the
+// resolvers generate, rerun the tests with UPDATE_SCHEMA_SNAPSHOT=1 to rewrite it after changing the API.
+// schema.released.graphql is the schema of the last release, clients may rely on everything in it, so
+// changes that would break them fail until the release that ships them copies schema.graphql over it.
+
+use crate::graphql::schema::{MutationRoot, QueryRoot, SubscriptionRoot};
+use async_graphql::{
+    parser::{
+        parse_schema,
+        types::{
+            BaseType, FieldDefinition, InputValueDefinition, Type, TypeKind, TypeSystemDefinition,
+        },
+    },
+    Positioned, Schema,
+};
+use std::collections::HashMap;
+
+const SNAPSHOT_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../schema.graphql");
+const RELEASED_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../schema.released.graphql");
+
+// only the type system ends up in the SDL, so the schema is built without any data or workers
+fn current_sdl() -> String {
+    Schema::build(
+        QueryRoot::default(),
+        MutationRoot::default(),
+        SubscriptionRoot::default(),
+    )
+    .enable_federation()
+    .finish()
+    .sdl()
+}
+
+fn type_kinds(sdl: &str) -> HashMap<String, TypeKind> {
+    parse_schema(sdl)
+        .expect("snapshot is not valid SDL")
+        .definitions
+        .into_iter()
+        .filter_map(|definition| match definition {
+            TypeSystemDefinition::Type(ty) => Some((ty.node.name.node.to_string(), ty.node.kind)),
+            _ => None,
+        })
+        .collect()
+}
+
+fn kind_name(kind: &TypeKind) -> &'static str {
+    match kind {
+        TypeKind::Scalar => "scalar",
+        TypeKind::Object(_) => "object",
+        TypeKind::Interface(_) => "interface",
+        TypeKind::Union(_) => "union",
+        TypeKind::Enum(_) => "enum",
+        TypeKind::InputObject(_) => "input object",
+    }
+}
+
+// clients reading a field cope with it never being null any more, not with it becoming nullable
+fn output_compatible(old: &Type, new: &Type) -> bool {
+    if !old.nullable && new.nullable {
+        return false;
+    }
+    base_compatible(&old.base, &new.base, output_compatible)
+}
+
+// clients sending a value cope with it becoming optional, not with it becoming required
+fn input_compatible(old: &Type, new: &Type) -> bool {
+    if old.nullable && !new.nullable {
+        return false;
+    }
+    base_compatible(&old.base, &new.base, input_compatible)
+}
+
+fn base_compatible(old: &BaseType, new: &BaseType, inner: fn(&Type, &Type) -> bool) -> bool {
+    match (old, new) {
+        (BaseType::Named(old), BaseType::Named(new)) => old == new,
+        (BaseType::List(old), BaseType::List(new)) => inner(old, new),
+        _ => false,
+    }
+}
+
+fn is_required(value: &InputValueDefinition) -> bool {
+    !value.ty.node.nullable && value.default_value.is_none()
+}
+
+// arguments of a field and fields of an input object follow the same rules
+fn input_value_changes(
+    owner: &str,
+    what: &str,
+    old: &[Positioned<InputValueDefinition>],
+    new: &[Positioned<InputValueDefinition>],
+) -> Vec<String> {
+    let mut changes = Vec::new();
+    for old_value in old {
+        let name = &old_value.node.name.node;
+        match new.iter().find(|value| &value.node.name.node == name) {
+            None => changes.push(format!("{} {} {} was removed", owner, what, name)),
+            Some(new_value)
+                if !input_compatible(&old_value.node.ty.node, &new_value.node.ty.node) =>
+            {
+                changes.push(format!(
+                    "{} {} {} changed from {} to {}",
+                    owner, what, name, old_value.node.ty.node, new_value.node.ty.node
+                ))
+            }
+            Some(_) => {}
+        }
+    }
+    for new_value in new {
+        let name = &new_value.node.name.node;
+        if is_required(&new_value.node) && !old.iter().any(|value| &value.node.name.node == name) {
+            changes.push(format!("{} gained the required {} {}", owner, what, name));
+        }
+    }
+    changes
+}
+
+// changes between two SDL documents that would break a client written against the old one,
+// additions of types, optional arguments, fields and enum values are all fine
+fn breaking_changes(old_sdl: &str, new_sdl: &str) -> Vec<String> {
+    let new_types = type_kinds(new_sdl);
+    let mut changes = Vec::new();
+
+    for (name, old_kind) in type_kinds(old_sdl) {
+        let Some(new_kind) = new_types.get(&name) else {
+            changes.push(format!("type {} was removed", name));
+            continue;
+        };
+
+        match (&old_kind, new_kind) {
+            (TypeKind::Scalar, TypeKind::Scalar) => {}
+            (TypeKind::Object(old), TypeKind::Object(new)) => {
+                for interface in &old.implements {
+                    if !new.implements.iter().any(|i| i.node == interface.node) {
+                        changes.push(format!("{} no longer implements {}", name, interface.node));
+                    }
+                }
+                changes.extend(field_changes(&name, &old.fields, &new.fields));
+            }
+            (TypeKind::Interface(old), TypeKind::Interface(new)) => {
+                changes.extend(field_changes(&name, &old.fields, &new.fields));
+            }
+            (TypeKind::Union(old), TypeKind::Union(new)) => {
+                for member in &old.members {
+                    if !new.members.iter().any(|m| m.node == member.node) {
+                        changes.push(format!("union {} lost member {}", name, member.node));
+                    }
+                }
+            }
+            (TypeKind::Enum(old), TypeKind::Enum(new)) => {
+                for value in &old.values {
+                    if !new
+                        .values
+                        .iter()
+                        .any(|v| v.node.value.node == value.node.value.node)
+                    {
+                        changes.push(format!(
+                            "enum {} lost value {}",
+                            name, value.node.value.node
+                        ));
+                    }
+                }
+            }
+            (TypeKind::InputObject(old), TypeKind::InputObject(new)) => {
+                changes.extend(input_value_changes(
+                    &name,
+                    "field",
+                    &old.fields,
+                    &new.fields,
+                ));
+            }
+            (old_kind, new_kind) => changes.push(format!(
+                "type {} changed from {} to {}",
+                name,
+                kind_name(old_kind),
+                kind_name(new_kind)
+            )),
+        }
+    }
+
+    changes.sort();
+    changes
+}
+
+fn field_changes(
+    owner: &str,
+    old: &[Positioned<FieldDefinition>],
+    new: &[Positioned<FieldDefinition>],
+) -> Vec<String> {
+    let mut changes = Vec::new();
+    for old_field in old {
+        let name = &old_field.node.name.node;
+        let Some(new_field) = new.iter().find(|field| &field.node.name.node == name) else {
+            changes.push(format!("{}.{} was removed", owner, name));
+            continue;
+        };
+        if !output_compatible(&old_field.node.ty.node, &new_field.node.ty.node) {
+            changes.push(format!(
+                "{}.{} changed from {} to {}",
+                owner, name, old_field.node.ty.node, new_field.node.ty.node
+            ));
+        }
+        changes.extend(input_value_changes(
+            &format!("{}.{}", owner, name),
+            "argument",
+            &old_field.node.arguments,
+            &new_field.node.arguments,
+        ));
+    }
+    changes
+}
+
+#[test]
+fn schema_matches_snapshot() {
+    let sdl = current_sdl();
+    if std::env::var_os("UPDATE_SCHEMA_SNAPSHOT").is_some() {
+        std::fs::write(SNAPSHOT_PATH, &sdl).expect("failed to write schema.graphql");
+        return;
+    }
+
+    let snapshot = std::fs::read_to_string(SNAPSHOT_PATH).unwrap_or_default();
+    assert!(
+        snapshot == sdl,
+        "the GraphQL schema changed, review the difference and rerun with UPDATE_SCHEMA_SNAPSHOT=1 to accept it"
+    );
+}
+
+#[test]
+fn no_breaking_changes_since_release() {
+    let released =
+        std::fs::read_to_string(RELEASED_PATH).expect("schema.released.graphql is missing");
+    let changes = breaking_changes(&released, &current_sdl());
+    assert!(
+        changes.is_empty(),
+        "breaking changes against the released schema:\n  {}",
+        changes.join("\n  ")
+    );
+}
+
+#[test]
+fn detects_removed_and_tightened_api() {
+    let old = r#"
+        type Query { product(id: Int!, locale: String): Product }
+        type Product { name: String! price: Float tags: [String!]! }
+        input Filter { name: String category: Int }
+        enum Status { ACTIVE ARCHIVED }
+    "#;
+    let new = r#"
+        type Query { product(id: Int!, locale: String!, region: String!): Product }
+        type Product { name: String price: Float! }
+        input Filter { name: String category: Int! }
+        enum Status { ACTIVE }
+    "#;
+
+    assert_eq!(
+        breaking_changes(old, new),
+        vec![
+            "Filter field category changed from Int to Int!",
+            "Product.name changed from String! to String",
+            "Product.tags was removed",
+            "Query.product argument locale changed from String to String!",
+            "Query.product gained the required argument region",
+            "enum Status lost value ARCHIVED",
+        ]
+    );
+}
+
+#[test]
+fn additions_are_not_breaking() {
+    let old = r#"
+        type Query { product(id: Int!): Product }
+        type Product { name: String }
+        input Filter { name: String! }
+        enum Status { ACTIVE }
+    "#;
+    let new = r#"
+        type Query { product(id: Int!, locale: String, limit: Int! = 10): Product products: [Product!]! }
+        type Product { name: String! sku: String }
+        input Filter { name: String category: Int }
+        enum Status { ACTIVE ARCHIVED }
+        type Review { rating: Int! }
+    "#;
+
+    assert!(breaking_changes(old, new).is_empty());
+}
@@ -0,0 +1,181 @@
+use crate::{
+    auth::{RoleGuard, ROLE_SUPPLIER},
+    config::SponsorshipPolicy,
+    error::AppError,
+    graphql::{complexity, macros::role_guard, schema::ClientIp},
+    models::{
+        products::check_if_supplier_owns_product,
+        sponsorships::{RegisterSponsoredCampaign, SponsoredCampaigns},
+        user::get_customer_supplier_id,
+    },
+    terms::TermsGuard,
+};
+use async_graphql::{Context, ErrorExtensions, Object};
+use chrono::{Duration, Utc};
+use sea_orm::{
+    prelude::Decimal, ActiveModelTrait, ActiveValue::Set, ColumnTrait, DatabaseConnection,
+    EntityTrait, PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, TransactionTrait,
+};
+
+#[derive(Default)]
+pub struct SponsorshipsQuery;
+
+#[derive(Default)]
+pub struct SponsorshipsMutation;
+
+#[Object]
+impl SponsorshipsQuery {
+    #[graphql(
+        guard = "role_guard!(ROLE_SUPPLIER)",
+        complexity = "complexity::unbounded(child_complexity)"
+    )]
+    async fn my_sponsored_campaigns(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<Vec<SponsoredCampaigns>, async_graphql::Error> {
+        use crate::entity::{
+            prelude::SponsoredCampaigns as SponsoredCampaignsEntity, sponsored_campaigns,
+        };
+        let db = ctx.data::<DatabaseConnection>()?;
+        let supplier_id = get_customer_supplier_id(db, ctx, ROLE_SUPPLIER).await?;
+
+        let campaigns = SponsoredCampaignsEntity::find()
+            .filter(sponsored_campaigns::Column::SupplierId.eq(supplier_id))
+            .order_by_desc(sponsored_campaigns::Column::CreatedAt)
+            .all(db)
+            .await?;
+
+        Ok(campaigns
+            .into_iter()
+            .map(|campaign| campaign.into())
+            .collect())
+    }
+}
+
+#[Object]
+impl SponsorshipsMutation {
+    #[graphql(guard = "role_guard!(ROLE_SUPPLIER).and(TermsGuard)")]
+    async fn create_sponsored_campaign(
+        &self,
+        ctx: &Context<'_>,
+        input: RegisterSponsoredCampaign,
+    ) -> Result<SponsoredCampaigns, async_graphql::Error> {
+        use crate::entity::{
+            prelude::SponsoredCampaigns as SponsoredCampaignsEntity, sponsored_campaigns,
+        };
+        let db = ctx.data::<DatabaseConnection>()?;
+        let supplier_id = get_customer_supplier_id(db, ctx, ROLE_SUPPLIER).await?;
+        check_if_supplier_owns_product(db, supplier_id, input.product_id).await?;
+
+        let keyword = input.keyword.trim().to_string();
+        let bid_per_click = Decimal::from_str_exact(&input.bid_per_click)?;
+        let budget = Decimal::from_str_exact(&input.budget)?;
+        if keyword.is_empty() {
+            return Err(AppError::invalid("Keyword cannot be empty").extend());
+        }
+        if bid_per_click <= Decimal::ZERO || budget < bid_per_click {
+            return Err(AppError::invalid(
+                "Budget must cover at least one click at a positive bid",
+            )
+            .extend());
+        }
+
+        let campaign = SponsoredCampaignsEntity::insert(sponsored_campaigns::ActiveModel {
+            supplier_id: Set(supplier_id),
+            product_id: Set(input.product_id.into()),
+            keyword: Set(keyword),
+            bid_per_click: Set(bid_per_click),
+            budget: Set(budget),
+            ..Default::default()
+        })
+        .exec_with_returning(db)
+        .await?;
+
+        Ok(campaign.into())
+    }
+
+    #[graphql(guard = "role_guard!(ROLE_SUPPLIER)")]
+    async fn set_sponsored_campaign_active(
+        &self,
+        ctx: &Context<'_>,
+        campaign_id: i32,
+        active: bool,
+    ) -> Result<SponsoredCampaigns, async_graphql::Error> {
+        use crate::entity::{
+            prelude::SponsoredCampaigns as SponsoredCampaignsEntity, sponsored_campaigns,
+        };
+        let db = ctx.data::<DatabaseConnection>()?;
+        let supplier_id = get_customer_supplier_id(db, ctx, ROLE_SUPPLIER).await?;
+
+        let campaign = SponsoredCampaignsEntity::find_by_id(campaign_id)
+            .one(db)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Campaign").extend())?;
+        if campaign.supplier_id != supplier_id {
+            return Err(AppError::forbidden().extend());
+        }
+
+        let mut campaign: sponsored_campaigns::ActiveModel = campaign.into();
+        campaign.active = Set(active);
+        Ok(campaign.update(db).await?.into())
+    }
+
+    // called by clients when a sponsored result is opened, returns whether the click was billed
+    async fn record_sponsored_click(
+        &self,
+        ctx: &Context<'_>,
+        campaign_id: i32,
+    ) -> Result<bool, async_graphql::Error> {
+        use crate::entity::{
+            prelude::{
+                SponsoredCampaigns as SponsoredCampaignsEntity,
+                SponsoredClicks as SponsoredClicksEntity,
+            },
+            sponsored_campaigns, sponsored_clicks,
+        };
+        let db = ctx.data::<DatabaseConnection>()?;
+        let ClientIp(ip_address) = ctx.data::<ClientIp>()?;
+        let policy = ctx.data::<SponsorshipPolicy>()?;
+        let txn = db.begin().await?;
+
+        let campaign = SponsoredCampaignsEntity::find_by_id(campaign_id)
+            .lock_exclusive()
+            .one(&txn)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Campaign").extend())?;
+        if !campaign.active || campaign.spent + campaign.bid_per_click > campaign.budget {
+            return Ok(false);
+        }
+
+        let recent_clicks = SponsoredClicksEntity::find()
+            .filter(sponsored_clicks::Column::CampaignId.eq(campaign_id))
+            .filter(sponsored_clicks::Column::IpAddress.eq(ip_address.as_str()))
+            .filter(
+                sponsored_clicks::Column::ClickedAt
+                    .gt(Utc::now() - Duration::minutes(policy.click_dedup_minutes)),
+            )
+            .count(&txn)
+            .await?;
+        if recent_clicks > 0 {
+            return Ok(false);
+        }
+
+        SponsoredClicksEntity::insert(sponsored_clicks::ActiveModel {
+            campaign_id: Set(campaign_id),
+            ip_address: Set(ip_address.clone()),
+            cost: Set(campaign.bid_per_click),
+            ..Default::default()
+        })
+        .exec(&txn)
+        .await?;
+
+        let spent = campaign.spent + campaign.bid_per_click;
+        let mut campaign: sponsored_campaigns::ActiveModel = campaign.into();
+        campaign.spent = Set(spent);
+        campaign.update(&txn).await?;
+
+        txn.commit().await?;
+
+        Ok(true)
+    }
+}
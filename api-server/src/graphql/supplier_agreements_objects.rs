@@ -0,0 +1,265 @@
+use crate::{
+    auth::{AuthenticatedUser, RoleGuard, ROLE_ADMIN, ROLE_SUPPLIER},
+    config::UploadPolicy,
+    error::AppError,
+    graphql::{complexity, macros::role_guard},
+    models::{
+        supplier_agreements::{
+            payout_eligibility, release_payout, PayoutEligibility, RegisterSupplierAgreement,
+            SupplierAgreements, SupplierPayouts,
+        },
+        user::{get_customer_supplier_id, tenant_supplier},
+    },
+    storage::Storage,
+    tenancy::current_tenant,
+    uploads::{validate_upload, UploadKind},
+};
+use async_graphql::{Context, ErrorExtensions, Object, Upload};
+use chrono::{Days, NaiveDate, Utc};
+use sea_orm::{
+    prelude::Decimal, ActiveValue::Set, ColumnTrait, DatabaseConnection, EntityTrait, JoinType,
+    PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, RelationTrait,
+};
+use std::sync::Arc;
+
+#[derive(Default)]
+pub struct SupplierAgreementsQuery;
+
+#[derive(Default)]
+pub struct SupplierAgreementsMutation;
+
+#[Object]
+impl SupplierAgreementsQuery {
+    // latest to take effect first
+    #[graphql(
+        guard = "role_guard!(ROLE_ADMIN)",
+        complexity = "complexity::unbounded(child_complexity)"
+    )]
+    async fn supplier_agreements(
+        &self,
+        ctx: &Context<'_>,
+        supplier_id: i32,
+    ) -> Result<Vec<SupplierAgreements>, async_graphql::Error> {
+        use crate::entity::{
+            prelude::SupplierAgreements as SupplierAgreementsEntity, supplier_agreements,
+        };
+        let db = ctx.data::<DatabaseConnection>()?;
+        tenant_supplier(ctx, supplier_id).await?;
+
+        Ok(SupplierAgreementsEntity::find()
+            .filter(supplier_agreements::Column::SupplierId.eq(supplier_id))
+            .order_by_desc(supplier_agreements::Column::EffectiveFrom)
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|agreement| agreement.into())
+            .collect())
+    }
+
+    // agreements ending within the next days, soonest first, so they can be renewed before
+    // payouts are held
+    #[graphql(
+        guard = "role_guard!(ROLE_ADMIN)",
+        complexity = "complexity::unbounded(child_complexity)"
+    )]
+    async fn expiring_supplier_agreements(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 30)] within_days: u32,
+    ) -> Result<Vec<SupplierAgreements>, async_graphql::Error> {
+        use crate::entity::{
+            prelude::SupplierAgreements as SupplierAgreementsEntity, supplier_agreements,
+            suppliers, users,
+        };
+        let db = ctx.data::<DatabaseConnection>()?;
+        let today = Utc::now().date_naive();
+        let until = today
+            .checked_add_days(Days::new(within_days.into()))
+            .ok_or_else(|| AppError::invalid("withinDays is too large").extend())?;
+
+        Ok(SupplierAgreementsEntity::find()
+            .join(
+                JoinType::InnerJoin,
+                supplier_agreements::Relation::Suppliers.def(),
+            )
+            .join(JoinType::InnerJoin, suppliers::Relation::Users.def())
+            .filter(users::Column::TenantId.eq(current_tenant(ctx).0))
+            .filter(supplier_agreements::Column::EffectiveUntil.between(today, until))
+            .order_by_asc(supplier_agreements::Column::EffectiveUntil)
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|agreement| agreement.into())
+            .collect())
+    }
+
+    #[graphql(guard = "role_guard!(ROLE_ADMIN)")]
+    async fn payout_eligibility(
+        &self,
+        ctx: &Context<'_>,
+        supplier_id: i32,
+    ) -> Result<PayoutEligibility, async_graphql::Error> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let supplier = tenant_supplier(ctx, supplier_id).await?;
+
+        Ok(payout_eligibility(db, &supplier, Utc::now().date_naive()).await?)
+    }
+
+    // latest period first
+    #[graphql(
+        guard = "role_guard!(ROLE_ADMIN)",
+        complexity = "complexity::unbounded(child_complexity)"
+    )]
+    async fn supplier_payouts(
+        &self,
+        ctx: &Context<'_>,
+        supplier_id: i32,
+    ) -> Result<Vec<SupplierPayouts>, async_graphql::Error> {
+        use crate::entity::{prelude::SupplierPayouts as SupplierPayoutsEntity, supplier_payouts};
+        let db = ctx.data::<DatabaseConnection>()?;
+        tenant_supplier(ctx, supplier_id).await?;
+
+        Ok(SupplierPayoutsEntity::find()
+            .filter(supplier_payouts::Column::SupplierId.eq(supplier_id))
+            .order_by_desc(supplier_payouts::Column::PeriodStart)
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|payout| payout.into())
+            .collect())
+    }
+
+    // the supplier's own agreements, latest to take effect first
+    #[graphql(
+        guard = "role_guard!(ROLE_SUPPLIER)",
+        complexity = "complexity::unbounded(child_complexity)"
+    )]
+    async fn my_agreements(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<Vec<SupplierAgreements>, async_graphql::Error> {
+        use crate::entity::{
+            prelude::SupplierAgreements as SupplierAgreementsEntity, supplier_agreements,
+        };
+        let db = ctx.data::<DatabaseConnection>()?;
+        let supplier_id = get_customer_supplier_id(db, ctx, ROLE_SUPPLIER).await?;
+
+        Ok(SupplierAgreementsEntity::find()
+            .filter(supplier_agreements::Column::SupplierId.eq(supplier_id))
+            .order_by_desc(supplier_agreements::Column::EffectiveFrom)
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|agreement| agreement.into())
+            .collect())
+    }
+}
+
+#[Object]
+impl SupplierAgreementsMutation {
+    // stores the signed PDF with its terms, a new version is uploaded rather than editing an old one
+    #[graphql(guard = "role_guard!(ROLE_ADMIN)")]
+    async fn upload_supplier_agreement(
+        &self,
+        ctx: &Context<'_>,
+        input: RegisterSupplierAgreement,
+        file: Upload,
+    ) -> Result<SupplierAgreements, async_graphql::Error> {
+        use crate::entity::{
+            prelude::SupplierAgreements as SupplierAgreementsEntity, supplier_agreements,
+        };
+        let db = ctx.data::<DatabaseConnection>()?;
+        let user_id = AuthenticatedUser::from_ctx(ctx)
+            .map_err(|e| e.extend())?
+            .user_id
+            .parse::<i32>()?;
+        tenant_supplier(ctx, input.supplier_id).await?;
+
+        let version = input.version.trim().to_string();
+        if version.is_empty() || version.len() > 50 {
+            return Err(AppError::invalid("Version must be 1 to 50 characters").extend());
+        }
+        let commission_percent = Decimal::from_str_exact(input.commission_percent.trim())?;
+        if commission_percent < Decimal::ZERO || commission_percent > Decimal::ONE_HUNDRED {
+            return Err(AppError::invalid("Commission must be between 0 and 100 percent").extend());
+        }
+        if input
+            .effective_until
+            .is_some_and(|until| until < input.effective_from)
+        {
+            return Err(AppError::invalid("Agreement can't end before it takes effect").extend());
+        }
+        let existing = SupplierAgreementsEntity::find()
+            .filter(supplier_agreements::Column::SupplierId.eq(input.supplier_id))
+            .filter(supplier_agreements::Column::Version.eq(version.as_str()))
+            .count(db)
+            .await?;
+        if existing > 0 {
+            return Err(AppError::invalid(format!(
+                "Version {} of this supplier's agreement is already stored",
+                version
+            ))
+            .extend());
+        }
+
+        let upload = validate_upload(ctx, file, UploadKind::Pdf, ctx.data::<UploadPolicy>()?)
+            .map_err(|e| e.extend())?;
+        let key = format!(
+            "suppliers/{}/agreements/{}",
+            input.supplier_id,
+            upload.storage_name()
+        );
+        // signed contracts aren't public, they're handed out as expiring links
+        ctx.data::<Arc<dyn Storage>>()?
+            .put_private(&key, upload.bytes, upload.content_type)
+            .await
+            .map_err(|e| e.extend())?;
+
+        Ok(
+            SupplierAgreementsEntity::insert(supplier_agreements::ActiveModel {
+                supplier_id: Set(input.supplier_id),
+                version: Set(version),
+                commission_percent: Set(commission_percent),
+                document_key: Set(key),
+                effective_from: Set(input.effective_from),
+                effective_until: Set(input.effective_until),
+                uploaded_by: Set(Some(user_id)),
+                ..Default::default()
+            })
+            .exec_with_returning(db)
+            .await?
+            .into(),
+        )
+    }
+    // pays the supplier for the orders placed in the period, refused while payoutEligibility
+    // holds the supplier's payouts
+    #[graphql(guard = "role_guard!(ROLE_ADMIN)")]
+    async fn release_supplier_payout(
+        &self,
+        ctx: &Context<'_>,
+        supplier_id: i32,
+        period_start: NaiveDate,
+        period_end: NaiveDate,
+    ) -> Result<Vec<SupplierPayouts>, async_graphql::Error> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let user_id = AuthenticatedUser::from_ctx(ctx)
+            .map_err(|e| e.extend())?
+            .user_id
+            .parse::<i32>()?;
+        tenant_supplier(ctx, supplier_id).await?;
+
+        Ok(release_payout(
+            db,
+            supplier_id,
+            period_start,
+            period_end,
+            Utc::now().date_naive(),
+            user_id,
+        )
+        .await
+        .map_err(|e| e.extend())?
+        .into_iter()
+        .map(|payout| payout.into())
+        .collect())
+    }
+}
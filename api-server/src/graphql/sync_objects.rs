@@ -0,0 +1,177 @@
+use crate::{
+    config::RegionConfig,
+    graphql::complexity,
+    models::{
+        products::filter_region,
+        sync::{
+            decode_feed_cursor, deleted_in_sync_window, in_sync_window, sync_horizon, sync_page,
+            CategoriesSync, PricesSync, ProductsSync, MAX_SYNC_BATCH, SYNC_ENTITY_CATEGORY,
+            SYNC_ENTITY_PRODUCT,
+        },
+    },
+    tenancy::{current_tenant, TenantScope},
+};
+use async_graphql::{Context, Object};
+use sea_orm::{DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, QuerySelect};
+
+#[derive(Default)]
+pub struct SyncQuery;
+
+#[Object]
+impl SyncQuery {
+    #[graphql(complexity = "complexity::list(limit.clamp(1, MAX_SYNC_BATCH), child_complexity)")]
+    async fn products_updated_since(
+        &self,
+        ctx: &Context<'_>,
+        cursor: Option<String>,
+        #[graphql(default = 100)] limit: u64,
+    ) -> Result<ProductsSync, async_graphql::Error> {
+        use crate::entity::{prelude::Products as ProductsEntity, products};
+        let db = ctx.data::<DatabaseConnection>()?;
+        let limit = limit.clamp(1, MAX_SYNC_BATCH);
+        let tenant = current_tenant(ctx);
+        let cursor = cursor.as_deref().map(decode_feed_cursor).transpose()?;
+        let horizon = sync_horizon(db).await?;
+
+        let query = ProductsEntity::find()
+            .for_tenant(tenant)
+            .filter(in_sync_window(
+                products::Column::SyncXid,
+                products::Column::ProductId,
+                cursor,
+                horizon,
+            ));
+        let products = filter_region(query, ctx.data::<RegionConfig>()?.effective_region(None))
+            .order_by_asc(products::Column::SyncXid)
+            .order_by_asc(products::Column::ProductId)
+            .limit(limit + 1)
+            .all(db)
+            .await?;
+        let deleted =
+            deleted_in_sync_window(db, SYNC_ENTITY_PRODUCT, tenant, cursor, horizon, limit + 1)
+                .await?;
+
+        let page = sync_page(
+            products
+                .into_iter()
+                .map(|product| (product.sync_xid, product.product_id, product))
+                .collect(),
+            deleted,
+            limit,
+        );
+        Ok(ProductsSync {
+            products: page
+                .changed
+                .into_iter()
+                .map(|product| product.into())
+                .collect(),
+            deleted_product_ids: page.deleted.into_iter().map(|id| id.into()).collect(),
+            next_cursor: page.next_cursor,
+            has_more: page.has_more,
+        })
+    }
+
+    #[graphql(complexity = "complexity::list(limit.clamp(1, MAX_SYNC_BATCH), child_complexity)")]
+    async fn categories_updated_since(
+        &self,
+        ctx: &Context<'_>,
+        cursor: Option<String>,
+        #[graphql(default = 100)] limit: u64,
+    ) -> Result<CategoriesSync, async_graphql::Error> {
+        use crate::entity::{categories, prelude::Categories as CategoriesEntity};
+        let db = ctx.data::<DatabaseConnection>()?;
+        let limit = limit.clamp(1, MAX_SYNC_BATCH);
+        let tenant = current_tenant(ctx);
+        let cursor = cursor.as_deref().map(decode_feed_cursor).transpose()?;
+        let horizon = sync_horizon(db).await?;
+
+        let categories = CategoriesEntity::find()
+            .for_tenant(tenant)
+            .filter(in_sync_window(
+                categories::Column::SyncXid,
+                categories::Column::CategoryId,
+                cursor,
+                horizon,
+            ))
+            .order_by_asc(categories::Column::SyncXid)
+            .order_by_asc(categories::Column::CategoryId)
+            .limit(limit + 1)
+            .all(db)
+            .await?;
+        let deleted =
+            deleted_in_sync_window(db, SYNC_ENTITY_CATEGORY, tenant, cursor, horizon, limit + 1)
+                .await?;
+
+        let page = sync_page(
+            categories
+                .into_iter()
+                .map(|category| (category.sync_xid, category.category_id, category))
+                .collect(),
+            deleted,
+            limit,
+        );
+        Ok(CategoriesSync {
+            categories: page
+                .changed
+                .into_iter()
+                .map(|category| category.into())
+                .collect(),
+            deleted_category_ids: page.deleted,
+            next_cursor: page.next_cursor,
+            has_more: page.has_more,
+        })
+    }
+
+    #[graphql(complexity = "complexity::list(limit.clamp(1, MAX_SYNC_BATCH), child_complexity)")]
+    async fn prices_updated_since(
+        &self,
+        ctx: &Context<'_>,
+        cursor: Option<String>,
+        #[graphql(default = 100)] limit: u64,
+    ) -> Result<PricesSync, async_graphql::Error> {
+        use crate::entity::{prelude::Products as ProductsEntity, products};
+        let db = ctx.data::<DatabaseConnection>()?;
+        let limit = limit.clamp(1, MAX_SYNC_BATCH);
+        let tenant = current_tenant(ctx);
+        let cursor = cursor.as_deref().map(decode_feed_cursor).transpose()?;
+        let horizon = sync_horizon(db).await?;
+
+        let query = ProductsEntity::find()
+            .for_tenant(tenant)
+            .filter(in_sync_window(
+                products::Column::PriceSyncXid,
+                products::Column::ProductId,
+                cursor,
+                horizon,
+            ));
+        let products = filter_region(query, ctx.data::<RegionConfig>()?.effective_region(None))
+            .order_by_asc(products::Column::PriceSyncXid)
+            .order_by_asc(products::Column::ProductId)
+            .limit(limit + 1)
+            .all(db)
+            .await?;
+        // a deleted product takes its price with it
+        let deleted =
+            deleted_in_sync_window(db, SYNC_ENTITY_PRODUCT, tenant, cursor, horizon, limit + 1)
+                .await?;
+
+        let page = sync_page(
+            products
+                .into_iter()
+                .map(|product| (product.price_sync_xid, product.product_id, product))
+                .collect(),
+            deleted,
+            limit,
+        );
+        Ok(PricesSync {
+            prices: page
+                .changed
+                .into_iter()
+                .map(|product| product.into())
+                .collect(),
+            deleted_product_ids: page.deleted.into_iter().map(|id| id.into()).collect(),
+            next_cursor: page.next_cursor,
+            has_more: page.has_more,
+        })
+    }
+}
@@ -0,0 +1,194 @@
+use crate::{
+    auth::{AuthenticatedUser, RoleGuard, ROLE_ADMIN, ROLE_CUSTOMER, ROLE_SUPPLIER},
+    error::AppError,
+    graphql::{complexity, macros::role_guard},
+    models::terms::{PolicyType, PolicyVersions, RegisterPolicyVersion},
+    tenancy::{current_tenant, TenantScope},
+    terms::{current_policy_versions, pending_policy_versions},
+};
+use async_graphql::{Context, ErrorExtensions, Object};
+use chrono::Utc;
+use sea_orm::{
+    sea_query::OnConflict, ActiveValue::Set, ColumnTrait, DatabaseConnection, EntityTrait,
+    ModelTrait, PaginatorTrait, QueryFilter, QueryOrder,
+};
+
+#[derive(Default)]
+pub struct TermsQuery;
+
+#[derive(Default)]
+pub struct TermsMutation;
+
+#[Object]
+impl TermsQuery {
+    #[graphql(complexity = "complexity::unbounded(child_complexity)")]
+    async fn current_policies(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<Vec<PolicyVersions>, async_graphql::Error> {
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        let policies = current_policy_versions(db, current_tenant(ctx)).await?;
+
+        Ok(policies.into_iter().map(|policy| policy.into()).collect())
+    }
+
+    #[graphql(
+        guard = "role_guard!(ROLE_CUSTOMER, ROLE_SUPPLIER, ROLE_ADMIN)",
+        complexity = "complexity::unbounded(child_complexity)"
+    )]
+    async fn pending_policies(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<Vec<PolicyVersions>, async_graphql::Error> {
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        let user_id = AuthenticatedUser::from_ctx(ctx)
+            .map_err(|e| e.extend())?
+            .user_id
+            .parse::<i32>()?;
+        let policies = pending_policy_versions(db, current_tenant(ctx), user_id.into()).await?;
+
+        Ok(policies.into_iter().map(|policy| policy.into()).collect())
+    }
+
+    #[graphql(
+        guard = "role_guard!(ROLE_ADMIN)",
+        complexity = "complexity::unbounded(child_complexity)"
+    )]
+    async fn policy_versions(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<Vec<PolicyVersions>, async_graphql::Error> {
+        use crate::entity::{policy_versions, prelude::PolicyVersions as PolicyVersionsEntity};
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        let policies = PolicyVersionsEntity::find()
+            .for_tenant(current_tenant(ctx))
+            .order_by_desc(policy_versions::Column::PublishedAt)
+            .all(db)
+            .await?;
+
+        Ok(policies.into_iter().map(|policy| policy.into()).collect())
+    }
+}
+
+#[Object]
+impl TermsMutation {
+    #[graphql(guard = "role_guard!(ROLE_CUSTOMER, ROLE_SUPPLIER, ROLE_ADMIN)")]
+    async fn accept_terms(
+        &self,
+        ctx: &Context<'_>,
+        version: String,
+        #[graphql(default_with = "PolicyType::Terms")] policy_type: PolicyType,
+    ) -> Result<String, async_graphql::Error> {
+        use crate::entity::{
+            policy_acceptances, policy_versions,
+            prelude::{
+                PolicyAcceptances as PolicyAcceptancesEntity,
+                PolicyVersions as PolicyVersionsEntity,
+            },
+        };
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        let user_id = AuthenticatedUser::from_ctx(ctx)
+            .map_err(|e| e.extend())?
+            .user_id
+            .parse::<i32>()?;
+
+        let policy = PolicyVersionsEntity::find()
+            .for_tenant(current_tenant(ctx))
+            .filter(policy_versions::Column::PolicyType.eq(policy_type.as_str()))
+            .filter(policy_versions::Column::Version.eq(&version))
+            .filter(policy_versions::Column::PublishedAt.lte(Utc::now()))
+            .one(db)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Policy version").extend())?;
+
+        let acceptance = policy_acceptances::ActiveModel {
+            user_id: Set(user_id),
+            policy_version_id: Set(policy.policy_version_id),
+            ..Default::default()
+        };
+
+        PolicyAcceptancesEntity::insert(acceptance)
+            .on_conflict(
+                OnConflict::columns([
+                    policy_acceptances::Column::UserId,
+                    policy_acceptances::Column::PolicyVersionId,
+                ])
+                .do_nothing()
+                .to_owned(),
+            )
+            .exec_without_returning(db)
+            .await?;
+
+        Ok(format!(
+            "Accepted {} {}",
+            policy.policy_type, policy.version
+        ))
+    }
+
+    #[graphql(guard = "role_guard!(ROLE_ADMIN)")]
+    async fn publish_policy_version(
+        &self,
+        ctx: &Context<'_>,
+        input: RegisterPolicyVersion,
+    ) -> Result<PolicyVersions, async_graphql::Error> {
+        use crate::entity::{policy_versions, prelude::PolicyVersions as PolicyVersionsEntity};
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        let policy = policy_versions::ActiveModel {
+            policy_type: Set(input.policy_type.as_str().to_string()),
+            version: Set(input.version),
+            document_url: Set(input.document_url),
+            published_at: Set(Some(
+                input
+                    .published_at
+                    .unwrap_or_else(|| Utc::now().fixed_offset()),
+            )),
+            tenant_id: Set(current_tenant(ctx).0),
+            ..Default::default()
+        };
+
+        let insert_policy = PolicyVersionsEntity::insert(policy)
+            .exec_with_returning(db)
+            .await?;
+
+        Ok(insert_policy.into())
+    }
+
+    #[graphql(guard = "role_guard!(ROLE_ADMIN)")]
+    async fn delete_policy_version(
+        &self,
+        ctx: &Context<'_>,
+        policy_version_id: i32,
+    ) -> Result<String, async_graphql::Error> {
+        use crate::entity::prelude::{
+            PolicyAcceptances as PolicyAcceptancesEntity, PolicyVersions as PolicyVersionsEntity,
+        };
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        let policy = PolicyVersionsEntity::find_by_id(policy_version_id)
+            .for_tenant(current_tenant(ctx))
+            .one(db)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Policy version").extend())?;
+
+        // acceptances are the audit trail, so only never accepted versions can go
+        if policy
+            .find_related(PolicyAcceptancesEntity)
+            .count(db)
+            .await?
+            > 0
+        {
+            return Err(
+                AppError::invalid("Policy version has already been accepted by users").extend(),
+            );
+        }
+
+        policy.delete(db).await?;
+
+        Ok("Policy version deleted".to_string())
+    }
+}
@@ -1,17 +1,46 @@
 use crate::models::user::AuthUser;
 use crate::{
-    auth::{Auth, RoleGuard, ROLE_CUSTOMER, ROLE_SUPPLIER},
-    graphql::macros::role_guard,
-    models::user::{
-        Customers, LoginUser, RegisterCustomer, RegisterSupplier, RegisterUser, Suppliers, Users,
+    auth::{Auth, AuthenticatedUser, RoleGuard, ROLE_CUSTOMER, ROLE_SUPPLIER},
+    breached_passwords::BreachedPasswordCheck,
+    config::{
+        EmailVerificationPolicy, JwtPolicy, PasswordPolicy, PasswordResetPolicy, RegionConfig,
+        SessionPolicy, StepUpPolicy,
     },
+    customs::normalize_country,
+    error::{AppError, AuthErrorCode},
+    graphql::{complexity, macros::role_guard, schema::ClientIp},
+    models::{
+        activity::{account_activity, ActivityFeed},
+        onboarding::{normalize_iban, onboarding_status, OnboardingStatus},
+        order_und_pagination::Pagination,
+        user::{
+            get_customer_supplier_id, held_roles, issue_auth_user, Customers, LoginUser,
+            RegisterCustomer, RegisterSupplier, RegisterUser, Suppliers, Users,
+        },
+    },
+    notifications::{is_valid_locale, send_templated, TemplateKey},
+    pii::{pii, PiiKind},
+    sessions::{
+        consume_password_reset_token, consume_refresh_token, issue_password_reset_token,
+        revoke_access_token, revoke_refresh_token, revoke_user_sessions,
+    },
+    step_up::{
+        flag_accounts_from_suspicious_ip, issue_login_challenge, record_security_event,
+        requires_step_up, verify_login_challenge, EVENT_LOGIN_FAILED, EVENT_LOGIN_SUCCEEDED,
+        EVENT_PASSWORD_CHANGED, EVENT_PASSWORD_RESET, EVENT_PROFILE_UPDATED, EVENT_ROLE_ADDED,
+        EVENT_STEP_UP_CHALLENGED, EVENT_STEP_UP_PASSED,
+    },
+    tenancy::{current_tenant, TenantScope},
+    vat::{normalize_vat_id, VatIdValidator},
+    verify_mail::mark_email_verified,
 };
-use async_graphql::{Context, Object};
-use chrono::Duration;
+use async_graphql::{Context, ErrorExtensions, Object};
+use chrono::{Duration, Utc};
 use sea_orm::{
-    ActiveEnum, ActiveModelTrait, ActiveValue::Set, ColumnTrait, DatabaseConnection, EntityTrait,
-    QueryFilter,
+    ActiveEnum, ActiveModelTrait, ActiveValue::Set, ColumnTrait, ConnectionTrait,
+    DatabaseConnection, DbBackend, EntityTrait, QueryFilter, Statement,
 };
+use std::sync::Arc;
 
 #[derive(Default)]
 pub struct UsersQuery;
@@ -26,62 +55,95 @@ impl UsersQuery {
         use crate::entity::prelude::Users as UsersEntity;
         let db = ctx.data::<DatabaseConnection>()?;
 
-        let token = ctx
-            .data_opt::<String>()
-            .ok_or("No authorization token found")?;
+        let user_id = AuthenticatedUser::from_ctx(ctx)
+            .map_err(|e| e.extend())?
+            .user_id
+            .parse::<i32>()?;
 
-        let user =
-            UsersEntity::find_by_id(Auth::verify_token(token)?.user_id.parse::<i32>().unwrap())
-                .one(db)
-                .await
-                .map_err(|_| "User not found")?
-                .map(|user| user.into())
-                .unwrap();
+        let user = UsersEntity::find_by_id(user_id)
+            .one(db)
+            .await?
+            .ok_or_else(|| AppError::NotFound("User").extend())?;
 
-        Ok(user)
+        Ok(user.into())
     }
 
     #[graphql(guard = "role_guard!(ROLE_CUSTOMER)")]
     async fn customer_profile(&self, ctx: &Context<'_>) -> Result<Customers, async_graphql::Error> {
         use crate::entity::{customers, prelude::Customers as CustomersEntity};
         let db = ctx.data::<DatabaseConnection>()?;
-        let token = ctx
-            .data_opt::<String>()
-            .ok_or("No authorization token found")?;
 
-        let user_id = Auth::verify_token(token)?.user_id.parse::<i32>()?;
+        let user_id = AuthenticatedUser::from_ctx(ctx)
+            .map_err(|e| e.extend())?
+            .user_id
+            .parse::<i32>()?;
 
         let customer = CustomersEntity::find()
             .filter(customers::Column::UserId.eq(user_id))
             .one(db)
-            .await
-            .map_err(|_| "Customer not found")?
-            .map(|customer| customer.into())
-            .unwrap();
+            .await?
+            .ok_or_else(|| AppError::NotFound("Customer").extend())?;
+
+        Ok(customer.into())
+    }
+
+    // sign ins, password and profile changes, orders and reviews of the account, newest first
+    #[graphql(
+        guard = "role_guard!(ROLE_CUSTOMER)",
+        complexity = "complexity::list(pagination.page_size, child_complexity)"
+    )]
+    async fn my_activity(
+        &self,
+        ctx: &Context<'_>,
+        pagination: Pagination,
+    ) -> Result<ActivityFeed, async_graphql::Error> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let user_id = AuthenticatedUser::from_ctx(ctx)
+            .map_err(|e| e.extend())?
+            .user_id
+            .parse::<i32>()?;
+        let customer_id = get_customer_supplier_id(db, ctx, ROLE_CUSTOMER).await?;
 
-        Ok(customer)
+        account_activity(db, current_tenant(ctx), user_id, customer_id, &pagination)
+            .await
+            .map_err(|e| e.extend())
     }
 
     #[graphql(guard = "role_guard!(ROLE_SUPPLIER)")]
     async fn supplier_profile(&self, ctx: &Context<'_>) -> Result<Suppliers, async_graphql::Error> {
         use crate::entity::{prelude::Suppliers as SuppliersEntity, suppliers};
         let db = ctx.data::<DatabaseConnection>()?;
-        let token = ctx
-            .data_opt::<String>()
-            .ok_or("No authorization token found")?;
+
+        let user_id = AuthenticatedUser::from_ctx(ctx)
+            .map_err(|e| e.extend())?
+            .user_id
+            .parse::<i32>()?;
 
         let supplier = SuppliersEntity::find()
-            .filter(
-                suppliers::Column::UserId
-                    .eq(Auth::verify_token(token)?.user_id.parse::<i32>().unwrap()),
-            )
+            .filter(suppliers::Column::UserId.eq(user_id))
             .one(db)
-            .await
-            .map_err(|e| format!("{}", e))?
-            .map(|supplier| supplier.into())
-            .unwrap();
+            .await?
+            .ok_or_else(|| AppError::NotFound("Supplier").extend())?;
+
+        Ok(supplier.into())
+    }
+
+    // remaining setup steps for a guided supplier onboarding
+    #[graphql(guard = "role_guard!(ROLE_SUPPLIER)")]
+    async fn onboarding_status(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<OnboardingStatus, async_graphql::Error> {
+        use crate::entity::prelude::Suppliers as SuppliersEntity;
+        let db = ctx.data::<DatabaseConnection>()?;
+        let supplier_id = get_customer_supplier_id(db, ctx, ROLE_SUPPLIER).await?;
+
+        let supplier = SuppliersEntity::find_by_id(supplier_id)
+            .one(db)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Supplier").extend())?;
 
-        Ok(supplier)
+        Ok(onboarding_status(db, &supplier).await?)
     }
 }
 
@@ -96,13 +158,15 @@ impl UsersMutation {
 
         Auth::check_email(&input.email)?;
 
+        // addresses are unique per marketplace, the same person may register on each
         if UsersEntity::find()
             .filter(users::Column::Email.eq(&input.email))
+            .for_tenant(current_tenant(ctx))
             .one(ctx.data::<DatabaseConnection>()?)
             .await?
             .is_some()
         {
-            return Err("User already exists".into());
+            return Err(AppError::invalid("User already exists").extend());
         }
 
         let db = ctx.data::<DatabaseConnection>()?;
@@ -110,27 +174,50 @@ impl UsersMutation {
         let role = match input.role.as_str() {
             ROLE_CUSTOMER => UserRole::Customer,
             ROLE_SUPPLIER => UserRole::Supplier,
-            _ => return Err("Invalid role".into()),
+            _ => return Err(AppError::invalid("Invalid role").extend()),
         };
 
-        let password = match Auth::check_password_strength(&input.password) {
-            Ok(_) => Auth::hash_password(&input.password)?,
-            Err(e) => return Err(e.into()),
-        };
+        Auth::enforce_password_policy(
+            &input.password,
+            ctx.data::<PasswordPolicy>()?,
+            ctx.data::<Arc<dyn BreachedPasswordCheck>>()?.as_ref(),
+        )
+        .await
+        .map_err(|e| e.extend())?;
+        let password = Auth::hash_password(&input.password).map_err(|e| e.extend())?;
 
         let user = users::ActiveModel {
             email: Set(input.email),
             password: Set(password),
             role: Set(role),
+            tenant_id: Set(current_tenant(ctx).0),
             ..Default::default()
         };
         let insert_user = UsersEntity::insert(user).exec_with_returning(db).await?;
 
-        Ok(Auth::create_token(
-            insert_user.user_id,
+        // the account works right away, the address stays unverified until the link is opened
+        if let Err(e) = Auth::send_email_verification(
+            db,
+            ctx.data::<redis::Client>()?,
+            ctx.data::<EmailVerificationPolicy>()?,
+            &insert_user,
+        )
+        .await
+        {
+            eprintln!(
+                "Verification email for user {} failed: {}",
+                insert_user.user_id, e
+            );
+        }
+
+        Auth::create_token(
+            ctx.data::<JwtPolicy>()?,
+            insert_user.user_id.into(),
+            insert_user.tenant_id.into(),
             insert_user.role.to_value(),
-            Duration::days(30),
-        )?)
+            Duration::minutes(ctx.data::<SessionPolicy>()?.access_token_minutes),
+        )
+        .map_err(|e| e.extend())
     }
 
     #[graphql(guard = "role_guard!(ROLE_CUSTOMER)")]
@@ -140,16 +227,16 @@ impl UsersMutation {
         input: RegisterCustomer,
     ) -> Result<Customers, async_graphql::Error> {
         use crate::entity::{customers, prelude::Customers as CustomersEntity};
-        let token = ctx
-            .data_opt::<String>()
-            .ok_or("No authorization token found")?;
 
         let db = ctx.data::<DatabaseConnection>()?;
 
         let customer = customers::ActiveModel {
             first_name: Set(input.first_name),
             last_name: Set(input.last_name),
-            user_id: Set(Auth::verify_token(token)?.user_id.parse::<i32>()?),
+            user_id: Set(AuthenticatedUser::from_ctx(ctx)
+                .map_err(|e| e.extend())?
+                .user_id
+                .parse::<i32>()?),
             ..Default::default()
         };
 
@@ -160,6 +247,68 @@ impl UsersMutation {
         Ok(insert_customer.into())
     }
 
+    // business customers register their VAT ID here, passing null removes it again
+    #[graphql(guard = "role_guard!(ROLE_CUSTOMER)")]
+    async fn set_vat_id(
+        &self,
+        ctx: &Context<'_>,
+        vat_id: Option<String>,
+    ) -> Result<Customers, async_graphql::Error> {
+        use crate::entity::{customers, prelude::Customers as CustomersEntity};
+        let db = ctx.data::<DatabaseConnection>()?;
+        let customer_id = get_customer_supplier_id(db, ctx, ROLE_CUSTOMER).await?;
+
+        let (vat_id, validated_at) = match vat_id {
+            Some(vat_id) => {
+                let (country_code, number) = normalize_vat_id(&vat_id).ok_or_else(|| {
+                    AppError::Validation {
+                        message: "VAT ID is not in a recognised format".to_string(),
+                        failed_rules: vec!["VAT_ID_FORMAT".to_string()],
+                    }
+                    .extend()
+                })?;
+
+                let validator = ctx.data::<Arc<dyn VatIdValidator>>()?;
+                if !validator
+                    .is_valid(&country_code, &number)
+                    .await
+                    .map_err(|e| e.extend())?
+                {
+                    return Err(AppError::Validation {
+                        message: "VAT ID was rejected by the validation service".to_string(),
+                        failed_rules: vec!["VAT_ID_INVALID".to_string()],
+                    }
+                    .extend());
+                }
+
+                (
+                    Some(format!("{}{}", country_code, number)),
+                    Some(Utc::now().fixed_offset()),
+                )
+            }
+            None => (None, None),
+        };
+
+        let customer = customers::ActiveModel {
+            customer_id: Set(customer_id),
+            vat_id: Set(vat_id),
+            vat_id_validated_at: Set(validated_at),
+            ..Default::default()
+        };
+        let customer = CustomersEntity::update(customer).exec(db).await?;
+        record_security_event(
+            db,
+            Some(customer.user_id),
+            None,
+            ctx.data_opt::<ClientIp>().map(|ip| ip.0.clone()),
+            EVENT_PROFILE_UPDATED,
+        )
+        .await
+        .map_err(|e| e.extend())?;
+
+        Ok(customer.into())
+    }
+
     #[graphql(guard = "role_guard!(ROLE_SUPPLIER)")]
     async fn register_supplier(
         &self,
@@ -169,14 +318,20 @@ impl UsersMutation {
         use crate::entity::{prelude::Suppliers as SuppliersEntity, suppliers};
 
         let db = ctx.data::<DatabaseConnection>()?;
-        let token = ctx
-            .data_opt::<String>()
-            .ok_or("No authorization token found")?;
 
         let supplier = suppliers::ActiveModel {
-            user_id: Set(Auth::verify_token(token)?.user_id.parse::<i32>()?),
+            user_id: Set(AuthenticatedUser::from_ctx(ctx)
+                .map_err(|e| e.extend())?
+                .user_id
+                .parse::<i32>()?),
             name: Set(input.name),
             contact_phone: Set(input.contact_phone),
+            region: Set(ctx.data::<RegionConfig>()?.effective_region(input.region)),
+            ship_from_country: Set(input
+                .ship_from_country
+                .as_deref()
+                .map(normalize_country)
+                .filter(|country| !country.is_empty())),
             ..Default::default()
         };
 
@@ -187,6 +342,109 @@ impl UsersMutation {
         Ok(insert_supplier.into())
     }
 
+    #[graphql(guard = "role_guard!(ROLE_SUPPLIER)")]
+    async fn update_supplier_profile(
+        &self,
+        ctx: &Context<'_>,
+        input: RegisterSupplier,
+    ) -> Result<Suppliers, async_graphql::Error> {
+        use crate::entity::{prelude::Suppliers as SuppliersEntity, suppliers};
+        let db = ctx.data::<DatabaseConnection>()?;
+        let supplier_id = get_customer_supplier_id(db, ctx, ROLE_SUPPLIER).await?;
+
+        let supplier = SuppliersEntity::find_by_id(supplier_id)
+            .one(db)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Supplier").extend())?;
+
+        let mut supplier: suppliers::ActiveModel = supplier.into();
+        supplier.name = Set(input.name);
+        supplier.contact_phone = Set(input.contact_phone);
+        supplier.region = Set(ctx.data::<RegionConfig>()?.effective_region(input.region));
+        supplier.ship_from_country = Set(input
+            .ship_from_country
+            .as_deref()
+            .map(normalize_country)
+            .filter(|country| !country.is_empty()));
+        Ok(supplier.update(db).await?.into())
+    }
+
+    #[graphql(guard = "role_guard!(ROLE_SUPPLIER)")]
+    async fn set_payout_details(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(directive = pii::apply(PiiKind::Name))] account_holder: String,
+        #[graphql(directive = pii::apply(PiiKind::BankAccount))] iban: String,
+    ) -> Result<Suppliers, async_graphql::Error> {
+        use crate::entity::{prelude::Suppliers as SuppliersEntity, suppliers};
+        let db = ctx.data::<DatabaseConnection>()?;
+        let supplier_id = get_customer_supplier_id(db, ctx, ROLE_SUPPLIER).await?;
+
+        let iban = normalize_iban(&iban).ok_or_else(|| {
+            AppError::Validation {
+                message: "IBAN is not valid".to_string(),
+                failed_rules: vec!["IBAN_CHECKSUM".to_string()],
+            }
+            .extend()
+        })?;
+        if account_holder.trim().is_empty() {
+            return Err(AppError::invalid("Account holder cannot be empty").extend());
+        }
+
+        let supplier = SuppliersEntity::find_by_id(supplier_id)
+            .one(db)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Supplier").extend())?;
+
+        let mut supplier: suppliers::ActiveModel = supplier.into();
+        supplier.payout_account_holder = Set(Some(account_holder.trim().to_string()));
+        supplier.payout_iban = Set(Some(iban));
+        Ok(supplier.update(db).await?.into())
+    }
+
+    // opts in or out of the daily activity digest, the time zone is an IANA name such as "Europe/Berlin"
+    #[graphql(guard = "role_guard!(ROLE_SUPPLIER)")]
+    async fn set_digest_preferences(
+        &self,
+        ctx: &Context<'_>,
+        enabled: bool,
+        timezone: Option<String>,
+    ) -> Result<Suppliers, async_graphql::Error> {
+        use crate::entity::{prelude::Suppliers as SuppliersEntity, suppliers};
+        let db = ctx.data::<DatabaseConnection>()?;
+        let supplier_id = get_customer_supplier_id(db, ctx, ROLE_SUPPLIER).await?;
+
+        let supplier = SuppliersEntity::find_by_id(supplier_id)
+            .one(db)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Supplier").extend())?;
+
+        let mut supplier: suppliers::ActiveModel = supplier.into();
+        supplier.digest_enabled = Set(enabled);
+        if let Some(timezone) = timezone {
+            // postgres does the local time math for the digest, so it decides which names are valid
+            let known = db
+                .query_one(Statement::from_sql_and_values(
+                    DbBackend::Postgres,
+                    "SELECT EXISTS (SELECT 1 FROM pg_timezone_names WHERE name = $1) AS known",
+                    vec![timezone.clone().into()],
+                ))
+                .await?
+                .map(|row| row.try_get::<bool>("", "known"))
+                .transpose()?
+                .unwrap_or(false);
+            if !known {
+                return Err(AppError::Validation {
+                    message: format!("Unknown time zone {}", timezone),
+                    failed_rules: vec!["TIMEZONE".to_string()],
+                }
+                .extend());
+            }
+            supplier.timezone = Set(timezone);
+        }
+        Ok(supplier.update(db).await?.into())
+    }
+
     async fn login(
         &self,
         ctx: &Context<'_>,
@@ -195,77 +453,519 @@ impl UsersMutation {
         use crate::entity::{prelude::Users as UsersEntity, users};
 
         let db = ctx.data::<DatabaseConnection>()?;
+        let step_up_policy = ctx.data::<StepUpPolicy>()?;
+        let ip_address = ctx.data_opt::<ClientIp>().map(|ip| ip.0.clone());
 
-        let user: Users = UsersEntity::find()
+        // accounts only sign in to the marketplace they registered with
+        let user = UsersEntity::find()
             .filter(users::Column::Email.eq(&login_details.email))
+            .for_tenant(current_tenant(ctx))
             .one(db)
-            .await
-            .map_err(|_| "User not found")?
-            .map(|user| user.into())
-            .unwrap();
+            .await?;
 
-        let token = match Auth::verify_password(&login_details.password, &user.password) {
-            Ok(verification_status) => {
-                if verification_status {
-                    Auth::create_token(user.user_id, user.role.clone(), Duration::days(30))?
-                } else {
-                    return Err("Invalid password".into());
+        let verification = match &user {
+            Some(user) => Auth::verify_password(&login_details.password, &user.password),
+            None => Ok(false),
+        };
+
+        record_security_event(
+            db,
+            user.as_ref().map(|user| user.user_id),
+            Some(login_details.email.clone()),
+            ip_address.clone(),
+            if matches!(verification, Ok(true)) {
+                EVENT_LOGIN_SUCCEEDED
+            } else {
+                EVENT_LOGIN_FAILED
+            },
+        )
+        .await
+        .map_err(|e| e.extend())?;
+
+        let suspicious_ip = match &ip_address {
+            Some(ip_address) => flag_accounts_from_suspicious_ip(db, ip_address, step_up_policy)
+                .await
+                .map_err(|e| e.extend())?,
+            None => false,
+        };
+
+        let user = user.ok_or_else(|| AppError::NotFound("User").extend())?;
+        match verification {
+            Ok(true) => {}
+            Ok(false) => {
+                return Err(AppError::Auth {
+                    message: "Invalid password".to_string(),
+                    code: AuthErrorCode::InvalidCredentials,
+                    user_id: None,
                 }
+                .extend())
             }
-            Err(_) => return Err("Password not readable, please reset password".into()),
-        };
+            Err(_) => {
+                return Err(AppError::Internal(
+                    "Password not readable, please reset password".to_string(),
+                )
+                .extend())
+            }
+        }
+        if user.disabled_at.is_some() {
+            return Err(AppError::Auth {
+                message: "Account is disabled".to_string(),
+                code: AuthErrorCode::InsufficientPermissions,
+                user_id: None,
+            }
+            .extend());
+        }
 
-        Ok(AuthUser {
-            user_role: user.role,
-            token,
-        })
+        if suspicious_ip || requires_step_up(&user) {
+            let challenge_id = issue_login_challenge(db, &user, step_up_policy)
+                .await
+                .map_err(|e| e.extend())?;
+            record_security_event(
+                db,
+                Some(user.user_id),
+                Some(user.email),
+                ip_address,
+                EVENT_STEP_UP_CHALLENGED,
+            )
+            .await
+            .map_err(|e| e.extend())?;
+            return Err(AppError::StepUpRequired { challenge_id }.extend());
+        }
+
+        issue_auth_user(
+            db,
+            ctx.data::<redis::Client>()?,
+            ctx.data::<SessionPolicy>()?,
+            ctx.data::<JwtPolicy>()?,
+            &user,
+        )
+        .await
     }
 
-    #[graphql(guard = "role_guard!(ROLE_CUSTOMER, ROLE_SUPPLIER)")]
+    // second step of a login that was answered with STEP_UP_REQUIRED
+    async fn verify_login_code(
+        &self,
+        ctx: &Context<'_>,
+        challenge_id: i32,
+        #[graphql(directive = pii::apply(PiiKind::Secret))] code: String,
+    ) -> Result<AuthUser, async_graphql::Error> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let ip_address = ctx.data_opt::<ClientIp>().map(|ip| ip.0.clone());
+
+        let user = verify_login_challenge(db, challenge_id, &code, ctx.data::<StepUpPolicy>()?)
+            .await
+            .map_err(|e| e.extend())?;
+
+        record_security_event(
+            db,
+            Some(user.user_id),
+            Some(user.email.clone()),
+            ip_address,
+            EVENT_STEP_UP_PASSED,
+        )
+        .await
+        .map_err(|e| e.extend())?;
+
+        issue_auth_user(
+            db,
+            ctx.data::<redis::Client>()?,
+            ctx.data::<SessionPolicy>()?,
+            ctx.data::<JwtPolicy>()?,
+            &user,
+        )
+        .await
+    }
+
+    // a fresh access token for a valid one, kept for clients from before refresh tokens. It lives
+    // only as long as any other access token, so the session can't be stretched past logout
+    #[graphql(
+        guard = "role_guard!(ROLE_CUSTOMER, ROLE_SUPPLIER)",
+        deprecation = "Use refreshSession with the refresh token from login"
+    )]
     async fn refresh_token(&self, ctx: &Context<'_>) -> Result<String, async_graphql::Error> {
-        let token = ctx
-            .data_opt::<String>()
-            .ok_or("No authorization token found")?;
-        Ok(Auth::refresh_token(token)?)
+        use crate::entity::prelude::Users as UsersEntity;
+        let db = ctx.data::<DatabaseConnection>()?;
+        let claims = &ctx
+            .data_opt::<AuthenticatedUser>()
+            .ok_or_else(|| AppError::Unauthenticated.extend())?
+            .0;
+
+        let user = UsersEntity::find_by_id(claims.user_id.parse::<i32>()?)
+            .for_tenant(current_tenant(ctx))
+            .one(db)
+            .await?
+            .ok_or_else(|| AppError::NotFound("User").extend())?;
+        if user.disabled_at.is_some() {
+            return Err(AppError::Auth {
+                message: "Account is disabled".to_string(),
+                code: AuthErrorCode::InsufficientPermissions,
+                user_id: None,
+            }
+            .extend());
+        }
+
+        Auth::create_token_with_roles(
+            ctx.data::<JwtPolicy>()?,
+            user.user_id.into(),
+            user.tenant_id.into(),
+            user.role.to_value(),
+            held_roles(db, &user).await?,
+            Duration::minutes(ctx.data::<SessionPolicy>()?.access_token_minutes),
+        )
+        .map_err(|e| e.extend())
+    }
+
+    // trades a refresh token for a new access token and refresh token, the old refresh token stops working.
+    // Works with an expired access token, so no authorization header is needed
+    async fn refresh_session(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(directive = pii::apply(PiiKind::Secret))] refresh_token: String,
+    ) -> Result<AuthUser, async_graphql::Error> {
+        use crate::entity::prelude::Users as UsersEntity;
+        let db = ctx.data::<DatabaseConnection>()?;
+        let redis = ctx.data::<redis::Client>()?;
+
+        let user_id = consume_refresh_token(redis, &refresh_token)
+            .await
+            .map_err(|e| e.extend())?;
+        let user = UsersEntity::find_by_id(user_id)
+            .for_tenant(current_tenant(ctx))
+            .one(db)
+            .await?
+            .ok_or_else(|| AppError::NotFound("User").extend())?;
+
+        issue_auth_user(
+            db,
+            redis,
+            ctx.data::<SessionPolicy>()?,
+            ctx.data::<JwtPolicy>()?,
+            &user,
+        )
+        .await
+    }
+
+    // ends the session, the refresh token is revoked and so is the access token the request was made with
+    async fn logout(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(directive = pii::apply(PiiKind::Secret))] refresh_token: Option<String>,
+    ) -> Result<String, async_graphql::Error> {
+        let redis = ctx.data::<redis::Client>()?;
+
+        if let Some(refresh_token) = refresh_token {
+            revoke_refresh_token(redis, &refresh_token)
+                .await
+                .map_err(|e| e.extend())?;
+        }
+        if let (Some(token), Some(user)) = (
+            ctx.data_opt::<String>(),
+            ctx.data_opt::<AuthenticatedUser>(),
+        ) {
+            revoke_access_token(redis, token, user.0.exp)
+                .await
+                .map_err(|e| e.extend())?;
+        }
+
+        Ok("Logged out".to_string())
     }
 
     #[graphql(guard = "role_guard!(ROLE_CUSTOMER, ROLE_SUPPLIER)")]
     async fn change_password(
         &self,
         ctx: &Context<'_>,
-        old_password: String,
-        new_password: String,
+        #[graphql(directive = pii::apply(PiiKind::Secret))] old_password: String,
+        #[graphql(directive = pii::apply(PiiKind::Secret))] new_password: String,
     ) -> Result<String, async_graphql::Error> {
         use crate::entity::{prelude::Users as UsersEntity, users};
 
-        let token = ctx
-            .data_opt::<String>()
-            .ok_or("No authorization token found")?;
-        let user_id = Auth::verify_token(token)?.user_id.parse::<i32>()?;
+        let user_id = AuthenticatedUser::from_ctx(ctx)
+            .map_err(|e| e.extend())?
+            .user_id
+            .parse::<i32>()?;
         let db = ctx.data::<DatabaseConnection>()?;
 
         let user = UsersEntity::find_by_id(user_id)
             .one(db)
-            .await
-            .map_err(|_| "User not found")?
-            .unwrap();
+            .await?
+            .ok_or_else(|| AppError::NotFound("User").extend())?;
 
         let user_model: Users = user.clone().into();
 
         match Auth::verify_password(&old_password, &user_model.password) {
             Ok(verification_status) => {
                 if verification_status {
-                    let new_password = Auth::hash_password(&new_password)?;
+                    Auth::enforce_password_policy(
+                        &new_password,
+                        ctx.data::<PasswordPolicy>()?,
+                        ctx.data::<Arc<dyn BreachedPasswordCheck>>()?.as_ref(),
+                    )
+                    .await
+                    .map_err(|e| e.extend())?;
+                    let new_password =
+                        Auth::hash_password(&new_password).map_err(|e| e.extend())?;
                     let mut user: users::ActiveModel = user.into();
                     user.password = Set(new_password);
-                    user.update(db).await?;
+                    let user = user.update(db).await?;
+                    record_security_event(
+                        db,
+                        Some(user.user_id),
+                        Some(user.email),
+                        ctx.data_opt::<ClientIp>().map(|ip| ip.0.clone()),
+                        EVENT_PASSWORD_CHANGED,
+                    )
+                    .await
+                    .map_err(|e| e.extend())?;
                     Ok("Password updated successfully".to_string())
                 } else {
-                    Err("Invalid password".into())
+                    Err(AppError::Auth {
+                        message: "Invalid password".to_string(),
+                        code: AuthErrorCode::InvalidCredentials,
+                        user_id: None,
+                    }
+                    .extend())
                 }
             }
-            Err(_) => Err("Password not readable, please reset password".into()),
+            Err(_) => Err(AppError::Internal(
+                "Password not readable, please reset password".to_string(),
+            )
+            .extend()),
+        }
+    }
+
+    // answers the same whether or not the email belongs to an account, so accounts can't be
+    // discovered through it
+    async fn request_password_reset(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(directive = pii::apply(PiiKind::Email))] email: String,
+    ) -> Result<String, async_graphql::Error> {
+        use crate::entity::{prelude::Users as UsersEntity, users};
+        let db = ctx.data::<DatabaseConnection>()?;
+        let policy = ctx.data::<PasswordResetPolicy>()?;
+
+        let user = UsersEntity::find()
+            .filter(users::Column::Email.eq(email.trim()))
+            .for_tenant(current_tenant(ctx))
+            .one(db)
+            .await?;
+        if let Some(user) = user.filter(|user| user.disabled_at.is_none()) {
+            let token = issue_password_reset_token(
+                ctx.data::<redis::Client>()?,
+                user.user_id,
+                policy.token_minutes,
+            )
+            .await
+            .map_err(|e| e.extend())?;
+            if let Err(e) = send_templated(
+                db,
+                current_tenant(ctx),
+                TemplateKey::PasswordReset,
+                user.email,
+                user.locale.as_deref(),
+                &[
+                    ("reset_url", format!("{}/{}", policy.url, token)),
+                    ("ttl_minutes", policy.token_minutes.to_string()),
+                ],
+            )
+            .await
+            {
+                eprintln!(
+                    "Password reset email for user {} failed: {}",
+                    user.user_id, e
+                );
+            }
         }
+
+        Ok("If the email belongs to an account, a reset link is on its way".to_string())
+    }
+
+    // the link's token is spent only once the new password passes the policy, and every session
+    // of the account ends so whoever knew the old password is signed out
+    async fn reset_password(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(directive = pii::apply(PiiKind::Secret))] token: String,
+        #[graphql(directive = pii::apply(PiiKind::Secret))] new_password: String,
+    ) -> Result<String, async_graphql::Error> {
+        use crate::entity::{prelude::Users as UsersEntity, users};
+        let db = ctx.data::<DatabaseConnection>()?;
+        let redis = ctx.data::<redis::Client>()?;
+
+        Auth::enforce_password_policy(
+            &new_password,
+            ctx.data::<PasswordPolicy>()?,
+            ctx.data::<Arc<dyn BreachedPasswordCheck>>()?.as_ref(),
+        )
+        .await
+        .map_err(|e| e.extend())?;
+        let user_id = consume_password_reset_token(redis, &token)
+            .await
+            .map_err(|e| e.extend())?;
+
+        let user = UsersEntity::find_by_id(user_id)
+            .for_tenant(current_tenant(ctx))
+            .one(db)
+            .await?
+            .ok_or_else(|| AppError::NotFound("User").extend())?;
+        if user.disabled_at.is_some() {
+            return Err(AppError::Auth {
+                message: "Account is disabled".to_string(),
+                code: AuthErrorCode::InsufficientPermissions,
+                user_id: None,
+            }
+            .extend());
+        }
+        let email = user.email.clone();
+        let mut user: users::ActiveModel = user.into();
+        user.password = Set(Auth::hash_password(&new_password).map_err(|e| e.extend())?);
+        user.update(db).await?;
+
+        revoke_user_sessions(redis, ctx.data::<SessionPolicy>()?, user_id)
+            .await
+            .map_err(|e| e.extend())?;
+        record_security_event(
+            db,
+            Some(user_id),
+            Some(email),
+            ctx.data_opt::<ClientIp>().map(|ip| ip.0.clone()),
+            EVENT_PASSWORD_RESET,
+        )
+        .await
+        .map_err(|e| e.extend())?;
+
+        Ok("Password reset, please log in again".to_string())
+    }
+
+    // lets a customer also sell or a supplier also buy, the returned token carries both roles.
+    // The password is asked again, and selling needs a verified email address first.
+    #[graphql(guard = "role_guard!(ROLE_CUSTOMER, ROLE_SUPPLIER)")]
+    async fn add_role(
+        &self,
+        ctx: &Context<'_>,
+        role: String,
+        #[graphql(directive = pii::apply(PiiKind::Secret))] password: String,
+    ) -> Result<AuthUser, async_graphql::Error> {
+        use crate::entity::{
+            prelude::{UserRoles as UserRolesEntity, Users as UsersEntity},
+            sea_orm_active_enums::UserRole,
+            user_roles,
+        };
+        let db = ctx.data::<DatabaseConnection>()?;
+        let user_id = AuthenticatedUser::from_ctx(ctx)
+            .map_err(|e| e.extend())?
+            .user_id
+            .parse::<i32>()?;
+
+        let new_role = match role.as_str() {
+            ROLE_CUSTOMER => UserRole::Customer,
+            ROLE_SUPPLIER => UserRole::Supplier,
+            _ => return Err(AppError::invalid("Invalid role").extend()),
+        };
+
+        let user = UsersEntity::find_by_id(user_id)
+            .one(db)
+            .await?
+            .ok_or_else(|| AppError::NotFound("User").extend())?;
+        match Auth::verify_password(&password, &user.password) {
+            Ok(true) => {}
+            Ok(false) => {
+                return Err(AppError::Auth {
+                    message: "Invalid password".to_string(),
+                    code: AuthErrorCode::InvalidCredentials,
+                    user_id: None,
+                }
+                .extend())
+            }
+            Err(_) => {
+                return Err(AppError::Internal(
+                    "Password not readable, please reset password".to_string(),
+                )
+                .extend())
+            }
+        }
+        if new_role == UserRole::Supplier && !user.email_verified {
+            return Err(AppError::invalid(
+                "Verify your email address before adding the supplier role",
+            )
+            .extend());
+        }
+        if held_roles(db, &user).await?.contains(&role) {
+            return Err(AppError::invalid("Account already has this role").extend());
+        }
+
+        UserRolesEntity::insert(user_roles::ActiveModel {
+            user_id: Set(user_id),
+            role: Set(new_role),
+            ..Default::default()
+        })
+        .exec(db)
+        .await?;
+
+        record_security_event(
+            db,
+            Some(user_id),
+            Some(user.email.clone()),
+            ctx.data_opt::<ClientIp>().map(|ip| ip.0.clone()),
+            EVENT_ROLE_ADDED,
+        )
+        .await
+        .map_err(|e| e.extend())?;
+
+        issue_auth_user(
+            db,
+            ctx.data::<redis::Client>()?,
+            ctx.data::<SessionPolicy>()?,
+            ctx.data::<JwtPolicy>()?,
+            &user,
+        )
+        .await
+    }
+
+    // emails are sent in this locale when a template variant exists, null goes back to the default
+    #[graphql(guard = "role_guard!(ROLE_CUSTOMER, ROLE_SUPPLIER)")]
+    async fn set_locale(
+        &self,
+        ctx: &Context<'_>,
+        locale: Option<String>,
+    ) -> Result<Users, async_graphql::Error> {
+        use crate::entity::{prelude::Users as UsersEntity, users};
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        if locale
+            .as_deref()
+            .is_some_and(|locale| !is_valid_locale(locale))
+        {
+            return Err(AppError::Validation {
+                message: "Locale must look like \"en\" or \"de-AT\"".to_string(),
+                failed_rules: vec!["LOCALE_FORMAT".to_string()],
+            }
+            .extend());
+        }
+
+        let user_id = AuthenticatedUser::from_ctx(ctx)
+            .map_err(|e| e.extend())?
+            .user_id
+            .parse::<i32>()?;
+        let user = UsersEntity::find_by_id(user_id)
+            .one(db)
+            .await?
+            .ok_or_else(|| AppError::NotFound("User").extend())?;
+
+        let mut user: users::ActiveModel = user.into();
+        user.locale = Set(locale);
+        let user = user.update(db).await?;
+        record_security_event(
+            db,
+            Some(user.user_id),
+            Some(user.email.clone()),
+            ctx.data_opt::<ClientIp>().map(|ip| ip.0.clone()),
+            EVENT_PROFILE_UPDATED,
+        )
+        .await
+        .map_err(|e| e.extend())?;
+
+        Ok(user.into())
     }
 
     #[graphql(guard = "role_guard!(ROLE_CUSTOMER, ROLE_SUPPLIER)")]
@@ -274,18 +974,45 @@ impl UsersMutation {
         ctx: &Context<'_>,
     ) -> Result<String, async_graphql::Error> {
         use crate::entity::prelude::Users as UsersEntity;
-        let token = ctx
-            .data_opt::<String>()
-            .ok_or("No authorization token found")?;
 
-        let user_id = Auth::verify_token(token)?.user_id.parse::<i32>()?;
+        let user_id = AuthenticatedUser::from_ctx(ctx)
+            .map_err(|e| e.extend())?
+            .user_id
+            .parse::<i32>()?;
         let db = ctx.data::<DatabaseConnection>()?;
 
-        let user = UsersEntity::find_by_id(user_id).one(db).await?.unwrap();
-        let user_email = user.email;
-        let user_id = user.user_id;
-        let user_role = user.role.to_value();
+        let user = UsersEntity::find_by_id(user_id)
+            .one(db)
+            .await?
+            .ok_or_else(|| AppError::NotFound("User").extend())?;
+        if user.email_verified {
+            return Ok("Email already verified".to_string());
+        }
+
+        Auth::send_email_verification(
+            db,
+            ctx.data::<redis::Client>()?,
+            ctx.data::<EmailVerificationPolicy>()?,
+            &user,
+        )
+        .await
+        .map_err(|e| e.extend())?;
+        Ok("Email verification sent".to_string())
+    }
 
-        Ok(Auth::send_email_verification(user_email, user_id, user_role).await?)
+    // takes the token from the verification email, the same as opening the link
+    async fn verify_email(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(directive = pii::apply(PiiKind::Secret))] token: String,
+    ) -> Result<String, async_graphql::Error> {
+        mark_email_verified(
+            ctx.data::<DatabaseConnection>()?,
+            ctx.data::<redis::Client>()?,
+            &token,
+        )
+        .await
+        .map_err(|e| e.extend())?;
+        Ok("Email verified successfully".to_string())
     }
 }
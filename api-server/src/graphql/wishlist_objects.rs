@@ -0,0 +1,129 @@
+use crate::{
+    auth::{RoleGuard, ROLE_CUSTOMER},
+    error::AppError,
+    graphql::{complexity, macros::role_guard},
+    ids::ProductId,
+    models::{
+        products::{on_sale, Products},
+        user::get_customer_supplier_id,
+    },
+};
+use async_graphql::{Context, ErrorExtensions, Object};
+use chrono::Utc;
+use sea_orm::{
+    sea_query::OnConflict, ActiveValue::Set, ColumnTrait, DatabaseConnection, EntityTrait,
+    QueryFilter, QueryOrder,
+};
+
+#[derive(Default)]
+pub struct WishlistQuery;
+
+#[derive(Default)]
+pub struct WishlistMutation;
+
+async fn wishlist_customer(ctx: &Context<'_>) -> Result<i32, async_graphql::Error> {
+    let db = ctx.data::<DatabaseConnection>()?;
+    get_customer_supplier_id(db, ctx, ROLE_CUSTOMER).await
+}
+
+// newest first, products taken off sale stay saved but aren't listed until they're back
+async fn wishlist_products(
+    db: &DatabaseConnection,
+    customer_id: i32,
+) -> Result<Vec<Products>, async_graphql::Error> {
+    use crate::entity::{
+        prelude::{Products as ProductsEntity, WishlistItems},
+        wishlist_items,
+    };
+
+    Ok(on_sale(ProductsEntity::find())
+        .inner_join(WishlistItems)
+        .filter(wishlist_items::Column::CustomerId.eq(customer_id))
+        .order_by_desc(wishlist_items::Column::AddedAt)
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|product| product.into())
+        .collect())
+}
+
+#[Object]
+impl WishlistQuery {
+    #[graphql(
+        guard = "role_guard!(ROLE_CUSTOMER)",
+        complexity = "complexity::unbounded(child_complexity)"
+    )]
+    async fn wishlist(&self, ctx: &Context<'_>) -> Result<Vec<Products>, async_graphql::Error> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let customer_id = wishlist_customer(ctx).await?;
+
+        wishlist_products(db, customer_id).await
+    }
+}
+
+#[Object]
+impl WishlistMutation {
+    // saving a product that is already on the wishlist keeps it where it was, returns the wishlist
+    #[graphql(
+        guard = "role_guard!(ROLE_CUSTOMER)",
+        complexity = "complexity::unbounded(child_complexity)"
+    )]
+    async fn add_to_wishlist(
+        &self,
+        ctx: &Context<'_>,
+        product_id: ProductId,
+    ) -> Result<Vec<Products>, async_graphql::Error> {
+        use crate::entity::{
+            prelude::{Products as ProductsEntity, WishlistItems},
+            wishlist_items,
+        };
+        let db = ctx.data::<DatabaseConnection>()?;
+        let customer_id = wishlist_customer(ctx).await?;
+
+        on_sale(ProductsEntity::find_by_id(product_id))
+            .one(db)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Product").extend())?;
+
+        WishlistItems::insert(wishlist_items::ActiveModel {
+            customer_id: Set(customer_id),
+            product_id: Set(product_id.0),
+            added_at: Set(Utc::now().fixed_offset()),
+        })
+        .on_conflict(
+            OnConflict::columns([
+                wishlist_items::Column::CustomerId,
+                wishlist_items::Column::ProductId,
+            ])
+            .do_nothing()
+            .to_owned(),
+        )
+        .exec_without_returning(db)
+        .await?;
+
+        wishlist_products(db, customer_id).await
+    }
+
+    // removing a product that isn't on the wishlist is not an error, returns the wishlist
+    #[graphql(
+        guard = "role_guard!(ROLE_CUSTOMER)",
+        complexity = "complexity::unbounded(child_complexity)"
+    )]
+    async fn remove_from_wishlist(
+        &self,
+        ctx: &Context<'_>,
+        product_id: ProductId,
+    ) -> Result<Vec<Products>, async_graphql::Error> {
+        use crate::entity::{prelude::WishlistItems, wishlist_items};
+        let db = ctx.data::<DatabaseConnection>()?;
+        let customer_id = wishlist_customer(ctx).await?;
+
+        WishlistItems::delete_many()
+            .filter(wishlist_items::Column::CustomerId.eq(customer_id))
+            .filter(wishlist_items::Column::ProductId.eq(product_id.0))
+            .exec(db)
+            .await?;
+
+        wishlist_products(db, customer_id).await
+    }
+}
@@ -0,0 +1,131 @@
+use crate::{
+    auth::Auth,
+    config::GuestOrderPolicy,
+    entity::prelude::{Bills, Orders},
+    entity::{bills, orders},
+    error::{AppError, AuthErrorCode},
+};
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use axum::{extract::Path, http::StatusCode, Extension, Json};
+use chrono::{Duration, Utc};
+use hmac::{Hmac, Mac};
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::env;
+
+// how long the lookup link mailed after a guest checkout keeps working
+const ORDER_LINK_TTL_DAYS: i64 = 30;
+
+// handed to the browser once, only the hash is stored on the cart
+pub fn generate_cart_token() -> (String, String) {
+    let mut bytes = [0u8; 24];
+    OsRng.fill_bytes(&mut bytes);
+    let token: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    let hash = Auth::hash_secret(&token);
+    (token, hash)
+}
+
+fn order_link_mac(payload: &str) -> Result<Hmac<Sha256>, AppError> {
+    let secret = env::var("TOKEN_SECRET")
+        .map_err(|_| AppError::Internal("TOKEN_SECRET must be set".to_string()))?;
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .map_err(|e| AppError::Internal(format!("Invalid token secret: {}", e)))?;
+    mac.update(payload.as_bytes());
+    Ok(mac)
+}
+
+// "<order id>.<expiry>.<hex hmac>", enough to read one order without an account
+pub fn sign_order_link(order_id: i32) -> Result<String, AppError> {
+    let payload = format!(
+        "{}.{}",
+        order_id,
+        (Utc::now() + Duration::days(ORDER_LINK_TTL_DAYS)).timestamp()
+    );
+    let signature: String = order_link_mac(&payload)?
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    Ok(format!("{}.{}", payload, signature))
+}
+
+pub fn verify_order_link(token: &str) -> Result<i32, AppError> {
+    let invalid = || AppError::Auth {
+        message: "Invalid or expired order link".to_string(),
+        code: AuthErrorCode::InvalidCredentials,
+        user_id: None,
+    };
+
+    let (payload, signature) = token.rsplit_once('.').ok_or_else(invalid)?;
+    let signature = (0..signature.len())
+        .step_by(2)
+        .map(|index| {
+            signature
+                .get(index..index + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+        })
+        .collect::<Option<Vec<u8>>>()
+        .ok_or_else(invalid)?;
+    order_link_mac(payload)?
+        .verify_slice(&signature)
+        .map_err(|_| invalid())?;
+
+    let (order_id, expires_at) = payload.split_once('.').ok_or_else(invalid)?;
+    if expires_at.parse::<i64>().map_err(|_| invalid())? < Utc::now().timestamp() {
+        return Err(invalid());
+    }
+    order_id.parse::<i32>().map_err(|_| invalid())
+}
+
+pub fn order_link_url(policy: &GuestOrderPolicy, token: &str) -> String {
+    format!("{}/{}", policy.url.trim_end_matches('/'), token)
+}
+
+// GET /guest-orders/:token, the link mailed after a guest checkout
+pub async fn guest_order_lookup(
+    Path(token): Path<String>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> (StatusCode, Json<Value>) {
+    let order_id = match verify_order_link(&token) {
+        Ok(order_id) => order_id,
+        Err(e) => {
+            return (
+                StatusCode::UNAUTHORIZED,
+                Json(json!({ "message": e.to_string() })),
+            )
+        }
+    };
+
+    let order = Orders::find()
+        .filter(orders::Column::OrderId.eq(order_id))
+        .one(&db)
+        .await;
+    let bill = Bills::find()
+        .filter(bills::Column::OrderId.eq(order_id))
+        .one(&db)
+        .await;
+
+    match (order, bill) {
+        (Ok(Some(order)), Ok(bill)) => (
+            StatusCode::OK,
+            Json(json!({
+                "orderId": order.order_id,
+                "status": order.status,
+                "orderDate": order.order_date,
+                "totalAmount": order.total_amount.to_string(),
+                "taxAmount": order.tax_amount.to_string(),
+                "paymentStatus": bill.map(|bill| bill.payment_status),
+            })),
+        ),
+        (Ok(None), _) => (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "message": "Order not found" })),
+        ),
+        (Err(e), _) | (_, Err(e)) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "message": e.to_string() })),
+        ),
+    }
+}
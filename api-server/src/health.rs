@@ -0,0 +1,88 @@
+use crate::config::HealthPolicy;
+use axum::{http::StatusCode, Extension, Json};
+use sea_orm::DatabaseConnection;
+use serde_json::{json, Value};
+use std::{future::Future, time::Duration};
+use tokio::time::{timeout, Instant};
+
+// "up" or "down" with how long the check took, a check that times out is down
+async fn check<F, E>(limit: Duration, probe: F) -> Value
+where
+    F: Future<Output = Result<(), E>>,
+    E: std::fmt::Display,
+{
+    let started = Instant::now();
+    let (status, error) = match timeout(limit, probe).await {
+        Ok(Ok(())) => ("up", None),
+        Ok(Err(e)) => ("down", Some(e.to_string())),
+        Err(_) => (
+            "down",
+            Some(format!("No answer within {} ms", limit.as_millis())),
+        ),
+    };
+    json!({
+        "status": status,
+        "latencyMs": started.elapsed().as_millis() as u64,
+        "error": error,
+    })
+}
+
+async fn dependencies(
+    db: &DatabaseConnection,
+    redis: &redis::Client,
+    policy: &HealthPolicy,
+) -> (bool, Value) {
+    let limit = Duration::from_millis(policy.timeout_ms);
+    let (database, redis) = tokio::join!(
+        check(limit, db.ping()),
+        check(limit, async {
+            let mut connection = redis.get_multiplexed_async_connection().await?;
+            redis::cmd("PING")
+                .query_async::<String>(&mut connection)
+                .await
+                .map(|_| ())
+        }),
+    );
+    let up = [&database, &redis]
+        .iter()
+        .all(|check| check["status"] == "up");
+    (up, json!({ "database": database, "redis": redis }))
+}
+
+// GET /healthz, liveness: answers 200 as long as the process serves requests, the dependency
+// checks are reported but a database or Redis outage shouldn't get the instance restarted
+pub async fn healthz(
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(redis): Extension<redis::Client>,
+    Extension(policy): Extension<HealthPolicy>,
+) -> (StatusCode, Json<Value>) {
+    let (up, checks) = dependencies(&db, &redis, &policy).await;
+    (
+        StatusCode::OK,
+        Json(json!({
+            "status": if up { "ok" } else { "degraded" },
+            "checks": checks,
+        })),
+    )
+}
+
+// GET /readyz, readiness: 503 while the database or Redis can't be reached, so load balancers
+// stop sending traffic until they are back
+pub async fn readyz(
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(redis): Extension<redis::Client>,
+    Extension(policy): Extension<HealthPolicy>,
+) -> (StatusCode, Json<Value>) {
+    let (up, checks) = dependencies(&db, &redis, &policy).await;
+    (
+        if up {
+            StatusCode::OK
+        } else {
+            StatusCode::SERVICE_UNAVAILABLE
+        },
+        Json(json!({
+            "status": if up { "ready" } else { "unavailable" },
+            "checks": checks,
+        })),
+    )
+}
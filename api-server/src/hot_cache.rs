@@ -0,0 +1,184 @@
+use crate::{
+    config::HotCachePolicy,
+    error::AppError,
+    metrics::record_cache_lookup,
+    pubsub::{publish, spawn_subscriber},
+    retry::retry_redis,
+};
+use redis::AsyncCommands;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+const INVALIDATE_CHANNEL: &str = "hot_cache:invalidate";
+const KEY_PREFIX: &str = "hot_cache:";
+
+struct Entry {
+    value: Arc<str>,
+    expires_at: Instant,
+    last_read: u64,
+}
+
+#[derive(Default)]
+struct Entries {
+    entries: HashMap<String, Entry>,
+    // bumped on every read, the entry with the smallest stamp is the least recently read
+    clock: u64,
+}
+
+// a short lived per instance copy of values read on nearly every request, in front of the copy all
+// instances share through Redis. Values are kept as JSON so both tiers hold the same thing
+#[derive(Clone)]
+pub struct HotCache {
+    entries: Arc<Mutex<Entries>>,
+    redis: redis::Client,
+    policy: HotCachePolicy,
+}
+
+impl HotCache {
+    pub fn new(redis: redis::Client, policy: HotCachePolicy) -> Self {
+        Self {
+            entries: Arc::default(),
+            redis,
+            policy,
+        }
+    }
+
+    // drops keys other instances invalidated, the local TTL covers messages lost while disconnected
+    pub fn spawn_invalidation_listener(&self) {
+        let cache = self.clone();
+        spawn_subscriber(self.redis.clone(), INVALIDATE_CHANNEL, move |key| {
+            cache.forget(&key);
+            async {}
+        });
+    }
+
+    fn local(&self, key: &str) -> Option<Arc<str>> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.clock += 1;
+        let clock = entries.clock;
+        match entries.entries.get_mut(key) {
+            Some(entry) if entry.expires_at > Instant::now() => {
+                entry.last_read = clock;
+                Some(entry.value.clone())
+            }
+            Some(_) => {
+                entries.entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn remember(&self, key: &str, value: Arc<str>) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.clock += 1;
+        let clock = entries.clock;
+        if !entries.entries.contains_key(key) && entries.entries.len() >= self.policy.capacity {
+            let least_recent = entries
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_read)
+                .map(|(key, _)| key.clone());
+            if let Some(least_recent) = least_recent {
+                entries.entries.remove(&least_recent);
+            }
+        }
+        entries.entries.insert(
+            key.to_string(),
+            Entry {
+                value,
+                expires_at: Instant::now() + Duration::from_secs(self.policy.local_ttl_secs),
+                last_read: clock,
+            },
+        );
+    }
+
+    fn forget(&self, key: &str) {
+        self.entries.lock().unwrap().entries.remove(key);
+    }
+
+    // this instance's copy, else the shared one, else whatever load returns, which is then kept in both.
+    // Redis being away only costs the shared tier, reads fall through to load
+    pub async fn get_or_load<T, F, Fut>(&self, key: &str, load: F) -> Result<T, AppError>
+    where
+        T: Serialize + DeserializeOwned,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, AppError>>,
+    {
+        self.get_or_load_for(key, self.policy.shared_ttl_secs, load)
+            .await
+    }
+
+    // get_or_load with its own lifetime for the shared copy
+    pub async fn get_or_load_for<T, F, Fut>(
+        &self,
+        key: &str,
+        shared_ttl_secs: u64,
+        load: F,
+    ) -> Result<T, AppError>
+    where
+        T: Serialize + DeserializeOwned,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, AppError>>,
+    {
+        if let Some(value) = self.local(key) {
+            if let Ok(value) = serde_json::from_str(&value) {
+                record_cache_lookup("hot_cache", "local");
+                return Ok(value);
+            }
+        }
+
+        let shared_key = format!("{}{}", KEY_PREFIX, key);
+        let shared: Option<String> = retry_redis("hot_cache_get", || async {
+            let mut connection = self.redis.get_multiplexed_async_connection().await?;
+            connection.get(&shared_key).await
+        })
+        .await
+        .unwrap_or_else(|e| {
+            eprintln!("Hot cache read of {} failed: {}", key, e);
+            None
+        });
+        if let Some(shared) = shared {
+            if let Ok(value) = serde_json::from_str(&shared) {
+                self.remember(key, shared.into());
+                record_cache_lookup("hot_cache", "shared");
+                return Ok(value);
+            }
+        }
+
+        record_cache_lookup("hot_cache", "miss");
+        let value = load().await?;
+        let json = serde_json::to_string(&value)
+            .map_err(|e| AppError::Internal(format!("Failed to cache {}: {}", key, e)))?;
+        if let Err(e) = retry_redis("hot_cache_set", || async {
+            let mut connection = self.redis.get_multiplexed_async_connection().await?;
+            connection
+                .set_ex::<_, _, ()>(&shared_key, &json, shared_ttl_secs)
+                .await
+        })
+        .await
+        {
+            eprintln!("Hot cache write of {} failed: {}", key, e);
+        }
+        self.remember(key, json.into());
+        Ok(value)
+    }
+
+    // drops the key everywhere, the next read goes back to the database
+    pub async fn invalidate(&self, key: &str) -> Result<(), AppError> {
+        self.forget(key);
+        let shared_key = format!("{}{}", KEY_PREFIX, key);
+        retry_redis("hot_cache_invalidate", || async {
+            let mut connection = self.redis.get_multiplexed_async_connection().await?;
+            connection.del::<_, ()>(&shared_key).await
+        })
+        .await
+        .map_err(|e| AppError::Internal(format!("Hot cache invalidation failed: {}", e)))?;
+        publish(&self.redis, INVALIDATE_CHANNEL, key).await
+    }
+}
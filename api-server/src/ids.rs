@@ -0,0 +1,108 @@
+use async_graphql::{
+    parser::types::Field, registry::Registry, ContextSelectionSet, InputType, InputValueError,
+    InputValueResult, OutputType, Positioned, ServerResult, Value, ID,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+use std::{borrow::Cow, fmt};
+
+// wraps a table's i32 key so ids of different tables can't be swapped by accident,
+// still goes over the wire as a plain Int. Scalars of their own (UserId, ProductId, ...) would
+// retype every id argument and field clients already send as Int, which the released schema
+// check reports as breaking, so the type safety stays on the Rust side
+macro_rules! typed_id {
+    ($name:ident) => {
+        #[derive(
+            Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize,
+        )]
+        #[serde(transparent)]
+        pub struct $name(pub i32);
+
+        // registered as Int rather than a scalar of its own, see above
+        impl InputType for $name {
+            type RawValueType = Self;
+
+            fn type_name() -> Cow<'static, str> {
+                <i32 as InputType>::type_name()
+            }
+
+            fn create_type_info(registry: &mut Registry) -> String {
+                <i32 as InputType>::create_type_info(registry)
+            }
+
+            fn parse(value: Option<Value>) -> InputValueResult<Self> {
+                <i32 as InputType>::parse(value)
+                    .map($name)
+                    .map_err(InputValueError::propagate)
+            }
+
+            fn to_value(&self) -> Value {
+                Value::Number(self.0.into())
+            }
+
+            fn as_raw_value(&self) -> Option<&Self::RawValueType> {
+                Some(self)
+            }
+        }
+
+        impl OutputType for $name {
+            fn type_name() -> Cow<'static, str> {
+                <i32 as OutputType>::type_name()
+            }
+
+            fn create_type_info(registry: &mut Registry) -> String {
+                <i32 as OutputType>::create_type_info(registry)
+            }
+
+            async fn resolve(
+                &self,
+                ctx: &ContextSelectionSet<'_>,
+                field: &Positioned<Field>,
+            ) -> ServerResult<Value> {
+                self.0.resolve(ctx, field).await
+            }
+        }
+
+        impl From<i32> for $name {
+            fn from(id: i32) -> Self {
+                $name(id)
+            }
+        }
+
+        impl From<$name> for i32 {
+            fn from(id: $name) -> Self {
+                id.0
+            }
+        }
+
+        impl From<$name> for sea_orm::Value {
+            fn from(id: $name) -> Self {
+                id.0.into()
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                self.0.fmt(f)
+            }
+        }
+    };
+}
+
+typed_id!(UserId);
+typed_id!(ProductId);
+typed_id!(OrderId);
+typed_id!(TenantId);
+
+// Relay global object id, the GraphQL type name and the key base64 encoded so clients treat it as
+// opaque and no two objects of different types share one
+pub fn global_id(type_name: &str, id: i32) -> ID {
+    ID(STANDARD.encode(format!("{}:{}", type_name, id)))
+}
+
+// the type name and key behind a global id, None when it isn't one
+pub fn parse_global_id(id: &ID) -> Option<(String, i32)> {
+    let decoded = String::from_utf8(STANDARD.decode(id.as_str()).ok()?).ok()?;
+    let (type_name, key) = decoded.split_once(':')?;
+    Some((type_name.to_string(), key.parse().ok()?))
+}
@@ -0,0 +1,278 @@
+use crate::{
+    config::UploadPolicy,
+    entity::{
+        image_zip_job_results, image_zip_jobs,
+        prelude::{ImageZipJobResults, ImageZipJobs, Products},
+        products,
+    },
+    error::AppError,
+    images::{attach_product_image, ImageQueue},
+    jobs::JobTable,
+    storage::Storage,
+    uploads::{validate_bytes, UploadKind},
+};
+use sea_orm::{
+    sea_query::{Expr, OnConflict},
+    ActiveValue::Set,
+    ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter,
+};
+use std::{
+    collections::HashMap,
+    io::{Cursor, Read},
+    path::Path,
+    sync::Arc,
+};
+use tokio::sync::mpsc::{self, error::TrySendError};
+
+const ABANDONED_SWEEP_SECONDS: u64 = 60;
+// archives waiting for the worker, each is held in memory until it's unpacked
+const QUEUE_CAPACITY: usize = 8;
+
+const IMAGE_ZIP_JOBS: JobTable = JobTable {
+    table: "image_zip_jobs",
+    id_column: "job_id",
+    label: "Image ZIP job",
+};
+
+pub struct ImageZipTask {
+    pub job_id: i32,
+    pub supplier_id: i32,
+    pub bytes: Vec<u8>,
+}
+
+// like image variants the archive is only held in memory, a ZIP still queued when the
+// server stops has to be uploaded again
+#[derive(Clone)]
+pub struct ImageZipQueue {
+    sender: mpsc::Sender<ImageZipTask>,
+}
+
+impl ImageZipQueue {
+    // a place in the queue, taken before the job is stored so a full queue leaves no job behind
+    pub fn reserve(&self) -> Result<mpsc::Permit<'_, ImageZipTask>, AppError> {
+        self.sender.try_reserve().map_err(|e| match e {
+            TrySendError::Full(()) => AppError::invalid(
+                "Too many ZIPs are being processed, upload it again in a few minutes",
+            ),
+            TrySendError::Closed(()) => {
+                AppError::Internal("Image ZIP worker is not running".to_string())
+            }
+        })
+    }
+}
+
+pub fn spawn_image_zip_worker(
+    db: DatabaseConnection,
+    storage: Arc<dyn Storage>,
+    images: ImageQueue,
+    policy: UploadPolicy,
+) -> ImageZipQueue {
+    let (sender, mut receiver) = mpsc::channel::<ImageZipTask>(QUEUE_CAPACITY);
+
+    // the archive of a job whose worker stopped is gone with it, such jobs can only be failed
+    let sweep_db = db.clone();
+    tokio::spawn(async move {
+        let mut interval =
+            tokio::time::interval(std::time::Duration::from_secs(ABANDONED_SWEEP_SECONDS));
+        loop {
+            interval.tick().await;
+            if let Err(e) = IMAGE_ZIP_JOBS
+                .fail_expired(
+                    &sweep_db,
+                    "The upload was interrupted, upload the ZIP again",
+                )
+                .await
+            {
+                eprintln!("Abandoned image ZIP jobs could not be failed: {}", e);
+            }
+        }
+    });
+
+    tokio::spawn(async move {
+        while let Some(task) = receiver.recv().await {
+            let job_id = task.job_id;
+            let claimed = match IMAGE_ZIP_JOBS.claim(&db, job_id).await {
+                Ok(claimed) => claimed,
+                Err(e) => {
+                    eprintln!("Image ZIP job {} could not be claimed: {}", job_id, e);
+                    continue;
+                }
+            };
+            if !claimed {
+                continue;
+            }
+            let job = run_job(&db, storage.as_ref(), &images, &policy, task);
+            if let Err(e) = IMAGE_ZIP_JOBS.run(&db, job_id, job).await {
+                eprintln!("Image ZIP job {} could not be finished: {}", job_id, e);
+            }
+        }
+    });
+
+    ImageZipQueue { sender }
+}
+
+// one file of the archive, read errors are reported on the file instead of failing the job
+struct ZipEntry {
+    name: String,
+    bytes: Result<Vec<u8>, String>,
+}
+
+// directories and the metadata macOS and dotfiles leave behind aren't images anyone meant to upload
+fn is_skipped(name: &str) -> bool {
+    name.starts_with("__MACOSX/")
+        || Path::new(name)
+            .file_name()
+            .and_then(|file_name| file_name.to_str())
+            .is_none_or(|file_name| file_name.starts_with('.'))
+}
+
+fn unpack(bytes: Vec<u8>, policy: &UploadPolicy) -> Result<Vec<ZipEntry>, AppError> {
+    let mut archive =
+        zip::ZipArchive::new(Cursor::new(bytes)).map_err(|e| AppError::Validation {
+            message: format!("Could not read the ZIP: {}", e),
+            failed_rules: vec!["ZIP_MALFORMED".to_string()],
+        })?;
+
+    let mut entries = Vec::new();
+    // what the files unpack to, headers can claim anything so the bytes read are counted
+    let mut unpacked = 0;
+    for index in 0..archive.len() {
+        let mut file = archive.by_index(index).map_err(|e| {
+            AppError::Internal(format!("Could not read ZIP entry {}: {}", index, e))
+        })?;
+        let name: String = file.name().chars().take(255).collect();
+        if file.is_dir() || is_skipped(&name) {
+            continue;
+        }
+        if entries.len() == policy.max_zip_entries {
+            return Err(AppError::Validation {
+                message: format!("A ZIP may hold at most {} files", policy.max_zip_entries),
+                failed_rules: vec!["ZIP_TOO_MANY_FILES".to_string()],
+            });
+        }
+
+        // the sizes in the headers can lie, read one byte past the limit like direct uploads do
+        let mut bytes = Vec::new();
+        let read = (&mut file)
+            .take(policy.max_image_bytes as u64 + 1)
+            .read_to_end(&mut bytes)
+            .map(|_| bytes)
+            .map_err(|e| format!("Could not unpack {}: {}", name, e));
+        unpacked += read.as_ref().map_or(0, Vec::len);
+        if unpacked > policy.max_zip_unpacked_bytes {
+            return Err(AppError::Validation {
+                message: format!(
+                    "A ZIP may unpack to at most {} bytes",
+                    policy.max_zip_unpacked_bytes
+                ),
+                failed_rules: vec!["ZIP_TOO_LARGE_UNPACKED".to_string()],
+            });
+        }
+        entries.push(ZipEntry { name, bytes: read });
+    }
+
+    Ok(entries)
+}
+
+// the SKU a file is for, its name without folders or extension
+fn sku_of(name: &str) -> Option<String> {
+    Path::new(name)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .map(|stem| stem.trim().to_string())
+        .filter(|stem| !stem.is_empty() && stem.chars().count() <= 64)
+}
+
+async fn run_job(
+    db: &DatabaseConnection,
+    storage: &dyn Storage,
+    images: &ImageQueue,
+    policy: &UploadPolicy,
+    task: ImageZipTask,
+) -> Result<(), AppError> {
+    let unpack_policy = policy.clone();
+    let entries = tokio::task::spawn_blocking(move || unpack(task.bytes, &unpack_policy))
+        .await
+        .map_err(|e| AppError::Internal(format!("Image ZIP worker panicked: {}", e)))??;
+    ImageZipJobs::update_many()
+        .col_expr(
+            image_zip_jobs::Column::TotalFiles,
+            Expr::value(entries.len() as i32),
+        )
+        .filter(image_zip_jobs::Column::JobId.eq(task.job_id))
+        .exec(db)
+        .await?;
+
+    let mut by_sku: HashMap<String, Vec<i32>> = HashMap::new();
+    for product in Products::find()
+        .filter(products::Column::SupplierId.eq(task.supplier_id))
+        .filter(products::Column::Sku.is_not_null())
+        .all(db)
+        .await?
+    {
+        if let Some(sku) = product.sku {
+            by_sku.entry(sku).or_default().push(product.product_id);
+        }
+    }
+
+    for entry in entries {
+        let sku = sku_of(&entry.name);
+        let (outcome, product_id, message) = match (&sku, entry.bytes) {
+            (_, Err(message)) => ("FAILED", None, Some(message)),
+            (None, _) => ("NO_MATCHING_SKU", None, None),
+            (Some(sku), Ok(bytes)) => match by_sku.get(sku).map(Vec::as_slice) {
+                None | Some([]) => ("NO_MATCHING_SKU", None, None),
+                Some([product_id]) => {
+                    match validate_bytes(&entry.name, bytes, UploadKind::Image, policy) {
+                        Err(e) => ("REJECTED", None, Some(e.to_string())),
+                        Ok(upload) => {
+                            match attach_product_image(db, storage, images, *product_id, upload)
+                                .await
+                            {
+                                Ok(_) => ("ATTACHED", Some(*product_id), None),
+                                Err(e) => ("FAILED", None, Some(e.to_string())),
+                            }
+                        }
+                    }
+                }
+                Some(product_ids) => (
+                    "FAILED",
+                    None,
+                    Some(format!("{} products share this SKU", product_ids.len())),
+                ),
+            },
+        };
+
+        // an archive can repeat a path, only the first file under it is reported
+        ImageZipJobResults::insert(image_zip_job_results::ActiveModel {
+            job_id: Set(task.job_id),
+            entry_name: Set(entry.name),
+            sku: Set(sku),
+            product_id: Set(product_id),
+            outcome: Set(outcome.to_string()),
+            message: Set(message),
+        })
+        .on_conflict(
+            OnConflict::columns([
+                image_zip_job_results::Column::JobId,
+                image_zip_job_results::Column::EntryName,
+            ])
+            .do_nothing()
+            .to_owned(),
+        )
+        .exec_without_returning(db)
+        .await?;
+        if outcome == "ATTACHED" {
+            ImageZipJobs::update_many()
+                .col_expr(
+                    image_zip_jobs::Column::AttachedFiles,
+                    Expr::col(image_zip_jobs::Column::AttachedFiles).add(1),
+                )
+                .filter(image_zip_jobs::Column::JobId.eq(task.job_id))
+                .exec(db)
+                .await?;
+        }
+    }
+
+    Ok(())
+}
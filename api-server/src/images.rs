@@ -0,0 +1,297 @@
+use crate::{
+    entity::{
+        prelude::{ProductImageVariants, ProductImages, Products},
+        product_image_variants, product_images, products,
+    },
+    error::AppError,
+    storage::Storage,
+    uploads::ValidatedUpload,
+};
+use async_graphql::Enum;
+use image::{
+    codecs::{jpeg::JpegEncoder, webp::WebPEncoder},
+    imageops::FilterType,
+    ExtendedColorType, ImageEncoder,
+};
+use sea_orm::{
+    sea_query::OnConflict, ActiveModelTrait, ActiveValue::Set, DatabaseConnection, EntityTrait,
+};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+pub enum ImageSize {
+    Thumbnail,
+    Small,
+    Medium,
+    Large,
+}
+
+impl ImageSize {
+    pub const ALL: [ImageSize; 4] = [
+        ImageSize::Thumbnail,
+        ImageSize::Small,
+        ImageSize::Medium,
+        ImageSize::Large,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ImageSize::Thumbnail => "THUMBNAIL",
+            ImageSize::Small => "SMALL",
+            ImageSize::Medium => "MEDIUM",
+            ImageSize::Large => "LARGE",
+        }
+    }
+
+    // longest edge of the variant in pixels
+    pub fn max_edge(&self) -> u32 {
+        match self {
+            ImageSize::Thumbnail => 150,
+            ImageSize::Small => 400,
+            ImageSize::Medium => 800,
+            ImageSize::Large => 1600,
+        }
+    }
+}
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+pub enum ImageFormat {
+    Webp,
+    Jpeg,
+}
+
+impl ImageFormat {
+    pub const ALL: [ImageFormat; 2] = [ImageFormat::Webp, ImageFormat::Jpeg];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ImageFormat::Webp => "WEBP",
+            ImageFormat::Jpeg => "JPEG",
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Webp => "webp",
+            ImageFormat::Jpeg => "jpg",
+        }
+    }
+
+    fn content_type(&self) -> &'static str {
+        match self {
+            ImageFormat::Webp => "image/webp",
+            ImageFormat::Jpeg => "image/jpeg",
+        }
+    }
+}
+
+pub struct ImageJob {
+    // the connection of the upload, so variants of sandbox products stay in the sandbox
+    pub db: DatabaseConnection,
+    pub product_id: i32,
+    pub source_url: String,
+    // storage key of the original, variants are stored next to it
+    pub source_key: String,
+    pub bytes: Vec<u8>,
+}
+
+#[derive(Clone)]
+pub struct ImageQueue {
+    sender: mpsc::UnboundedSender<ImageJob>,
+}
+
+impl ImageQueue {
+    pub fn enqueue(&self, job: ImageJob) -> Result<(), AppError> {
+        self.sender
+            .send(job)
+            .map_err(|_| AppError::Internal("Image worker is not running".to_string()))
+    }
+}
+
+// uploads return as soon as the original is stored, variants are filled in here in the background
+pub fn spawn_image_worker(storage: Arc<dyn Storage>) -> ImageQueue {
+    let (sender, mut receiver) = mpsc::unbounded_channel::<ImageJob>();
+
+    tokio::spawn(async move {
+        while let Some(job) = receiver.recv().await {
+            let source_url = job.source_url.clone();
+            if let Err(e) = process_image(storage.as_ref(), job).await {
+                eprintln!("Image processing failed for {}: {}", source_url, e);
+            }
+        }
+    });
+
+    ImageQueue { sender }
+}
+
+// stores the original, records and lists it on the product and queues the variants
+pub async fn attach_product_image(
+    db: &DatabaseConnection,
+    storage: &dyn Storage,
+    queue: &ImageQueue,
+    product_id: i32,
+    upload: ValidatedUpload,
+) -> Result<products::Model, AppError> {
+    let key = format!("products/{}/{}", product_id, upload.storage_name());
+    let url = storage
+        .put(&key, upload.bytes.clone(), upload.content_type)
+        .await?;
+    // the name is content addressed, uploading the same file again keeps the first record
+    ProductImages::insert(product_images::ActiveModel {
+        product_id: Set(product_id),
+        url: Set(url.clone()),
+        storage_key: Set(key.clone()),
+        content_type: Set(upload.content_type.to_string()),
+        size_bytes: Set(upload.bytes.len().min(i32::MAX as usize) as i32),
+        ..Default::default()
+    })
+    .on_conflict(
+        OnConflict::columns([
+            product_images::Column::ProductId,
+            product_images::Column::Url,
+        ])
+        .do_nothing()
+        .to_owned(),
+    )
+    .exec_without_returning(db)
+    .await?;
+    queue.enqueue(ImageJob {
+        db: db.clone(),
+        product_id,
+        source_url: url.clone(),
+        source_key: key,
+        bytes: upload.bytes,
+    })?;
+
+    let product = Products::find_by_id(product_id)
+        .one(db)
+        .await?
+        .ok_or_else(|| AppError::Internal(format!("Product {} not found", product_id)))?;
+    let mut media_paths = product.media_paths.clone().unwrap_or_default();
+    if !media_paths.contains(&url) {
+        media_paths.push(url);
+    }
+
+    let mut product: products::ActiveModel = product.into();
+    product.media_paths = Set(Some(media_paths));
+    Ok(product.update(db).await?)
+}
+
+struct EncodedVariant {
+    size: ImageSize,
+    format: ImageFormat,
+    width: u32,
+    height: u32,
+    bytes: Vec<u8>,
+}
+
+fn encode_variants(bytes: &[u8]) -> Result<Vec<EncodedVariant>, AppError> {
+    let source = image::load_from_memory(bytes)
+        .map_err(|e| AppError::Internal(format!("Failed to decode image: {}", e)))?;
+
+    let mut variants = Vec::new();
+    for size in ImageSize::ALL {
+        // never upscale, sources smaller than the variant are only transcoded
+        let resized = if source.width().max(source.height()) > size.max_edge() {
+            source.resize(size.max_edge(), size.max_edge(), FilterType::Lanczos3)
+        } else {
+            source.clone()
+        };
+
+        for format in ImageFormat::ALL {
+            let mut buffer = Vec::new();
+            match format {
+                ImageFormat::Webp => {
+                    let rgba = resized.to_rgba8();
+                    WebPEncoder::new_lossless(&mut buffer).write_image(
+                        rgba.as_raw(),
+                        rgba.width(),
+                        rgba.height(),
+                        ExtendedColorType::Rgba8,
+                    )
+                }
+                ImageFormat::Jpeg => {
+                    let rgb = resized.to_rgb8();
+                    JpegEncoder::new_with_quality(&mut buffer, 85).write_image(
+                        rgb.as_raw(),
+                        rgb.width(),
+                        rgb.height(),
+                        ExtendedColorType::Rgb8,
+                    )
+                }
+            }
+            .map_err(|e| AppError::Internal(format!("Failed to encode image: {}", e)))?;
+
+            variants.push(EncodedVariant {
+                size,
+                format,
+                width: resized.width(),
+                height: resized.height(),
+                bytes: buffer,
+            });
+        }
+    }
+
+    Ok(variants)
+}
+
+async fn process_image(storage: &dyn Storage, job: ImageJob) -> Result<(), AppError> {
+    let ImageJob {
+        db,
+        product_id,
+        source_url,
+        source_key,
+        bytes,
+    } = job;
+
+    // resizing is CPU bound, keep it off the async workers
+    let variants = tokio::task::spawn_blocking(move || encode_variants(&bytes))
+        .await
+        .map_err(|e| AppError::Internal(format!("Image worker panicked: {}", e)))??;
+
+    let stem = source_key
+        .rsplit_once('.')
+        .map(|(stem, _)| stem)
+        .unwrap_or(&source_key);
+
+    for variant in variants {
+        let key = format!(
+            "{}_{}.{}",
+            stem,
+            variant.size.as_str().to_lowercase(),
+            variant.format.extension()
+        );
+        let url = storage
+            .put(&key, variant.bytes, variant.format.content_type())
+            .await?;
+
+        ProductImageVariants::insert(product_image_variants::ActiveModel {
+            product_id: Set(product_id),
+            source_url: Set(source_url.clone()),
+            size: Set(variant.size.as_str().to_string()),
+            format: Set(variant.format.as_str().to_string()),
+            url: Set(url),
+            width: Set(variant.width as i32),
+            height: Set(variant.height as i32),
+            ..Default::default()
+        })
+        .on_conflict(
+            OnConflict::columns([
+                product_image_variants::Column::SourceUrl,
+                product_image_variants::Column::Size,
+                product_image_variants::Column::Format,
+            ])
+            .update_columns([
+                product_image_variants::Column::Url,
+                product_image_variants::Column::Width,
+                product_image_variants::Column::Height,
+            ])
+            .to_owned(),
+        )
+        .exec_without_returning(&db)
+        .await?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,255 @@
+use crate::{
+    entity::{
+        integrity_checks::{self, Model as IntegrityChecksModel},
+        integrity_findings,
+        prelude::{
+            IntegrityChecks, IntegrityFindings, ProductImageVariants, ProductImages, Products,
+        },
+        products,
+    },
+    error::AppError,
+    ids::TenantId,
+    jobs::JobTable,
+    storage::Storage,
+};
+use async_graphql::Enum;
+use sea_orm::{
+    ActiveValue::Set, ColumnTrait, DatabaseConnection, DbBackend, EntityTrait, FromQueryResult,
+    QueryFilter, Statement,
+};
+use std::sync::Arc;
+
+const INTEGRITY_POLL_SECONDS: u64 = 30;
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+pub enum IntegrityFindingKind {
+    // on sale without a supplier, or pointing at a supplier that is gone
+    OrphanedProduct,
+    // order item whose order is gone
+    OrphanedOrderItem,
+    // units booked on a day that don't match what the open orders hold for it
+    BookingMismatch,
+    // order without a bill, or billed for another amount than its total
+    BillMismatch,
+    // product image that is no longer in storage
+    DanglingMedia,
+}
+
+impl IntegrityFindingKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IntegrityFindingKind::OrphanedProduct => "ORPHANED_PRODUCT",
+            IntegrityFindingKind::OrphanedOrderItem => "ORPHANED_ORDER_ITEM",
+            IntegrityFindingKind::BookingMismatch => "BOOKING_MISMATCH",
+            IntegrityFindingKind::BillMismatch => "BILL_MISMATCH",
+            IntegrityFindingKind::DanglingMedia => "DANGLING_MEDIA",
+        }
+    }
+}
+
+#[derive(FromQueryResult)]
+struct Finding {
+    row_ref: String,
+    detail: String,
+}
+
+// (kind, table, query) of the checks that run in SQL, each takes the tenant as $1. The foreign
+// keys rule most of these out, they catch rows written while constraints were off or by hand
+const SQL_CHECKS: [(IntegrityFindingKind, &str, &str); 4] = [
+    (
+        IntegrityFindingKind::OrphanedProduct,
+        "products",
+        r#"SELECT p.product_id::text AS row_ref,
+                CASE WHEN p.supplier_id IS NULL THEN 'On sale without a supplier'
+                     ELSE 'Supplier ' || p.supplier_id || ' does not exist' END AS detail
+            FROM products p
+            LEFT JOIN suppliers s ON s.supplier_id = p.supplier_id
+            WHERE p.tenant_id = $1
+              AND s.supplier_id IS NULL
+              AND (p.supplier_id IS NOT NULL OR p.archived_at IS NULL)"#,
+    ),
+    (
+        IntegrityFindingKind::OrphanedOrderItem,
+        "order_items",
+        r#"SELECT oi.order_item_id::text AS row_ref,
+                'Order ' || oi.order_id || ' does not exist' AS detail
+            FROM order_items oi
+            JOIN products p ON p.product_id = oi.product_id
+            LEFT JOIN orders o ON o.order_id = oi.order_id
+            WHERE p.tenant_id = $1 AND o.order_id IS NULL"#,
+    ),
+    (
+        IntegrityFindingKind::BookingMismatch,
+        "product_availability",
+        r#"SELECT pa.product_id || ':' || pa.available_on AS row_ref,
+                pa.booked || ' booked, open orders hold ' || COALESCE(held.quantity, 0) AS detail
+            FROM product_availability pa
+            JOIN products p ON p.product_id = pa.product_id
+            LEFT JOIN (
+                SELECT oi.product_id, oi.booking_date, SUM(oi.quantity) AS quantity
+                FROM order_items oi
+                JOIN orders o ON o.order_id = oi.order_id
+                WHERE oi.booking_date IS NOT NULL AND o.status <> 'CANCELLED'
+                GROUP BY oi.product_id, oi.booking_date
+            ) held ON held.product_id = pa.product_id AND held.booking_date = pa.available_on
+            WHERE p.tenant_id = $1 AND pa.booked <> COALESCE(held.quantity, 0)"#,
+    ),
+    (
+        IntegrityFindingKind::BillMismatch,
+        "orders",
+        r#"SELECT o.order_id::text AS row_ref,
+                CASE WHEN b.bill_id IS NULL THEN 'No bill'
+                     ELSE 'Billed ' || b.total_amount || ', order total ' || o.total_amount END
+                    AS detail
+            FROM orders o
+            LEFT JOIN bills b ON b.order_id = o.order_id
+            WHERE o.tenant_id = $1
+              AND (b.bill_id IS NULL OR b.total_amount <> o.total_amount)"#,
+    ),
+];
+
+// images of the tenant's products, their uploads and resized variants that storage no longer
+// has, URLs pointing elsewhere can't be checked and are left alone
+async fn dangling_media(
+    db: &DatabaseConnection,
+    storage: &dyn Storage,
+    tenant: TenantId,
+) -> Result<Vec<(&'static str, Finding)>, AppError> {
+    let products = Products::find()
+        .filter(products::Column::TenantId.eq(tenant.0))
+        .filter(products::Column::MediaPaths.is_not_null())
+        .all(db)
+        .await?;
+    let images = ProductImages::find()
+        .inner_join(Products)
+        .filter(products::Column::TenantId.eq(tenant.0))
+        .all(db)
+        .await?;
+    let variants = ProductImageVariants::find()
+        .inner_join(Products)
+        .filter(products::Column::TenantId.eq(tenant.0))
+        .all(db)
+        .await?;
+
+    let mut findings = Vec::new();
+    for product in products {
+        for path in product.media_paths.unwrap_or_default() {
+            if storage.exists(&path).await? == Some(false) {
+                findings.push((
+                    "products",
+                    Finding {
+                        row_ref: product.product_id.to_string(),
+                        detail: format!("Media {} is missing", path),
+                    },
+                ));
+            }
+        }
+    }
+    for image in images {
+        if storage.exists(&image.url).await? == Some(false) {
+            findings.push((
+                "product_images",
+                Finding {
+                    row_ref: image.image_id.to_string(),
+                    detail: format!(
+                        "Image {} of product {} is missing",
+                        image.url, image.product_id
+                    ),
+                },
+            ));
+        }
+    }
+    for variant in variants {
+        if storage.exists(&variant.url).await? == Some(false) {
+            findings.push((
+                "product_image_variants",
+                Finding {
+                    row_ref: variant.image_variant_id.to_string(),
+                    detail: format!(
+                        "Variant {} of product {} is missing",
+                        variant.url, variant.product_id
+                    ),
+                },
+            ));
+        }
+    }
+    Ok(findings)
+}
+
+async fn run_checks(
+    db: &DatabaseConnection,
+    storage: &dyn Storage,
+    check: &IntegrityChecksModel,
+) -> Result<i32, AppError> {
+    // a check picked up again after its worker stopped starts over
+    IntegrityFindings::delete_many()
+        .filter(integrity_findings::Column::IntegrityCheckId.eq(check.integrity_check_id))
+        .exec(db)
+        .await?;
+
+    let mut found = Vec::new();
+    for (kind, table, sql) in SQL_CHECKS {
+        let findings = Finding::find_by_statement(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            sql,
+            [check.tenant_id.into()],
+        ))
+        .all(db)
+        .await?;
+        found.extend(findings.into_iter().map(|finding| (kind, table, finding)));
+    }
+    found.extend(
+        dangling_media(db, storage, check.tenant_id.into())
+            .await?
+            .into_iter()
+            .map(|(table, finding)| (IntegrityFindingKind::DanglingMedia, table, finding)),
+    );
+
+    let count = found.len() as i32;
+    // large reports are written in slices, one insert can only carry so many parameters
+    let rows: Vec<integrity_findings::ActiveModel> = found
+        .into_iter()
+        .map(|(kind, table, finding)| integrity_findings::ActiveModel {
+            integrity_check_id: Set(check.integrity_check_id),
+            kind: Set(kind.as_str().to_string()),
+            table_name: Set(table.to_string()),
+            row_ref: Set(finding.row_ref),
+            detail: Set(finding.detail),
+            ..Default::default()
+        })
+        .collect();
+    for slice in rows.chunks(1000) {
+        IntegrityFindings::insert_many(slice.to_vec())
+            .exec_without_returning(db)
+            .await?;
+    }
+    Ok(count)
+}
+
+const INTEGRITY_JOBS: JobTable = JobTable {
+    table: "integrity_checks",
+    id_column: "integrity_check_id",
+    label: "Integrity check",
+};
+
+pub fn spawn_integrity_check_worker(db: DatabaseConnection, storage: Arc<dyn Storage>) {
+    let worker_db = db.clone();
+    INTEGRITY_JOBS.spawn_worker(db, INTEGRITY_POLL_SECONDS, move |integrity_check_id| {
+        let (db, storage) = (worker_db.clone(), storage.clone());
+        async move {
+            let check = IntegrityChecks::find_by_id(integrity_check_id)
+                .one(&db)
+                .await?
+                .ok_or(AppError::NotFound("Integrity check"))?;
+            let count = run_checks(&db, storage.as_ref(), &check).await?;
+            IntegrityChecks::update(integrity_checks::ActiveModel {
+                integrity_check_id: Set(integrity_check_id),
+                finding_count: Set(Some(count)),
+                ..Default::default()
+            })
+            .exec(&db)
+            .await?;
+            Ok(())
+        }
+    });
+}
@@ -0,0 +1,176 @@
+use crate::error::AppError;
+use sea_orm::{ConnectionTrait, DatabaseConnection, DbBackend, Statement};
+use std::future::Future;
+
+// how long a claimed job stays with its worker without a heartbeat, a job whose lease ran out
+// is taken to be abandoned by a worker that stopped or crashed
+const LEASE_SECONDS: f64 = 300.0;
+const HEARTBEAT_SECONDS: u64 = 60;
+
+// a table of background jobs, each with status (PENDING, RUNNING, DONE, FAILED), error,
+// requested_at, finished_at and lease_expires_at columns
+#[derive(Clone, Copy)]
+pub struct JobTable {
+    pub table: &'static str,
+    pub id_column: &'static str,
+    // what the jobs are called in the logs
+    pub label: &'static str,
+}
+
+impl JobTable {
+    // claims the oldest pending job, or a running one whose lease ran out. Workers on other
+    // instances skip the row while it's being claimed instead of waiting for it
+    pub async fn claim_next(&self, db: &DatabaseConnection) -> Result<Option<i32>, AppError> {
+        let row = db
+            .query_one(Statement::from_sql_and_values(
+                DbBackend::Postgres,
+                format!(
+                    "UPDATE {table} SET status = 'RUNNING', \
+                     lease_expires_at = now() + make_interval(secs => $1) \
+                     WHERE {id} = (SELECT {id} FROM {table} \
+                     WHERE status = 'PENDING' OR (status = 'RUNNING' \
+                     AND (lease_expires_at IS NULL OR lease_expires_at < now())) \
+                     ORDER BY requested_at LIMIT 1 FOR UPDATE SKIP LOCKED) \
+                     RETURNING {id} AS job_id",
+                    table = self.table,
+                    id = self.id_column
+                ),
+                vec![LEASE_SECONDS.into()],
+            ))
+            .await?;
+        Ok(row
+            .map(|row| row.try_get::<i32>("", "job_id"))
+            .transpose()?)
+    }
+
+    // claims one particular job, false when it isn't pending anymore
+    pub async fn claim(&self, db: &DatabaseConnection, job_id: i32) -> Result<bool, AppError> {
+        let claimed = db
+            .execute(Statement::from_sql_and_values(
+                DbBackend::Postgres,
+                format!(
+                    "UPDATE {} SET status = 'RUNNING', \
+                     lease_expires_at = now() + make_interval(secs => $1) \
+                     WHERE {} = $2 AND status = 'PENDING'",
+                    self.table, self.id_column
+                ),
+                vec![LEASE_SECONDS.into(), job_id.into()],
+            ))
+            .await?
+            .rows_affected();
+        Ok(claimed == 1)
+    }
+
+    async fn renew_lease(&self, db: &DatabaseConnection, job_id: i32) -> Result<(), AppError> {
+        db.execute(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            format!(
+                "UPDATE {} SET lease_expires_at = now() + make_interval(secs => $1) \
+                 WHERE {} = $2 AND status = 'RUNNING'",
+                self.table, self.id_column
+            ),
+            vec![LEASE_SECONDS.into(), job_id.into()],
+        ))
+        .await?;
+        Ok(())
+    }
+
+    // records how the job ended, what it produced is stored by the job itself
+    pub async fn finish(
+        &self,
+        db: &DatabaseConnection,
+        job_id: i32,
+        result: Result<(), AppError>,
+    ) -> Result<(), AppError> {
+        let (status, error) = match result {
+            Ok(()) => ("DONE", None),
+            Err(e) => ("FAILED", Some(e.to_string())),
+        };
+        db.execute(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            format!(
+                "UPDATE {} SET status = $1, error = $2, finished_at = now(), \
+                 lease_expires_at = NULL WHERE {} = $3",
+                self.table, self.id_column
+            ),
+            vec![status.into(), error.into(), job_id.into()],
+        ))
+        .await?;
+        Ok(())
+    }
+
+    // fails the running jobs whose lease ran out, for jobs that can't be picked up again
+    pub async fn fail_expired(
+        &self,
+        db: &DatabaseConnection,
+        error: &str,
+    ) -> Result<u64, AppError> {
+        Ok(db
+            .execute(Statement::from_sql_and_values(
+                DbBackend::Postgres,
+                format!(
+                    "UPDATE {} SET status = 'FAILED', error = $1, finished_at = now(), \
+                     lease_expires_at = NULL WHERE status = 'RUNNING' \
+                     AND (lease_expires_at IS NULL OR lease_expires_at < now())",
+                    self.table
+                ),
+                vec![error.into()],
+            ))
+            .await?
+            .rows_affected())
+    }
+
+    // runs a claimed job to the end while a heartbeat keeps its lease, then records the outcome
+    pub async fn run<F>(&self, db: &DatabaseConnection, job_id: i32, job: F) -> Result<(), AppError>
+    where
+        F: Future<Output = Result<(), AppError>>,
+    {
+        let jobs = *self;
+        let heartbeat_db = db.clone();
+        let heartbeat = tokio::spawn(async move {
+            let mut interval =
+                tokio::time::interval(std::time::Duration::from_secs(HEARTBEAT_SECONDS));
+            // the first tick completes right away, the claim just set the lease
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                if let Err(e) = jobs.renew_lease(&heartbeat_db, job_id).await {
+                    eprintln!(
+                        "{} {} lease could not be renewed: {}",
+                        jobs.label, job_id, e
+                    );
+                }
+            }
+        });
+        let result = job.await;
+        heartbeat.abort();
+
+        self.finish(db, job_id, result).await
+    }
+
+    // polls for jobs and runs them one after another, run gets the id of the claimed job
+    pub fn spawn_worker<F, Fut>(self, db: DatabaseConnection, poll_seconds: u64, run: F)
+    where
+        F: Fn(i32) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<(), AppError>> + Send,
+    {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(poll_seconds));
+            loop {
+                interval.tick().await;
+                // drain the queue before waiting for the next tick
+                loop {
+                    let result = match self.claim_next(&db).await {
+                        Ok(Some(job_id)) => self.run(&db, job_id, run(job_id)).await,
+                        Ok(None) => break,
+                        Err(e) => Err(e),
+                    };
+                    if let Err(e) = result {
+                        eprintln!("{} failed: {}", self.label, e);
+                        break;
+                    }
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,71 @@
+use crate::{
+    carriers::Carrier, error::AppError, models::fulfillment::CustomsDeclaration, pdf::TextPdf,
+};
+use async_trait::async_trait;
+
+// what a label needs to know about one shipment
+pub struct LabelRequest {
+    pub order_id: i32,
+    pub carrier: Carrier,
+    // known once the order was marked shipped, providers that book the parcel assign their own
+    pub tracking_number: Option<String>,
+    pub ship_from: Vec<String>,
+    pub ship_to: Vec<String>,
+    // set for parcels going abroad, carrier integrations send it along as the electronic customs data
+    pub customs: Option<CustomsDeclaration>,
+}
+
+// where shipping labels come from, a carrier integration books the parcel and hands back the
+// carrier's own PDF
+#[async_trait]
+pub trait LabelProvider: Send + Sync {
+    fn provider(&self) -> &'static str;
+
+    // one PDF per shipment
+    async fn create_label(&self, request: &LabelRequest) -> Result<Vec<u8>, AppError>;
+}
+
+// prints the addresses and tracking number on a plain page, for suppliers who book their parcels
+// with the carrier themselves
+pub struct PrintedLabels;
+
+#[async_trait]
+impl LabelProvider for PrintedLabels {
+    fn provider(&self) -> &'static str {
+        "printed"
+    }
+
+    async fn create_label(&self, request: &LabelRequest) -> Result<Vec<u8>, AppError> {
+        let mut lines = vec![
+            format!("{} SHIPMENT", request.carrier.display_name().to_uppercase()),
+            format!("Order #{}", request.order_id),
+            String::new(),
+            "FROM".to_string(),
+        ];
+        lines.extend(request.ship_from.iter().cloned());
+        lines.push(String::new());
+        lines.push("TO".to_string());
+        lines.extend(request.ship_to.iter().cloned());
+        lines.push(String::new());
+        lines.push(format!(
+            "Tracking: {}",
+            request
+                .tracking_number
+                .as_deref()
+                .unwrap_or("assigned at drop-off")
+        ));
+        if let Some(customs) = &request.customs {
+            lines.push(String::new());
+            lines.push(format!(
+                "CUSTOMS: {} item(s), declared value {} {}, commercial invoice enclosed",
+                customs.items.len(),
+                customs.total_value,
+                customs.currency
+            ));
+        }
+
+        let mut pdf = TextPdf::default();
+        pdf.add_page(lines);
+        Ok(pdf.finish())
+    }
+}
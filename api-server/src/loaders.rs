@@ -0,0 +1,138 @@
+use crate::models::{
+    category_attributes::ProductAttributes,
+    price_history::promotion_reference_prices,
+    products::{Categories, ProductSupplier},
+};
+use async_graphql::dataloader::Loader;
+use sea_orm::prelude::Decimal;
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder};
+use std::collections::{HashMap, HashSet};
+
+// categories by id, every product in a list asking for its category ends up in one query. Neither
+// table has a sandbox copy, so the production pool serves sandbox requests just the same
+pub struct CategoryLoader(pub DatabaseConnection);
+
+impl Loader<i32> for CategoryLoader {
+    type Value = Categories;
+    type Error = async_graphql::Error;
+
+    async fn load(&self, keys: &[i32]) -> Result<HashMap<i32, Categories>, async_graphql::Error> {
+        use crate::entity::{categories, prelude::Categories as CategoriesEntity};
+
+        Ok(CategoriesEntity::find()
+            .filter(categories::Column::CategoryId.is_in(keys.iter().copied()))
+            .all(&self.0)
+            .await?
+            .into_iter()
+            .map(|category| (category.category_id, category.into()))
+            .collect())
+    }
+}
+
+pub struct SupplierLoader(pub DatabaseConnection);
+
+impl Loader<i32> for SupplierLoader {
+    type Value = ProductSupplier;
+    type Error = async_graphql::Error;
+
+    async fn load(
+        &self,
+        keys: &[i32],
+    ) -> Result<HashMap<i32, ProductSupplier>, async_graphql::Error> {
+        use crate::entity::{prelude::Suppliers as SuppliersEntity, suppliers};
+
+        Ok(SuppliersEntity::find()
+            .filter(suppliers::Column::SupplierId.is_in(keys.iter().copied()))
+            .all(&self.0)
+            .await?
+            .into_iter()
+            .map(|supplier| (supplier.supplier_id, supplier.into()))
+            .collect())
+    }
+}
+
+// a product's attributes sorted by name, products without any get an empty list. The table has a
+// sandbox copy, sandbox requests get a loader of their own
+pub struct ProductAttributesLoader(pub DatabaseConnection);
+
+impl Loader<i32> for ProductAttributesLoader {
+    type Value = Vec<ProductAttributes>;
+    type Error = async_graphql::Error;
+
+    async fn load(
+        &self,
+        keys: &[i32],
+    ) -> Result<HashMap<i32, Vec<ProductAttributes>>, async_graphql::Error> {
+        use crate::entity::{
+            prelude::ProductAttributes as ProductAttributesEntity, product_attributes,
+        };
+
+        let mut attributes: HashMap<i32, Vec<ProductAttributes>> =
+            keys.iter().map(|key| (*key, Vec::new())).collect();
+        for attribute in ProductAttributesEntity::find()
+            .filter(product_attributes::Column::ProductId.is_in(keys.iter().copied()))
+            .order_by_asc(product_attributes::Column::Name)
+            .all(&self.0)
+            .await?
+        {
+            attributes
+                .entry(attribute.product_id)
+                .or_default()
+                .push(ProductAttributes {
+                    name: attribute.name,
+                    value: attribute.value,
+                });
+        }
+        Ok(attributes)
+    }
+}
+
+// the reference price wasPrice and discountPercent are worked out against, for products on
+// promotion only. Prices and discounts have sandbox copies, sandbox requests get a loader of their own
+pub struct ReferencePriceLoader(pub DatabaseConnection);
+
+impl Loader<i32> for ReferencePriceLoader {
+    type Value = Decimal;
+    type Error = async_graphql::Error;
+
+    async fn load(&self, keys: &[i32]) -> Result<HashMap<i32, Decimal>, async_graphql::Error> {
+        Ok(promotion_reference_prices(&self.0, keys.to_vec()).await?)
+    }
+}
+
+// whether a signed in user has a product on their wishlist, keyed by (user id, product id) so the
+// loader can stay shared between requests. Users without a customer profile have nothing saved
+pub struct WishlistLoader(pub DatabaseConnection);
+
+impl Loader<(i32, i32)> for WishlistLoader {
+    type Value = bool;
+    type Error = async_graphql::Error;
+
+    async fn load(
+        &self,
+        keys: &[(i32, i32)],
+    ) -> Result<HashMap<(i32, i32), bool>, async_graphql::Error> {
+        use crate::entity::{
+            customers,
+            prelude::{Customers as CustomersEntity, WishlistItems as WishlistItemsEntity},
+            wishlist_items,
+        };
+
+        let saved: HashSet<(i32, i32)> = WishlistItemsEntity::find()
+            .find_also_related(CustomersEntity)
+            .filter(customers::Column::UserId.is_in(keys.iter().map(|(user_id, _)| *user_id)))
+            .filter(
+                wishlist_items::Column::ProductId
+                    .is_in(keys.iter().map(|(_, product_id)| *product_id)),
+            )
+            .all(&self.0)
+            .await?
+            .into_iter()
+            .filter_map(|(item, customer)| {
+                customer.map(|customer| (customer.user_id, item.product_id))
+            })
+            .collect();
+
+        Ok(keys.iter().map(|key| (*key, saved.contains(key))).collect())
+    }
+}
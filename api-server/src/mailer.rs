@@ -0,0 +1,91 @@
+use crate::{config::env_or, error::AppError};
+use async_trait::async_trait;
+use mail_send::mail_builder::MessageBuilder;
+use mail_send::SmtpClientBuilder;
+use std::env;
+use std::sync::LazyLock;
+
+// how mail leaves the server, MAIL_TRANSPORT picks the implementation
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(
+        &self,
+        sender_name: &str,
+        to: String,
+        subject: &str,
+        html_body: String,
+    ) -> Result<(), AppError>;
+}
+
+pub struct SmtpMailer;
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(
+        &self,
+        sender_name: &str,
+        to: String,
+        subject: &str,
+        html_body: String,
+    ) -> Result<(), AppError> {
+        let message = MessageBuilder::new()
+            .from((sender_name, "postmaster@testing.giripriyadarshan.com"))
+            .to(to)
+            .subject(subject)
+            .html_body(html_body);
+
+        // Connect to the SMTP submissions port, upgrade to TLS and
+        // authenticate using the provided credentials.
+        let smtp_username = env::var("SMTP_USERNAME")
+            .map_err(|_| AppError::Internal("SMTP_USERNAME must be set".to_string()))?;
+        let smtp_password = env::var("SMTP_PASSWORD")
+            .map_err(|_| AppError::Internal("SMTP_PASSWORD must be set".to_string()))?;
+        SmtpClientBuilder::new("smtp.mailgun.org", 587)
+            .implicit_tls(false)
+            .credentials((smtp_username.as_str(), smtp_password.as_str()))
+            .connect()
+            .await
+            .map_err(|e| AppError::Internal(format!("Failed to connect to SMTP server: {}", e)))?
+            .send(message)
+            .await
+            .map_err(|e| AppError::Internal(format!("Failed to send email: {}", e)))
+    }
+}
+
+// prints the mail instead of sending it, for running locally without SMTP credentials
+pub struct LogMailer;
+
+#[async_trait]
+impl Mailer for LogMailer {
+    async fn send(
+        &self,
+        sender_name: &str,
+        to: String,
+        subject: &str,
+        html_body: String,
+    ) -> Result<(), AppError> {
+        println!(
+            "Mail from {} to {}: {}\n{}",
+            sender_name, to, subject, html_body
+        );
+        Ok(())
+    }
+}
+
+fn mailer_from_env() -> Box<dyn Mailer> {
+    match env_or("MAIL_TRANSPORT", "smtp".to_string()).as_str() {
+        "log" => Box::new(LogMailer),
+        _ => Box::new(SmtpMailer),
+    }
+}
+
+static MAILER: LazyLock<Box<dyn Mailer>> = LazyLock::new(mailer_from_env);
+
+pub async fn send_mail(
+    sender_name: &str,
+    to: String,
+    subject: &str,
+    html_body: String,
+) -> Result<(), AppError> {
+    MAILER.send(sender_name, to, subject, html_body).await
+}
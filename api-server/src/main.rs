@@ -1,15 +1,88 @@
+// the merged query and mutation roots nest one level per domain, past the default limit
+#![recursion_limit = "256"]
+
+mod accounting;
+mod api_keys;
 mod auth;
+mod breached_passwords;
+mod broker;
+mod bulk_users;
+mod carriers;
+mod cart_expiry;
+mod catalog_cache;
+mod config;
+mod content_filter;
+mod customs;
+mod digest;
+mod domain_events;
 mod entity;
 mod error;
+mod fulfillment;
 mod graphql;
+mod guest;
+mod health;
+mod hot_cache;
+mod ids;
+mod image_zips;
+mod images;
+mod integrity;
+mod jobs;
+mod labels;
+mod loaders;
+mod mailer;
+mod metrics;
 mod models;
+mod money;
+mod newsletter;
+mod notifications;
+mod order_events;
+mod payment_gateway;
+mod payment_webhooks;
+mod pdf;
+mod permissions;
+mod pii;
+mod pubsub;
+mod punchout;
+mod rate_limit;
+mod reassignment;
+mod request_log;
+mod retention;
+mod retry;
+mod sandbox;
+mod search;
+mod sessions;
+mod step_up;
+mod storage;
+mod telemetry;
+mod tenancy;
+mod terms;
+mod uploads;
+mod vat;
 mod verify_mail;
+mod videos;
 
 use crate::error::handle_error;
+use crate::guest::guest_order_lookup;
+use crate::health::{healthz, readyz};
+use crate::metrics::{internal_metrics, metrics, track_requests};
+use crate::newsletter::confirm_newsletter;
+use crate::payment_gateway::{PaymentGateway, StripeGateway};
+use crate::payment_webhooks::receive_payment_webhook;
+use crate::punchout::submit_punchout_order;
+use crate::rate_limit::RateLimiter;
+use crate::storage::{serve_private_file, storage_from_env, LocalStorage};
+use crate::telemetry::init_tracing;
+use crate::uploads::{limit_body, BodyLimits};
 use crate::verify_mail::verify_mail;
 use crate::{
+    config::{
+        CurrencyPolicy, EmailVerificationPolicy, HealthPolicy, JwtPolicy, MetricsPolicy, PiiPolicy,
+        QueryLimits, RegionConfig, TaxPolicy, TelemetryPolicy, UploadPolicy,
+    },
     error::AppError,
-    graphql::schema::{graphiql, graphql_handler},
+    graphql::schema::{graphiql, graphql_handler, graphql_ws_handler},
+    sandbox::SandboxDb,
+    tenancy::TenantDirectory,
 };
 use axum::{
     error_handling::HandleErrorLayer,
@@ -20,20 +93,27 @@ use axum::{
         },
         Method,
     },
-    routing::get,
+    middleware::{from_fn, from_fn_with_state},
+    routing::{get, post},
     BoxError, Extension, Router,
 };
 use dotenv::dotenv;
 use sea_orm::Database;
-use std::env;
+use std::{convert::Infallible, env, net::SocketAddr, sync::Arc};
 use tokio::net::TcpListener;
 use tower::{layer::util::Identity, ServiceBuilder};
-use tower_http::cors::{Any, CorsLayer};
+use tower_http::{
+    cors::{Any, CorsLayer},
+    services::ServeDir,
+    trace::TraceLayer,
+};
 
 #[tokio::main]
 async fn main() -> Result<(), AppError> {
     dotenv().ok();
 
+    let tracer_provider = init_tracing(&TelemetryPolicy::from_env())?;
+
     // Initialize SeaORM
     let database_url = env::var("DATABASE_URL")
         .map_err(|_| AppError::Internal("DATABASE_URL must be set".to_string()))?;
@@ -45,7 +125,32 @@ async fn main() -> Result<(), AppError> {
             context: None,
         })?;
 
-    let schema = graphql::schema::create_schema(db.clone());
+    let sandbox = SandboxDb::connect(&database_url).await?;
+
+    let redis = pubsub::redis_client()?;
+    let pii_policy = PiiPolicy::from_env()?;
+    let storage = storage_from_env()?;
+    let jwt_policy = JwtPolicy::from_env();
+    let payment_gateway = Arc::new(StripeGateway::from_env()?) as Arc<dyn PaymentGateway>;
+
+    let tenants = TenantDirectory::default();
+    tenants.spawn_refresher(db.clone());
+
+    // shared so API keys get a single budget across GraphQL and punchout
+    let rate_limiter = RateLimiter::from_env();
+    rate_limiter.spawn_reloader(db.clone(), redis.clone());
+    rate_limiter.spawn_pruner();
+
+    let schema = graphql::schema::create_schema(
+        db.clone(),
+        sandbox.clone(),
+        redis.clone(),
+        rate_limiter.clone(),
+        pii_policy,
+        jwt_policy.clone(),
+        storage,
+        payment_gateway.clone(),
+    );
     let cors = CorsLayer::new()
         .allow_origin(Any)
         .allow_methods([Method::GET, Method::POST])
@@ -62,34 +167,151 @@ async fn main() -> Result<(), AppError> {
         .layer(HandleErrorLayer::new(handle_error))
         .layer(cors);
 
+    // Stripe is pointed at /webhooks/stripe, /webhooks/payments stays for endpoints set up before
+    let payment_webhook = post(receive_payment_webhook)
+        .layer::<_, BoxError>(Extension(db.clone()))
+        .layer::<_, BoxError>(Extension(payment_gateway))
+        .layer(Identity::new())
+        .layer(middleware_stack.clone());
+
+    let metrics_policy = MetricsPolicy::from_env();
+    let metrics_db = db.clone();
     let app = Router::new()
         .route(
             "/",
             get(graphiql)
                 .post(graphql_handler)
+                .layer::<_, Infallible>(from_fn_with_state(
+                    BodyLimits {
+                        query: QueryLimits::from_env().max_body_bytes,
+                        upload: UploadPolicy::from_env().body_limit(),
+                    },
+                    limit_body,
+                ))
+                .layer::<_, BoxError>(Extension(schema.clone()))
+                .layer::<_, BoxError>(Extension(db.clone()))
+                .layer::<_, BoxError>(Extension(sandbox.clone()))
+                .layer::<_, BoxError>(Extension(rate_limiter.clone()))
+                .layer::<_, BoxError>(Extension(tenants.clone()))
+                .layer::<_, BoxError>(Extension(jwt_policy.clone()))
+                .layer::<_, BoxError>(Extension(redis.clone()))
+                .layer(Identity::new())
+                .layer(middleware_stack.clone()),
+        )
+        .route(
+            "/ws",
+            get(graphql_ws_handler)
                 .layer::<_, BoxError>(Extension(schema))
+                .layer::<_, BoxError>(Extension(tenants))
+                .layer::<_, BoxError>(Extension(redis.clone()))
+                .layer::<_, BoxError>(Extension(jwt_policy.clone()))
+                .layer(Identity::new())
+                .layer(middleware_stack.clone()),
+        )
+        .route(
+            "/healthz",
+            get(healthz)
+                .layer::<_, BoxError>(Extension(db.clone()))
+                .layer::<_, BoxError>(Extension(redis.clone()))
+                .layer::<_, BoxError>(Extension(HealthPolicy::from_env()))
+                .layer(Identity::new())
+                .layer(middleware_stack.clone()),
+        )
+        .route(
+            "/readyz",
+            get(readyz)
+                .layer::<_, BoxError>(Extension(db.clone()))
+                .layer::<_, BoxError>(Extension(redis.clone()))
+                .layer::<_, BoxError>(Extension(HealthPolicy::from_env()))
                 .layer(Identity::new())
                 .layer(middleware_stack.clone()),
         )
+        .route(
+            "/metrics",
+            get(metrics)
+                .layer::<_, BoxError>(Extension(db.clone()))
+                .layer::<_, BoxError>(Extension(metrics_policy.clone()))
+                .layer(Identity::new())
+                .layer(middleware_stack.clone()),
+        )
+        .route(
+            "/guest-orders/:token",
+            get(guest_order_lookup)
+                .layer::<_, BoxError>(Extension(db.clone()))
+                .layer(Identity::new())
+                .layer(middleware_stack.clone()),
+        )
+        .route(
+            "/newsletter/confirm/:token",
+            get(confirm_newsletter)
+                .layer::<_, BoxError>(Extension(db.clone()))
+                .layer(Identity::new())
+                .layer(middleware_stack.clone()),
+        )
+        .route(
+            "/punchout/orders",
+            post(submit_punchout_order)
+                .layer::<_, BoxError>(Extension(db.clone()))
+                .layer::<_, BoxError>(Extension(sandbox))
+                .layer::<_, BoxError>(Extension(rate_limiter))
+                .layer::<_, BoxError>(Extension(RegionConfig::from_env()))
+                .layer::<_, BoxError>(Extension(CurrencyPolicy::from_env()))
+                .layer::<_, BoxError>(Extension(TaxPolicy::from_env()))
+                .layer::<_, BoxError>(Extension(EmailVerificationPolicy::from_env()))
+                .layer::<_, BoxError>(Extension(jwt_policy.clone()))
+                .layer(Identity::new())
+                .layer(middleware_stack.clone()),
+        )
+        .route("/webhooks/payments", payment_webhook.clone())
+        .route("/webhooks/stripe", payment_webhook)
         .route(
             "/verify/:token",
             get(verify_mail)
                 .layer::<_, BoxError>(Extension(db))
+                .layer::<_, BoxError>(Extension(redis))
                 .layer(Identity::new())
                 .layer(middleware_stack),
-        );
+        )
+        // private files, only with a signature handed out by storage.signed_url
+        .route("/files/*key", get(serve_private_file))
+        .route_layer(from_fn(track_requests))
+        // a span per request, the resolvers' and the queries' spans hang off it
+        .route_layer(TraceLayer::new_for_http())
+        .nest_service("/uploads", ServeDir::new(LocalStorage::from_env().root()));
+
+    if let Some(addr) = metrics_policy.internal_addr {
+        let listener = TcpListener::bind(&addr).await.map_err(|e| {
+            AppError::Internal(format!("Failed to bind metrics to {}: {}", addr, e))
+        })?;
+        let internal = Router::new()
+            .route("/metrics", get(internal_metrics))
+            .layer(Extension(metrics_db));
+        tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, internal).await {
+                eprintln!("Metrics server error: {}", e);
+            }
+        });
+        println!("Metrics served at http://{}/metrics", addr);
+    }
 
     let port = env::var("PORT").map_err(|_| AppError::Internal("PORT must be set".to_string()))?;
     println!("GraphQL server running at http://localhost:{}/", port);
 
-    axum::serve(
+    let served = axum::serve(
         TcpListener::bind(format!("0.0.0.0:{}", port))
             .await
             .map_err(|e| AppError::Internal(format!("Failed to bind to port: {}", e)))?,
-        app,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
     )
     .await
-    .map_err(|e| AppError::Internal(format!("Server error: {}", e)))?;
+    .map_err(|e| AppError::Internal(format!("Server error: {}", e)));
+
+    // sends whatever spans are still batched
+    if let Some(provider) = tracer_provider {
+        if let Err(e) = provider.shutdown() {
+            eprintln!("Flushing traces failed: {}", e);
+        }
+    }
 
-    Ok(())
+    served
 }
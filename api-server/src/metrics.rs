@@ -0,0 +1,290 @@
+use crate::{config::MetricsPolicy, retry::retry_counts};
+use async_graphql::{
+    async_trait,
+    extensions::{Extension, ExtensionContext, ExtensionFactory, NextExecute},
+    Response,
+};
+use axum::{
+    extract::{MatchedPath, Request},
+    http::{header::CONTENT_TYPE, HeaderMap, StatusCode},
+    middleware::Next,
+    response::IntoResponse,
+    Extension as AxumExtension,
+};
+use sea_orm::DatabaseConnection;
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{Arc, LazyLock, Mutex},
+    time::Instant,
+};
+
+// upper bounds in seconds, the usual Prometheus defaults
+const BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+// operation names come from clients, names past this many are counted under "other"
+const MAX_OPERATION_NAMES: usize = 500;
+
+#[derive(Default)]
+struct Histogram {
+    // per bucket, not cumulative, summed up when rendered
+    buckets: [u64; BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        if let Some(bucket) = BUCKETS.iter().position(|bound| seconds <= *bound) {
+            self.buckets[bucket] += 1;
+        }
+        self.sum += seconds;
+        self.count += 1;
+    }
+
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        let mut cumulative = 0;
+        for (bound, count) in BUCKETS.iter().zip(self.buckets) {
+            cumulative += count;
+            let _ = writeln!(
+                out,
+                "{}_bucket{{{},le=\"{}\"}} {}",
+                name, labels, bound, cumulative
+            );
+        }
+        let _ = writeln!(
+            out,
+            "{}_bucket{{{},le=\"+Inf\"}} {}",
+            name, labels, self.count
+        );
+        let _ = writeln!(out, "{}_sum{{{}}} {}", name, labels, self.sum);
+        let _ = writeln!(out, "{}_count{{{}}} {}", name, labels, self.count);
+    }
+}
+
+// since startup, exported on /metrics
+#[derive(Default)]
+struct Metrics {
+    // by method, route and status code
+    http_requests: BTreeMap<(String, String, u16), u64>,
+    // by route
+    http_durations: BTreeMap<String, Histogram>,
+    // by operation name and whether the response carried errors
+    graphql_operations: BTreeMap<(String, bool), Histogram>,
+    // by cache and outcome
+    cache_lookups: BTreeMap<(&'static str, &'static str), u64>,
+}
+
+static METRICS: LazyLock<Mutex<Metrics>> = LazyLock::new(Default::default);
+
+fn record(update: impl FnOnce(&mut Metrics)) {
+    if let Ok(mut metrics) = METRICS.lock() {
+        update(&mut metrics);
+    }
+}
+
+// outcome is one of the cache's tiers it was answered from, or "miss"
+pub fn record_cache_lookup(cache: &'static str, outcome: &'static str) {
+    record(|metrics| *metrics.cache_lookups.entry((cache, outcome)).or_default() += 1);
+}
+
+// label values are quoted, so backslashes, quotes and newlines have to be escaped
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+// axum middleware counting and timing every request by the route it matched, so ids in paths
+// don't each get their own series
+pub async fn track_requests(request: Request, next: Next) -> impl IntoResponse {
+    let method = request.method().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+    let started = Instant::now();
+
+    let response = next.run(request).await;
+
+    let seconds = started.elapsed().as_secs_f64();
+    let status = response.status().as_u16();
+    record(|metrics| {
+        *metrics
+            .http_requests
+            .entry((method, route.clone(), status))
+            .or_default() += 1;
+        metrics
+            .http_durations
+            .entry(route)
+            .or_default()
+            .observe(seconds);
+    });
+    response
+}
+
+// times every GraphQL operation under its operation name
+pub struct OperationMetrics;
+
+impl ExtensionFactory for OperationMetrics {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(OperationMetricsExtension)
+    }
+}
+
+struct OperationMetricsExtension;
+
+#[async_trait::async_trait]
+impl Extension for OperationMetricsExtension {
+    async fn execute(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        operation_name: Option<&str>,
+        next: NextExecute<'_>,
+    ) -> Response {
+        let started = Instant::now();
+        let response = next.run(ctx, operation_name).await;
+
+        let seconds = started.elapsed().as_secs_f64();
+        let failed = response.is_err();
+        let name = operation_name.unwrap_or("anonymous");
+        record(|metrics| {
+            let known = metrics
+                .graphql_operations
+                .keys()
+                .any(|(known, _)| known == name);
+            let name = if known || metrics.graphql_operations.len() < MAX_OPERATION_NAMES {
+                name
+            } else {
+                "other"
+            };
+            metrics
+                .graphql_operations
+                .entry((name.to_string(), failed))
+                .or_default()
+                .observe(seconds);
+        });
+        response
+    }
+}
+
+fn render(db: &DatabaseConnection) -> String {
+    let mut out = String::new();
+    let Ok(metrics) = METRICS.lock() else {
+        return out;
+    };
+
+    out.push_str("# HELP http_requests_total HTTP requests by method, route and status\n");
+    out.push_str("# TYPE http_requests_total counter\n");
+    for ((method, route, status), count) in &metrics.http_requests {
+        let _ = writeln!(
+            out,
+            "http_requests_total{{method=\"{}\",route=\"{}\",status=\"{}\"}} {}",
+            method,
+            escape(route),
+            status,
+            count
+        );
+    }
+
+    out.push_str("# HELP http_request_duration_seconds HTTP request latency by route\n");
+    out.push_str("# TYPE http_request_duration_seconds histogram\n");
+    for (route, histogram) in &metrics.http_durations {
+        histogram.render(
+            &mut out,
+            "http_request_duration_seconds",
+            &format!("route=\"{}\"", escape(route)),
+        );
+    }
+
+    out.push_str(
+        "# HELP graphql_operation_duration_seconds GraphQL operation latency by operation name\n",
+    );
+    out.push_str("# TYPE graphql_operation_duration_seconds histogram\n");
+    for ((operation, failed), histogram) in &metrics.graphql_operations {
+        histogram.render(
+            &mut out,
+            "graphql_operation_duration_seconds",
+            &format!(
+                "operation=\"{}\",outcome=\"{}\"",
+                escape(operation),
+                if *failed { "error" } else { "ok" }
+            ),
+        );
+    }
+
+    out.push_str(
+        "# HELP cache_lookups_total Cache lookups by cache and where they were answered\n",
+    );
+    out.push_str("# TYPE cache_lookups_total counter\n");
+    for ((cache, outcome), count) in &metrics.cache_lookups {
+        let _ = writeln!(
+            out,
+            "cache_lookups_total{{cache=\"{}\",outcome=\"{}\"}} {}",
+            cache, outcome, count
+        );
+    }
+    drop(metrics);
+
+    out.push_str("# HELP retries_total Retries of transient failures by operation\n");
+    out.push_str("# TYPE retries_total counter\n");
+    for (operation, counts) in retry_counts() {
+        let _ = writeln!(
+            out,
+            "retries_total{{operation=\"{}\"}} {}",
+            operation, counts.retries
+        );
+    }
+
+    let pool = db.get_postgres_connection_pool();
+    let (size, idle) = (pool.size(), pool.num_idle() as u32);
+    out.push_str("# HELP db_pool_connections Database pool connections by state\n");
+    out.push_str("# TYPE db_pool_connections gauge\n");
+    let _ = writeln!(out, "db_pool_connections{{state=\"idle\"}} {}", idle);
+    let _ = writeln!(
+        out,
+        "db_pool_connections{{state=\"in_use\"}} {}",
+        size.saturating_sub(idle)
+    );
+    out.push_str("# HELP db_pool_max_connections Connections the database pool may open\n");
+    out.push_str("# TYPE db_pool_max_connections gauge\n");
+    let _ = writeln!(
+        out,
+        "db_pool_max_connections {}",
+        pool.options().get_max_connections()
+    );
+
+    out
+}
+
+// GET /metrics on the public port in the Prometheus text format, for scrapers sending METRICS_TOKEN
+// as a bearer token. Without a token configured it isn't served there, see internal_metrics
+pub async fn metrics(
+    AxumExtension(db): AxumExtension<DatabaseConnection>,
+    AxumExtension(policy): AxumExtension<MetricsPolicy>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let Some(token) = policy.token else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    if headers
+        .get("authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|auth| auth.strip_prefix("Bearer "))
+        != Some(token.as_str())
+    {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+
+    internal_metrics(AxumExtension(db)).await.into_response()
+}
+
+// GET /metrics on the METRICS_ADDR listener, which only the private network reaches
+pub async fn internal_metrics(
+    AxumExtension(db): AxumExtension<DatabaseConnection>,
+) -> impl IntoResponse {
+    ([(CONTENT_TYPE, "text/plain; version=0.0.4")], render(&db))
+}
@@ -0,0 +1,58 @@
+use crate::{entity::accounting_exports::Model as AccountingExportsModel, storage::Storage};
+use async_graphql::{ComplexObject, Context, SimpleObject};
+use chrono::Duration;
+use sea_orm::prelude::{Date, DateTimeWithTimeZone};
+use std::sync::Arc;
+
+// how long a download link handed out with an export keeps working
+const EXPORT_URL_MINUTES: i64 = 15;
+
+#[derive(SimpleObject)]
+#[graphql(complex)]
+pub struct AccountingExports {
+    pub accounting_export_id: i32,
+    pub supplier_id: Option<i32>,
+    pub format: String,
+    pub period_start: Date,
+    pub period_end: Date,
+    pub status: String,
+    #[graphql(skip)]
+    pub file_key: Option<String>,
+    pub row_count: Option<i32>,
+    pub error: Option<String>,
+    pub requested_at: DateTimeWithTimeZone,
+    pub finished_at: Option<DateTimeWithTimeZone>,
+}
+
+impl From<AccountingExportsModel> for AccountingExports {
+    fn from(val: AccountingExportsModel) -> AccountingExports {
+        AccountingExports {
+            accounting_export_id: val.accounting_export_id,
+            supplier_id: val.supplier_id,
+            format: val.format,
+            period_start: val.period_start,
+            period_end: val.period_end,
+            status: val.status,
+            file_key: val.file_key,
+            row_count: val.row_count,
+            error: val.error,
+            requested_at: val.requested_at,
+            finished_at: val.finished_at,
+        }
+    }
+}
+
+#[ComplexObject]
+impl AccountingExports {
+    // set once the job is DONE, a signed link that expires after a few minutes, query the
+    // export again for a fresh one
+    async fn file_url(&self, ctx: &Context<'_>) -> Result<Option<String>, async_graphql::Error> {
+        let Some(key) = &self.file_key else {
+            return Ok(None);
+        };
+        let storage = ctx.data::<Arc<dyn Storage>>()?;
+        Ok(Some(
+            storage.signed_url(key, Duration::minutes(EXPORT_URL_MINUTES))?,
+        ))
+    }
+}
@@ -0,0 +1,188 @@
+use crate::{
+    domain_events::{ORDER_PLACED, REVIEW_POSTED},
+    error::AppError,
+    ids::TenantId,
+    models::order_und_pagination::{PageInfo, Pagination},
+    step_up::{
+        EVENT_LOGIN_FAILED, EVENT_LOGIN_SUCCEEDED, EVENT_PASSWORD_CHANGED, EVENT_PASSWORD_RESET,
+        EVENT_PROFILE_UPDATED, EVENT_ROLE_ADDED, EVENT_STEP_UP_CHALLENGED, EVENT_STEP_UP_PASSED,
+    },
+};
+use async_graphql::{Enum, SimpleObject};
+use sea_orm::{
+    prelude::DateTimeWithTimeZone, ConnectionTrait, DbBackend, FromQueryResult, JsonValue,
+    Statement,
+};
+
+// the account's events from security_events and its order and review events from domain_events,
+// newest first. Security events are only there for as long as the audit log retention keeps them
+const ACTIVITY_SQL: &str = r#"
+    SELECT event_type, created_at AS occurred_at, NULL::jsonb AS payload
+        FROM security_events
+        WHERE user_id = $1 AND created_at IS NOT NULL
+    UNION ALL
+    SELECT event_type, occurred_at, payload
+        FROM domain_events
+        WHERE tenant_id = $2
+          AND event_type IN ($3, $4)
+          AND payload ->> 'customerId' = $5
+"#;
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+pub enum ActivityKind {
+    Login,
+    FailedLogin,
+    // a one time code asked for or passed, a role added
+    Security,
+    PasswordChange,
+    ProfileChange,
+    Order,
+    Review,
+}
+
+#[derive(SimpleObject)]
+pub struct ActivityEntry {
+    pub kind: ActivityKind,
+    pub occurred_at: DateTimeWithTimeZone,
+    pub summary: String,
+    // the order or review the entry is about
+    pub reference_id: Option<i32>,
+}
+
+#[derive(SimpleObject)]
+pub struct ActivityFeed {
+    pub entries: Vec<ActivityEntry>,
+    pub page_info: PageInfo,
+}
+
+#[derive(FromQueryResult)]
+struct ActivityRow {
+    event_type: String,
+    occurred_at: DateTimeWithTimeZone,
+    payload: Option<JsonValue>,
+}
+
+#[derive(FromQueryResult)]
+struct ActivityCount {
+    total: i64,
+}
+
+impl From<ActivityRow> for ActivityEntry {
+    fn from(row: ActivityRow) -> ActivityEntry {
+        let id = |key: &str| {
+            row.payload
+                .as_ref()
+                .and_then(|payload| payload.get(key))
+                .and_then(|value| value.as_i64())
+                .and_then(|value| i32::try_from(value).ok())
+        };
+        let (kind, summary, reference_id) = match row.event_type.as_str() {
+            EVENT_LOGIN_SUCCEEDED => (ActivityKind::Login, "Signed in".to_string(), None),
+            EVENT_LOGIN_FAILED => (
+                ActivityKind::FailedLogin,
+                "Sign in failed".to_string(),
+                None,
+            ),
+            EVENT_STEP_UP_CHALLENGED => (
+                ActivityKind::Security,
+                "One time code sent".to_string(),
+                None,
+            ),
+            EVENT_STEP_UP_PASSED => (
+                ActivityKind::Security,
+                "One time code accepted".to_string(),
+                None,
+            ),
+            EVENT_ROLE_ADDED => (ActivityKind::Security, "Role added".to_string(), None),
+            EVENT_PASSWORD_CHANGED => (
+                ActivityKind::PasswordChange,
+                "Password changed".to_string(),
+                None,
+            ),
+            EVENT_PASSWORD_RESET => (
+                ActivityKind::PasswordChange,
+                "Password reset".to_string(),
+                None,
+            ),
+            EVENT_PROFILE_UPDATED => (
+                ActivityKind::ProfileChange,
+                "Profile updated".to_string(),
+                None,
+            ),
+            ORDER_PLACED => (
+                ActivityKind::Order,
+                match id("orderId") {
+                    Some(order_id) => format!("Order #{} placed", order_id),
+                    None => "Order placed".to_string(),
+                },
+                id("orderId"),
+            ),
+            REVIEW_POSTED => (
+                ActivityKind::Review,
+                "Review posted".to_string(),
+                id("reviewId"),
+            ),
+            other => (ActivityKind::Security, other.to_string(), None),
+        };
+
+        ActivityEntry {
+            kind,
+            occurred_at: row.occurred_at,
+            summary,
+            reference_id,
+        }
+    }
+}
+
+pub async fn account_activity<C: ConnectionTrait>(
+    db: &C,
+    tenant: TenantId,
+    user_id: i32,
+    customer_id: i32,
+    pagination: &Pagination,
+) -> Result<ActivityFeed, AppError> {
+    let page_size = pagination.page_size.clamp(1, 100);
+    let offset = pagination.page.saturating_sub(1).saturating_mul(page_size);
+    let values = || {
+        vec![
+            user_id.into(),
+            tenant.0.into(),
+            ORDER_PLACED.into(),
+            REVIEW_POSTED.into(),
+            customer_id.to_string().into(),
+        ]
+    };
+
+    let total = ActivityCount::find_by_statement(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        format!("SELECT COUNT(*) AS total FROM ({}) activity", ACTIVITY_SQL),
+        values(),
+    ))
+    .one(db)
+    .await?
+    .map_or(0, |count| count.total.max(0) as u64);
+
+    let mut page_values = values();
+    page_values.extend([(page_size as i64).into(), (offset as i64).into()]);
+    let entries = ActivityRow::find_by_statement(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        format!(
+            "{} ORDER BY occurred_at DESC LIMIT $6 OFFSET $7",
+            ACTIVITY_SQL
+        ),
+        page_values,
+    ))
+    .all(db)
+    .await?
+    .into_iter()
+    .map(|row| row.into())
+    .collect();
+
+    Ok(ActivityFeed {
+        entries,
+        page_info: PageInfo {
+            total_pages: total.div_ceil(page_size),
+            total_items: total,
+        },
+    })
+}
@@ -1,5 +1,6 @@
 use crate::entity::address_types::Model as AddressTypesModel;
 use crate::entity::addresses::{self, Model as AddressModel};
+use crate::pii::{pii, PiiKind};
 use async_graphql::{InputObject, SimpleObject};
 use sea_orm::{ActiveModelTrait, ActiveValue::Set, ColumnTrait, EntityTrait, QueryFilter};
 
@@ -7,12 +8,15 @@ use sea_orm::{ActiveModelTrait, ActiveValue::Set, ColumnTrait, EntityTrait, Quer
 pub struct Addresses {
     address_id: i32,
     address_type_id: Option<i32>,
+    #[graphql(directive = pii::apply(PiiKind::Address))]
     city: String,
     country: String,
     customer_id: i32,
     is_default: Option<bool>,
+    #[graphql(directive = pii::apply(PiiKind::Address))]
     postal_code: String,
     state: Option<String>,
+    #[graphql(directive = pii::apply(PiiKind::Address))]
     street_address: String,
 }
 
@@ -23,7 +27,8 @@ impl From<AddressModel> for Addresses {
             address_type_id: address.address_type_id,
             city: address.city,
             country: address.country,
-            customer_id: address.customer_id,
+            // only listed by customer, addresses entered at guest checkout never get here
+            customer_id: address.customer_id.unwrap_or_default(),
             is_default: address.is_default,
             postal_code: address.postal_code,
             state: address.state,
@@ -35,26 +40,47 @@ impl From<AddressModel> for Addresses {
 #[derive(InputObject)]
 pub struct RegisterAddress {
     pub address_type: String,
+    #[graphql(directive = pii::apply(PiiKind::Address))]
     pub city: String,
     pub country: String,
     pub customer_id: i64,
     pub is_default: bool,
+    #[graphql(directive = pii::apply(PiiKind::Address))]
     pub postal_code: String,
     pub state: String,
+    #[graphql(directive = pii::apply(PiiKind::Address))]
     pub street_address: String,
 }
 
-impl RegisterAddress {
-    pub(crate) fn clone(&self) -> RegisterAddress {
-        RegisterAddress {
-            address_type: self.address_type.clone(),
-            city: self.city.clone(),
-            country: self.country.clone(),
-            customer_id: self.customer_id,
-            is_default: self.is_default,
-            postal_code: self.postal_code.clone(),
-            state: self.state.clone(),
-            street_address: self.street_address.clone(),
+// a customer's own address, always saved to the caller's address book
+#[derive(InputObject)]
+pub struct AddressInput {
+    // the customer's label for it, e.g. "Home" or "Office"
+    pub address_type: String,
+    #[graphql(directive = pii::apply(PiiKind::Address))]
+    pub street_address: String,
+    #[graphql(directive = pii::apply(PiiKind::Address))]
+    pub city: String,
+    pub state: Option<String>,
+    #[graphql(directive = pii::apply(PiiKind::Address))]
+    pub postal_code: String,
+    pub country: String,
+    // a customer's first address becomes the default either way
+    #[graphql(default)]
+    pub is_default: bool,
+}
+
+// the customer ID registerAddress takes is ignored, addresses go to the caller
+impl From<RegisterAddress> for AddressInput {
+    fn from(input: RegisterAddress) -> Self {
+        AddressInput {
+            address_type: input.address_type,
+            street_address: input.street_address,
+            city: input.city,
+            state: Some(input.state),
+            postal_code: input.postal_code,
+            country: input.country,
+            is_default: input.is_default,
         }
     }
 }
@@ -74,37 +100,45 @@ impl From<AddressTypesModel> for AddressType {
     }
 }
 
+#[allow(dead_code)]
 #[derive(InputObject)]
 pub struct RegisterAddressType {
     pub name: String,
 }
 
+pub async fn clear_default_address(
+    customer_id: i32,
+    txn: &sea_orm::DatabaseTransaction,
+) -> Result<(), async_graphql::Error> {
+    let default_address = addresses::Entity::find()
+        .filter(addresses::Column::CustomerId.eq(customer_id))
+        .filter(addresses::Column::IsDefault.eq(true))
+        .one(txn)
+        .await?;
+    if let Some(default_address) = default_address {
+        let mut default_address: addresses::ActiveModel = default_address.into();
+        default_address.is_default = Set(Some(false));
+        default_address.update(txn).await?;
+    }
+    Ok(())
+}
+
 pub async fn create_address(
-    input: RegisterAddress,
+    input: AddressInput,
     customer_id: i32,
     address_type_id: i32,
     txn: &sea_orm::DatabaseTransaction,
 ) -> Result<addresses::ActiveModel, async_graphql::Error> {
-    //check if default address exists and make it not default
     if input.is_default {
-        let default_address = addresses::Entity::find()
-            .filter(addresses::Column::CustomerId.eq(customer_id))
-            .filter(addresses::Column::IsDefault.eq(true))
-            .one(txn)
-            .await?;
-        if default_address.is_some() {
-            let mut default_address: addresses::ActiveModel = default_address.unwrap().into();
-            default_address.is_default = Set(Some(false));
-            default_address.update(txn).await?;
-        }
+        clear_default_address(customer_id, txn).await?;
     }
 
     Ok(addresses::ActiveModel {
-        customer_id: Set(customer_id),
+        customer_id: Set(Some(customer_id)),
         address_type_id: Set(Some(address_type_id)),
         street_address: Set(input.street_address),
         city: Set(input.city),
-        state: Set(Some(input.state)),
+        state: Set(input.state),
         country: Set(input.country),
         postal_code: Set(input.postal_code),
         is_default: Set(Some(input.is_default)),
@@ -0,0 +1,476 @@
+use crate::{
+    domain_events::{PRODUCT_ADDED_TO_CART, PRODUCT_VIEWED},
+    error::AppError,
+    ids::{ProductId, TenantId},
+    models::money::Money,
+    money::currency_rule,
+};
+use async_graphql::{Enum, SimpleObject};
+use chrono::{Datelike, Days, Months, NaiveDate, NaiveDateTime, Utc};
+use sea_orm::{
+    prelude::{DateTimeWithTimeZone, Decimal},
+    sea_query::Expr,
+    ColumnTrait, DatabaseConnection, EntityTrait, JoinType, QueryFilter, QueryOrder, QuerySelect,
+    RelationTrait, Select,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+const TOP_PRODUCTS: u64 = 10;
+const LINE_REVENUE: &str =
+    "order_items.unit_price * order_items.quantity - order_items.discount_amount";
+
+// the days up to and including today
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+pub enum PerformanceWindow {
+    // 7 days
+    Week,
+    // 30 days
+    Month,
+    // 90 days
+    Quarter,
+}
+
+impl PerformanceWindow {
+    pub fn days(&self) -> u64 {
+        match self {
+            PerformanceWindow::Week => 7,
+            PerformanceWindow::Month => 30,
+            PerformanceWindow::Quarter => 90,
+        }
+    }
+}
+
+#[derive(SimpleObject, Clone)]
+pub struct ProductPerformanceDay {
+    pub date: NaiveDate,
+    pub views: u64,
+    pub add_to_carts: u64,
+    pub orders: u64,
+    pub units_sold: u64,
+    pub units_returned: u64,
+    // after line discounts, cancelled orders left out
+    pub revenue: f64,
+}
+
+#[derive(SimpleObject)]
+pub struct ProductPerformance {
+    pub product_id: ProductId,
+    pub window: PerformanceWindow,
+    pub views: u64,
+    pub add_to_carts: u64,
+    // add to carts per view
+    pub add_to_cart_rate: f64,
+    pub orders: u64,
+    // orders per view
+    pub conversion_rate: f64,
+    pub units_sold: u64,
+    pub units_returned: u64,
+    // units returned per unit sold, returns that weren't rejected
+    pub returns_rate: f64,
+    pub revenue: Money,
+    // oldest first, days without activity included
+    pub days: Vec<ProductPerformanceDay>,
+}
+
+fn rate(count: u64, total: u64) -> f64 {
+    match total {
+        0 => 0.0,
+        total => count as f64 / total as f64,
+    }
+}
+
+// views and add to carts come from analytics_events, orders, revenue and returns from the orders
+// themselves, all counted by the day they happened on (UTC)
+pub async fn product_performance(
+    db: &DatabaseConnection,
+    tenant: TenantId,
+    product_id: ProductId,
+    window: PerformanceWindow,
+    currency: &str,
+    locale: &str,
+) -> Result<ProductPerformance, AppError> {
+    use crate::entity::{
+        analytics_events, order_items, orders,
+        prelude::{AnalyticsEvents, OrderItems, Orders, ReturnRequests},
+        return_requests,
+    };
+
+    let today = Utc::now().date_naive();
+    let first_day = today - Days::new(window.days() - 1);
+    let since = first_day
+        .and_hms_opt(0, 0, 0)
+        .unwrap()
+        .and_utc()
+        .fixed_offset();
+
+    let mut days: BTreeMap<NaiveDate, ProductPerformanceDay> = first_day
+        .iter_days()
+        .take_while(|date| *date <= today)
+        .map(|date| {
+            (
+                date,
+                ProductPerformanceDay {
+                    date,
+                    views: 0,
+                    add_to_carts: 0,
+                    orders: 0,
+                    units_sold: 0,
+                    units_returned: 0,
+                    revenue: 0.0,
+                },
+            )
+        })
+        .collect();
+
+    let events = AnalyticsEvents::find()
+        .filter(analytics_events::Column::TenantId.eq(tenant.0))
+        .filter(analytics_events::Column::EventType.is_in([PRODUCT_VIEWED, PRODUCT_ADDED_TO_CART]))
+        .filter(analytics_events::Column::OccurredAt.gte(since))
+        .filter(Expr::cust_with_values(
+            "properties ->> 'productId' = $1",
+            [product_id.0.to_string()],
+        ))
+        .all(db)
+        .await?;
+    for event in events {
+        if let Some(day) = days.get_mut(&event.occurred_at.date_naive()) {
+            match event.event_type.as_str() {
+                PRODUCT_VIEWED => day.views += 1,
+                _ => day.add_to_carts += 1,
+            }
+        }
+    }
+
+    let mut revenue = Decimal::ZERO;
+    let mut orders_by_day: BTreeMap<NaiveDate, BTreeSet<i32>> = BTreeMap::new();
+    for (item, order) in OrderItems::find()
+        .find_also_related(Orders)
+        .filter(order_items::Column::ProductId.eq(product_id.0))
+        .filter(orders::Column::TenantId.eq(tenant.0))
+        .filter(orders::Column::Status.ne("CANCELLED"))
+        .filter(orders::Column::OrderDate.gte(since))
+        .all(db)
+        .await?
+    {
+        let Some(date) = order
+            .and_then(|order| order.order_date)
+            .map(|date| date.date_naive())
+        else {
+            continue;
+        };
+        let Some(day) = days.get_mut(&date) else {
+            continue;
+        };
+        let line = item.unit_price * Decimal::from(item.quantity) - item.discount_amount;
+        revenue += line;
+        day.units_sold += item.quantity.max(0) as u64;
+        day.revenue += line.to_string().parse::<f64>().unwrap_or_default();
+        orders_by_day.entry(date).or_default().insert(item.order_id);
+    }
+    for (date, order_ids) in orders_by_day {
+        if let Some(day) = days.get_mut(&date) {
+            day.orders = order_ids.len() as u64;
+        }
+    }
+
+    for (request, _) in ReturnRequests::find()
+        .find_also_related(OrderItems)
+        .filter(order_items::Column::ProductId.eq(product_id.0))
+        .filter(return_requests::Column::Status.ne("REJECTED"))
+        .filter(return_requests::Column::RequestedAt.gte(since))
+        .all(db)
+        .await?
+    {
+        if let Some(day) = days.get_mut(&request.requested_at.date_naive()) {
+            day.units_returned += request.quantity.max(0) as u64;
+        }
+    }
+
+    let days: Vec<ProductPerformanceDay> = days.into_values().collect();
+    let total = |count: fn(&ProductPerformanceDay) -> u64| days.iter().map(count).sum::<u64>();
+    let (views, add_to_carts, orders) = (
+        total(|day| day.views),
+        total(|day| day.add_to_carts),
+        total(|day| day.orders),
+    );
+    let (units_sold, units_returned) =
+        (total(|day| day.units_sold), total(|day| day.units_returned));
+
+    Ok(ProductPerformance {
+        product_id,
+        window,
+        views,
+        add_to_carts,
+        add_to_cart_rate: rate(add_to_carts, views),
+        orders,
+        conversion_rate: rate(orders, views),
+        units_sold,
+        units_returned,
+        returns_rate: rate(units_returned, units_sold),
+        revenue: Money::new(revenue, currency, locale),
+        days,
+    })
+}
+
+// one row per day, plain numbers so spreadsheets don't need to parse a locale
+pub fn performance_csv(report: &ProductPerformance, currency: &str) -> Result<Vec<u8>, AppError> {
+    let decimals = currency_rule(currency).decimals as usize;
+    let csv_error = |e: csv::Error| AppError::Internal(format!("Failed to write report: {}", e));
+
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer
+        .write_record([
+            "date",
+            "views",
+            "add_to_carts",
+            "orders",
+            "units_sold",
+            "units_returned",
+            "revenue",
+        ])
+        .map_err(csv_error)?;
+    for day in &report.days {
+        writer
+            .write_record([
+                day.date.to_string(),
+                day.views.to_string(),
+                day.add_to_carts.to_string(),
+                day.orders.to_string(),
+                day.units_sold.to_string(),
+                day.units_returned.to_string(),
+                format!("{:.*}", decimals, day.revenue),
+            ])
+            .map_err(csv_error)?;
+    }
+
+    writer
+        .into_inner()
+        .map_err(|e| AppError::Internal(format!("Failed to write report: {}", e)))
+}
+
+// how supplierAnalytics groups revenue over time, weeks start on Monday, all in UTC
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+pub enum AnalyticsBucket {
+    Day,
+    Week,
+    Month,
+}
+
+impl AnalyticsBucket {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AnalyticsBucket::Day => "day",
+            AnalyticsBucket::Week => "week",
+            AnalyticsBucket::Month => "month",
+        }
+    }
+
+    fn start_of(&self, date: NaiveDate) -> NaiveDate {
+        match self {
+            AnalyticsBucket::Day => date,
+            AnalyticsBucket::Week => date - Days::new(date.weekday().num_days_from_monday() as u64),
+            AnalyticsBucket::Month => date.with_day(1).unwrap_or(date),
+        }
+    }
+
+    fn after(&self, start: NaiveDate) -> NaiveDate {
+        match self {
+            AnalyticsBucket::Day => start + Days::new(1),
+            AnalyticsBucket::Week => start + Days::new(7),
+            AnalyticsBucket::Month => start + Months::new(1),
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct SupplierTopProduct {
+    pub product_id: ProductId,
+    pub name: String,
+    pub units_sold: u64,
+    pub revenue: Money,
+}
+
+#[derive(SimpleObject)]
+pub struct RevenueBucket {
+    // the first day of the bucket, the first and last bucket may be cut short by the window
+    pub starts_on: NaiveDate,
+    pub orders: u64,
+    pub revenue: Money,
+}
+
+#[derive(SimpleObject)]
+pub struct SupplierAnalytics {
+    pub window: PerformanceWindow,
+    pub bucket: AnalyticsBucket,
+    // after line discounts, cancelled orders left out, only the supplier's own lines of an order
+    pub sales_total: Money,
+    pub orders: u64,
+    pub units_sold: u64,
+    pub average_order_value: Money,
+    // by revenue, at most 10
+    pub top_products: Vec<SupplierTopProduct>,
+    // oldest first, buckets without sales included
+    pub revenue_over_time: Vec<RevenueBucket>,
+    // when the numbers were counted, they may be served from the cache for a while
+    pub counted_at: DateTimeWithTimeZone,
+}
+
+// the raw numbers behind SupplierAnalytics, what gets cached
+#[derive(Serialize, Deserialize)]
+pub struct SupplierSales {
+    revenue: Decimal,
+    orders: i64,
+    units_sold: i64,
+    top_products: Vec<(i32, String, i64, Decimal)>,
+    buckets: Vec<(NaiveDate, i64, Decimal)>,
+    counted_at: DateTimeWithTimeZone,
+}
+
+// the supplier's order lines of the tenant in the window, cancelled orders left out
+fn supplier_lines(
+    tenant: TenantId,
+    supplier_id: i32,
+    since: DateTimeWithTimeZone,
+) -> Select<crate::entity::order_items::Entity> {
+    use crate::entity::{order_items, orders, prelude::OrderItems, products};
+
+    OrderItems::find()
+        .select_only()
+        .join(JoinType::InnerJoin, order_items::Relation::Orders.def())
+        .join(JoinType::InnerJoin, order_items::Relation::Products.def())
+        .filter(products::Column::SupplierId.eq(supplier_id))
+        .filter(orders::Column::TenantId.eq(tenant.0))
+        .filter(orders::Column::Status.ne("CANCELLED"))
+        .filter(orders::Column::OrderDate.gte(since))
+}
+
+// totals, best sellers and revenue per bucket, each counted by the database in one query
+pub async fn supplier_sales(
+    db: &DatabaseConnection,
+    tenant: TenantId,
+    supplier_id: i32,
+    window: PerformanceWindow,
+    bucket: AnalyticsBucket,
+) -> Result<SupplierSales, AppError> {
+    use crate::entity::{order_items, products};
+
+    let first_day = Utc::now().date_naive() - Days::new(window.days() - 1);
+    let since = first_day
+        .and_hms_opt(0, 0, 0)
+        .unwrap()
+        .and_utc()
+        .fixed_offset();
+
+    let (revenue, orders, units_sold) = supplier_lines(tenant, supplier_id, since)
+        .column_as(
+            Expr::cust(format!("COALESCE(SUM({}), 0)", LINE_REVENUE)),
+            "revenue",
+        )
+        .column_as(Expr::cust("COUNT(DISTINCT order_items.order_id)"), "orders")
+        .column_as(
+            Expr::cust("COALESCE(SUM(order_items.quantity), 0)::bigint"),
+            "units_sold",
+        )
+        .into_tuple::<(Decimal, i64, i64)>()
+        .one(db)
+        .await?
+        .unwrap_or((Decimal::ZERO, 0, 0));
+
+    let top_products = supplier_lines(tenant, supplier_id, since)
+        .column(order_items::Column::ProductId)
+        .column(products::Column::Name)
+        .column_as(
+            Expr::cust("SUM(order_items.quantity)::bigint"),
+            "units_sold",
+        )
+        .column_as(Expr::cust(format!("SUM({})", LINE_REVENUE)), "revenue")
+        .group_by(order_items::Column::ProductId)
+        .group_by(products::Column::Name)
+        .order_by_desc(Expr::cust("revenue"))
+        .order_by_asc(order_items::Column::ProductId)
+        .limit(TOP_PRODUCTS)
+        .into_tuple::<(i32, String, i64, Decimal)>()
+        .all(db)
+        .await?;
+
+    let bucket_start = format!(
+        "date_trunc('{}', orders.order_date AT TIME ZONE 'UTC')",
+        bucket.as_str()
+    );
+    let by_bucket: BTreeMap<NaiveDate, (i64, Decimal)> = supplier_lines(tenant, supplier_id, since)
+        .column_as(Expr::cust(&bucket_start), "starts_on")
+        .column_as(Expr::cust("COUNT(DISTINCT order_items.order_id)"), "orders")
+        .column_as(Expr::cust(format!("SUM({})", LINE_REVENUE)), "revenue")
+        .group_by(Expr::cust(&bucket_start))
+        .into_tuple::<(NaiveDateTime, i64, Decimal)>()
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|(starts_on, orders, revenue)| (starts_on.date(), (orders, revenue)))
+        .collect();
+
+    let today = Utc::now().date_naive();
+    let mut buckets = Vec::new();
+    let mut start = bucket.start_of(first_day);
+    while start <= today {
+        let (orders, revenue) = by_bucket.get(&start).copied().unwrap_or((0, Decimal::ZERO));
+        buckets.push((start, orders, revenue));
+        start = bucket.after(start);
+    }
+
+    Ok(SupplierSales {
+        revenue,
+        orders,
+        units_sold,
+        top_products,
+        buckets,
+        counted_at: Utc::now().fixed_offset(),
+    })
+}
+
+impl SupplierSales {
+    pub fn into_analytics(
+        self,
+        window: PerformanceWindow,
+        bucket: AnalyticsBucket,
+        currency: &str,
+        locale: &str,
+    ) -> SupplierAnalytics {
+        let average_order_value = match self.orders {
+            0 => Decimal::ZERO,
+            orders => self.revenue / Decimal::from(orders),
+        };
+
+        SupplierAnalytics {
+            window,
+            bucket,
+            sales_total: Money::new(self.revenue, currency, locale),
+            orders: self.orders.max(0) as u64,
+            units_sold: self.units_sold.max(0) as u64,
+            average_order_value: Money::new(average_order_value, currency, locale),
+            top_products: self
+                .top_products
+                .into_iter()
+                .map(
+                    |(product_id, name, units_sold, revenue)| SupplierTopProduct {
+                        product_id: ProductId(product_id),
+                        name,
+                        units_sold: units_sold.max(0) as u64,
+                        revenue: Money::new(revenue, currency, locale),
+                    },
+                )
+                .collect(),
+            revenue_over_time: self
+                .buckets
+                .into_iter()
+                .map(|(starts_on, orders, revenue)| RevenueBucket {
+                    starts_on,
+                    orders: orders.max(0) as u64,
+                    revenue: Money::new(revenue, currency, locale),
+                })
+                .collect(),
+            counted_at: self.counted_at,
+        }
+    }
+}
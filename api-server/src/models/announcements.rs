@@ -0,0 +1,161 @@
+use crate::{
+    auth::{ROLE_ADMIN, ROLE_CUSTOMER, ROLE_SUPPLIER},
+    entity::{
+        announcements, announcements::Model as AnnouncementsModel,
+        prelude::Announcements as AnnouncementsEntity,
+    },
+    ids::TenantId,
+    permissions::CallerPermissions,
+    tenancy::TenantScope,
+};
+use async_graphql::{Enum, InputObject, SimpleObject};
+use chrono::{DateTime, FixedOffset, Utc};
+use sea_orm::{
+    prelude::DateTimeWithTimeZone, sea_query::Condition, ColumnTrait, ConnectionTrait, DbErr,
+    EntityTrait, QueryFilter, QueryOrder,
+};
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+pub enum AnnouncementKind {
+    Info,
+    Maintenance,
+    Sale,
+}
+
+impl AnnouncementKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AnnouncementKind::Info => "INFO",
+            AnnouncementKind::Maintenance => "MAINTENANCE",
+            AnnouncementKind::Sale => "SALE",
+        }
+    }
+}
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+pub enum AnnouncementAudience {
+    All,
+    Customers,
+    Suppliers,
+}
+
+impl AnnouncementAudience {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AnnouncementAudience::All => "ALL",
+            AnnouncementAudience::Customers => "CUSTOMERS",
+            AnnouncementAudience::Suppliers => "SUPPLIERS",
+        }
+    }
+}
+
+#[derive(SimpleObject, Clone)]
+pub struct Announcements {
+    pub announcement_id: i32,
+    pub title: String,
+    pub body: String,
+    pub kind: String,
+    pub audience: String,
+    pub starts_at: DateTimeWithTimeZone,
+    pub ends_at: Option<DateTimeWithTimeZone>,
+    pub created_at: DateTimeWithTimeZone,
+    pub updated_at: DateTimeWithTimeZone,
+}
+
+impl From<AnnouncementsModel> for Announcements {
+    fn from(val: AnnouncementsModel) -> Announcements {
+        Announcements {
+            announcement_id: val.announcement_id,
+            title: val.title,
+            body: val.body,
+            kind: val.kind,
+            audience: val.audience,
+            starts_at: val.starts_at,
+            ends_at: val.ends_at,
+            created_at: val.created_at,
+            updated_at: val.updated_at,
+        }
+    }
+}
+
+#[derive(InputObject)]
+pub struct AnnouncementInput {
+    pub title: String,
+    pub body: String,
+    #[graphql(default_with = "AnnouncementKind::Info")]
+    pub kind: AnnouncementKind,
+    #[graphql(default_with = "AnnouncementAudience::All")]
+    pub audience: AnnouncementAudience,
+    // starts immediately when left out
+    pub starts_at: Option<DateTimeWithTimeZone>,
+    pub ends_at: Option<DateTimeWithTimeZone>,
+}
+
+// published whenever an announcement is created, changed or removed
+#[derive(Clone, Copy)]
+pub struct AnnouncementsChanged;
+
+// anonymous visitors only see announcements for everyone, admins see every audience. Goes by the
+// roles the account holds now, a role taken away stops showing its announcements right away
+pub fn audiences_for(permissions: Option<&CallerPermissions>) -> Vec<&'static str> {
+    let mut audiences = vec![AnnouncementAudience::All.as_str()];
+    if let Some(permissions) = permissions {
+        if permissions.has_role(ROLE_CUSTOMER) || permissions.has_role(ROLE_ADMIN) {
+            audiences.push(AnnouncementAudience::Customers.as_str());
+        }
+        if permissions.has_role(ROLE_SUPPLIER) || permissions.has_role(ROLE_ADMIN) {
+            audiences.push(AnnouncementAudience::Suppliers.as_str());
+        }
+    }
+    audiences
+}
+
+pub async fn active_announcements<C: ConnectionTrait>(
+    db: &C,
+    tenant: TenantId,
+    audiences: &[&'static str],
+) -> Result<Vec<AnnouncementsModel>, DbErr> {
+    let now = Utc::now();
+    AnnouncementsEntity::find()
+        .for_tenant(tenant)
+        .filter(announcements::Column::Audience.is_in(audiences.iter().copied()))
+        .filter(announcements::Column::StartsAt.lte(now))
+        .filter(
+            Condition::any()
+                .add(announcements::Column::EndsAt.is_null())
+                .add(announcements::Column::EndsAt.gt(now)),
+        )
+        .order_by_desc(announcements::Column::StartsAt)
+        .all(db)
+        .await
+}
+
+// the next time an announcement for these audiences starts or ends, subscribers re-send the list then
+pub async fn next_schedule_change<C: ConnectionTrait>(
+    db: &C,
+    tenant: TenantId,
+    audiences: &[&'static str],
+) -> Result<Option<DateTime<FixedOffset>>, DbErr> {
+    let now = Utc::now();
+    let next_start = AnnouncementsEntity::find()
+        .for_tenant(tenant)
+        .filter(announcements::Column::Audience.is_in(audiences.iter().copied()))
+        .filter(announcements::Column::StartsAt.gt(now))
+        .order_by_asc(announcements::Column::StartsAt)
+        .one(db)
+        .await?
+        .map(|announcement| announcement.starts_at);
+    let next_end = AnnouncementsEntity::find()
+        .for_tenant(tenant)
+        .filter(announcements::Column::Audience.is_in(audiences.iter().copied()))
+        .filter(announcements::Column::EndsAt.gt(now))
+        .order_by_asc(announcements::Column::EndsAt)
+        .one(db)
+        .await?
+        .and_then(|announcement| announcement.ends_at);
+
+    Ok(match (next_start, next_end) {
+        (Some(start), Some(end)) => Some(start.min(end)),
+        (start, end) => start.or(end),
+    })
+}
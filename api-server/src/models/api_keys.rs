@@ -0,0 +1,129 @@
+use crate::{
+    auth::{ROLE_CUSTOMER, ROLE_SUPPLIER},
+    entity::{
+        api_key_usage::Model as ApiKeyUsageModel,
+        api_keys::{self, Model as ApiKeysModel},
+    },
+    error::AppError,
+    models::user::get_customer_supplier_id,
+    permissions::caller_permissions,
+};
+use async_graphql::{Context, Error, ErrorExtensions, SimpleObject};
+use sea_orm::{
+    prelude::{Date, DateTimeWithTimeZone},
+    sea_query::SimpleExpr,
+    ActiveValue::Set,
+    ColumnTrait, DatabaseConnection,
+};
+
+#[derive(SimpleObject)]
+pub struct ApiKeys {
+    pub api_key_id: i32,
+    pub supplier_id: Option<i32>,
+    pub customer_id: Option<i32>,
+    pub name: String,
+    pub key_prefix: String,
+    pub created_at: Option<DateTimeWithTimeZone>,
+    pub revoked_at: Option<DateTimeWithTimeZone>,
+    pub sandbox: bool,
+}
+
+impl From<ApiKeysModel> for ApiKeys {
+    fn from(val: ApiKeysModel) -> ApiKeys {
+        ApiKeys {
+            api_key_id: val.api_key_id,
+            supplier_id: val.supplier_id,
+            customer_id: val.customer_id,
+            name: val.name,
+            key_prefix: val.key_prefix,
+            created_at: val.created_at,
+            revoked_at: val.revoked_at,
+            sandbox: val.sandbox,
+        }
+    }
+}
+
+// suppliers and customers both manage their own keys
+pub enum ApiKeyOwner {
+    Supplier(i32),
+    Customer(i32),
+}
+
+impl ApiKeyOwner {
+    // the role the account signed up with when it's one of the two, otherwise any role added
+    // later, so admins who also sell or buy manage keys too. Taken from the roles the account
+    // holds now rather than the token's
+    pub async fn from_ctx(db: &DatabaseConnection, ctx: &Context<'_>) -> Result<Self, Error> {
+        let permissions = caller_permissions(ctx).await?;
+        let role = permissions
+            .roles
+            .iter()
+            .map(String::as_str)
+            .find(|role| [ROLE_SUPPLIER, ROLE_CUSTOMER].contains(role))
+            .ok_or_else(|| AppError::invalid("Invalid role").extend())?;
+        let id = get_customer_supplier_id(db, ctx, role).await?;
+        match role {
+            ROLE_SUPPLIER => Ok(ApiKeyOwner::Supplier(id)),
+            _ => Ok(ApiKeyOwner::Customer(id)),
+        }
+    }
+
+    pub fn filter(&self) -> SimpleExpr {
+        match self {
+            ApiKeyOwner::Supplier(id) => api_keys::Column::SupplierId.eq(*id),
+            ApiKeyOwner::Customer(id) => api_keys::Column::CustomerId.eq(*id),
+        }
+    }
+
+    pub fn owns(&self, api_key: &ApiKeysModel) -> bool {
+        match self {
+            ApiKeyOwner::Supplier(id) => api_key.supplier_id == Some(*id),
+            ApiKeyOwner::Customer(id) => api_key.customer_id == Some(*id),
+        }
+    }
+
+    pub fn assign(&self, api_key: &mut api_keys::ActiveModel) {
+        match self {
+            ApiKeyOwner::Supplier(id) => api_key.supplier_id = Set(Some(*id)),
+            ApiKeyOwner::Customer(id) => api_key.customer_id = Set(Some(*id)),
+        }
+    }
+}
+
+// the plain key is only ever returned here, the database keeps a hash of it
+#[derive(SimpleObject)]
+pub struct CreatedApiKey {
+    pub api_key: ApiKeys,
+    pub key: String,
+}
+
+#[derive(SimpleObject)]
+pub struct ApiUsageDay {
+    pub api_key_id: i32,
+    pub key_name: String,
+    pub key_prefix: String,
+    pub usage_date: Date,
+    pub request_count: i32,
+    pub error_count: i32,
+    pub rate_limited_count: i32,
+    pub error_rate: f64,
+}
+
+impl From<(ApiKeyUsageModel, ApiKeysModel)> for ApiUsageDay {
+    fn from((usage, api_key): (ApiKeyUsageModel, ApiKeysModel)) -> ApiUsageDay {
+        ApiUsageDay {
+            api_key_id: usage.api_key_id,
+            key_name: api_key.name,
+            key_prefix: api_key.key_prefix,
+            usage_date: usage.usage_date,
+            request_count: usage.request_count,
+            error_count: usage.error_count,
+            rate_limited_count: usage.rate_limited_count,
+            error_rate: if usage.request_count > 0 {
+                usage.error_count as f64 / usage.request_count as f64
+            } else {
+                0.0
+            },
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use crate::{
+    entity::{prelude::ProductAvailability, product_availability},
+    error::AppError,
+    ids::ProductId,
+};
+use async_graphql::{InputObject, SimpleObject};
+use chrono::{Datelike, NaiveDate};
+use sea_orm::{
+    sea_query::Expr, ColumnTrait, ConnectionTrait, EntityTrait, QueryFilter, QueryOrder,
+};
+use std::collections::HashMap;
+
+#[derive(SimpleObject)]
+pub struct AvailabilityDay {
+    pub date: NaiveDate,
+    pub capacity: i32,
+    pub booked: i32,
+    pub remaining: i32,
+}
+
+impl From<product_availability::Model> for AvailabilityDay {
+    fn from(val: product_availability::Model) -> AvailabilityDay {
+        AvailabilityDay {
+            date: val.available_on,
+            capacity: val.capacity,
+            booked: val.booked,
+            remaining: (val.capacity - val.booked).max(0),
+        }
+    }
+}
+
+#[derive(InputObject)]
+pub struct AvailabilityDayInput {
+    pub date: NaiveDate,
+    // 0 closes the day for new bookings
+    pub capacity: i32,
+}
+
+// the date picked at checkout for a date bookable product in the cart
+#[derive(InputObject)]
+pub struct BookingDate {
+    pub product_id: ProductId,
+    pub date: NaiveDate,
+}
+
+// "2025-03" to the first day of that month and the first day of the next
+fn month_range(month: &str) -> Result<(NaiveDate, NaiveDate), AppError> {
+    let invalid = || AppError::Validation {
+        message: format!("{} is not a month in YYYY-MM form", month),
+        failed_rules: vec!["MONTH".to_string()],
+    };
+    let start =
+        NaiveDate::parse_from_str(&format!("{}-01", month), "%Y-%m-%d").map_err(|_| invalid())?;
+    let end = match start.month() {
+        12 => NaiveDate::from_ymd_opt(start.year() + 1, 1, 1),
+        month => NaiveDate::from_ymd_opt(start.year(), month + 1, 1),
+    }
+    .ok_or_else(invalid)?;
+    Ok((start, end))
+}
+
+// every day of the month, days the supplier never opened show up with no capacity
+pub async fn month_availability<C: ConnectionTrait>(
+    db: &C,
+    product_id: ProductId,
+    month: &str,
+) -> Result<Vec<AvailabilityDay>, AppError> {
+    let (start, end) = month_range(month)?;
+    let mut stored: HashMap<NaiveDate, product_availability::Model> = ProductAvailability::find()
+        .filter(product_availability::Column::ProductId.eq(product_id))
+        .filter(product_availability::Column::AvailableOn.gte(start))
+        .filter(product_availability::Column::AvailableOn.lt(end))
+        .order_by_asc(product_availability::Column::AvailableOn)
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|day| (day.available_on, day))
+        .collect();
+
+    Ok(start
+        .iter_days()
+        .take_while(|date| *date < end)
+        .map(|date| match stored.remove(&date) {
+            Some(day) => day.into(),
+            None => AvailabilityDay {
+                date,
+                capacity: 0,
+                booked: 0,
+                remaining: 0,
+            },
+        })
+        .collect())
+}
+
+// takes the units out of the day in one statement, so two checkouts can't both get the last slot
+pub async fn reserve_date<C: ConnectionTrait>(
+    db: &C,
+    product_id: i32,
+    date: NaiveDate,
+    quantity: i32,
+) -> Result<(), AppError> {
+    let reserved = ProductAvailability::update_many()
+        .col_expr(
+            product_availability::Column::Booked,
+            Expr::col(product_availability::Column::Booked).add(quantity),
+        )
+        .filter(product_availability::Column::ProductId.eq(product_id))
+        .filter(product_availability::Column::AvailableOn.eq(date))
+        .filter(
+            Expr::col(product_availability::Column::Booked)
+                .lte(Expr::col(product_availability::Column::Capacity).sub(quantity)),
+        )
+        .exec(db)
+        .await?
+        .rows_affected;
+    if reserved == 0 {
+        return Err(AppError::Validation {
+            message: format!(
+                "Product {} is not available for {} on {}",
+                product_id, quantity, date
+            ),
+            failed_rules: vec!["BOOKING_DATE_UNAVAILABLE".to_string()],
+        });
+    }
+    Ok(())
+}
+
+// gives the units of a cancelled booking back to the day
+pub async fn release_date<C: ConnectionTrait>(
+    db: &C,
+    product_id: i32,
+    date: NaiveDate,
+    quantity: i32,
+) -> Result<(), AppError> {
+    ProductAvailability::update_many()
+        .col_expr(
+            product_availability::Column::Booked,
+            Expr::cust_with_values("GREATEST(booked - $1, 0)", [quantity]),
+        )
+        .filter(product_availability::Column::ProductId.eq(product_id))
+        .filter(product_availability::Column::AvailableOn.eq(date))
+        .exec(db)
+        .await?;
+    Ok(())
+}
+
+// the date picked for a cart line's product, cart checkouts take one date per product
+pub fn booking_date_for(booking_dates: &[BookingDate], product_id: i32) -> Option<NaiveDate> {
+    booking_dates
+        .iter()
+        .find(|booking| booking.product_id.0 == product_id)
+        .map(|booking| booking.date)
+}
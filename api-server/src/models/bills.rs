@@ -9,6 +9,9 @@ pub struct Bills {
     order_id: i32,
     payment_status: String,
     total_amount: f64,
+    tax_amount: f64,
+    vat_id: Option<String>,
+    po_number: Option<String>,
 }
 
 impl From<BillsModel> for Bills {
@@ -19,10 +22,14 @@ impl From<BillsModel> for Bills {
             payment_status: val.payment_status,
             total_amount: f64::try_from(val.total_amount).unwrap(),
             order_id: val.order_id,
+            tax_amount: f64::try_from(val.tax_amount).unwrap(),
+            vat_id: val.vat_id,
+            po_number: val.po_number,
         }
     }
 }
 
+#[allow(dead_code)]
 #[derive(InputObject)]
 pub struct RegisterBill {
     bill_date: Option<DateTimeWithTimeZone>,
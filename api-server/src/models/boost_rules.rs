@@ -0,0 +1,172 @@
+use crate::{
+    entity::{
+        boost_rules::{self, Model as BoostRulesModel},
+        prelude::{BoostRules as BoostRulesEntity, Products as ProductsEntity},
+    },
+    error::AppError,
+    ids::TenantId,
+    pubsub::{publish, spawn_subscriber},
+};
+use async_graphql::{Enum, InputObject, SimpleObject};
+use sea_orm::{
+    sea_query::{Expr, Order},
+    ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, Select,
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+pub const BOOST_RULES_CHANNEL: &str = "boost_rules:reload";
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+pub enum BoostRuleType {
+    NewArrival,
+    Supplier,
+    Margin,
+}
+
+impl BoostRuleType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BoostRuleType::NewArrival => "NEW_ARRIVAL",
+            BoostRuleType::Supplier => "SUPPLIER",
+            BoostRuleType::Margin => "MARGIN",
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct BoostRules {
+    pub boost_rule_id: i32,
+    pub name: String,
+    pub rule_type: String,
+    pub weight: String,
+    pub supplier_id: Option<i32>,
+    pub max_age_days: Option<i32>,
+    pub min_margin_percent: Option<String>,
+    pub active: bool,
+}
+
+impl From<BoostRulesModel> for BoostRules {
+    fn from(val: BoostRulesModel) -> BoostRules {
+        BoostRules {
+            boost_rule_id: val.boost_rule_id,
+            name: val.name,
+            rule_type: val.rule_type,
+            weight: val.weight.to_string(),
+            supplier_id: val.supplier_id,
+            max_age_days: val.max_age_days,
+            min_margin_percent: val.min_margin_percent.map(|margin| margin.to_string()),
+            active: val.active,
+        }
+    }
+}
+
+#[derive(InputObject)]
+pub struct RegisterBoostRule {
+    pub name: String,
+    pub rule_type: BoostRuleType,
+    pub weight: String,
+    pub supplier_id: Option<i32>,
+    pub max_age_days: Option<i32>,
+    pub min_margin_percent: Option<String>,
+    #[graphql(default = true)]
+    pub active: bool,
+}
+
+// active rules kept in memory per tenant so listings don't hit the table on every request,
+// every instance reloads when a change is announced on BOOST_RULES_CHANNEL
+#[derive(Clone, Default)]
+pub struct BoostRuleSet {
+    rules: Arc<RwLock<HashMap<TenantId, Vec<BoostRulesModel>>>>,
+}
+
+impl BoostRuleSet {
+    pub async fn reload(&self, db: &DatabaseConnection) -> Result<(), AppError> {
+        let rules = BoostRulesEntity::find()
+            .filter(boost_rules::Column::Active.eq(true))
+            .all(db)
+            .await?;
+        let mut by_tenant: HashMap<TenantId, Vec<BoostRulesModel>> = HashMap::new();
+        for rule in rules {
+            by_tenant
+                .entry(rule.tenant_id.into())
+                .or_default()
+                .push(rule);
+        }
+        *self.rules.write().unwrap() = by_tenant;
+        Ok(())
+    }
+
+    // initial load plus a reload whenever another instance announces a change
+    pub fn spawn_reloader(&self, db: DatabaseConnection, redis: redis::Client) {
+        let (initial, initial_db) = (self.clone(), db.clone());
+        tokio::spawn(async move {
+            if let Err(e) = initial.reload(&initial_db).await {
+                eprintln!("Loading boost rules failed: {}", e);
+            }
+        });
+
+        let rule_set = self.clone();
+        spawn_subscriber(redis, BOOST_RULES_CHANNEL, move |_| {
+            let (rule_set, db) = (rule_set.clone(), db.clone());
+            async move {
+                if let Err(e) = rule_set.reload(&db).await {
+                    eprintln!("Reloading boost rules failed: {}", e);
+                }
+            }
+        });
+    }
+
+    // reloads here right away and tells the other instances to do the same
+    pub async fn announce_change(
+        &self,
+        db: &DatabaseConnection,
+        redis: &redis::Client,
+    ) -> Result<(), AppError> {
+        self.reload(db).await?;
+        if let Err(e) = publish(redis, BOOST_RULES_CHANNEL, "reload").await {
+            eprintln!("Announcing boost rule change failed: {}", e);
+        }
+        Ok(())
+    }
+
+    // orders the listing by the summed weight of the tenant's rules each product matches, ahead of the requested order
+    pub fn apply(
+        &self,
+        tenant: TenantId,
+        entity: Select<ProductsEntity>,
+    ) -> Select<ProductsEntity> {
+        let rules = self.rules.read().unwrap();
+        let terms: Vec<String> = rules
+            .get(&tenant)
+            .into_iter()
+            .flatten()
+            .filter_map(|rule| {
+                let condition = match rule.rule_type.as_str() {
+                    "NEW_ARRIVAL" => format!(
+                        "products.created_at >= CURRENT_TIMESTAMP - INTERVAL '1 day' * {}",
+                        rule.max_age_days?
+                    ),
+                    "SUPPLIER" => format!("products.supplier_id = {}", rule.supplier_id?),
+                    "MARGIN" => format!(
+                        "products.cost_price IS NOT NULL AND products.base_price > 0 \
+                            AND (products.base_price - products.cost_price) * 100 / products.base_price >= {}",
+                        rule.min_margin_percent?
+                    ),
+                    _ => return None,
+                };
+                Some(format!(
+                    "CASE WHEN {} THEN {} ELSE 0 END",
+                    condition, rule.weight
+                ))
+            })
+            .collect();
+
+        if terms.is_empty() {
+            return entity;
+        }
+        entity.order_by(Expr::cust(terms.join(" + ")), Order::Desc)
+    }
+}
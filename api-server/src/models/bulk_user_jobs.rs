@@ -0,0 +1,126 @@
+use crate::{
+    entity::{
+        bulk_user_job_results::Model as BulkUserJobResultsModel,
+        bulk_user_jobs::Model as BulkUserJobsModel,
+    },
+    graphql::complexity,
+};
+use async_graphql::{ComplexObject, Context, Enum, SimpleObject};
+use sea_orm::{
+    prelude::DateTimeWithTimeZone, ActiveEnum, ColumnTrait, DatabaseConnection, EntityTrait,
+    QueryFilter, QueryOrder,
+};
+
+// accounts one job may target, bigger incidents are split over several jobs
+pub const MAX_BULK_USERS: usize = 10_000;
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+pub enum BulkUserAction {
+    Disable,
+    ResetPassword,
+    AssignRole,
+}
+
+impl BulkUserAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BulkUserAction::Disable => "DISABLE",
+            BulkUserAction::ResetPassword => "RESET_PASSWORD",
+            BulkUserAction::AssignRole => "ASSIGN_ROLE",
+        }
+    }
+
+    pub fn parse(action: &str) -> Option<Self> {
+        match action {
+            "DISABLE" => Some(BulkUserAction::Disable),
+            "RESET_PASSWORD" => Some(BulkUserAction::ResetPassword),
+            "ASSIGN_ROLE" => Some(BulkUserAction::AssignRole),
+            _ => None,
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+#[graphql(complex)]
+pub struct BulkUserJobs {
+    pub job_id: i32,
+    pub action: String,
+    // only set for ASSIGN_ROLE
+    pub role: Option<String>,
+    pub status: String,
+    pub total_users: i32,
+    // accounts the worker is done with, failed ones included
+    pub processed_users: i32,
+    pub failed_users: i32,
+    pub error: Option<String>,
+    pub requested_at: DateTimeWithTimeZone,
+    pub finished_at: Option<DateTimeWithTimeZone>,
+}
+
+impl From<BulkUserJobsModel> for BulkUserJobs {
+    fn from(val: BulkUserJobsModel) -> BulkUserJobs {
+        BulkUserJobs {
+            job_id: val.job_id,
+            action: val.action,
+            role: val.role.map(|role| role.to_value()),
+            status: val.status,
+            total_users: val.total_users,
+            processed_users: val.processed_users,
+            failed_users: val.failed_users,
+            error: val.error,
+            requested_at: val.requested_at,
+            finished_at: val.finished_at,
+        }
+    }
+}
+
+#[ComplexObject]
+impl BulkUserJobs {
+    // the report, one entry per targeted account, onlyProblems leaves out the ones that went through
+    #[graphql(complexity = "complexity::unbounded(child_complexity)")]
+    async fn results(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = false)] only_problems: bool,
+    ) -> Result<Vec<BulkUserJobResults>, async_graphql::Error> {
+        use crate::entity::{
+            bulk_user_job_results, prelude::BulkUserJobResults as BulkUserJobResultsEntity,
+        };
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        let mut results = BulkUserJobResultsEntity::find()
+            .filter(bulk_user_job_results::Column::JobId.eq(self.job_id));
+        if only_problems {
+            results =
+                results.filter(bulk_user_job_results::Column::Outcome.is_in(["SKIPPED", "FAILED"]));
+        }
+
+        Ok(results
+            .order_by_asc(bulk_user_job_results::Column::UserId)
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|result| result.into())
+            .collect())
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct BulkUserJobResults {
+    pub user_id: i32,
+    // PENDING until the worker gets to the account, then DONE, SKIPPED or FAILED
+    pub outcome: String,
+    pub message: Option<String>,
+    pub processed_at: Option<DateTimeWithTimeZone>,
+}
+
+impl From<BulkUserJobResultsModel> for BulkUserJobResults {
+    fn from(val: BulkUserJobResultsModel) -> BulkUserJobResults {
+        BulkUserJobResults {
+            user_id: val.user_id,
+            outcome: val.outcome,
+            message: val.message,
+            processed_at: val.processed_at,
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use crate::{
+    error::AppError,
+    ids::TenantId,
+    models::order_und_pagination::{
+        OrderAndPagination, OrderBy, OrderByColumn, OrderByOrder, Pagination,
+    },
+};
+use async_graphql::{Enum, SimpleObject};
+use chrono::{Duration, Utc};
+use sea_orm::{ConnectionTrait, DbBackend, FromQueryResult, Statement};
+
+// how far back best sellers are counted
+const BEST_SELLER_DAYS: i64 = 30;
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+pub enum WarmCacheScope {
+    All,
+    CategoryTree,
+    // productsWithId for each of the best selling products
+    TopProducts,
+}
+
+impl WarmCacheScope {
+    pub fn includes(&self, scope: WarmCacheScope) -> bool {
+        *self == WarmCacheScope::All || *self == scope
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct WarmedCache {
+    pub category_tree: bool,
+    pub top_products: i32,
+}
+
+// what a product page asks productsWithId for when warmCache isn't given a paginator
+pub fn product_page_paginator() -> OrderAndPagination {
+    OrderAndPagination {
+        order_by: OrderBy {
+            column: OrderByColumn::Date,
+            order: OrderByOrder::Desc,
+        },
+        pagination: Pagination {
+            page: 1,
+            page_size: 1,
+        },
+    }
+}
+
+#[derive(FromQueryResult)]
+struct BestSeller {
+    product_id: i32,
+}
+
+// the tenant's products by units sold over the last 30 days, cancelled orders left out
+pub async fn best_sellers<C: ConnectionTrait>(
+    db: &C,
+    tenant: TenantId,
+    limit: u64,
+) -> Result<Vec<i32>, AppError> {
+    let since = (Utc::now() - Duration::days(BEST_SELLER_DAYS)).fixed_offset();
+
+    let best_sellers = BestSeller::find_by_statement(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        r#"SELECT oi.product_id
+            FROM order_items oi
+            JOIN orders o ON o.order_id = oi.order_id
+            WHERE o.tenant_id = $1 AND o.order_date >= $2 AND o.status <> 'CANCELLED'
+            GROUP BY oi.product_id
+            ORDER BY SUM(oi.quantity) DESC, oi.product_id
+            LIMIT $3"#,
+        vec![tenant.0.into(), since.into(), (limit as i64).into()],
+    ))
+    .all(db)
+    .await?;
+
+    Ok(best_sellers
+        .into_iter()
+        .map(|best_seller| best_seller.product_id)
+        .collect())
+}
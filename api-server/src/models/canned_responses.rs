@@ -0,0 +1,138 @@
+use crate::{
+    auth::{ROLE_ADMIN, ROLE_SUPPLIER},
+    entity::{canned_responses, canned_responses::Model as CannedResponsesModel},
+    error::AppError,
+    models::user::get_customer_supplier_id,
+    notifications::{substitute, unknown_placeholder_names},
+    permissions::caller_permissions,
+};
+use async_graphql::{Context, Error, ErrorExtensions, InputObject, SimpleObject};
+use sea_orm::{prelude::DateTimeWithTimeZone, ColumnTrait, Condition, DatabaseConnection};
+
+// filled in from the order a response is sent to
+pub const CANNED_PLACEHOLDERS: [&str; 5] = [
+    "order_id",
+    "customer_name",
+    "customer_first_name",
+    "order_status",
+    "order_total",
+];
+
+#[derive(SimpleObject)]
+pub struct CannedResponses {
+    pub canned_response_id: i32,
+    // null for the shared responses admins keep for the whole marketplace
+    pub supplier_id: Option<i32>,
+    pub title: String,
+    pub body: String,
+    pub created_at: DateTimeWithTimeZone,
+    pub updated_at: DateTimeWithTimeZone,
+}
+
+impl From<CannedResponsesModel> for CannedResponses {
+    fn from(val: CannedResponsesModel) -> CannedResponses {
+        CannedResponses {
+            canned_response_id: val.canned_response_id,
+            supplier_id: val.supplier_id,
+            title: val.title,
+            body: val.body,
+            created_at: val.created_at,
+            updated_at: val.updated_at,
+        }
+    }
+}
+
+#[derive(InputObject)]
+pub struct RegisterCannedResponse {
+    pub title: String,
+    // may use {{order_id}}, {{customer_name}}, {{customer_first_name}}, {{order_status}} and {{order_total}}
+    pub body: String,
+}
+
+pub fn validate_canned_response(input: &RegisterCannedResponse) -> Result<(), AppError> {
+    let mut failed_rules = Vec::new();
+    if input.title.trim().is_empty() {
+        failed_rules.push("title cannot be empty".to_string());
+    }
+    if input.body.trim().is_empty() {
+        failed_rules.push("body cannot be empty".to_string());
+    }
+    for placeholder in unknown_placeholder_names(&CANNED_PLACEHOLDERS, &input.body) {
+        failed_rules.push(format!("unknown placeholder {{{{{}}}}}", placeholder));
+    }
+    if !failed_rules.is_empty() {
+        return Err(AppError::Validation {
+            message: "Canned response is invalid".to_string(),
+            failed_rules,
+        });
+    }
+    Ok(())
+}
+
+// suppliers keep their own responses, admins the shared ones, accounts with both manage their own.
+// Goes by the roles the account holds now, not the ones in its token
+pub async fn canned_response_owner(
+    db: &DatabaseConnection,
+    ctx: &Context<'_>,
+) -> Result<Option<i32>, Error> {
+    let permissions = caller_permissions(ctx).await?;
+    if permissions.has_role(ROLE_SUPPLIER) {
+        return Ok(Some(
+            get_customer_supplier_id(db, ctx, ROLE_SUPPLIER).await?,
+        ));
+    }
+    if permissions.has_role(ROLE_ADMIN) {
+        return Ok(None);
+    }
+    Err(AppError::forbidden().extend())
+}
+
+// the responses an owner may send, suppliers can use the shared ones next to their own
+pub fn usable_by(owner: Option<i32>) -> Condition {
+    let shared = canned_responses::Column::SupplierId.is_null();
+    match owner {
+        Some(supplier_id) => Condition::any()
+            .add(shared)
+            .add(canned_responses::Column::SupplierId.eq(supplier_id)),
+        None => Condition::all().add(shared),
+    }
+}
+
+// the body with the order's details filled in, plain text since it ends up in the chat as is
+pub async fn resolve_canned_response(
+    db: &DatabaseConnection,
+    order_id: i32,
+    body: &str,
+) -> Result<String, Error> {
+    use crate::entity::prelude::{Customers, Orders};
+    use sea_orm::EntityTrait;
+
+    let order = Orders::find_by_id(order_id)
+        .one(db)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Order").extend())?;
+    let customer = match order.customer_id {
+        Some(customer_id) => Customers::find_by_id(customer_id).one(db).await?,
+        None => None,
+    };
+    // guests never gave a name, only an email address
+    let (customer_name, customer_first_name) = match customer {
+        Some(customer) => (
+            format!("{} {}", customer.first_name, customer.last_name),
+            customer.first_name,
+        ),
+        None => ("Customer".to_string(), "Customer".to_string()),
+    };
+
+    Ok(substitute(
+        body,
+        &[
+            ("order_id", order.order_id.to_string()),
+            ("customer_name", customer_name),
+            ("customer_first_name", customer_first_name),
+            ("order_status", order.status),
+            ("order_total", order.total_amount.to_string()),
+        ],
+        false,
+    ))
+}
@@ -1,6 +1,8 @@
+use crate::ids::ProductId;
 use async_graphql::{InputObject, SimpleObject};
 use sea_orm::prelude::DateTimeWithTimeZone;
 
+#[allow(dead_code)]
 #[derive(SimpleObject)]
 pub struct ShoppingCarts {
     pub cart_id: i32,
@@ -8,6 +10,7 @@ pub struct ShoppingCarts {
     pub created_at: Option<DateTimeWithTimeZone>,
 }
 
+#[allow(dead_code)]
 #[derive(InputObject)]
 pub struct RegisterShoppingCart {
     pub customer_id: i32,
@@ -17,13 +20,41 @@ pub struct RegisterShoppingCart {
 pub struct CartItems {
     pub cart_item_id: i32,
     pub cart_id: i32,
-    pub product_id: i32,
+    pub product_id: ProductId,
     pub quantity: i32,
 }
 
+#[allow(dead_code)]
 #[derive(InputObject)]
 pub struct RegisterCartItem {
     pub cart_id: i32,
-    pub product_id: i32,
+    pub product_id: ProductId,
     pub quantity: i32,
 }
+
+// a customer's cart as it was when it expired
+#[derive(SimpleObject)]
+pub struct ExpiredCarts {
+    pub expired_cart_id: i32,
+    pub item_count: i32,
+    // at the base prices of the moment it expired
+    pub total_value: String,
+    pub expired_at: DateTimeWithTimeZone,
+    pub restorable_until: DateTimeWithTimeZone,
+    pub items: Vec<ExpiredCartItems>,
+}
+
+#[derive(SimpleObject)]
+pub struct ExpiredCartItems {
+    pub product_id: ProductId,
+    pub quantity: i32,
+    pub unit_price: String,
+}
+
+#[derive(SimpleObject)]
+pub struct RestoredCart {
+    pub cart_id: i32,
+    pub restored_items: Vec<ProductId>,
+    // no longer on sale, over the customer's purchase limit or out of stock, or already in the cart
+    pub skipped_items: Vec<ProductId>,
+}
@@ -0,0 +1,329 @@
+use crate::{
+    auth::Auth,
+    config::{ContentFilterPolicy, DuplicatePolicy},
+    content_filter::{filter_content, ContentField},
+    entity::{prelude::Products as ProductsEntity, products},
+    error::AppError,
+    ids::ProductId,
+    models::{
+        category_attributes::{
+            checked_attributes, parse_csv_attributes, product_attributes, ProductAttributeInput,
+        },
+        products::{check_duplicate_products, DuplicateWarning, ProductCsvRow},
+    },
+    retry::retry_redis,
+};
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use async_graphql::{Context, SimpleObject};
+use chrono::{Duration, Utc};
+use redis::AsyncCommands;
+use sea_orm::{
+    prelude::{DateTimeWithTimeZone, Decimal},
+    ColumnTrait, ConnectionTrait, EntityTrait, QueryFilter, QueryOrder,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+// catalog_import:<sha256 of the apply token> holds the previewed CSV until it's applied or expires
+const CATALOG_IMPORT_PREFIX: &str = "catalog_import:";
+pub const CATALOG_PREVIEW_TTL_MINUTES: i64 = 30;
+
+#[derive(SimpleObject, Serialize, Deserialize, PartialEq)]
+pub struct CatalogCreate {
+    pub name: String,
+    pub base_price: String,
+    pub category_id: Option<i32>,
+    pub stock_quantity: i32,
+    pub sku: Option<String>,
+    // previews stored before the warnings were added have none
+    #[serde(default)]
+    pub duplicate_warnings: Vec<DuplicateWarning>,
+}
+
+// one column of an existing product the import would change, null stands for an empty value
+#[derive(SimpleObject, Serialize, Deserialize, PartialEq)]
+pub struct CatalogFieldChange {
+    pub field: String,
+    pub old_value: Option<String>,
+    pub new_value: Option<String>,
+}
+
+#[derive(SimpleObject, Serialize, Deserialize, PartialEq)]
+pub struct CatalogUpdate {
+    pub product_id: ProductId,
+    pub name: String,
+    // an archived product is updated but stays archived
+    pub archived: bool,
+    pub changes: Vec<CatalogFieldChange>,
+}
+
+// a listed product of the supplier that isn't in the CSV, applying the import archives it
+#[derive(SimpleObject, Serialize, Deserialize, PartialEq)]
+pub struct CatalogDeactivation {
+    pub product_id: ProductId,
+    pub name: String,
+}
+
+// what applying the CSV would do to the supplier's catalog
+#[derive(SimpleObject, Serialize, Deserialize, PartialEq)]
+pub struct CatalogDiff {
+    pub creates: Vec<CatalogCreate>,
+    pub updates: Vec<CatalogUpdate>,
+    // existing products the CSV leaves exactly as they are
+    pub unchanged: i32,
+    pub deactivations: Vec<CatalogDeactivation>,
+}
+
+#[derive(SimpleObject)]
+pub struct CatalogImportPreview {
+    // hand to applyProductsCsv to carry out exactly this diff, usable once
+    pub apply_token: String,
+    pub expires_at: DateTimeWithTimeZone,
+    pub diff: CatalogDiff,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PendingCatalogImport {
+    pub supplier_id: i32,
+    pub csv: String,
+    pub diff: CatalogDiff,
+}
+
+fn redis_error(e: redis::RedisError) -> AppError {
+    AppError::Internal(format!("Catalog import store unavailable: {}", e))
+}
+
+fn attributes_text(attributes: &[ProductAttributeInput]) -> Option<String> {
+    let mut pairs: Vec<String> = attributes
+        .iter()
+        .map(|attribute| format!("{}={}", attribute.name.trim(), attribute.value.trim()))
+        .collect();
+    pairs.sort();
+    (!pairs.is_empty()).then(|| pairs.join("; "))
+}
+
+fn change<T: ToString + PartialEq>(
+    changes: &mut Vec<CatalogFieldChange>,
+    field: &str,
+    old_value: Option<T>,
+    new_value: Option<T>,
+) {
+    if old_value != new_value {
+        changes.push(CatalogFieldChange {
+            field: field.to_string(),
+            old_value: old_value.map(|value| value.to_string()),
+            new_value: new_value.map(|value| value.to_string()),
+        });
+    }
+}
+
+// the attributes of a catalog row checked against its category's template, failed rules carry
+// the product's name so the supplier can find the row
+pub async fn checked_row_attributes(
+    ctx: &Context<'_>,
+    name: &str,
+    category_id: Option<i32>,
+    attributes: &[ProductAttributeInput],
+) -> Result<Vec<(String, String)>, AppError> {
+    checked_attributes(ctx, category_id, attributes)
+        .await
+        .map_err(|e| match e {
+            AppError::Validation {
+                message,
+                failed_rules,
+            } => AppError::Validation {
+                message,
+                failed_rules: failed_rules
+                    .into_iter()
+                    .map(|rule| format!("{}: {}", name, rule))
+                    .collect(),
+            },
+            e => e,
+        })
+}
+
+// compares the parsed CSV with the supplier's products the same way importProductsCsv matches
+// them, by name. Descriptions are compared after the content filter has been over them, and rows
+// are checked the way the import checks them, so a preview fails where applying it would
+pub async fn catalog_diff<C: ConnectionTrait>(
+    ctx: &Context<'_>,
+    db: &C,
+    supplier_id: i32,
+    rows: &[(ProductCsvRow, Decimal)],
+) -> Result<CatalogDiff, AppError> {
+    let content_policy = ctx
+        .data::<ContentFilterPolicy>()
+        .map_err(|e| AppError::Internal(e.message))?;
+    let duplicate_policy = ctx
+        .data::<DuplicatePolicy>()
+        .map_err(|e| AppError::Internal(e.message))?;
+    let mut existing: HashMap<String, products::Model> = ProductsEntity::find()
+        .filter(products::Column::SupplierId.eq(supplier_id))
+        .order_by_asc(products::Column::ProductId)
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|product| (product.name.clone(), product))
+        .collect();
+
+    let mut diff = CatalogDiff {
+        creates: Vec::new(),
+        updates: Vec::new(),
+        unchanged: 0,
+        deactivations: Vec::new(),
+    };
+    let mut seen = HashSet::new();
+    for (row, base_price) in rows {
+        seen.insert(row.name.clone());
+        let description = row
+            .description
+            .clone()
+            .map(|text| filter_content(content_policy, ContentField::ProductDescription, text))
+            .transpose()?;
+        let column = row
+            .attributes
+            .as_deref()
+            .map(str::trim)
+            .filter(|column| !column.is_empty());
+        let Some(product) = existing.get(&row.name) else {
+            let attributes = column.map(parse_csv_attributes).unwrap_or_default();
+            checked_row_attributes(ctx, &row.name, row.category_id, &attributes).await?;
+            let duplicate_warnings = check_duplicate_products(
+                db,
+                supplier_id,
+                &row.name,
+                row.category_id,
+                None,
+                duplicate_policy,
+            )
+            .await?;
+            diff.creates.push(CatalogCreate {
+                name: row.name.clone(),
+                base_price: base_price.to_string(),
+                category_id: row.category_id,
+                stock_quantity: row.stock_quantity,
+                sku: row.sku.clone(),
+                duplicate_warnings,
+            });
+            continue;
+        };
+        let current_attributes = product_attributes(db, product.product_id).await?;
+        let attributes = match column {
+            Some(column) => parse_csv_attributes(column),
+            None => current_attributes.clone(),
+        };
+        checked_row_attributes(ctx, &row.name, row.category_id, &attributes).await?;
+
+        let mut changes = Vec::new();
+        change(
+            &mut changes,
+            "description",
+            product.description.clone(),
+            description,
+        );
+        change(
+            &mut changes,
+            "basePrice",
+            Some(product.base_price),
+            Some(*base_price),
+        );
+        change(
+            &mut changes,
+            "categoryId",
+            product.category_id,
+            row.category_id,
+        );
+        change(
+            &mut changes,
+            "stockQuantity",
+            Some(product.stock_quantity),
+            Some(row.stock_quantity),
+        );
+        if row.sku.is_some() {
+            change(&mut changes, "sku", product.sku.clone(), row.sku.clone());
+        }
+        if column.is_some() {
+            change(
+                &mut changes,
+                "attributes",
+                attributes_text(&current_attributes),
+                attributes_text(&attributes),
+            );
+        }
+
+        if changes.is_empty() {
+            diff.unchanged += 1;
+        } else {
+            diff.updates.push(CatalogUpdate {
+                product_id: product.product_id.into(),
+                name: product.name.clone(),
+                archived: product.archived_at.is_some(),
+                changes,
+            });
+        }
+    }
+
+    existing.retain(|name, product| !seen.contains(name) && product.archived_at.is_none());
+    let mut missing: Vec<products::Model> = existing.into_values().collect();
+    missing.sort_by_key(|product| product.product_id);
+    diff.deactivations = missing
+        .into_iter()
+        .map(|product| CatalogDeactivation {
+            product_id: product.product_id.into(),
+            name: product.name,
+        })
+        .collect();
+
+    Ok(diff)
+}
+
+// only the hash of the apply token is stored, the token itself goes to the supplier once
+pub async fn store_catalog_import(
+    redis: &redis::Client,
+    pending: &PendingCatalogImport,
+) -> Result<(String, DateTimeWithTimeZone), AppError> {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    let token: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    let key = format!("{}{}", CATALOG_IMPORT_PREFIX, Auth::hash_secret(&token));
+    let value = serde_json::to_string(pending)
+        .map_err(|e| AppError::Internal(format!("Catalog import not serializable: {}", e)))?;
+    let ttl = CATALOG_PREVIEW_TTL_MINUTES * 60;
+
+    retry_redis("catalog_import_store", || async {
+        let mut connection = redis.get_multiplexed_async_connection().await?;
+        connection
+            .set_ex::<_, _, ()>(&key, &value, ttl as u64)
+            .await
+    })
+    .await
+    .map_err(redis_error)?;
+
+    Ok((
+        token,
+        (Utc::now() + Duration::minutes(CATALOG_PREVIEW_TTL_MINUTES)).fixed_offset(),
+    ))
+}
+
+// takes the previewed import out in one step, so an apply token can be used exactly once
+pub async fn take_catalog_import(
+    redis: &redis::Client,
+    apply_token: &str,
+) -> Result<PendingCatalogImport, AppError> {
+    let key = format!(
+        "{}{}",
+        CATALOG_IMPORT_PREFIX,
+        Auth::hash_secret(apply_token)
+    );
+    let value: Option<String> = retry_redis("catalog_import_take", || async {
+        let mut connection = redis.get_multiplexed_async_connection().await?;
+        connection.get_del(&key).await
+    })
+    .await
+    .map_err(redis_error)?;
+
+    let value = value
+        .ok_or_else(|| AppError::invalid("Apply token is invalid, expired or already used"))?;
+    serde_json::from_str(&value)
+        .map_err(|e| AppError::Internal(format!("Catalog import unreadable: {}", e)))
+}
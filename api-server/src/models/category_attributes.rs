@@ -0,0 +1,363 @@
+use crate::{
+    entity::{
+        category_attributes::{self, Model as CategoryAttributesModel},
+        prelude::{
+            CategoryAttributes as CategoryAttributesEntity,
+            ProductAttributes as ProductAttributesEntity, Products,
+        },
+        product_attributes, products,
+    },
+    error::AppError,
+    ids::TenantId,
+    models::products::{category_tree, Categories},
+};
+use async_graphql::{Context, InputObject, SimpleObject};
+use sea_orm::{
+    sea_query::{Expr, Query},
+    ActiveValue::Set,
+    ColumnTrait, Condition, ConnectionTrait, DatabaseConnection, EntityTrait, QueryFilter,
+    QueryOrder, QuerySelect,
+};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+#[derive(SimpleObject)]
+pub struct CategoryAttributes {
+    pub attribute_id: i32,
+    // the category that defines it, an ancestor's id for inherited attributes
+    pub category_id: i32,
+    pub name: String,
+    pub required: bool,
+    // null for free text
+    pub allowed_values: Option<Vec<String>>,
+}
+
+impl From<CategoryAttributesModel> for CategoryAttributes {
+    fn from(val: CategoryAttributesModel) -> CategoryAttributes {
+        CategoryAttributes {
+            attribute_id: val.attribute_id,
+            category_id: val.category_id,
+            name: val.name,
+            required: val.required,
+            allowed_values: val.allowed_values,
+        }
+    }
+}
+
+#[derive(InputObject)]
+pub struct CategoryAttributeInput {
+    pub name: String,
+    #[graphql(default)]
+    pub required: bool,
+    pub allowed_values: Option<Vec<String>>,
+}
+
+#[derive(InputObject, Clone)]
+pub struct ProductAttributeInput {
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(SimpleObject, Clone)]
+pub struct ProductAttributes {
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(SimpleObject)]
+pub struct AttributeFacet {
+    pub name: String,
+    // most common value first
+    pub values: Vec<FacetValue>,
+}
+
+#[derive(SimpleObject)]
+pub struct FacetValue {
+    pub value: String,
+    pub product_count: i64,
+}
+
+// attribute names are matched case-insensitively, stored lowercase
+pub fn normalize_attribute_name(name: &str) -> String {
+    name.trim().to_lowercase()
+}
+
+// the category and every category above it, nearest first
+fn lineage(tree: &[Categories], category_id: i32) -> Vec<i32> {
+    let parents: HashMap<i32, Option<i32>> = tree
+        .iter()
+        .map(|category| (category.category_id, category.parent_category_id))
+        .collect();
+    let mut lineage = Vec::new();
+    let mut next = Some(category_id);
+    // the walk stops should the tree loop
+    while let Some(id) = next.filter(|id| !lineage.contains(id) && parents.contains_key(id)) {
+        lineage.push(id);
+        next = parents[&id];
+    }
+    lineage
+}
+
+// the category and every category below it
+pub fn category_subtree(tree: &[Categories], category_id: i32) -> Vec<i32> {
+    let mut children: HashMap<i32, Vec<i32>> = HashMap::new();
+    for category in tree {
+        if let Some(parent) = category.parent_category_id {
+            children
+                .entry(parent)
+                .or_default()
+                .push(category.category_id);
+        }
+    }
+    let mut subtree = vec![category_id];
+    let mut index = 0;
+    while index < subtree.len() {
+        for &child in children.get(&subtree[index]).into_iter().flatten() {
+            if !subtree.contains(&child) {
+                subtree.push(child);
+            }
+        }
+        index += 1;
+    }
+    subtree
+}
+
+// what products of the category may carry, its own attributes and the ones it inherits, by name.
+// Empty for uncategorised products
+pub async fn attribute_template<C: ConnectionTrait>(
+    db: &C,
+    tree: &[Categories],
+    category_id: Option<i32>,
+) -> Result<Vec<CategoryAttributesModel>, AppError> {
+    let Some(category_id) = category_id else {
+        return Ok(Vec::new());
+    };
+    let lineage = lineage(tree, category_id);
+    let defined = CategoryAttributesEntity::find()
+        .filter(category_attributes::Column::CategoryId.is_in(lineage.clone()))
+        .all(db)
+        .await?;
+
+    // the nearest category's definition wins over an ancestor's of the same name
+    let mut template: BTreeMap<String, CategoryAttributesModel> = BTreeMap::new();
+    for category_id in lineage.iter().rev() {
+        for attribute in defined.iter().filter(|a| a.category_id == *category_id) {
+            template.insert(attribute.name.clone(), attribute.clone());
+        }
+    }
+    Ok(template.into_values().collect())
+}
+
+// checks the product's attributes against its category's template, names come back normalized
+pub fn validate_attributes(
+    template: &[CategoryAttributesModel],
+    attributes: &[ProductAttributeInput],
+) -> Result<Vec<(String, String)>, AppError> {
+    let mut failed_rules = Vec::new();
+    let mut validated = Vec::with_capacity(attributes.len());
+    let mut seen = HashSet::new();
+
+    for attribute in attributes {
+        let name = normalize_attribute_name(&attribute.name);
+        let value = attribute.value.trim().to_string();
+        if !seen.insert(name.clone()) {
+            failed_rules.push(format!("{}: given more than once", name));
+            continue;
+        }
+        let Some(definition) = template.iter().find(|a| a.name == name) else {
+            failed_rules.push(format!("{}: not an attribute of the category", name));
+            continue;
+        };
+        if value.is_empty() || value.chars().count() > 100 {
+            failed_rules.push(format!("{}: value must be 1 to 100 characters", name));
+            continue;
+        }
+        if let Some(allowed) = &definition.allowed_values {
+            if !allowed.contains(&value) {
+                failed_rules.push(format!("{}: must be one of {}", name, allowed.join(", ")));
+                continue;
+            }
+        }
+        validated.push((name, value));
+    }
+    for definition in template.iter().filter(|a| a.required) {
+        if !seen.contains(&definition.name) {
+            failed_rules.push(format!("{}: required", definition.name));
+        }
+    }
+
+    if !failed_rules.is_empty() {
+        return Err(AppError::Validation {
+            message: "Product attributes don't match the category's template".to_string(),
+            failed_rules,
+        });
+    }
+    Ok(validated)
+}
+
+// validate_attributes against the template of the category the product is saved under
+pub async fn checked_attributes(
+    ctx: &Context<'_>,
+    category_id: Option<i32>,
+    attributes: &[ProductAttributeInput],
+) -> Result<Vec<(String, String)>, AppError> {
+    let db = ctx
+        .data::<DatabaseConnection>()
+        .map_err(|e| AppError::Internal(e.message))?;
+    let tree = category_tree(ctx).await?;
+    let template = attribute_template(db, &tree, category_id).await?;
+    validate_attributes(&template, attributes)
+}
+
+// the attributes column of a catalog import, "size=M; colour=red"
+pub fn parse_csv_attributes(column: &str) -> Vec<ProductAttributeInput> {
+    column
+        .split(';')
+        .filter(|pair| !pair.trim().is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            ProductAttributeInput {
+                name: name.to_string(),
+                value: value.to_string(),
+            }
+        })
+        .collect()
+}
+
+pub async fn product_attributes<C: ConnectionTrait>(
+    db: &C,
+    product_id: i32,
+) -> Result<Vec<ProductAttributeInput>, AppError> {
+    Ok(ProductAttributesEntity::find()
+        .filter(product_attributes::Column::ProductId.eq(product_id))
+        .order_by_asc(product_attributes::Column::Name)
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|attribute| ProductAttributeInput {
+            name: attribute.name,
+            value: attribute.value,
+        })
+        .collect())
+}
+
+// swaps the product's attributes for the validated ones
+pub async fn replace_product_attributes<C: ConnectionTrait>(
+    db: &C,
+    product_id: i32,
+    attributes: Vec<(String, String)>,
+) -> Result<(), AppError> {
+    ProductAttributesEntity::delete_many()
+        .filter(product_attributes::Column::ProductId.eq(product_id))
+        .exec(db)
+        .await?;
+    if attributes.is_empty() {
+        return Ok(());
+    }
+    ProductAttributesEntity::insert_many(attributes.into_iter().map(|(name, value)| {
+        product_attributes::ActiveModel {
+            product_id: Set(product_id),
+            name: Set(name),
+            value: Set(value),
+        }
+    }))
+    .exec_without_returning(db)
+    .await?;
+    Ok(())
+}
+
+// keeps products carrying the attribute with that value, for narrowing searches by facet
+pub fn has_attribute(name: &str, value: &str) -> sea_orm::sea_query::SimpleExpr {
+    products::Column::ProductId.in_subquery(
+        Query::select()
+            .column(product_attributes::Column::ProductId)
+            .from(ProductAttributesEntity)
+            .and_where(product_attributes::Column::Name.eq(normalize_attribute_name(name)))
+            .and_where(product_attributes::Column::Value.eq(value.trim()))
+            .to_owned(),
+    )
+}
+
+// keeps products lacking one of the template's required attributes, None when nothing is required
+pub fn lacks_required_attribute(template: &[CategoryAttributesModel]) -> Option<Condition> {
+    let required: Vec<&CategoryAttributesModel> = template.iter().filter(|a| a.required).collect();
+    if required.is_empty() {
+        return None;
+    }
+    Some(
+        required
+            .into_iter()
+            .fold(Condition::any(), |lacking, attribute| {
+                lacking.add(
+                    products::Column::ProductId.not_in_subquery(
+                        Query::select()
+                            .column(product_attributes::Column::ProductId)
+                            .from(ProductAttributesEntity)
+                            .and_where(product_attributes::Column::Name.eq(attribute.name.clone()))
+                            .to_owned(),
+                    ),
+                )
+            }),
+    )
+}
+
+// the rules reported when products would end up without the template's required attributes
+pub fn required_attribute_rules(template: &[CategoryAttributesModel]) -> Vec<String> {
+    template
+        .iter()
+        .filter(|a| a.required)
+        .map(|a| format!("{}: required", a.name))
+        .collect()
+}
+
+// value counts over the on-sale products of the categories, for the template's attributes only
+pub async fn attribute_facets<C: ConnectionTrait>(
+    db: &C,
+    tenant: TenantId,
+    category_ids: Vec<i32>,
+    template: &[CategoryAttributesModel],
+) -> Result<Vec<AttributeFacet>, AppError> {
+    let counts: Vec<(String, String, i64)> = ProductAttributesEntity::find()
+        .select_only()
+        .column(product_attributes::Column::Name)
+        .column(product_attributes::Column::Value)
+        .column_as(
+            Expr::col(product_attributes::Column::ProductId).count(),
+            "product_count",
+        )
+        .inner_join(Products)
+        .filter(products::Column::CategoryId.is_in(category_ids))
+        .filter(products::Column::TenantId.eq(tenant.0))
+        .filter(products::Column::ArchivedAt.is_null())
+        .filter(
+            product_attributes::Column::Name
+                .is_in(template.iter().map(|attribute| attribute.name.clone())),
+        )
+        .group_by(product_attributes::Column::Name)
+        .group_by(product_attributes::Column::Value)
+        .into_tuple()
+        .all(db)
+        .await?;
+
+    let mut by_name: HashMap<String, Vec<FacetValue>> = HashMap::new();
+    for (name, value, product_count) in counts {
+        by_name.entry(name).or_default().push(FacetValue {
+            value,
+            product_count,
+        });
+    }
+    Ok(template
+        .iter()
+        .map(|attribute| {
+            let mut values = by_name.remove(&attribute.name).unwrap_or_default();
+            values.sort_by(|a, b| {
+                b.product_count
+                    .cmp(&a.product_count)
+                    .then_with(|| a.value.cmp(&b.value))
+            });
+            AttributeFacet {
+                name: attribute.name.clone(),
+                values,
+            }
+        })
+        .collect())
+}
@@ -0,0 +1,76 @@
+use sea_orm::{ConnectionTrait, DbBackend, DbErr, Statement, TransactionTrait};
+
+// adds delta to the category and every category above it, UNION stops the walk should the tree loop
+pub async fn adjust_category_count<C: ConnectionTrait>(
+    db: &C,
+    category_id: Option<i32>,
+    delta: i32,
+) -> Result<(), DbErr> {
+    let Some(category_id) = category_id else {
+        return Ok(());
+    };
+    if delta == 0 {
+        return Ok(());
+    }
+
+    db.execute(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        r#"WITH RECURSIVE ancestors AS (
+                SELECT category_id, parent_category_id FROM categories WHERE category_id = $1
+                UNION
+                SELECT c.category_id, c.parent_category_id
+                FROM categories c
+                JOIN ancestors a ON c.category_id = a.parent_category_id
+            )
+            INSERT INTO category_product_counts (category_id, product_count)
+            SELECT category_id, GREATEST($2, 0) FROM ancestors
+            ON CONFLICT (category_id) DO UPDATE
+                SET product_count = GREATEST(category_product_counts.product_count + $2, 0)"#,
+        vec![category_id.into(), delta.into()],
+    ))
+    .await?;
+
+    Ok(())
+}
+
+// a product changing category, nothing happens when it stays where it was
+pub async fn move_category_count<C: ConnectionTrait>(
+    db: &C,
+    from: Option<i32>,
+    to: Option<i32>,
+) -> Result<(), DbErr> {
+    if from == to {
+        return Ok(());
+    }
+    adjust_category_count(db, from, -1).await?;
+    adjust_category_count(db, to, 1).await
+}
+
+// recounts every product still on sale, for backfilling and after the category tree changes
+pub async fn rebuild_category_counts<C: ConnectionTrait + TransactionTrait>(
+    db: &C,
+) -> Result<(), DbErr> {
+    let txn = db.begin().await?;
+    // holds back the incremental updates until the recount is in
+    txn.execute_unprepared("LOCK TABLE category_product_counts IN EXCLUSIVE MODE")
+        .await?;
+    txn.execute_unprepared(
+        r#"WITH RECURSIVE tree AS (
+                SELECT category_id, category_id AS ancestor_id FROM categories
+                UNION
+                SELECT t.category_id, c.parent_category_id
+                FROM tree t
+                JOIN categories c ON c.category_id = t.ancestor_id
+                WHERE c.parent_category_id IS NOT NULL
+            )
+            INSERT INTO category_product_counts (category_id, product_count)
+            SELECT c.category_id, COUNT(p.product_id)
+            FROM categories c
+            LEFT JOIN tree t ON t.ancestor_id = c.category_id
+            LEFT JOIN products p ON p.category_id = t.category_id AND p.archived_at IS NULL
+            GROUP BY c.category_id
+            ON CONFLICT (category_id) DO UPDATE SET product_count = excluded.product_count"#,
+    )
+    .await?;
+    txn.commit().await
+}
@@ -0,0 +1,44 @@
+use crate::entity::category_reassignments::Model as CategoryReassignmentsModel;
+use async_graphql::{InputObject, SimpleObject};
+use sea_orm::prelude::DateTimeWithTimeZone;
+
+#[derive(SimpleObject)]
+pub struct CategoryReassignments {
+    pub reassignment_id: i32,
+    pub from_category_id: i32,
+    pub to_category_id: i32,
+    pub name_contains: Option<String>,
+    pub supplier_id: Option<i32>,
+    pub status: String,
+    // counted when the worker picks the job up, products added to the category afterwards are moved too
+    pub total_products: Option<i32>,
+    pub moved_products: i32,
+    pub error: Option<String>,
+    pub requested_at: DateTimeWithTimeZone,
+    pub finished_at: Option<DateTimeWithTimeZone>,
+}
+
+impl From<CategoryReassignmentsModel> for CategoryReassignments {
+    fn from(val: CategoryReassignmentsModel) -> CategoryReassignments {
+        CategoryReassignments {
+            reassignment_id: val.reassignment_id,
+            from_category_id: val.from_category_id,
+            to_category_id: val.to_category_id,
+            name_contains: val.name_contains,
+            supplier_id: val.supplier_id,
+            status: val.status,
+            total_products: val.total_products,
+            moved_products: val.moved_products,
+            error: val.error,
+            requested_at: val.requested_at,
+            finished_at: val.finished_at,
+        }
+    }
+}
+
+// narrows which products of the source category are moved, everything is moved without one
+#[derive(InputObject)]
+pub struct ReassignProductsFilter {
+    pub name_contains: Option<String>,
+    pub supplier_id: Option<i32>,
+}
@@ -0,0 +1,302 @@
+use crate::{
+    entity::{
+        cart_items, coupon_redemptions, coupons,
+        coupons::Model as CouponsModel,
+        prelude::{
+            CartCoupons as CartCouponsEntity, CartItems as CartItemsEntity,
+            CouponRedemptions as CouponRedemptionsEntity, Coupons as CouponsEntity,
+            Products as ProductsEntity,
+        },
+        products::Model as ProductsModel,
+    },
+    error::AppError,
+    models::regional_prices::{check_same_currency, price_in_region},
+    money::round_amount,
+};
+use async_graphql::{Enum, InputObject, SimpleObject};
+use chrono::Utc;
+use sea_orm::{
+    prelude::{DateTimeWithTimeZone, Decimal},
+    sea_query::Expr,
+    ActiveValue::Set,
+    ColumnTrait, ConnectionTrait, EntityTrait, PaginatorTrait, QueryFilter,
+};
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+pub enum CouponType {
+    Percentage,
+    Fixed,
+}
+
+impl CouponType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CouponType::Percentage => "PERCENTAGE",
+            CouponType::Fixed => "FIXED",
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct Coupons {
+    pub coupon_id: i32,
+    pub code: String,
+    pub description: Option<String>,
+    pub coupon_type: String,
+    // percent off for PERCENTAGE, an amount in the store currency for FIXED
+    pub value: String,
+    pub max_uses: Option<i32>,
+    pub max_uses_per_customer: Option<i32>,
+    pub times_used: i32,
+    pub valid_from: Option<DateTimeWithTimeZone>,
+    pub valid_until: Option<DateTimeWithTimeZone>,
+    // only the category's products are discounted when set
+    pub category_id: Option<i32>,
+    // only the supplier's products are discounted when set
+    pub supplier_id: Option<i32>,
+    pub active: bool,
+    pub created_at: DateTimeWithTimeZone,
+}
+
+impl From<CouponsModel> for Coupons {
+    fn from(val: CouponsModel) -> Coupons {
+        Coupons {
+            coupon_id: val.coupon_id,
+            code: val.code,
+            description: val.description,
+            coupon_type: val.coupon_type,
+            value: val.value.to_string(),
+            max_uses: val.max_uses,
+            max_uses_per_customer: val.max_uses_per_customer,
+            times_used: val.times_used,
+            valid_from: val.valid_from,
+            valid_until: val.valid_until,
+            category_id: val.category_id,
+            supplier_id: val.supplier_id,
+            active: val.active,
+            created_at: val.created_at,
+        }
+    }
+}
+
+#[derive(InputObject)]
+pub struct RegisterCoupon {
+    // matched without regard to case, unique within the marketplace
+    pub code: String,
+    pub description: Option<String>,
+    pub coupon_type: CouponType,
+    pub value: String,
+    pub max_uses: Option<i32>,
+    pub max_uses_per_customer: Option<i32>,
+    pub valid_from: Option<DateTimeWithTimeZone>,
+    pub valid_until: Option<DateTimeWithTimeZone>,
+    pub category_id: Option<i32>,
+    pub supplier_id: Option<i32>,
+}
+
+// the cart priced with its coupon, amounts in currency
+#[derive(SimpleObject)]
+pub struct CartTotals {
+    pub cart_id: i32,
+    pub coupon_code: Option<String>,
+    // the store currency unless the region prices the cart's products in another
+    pub currency: String,
+    pub subtotal: String,
+    // the part of the subtotal the coupon applies to
+    pub eligible_subtotal: String,
+    pub discount: String,
+    pub total: String,
+}
+
+// why the coupon can't be used right now, None when it can. Only ordered coupons are counted,
+// guests can't use coupons limited per customer
+pub async fn coupon_unusable_reason<C: ConnectionTrait>(
+    db: &C,
+    coupon: &CouponsModel,
+    customer_id: Option<i32>,
+) -> Result<Option<String>, AppError> {
+    let now = Utc::now().fixed_offset();
+    if !coupon.active {
+        return Ok(Some("Coupon is no longer active".to_string()));
+    }
+    if coupon.valid_from.is_some_and(|from| now < from) {
+        return Ok(Some("Coupon isn't valid yet".to_string()));
+    }
+    if coupon.valid_until.is_some_and(|until| now >= until) {
+        return Ok(Some("Coupon has expired".to_string()));
+    }
+    if coupon
+        .max_uses
+        .is_some_and(|max_uses| coupon.times_used >= max_uses)
+    {
+        return Ok(Some("Coupon has been used up".to_string()));
+    }
+    if let Some(max_uses) = coupon.max_uses_per_customer {
+        let Some(customer_id) = customer_id else {
+            return Ok(Some("Sign in to use this coupon".to_string()));
+        };
+        let used = CouponRedemptionsEntity::find()
+            .filter(coupon_redemptions::Column::CouponId.eq(coupon.coupon_id))
+            .filter(coupon_redemptions::Column::CustomerId.eq(customer_id))
+            .count(db)
+            .await?;
+        if used >= max_uses as u64 {
+            return Ok(Some(
+                "Coupon has already been used as often as allowed".to_string(),
+            ));
+        }
+    }
+
+    Ok(None)
+}
+
+// whether the product is in the coupon's category and supplier scope
+pub fn coupon_covers(coupon: &CouponsModel, product: &ProductsModel) -> bool {
+    coupon
+        .category_id
+        .is_none_or(|category_id| product.category_id == Some(category_id))
+        && coupon
+            .supplier_id
+            .is_none_or(|supplier_id| product.supplier_id == Some(supplier_id))
+}
+
+// what the coupon takes off the covered lines, a fixed coupon never more than they cost. Fixed
+// coupons are an amount in the store currency and don't apply to lines priced in another
+pub fn coupon_discount(
+    coupon: &CouponsModel,
+    eligible_subtotal: Decimal,
+    currency: &str,
+    store_currency: &str,
+) -> Result<Decimal, AppError> {
+    if coupon.coupon_type == CouponType::Percentage.as_str() {
+        Ok(round_amount(
+            eligible_subtotal * coupon.value / Decimal::ONE_HUNDRED,
+            currency,
+        ))
+    } else if currency != store_currency {
+        Err(AppError::invalid(format!(
+            "Coupon {} is in {} and can't be used on an order in {}",
+            coupon.code, store_currency, currency
+        )))
+    } else {
+        Ok(coupon.value.min(eligible_subtotal))
+    }
+}
+
+// the coupon applied to the cart, whether or not it can still be used
+pub async fn cart_coupon<C: ConnectionTrait>(
+    db: &C,
+    cart_id: i32,
+) -> Result<Option<CouponsModel>, AppError> {
+    Ok(CartCouponsEntity::find_by_id(cart_id)
+        .find_also_related(CouponsEntity)
+        .one(db)
+        .await?
+        .and_then(|(_, coupon)| coupon))
+}
+
+// counts the coupon against its limits for the order, call it in the transaction placing the
+// order with the coupon row locked
+pub async fn redeem_coupon<C: ConnectionTrait>(
+    db: &C,
+    coupon: &CouponsModel,
+    customer_id: Option<i32>,
+    order_id: i32,
+    discount: Decimal,
+) -> Result<(), AppError> {
+    CouponRedemptionsEntity::insert(coupon_redemptions::ActiveModel {
+        coupon_id: Set(coupon.coupon_id),
+        customer_id: Set(customer_id),
+        order_id: Set(order_id),
+        discount_amount: Set(discount),
+        ..Default::default()
+    })
+    .exec(db)
+    .await?;
+    CouponsEntity::update_many()
+        .col_expr(
+            coupons::Column::TimesUsed,
+            Expr::col(coupons::Column::TimesUsed).add(1),
+        )
+        .filter(coupons::Column::CouponId.eq(coupon.coupon_id))
+        .exec(db)
+        .await?;
+    Ok(())
+}
+
+// gives the use back when an order that never got paid is voided
+pub async fn release_redemption<C: ConnectionTrait>(db: &C, order_id: i32) -> Result<(), AppError> {
+    let Some(redemption) = CouponRedemptionsEntity::find()
+        .filter(coupon_redemptions::Column::OrderId.eq(order_id))
+        .one(db)
+        .await?
+    else {
+        return Ok(());
+    };
+    CouponRedemptionsEntity::delete_by_id(redemption.redemption_id)
+        .exec(db)
+        .await?;
+    CouponsEntity::update_many()
+        .col_expr(
+            coupons::Column::TimesUsed,
+            Expr::col(coupons::Column::TimesUsed).sub(1),
+        )
+        .filter(coupons::Column::CouponId.eq(redemption.coupon_id))
+        .filter(coupons::Column::TimesUsed.gt(0))
+        .exec(db)
+        .await?;
+    Ok(())
+}
+
+// prices the cart's items as an order would and takes the coupon off the items in its scope
+pub async fn cart_totals<C: ConnectionTrait>(
+    db: &C,
+    cart_id: i32,
+    coupon: Option<&CouponsModel>,
+    region: Option<&str>,
+    store_currency: &str,
+) -> Result<CartTotals, AppError> {
+    let mut subtotal = Decimal::ZERO;
+    let mut eligible_subtotal = Decimal::ZERO;
+    let mut currency = None;
+    for (item, product) in CartItemsEntity::find()
+        .filter(cart_items::Column::CartId.eq(cart_id))
+        .find_also_related(ProductsEntity)
+        .all(db)
+        .await?
+    {
+        let Some(product) = product else {
+            continue;
+        };
+        let (unit_price, line_currency) = price_in_region(
+            db,
+            product.product_id,
+            product.base_price,
+            region,
+            store_currency,
+        )
+        .await?;
+        check_same_currency(&mut currency, line_currency)?;
+        let line = unit_price * Decimal::from(item.quantity);
+        subtotal += line;
+        if coupon.is_some_and(|coupon| coupon_covers(coupon, &product)) {
+            eligible_subtotal += line;
+        }
+    }
+
+    let currency = currency.unwrap_or_else(|| store_currency.to_string());
+    let discount = match coupon {
+        Some(coupon) => coupon_discount(coupon, eligible_subtotal, &currency, store_currency)?,
+        None => Decimal::ZERO,
+    };
+
+    Ok(CartTotals {
+        cart_id,
+        coupon_code: coupon.map(|coupon| coupon.code.clone()),
+        currency,
+        subtotal: subtotal.to_string(),
+        eligible_subtotal: eligible_subtotal.to_string(),
+        discount: discount.to_string(),
+        total: (subtotal - discount).to_string(),
+    })
+}
@@ -0,0 +1,145 @@
+use crate::{
+    entity::{bills, disputes::Model as DisputesModel, prelude::Bills},
+    error::AppError,
+    payment_gateway::PaymentGateway,
+};
+use async_graphql::{Enum, SimpleObject};
+use chrono::{Duration, Utc};
+use sea_orm::{
+    prelude::DateTimeWithTimeZone, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter,
+};
+use std::collections::HashMap;
+
+#[derive(SimpleObject)]
+pub struct Disputes {
+    pub dispute_id: i32,
+    pub provider: String,
+    pub provider_dispute_id: String,
+    pub provider_payment_id: String,
+    pub order_id: Option<i32>,
+    pub amount: f64,
+    pub reason: Option<String>,
+    pub status: String,
+    pub opened_at: DateTimeWithTimeZone,
+    pub updated_at: DateTimeWithTimeZone,
+}
+
+impl From<DisputesModel> for Disputes {
+    fn from(val: DisputesModel) -> Disputes {
+        Disputes {
+            dispute_id: val.dispute_id,
+            provider: val.provider,
+            provider_dispute_id: val.provider_dispute_id,
+            provider_payment_id: val.provider_payment_id,
+            order_id: val.order_id,
+            amount: val.amount.to_string().parse::<f64>().unwrap(),
+            reason: val.reason,
+            status: val.status,
+            opened_at: val.opened_at,
+            updated_at: val.updated_at,
+        }
+    }
+}
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+pub enum ReconciliationIssue {
+    // charged at the provider but no bill points at it
+    MissingLocally,
+    // a bill references a payment the provider doesn't list
+    MissingAtProvider,
+    AmountMismatch,
+    StatusMismatch,
+}
+
+#[derive(SimpleObject)]
+pub struct ReconciliationEntry {
+    pub provider_payment_id: String,
+    pub issue: ReconciliationIssue,
+    pub order_id: Option<i32>,
+    pub local_amount: Option<f64>,
+    pub provider_amount: Option<f64>,
+    pub local_status: Option<String>,
+    pub provider_status: Option<String>,
+}
+
+// only the payments that disagree are returned
+pub async fn reconcile_payments(
+    db: &DatabaseConnection,
+    gateway: &dyn PaymentGateway,
+    days: i64,
+) -> Result<Vec<ReconciliationEntry>, AppError> {
+    let since = Utc::now() - Duration::days(days);
+
+    let mut provider_payments: HashMap<String, _> = gateway
+        .list_payments(since)
+        .await?
+        .into_iter()
+        .map(|payment| (payment.provider_payment_id.clone(), payment))
+        .collect();
+
+    let local_bills = Bills::find()
+        .filter(bills::Column::ProviderPaymentId.is_not_null())
+        .filter(bills::Column::BillDate.gte(since.fixed_offset()))
+        .all(db)
+        .await?;
+
+    let mut entries = Vec::new();
+    for bill in local_bills {
+        let Some(provider_payment_id) = bill.provider_payment_id.clone() else {
+            continue;
+        };
+        let local_amount = Some(bill.total_amount.to_string().parse::<f64>().unwrap());
+
+        let Some(payment) = provider_payments.remove(&provider_payment_id) else {
+            entries.push(ReconciliationEntry {
+                provider_payment_id,
+                issue: ReconciliationIssue::MissingAtProvider,
+                order_id: Some(bill.order_id),
+                local_amount,
+                provider_amount: None,
+                local_status: Some(bill.payment_status),
+                provider_status: None,
+            });
+            continue;
+        };
+
+        // disputed bills legitimately drift from the original charge status
+        let issue = if payment.amount != bill.total_amount {
+            Some(ReconciliationIssue::AmountMismatch)
+        } else if payment.status.as_str() != bill.payment_status
+            && !matches!(bill.payment_status.as_str(), "DISPUTED" | "CHARGED_BACK")
+        {
+            Some(ReconciliationIssue::StatusMismatch)
+        } else {
+            None
+        };
+
+        if let Some(issue) = issue {
+            entries.push(ReconciliationEntry {
+                provider_payment_id,
+                issue,
+                order_id: Some(bill.order_id),
+                local_amount,
+                provider_amount: Some(payment.amount.to_string().parse::<f64>().unwrap()),
+                local_status: Some(bill.payment_status),
+                provider_status: Some(payment.status.as_str().to_string()),
+            });
+        }
+    }
+
+    entries.extend(
+        provider_payments
+            .into_values()
+            .map(|payment| ReconciliationEntry {
+                provider_payment_id: payment.provider_payment_id,
+                issue: ReconciliationIssue::MissingLocally,
+                order_id: None,
+                local_amount: None,
+                provider_amount: Some(payment.amount.to_string().parse::<f64>().unwrap()),
+                local_status: None,
+                provider_status: Some(payment.status.as_str().to_string()),
+            }),
+    );
+
+    Ok(entries)
+}
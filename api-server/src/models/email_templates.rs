@@ -0,0 +1,44 @@
+use crate::{entity::email_templates::Model as EmailTemplatesModel, notifications::TemplateKey};
+use async_graphql::{InputObject, SimpleObject};
+use sea_orm::prelude::DateTimeWithTimeZone;
+
+#[derive(SimpleObject)]
+pub struct EmailTemplates {
+    pub email_template_id: i32,
+    pub template_key: String,
+    pub locale: String,
+    pub subject: String,
+    pub html_body: String,
+    pub updated_at: DateTimeWithTimeZone,
+}
+
+impl From<EmailTemplatesModel> for EmailTemplates {
+    fn from(val: EmailTemplatesModel) -> EmailTemplates {
+        EmailTemplates {
+            email_template_id: val.email_template_id,
+            template_key: val.template_key,
+            locale: val.locale,
+            subject: val.subject,
+            html_body: val.html_body,
+            updated_at: val.updated_at,
+        }
+    }
+}
+
+#[derive(InputObject)]
+pub struct RegisterEmailTemplate {
+    pub template_key: TemplateKey,
+    pub locale: String,
+    pub subject: String,
+    // {{placeholder}} values are HTML escaped when rendered
+    pub html_body: String,
+}
+
+#[derive(SimpleObject)]
+pub struct RenderedEmail {
+    pub template_key: String,
+    // locale of the variant that was picked after falling back
+    pub locale: String,
+    pub subject: String,
+    pub html_body: String,
+}
@@ -0,0 +1,43 @@
+use crate::ids::OrderId;
+use async_graphql::SimpleObject;
+use chrono::NaiveDate;
+
+#[derive(SimpleObject)]
+pub struct FulfillmentDocument {
+    // a link to the PDF that works for an hour
+    pub url: String,
+    // the orders it covers, in page order
+    pub order_ids: Vec<OrderId>,
+}
+
+#[derive(SimpleObject)]
+pub struct FulfillmentBatch {
+    pub date: NaiveDate,
+    // null when none of the day's orders are waiting to be shipped
+    pub packing_slips: Option<FulfillmentDocument>,
+    // one per order, only when a carrier was given
+    pub labels: Vec<FulfillmentDocument>,
+}
+
+#[derive(SimpleObject, Clone)]
+pub struct CustomsItem {
+    pub product_id: i32,
+    pub description: String,
+    pub quantity: i32,
+    pub hs_code: String,
+    pub origin_country: String,
+    // per unit, as declared at checkout
+    pub unit_value: String,
+    pub total_value: String,
+}
+
+// one supplier's parcel of an order going abroad, what a commercial invoice or CN23 lists
+#[derive(SimpleObject, Clone)]
+pub struct CustomsDeclaration {
+    pub order_id: OrderId,
+    pub destination_country: String,
+    pub items: Vec<CustomsItem>,
+    pub total_value: String,
+    // the values above are in it, rounded to its minor unit
+    pub currency: String,
+}
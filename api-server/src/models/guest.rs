@@ -0,0 +1,126 @@
+use crate::carriers::{Shipment, SupplierShipment};
+use crate::entity::orders::Model as OrdersModel;
+use crate::ids::{global_id, OrderId};
+use crate::models::availability::BookingDate;
+use crate::models::money::Money;
+use crate::models::orders::{money, order_shipments};
+use crate::pii::{pii, PiiKind};
+use async_graphql::{ComplexObject, Context, InputObject, SimpleObject, ID};
+use sea_orm::{prelude::DateTimeWithTimeZone, DatabaseConnection};
+
+#[derive(SimpleObject)]
+pub struct GuestCart {
+    // only returned here, the client sends it back with every guest cart call
+    #[graphql(directive = pii::apply(PiiKind::Secret))]
+    pub guest_token: String,
+    pub cart_id: i32,
+}
+
+#[derive(InputObject)]
+pub struct GuestAddress {
+    #[graphql(directive = pii::apply(PiiKind::Address))]
+    pub street_address: String,
+    #[graphql(directive = pii::apply(PiiKind::Address))]
+    pub city: String,
+    pub state: Option<String>,
+    #[graphql(directive = pii::apply(PiiKind::Address))]
+    pub postal_code: String,
+    pub country: String,
+}
+
+#[derive(InputObject)]
+pub struct GuestCheckout {
+    #[graphql(directive = pii::apply(PiiKind::Secret))]
+    pub guest_token: String,
+    #[graphql(directive = pii::apply(PiiKind::Email))]
+    pub email: String,
+    pub shipping_address: GuestAddress,
+    // created client side with the provider's SDK, never stored for guests
+    #[graphql(directive = pii::apply(PiiKind::Secret))]
+    pub provider_token: String,
+    pub discount_code: Option<String>,
+    pub locale: Option<String>,
+    // priced like the catalog for the same region, ignored by pinned deployments
+    pub region: Option<String>,
+    // a date for every date bookable product in the cart
+    #[graphql(default)]
+    pub booking_dates: Vec<BookingDate>,
+}
+
+// an order placed at guest checkout, it never has a saved payment method and only has a customer
+// once it was claimed
+#[derive(SimpleObject)]
+#[graphql(complex)]
+pub struct GuestOrder {
+    pub order_id: OrderId,
+    pub customer_id: Option<i32>,
+    #[graphql(directive = pii::apply(PiiKind::Email))]
+    pub guest_email: Option<String>,
+    pub order_date: Option<DateTimeWithTimeZone>,
+    pub total_amount: f64,
+    pub status: String,
+    pub shipping_address_id: i32,
+    pub discount_id: Option<i32>,
+    pub region: Option<String>,
+    pub tax_amount: f64,
+    pub payout_frozen: bool,
+    pub shipment: Option<Shipment>,
+    pub shipped_at: Option<DateTimeWithTimeZone>,
+    pub currency: String,
+}
+
+impl From<OrdersModel> for GuestOrder {
+    fn from(val: OrdersModel) -> GuestOrder {
+        GuestOrder {
+            order_id: val.order_id.into(),
+            customer_id: val.customer_id,
+            guest_email: val.guest_email,
+            order_date: val.order_date,
+            total_amount: val.total_amount.to_string().parse::<f64>().unwrap(),
+            status: val.status,
+            shipping_address_id: val.shipping_address_id,
+            discount_id: val.discount_id,
+            region: val.region,
+            tax_amount: val.tax_amount.to_string().parse::<f64>().unwrap(),
+            payout_frozen: val.payout_frozen,
+            shipment: Shipment::from_order(val.carrier.as_deref(), val.tracking_number),
+            shipped_at: val.shipped_at,
+            currency: val.currency,
+        }
+    }
+}
+
+#[ComplexObject]
+impl GuestOrder {
+    // the same id space as Orders, node(id) resolves either
+    pub async fn id(&self) -> ID {
+        global_id("Orders", self.order_id.0)
+    }
+
+    async fn total(&self, locale: Option<String>) -> Money {
+        money(self.total_amount, &self.currency, locale)
+    }
+
+    async fn tax(&self, locale: Option<String>) -> Money {
+        money(self.tax_amount, &self.currency, locale)
+    }
+
+    async fn shipments(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<Vec<SupplierShipment>, async_graphql::Error> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        Ok(order_shipments(db, self.order_id.0).await?)
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct GuestCheckoutResult {
+    pub order: GuestOrder,
+    pub payment_status: String,
+    #[graphql(directive = pii::apply(PiiKind::Secret))]
+    pub client_secret: Option<String>,
+    // signed token for guestOrder, also mailed to the guest as a link
+    #[graphql(directive = pii::apply(PiiKind::Secret))]
+    pub lookup_token: String,
+}
@@ -0,0 +1,95 @@
+use crate::{
+    entity::{
+        image_zip_job_results::Model as ImageZipJobResultsModel,
+        image_zip_jobs::Model as ImageZipJobsModel,
+    },
+    graphql::complexity,
+};
+use async_graphql::{ComplexObject, Context, SimpleObject};
+use sea_orm::{
+    prelude::DateTimeWithTimeZone, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter,
+    QueryOrder,
+};
+
+#[derive(SimpleObject)]
+#[graphql(complex)]
+pub struct ImageZipJobs {
+    pub job_id: i32,
+    pub file_name: String,
+    pub status: String,
+    // 0 until the archive is unpacked, directories and hidden files aren't counted
+    pub total_files: i32,
+    pub attached_files: i32,
+    // why the whole archive failed, problems with single files are in the results
+    pub error: Option<String>,
+    pub requested_at: DateTimeWithTimeZone,
+    pub finished_at: Option<DateTimeWithTimeZone>,
+}
+
+impl From<ImageZipJobsModel> for ImageZipJobs {
+    fn from(val: ImageZipJobsModel) -> ImageZipJobs {
+        ImageZipJobs {
+            job_id: val.job_id,
+            file_name: val.file_name,
+            status: val.status,
+            total_files: val.total_files,
+            attached_files: val.attached_files,
+            error: val.error,
+            requested_at: val.requested_at,
+            finished_at: val.finished_at,
+        }
+    }
+}
+
+#[ComplexObject]
+impl ImageZipJobs {
+    // the per file report, onlyProblems leaves out the images that were attached
+    #[graphql(complexity = "complexity::unbounded(child_complexity)")]
+    async fn results(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = false)] only_problems: bool,
+    ) -> Result<Vec<ImageZipJobResults>, async_graphql::Error> {
+        use crate::entity::{
+            image_zip_job_results, prelude::ImageZipJobResults as ImageZipJobResultsEntity,
+        };
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        let mut results = ImageZipJobResultsEntity::find()
+            .filter(image_zip_job_results::Column::JobId.eq(self.job_id));
+        if only_problems {
+            results = results.filter(image_zip_job_results::Column::Outcome.ne("ATTACHED"));
+        }
+
+        Ok(results
+            .order_by_asc(image_zip_job_results::Column::EntryName)
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|result| result.into())
+            .collect())
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct ImageZipJobResults {
+    // the path of the file inside the archive
+    pub entry_name: String,
+    pub sku: Option<String>,
+    pub product_id: Option<i32>,
+    // ATTACHED, NO_MATCHING_SKU, REJECTED when the file isn't an acceptable image, or FAILED
+    pub outcome: String,
+    pub message: Option<String>,
+}
+
+impl From<ImageZipJobResultsModel> for ImageZipJobResults {
+    fn from(val: ImageZipJobResultsModel) -> ImageZipJobResults {
+        ImageZipJobResults {
+            entry_name: val.entry_name,
+            sku: val.sku,
+            product_id: val.product_id,
+            outcome: val.outcome,
+            message: val.message,
+        }
+    }
+}
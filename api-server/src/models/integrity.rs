@@ -0,0 +1,54 @@
+use crate::entity::{
+    integrity_checks::Model as IntegrityChecksModel,
+    integrity_findings::Model as IntegrityFindingsModel,
+};
+use async_graphql::SimpleObject;
+use sea_orm::prelude::DateTimeWithTimeZone;
+
+#[derive(SimpleObject)]
+pub struct IntegrityChecks {
+    pub integrity_check_id: i32,
+    pub status: String,
+    // set once the check is done
+    pub finding_count: Option<i32>,
+    pub error: Option<String>,
+    pub requested_by: Option<i32>,
+    pub requested_at: DateTimeWithTimeZone,
+    pub finished_at: Option<DateTimeWithTimeZone>,
+}
+
+impl From<IntegrityChecksModel> for IntegrityChecks {
+    fn from(val: IntegrityChecksModel) -> IntegrityChecks {
+        IntegrityChecks {
+            integrity_check_id: val.integrity_check_id,
+            status: val.status,
+            finding_count: val.finding_count,
+            error: val.error,
+            requested_by: val.requested_by,
+            requested_at: val.requested_at,
+            finished_at: val.finished_at,
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct IntegrityFindings {
+    pub integrity_finding_id: i32,
+    pub kind: String,
+    // the table and primary key of the row at fault, "productId:date" for booking days
+    pub table_name: String,
+    pub row_ref: String,
+    pub detail: String,
+}
+
+impl From<IntegrityFindingsModel> for IntegrityFindings {
+    fn from(val: IntegrityFindingsModel) -> IntegrityFindings {
+        IntegrityFindings {
+            integrity_finding_id: val.integrity_finding_id,
+            kind: val.kind,
+            table_name: val.table_name,
+            row_ref: val.row_ref,
+            detail: val.detail,
+        }
+    }
+}
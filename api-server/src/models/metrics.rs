@@ -0,0 +1,19 @@
+use async_graphql::SimpleObject;
+
+#[derive(SimpleObject)]
+pub struct RetryMetrics {
+    pub operation: String,
+    pub retries: u64,
+    pub recovered: u64,
+    pub exhausted: u64,
+}
+
+#[derive(SimpleObject)]
+pub struct CartExpiryMetrics {
+    pub carts_expired: u64,
+    // units, not lines
+    pub items_expired: u64,
+    // what the expired carts held at base prices
+    pub value_expired: String,
+    pub carts_restored: u64,
+}
@@ -1,45 +1,89 @@
+pub mod accounting;
+pub mod activity;
 pub mod addresses;
+pub mod analytics;
+pub mod announcements;
+pub mod api_keys;
+pub mod availability;
 pub mod bills;
+pub mod boost_rules;
+pub mod bulk_user_jobs;
+pub mod cache_warming;
+pub mod canned_responses;
 pub mod carts;
+pub mod catalog_imports;
+pub mod category_attributes;
+pub mod category_counts;
+pub mod category_reassignments;
+pub mod coupons;
+pub mod disputes;
+pub mod email_templates;
+pub mod fulfillment;
+pub mod guest;
+pub mod image_zip_jobs;
+pub mod integrity;
+pub mod metrics;
+pub mod money;
+pub mod newsletter;
+pub mod node;
+pub mod onboarding;
+pub mod order_messages;
+pub mod order_status;
+pub mod order_timeline;
 pub mod orders;
 pub mod payments;
+pub mod price_history;
+pub mod product_images;
 pub mod products;
+pub mod purchase_limits;
+pub mod rate_limits;
+pub mod refunds;
+pub mod regional_prices;
+pub mod retention;
+pub mod returns;
+pub mod review_summaries;
+pub mod sponsorships;
+pub mod supplier_agreements;
+pub mod sync;
+pub mod terms;
 pub mod user;
+pub mod videos;
 
 pub mod order_und_pagination {
     use async_graphql::{Enum, InputObject, SimpleObject};
+    use serde::{Deserialize, Serialize};
 
-    #[derive(InputObject)]
+    #[derive(InputObject, Serialize, Clone)]
     pub struct Pagination {
         pub page: u64,
         pub page_size: u64,
     }
 
-    #[derive(Enum, Copy, Clone, Eq, PartialEq)]
+    #[derive(Enum, Copy, Clone, Eq, PartialEq, Serialize)]
     pub enum OrderByColumn {
         Date,
         Amount,
     }
 
-    #[derive(Enum, Copy, Clone, Eq, PartialEq)]
+    #[derive(Enum, Copy, Clone, Eq, PartialEq, Serialize)]
     pub enum OrderByOrder {
         Asc,
         Desc,
     }
 
-    #[derive(InputObject)]
+    #[derive(InputObject, Serialize, Clone)]
     pub struct OrderBy {
         pub column: OrderByColumn,
         pub order: OrderByOrder,
     }
 
-    #[derive(InputObject)]
+    #[derive(InputObject, Serialize, Clone)]
     pub struct OrderAndPagination {
         pub order_by: OrderBy,
         pub pagination: Pagination,
     }
 
-    #[derive(SimpleObject)]
+    #[derive(SimpleObject, Serialize, Deserialize)]
     pub struct PageInfo {
         pub total_pages: u64,
         pub total_items: u64,
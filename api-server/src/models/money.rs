@@ -0,0 +1,26 @@
+use crate::money::{format_money, round_amount};
+use async_graphql::SimpleObject;
+use sea_orm::prelude::Decimal;
+
+// an amount together with its currency, rounded and formatted by the currency's rules
+#[derive(SimpleObject)]
+pub struct Money {
+    pub amount: f64,
+    // ISO 4217 code
+    pub currency: String,
+    // for display in the requested locale, e.g. "CHF 1,234.50" or "1.234,50 EUR"
+    pub formatted: String,
+}
+
+impl Money {
+    pub fn new(amount: Decimal, currency: &str, locale: &str) -> Self {
+        Money {
+            amount: round_amount(amount, currency)
+                .to_string()
+                .parse::<f64>()
+                .unwrap_or_default(),
+            currency: currency.to_string(),
+            formatted: format_money(amount, currency, locale),
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use crate::{
+    entity::{
+        email_suppressions::Model as EmailSuppressionsModel,
+        newsletter_subscriptions::Model as NewsletterSubscriptionsModel,
+    },
+    newsletter::sign_unsubscribe_token,
+    pii::{pii, PiiKind},
+};
+use async_graphql::{ComplexObject, Enum, ErrorExtensions, SimpleObject};
+use sea_orm::prelude::DateTimeWithTimeZone;
+
+#[derive(SimpleObject)]
+#[graphql(complex)]
+pub struct NewsletterSubscriptions {
+    pub subscription_id: i32,
+    #[graphql(directive = pii::apply(PiiKind::Email))]
+    pub email: String,
+    // PENDING until the confirmation link was opened, only SUBSCRIBED addresses may be mailed
+    pub status: String,
+    pub locale: Option<String>,
+    pub confirmed_at: Option<DateTimeWithTimeZone>,
+    pub unsubscribed_at: Option<DateTimeWithTimeZone>,
+    pub updated_at: DateTimeWithTimeZone,
+}
+
+impl From<NewsletterSubscriptionsModel> for NewsletterSubscriptions {
+    fn from(val: NewsletterSubscriptionsModel) -> NewsletterSubscriptions {
+        NewsletterSubscriptions {
+            subscription_id: val.subscription_id,
+            email: val.email,
+            status: val.status,
+            locale: val.locale,
+            confirmed_at: val.confirmed_at,
+            unsubscribed_at: val.unsubscribed_at,
+            updated_at: val.updated_at,
+        }
+    }
+}
+
+#[ComplexObject]
+impl NewsletterSubscriptions {
+    // for the unsubscribe links the ESP puts in every newsletter, goes to unsubscribeNewsletter
+    #[graphql(directive = pii::apply(PiiKind::Secret))]
+    async fn unsubscribe_token(&self) -> Result<String, async_graphql::Error> {
+        sign_unsubscribe_token(self.subscription_id).map_err(|e| e.extend())
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct NewsletterSubscriptionsSync {
+    pub subscriptions: Vec<NewsletterSubscriptions>,
+    pub next_cursor: Option<String>,
+    pub has_more: bool,
+}
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+pub enum SuppressionReason {
+    Bounced,
+    Complained,
+    Manual,
+}
+
+impl SuppressionReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SuppressionReason::Bounced => "BOUNCED",
+            SuppressionReason::Complained => "COMPLAINED",
+            SuppressionReason::Manual => "MANUAL",
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct EmailSuppressions {
+    #[graphql(directive = pii::apply(PiiKind::Email))]
+    pub email: String,
+    pub reason: String,
+    pub created_at: DateTimeWithTimeZone,
+}
+
+impl From<EmailSuppressionsModel> for EmailSuppressions {
+    fn from(val: EmailSuppressionsModel) -> EmailSuppressions {
+        EmailSuppressions {
+            email: val.email,
+            reason: val.reason,
+            created_at: val.created_at,
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use crate::{
+    auth::AuthenticatedUser,
+    entity::prelude::{Orders as OrdersEntity, Products as ProductsEntity, Users as UsersEntity},
+    ids::{parse_global_id, OrderId, ProductId, UserId},
+    models::{
+        guest::GuestOrder,
+        order_messages::check_order_participant,
+        orders::{Orders, PlacedOrder},
+        products::{category_tree, on_sale, Categories, Products},
+        user::Users,
+    },
+    tenancy::{current_tenant, TenantScope},
+};
+use async_graphql::{Context, Interface, ID};
+use sea_orm::{DatabaseConnection, EntityTrait};
+
+// the Relay Node interface, every object with a global id that node() can fetch again. id is
+// the Relay global id, node(id) gives the object back
+#[derive(Interface)]
+#[graphql(field(name = "id", ty = "ID"))]
+pub enum Node {
+    Categories(Categories),
+    GuestOrder(GuestOrder),
+    Orders(Orders),
+    Products(Products),
+    Users(Users),
+}
+
+// null for ids that don't resolve to anything the caller may see, the same as a missing object,
+// so node() can't be used to probe for orders or accounts of others
+pub async fn load_node(ctx: &Context<'_>, id: &ID) -> Result<Option<Node>, async_graphql::Error> {
+    let Some((type_name, key)) = parse_global_id(id) else {
+        return Ok(None);
+    };
+    let db = ctx.data::<DatabaseConnection>()?;
+    let tenant = current_tenant(ctx);
+
+    let node = match type_name.as_str() {
+        "Products" => visible_product(ctx, ProductId(key))
+            .await?
+            .map(Node::Products),
+        "Categories" => category_tree(ctx)
+            .await?
+            .into_iter()
+            .find(|category| category.category_id == key)
+            .map(Node::Categories),
+        "Orders" => {
+            if check_order_participant(db, ctx, OrderId(key)).await.is_ok() {
+                OrdersEntity::find_by_id(key)
+                    .for_tenant(tenant)
+                    .one(db)
+                    .await?
+                    .map(|order| match order.into() {
+                        PlacedOrder::Orders(order) => Node::Orders(order),
+                        PlacedOrder::GuestOrder(order) => Node::GuestOrder(order),
+                    })
+            } else {
+                None
+            }
+        }
+        "Users" => own_user(ctx, UserId(key)).await?.map(Node::Users),
+        _ => None,
+    };
+
+    Ok(node)
+}
+
+// a product as productsWithId lists it, in the request's tenant and not archived
+pub async fn visible_product(
+    ctx: &Context<'_>,
+    product_id: ProductId,
+) -> Result<Option<Products>, async_graphql::Error> {
+    let db = ctx.data::<DatabaseConnection>()?;
+
+    Ok(
+        on_sale(ProductsEntity::find_by_id(product_id).for_tenant(current_tenant(ctx)))
+            .one(db)
+            .await?
+            .map(|product| product.into()),
+    )
+}
+
+// only the caller's own account, as getUser
+pub async fn own_user(
+    ctx: &Context<'_>,
+    user_id: UserId,
+) -> Result<Option<Users>, async_graphql::Error> {
+    let db = ctx.data::<DatabaseConnection>()?;
+    let claims = AuthenticatedUser::from_ctx(ctx).ok();
+    if claims.is_none_or(|claims| claims.user_id != user_id.to_string()) {
+        return Ok(None);
+    }
+
+    Ok(UsersEntity::find_by_id(user_id.0)
+        .for_tenant(current_tenant(ctx))
+        .one(db)
+        .await?
+        .map(|user| user.into()))
+}
@@ -0,0 +1,131 @@
+use crate::{
+    entity::{
+        prelude::{Products as ProductsEntity, Users as UsersEntity},
+        products,
+        suppliers::Model as SuppliersModel,
+    },
+    models::supplier_agreements::active_agreement,
+};
+use async_graphql::{Enum, SimpleObject};
+use chrono::Utc;
+use lazy_regex::regex;
+use sea_orm::{ColumnTrait, DatabaseConnection, DbErr, EntityTrait, PaginatorTrait, QueryFilter};
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+pub enum OnboardingStepKind {
+    ProfileComplete,
+    EmailVerified,
+    FirstProduct,
+    PayoutDetails,
+    CommissionAgreement,
+}
+
+impl OnboardingStepKind {
+    fn title(&self) -> &'static str {
+        match self {
+            OnboardingStepKind::ProfileComplete => "Complete your supplier profile",
+            OnboardingStepKind::EmailVerified => "Verify your email address",
+            OnboardingStepKind::FirstProduct => "List your first product",
+            OnboardingStepKind::PayoutDetails => "Add payout details",
+            OnboardingStepKind::CommissionAgreement => "Sign the commission agreement",
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct OnboardingStep {
+    pub step: OnboardingStepKind,
+    pub title: String,
+    pub completed: bool,
+}
+
+#[derive(SimpleObject)]
+pub struct OnboardingStatus {
+    // in the order a guided setup should walk through them
+    pub steps: Vec<OnboardingStep>,
+    pub completed_steps: i32,
+    pub total_steps: i32,
+    pub complete: bool,
+}
+
+// computed on every request, nothing about onboarding is stored
+pub async fn onboarding_status(
+    db: &DatabaseConnection,
+    supplier: &SuppliersModel,
+) -> Result<OnboardingStatus, DbErr> {
+    let email_verified = UsersEntity::find_by_id(supplier.user_id)
+        .one(db)
+        .await?
+        .is_some_and(|user| user.email_verified);
+
+    let has_product = ProductsEntity::find()
+        .filter(products::Column::SupplierId.eq(supplier.supplier_id))
+        .count(db)
+        .await?
+        > 0;
+
+    // payouts are held until an agreement is in effect
+    let has_agreement = active_agreement(db, supplier.supplier_id, Utc::now().date_naive())
+        .await?
+        .is_some();
+
+    let profile_complete = !supplier.name.trim().is_empty()
+        && supplier
+            .contact_phone
+            .as_deref()
+            .is_some_and(|phone| !phone.trim().is_empty())
+        && supplier.region.is_some();
+
+    let steps: Vec<OnboardingStep> = [
+        (OnboardingStepKind::ProfileComplete, profile_complete),
+        (OnboardingStepKind::EmailVerified, email_verified),
+        (OnboardingStepKind::FirstProduct, has_product),
+        (
+            OnboardingStepKind::PayoutDetails,
+            supplier.payout_iban.is_some(),
+        ),
+        (OnboardingStepKind::CommissionAgreement, has_agreement),
+    ]
+    .into_iter()
+    .map(|(step, completed)| OnboardingStep {
+        step,
+        title: step.title().to_string(),
+        completed,
+    })
+    .collect();
+
+    let completed_steps = steps.iter().filter(|step| step.completed).count() as i32;
+    let total_steps = steps.len() as i32;
+
+    Ok(OnboardingStatus {
+        steps,
+        completed_steps,
+        total_steps,
+        complete: completed_steps == total_steps,
+    })
+}
+
+// strips spacing and checks the ISO 13616 mod 97 checksum
+pub fn normalize_iban(iban: &str) -> Option<String> {
+    let iban: String = iban
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_uppercase();
+
+    if !regex!(r"^[A-Z]{2}[0-9]{2}[A-Z0-9]{11,30}$").is_match(&iban) {
+        return None;
+    }
+
+    let (head, tail) = iban.split_at(4);
+    let remainder = tail.chars().chain(head.chars()).fold(0u32, |acc, c| {
+        let value = c.to_digit(36).unwrap_or(0);
+        if value >= 10 {
+            (acc * 100 + value) % 97
+        } else {
+            (acc * 10 + value) % 97
+        }
+    });
+
+    (remainder == 1).then_some(iban)
+}
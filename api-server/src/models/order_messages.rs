@@ -0,0 +1,129 @@
+use crate::{
+    auth::{AuthenticatedUser, Claims, ROLE_ADMIN, ROLE_CUSTOMER, ROLE_SUPPLIER},
+    entity::order_messages::Model as OrderMessagesModel,
+    error::AppError,
+    ids::{OrderId, UserId},
+    models::user::get_customer_supplier_id,
+    permissions::caller_permissions,
+    storage::Storage,
+    tenancy::{current_tenant, TenantScope},
+};
+use async_graphql::{ComplexObject, Context, Error, ErrorExtensions, SimpleObject};
+use chrono::Duration;
+use sea_orm::{
+    prelude::DateTimeWithTimeZone, ColumnTrait, DatabaseConnection, EntityTrait, JoinType,
+    QueryFilter, QuerySelect, RelationTrait,
+};
+use std::sync::Arc;
+
+// how long the attachment links handed out with a message keep working
+const ATTACHMENT_URL_MINUTES: i64 = 15;
+
+#[derive(SimpleObject, Clone)]
+#[graphql(complex)]
+pub struct OrderMessages {
+    pub message_id: i32,
+    pub order_id: OrderId,
+    pub sender_user_id: UserId,
+    pub sender_role: String,
+    pub body: String,
+    #[graphql(skip)]
+    pub attachment_keys: Vec<String>,
+    pub created_at: Option<DateTimeWithTimeZone>,
+}
+
+impl From<OrderMessagesModel> for OrderMessages {
+    fn from(val: OrderMessagesModel) -> OrderMessages {
+        OrderMessages {
+            message_id: val.message_id,
+            order_id: val.order_id.into(),
+            sender_user_id: val.sender_user_id.into(),
+            sender_role: val.sender_role,
+            body: val.body,
+            attachment_keys: val.attachment_keys,
+            created_at: val.created_at,
+        }
+    }
+}
+
+#[ComplexObject]
+impl OrderMessages {
+    // signed links that expire after a few minutes, read the thread again for fresh ones
+    async fn attachment_urls(&self, ctx: &Context<'_>) -> Result<Vec<String>, Error> {
+        let storage = ctx.data::<Arc<dyn Storage>>()?;
+        self.attachment_keys
+            .iter()
+            .map(|key| {
+                storage
+                    .signed_url(key, Duration::minutes(ATTACHMENT_URL_MINUTES))
+                    .map_err(|e| e.extend())
+            })
+            .collect()
+    }
+}
+
+// only the customer who placed the order, suppliers with a product in it and admins of its
+// marketplace can see its thread
+pub async fn check_order_participant(
+    db: &DatabaseConnection,
+    ctx: &Context<'_>,
+    order_id: OrderId,
+) -> Result<Claims, Error> {
+    check_order_participant_role(db, ctx, order_id)
+        .await
+        .map(|(claims, _)| claims)
+}
+
+// also says which of the caller's roles they take part through, accounts holding several roles
+// count as the customer or supplier of the order before counting as an admin. Roles are the ones
+// the account holds now, not the ones its token was issued with
+pub async fn check_order_participant_role(
+    db: &DatabaseConnection,
+    ctx: &Context<'_>,
+    order_id: OrderId,
+) -> Result<(Claims, &'static str), Error> {
+    use crate::entity::{order_items, orders, prelude::Orders as OrdersEntity, products};
+    let claims = AuthenticatedUser::from_ctx(ctx).map_err(|e| e.extend())?;
+    let permissions = caller_permissions(ctx).await?;
+    let tenant = current_tenant(ctx);
+
+    if permissions.has_role(ROLE_CUSTOMER) {
+        if let Ok(customer_id) = get_customer_supplier_id(db, ctx, ROLE_CUSTOMER).await {
+            if OrdersEntity::find_by_id(order_id)
+                .for_tenant(tenant)
+                .filter(orders::Column::CustomerId.eq(customer_id))
+                .one(db)
+                .await?
+                .is_some()
+            {
+                return Ok((claims, ROLE_CUSTOMER));
+            }
+        }
+    }
+    if permissions.has_role(ROLE_SUPPLIER) {
+        if let Ok(supplier_id) = get_customer_supplier_id(db, ctx, ROLE_SUPPLIER).await {
+            if OrdersEntity::find_by_id(order_id)
+                .for_tenant(tenant)
+                .join(JoinType::InnerJoin, orders::Relation::OrderItems.def())
+                .join(JoinType::InnerJoin, order_items::Relation::Products.def())
+                .filter(products::Column::SupplierId.eq(supplier_id))
+                .one(db)
+                .await?
+                .is_some()
+            {
+                return Ok((claims, ROLE_SUPPLIER));
+            }
+        }
+    }
+    if permissions.has_role(ROLE_ADMIN)
+        && OrdersEntity::find_by_id(order_id)
+            .for_tenant(tenant)
+            .one(db)
+            .await?
+            .is_some()
+    {
+        return Ok((claims, ROLE_ADMIN));
+    }
+
+    Err(AppError::forbidden().extend())
+}
@@ -0,0 +1,85 @@
+use crate::error::AppError;
+use std::str::FromStr;
+
+// where an order is in its life, stored as the upper case name in orders.status
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum OrderStatus {
+    // placed, waiting for the payment
+    Pending,
+    // only once the payment came in, through charge_order or settle_payment
+    Paid,
+    // the supplier is putting it together, orders bought on account against a PO start here
+    // without a payment
+    Processing,
+    // handed to a carrier, only through markOrderShipped so the tracking number comes along
+    Shipped,
+    OutForDelivery,
+    Delivered,
+    // only through cancelOrder before anything was paid, or when the payment is declined. Both
+    // put the stock back, paid orders are refunded instead
+    Cancelled,
+}
+
+impl OrderStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OrderStatus::Pending => "PENDING",
+            OrderStatus::Paid => "PAID",
+            OrderStatus::Processing => "PROCESSING",
+            OrderStatus::Shipped => "SHIPPED",
+            OrderStatus::OutForDelivery => "OUT_FOR_DELIVERY",
+            OrderStatus::Delivered => "DELIVERED",
+            OrderStatus::Cancelled => "CANCELLED",
+        }
+    }
+
+    // statuses the order may move to from this one, delivered and cancelled orders stay put
+    pub fn next(&self) -> &'static [OrderStatus] {
+        use OrderStatus::*;
+        match self {
+            Pending => &[Paid, Processing, Cancelled],
+            Paid => &[Processing, Shipped],
+            Processing => &[Shipped, Cancelled],
+            Shipped => &[OutForDelivery, Delivered],
+            OutForDelivery => &[Delivered],
+            Delivered | Cancelled => &[],
+        }
+    }
+
+    pub fn can_become(&self, next: OrderStatus) -> bool {
+        self.next().contains(&next)
+    }
+}
+
+impl FromStr for OrderStatus {
+    type Err = AppError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_uppercase().as_str() {
+            "PENDING" => Ok(OrderStatus::Pending),
+            "PAID" => Ok(OrderStatus::Paid),
+            "PROCESSING" => Ok(OrderStatus::Processing),
+            "SHIPPED" => Ok(OrderStatus::Shipped),
+            "OUT_FOR_DELIVERY" => Ok(OrderStatus::OutForDelivery),
+            "DELIVERED" => Ok(OrderStatus::Delivered),
+            "CANCELLED" => Ok(OrderStatus::Cancelled),
+            other => Err(AppError::invalid(format!("Unknown order status {}", other))),
+        }
+    }
+}
+
+// the order's stored status checked against the one it is about to get
+pub fn check_transition(current: &str, next: OrderStatus) -> Result<OrderStatus, AppError> {
+    let current = current.parse::<OrderStatus>()?;
+    if !current.can_become(next) {
+        return Err(AppError::Validation {
+            message: format!(
+                "A {} order can't become {}",
+                current.as_str().to_lowercase(),
+                next.as_str().to_lowercase()
+            ),
+            failed_rules: vec!["orderStatusTransition".to_string()],
+        });
+    }
+    Ok(current)
+}
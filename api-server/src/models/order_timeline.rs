@@ -0,0 +1,258 @@
+use crate::{
+    entity::{
+        addresses, bills, disputes, order_address_changes, order_items, order_messages,
+        order_status_history, payment_events,
+        prelude::{
+            Addresses as AddressesEntity, Bills as BillsEntity, Disputes as DisputesEntity,
+            OrderAddressChanges as OrderAddressChangesEntity, OrderItems as OrderItemsEntity,
+            OrderMessages as OrderMessagesEntity, OrderStatusHistory as OrderStatusHistoryEntity,
+            Orders as OrdersEntity, PaymentEvents as PaymentEventsEntity,
+            Products as ProductsEntity, ReturnRequests as ReturnRequestsEntity,
+        },
+        return_requests,
+    },
+    error::AppError,
+    ids::OrderId,
+};
+use async_graphql::{Enum, ErrorExtensions, SimpleObject};
+use sea_orm::{
+    prelude::DateTimeWithTimeZone, ColumnTrait, ConnectionTrait, EntityTrait, QueryFilter,
+};
+use std::collections::HashMap;
+
+// there is no separate shipment record, fulfilment is tracked through these order statuses
+const SHIPMENT_STATUSES: [&str; 3] = ["SHIPPED", "OUT_FOR_DELIVERY", "DELIVERED"];
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+pub enum TimelineEntryKind {
+    StatusChange,
+    Shipment,
+    Payment,
+    Dispute,
+    Message,
+    ReturnRequested,
+    ReturnResolved,
+    AddressChanged,
+}
+
+#[derive(SimpleObject)]
+pub struct OrderTimelineEntry {
+    pub kind: TimelineEntryKind,
+    pub occurred_at: DateTimeWithTimeZone,
+    pub summary: String,
+    // the status the entry moved its subject to, where it has one
+    pub status: Option<String>,
+    // id of the bill, payment event, dispute, message or return request behind the entry, the
+    // new address for address changes
+    pub reference_id: Option<i32>,
+}
+
+// everything that happened to the order, oldest first
+pub async fn order_timeline<C: ConnectionTrait>(
+    db: &C,
+    order_id: OrderId,
+) -> Result<Vec<OrderTimelineEntry>, async_graphql::Error> {
+    let order = OrdersEntity::find_by_id(order_id)
+        .one(db)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Order").extend())?;
+    let mut entries = Vec::new();
+
+    let history = OrderStatusHistoryEntity::find()
+        .filter(order_status_history::Column::OrderId.eq(order_id))
+        .all(db)
+        .await?;
+    // orders placed before the history was kept only have their date to go by
+    if history.is_empty() {
+        if let Some(order_date) = order.order_date {
+            entries.push(OrderTimelineEntry {
+                kind: TimelineEntryKind::StatusChange,
+                occurred_at: order_date,
+                summary: "Order placed".to_string(),
+                status: None,
+                reference_id: None,
+            });
+        }
+    }
+    for change in history {
+        let kind = if SHIPMENT_STATUSES.contains(&change.status.as_str()) {
+            TimelineEntryKind::Shipment
+        } else {
+            TimelineEntryKind::StatusChange
+        };
+        let summary = match &change.previous_status {
+            None => "Order placed".to_string(),
+            Some(previous) => format!("Status changed from {} to {}", previous, change.status),
+        };
+        entries.push(OrderTimelineEntry {
+            kind,
+            occurred_at: change.changed_at,
+            summary,
+            status: Some(change.status),
+            reference_id: None,
+        });
+    }
+
+    let address_changes = OrderAddressChangesEntity::find()
+        .filter(order_address_changes::Column::OrderId.eq(order_id))
+        .all(db)
+        .await?;
+    let new_addresses: HashMap<i32, addresses::Model> = AddressesEntity::find()
+        .filter(
+            addresses::Column::AddressId.is_in(
+                address_changes
+                    .iter()
+                    .filter_map(|change| change.new_address_id),
+            ),
+        )
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|address| (address.address_id, address))
+        .collect();
+    for change in address_changes {
+        let summary = match change
+            .new_address_id
+            .and_then(|address_id| new_addresses.get(&address_id))
+        {
+            Some(address) => format!(
+                "Shipping address changed to {}, {}",
+                address.city, address.country
+            ),
+            None => "Shipping address changed".to_string(),
+        };
+        entries.push(OrderTimelineEntry {
+            kind: TimelineEntryKind::AddressChanged,
+            occurred_at: change.changed_at,
+            summary,
+            status: None,
+            reference_id: change.new_address_id,
+        });
+    }
+
+    let bill = BillsEntity::find()
+        .filter(bills::Column::OrderId.eq(order_id))
+        .one(db)
+        .await?;
+    if let Some(bill) = bill {
+        if let Some(bill_date) = bill.bill_date {
+            entries.push(OrderTimelineEntry {
+                kind: TimelineEntryKind::Payment,
+                occurred_at: bill_date,
+                summary: format!("Billed {}", bill.total_amount),
+                status: Some(bill.payment_status),
+                reference_id: Some(bill.bill_id),
+            });
+        }
+    }
+
+    // what the payment provider reported, captures, failures and refunds the bill alone doesn't show
+    let payment_events = PaymentEventsEntity::find()
+        .filter(payment_events::Column::OrderId.eq(order_id))
+        .all(db)
+        .await?;
+    for event in payment_events {
+        entries.push(OrderTimelineEntry {
+            kind: TimelineEntryKind::Payment,
+            occurred_at: event.received_at,
+            summary: format!("Payment provider reported {}", event.event_type),
+            status: None,
+            reference_id: Some(event.payment_event_id),
+        });
+    }
+
+    let disputes = DisputesEntity::find()
+        .filter(disputes::Column::OrderId.eq(order_id))
+        .all(db)
+        .await?;
+    for dispute in disputes {
+        entries.push(OrderTimelineEntry {
+            kind: TimelineEntryKind::Dispute,
+            occurred_at: dispute.opened_at,
+            summary: match &dispute.reason {
+                Some(reason) => format!("Payment of {} disputed: {}", dispute.amount, reason),
+                None => format!("Payment of {} disputed", dispute.amount),
+            },
+            status: None,
+            reference_id: Some(dispute.dispute_id),
+        });
+        if dispute.updated_at > dispute.opened_at {
+            entries.push(OrderTimelineEntry {
+                kind: TimelineEntryKind::Dispute,
+                occurred_at: dispute.updated_at,
+                summary: format!("Dispute {}", dispute.status.to_lowercase()),
+                status: Some(dispute.status),
+                reference_id: Some(dispute.dispute_id),
+            });
+        }
+    }
+
+    let messages = OrderMessagesEntity::find()
+        .filter(order_messages::Column::OrderId.eq(order_id))
+        .all(db)
+        .await?;
+    for message in messages {
+        let Some(created_at) = message.created_at else {
+            continue;
+        };
+        entries.push(OrderTimelineEntry {
+            kind: TimelineEntryKind::Message,
+            occurred_at: created_at,
+            summary: format!("Message from {}: {}", message.sender_role, message.body),
+            status: None,
+            reference_id: Some(message.message_id),
+        });
+    }
+
+    let items = OrderItemsEntity::find()
+        .filter(order_items::Column::OrderId.eq(order_id))
+        .find_also_related(ProductsEntity)
+        .all(db)
+        .await?;
+    let product_names: HashMap<i32, String> = items
+        .iter()
+        .filter_map(|(item, product)| {
+            product
+                .as_ref()
+                .map(|product| (item.order_item_id, product.name.clone()))
+        })
+        .collect();
+    let returns = ReturnRequestsEntity::find()
+        .filter(
+            return_requests::Column::OrderItemId
+                .is_in(items.iter().map(|(item, _)| item.order_item_id)),
+        )
+        .all(db)
+        .await?;
+    for request in returns {
+        let product = product_names
+            .get(&request.order_item_id)
+            .map(String::as_str)
+            .unwrap_or("a removed product");
+        entries.push(OrderTimelineEntry {
+            kind: TimelineEntryKind::ReturnRequested,
+            occurred_at: request.requested_at,
+            summary: format!("Return of {} x {} requested", request.quantity, product),
+            status: Some("REQUESTED".to_string()),
+            reference_id: Some(request.return_request_id),
+        });
+        if let Some(resolved_at) = request.resolved_at {
+            entries.push(OrderTimelineEntry {
+                kind: TimelineEntryKind::ReturnResolved,
+                occurred_at: resolved_at,
+                summary: format!(
+                    "Return of {} x {} {}",
+                    request.quantity,
+                    product,
+                    request.status.to_lowercase()
+                ),
+                status: Some(request.status),
+                reference_id: Some(request.return_request_id),
+            });
+        }
+    }
+
+    // stable, so entries sharing a timestamp keep the order they were gathered in
+    entries.sort_by_key(|entry| entry.occurred_at);
+    Ok(entries)
+}
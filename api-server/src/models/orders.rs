@@ -1,10 +1,52 @@
-use crate::entity::orders::Model as OrdersModel;
-use async_graphql::{InputObject, SimpleObject};
-use sea_orm::prelude::DateTimeWithTimeZone;
+use crate::{
+    carriers::{Carrier, Shipment, SupplierShipment},
+    config::{CurrencyPolicy, RegionConfig, StockLocking, StockPolicy, TaxPolicy, WelcomePolicy},
+    customs::customs_lines,
+    domain_events::{
+        record_event, FirstOrderPlaced, OrderPlaced, FIRST_ORDER_PLACED, ORDER_PLACED,
+    },
+    entity::{addresses::Model as AddressesModel, orders::Model as OrdersModel},
+    error::AppError,
+    ids::{global_id, OrderId, ProductId, TenantId},
+    models::{
+        availability::{release_date, reserve_date},
+        coupons::{
+            cart_coupon, coupon_covers, coupon_discount, coupon_unusable_reason, redeem_coupon,
+            release_redemption,
+        },
+        guest::GuestOrder,
+        money::Money,
+        order_status::OrderStatus,
+        products::on_sale,
+        purchase_limits::check_purchase_quantity,
+        regional_prices::{check_same_currency, price_in_region},
+    },
+    money::{round_amount, round_total, split_amount},
+    notifications::{send_templated, TemplateKey, DEFAULT_LOCALE},
+    order_events::publish_status_change,
+    payment_gateway::{Charge, ChargeStatus, PaymentGateway},
+    pii::{pii, PiiKind},
+    retry::retry_transaction,
+    tenancy::TenantScope,
+};
+use async_graphql::{
+    ComplexObject, Context, ErrorExtensions, InputObject, SimpleObject, Union, ID,
+};
+use chrono::NaiveDate;
+use sea_orm::{
+    prelude::{DateTimeWithTimeZone, Decimal},
+    sea_query::Expr,
+    ActiveModelTrait,
+    ActiveValue::Set,
+    ColumnTrait, ConnectionTrait, DatabaseConnection, DbErr, EntityTrait, JoinType, PaginatorTrait,
+    QueryFilter, QueryOrder, QuerySelect, RelationTrait, TransactionTrait,
+};
+use std::{collections::HashMap, sync::LazyLock};
 
 #[derive(SimpleObject)]
+#[graphql(complex)]
 pub struct Orders {
-    pub order_id: i32,
+    pub order_id: OrderId,
     pub customer_id: i32,
     pub order_date: Option<DateTimeWithTimeZone>,
     pub total_amount: f64,
@@ -12,43 +54,1107 @@ pub struct Orders {
     pub shipping_address_id: i32,
     pub payment_method_id: i32,
     pub discount_id: Option<i32>,
+    pub region: Option<String>,
+    pub tax_amount: f64,
+    pub po_number: Option<String>,
+    // held while a payment dispute on the order is open or lost
+    pub payout_frozen: bool,
+    // carrier, tracking number and deep link of the shipment that completed the order, see
+    // shipments for each supplier's when several suppliers ship
+    pub shipment: Option<Shipment>,
+    pub shipped_at: Option<DateTimeWithTimeZone>,
+    // the customer's first order, which got the welcome discount unless a discount code was used
+    pub first_order: bool,
+    pub welcome_discount: f64,
+    // ISO 4217 code every amount of the order is in
+    pub currency: String,
 }
 
-impl From<OrdersModel> for Orders {
-    fn from(val: OrdersModel) -> Orders {
-        Orders {
-            order_id: val.order_id,
-            customer_id: val.customer_id,
+// guest orders have no saved payment method, claimed or not, and are handed back as they are to
+// become a GuestOrder
+impl TryFrom<OrdersModel> for Orders {
+    type Error = OrdersModel;
+
+    fn try_from(val: OrdersModel) -> Result<Orders, OrdersModel> {
+        let (Some(customer_id), Some(payment_method_id)) = (val.customer_id, val.payment_method_id)
+        else {
+            return Err(val);
+        };
+        Ok(Orders {
+            order_id: val.order_id.into(),
+            customer_id,
             order_date: val.order_date,
             total_amount: val.total_amount.to_string().parse::<f64>().unwrap(),
             status: val.status,
             shipping_address_id: val.shipping_address_id,
-            payment_method_id: val.payment_method_id,
+            payment_method_id,
             discount_id: val.discount_id,
+            region: val.region,
+            tax_amount: val.tax_amount.to_string().parse::<f64>().unwrap(),
+            po_number: val.po_number,
+            payout_frozen: val.payout_frozen,
+            shipment: Shipment::from_order(val.carrier.as_deref(), val.tracking_number),
+            shipped_at: val.shipped_at,
+            first_order: val.first_order,
+            welcome_discount: val.welcome_discount.to_string().parse::<f64>().unwrap(),
+            currency: val.currency,
+        })
+    }
+}
+
+impl Orders {
+    // for orders placed by a customer with a saved payment method, which every customer order is
+    pub fn customer_order(val: OrdersModel) -> Result<Orders, async_graphql::Error> {
+        Orders::try_from(val).map_err(|order| {
+            AppError::Internal(format!("Order {} is a guest order", order.order_id)).extend()
+        })
+    }
+}
+
+// where customer and guest orders come back together, suppliers ship and node() resolves both
+#[derive(Union)]
+pub enum PlacedOrder {
+    Orders(Orders),
+    GuestOrder(GuestOrder),
+}
+
+impl From<OrdersModel> for PlacedOrder {
+    fn from(val: OrdersModel) -> PlacedOrder {
+        match Orders::try_from(val) {
+            Ok(order) => PlacedOrder::Orders(order),
+            Err(order) => PlacedOrder::GuestOrder(order.into()),
         }
     }
 }
 
-#[derive(InputObject)]
+pub fn money(amount: f64, currency: &str, locale: Option<String>) -> Money {
+    Money::new(
+        Decimal::try_from(amount).unwrap_or_default(),
+        currency,
+        locale.as_deref().unwrap_or(DEFAULT_LOCALE),
+    )
+}
+
+#[ComplexObject]
+impl Orders {
+    pub async fn id(&self) -> ID {
+        global_id("Orders", self.order_id.0)
+    }
+
+    // totalAmount in the order's currency, formatted for the locale, English unless given
+    async fn total(&self, locale: Option<String>) -> Money {
+        money(self.total_amount, &self.currency, locale)
+    }
+
+    async fn tax(&self, locale: Option<String>) -> Money {
+        money(self.tax_amount, &self.currency, locale)
+    }
+
+    // every supplier's shipment so far, in the order they shipped
+    async fn shipments(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<Vec<SupplierShipment>, async_graphql::Error> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        Ok(order_shipments(db, self.order_id.0).await?)
+    }
+}
+
+pub async fn order_shipments<C: ConnectionTrait>(
+    db: &C,
+    order_id: i32,
+) -> Result<Vec<SupplierShipment>, DbErr> {
+    use crate::entity::{order_shipments, prelude::OrderShipments};
+    Ok(OrderShipments::find()
+        .filter(order_shipments::Column::OrderId.eq(order_id))
+        .order_by_asc(order_shipments::Column::ShippedAt)
+        .all(db)
+        .await?
+        .into_iter()
+        .filter_map(SupplierShipment::from_model)
+        .collect())
+}
+
+#[derive(InputObject, Clone)]
 pub struct RegisterOrder {
     pub shipping_address_id: i32,
-    pub payment_method_id: i32,
+    // required for customers, guests pay with a one-off provider token instead
+    pub payment_method_id: Option<i32>,
     pub discount_code: Option<String>,
     pub order_items: Vec<RegisterOrderItem>,
+    // buyer's purchase order reference, carried onto the bill
+    pub po_number: Option<String>,
+    // priced like the catalog for the same region, ignored by pinned deployments
+    pub region: Option<String>,
 }
 
+#[allow(dead_code)]
 #[derive(SimpleObject)]
 pub struct OrderItems {
     pub order_item_id: i32,
-    pub order_id: i32,
-    pub product_id: i32,
+    pub order_id: OrderId,
+    pub product_id: ProductId,
     pub quantity: i32,
     pub unit_price: i32,
     pub discount_amount: i32,
+    pub booking_date: Option<NaiveDate>,
 }
 
-#[derive(InputObject)]
+#[derive(InputObject, Clone)]
 pub struct RegisterOrderItem {
-    pub product_id: i32,
+    pub product_id: ProductId,
     pub quantity: i32,
+    // required for date bookable products, the day the rental or slot is for
+    pub booking_date: Option<NaiveDate>,
+}
+
+#[derive(SimpleObject)]
+pub struct CheckoutResult {
+    pub order: Orders,
+    pub payment_status: String,
+    // set when the provider wants the customer to confirm the payment client side
+    #[graphql(directive = pii::apply(PiiKind::Secret))]
+    pub client_secret: Option<String>,
+}
+
+// who an order is placed for, guests are only known by their email until they claim the order
+#[derive(Clone)]
+pub enum OrderOwner {
+    Customer(i32),
+    Guest(String),
+}
+
+static STOCK_POLICY: LazyLock<StockPolicy> = LazyLock::new(StockPolicy::from_env);
+static WELCOME_POLICY: LazyLock<WelcomePolicy> = LazyLock::new(WelcomePolicy::from_env);
+
+// takes the units out of stock inside the order's transaction, how concurrent orders for the same
+// product are kept from overselling is up to STOCK_LOCKING
+pub async fn reserve_stock<C: ConnectionTrait>(
+    db: &C,
+    product_id: i32,
+    quantity: i32,
+) -> Result<(), AppError> {
+    use crate::entity::{prelude::Products as ProductsEntity, products};
+    let policy = &*STOCK_POLICY;
+
+    if policy.locking == StockLocking::Pessimistic {
+        let available = ProductsEntity::find_by_id(product_id)
+            .lock_exclusive()
+            .one(db)
+            .await?
+            .map_or(0, |product| product.stock_quantity);
+        if available < quantity {
+            return Err(AppError::InsufficientStock {
+                product_id,
+                available,
+            });
+        }
+        ProductsEntity::update_many()
+            .col_expr(
+                products::Column::StockQuantity,
+                Expr::value(available - quantity),
+            )
+            .filter(products::Column::ProductId.eq(product_id))
+            .exec(db)
+            .await?;
+        return Ok(());
+    }
+
+    for _ in 0..policy.optimistic_attempts.max(1) {
+        let available = ProductsEntity::find_by_id(product_id)
+            .one(db)
+            .await?
+            .map_or(0, |product| product.stock_quantity);
+        if available < quantity {
+            return Err(AppError::InsufficientStock {
+                product_id,
+                available,
+            });
+        }
+        let reserved = ProductsEntity::update_many()
+            .col_expr(
+                products::Column::StockQuantity,
+                Expr::value(available - quantity),
+            )
+            .filter(products::Column::ProductId.eq(product_id))
+            .filter(products::Column::StockQuantity.eq(available))
+            .exec(db)
+            .await?
+            .rows_affected;
+        if reserved > 0 {
+            return Ok(());
+        }
+    }
+    Err(AppError::Internal(format!(
+        "Stock of product {} kept changing, try again",
+        product_id
+    )))
+}
+
+// puts units of a cancelled order back, one increment so concurrent reservations never see a stale value
+pub async fn release_stock<C: ConnectionTrait>(
+    db: &C,
+    product_id: i32,
+    quantity: i32,
+) -> Result<(), AppError> {
+    use crate::entity::{prelude::Products as ProductsEntity, products};
+    ProductsEntity::update_many()
+        .col_expr(
+            products::Column::StockQuantity,
+            Expr::col(products::Column::StockQuantity).add(quantity),
+        )
+        .filter(products::Column::ProductId.eq(product_id))
+        .exec(db)
+        .await?;
+    Ok(())
+}
+
+// shared by registerOrder, punchout ingestion and guest checkout so all go through the same stock, discount and tax rules
+#[allow(clippy::too_many_arguments)]
+pub async fn place_order(
+    db: &DatabaseConnection,
+    owner: OrderOwner,
+    tenant: TenantId,
+    input: RegisterOrder,
+    // the cart being ordered, its coupon is redeemed with the order
+    cart_id: Option<i32>,
+    region: &RegionConfig,
+    currency_policy: &CurrencyPolicy,
+    tax_policy: &TaxPolicy,
+) -> Result<OrdersModel, async_graphql::Error> {
+    retry_transaction("place_order", || {
+        place_order_once(
+            db,
+            owner.clone(),
+            tenant,
+            input.clone(),
+            cart_id,
+            region,
+            currency_policy,
+            tax_policy,
+        )
+    })
+    .await
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn place_order_once(
+    db: &DatabaseConnection,
+    owner: OrderOwner,
+    tenant: TenantId,
+    input: RegisterOrder,
+    cart_id: Option<i32>,
+    region: &RegionConfig,
+    currency_policy: &CurrencyPolicy,
+    tax_policy: &TaxPolicy,
+) -> Result<OrdersModel, async_graphql::Error> {
+    use crate::entity::{
+        addresses, bills, discounts, order_items, orders, payment_methods,
+        prelude::{
+            Addresses as AddressesEntity, Bills as BillsEntity, Coupons as CouponsEntity,
+            Customers as CustomersEntity, Discounts as DiscountsEntity,
+            OrderItems as OrderItemsEntity, Orders as OrdersEntity,
+            PaymentMethods as PaymentMethodsEntity, Products as ProductsEntity,
+        },
+        products,
+    };
+    let txn = db.begin().await?;
+
+    let discount_id = match &input.discount_code {
+        Some(discount_code) => ProductsEntity::find()
+            .filter(products::Column::Name.eq(discount_code))
+            .for_tenant(tenant)
+            .one(db)
+            .await?
+            .map(|product| product.product_id),
+        None => None,
+    };
+
+    // repeated lines of a product count together against its purchase limit
+    let mut quantities: HashMap<ProductId, i32> = HashMap::new();
+    for item in &input.order_items {
+        *quantities.entry(item.product_id).or_default() += item.quantity;
+    }
+
+    let store_currency = &currency_policy.currency;
+    let order_region = region.effective_region(input.region.clone());
+    let mut order_currency = None;
+    let mut total_amount: f64 = 0.0;
+    let mut unit_prices: HashMap<ProductId, Decimal> = HashMap::new();
+    let mut ordered_products: HashMap<ProductId, products::Model> = HashMap::new();
+    for item in &input.order_items {
+        // another marketplace's products can't be ordered from this one
+        let mut product: products::Model = on_sale(ProductsEntity::find_by_id(item.product_id))
+            .for_tenant(tenant)
+            .one(&txn)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Product").extend())?;
+        // a limited product stays locked until the order commits, two orders of the same buyer
+        // placed at once would otherwise both count the purchases from before either
+        if product.max_per_customer.is_some() {
+            product = on_sale(ProductsEntity::find_by_id(item.product_id))
+                .for_tenant(tenant)
+                .lock_exclusive()
+                .one(&txn)
+                .await?
+                .ok_or_else(|| AppError::NotFound("Product").extend())?;
+        }
+        match (product.date_bookable, item.booking_date) {
+            (true, None) => {
+                return Err(AppError::invalid(format!(
+                    "Product {} needs a booking date",
+                    product.product_id
+                ))
+                .extend())
+            }
+            (false, Some(_)) => {
+                return Err(AppError::invalid(format!(
+                    "Product {} can't be booked for a date",
+                    product.product_id
+                ))
+                .extend())
+            }
+            _ => {}
+        }
+        if let Some(quantity) = quantities.remove(&item.product_id) {
+            check_purchase_quantity(&txn, Some(&owner), &product, quantity)
+                .await
+                .map_err(|e| e.extend())?;
+        }
+        let (unit_price, currency) = price_in_region(
+            db,
+            product.product_id,
+            product.base_price,
+            order_region.as_deref(),
+            store_currency,
+        )
+        .await?;
+        check_same_currency(&mut order_currency, currency).map_err(|e| e.extend())?;
+        unit_prices.insert(item.product_id, unit_price);
+        total_amount += unit_price.to_string().parse::<f64>()? * item.quantity as f64;
+        ordered_products.insert(item.product_id, product);
+    }
+
+    // lines crossing a border need their customs data now, carriers and customs ask for it at dispatch.
+    // Customers ship to their own addresses, guests to the one their checkout just stored
+    let shipping_address = AddressesEntity::find_by_id(input.shipping_address_id)
+        .filter(match &owner {
+            OrderOwner::Customer(customer_id) => addresses::Column::CustomerId.eq(*customer_id),
+            OrderOwner::Guest(_) => addresses::Column::CustomerId.is_null(),
+        })
+        .one(db)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Shipping address").extend())?;
+    let customs = customs_lines(
+        db,
+        &shipping_address.country,
+        &ordered_products
+            .iter()
+            .map(|(product_id, product)| (product, unit_prices[product_id]))
+            .collect::<Vec<_>>(),
+    )
+    .await
+    .map_err(|e| e.extend())?;
+
+    let currency = &order_currency.unwrap_or_else(|| store_currency.clone());
+
+    if let Some(discount_id) = discount_id {
+        let discount: discounts::Model = DiscountsEntity::find_by_id(discount_id)
+            .one(&txn)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Discount").extend())?;
+
+        if discount.discount_type == "PERCENTAGE" {
+            total_amount -=
+                total_amount * discount.discount_value.to_string().parse::<f64>()? / 100.0;
+        } else if currency != store_currency {
+            return Err(AppError::invalid(format!(
+                "The discount is in {} and can't be used on an order in {}",
+                store_currency, currency
+            ))
+            .extend());
+        } else {
+            total_amount -= discount.discount_value.to_string().parse::<f64>()?;
+        }
+
+        // increment discount usage
+        let mut discount: discounts::ActiveModel = discount.into();
+        discount.times_used = Set(Some(discount.times_used.unwrap().unwrap_or(0) + 1));
+    }
+
+    // customers with a validated VAT ID buy VAT exempt (reverse charge)
+    let (customer_id, guest_email, vat_id, placed_by, first_order) = match owner {
+        OrderOwner::Customer(customer_id) => {
+            let Some(payment_method_id) = input.payment_method_id else {
+                return Err(AppError::invalid("Payment method is required").extend());
+            };
+            PaymentMethodsEntity::find_by_id(payment_method_id)
+                .filter(payment_methods::Column::CustomerId.eq(customer_id))
+                .one(&txn)
+                .await?
+                .ok_or_else(|| AppError::NotFound("Payment method").extend())?;
+            // locked so two orders placed at once can't both count as the first
+            let customer = CustomersEntity::find_by_id(customer_id)
+                .lock_exclusive()
+                .one(&txn)
+                .await?
+                .ok_or_else(|| AppError::NotFound("Customer").extend())?;
+            let vat_id = customer
+                .vat_id
+                .filter(|_| customer.vat_id_validated_at.is_some());
+            // cancelled orders were never bought, a customer whose orders all got cancelled is
+            // still a first time buyer
+            let first_order = OrdersEntity::find()
+                .filter(orders::Column::CustomerId.eq(customer_id))
+                .filter(orders::Column::Status.ne("CANCELLED"))
+                .count(&txn)
+                .await?
+                == 0;
+            (
+                Some(customer_id),
+                None,
+                vat_id,
+                Some(customer.user_id),
+                first_order,
+            )
+        }
+        OrderOwner::Guest(email) => (None, Some(email), None, None, false),
+    };
+    // locked so two orders can't both take a coupon's last use, the limits are checked again
+    // since the coupon was applied
+    let coupon = match cart_id {
+        Some(cart_id) => match cart_coupon(&txn, cart_id).await? {
+            Some(coupon) => {
+                CouponsEntity::find_by_id(coupon.coupon_id)
+                    .lock_exclusive()
+                    .one(&txn)
+                    .await?
+            }
+            None => None,
+        },
+        None => None,
+    };
+    let coupon_discount_amount = match &coupon {
+        Some(coupon) => {
+            if let Some(reason) = coupon_unusable_reason(&txn, coupon, customer_id).await? {
+                return Err(AppError::invalid(reason).extend());
+            }
+            let eligible_subtotal: Decimal = input
+                .order_items
+                .iter()
+                .filter(|item| coupon_covers(coupon, &ordered_products[&item.product_id]))
+                .map(|item| unit_prices[&item.product_id] * Decimal::from(item.quantity))
+                .sum();
+            coupon_discount(coupon, eligible_subtotal, currency, store_currency)
+                .map_err(|e| e.extend())?
+        }
+        None => Decimal::ZERO,
+    };
+    total_amount -= coupon_discount_amount.to_string().parse::<f64>()?;
+
+    // taken before tax, a discount code or coupon already priced the order and they don't stack
+    let welcome_discount = match first_order && discount_id.is_none() && coupon.is_none() {
+        true => round_amount(
+            Decimal::from_str_exact(total_amount.to_string().as_str())?
+                * WELCOME_POLICY.discount_percent
+                / Decimal::ONE_HUNDRED,
+            currency,
+        ),
+        false => Decimal::ZERO,
+    };
+    total_amount -= welcome_discount.to_string().parse::<f64>()?;
+    let tax_amount = match vat_id {
+        Some(_) => Decimal::ZERO,
+        None => round_amount(
+            Decimal::from_str_exact(total_amount.to_string().as_str())? * tax_policy.vat_rate
+                / Decimal::ONE_HUNDRED,
+            currency,
+        ),
+    };
+    total_amount += tax_amount.to_string().parse::<f64>()?;
+
+    let order = orders::ActiveModel {
+        customer_id: Set(customer_id),
+        guest_email: Set(guest_email),
+        shipping_address_id: Set(input.shipping_address_id),
+        payment_method_id: Set(input.payment_method_id),
+        discount_id: Set(discount_id),
+        // what the customer is charged, so Swiss orders end on a multiple of 5 Rappen
+        total_amount: Set(round_total(
+            Decimal::from_str_exact(total_amount.to_string().as_str())?,
+            currency,
+        )),
+        status: Set("PENDING".to_string()),
+        region: Set(order_region),
+        po_number: Set(input.po_number.clone()),
+        tax_amount: Set(tax_amount),
+        tenant_id: Set(tenant.0),
+        first_order: Set(first_order),
+        welcome_discount: Set(welcome_discount),
+        currency: Set(currency.clone()),
+        ..Default::default()
+    };
+
+    let insert_order = OrdersEntity::insert(order)
+        .exec_with_returning(&txn)
+        .await?;
+
+    // what the customer was charged, spread over the lines so refunds and payouts price each line
+    // from it. A coupon only reduces the lines it covers, the other discounts and the total's
+    // rounding every line, VAT is taken on what is left of each
+    let line_values: Vec<Decimal> = input
+        .order_items
+        .iter()
+        .map(|item| unit_prices[&item.product_id] * Decimal::from(item.quantity))
+        .collect();
+    let coupon_shares = split_amount(
+        coupon_discount_amount,
+        &input
+            .order_items
+            .iter()
+            .zip(&line_values)
+            .map(|(item, value)| match &coupon {
+                Some(coupon) if coupon_covers(coupon, &ordered_products[&item.product_id]) => {
+                    *value
+                }
+                _ => Decimal::ZERO,
+            })
+            .collect::<Vec<_>>(),
+        currency,
+    );
+    let other_discounts = line_values.iter().copied().sum::<Decimal>()
+        - coupon_discount_amount
+        - (insert_order.total_amount - tax_amount);
+    let after_coupon: Vec<Decimal> = line_values
+        .iter()
+        .zip(&coupon_shares)
+        .map(|(value, share)| *value - *share)
+        .collect();
+    let line_discounts: Vec<Decimal> = split_amount(other_discounts, &after_coupon, currency)
+        .into_iter()
+        .zip(&coupon_shares)
+        .map(|(share, coupon_share)| share + *coupon_share)
+        .collect();
+    let line_taxes = split_amount(
+        tax_amount,
+        &line_values
+            .iter()
+            .zip(&line_discounts)
+            .map(|(value, discount)| *value - *discount)
+            .collect::<Vec<_>>(),
+        currency,
+    );
+
+    for (index, item) in input.order_items.iter().enumerate() {
+        let unit_price = unit_prices[&item.product_id];
+
+        if let Some(booking_date) = item.booking_date {
+            reserve_date(&txn, item.product_id.into(), booking_date, item.quantity)
+                .await
+                .map_err(|e| e.extend())?;
+        } else {
+            reserve_stock(&txn, item.product_id.into(), item.quantity)
+                .await
+                .map_err(|e| e.extend())?;
+        }
+
+        let customs = customs.get(&item.product_id.into());
+        let order_item = order_items::ActiveModel {
+            order_id: Set(insert_order.order_id),
+            product_id: Set(item.product_id.into()),
+            quantity: Set(item.quantity),
+            unit_price: Set(unit_price),
+            discount_amount: Set(line_discounts[index]),
+            tax_amount: Set(line_taxes[index]),
+            booking_date: Set(item.booking_date),
+            hs_code: Set(customs.map(|line| line.hs_code.clone())),
+            customs_value: Set(customs.map(|line| line.customs_value)),
+            origin_country: Set(customs.map(|line| line.origin_country.clone())),
+            ..Default::default()
+        };
+        OrderItemsEntity::insert(order_item).exec(&txn).await?;
+    }
+
+    BillsEntity::insert(bills::ActiveModel {
+        order_id: Set(insert_order.order_id),
+        total_amount: Set(insert_order.total_amount),
+        payment_status: Set("PENDING".to_string()),
+        tax_amount: Set(tax_amount),
+        vat_id: Set(vat_id),
+        po_number: Set(insert_order.po_number.clone()),
+        ..Default::default()
+    })
+    .exec(&txn)
+    .await?;
+    record_status_change(&txn, insert_order.order_id, None, "PENDING", placed_by).await?;
+    if let Some(coupon) = coupon
+        .as_ref()
+        .filter(|_| !coupon_discount_amount.is_zero())
+    {
+        redeem_coupon(
+            &txn,
+            coupon,
+            customer_id,
+            insert_order.order_id,
+            coupon_discount_amount,
+        )
+        .await
+        .map_err(|e| e.extend())?;
+    }
+    if let Some(customer_id) = customer_id {
+        record_event(
+            &txn,
+            tenant,
+            ORDER_PLACED,
+            &OrderPlaced {
+                customer_id,
+                order_id: insert_order.order_id,
+                total_amount: insert_order.total_amount.to_string(),
+            },
+        )
+        .await
+        .map_err(|e| e.extend())?;
+    }
+    if let (true, Some(customer_id)) = (first_order, customer_id) {
+        record_event(
+            &txn,
+            tenant,
+            FIRST_ORDER_PLACED,
+            &FirstOrderPlaced {
+                customer_id,
+                order_id: insert_order.order_id,
+                total_amount: insert_order.total_amount.to_string(),
+                welcome_discount: welcome_discount.to_string(),
+            },
+        )
+        .await
+        .map_err(|e| e.extend())?;
+    }
+
+    txn.commit().await?;
+
+    Ok(insert_order)
+}
+
+// pushed to orderStatusChanged subscribers on every instance
+#[derive(SimpleObject, Clone)]
+pub struct OrderStatusChanged {
+    pub order_id: OrderId,
+    pub previous_status: Option<String>,
+    pub status: String,
+    pub changed_at: DateTimeWithTimeZone,
+    // the history entry behind the change and the order's entry before it, instances relaying
+    // the change use them to drop duplicates and put late arrivals back in order
+    #[graphql(skip)]
+    pub sequence: i32,
+    #[graphql(skip)]
+    pub previous_sequence: Option<i32>,
+}
+
+// appends to the order's status history, call it in the transaction that changes the status and
+// publish the returned event once that transaction is committed
+pub async fn record_status_change<C: ConnectionTrait>(
+    db: &C,
+    order_id: i32,
+    previous_status: Option<&str>,
+    status: &str,
+    changed_by: Option<i32>,
+) -> Result<OrderStatusChanged, DbErr> {
+    use crate::entity::{order_status_history, prelude::OrderStatusHistory};
+    let entry = OrderStatusHistory::insert(order_status_history::ActiveModel {
+        order_id: Set(order_id),
+        previous_status: Set(previous_status.map(str::to_string)),
+        status: Set(status.to_string()),
+        changed_by: Set(changed_by),
+        ..Default::default()
+    })
+    .exec_with_returning(db)
+    .await?;
+    let previous_sequence = OrderStatusHistory::find()
+        .filter(order_status_history::Column::OrderId.eq(order_id))
+        .filter(order_status_history::Column::HistoryId.lt(entry.history_id))
+        .order_by_desc(order_status_history::Column::HistoryId)
+        .one(db)
+        .await?
+        .map(|previous| previous.history_id);
+    Ok(OrderStatusChanged {
+        order_id: entry.order_id.into(),
+        previous_status: entry.previous_status,
+        status: entry.status,
+        changed_at: entry.changed_at,
+        sequence: entry.history_id,
+        previous_sequence,
+    })
+}
+
+// the idempotency key an order is charged under, every attempt for the order uses the same one
+pub fn order_payment_key(order_id: i32) -> String {
+    format!("order-{}", order_id)
+}
+
+pub fn order_id_from_payment_key(key: &str) -> Option<i32> {
+    key.strip_prefix("order-")?.parse().ok()
+}
+
+// charges the order's total and records the outcome on its bill, a charge that went through
+// moves the order to PAID. A declined charge leaves no order behind, it is voided before the
+// error is returned. When the provider's answer is lost the order stays PENDING with a PROCESSING
+// bill until the payment's event or reconcile_unknown_charges settles it. Returns the order as it
+// stands after the charge
+pub async fn charge_order(
+    db: &DatabaseConnection,
+    redis: &redis::Client,
+    gateway: &dyn PaymentGateway,
+    order: OrdersModel,
+    provider_token: &str,
+    provider_customer_id: Option<&str>,
+) -> Result<(OrdersModel, Charge), async_graphql::Error> {
+    use crate::entity::{bills, orders, prelude::Bills as BillsEntity};
+
+    // keyed on the order so a retried request can't charge twice
+    let charge = match gateway
+        .charge(
+            provider_token,
+            provider_customer_id,
+            order.total_amount,
+            &order.currency,
+            &order_payment_key(order.order_id),
+        )
+        .await
+    {
+        Ok(charge) if charge.status != ChargeStatus::Failed => charge,
+        Ok(_) => {
+            void_unpaid_order(db, order.order_id).await?;
+            return Err(AppError::invalid("The payment was declined").extend());
+        }
+        // the charge may have gone through, the stock stays reserved and the customer can't
+        // cancel until the provider's answer is in
+        Err(e) => {
+            BillsEntity::update_many()
+                .col_expr(
+                    bills::Column::PaymentStatus,
+                    Expr::value(ChargeStatus::Processing.as_str()),
+                )
+                .filter(bills::Column::OrderId.eq(order.order_id))
+                .filter(bills::Column::ProviderPaymentId.is_null())
+                .exec(db)
+                .await?;
+            return Err(e.extend());
+        }
+    };
+
+    let txn = db.begin().await?;
+    let bill = BillsEntity::find()
+        .filter(bills::Column::OrderId.eq(order.order_id))
+        .one(&txn)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Bill").extend())?;
+    let mut bill: bills::ActiveModel = bill.into();
+    bill.payment_status = Set(charge.status.as_str().to_string());
+    bill.provider_payment_id = Set(charge.provider_payment_id.clone());
+    bill.update(&txn).await?;
+
+    // charges that need the customer's confirmation are settled by webhook instead
+    let (order, change) = match charge.status == ChargeStatus::Succeeded
+        && order.status == OrderStatus::Pending.as_str()
+    {
+        true => {
+            let order_id = order.order_id;
+            let mut order: orders::ActiveModel = order.into();
+            order.status = Set(OrderStatus::Paid.as_str().to_string());
+            let order = order.update(&txn).await?;
+            let change = record_status_change(
+                &txn,
+                order_id,
+                Some(OrderStatus::Pending.as_str()),
+                OrderStatus::Paid.as_str(),
+                None,
+            )
+            .await?;
+            (order, Some(change))
+        }
+        false => (order, None),
+    };
+    txn.commit().await?;
+
+    if let Some(change) = change {
+        if let Err(e) = publish_status_change(redis, &change).await {
+            eprintln!(
+                "Status change of order {} not published: {}",
+                order.order_id, e
+            );
+        }
+    }
+
+    Ok((order, charge))
+}
+
+// cancels an order whose payment didn't go through and gives its units, booked dates and coupon
+// use back. An order that is already cancelled is left as it is
+pub async fn void_unpaid_order(
+    db: &DatabaseConnection,
+    order_id: i32,
+) -> Result<(), async_graphql::Error> {
+    retry_transaction("void_unpaid_order", || void_unpaid_order_once(db, order_id)).await
+}
+
+async fn void_unpaid_order_once(
+    db: &DatabaseConnection,
+    order_id: i32,
+) -> Result<(), async_graphql::Error> {
+    use crate::entity::orders;
+    let txn = db.begin().await?;
+
+    let order = orders::Entity::find_by_id(order_id)
+        .lock_exclusive()
+        .one(&txn)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Order").extend())?;
+    if order.status == OrderStatus::Cancelled.as_str() {
+        return Ok(());
+    }
+    void_order(&txn, order, None).await?;
+
+    txn.commit().await?;
+    Ok(())
+}
+
+// the unpaid order's units, booked dates and coupon use given back, its bill failed and the order
+// cancelled. Runs in the caller's transaction with the order locked, the returned change is
+// published once that is committed
+pub async fn void_order<C: ConnectionTrait>(
+    txn: &C,
+    order: OrdersModel,
+    cancelled_by: Option<i32>,
+) -> Result<OrderStatusChanged, async_graphql::Error> {
+    use crate::entity::{bills, order_items, orders, prelude::Bills as BillsEntity};
+    let order_id = order.order_id;
+    for item in order_items::Entity::find()
+        .filter(order_items::Column::OrderId.eq(order_id))
+        .all(txn)
+        .await?
+    {
+        match item.booking_date {
+            Some(booking_date) => release_date(txn, item.product_id, booking_date, item.quantity)
+                .await
+                .map_err(|e| e.extend())?,
+            None => release_stock(txn, item.product_id, item.quantity)
+                .await
+                .map_err(|e| e.extend())?,
+        }
+    }
+    release_redemption(txn, order_id)
+        .await
+        .map_err(|e| e.extend())?;
+
+    BillsEntity::update_many()
+        .col_expr(
+            bills::Column::PaymentStatus,
+            Expr::value(ChargeStatus::Failed.as_str()),
+        )
+        .filter(bills::Column::OrderId.eq(order_id))
+        .exec(txn)
+        .await?;
+    let previous_status = order.status.clone();
+    let mut order: orders::ActiveModel = order.into();
+    order.status = Set(OrderStatus::Cancelled.as_str().to_string());
+    order.update(txn).await?;
+    Ok(record_status_change(
+        txn,
+        order_id,
+        Some(&previous_status),
+        OrderStatus::Cancelled.as_str(),
+        cancelled_by,
+    )
+    .await?)
+}
+
+// tells the buyer where the parcel is, registered customers in their own language
+pub async fn send_shipping_notification(
+    db: &DatabaseConnection,
+    order: &OrdersModel,
+    carrier: Carrier,
+    tracking_number: &str,
+) -> Result<(), AppError> {
+    use crate::entity::{customers, prelude::Users as UsersEntity, users};
+    let (email, locale) = match (order.customer_id, &order.guest_email) {
+        (Some(customer_id), _) => {
+            let user = UsersEntity::find()
+                .join(JoinType::InnerJoin, users::Relation::Customers.def())
+                .filter(customers::Column::CustomerId.eq(customer_id))
+                .one(db)
+                .await?
+                .ok_or_else(|| AppError::Internal("Customer of the order not found".to_string()))?;
+            (user.email, user.locale)
+        }
+        (None, Some(guest_email)) => (guest_email.clone(), None),
+        (None, None) => return Ok(()),
+    };
+
+    send_templated(
+        db,
+        TenantId(order.tenant_id),
+        TemplateKey::OrderShipped,
+        email,
+        locale.as_deref(),
+        &[
+            ("order_id", order.order_id.to_string()),
+            ("carrier", carrier.display_name().to_string()),
+            ("tracking_number", tracking_number.to_string()),
+            ("tracking_url", carrier.tracking_url(tracking_number)),
+        ],
+    )
+    .await
+}
+
+// statuses an order can still be sent elsewhere in, nothing has been handed to a carrier yet
+const ADDRESS_CHANGE_STATUSES: [&str; 2] = ["PENDING", "PAID"];
+
+// moves the customer's order to another of their addresses. The customs data of every line is
+// worked out again for the new destination, so an order that can't be shipped there is refused
+// and the lines going abroad carry what the carrier will ask for
+pub async fn change_shipping_address(
+    db: &DatabaseConnection,
+    customer_id: i32,
+    order_id: OrderId,
+    address_id: i32,
+    changed_by: i32,
+) -> Result<OrdersModel, async_graphql::Error> {
+    use crate::entity::{
+        addresses, order_address_changes, order_items, orders,
+        prelude::{
+            Addresses as AddressesEntity, OrderAddressChanges, OrderItems as OrderItemsEntity,
+            Orders as OrdersEntity, Products as ProductsEntity,
+        },
+    };
+    let txn = db.begin().await?;
+
+    // locked so the order can't be shipped while it is being moved
+    let order = OrdersEntity::find_by_id(order_id)
+        .lock_exclusive()
+        .one(&txn)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Order").extend())?;
+    if order.customer_id != Some(customer_id) {
+        return Err(AppError::forbidden().extend());
+    }
+    if !ADDRESS_CHANGE_STATUSES.contains(&order.status.as_str()) {
+        return Err(AppError::invalid(format!(
+            "The address of an order that is {} can't be changed",
+            order.status.to_lowercase()
+        ))
+        .extend());
+    }
+    if order.shipping_address_id == address_id {
+        return Ok(order);
+    }
+
+    let address = AddressesEntity::find_by_id(address_id)
+        .filter(addresses::Column::CustomerId.eq(customer_id))
+        .one(&txn)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Address").extend())?;
+
+    let items = OrderItemsEntity::find()
+        .filter(order_items::Column::OrderId.eq(order.order_id))
+        .find_also_related(ProductsEntity)
+        .all(&txn)
+        .await?;
+    let customs = customs_lines(
+        &txn,
+        &address.country,
+        &items
+            .iter()
+            .filter_map(|(item, product)| {
+                product.as_ref().map(|product| (product, item.unit_price))
+            })
+            .collect::<Vec<_>>(),
+    )
+    .await
+    .map_err(|e| e.extend())?;
+    for (item, _) in items {
+        let customs = customs.get(&item.product_id);
+        let mut item: order_items::ActiveModel = item.into();
+        item.hs_code = Set(customs.map(|line| line.hs_code.clone()));
+        item.customs_value = Set(customs.map(|line| line.customs_value));
+        item.origin_country = Set(customs.map(|line| line.origin_country.clone()));
+        item.update(&txn).await?;
+    }
+
+    let previous_address_id = order.shipping_address_id;
+    let mut order: orders::ActiveModel = order.into();
+    order.shipping_address_id = Set(address.address_id);
+    let order = order.update(&txn).await?;
+    OrderAddressChanges::insert(order_address_changes::ActiveModel {
+        order_id: Set(order.order_id),
+        previous_address_id: Set(Some(previous_address_id)),
+        new_address_id: Set(Some(address.address_id)),
+        changed_by: Set(Some(changed_by)),
+        ..Default::default()
+    })
+    .exec(&txn)
+    .await?;
+
+    txn.commit().await?;
+
+    if let Err(e) = notify_address_change(db, &order, &address).await {
+        eprintln!(
+            "Address change notification failed for order {}: {}",
+            order.order_id, e
+        );
+    }
+
+    Ok(order)
+}
+
+// every supplier with a product in the order, they may already be picking it
+async fn notify_address_change(
+    db: &DatabaseConnection,
+    order: &OrdersModel,
+    address: &AddressesModel,
+) -> Result<(), AppError> {
+    use crate::entity::{
+        order_items,
+        prelude::{
+            OrderItems as OrderItemsEntity, Products as ProductsEntity, Users as UsersEntity,
+        },
+        products, suppliers, users,
+    };
+
+    let product_ids: Vec<i32> = OrderItemsEntity::find()
+        .filter(order_items::Column::OrderId.eq(order.order_id))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|item| item.product_id)
+        .collect();
+    let supplier_ids: Vec<i32> = ProductsEntity::find()
+        .filter(products::Column::ProductId.is_in(product_ids))
+        .all(db)
+        .await?
+        .into_iter()
+        .filter_map(|product| product.supplier_id)
+        .collect();
+    let recipients = UsersEntity::find()
+        .join(JoinType::InnerJoin, users::Relation::Suppliers.def())
+        .filter(suppliers::Column::SupplierId.is_in(supplier_ids))
+        .all(db)
+        .await?;
+
+    for user in recipients {
+        send_templated(
+            db,
+            TenantId(order.tenant_id),
+            TemplateKey::ShippingAddressChanged,
+            user.email,
+            user.locale.as_deref(),
+            &[
+                ("order_id", order.order_id.to_string()),
+                ("city", address.city.clone()),
+                ("country", address.country.clone()),
+            ],
+        )
+        .await?;
+    }
+    Ok(())
 }
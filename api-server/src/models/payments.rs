@@ -3,7 +3,9 @@ use crate::entity::{
     payment_methods::{self, Model as PaymentMethodsModel},
     sea_orm_active_enums::PaymentMethodType,
 };
-use async_graphql::{InputObject, SimpleObject};
+use crate::error::AppError;
+use crate::pii::{pii, PiiKind};
+use async_graphql::{ErrorExtensions, InputObject, SimpleObject};
 use sea_orm::{
     prelude::Date, ActiveEnum, ActiveModelTrait, ActiveValue::Set, ColumnTrait,
     DatabaseTransaction, EntityTrait, QueryFilter,
@@ -16,14 +18,21 @@ pub struct PaymentMethods {
     pub payment_type: String,
     pub is_default: Option<bool>,
     pub bank_name: Option<String>,
+    #[graphql(directive = pii::apply(PiiKind::Name))]
     pub account_holder_name: Option<String>,
     pub card_number: Option<String>,
     pub card_expiration_date: Option<Date>,
+    #[graphql(directive = pii::apply(PiiKind::BankAccount))]
     pub iban: Option<String>,
     pub upi_id: Option<String>,
+    #[graphql(directive = pii::apply(PiiKind::BankAccount))]
     pub bank_account_number: Option<String>,
     pub ifsc_code: Option<String>,
     pub card_type_id: Option<i32>,
+    // set for methods saved through the payment provider, only these can be used for one click checkout
+    pub provider: Option<String>,
+    pub card_brand: Option<String>,
+    pub card_last4: Option<String>,
 }
 
 impl From<PaymentMethodsModel> for PaymentMethods {
@@ -35,13 +44,20 @@ impl From<PaymentMethodsModel> for PaymentMethods {
             is_default: payment_method.is_default,
             bank_name: payment_method.bank_name,
             account_holder_name: payment_method.account_holder_name,
-            card_number: payment_method.card_number,
+            // legacy rows still hold the number, only the last digits ever leave the server
+            card_number: payment_method.card_number.map(|number| {
+                let number = number.trim();
+                format!("************{}", &number[number.len().saturating_sub(4)..])
+            }),
             card_expiration_date: payment_method.card_expiration_date,
             iban: payment_method.iban,
             upi_id: payment_method.upi_id,
             bank_account_number: payment_method.bank_account_number,
             ifsc_code: payment_method.ifsc_code,
             card_type_id: payment_method.card_type_id,
+            provider: payment_method.provider,
+            card_brand: payment_method.card_brand,
+            card_last4: payment_method.card_last4,
         }
     }
 }
@@ -51,16 +67,89 @@ pub struct RegisterPaymentMethod {
     pub payment_type: String,
     pub is_default: Option<bool>,
     pub bank_name: Option<String>,
+    #[graphql(directive = pii::apply(PiiKind::Name))]
     pub account_holder_name: Option<String>,
+    #[graphql(
+        deprecation = "Cards are saved with savePaymentMethod, raw card numbers are rejected"
+    )]
     pub card_number: Option<String>,
+    #[graphql(
+        deprecation = "Cards are saved with savePaymentMethod, raw card numbers are rejected"
+    )]
     pub card_expiration_date: Option<Date>,
+    #[graphql(directive = pii::apply(PiiKind::BankAccount))]
     pub iban: Option<String>,
     pub upi_id: Option<String>,
+    #[graphql(directive = pii::apply(PiiKind::BankAccount))]
     pub bank_account_number: Option<String>,
     pub ifsc_code: Option<String>,
+    #[graphql(
+        deprecation = "Cards are saved with savePaymentMethod, raw card numbers are rejected"
+    )]
     pub card_type_name: Option<String>,
 }
 
+impl RegisterPaymentMethod {
+    // by their GraphQL names, for telling the client which of them the payment type has no use for
+    fn given_fields(&self) -> Vec<&'static str> {
+        [
+            ("bankName", self.bank_name.is_some()),
+            ("accountHolderName", self.account_holder_name.is_some()),
+            ("cardNumber", self.card_number.is_some()),
+            ("cardExpirationDate", self.card_expiration_date.is_some()),
+            ("iban", self.iban.is_some()),
+            ("upiId", self.upi_id.is_some()),
+            ("bankAccountNumber", self.bank_account_number.is_some()),
+            ("ifscCode", self.ifsc_code.is_some()),
+            ("cardTypeName", self.card_type_name.is_some()),
+        ]
+        .into_iter()
+        .filter_map(|(field, given)| given.then_some(field))
+        .collect()
+    }
+}
+
+// only one default per customer, the previous one is unset
+pub async fn clear_default_payment_method(
+    customer_id: i32,
+    txn: &DatabaseTransaction,
+) -> Result<(), async_graphql::Error> {
+    let default_payment_method = payment_methods::Entity::find()
+        .filter(payment_methods::Column::CustomerId.eq(customer_id))
+        .filter(payment_methods::Column::IsDefault.eq(true))
+        .one(txn)
+        .await?;
+    if let Some(default_payment_method) = default_payment_method {
+        let mut default_payment_method: payment_methods::ActiveModel =
+            default_payment_method.into();
+        default_payment_method.is_default = Set(Some(false));
+        default_payment_method.update(txn).await?;
+    }
+    Ok(())
+}
+
+// card brands reported by the provider are kept in card_types so cardType keeps working
+pub async fn card_type_for_brand(
+    brand: &str,
+    txn: &DatabaseTransaction,
+) -> Result<i32, async_graphql::Error> {
+    if let Some(card_type) = card_types::Entity::find()
+        .filter(card_types::Column::Name.eq(brand))
+        .one(txn)
+        .await?
+    {
+        return Ok(card_type.card_type_id);
+    }
+
+    Ok(card_types::Entity::insert(card_types::ActiveModel {
+        name: Set(brand.to_string()),
+        ..Default::default()
+    })
+    .exec(txn)
+    .await?
+    .last_insert_id)
+}
+
 pub async fn create_payment_method(
     customer_id: i32,
     is_default: Option<bool>,
@@ -69,90 +158,69 @@ pub async fn create_payment_method(
 ) -> Result<payment_methods::ActiveModel, async_graphql::Error> {
     // check if any default payment method exists and update it to not default
     if is_default.unwrap_or(false) {
-        let default_payment_method = payment_methods::Entity::find()
-            .filter(payment_methods::Column::CustomerId.eq(customer_id))
-            .filter(payment_methods::Column::IsDefault.eq(true))
-            .one(txn)
-            .await?;
-        if default_payment_method.is_some() {
-            // update the existing default payment method to not default
-            let mut default_payment_method: payment_methods::ActiveModel =
-                default_payment_method.unwrap().into();
-            default_payment_method.is_default = Set(Some(false));
-            default_payment_method.update(txn).await?;
-        }
+        clear_default_payment_method(customer_id, txn).await?;
     }
 
-    match input.payment_type.as_str() {
-        "card" => {
-            let card_type_id = Some(
-                card_types::Entity::insert(card_types::ActiveModel {
-                    name: Set(input
-                        .card_type_name
-                        .clone()
-                        .unwrap_or_else(|| "Unknown".to_string())),
-                    ..Default::default()
-                })
-                .exec(txn)
-                .await?
-                .last_insert_id,
-            );
-            Ok(payment_methods::ActiveModel {
-                customer_id: Set(customer_id),
-                payment_type: Set(PaymentMethodType::Card),
-                is_default: Set(is_default),
-                card_number: Set(Some(
-                    input.card_number.clone().ok_or("Card number is required")?,
-                )),
-                card_expiration_date: Set(Some(
-                    input
-                        .card_expiration_date
-                        .ok_or("Card expiration date is required")?,
-                )),
-                card_type_id: Set(card_type_id),
-                ..Default::default()
-            })
-        }
-        "upi" => Ok(payment_methods::ActiveModel {
-            customer_id: Set(customer_id),
-            payment_type: Set(PaymentMethodType::Upi),
-            is_default: Set(is_default),
-            upi_id: Set(Some(input.upi_id.clone().ok_or("UPI ID is required")?)),
-            ..Default::default()
-        }),
-        "iban" => Ok(payment_methods::ActiveModel {
-            customer_id: Set(customer_id),
-            payment_type: Set(PaymentMethodType::Iban),
-            is_default: Set(is_default),
-            iban: Set(Some(input.iban.clone().ok_or("IBAN number is required")?)),
-            ..Default::default()
-        }),
-        "netbanking" => Ok(payment_methods::ActiveModel {
-            customer_id: Set(customer_id),
-            payment_type: Set(PaymentMethodType::Netbanking),
-            is_default: Set(is_default),
-            bank_name: Set(Some(
-                input.bank_name.clone().ok_or("Bank name is required")?,
-            )),
-            account_holder_name: Set(Some(
-                input
-                    .account_holder_name
-                    .clone()
-                    .ok_or("Account holder name is required")?,
-            )),
-            bank_account_number: Set(Some(
-                input
-                    .bank_account_number
-                    .clone()
-                    .ok_or("Bank account number is required")?,
-            )),
-            ifsc_code: Set(Some(
-                input.ifsc_code.clone().ok_or("IFSC code is required")?,
-            )),
-            ..Default::default()
-        }),
-        _ => Err("Invalid payment type".into()),
+    // the fields each type stores, the required ones first. Anything else given is refused
+    // rather than dropped
+    let (payment_type, required, optional): (PaymentMethodType, &[&str], &[&str]) =
+        match input.payment_type.as_str() {
+            // raw card data is never accepted, cards go through savePaymentMethod with a provider token
+            "card" => {
+                return Err(AppError::invalid(
+                    "Cards must be tokenized by the payment provider, use savePaymentMethod",
+                )
+                .extend())
+            }
+            "upi" => (PaymentMethodType::Upi, &["upiId"], &["accountHolderName"]),
+            "iban" => (
+                PaymentMethodType::Iban,
+                &["iban"],
+                &["bankName", "accountHolderName"],
+            ),
+            "netbanking" => (
+                PaymentMethodType::Netbanking,
+                &[
+                    "bankName",
+                    "accountHolderName",
+                    "bankAccountNumber",
+                    "ifscCode",
+                ],
+                &[],
+            ),
+            _ => return Err(AppError::invalid("Invalid payment type").extend()),
+        };
+    let given = input.given_fields();
+    if let Some(field) = required.iter().find(|field| !given.contains(field)) {
+        return Err(AppError::invalid(format!(
+            "{} is required for {} payment methods",
+            field, input.payment_type
+        ))
+        .extend());
     }
+    if let Some(field) = given
+        .iter()
+        .find(|field| !required.contains(field) && !optional.contains(field))
+    {
+        return Err(AppError::invalid(format!(
+            "{} doesn't apply to {} payment methods",
+            field, input.payment_type
+        ))
+        .extend());
+    }
+
+    Ok(payment_methods::ActiveModel {
+        customer_id: Set(customer_id),
+        payment_type: Set(payment_type),
+        is_default: Set(is_default),
+        bank_name: Set(input.bank_name),
+        account_holder_name: Set(input.account_holder_name),
+        iban: Set(input.iban),
+        upi_id: Set(input.upi_id),
+        bank_account_number: Set(input.bank_account_number),
+        ifsc_code: Set(input.ifsc_code),
+        ..Default::default()
+    })
 }
 
 #[derive(SimpleObject)]
@@ -0,0 +1,139 @@
+use crate::entity::{
+    discounts,
+    prelude::{Discounts, ProductPriceHistory, Products},
+    product_price_history, products,
+};
+use chrono::{Duration, Utc};
+use sea_orm::{
+    prelude::Decimal,
+    sea_query::{Expr, Func},
+    ColumnTrait, Condition, ConnectionTrait, DbErr, EntityTrait, QueryFilter, QueryOrder,
+    QuerySelect,
+};
+use std::collections::{HashMap, HashSet};
+
+// how far back the reference price for a price drop badge looks
+pub const REFERENCE_PRICE_DAYS: i64 = 30;
+
+// the products running a discount of their own or of their category
+async fn on_promotion<C: ConnectionTrait>(
+    db: &C,
+    product_ids: Vec<i32>,
+) -> Result<HashSet<i32>, DbErr> {
+    let categories: Vec<(i32, Option<i32>)> = Products::find()
+        .select_only()
+        .column(products::Column::ProductId)
+        .column(products::Column::CategoryId)
+        .filter(products::Column::ProductId.is_in(product_ids.clone()))
+        .into_tuple()
+        .all(db)
+        .await?;
+    let now = Utc::now().fixed_offset();
+    let running: Vec<(Option<i32>, Option<i32>)> = Discounts::find()
+        .select_only()
+        .column(discounts::Column::ProductId)
+        .column(discounts::Column::CategoryId)
+        .filter(
+            Condition::any()
+                .add(discounts::Column::ProductId.is_in(product_ids))
+                .add(
+                    discounts::Column::CategoryId.is_in(
+                        categories
+                            .iter()
+                            .filter_map(|(_, category_id)| *category_id),
+                    ),
+                ),
+        )
+        .filter(
+            Condition::any()
+                .add(discounts::Column::ValidFrom.is_null())
+                .add(discounts::Column::ValidFrom.lte(now)),
+        )
+        .filter(
+            Condition::any()
+                .add(discounts::Column::ValidUntil.is_null())
+                .add(discounts::Column::ValidUntil.gt(now)),
+        )
+        .filter(
+            Condition::any()
+                .add(discounts::Column::MaxUses.is_null())
+                .add(
+                    Expr::expr(Func::coalesce([
+                        Expr::col(discounts::Column::TimesUsed).into(),
+                        Expr::val(0).into(),
+                    ]))
+                    .lt(Expr::col(discounts::Column::MaxUses)),
+                ),
+        )
+        .into_tuple()
+        .all(db)
+        .await?;
+
+    Ok(categories
+        .into_iter()
+        .filter(|(product_id, category_id)| {
+            running
+                .iter()
+                .any(|(discounted_product, discounted_category)| {
+                    *discounted_product == Some(*product_id)
+                        || (category_id.is_some() && discounted_category == category_id)
+                })
+        })
+        .map(|(product_id, _)| product_id)
+        .collect())
+}
+
+// the reference price of each product on promotion. That's the highest base price the product had
+// at any point in the last 30 days, including the price it already had when the window opened.
+// Products that aren't on promotion or have no recorded history are left out
+pub async fn promotion_reference_prices<C: ConnectionTrait>(
+    db: &C,
+    product_ids: Vec<i32>,
+) -> Result<HashMap<i32, Decimal>, DbErr> {
+    let promoted: Vec<i32> = on_promotion(db, product_ids).await?.into_iter().collect();
+    if promoted.is_empty() {
+        return Ok(HashMap::new());
+    }
+    let since = (Utc::now() - Duration::days(REFERENCE_PRICE_DAYS)).fixed_offset();
+
+    let highest_since: Vec<(i32, Decimal)> = ProductPriceHistory::find()
+        .select_only()
+        .column(product_price_history::Column::ProductId)
+        .expr(Expr::col(product_price_history::Column::Price).max())
+        .filter(product_price_history::Column::ProductId.is_in(promoted.clone()))
+        .filter(product_price_history::Column::RecordedAt.gte(since))
+        .group_by(product_price_history::Column::ProductId)
+        .into_tuple()
+        .all(db)
+        .await?;
+    let in_effect_at_start: Vec<(i32, Decimal)> = ProductPriceHistory::find()
+        .select_only()
+        .distinct_on([product_price_history::Column::ProductId])
+        .column(product_price_history::Column::ProductId)
+        .column(product_price_history::Column::Price)
+        .filter(product_price_history::Column::ProductId.is_in(promoted))
+        .filter(product_price_history::Column::RecordedAt.lt(since))
+        .order_by_asc(product_price_history::Column::ProductId)
+        .order_by_desc(product_price_history::Column::RecordedAt)
+        .into_tuple()
+        .all(db)
+        .await?;
+
+    let mut references: HashMap<i32, Decimal> = HashMap::new();
+    for (product_id, price) in highest_since.into_iter().chain(in_effect_at_start) {
+        let reference = references.entry(product_id).or_insert(price);
+        *reference = (*reference).max(price);
+    }
+    Ok(references)
+}
+
+// the whole percent the current price is below the reference, rounded down so the badge never
+// claims more than the actual drop. None unless the price really is lower
+pub fn price_drop(reference: Decimal, current: Decimal) -> Option<i32> {
+    if reference <= Decimal::ZERO || current >= reference {
+        return None;
+    }
+
+    let percent = ((reference - current) * Decimal::ONE_HUNDRED / reference).floor();
+    i32::try_from(percent).ok().filter(|percent| *percent > 0)
+}
@@ -0,0 +1,27 @@
+use crate::{entity::product_images::Model as ProductImagesModel, ids::ProductId};
+use async_graphql::SimpleObject;
+use sea_orm::prelude::DateTimeWithTimeZone;
+
+#[derive(SimpleObject)]
+pub struct ProductImages {
+    pub image_id: i32,
+    pub product_id: ProductId,
+    // the original as uploaded, resized variants come from imageUrl
+    pub url: String,
+    pub content_type: String,
+    pub size_bytes: i32,
+    pub uploaded_at: DateTimeWithTimeZone,
+}
+
+impl From<ProductImagesModel> for ProductImages {
+    fn from(val: ProductImagesModel) -> ProductImages {
+        ProductImages {
+            image_id: val.image_id,
+            product_id: ProductId(val.product_id),
+            url: val.url,
+            content_type: val.content_type,
+            size_bytes: val.size_bytes,
+            uploaded_at: val.uploaded_at,
+        }
+    }
+}
@@ -1,21 +1,55 @@
 use crate::{
+    auth::AuthenticatedUser,
+    catalog_cache::CatalogCache,
+    config::{CurrencyPolicy, DuplicatePolicy, RegionConfig, ReviewPolicy},
+    customs::normalize_hs_code,
     entity::{
         categories::Model as CategoriesModel, discounts::Model as DiscountsModel, products,
         products::Entity as ProductsEntity, products::Model as ProductsModel,
-        reviews::Model as ReviewsModel,
+        reviews::Model as ReviewsModel, suppliers::Model as SuppliersModel,
     },
-    models::order_und_pagination::{OrderAndPagination, OrderByColumn, OrderByOrder, PageInfo},
+    error::AppError,
+    graphql::complexity,
+    hot_cache::HotCache,
+    ids::{global_id, ProductId, TenantId},
+    images::{ImageFormat, ImageSize},
+    loaders::{
+        CategoryLoader, ProductAttributesLoader, ReferencePriceLoader, SupplierLoader,
+        WishlistLoader,
+    },
+    models::{
+        boost_rules::BoostRuleSet,
+        category_attributes::{ProductAttributeInput, ProductAttributes},
+        order_status::OrderStatus,
+        order_und_pagination::{OrderAndPagination, OrderByColumn, OrderByOrder, PageInfo},
+        price_history::price_drop,
+        product_images::ProductImages,
+        regional_prices::{price_in_region, RegionalPrice},
+        returns::{effective_return_policy, is_returnable, ProductReturnPolicy},
+        review_summaries::ReviewSummary,
+        videos::ProductVideos,
+    },
+    tenancy::{current_tenant, TenantScope},
+};
+use async_graphql::{
+    dataloader::DataLoader, ComplexObject, Context, Enum, ErrorExtensions, InputObject,
+    SimpleObject, ID,
 };
-use async_graphql::{InputObject, SimpleObject};
+use chrono::{Duration, Utc};
 use sea_orm::{
-    prelude::DateTimeWithTimeZone, sea_query::error::Error, ActiveValue::Set, ColumnTrait,
-    DatabaseConnection, DatabaseTransaction, EntityTrait, QueryFilter, QueryOrder, Select,
+    prelude::{DateTimeWithTimeZone, Decimal},
+    sea_query::error::Error,
+    ActiveValue::Set,
+    ColumnTrait, ConnectionTrait, DatabaseConnection, DatabaseTransaction, DbBackend, DbErr,
+    EntityTrait, FromQueryResult, PaginatorTrait, QueryFilter, QueryOrder, Select, Statement,
 };
-use std::string::ToString;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, string::ToString};
 
-#[derive(SimpleObject)]
+#[derive(SimpleObject, Serialize, Deserialize)]
+#[graphql(complex)]
 pub struct Products {
-    pub product_id: i32,
+    pub product_id: ProductId,
     pub name: String,
     pub description: Option<String>,
     pub base_price: String,
@@ -23,13 +57,31 @@ pub struct Products {
     pub supplier_id: Option<i32>,
     pub stock_quantity: i32,
     pub media_paths: Option<Vec<String>>,
-    pub base_product_id: Option<i32>,
+    pub base_product_id: Option<ProductId>,
+    pub sku: Option<String>,
+    // units one customer may buy within limitWindowDays, or ever when the window is null
+    pub max_per_customer: Option<i32>,
+    pub limit_window_days: Option<i32>,
+    // sold per date through availability instead of from stock
+    pub date_bookable: bool,
+    // off sale, only the supplier still sees it
+    pub archived_at: Option<DateTimeWithTimeZone>,
+    // why an admin took the product down, it can't be unarchived while this is set
+    pub removal_reason: Option<String>,
+    // customs data for shipping abroad, the declared value defaults to the price paid
+    pub hs_code: Option<String>,
+    pub customs_value: Option<String>,
+    // paid placement in search results, clients must label these as sponsored
+    pub is_sponsored: bool,
+    pub sponsored_campaign_id: Option<i32>,
+    // only filled in on the results of registerProduct, updateProduct and importProductsCsv
+    pub duplicate_warnings: Vec<DuplicateWarning>,
 }
 
 impl From<ProductsModel> for Products {
     fn from(val: ProductsModel) -> Products {
         Products {
-            product_id: val.product_id,
+            product_id: val.product_id.into(),
             name: val.name,
             description: val.description,
             base_price: val.base_price.to_string(),
@@ -37,12 +89,281 @@ impl From<ProductsModel> for Products {
             supplier_id: val.supplier_id,
             stock_quantity: val.stock_quantity,
             media_paths: val.media_paths,
-            base_product_id: val.base_product_id,
+            base_product_id: val.base_product_id.map(ProductId),
+            sku: val.sku,
+            max_per_customer: val.max_per_customer,
+            limit_window_days: val.limit_window_days,
+            date_bookable: val.date_bookable,
+            archived_at: val.archived_at,
+            removal_reason: val.removal_reason,
+            hs_code: val.hs_code,
+            customs_value: val.customs_value.map(|value| value.to_string()),
+            is_sponsored: false,
+            sponsored_campaign_id: None,
+            duplicate_warnings: Vec::new(),
         }
     }
 }
 
-#[derive(SimpleObject)]
+impl Products {
+    async fn regional_price(
+        &self,
+        ctx: &Context<'_>,
+        region: Option<String>,
+    ) -> Result<(Decimal, String), async_graphql::Error> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let region = ctx.data::<RegionConfig>()?.effective_region(region);
+
+        Ok(price_in_region(
+            db,
+            self.product_id.0,
+            Decimal::from_str_exact(&self.base_price)?,
+            region.as_deref(),
+            &ctx.data::<CurrencyPolicy>()?.currency,
+        )
+        .await?)
+    }
+
+    // the reference price and the drop below it, batched with the other products in the response
+    async fn price_drop(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<Option<(Decimal, i32)>, async_graphql::Error> {
+        let Some(reference) = ctx
+            .data::<DataLoader<ReferencePriceLoader>>()?
+            .load_one(self.product_id.0)
+            .await?
+        else {
+            return Ok(None);
+        };
+        let current = Decimal::from_str_exact(&self.base_price)?;
+        Ok(price_drop(reference, current).map(|percent| (reference, percent)))
+    }
+}
+
+#[ComplexObject]
+impl Products {
+    pub async fn id(&self) -> ID {
+        global_id("Products", self.product_id.0)
+    }
+
+    async fn review_summary(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<ReviewSummary, async_graphql::Error> {
+        use crate::entity::prelude::ReviewSummaries as ReviewSummariesEntity;
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        Ok(ReviewSummariesEntity::find_by_id(self.product_id)
+            .one(db)
+            .await?
+            .map(|summary| summary.into())
+            .unwrap_or_else(ReviewSummary::empty))
+    }
+
+    #[graphql(complexity = "complexity::unbounded(child_complexity)")]
+    async fn attributes(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<Vec<ProductAttributes>, async_graphql::Error> {
+        Ok(ctx
+            .data::<DataLoader<ProductAttributesLoader>>()?
+            .load_one(self.product_id.0)
+            .await?
+            .unwrap_or_default())
+    }
+
+    // null for uncategorised products, batched with the other products in the response
+    async fn category(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<Option<Categories>, async_graphql::Error> {
+        let Some(category_id) = self.category_id else {
+            return Ok(None);
+        };
+        ctx.data::<DataLoader<CategoryLoader>>()?
+            .load_one(category_id)
+            .await
+    }
+
+    // null for products without a supplier, batched with the other products in the response
+    async fn supplier(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<Option<ProductSupplier>, async_graphql::Error> {
+        let Some(supplier_id) = self.supplier_id else {
+            return Ok(None);
+        };
+        ctx.data::<DataLoader<SupplierLoader>>()?
+            .load_one(supplier_id)
+            .await
+    }
+
+    // false for anonymous callers and accounts without a customer profile, batched with the other
+    // products in the response
+    async fn is_wishlisted(&self, ctx: &Context<'_>) -> Result<bool, async_graphql::Error> {
+        let Ok(claims) = AuthenticatedUser::from_ctx(ctx) else {
+            return Ok(false);
+        };
+        let user_id = claims.user_id.parse::<i32>()?;
+
+        Ok(ctx
+            .data::<DataLoader<WishlistLoader>>()?
+            .load_one((user_id, self.product_id.0))
+            .await?
+            .unwrap_or(false))
+    }
+
+    // null for products without a supplier
+    async fn return_policy(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<Option<ProductReturnPolicy>, async_graphql::Error> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let Some(supplier_id) = self.supplier_id else {
+            return Ok(None);
+        };
+
+        let policy = effective_return_policy(db, supplier_id).await?;
+        Ok(Some(ProductReturnPolicy {
+            returnable: is_returnable(ctx, &policy, self.category_id)
+                .await
+                .map_err(|e| e.extend())?,
+            return_window_days: policy.return_window_days,
+            restocking_fee_percent: policy.restocking_fee_percent.to_string(),
+        }))
+    }
+
+    // path from the root category down to the product's own, empty for uncategorised products
+    #[graphql(complexity = "complexity::unbounded(child_complexity)")]
+    async fn breadcrumbs(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<Vec<Categories>, async_graphql::Error> {
+        let Some(category_id) = self.category_id else {
+            return Ok(Vec::new());
+        };
+        let tree = category_tree(ctx).await.map_err(|e| e.extend())?;
+
+        Ok(category_breadcrumbs(tree, category_id))
+    }
+
+    // uploaded originals, oldest first
+    #[graphql(complexity = "complexity::unbounded(child_complexity)")]
+    async fn images(&self, ctx: &Context<'_>) -> Result<Vec<ProductImages>, async_graphql::Error> {
+        use crate::entity::{prelude::ProductImages as ProductImagesEntity, product_images};
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        Ok(ProductImagesEntity::find()
+            .filter(product_images::Column::ProductId.eq(self.product_id.0))
+            .order_by_asc(product_images::Column::UploadedAt)
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|image| image.into())
+            .collect())
+    }
+
+    // only videos that finished processing, suppliers follow the rest through productVideos
+    #[graphql(complexity = "complexity::unbounded(child_complexity)")]
+    async fn videos(&self, ctx: &Context<'_>) -> Result<Vec<ProductVideos>, async_graphql::Error> {
+        use crate::entity::{prelude::ProductVideos as ProductVideosEntity, product_videos};
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        Ok(ProductVideosEntity::find()
+            .filter(product_videos::Column::ProductId.eq(self.product_id.0))
+            .filter(product_videos::Column::Status.eq("READY"))
+            .order_by_asc(product_videos::Column::CreatedAt)
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|video| video.into())
+            .collect())
+    }
+
+    // resized variant of the primary image, the original is returned until the worker has produced it
+    async fn image_url(
+        &self,
+        ctx: &Context<'_>,
+        size: ImageSize,
+        #[graphql(default_with = "ImageFormat::Webp")] format: ImageFormat,
+    ) -> Result<Option<String>, async_graphql::Error> {
+        use crate::entity::{prelude::ProductImageVariants, product_image_variants};
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        let Some(primary) = self.media_paths.as_ref().and_then(|paths| paths.first()) else {
+            return Ok(None);
+        };
+
+        let variant = ProductImageVariants::find()
+            .filter(product_image_variants::Column::SourceUrl.eq(primary.as_str()))
+            .filter(product_image_variants::Column::Size.eq(size.as_str()))
+            .filter(product_image_variants::Column::Format.eq(format.as_str()))
+            .one(db)
+            .await?;
+
+        Ok(Some(
+            variant
+                .map(|variant| variant.url)
+                .unwrap_or(primary.clone()),
+        ))
+    }
+
+    #[graphql(complexity = "complexity::unbounded(child_complexity)")]
+    async fn regional_prices(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<Vec<RegionalPrice>, async_graphql::Error> {
+        use crate::entity::{prelude::ProductRegionPrices, product_region_prices};
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        Ok(ProductRegionPrices::find()
+            .filter(product_region_prices::Column::ProductId.eq(self.product_id.0))
+            .order_by_asc(product_region_prices::Column::Region)
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|price| price.into())
+            .collect())
+    }
+
+    // what checkout charges in the region, pinned deployments always price in their own region
+    async fn price(
+        &self,
+        ctx: &Context<'_>,
+        region: Option<String>,
+    ) -> Result<String, async_graphql::Error> {
+        Ok(self.regional_price(ctx, region).await?.0.to_string())
+    }
+
+    // the ISO 4217 code price is in, a regional override can be in another currency than the store
+    async fn price_currency(
+        &self,
+        ctx: &Context<'_>,
+        region: Option<String>,
+    ) -> Result<String, async_graphql::Error> {
+        Ok(self.regional_price(ctx, region).await?.1)
+    }
+
+    // the highest base price of the last 30 days, only while the product is on promotion and sells
+    // below it
+    async fn was_price(&self, ctx: &Context<'_>) -> Result<Option<String>, async_graphql::Error> {
+        Ok(self
+            .price_drop(ctx)
+            .await?
+            .map(|(reference, _)| reference.to_string()))
+    }
+
+    // how far the base price is below wasPrice, in whole percent rounded down
+    async fn discount_percent(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<Option<i32>, async_graphql::Error> {
+        Ok(self.price_drop(ctx).await?.map(|(_, percent)| percent))
+    }
+}
+
+#[derive(SimpleObject, Serialize, Deserialize)]
 pub struct ProductsPaginate {
     pub products: Vec<Products>,
     pub page_info: PageInfo,
@@ -50,7 +371,7 @@ pub struct ProductsPaginate {
 
 pub async fn check_product_exists(
     txn: &DatabaseTransaction,
-    product_id: i32,
+    product_id: ProductId,
 ) -> Result<(), async_graphql::Error> {
     use crate::entity::products;
     if products::Entity::find_by_id(product_id)
@@ -58,7 +379,7 @@ pub async fn check_product_exists(
         .await?
         .is_none()
     {
-        return Err("Product not found".into());
+        return Err(AppError::NotFound("Product").extend());
     }
     Ok(())
 }
@@ -86,6 +407,104 @@ pub async fn paginate_products(
     }
 }
 
+// which products productsWithId lists, exactly one of them is set
+#[derive(Default)]
+pub struct ProductLookup {
+    pub category_id: Option<i32>,
+    pub supplier_id: Option<i32>,
+    pub base_product_id: Option<ProductId>,
+    pub product_id: Option<ProductId>,
+}
+
+// the answer of productsWithId, through the catalog cache. warmCache loads the same keys
+#[allow(clippy::too_many_arguments)]
+pub async fn cached_products_with_id(
+    catalog: &CatalogCache,
+    db: &DatabaseConnection,
+    boost_rules: &BoostRuleSet,
+    tenant: TenantId,
+    sandbox: bool,
+    lookup: ProductLookup,
+    region: Option<String>,
+    paginator: OrderAndPagination,
+) -> Result<ProductsPaginate, AppError> {
+    let page = paginator.pagination.page - 1;
+    let page_size = paginator.pagination.page_size;
+
+    let filter =
+        match (
+            lookup.category_id,
+            lookup.supplier_id,
+            lookup.base_product_id,
+            lookup.product_id,
+        ) {
+            (Some(category_id), None, None, None) => products::Column::CategoryId.eq(category_id),
+            (None, Some(supplier_id), None, None) => products::Column::SupplierId.eq(supplier_id),
+            (None, None, Some(base_product_id), None) => {
+                products::Column::BaseProductId.eq(base_product_id)
+            }
+            (None, None, None, Some(product_id)) => products::Column::ProductId.eq(product_id),
+            _ => return Err(AppError::invalid(
+                "Only one of category_id, supplier_id, base_product_id or product_id can be used",
+            )),
+        };
+
+    let args = (
+        lookup.category_id,
+        lookup.supplier_id,
+        lookup.base_product_id,
+        lookup.product_id,
+        region.clone(),
+        paginator.clone(),
+    );
+    catalog
+        .get_or_load(tenant, sandbox, "products_with_id", &args, || async {
+            let products = filter_region(
+                on_sale(ProductsEntity::find().filter(filter).for_tenant(tenant)),
+                region,
+            );
+            let products = boost_rules.apply(tenant, products);
+            let products = paginate_products(paginator, products)
+                .await
+                .map_err(|e| AppError::Internal(e.message))?;
+            let products = products.paginate(db, page_size);
+            let page_info = PageInfo {
+                total_pages: products.num_pages().await?,
+                total_items: products.num_items().await?,
+            };
+
+            Ok(ProductsPaginate {
+                products: products
+                    .fetch_page(page)
+                    .await?
+                    .into_iter()
+                    .map(|product| product.into())
+                    .collect(),
+                page_info,
+            })
+        })
+        .await
+}
+
+// limits a product listing to suppliers from one region, everything passes when no region applies
+pub fn filter_region(
+    entity: Select<ProductsEntity>,
+    region: Option<String>,
+) -> Select<ProductsEntity> {
+    use crate::entity::{prelude::Suppliers as SuppliersEntity, suppliers};
+    match region {
+        Some(region) => entity
+            .inner_join(SuppliersEntity)
+            .filter(suppliers::Column::Region.eq(region)),
+        None => entity,
+    }
+}
+
+// archived products stay reachable by id for past orders but are no longer listed or sold
+pub fn on_sale(entity: Select<ProductsEntity>) -> Select<ProductsEntity> {
+    entity.filter(products::Column::ArchivedAt.is_null())
+}
+
 #[derive(InputObject)]
 pub struct RegisterProduct {
     pub name: String,
@@ -95,23 +514,72 @@ pub struct RegisterProduct {
     pub supplier_id: Option<i32>,
     pub stock_quantity: i32,
     pub media_paths: Option<Vec<String>>,
-    pub base_product_id: Option<i32>,
+    pub base_product_id: Option<ProductId>,
+    // never exposed on Products, only used for margin based listing boosts
+    pub cost_price: Option<String>,
+    // stock keeping unit buyers order by, unique across the catalog
+    pub sku: Option<String>,
+    // purchase limit for limited drops, the window is ignored without a limit
+    pub max_per_customer: Option<i32>,
+    pub limit_window_days: Option<i32>,
+    // rentals and slot limited services, booked per date from the availability calendar
+    #[graphql(default)]
+    pub date_bookable: bool,
+    // checked against the category's attribute template, replaces the product's attributes on update
+    #[graphql(default)]
+    pub attributes: Vec<ProductAttributeInput>,
+    // required before the product can be shipped to another country than the supplier's
+    pub hs_code: Option<String>,
+    // per unit, declared to customs instead of the price paid
+    pub customs_value: Option<String>,
 }
 
 pub fn create_product_model(
     input: RegisterProduct,
     supplier_id: i32,
-) -> Result<products::ActiveModel, Error> {
+) -> Result<products::ActiveModel, async_graphql::Error> {
     use crate::entity::products;
+    if input.max_per_customer.is_some_and(|max| max <= 0)
+        || input.limit_window_days.is_some_and(|days| days <= 0)
+    {
+        return Err(
+            AppError::invalid("maxPerCustomer and limitWindowDays must be positive").extend(),
+        );
+    }
+    let customs_value = input
+        .customs_value
+        .as_deref()
+        .map(Decimal::from_str_exact)
+        .transpose()?;
+    if customs_value.is_some_and(|value| value < Decimal::ZERO) {
+        return Err(AppError::invalid("customsValue can't be negative").extend());
+    }
     Ok(products::ActiveModel {
         name: Set(input.name.clone()),
         description: Set(input.description.clone()),
-        base_price: Set(input.base_price.parse::<f32>().unwrap().try_into().unwrap()),
+        base_price: Set(Decimal::from_str_exact(input.base_price.trim())
+            .map_err(|_| AppError::invalid("basePrice must be a number").extend())?),
+        cost_price: Set(input
+            .cost_price
+            .as_deref()
+            .map(Decimal::from_str_exact)
+            .transpose()?),
         supplier_id: Set(Some(supplier_id)),
         category_id: Set(input.category_id),
-        base_product_id: Set(input.base_product_id),
+        base_product_id: Set(input.base_product_id.map(i32::from)),
         media_paths: Set(input.media_paths),
         stock_quantity: Set(input.stock_quantity),
+        sku: Set(input.sku),
+        max_per_customer: Set(input.max_per_customer),
+        limit_window_days: Set(input.max_per_customer.and(input.limit_window_days)),
+        date_bookable: Set(input.date_bookable),
+        hs_code: Set(input
+            .hs_code
+            .as_deref()
+            .map(normalize_hs_code)
+            .transpose()
+            .map_err(|e| e.extend())?),
+        customs_value: Set(customs_value),
         ..Default::default()
     })
 }
@@ -119,7 +587,7 @@ pub fn create_product_model(
 pub async fn check_if_supplier_owns_product(
     txn: &DatabaseConnection,
     supplier_id: i32,
-    product_id: i32,
+    product_id: ProductId,
 ) -> Result<(), async_graphql::Error> {
     use crate::entity::products;
     if products::Entity::find_by_id(product_id)
@@ -128,12 +596,132 @@ pub async fn check_if_supplier_owns_product(
         .await?
         .is_none()
     {
-        return Err("Supplier does not own this product".into());
+        return Err(AppError::invalid("Supplier does not own this product").extend());
     }
     Ok(())
 }
 
-#[derive(SimpleObject)]
+// an existing product of the same supplier that looks like the one being saved
+#[derive(SimpleObject, Serialize, Deserialize, PartialEq)]
+pub struct DuplicateWarning {
+    pub product_id: ProductId,
+    pub name: String,
+    pub similarity: f64,
+}
+
+#[derive(FromQueryResult)]
+struct SimilarProduct {
+    product_id: i32,
+    name: String,
+    similarity: f64,
+    exact: bool,
+}
+
+// trigram match on the name within the supplier's catalog, a shared category counts towards similarity.
+// exact name matches are rejected, close ones come back as warnings
+pub async fn check_duplicate_products<C: ConnectionTrait>(
+    db: &C,
+    supplier_id: i32,
+    name: &str,
+    category_id: Option<i32>,
+    exclude: Option<ProductId>,
+    policy: &DuplicatePolicy,
+) -> Result<Vec<DuplicateWarning>, AppError> {
+    let similar = SimilarProduct::find_by_statement(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        r#"SELECT product_id, name,
+                LEAST(similarity(lower(name), lower($2))
+                    + CASE WHEN category_id = $3 THEN 0.1 ELSE 0 END, 1)::float8 AS similarity,
+                lower(btrim(name)) = lower(btrim($2)) AS exact
+            FROM products
+            WHERE supplier_id = $1
+              AND ($4::int IS NULL OR product_id <> $4)
+              AND lower(name) % lower($2)
+            ORDER BY similarity DESC
+            LIMIT 5"#,
+        vec![
+            supplier_id.into(),
+            name.into(),
+            category_id.into(),
+            exclude.map(i32::from).into(),
+        ],
+    ))
+    .all(db)
+    .await?;
+
+    if let Some(duplicate) = similar.iter().find(|product| product.exact) {
+        return Err(AppError::Validation {
+            message: "Duplicate product".to_string(),
+            failed_rules: vec![format!(
+                "{} already exists as product {}",
+                duplicate.name, duplicate.product_id
+            )],
+        });
+    }
+
+    Ok(similar
+        .into_iter()
+        .filter(|product| product.similarity >= policy.similarity_threshold)
+        .map(|product| DuplicateWarning {
+            product_id: product.product_id.into(),
+            name: product.name,
+            similarity: product.similarity,
+        })
+        .collect())
+}
+
+// one line of a supplier catalog import, matched to existing products by name
+#[derive(Deserialize)]
+pub struct ProductCsvRow {
+    pub name: String,
+    pub description: Option<String>,
+    pub base_price: String,
+    pub category_id: Option<i32>,
+    pub stock_quantity: i32,
+    #[serde(default)]
+    pub sku: Option<String>,
+    // name=value pairs separated by ;, left empty an existing product keeps its attributes
+    #[serde(default)]
+    pub attributes: Option<String>,
+}
+
+pub fn parse_product_csv(bytes: &[u8]) -> Result<Vec<(ProductCsvRow, Decimal)>, AppError> {
+    let mut rows = Vec::new();
+    let mut failed_rules = Vec::new();
+
+    // line 1 is the header
+    for (line, record) in csv::Reader::from_reader(bytes)
+        .deserialize::<ProductCsvRow>()
+        .enumerate()
+        .map(|(index, record)| (index + 2, record))
+    {
+        match record {
+            Ok(row) => match Decimal::from_str_exact(row.base_price.trim()) {
+                Ok(price) if price > Decimal::ZERO => rows.push((row, price)),
+                _ => failed_rules.push(format!("line {}: invalid base_price", line)),
+            },
+            Err(e) => failed_rules.push(format!("line {}: {}", line, e)),
+        }
+    }
+
+    if !failed_rules.is_empty() {
+        return Err(AppError::Validation {
+            message: "Product CSV has invalid rows".to_string(),
+            failed_rules,
+        });
+    }
+    if rows.is_empty() {
+        return Err(AppError::Validation {
+            message: "Product CSV has no rows".to_string(),
+            failed_rules,
+        });
+    }
+
+    Ok(rows)
+}
+
+#[derive(SimpleObject, Serialize, Deserialize, Clone)]
+#[graphql(complex)]
 pub struct Categories {
     pub category_id: i32,
     pub name: String,
@@ -150,6 +738,123 @@ impl From<CategoriesModel> for Categories {
     }
 }
 
+// the public side of a supplier, what shoppers see next to its products
+#[derive(SimpleObject, Clone)]
+pub struct ProductSupplier {
+    pub supplier_id: i32,
+    pub name: String,
+    pub region: Option<String>,
+}
+
+impl From<SuppliersModel> for ProductSupplier {
+    fn from(val: SuppliersModel) -> ProductSupplier {
+        ProductSupplier {
+            supplier_id: val.supplier_id,
+            name: val.name,
+            region: val.region,
+        }
+    }
+}
+
+// every category of the tenant, read on nearly every catalog request so it goes through the hot cache
+pub async fn category_tree(ctx: &Context<'_>) -> Result<Vec<Categories>, AppError> {
+    use crate::entity::prelude::Categories as CategoriesEntity;
+    let db = ctx
+        .data::<DatabaseConnection>()
+        .map_err(|e| AppError::Internal(e.message))?;
+    let cache = ctx
+        .data::<HotCache>()
+        .map_err(|e| AppError::Internal(e.message))?;
+    let tenant = current_tenant(ctx);
+
+    cache
+        .get_or_load(&category_tree_key(tenant), || async {
+            Ok(CategoriesEntity::find()
+                .for_tenant(tenant)
+                .all(db)
+                .await?
+                .into_iter()
+                .map(|category| category.into())
+                .collect())
+        })
+        .await
+}
+
+pub fn category_tree_key(tenant: TenantId) -> String {
+    format!("category_tree:{}", tenant.0)
+}
+
+// the category and every category above it, root first, the walk stops should the tree loop
+pub fn category_breadcrumbs(tree: Vec<Categories>, category_id: i32) -> Vec<Categories> {
+    let mut by_id: HashMap<i32, Categories> = tree
+        .into_iter()
+        .map(|category| (category.category_id, category))
+        .collect();
+    let mut path = Vec::new();
+    let mut next = Some(category_id);
+    while let Some(category) = next.and_then(|id| by_id.remove(&id)) {
+        next = category.parent_category_id;
+        path.push(category);
+    }
+    path.reverse();
+    path
+}
+
+// the categories directly under parent, or the top level ones, by name
+pub fn category_children(
+    tree: Vec<Categories>,
+    parent_category_id: Option<i32>,
+) -> Vec<Categories> {
+    let mut children: Vec<Categories> = tree
+        .into_iter()
+        .filter(|category| {
+            category.parent_category_id == parent_category_id
+                && Some(category.category_id) != parent_category_id
+        })
+        .collect();
+    children.sort_by(|a, b| a.name.cmp(&b.name));
+    children
+}
+
+#[ComplexObject]
+impl Categories {
+    pub async fn id(&self) -> ID {
+        global_id("Categories", self.category_id)
+    }
+
+    // path from the root category down to this one, itself included
+    #[graphql(complexity = "complexity::unbounded(child_complexity)")]
+    async fn breadcrumbs(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<Vec<Categories>, async_graphql::Error> {
+        let tree = category_tree(ctx).await.map_err(|e| e.extend())?;
+
+        Ok(category_breadcrumbs(tree, self.category_id))
+    }
+
+    // assembled from the same cached list as categoryTree, so walking down costs no queries
+    #[graphql(complexity = "complexity::unbounded(child_complexity)")]
+    async fn children(&self, ctx: &Context<'_>) -> Result<Vec<Categories>, async_graphql::Error> {
+        let tree = category_tree(ctx).await.map_err(|e| e.extend())?;
+
+        Ok(category_children(tree, Some(self.category_id)))
+    }
+
+    // includes the products of every subcategory, read from the maintained counts
+    async fn product_count(&self, ctx: &Context<'_>) -> Result<i32, async_graphql::Error> {
+        use crate::entity::prelude::CategoryProductCounts as CategoryProductCountsEntity;
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        Ok(CategoryProductCountsEntity::find_by_id(self.category_id)
+            .one(db)
+            .await?
+            .map(|count| count.product_count)
+            .unwrap_or(0))
+    }
+}
+
+#[allow(dead_code)]
 #[derive(InputObject)]
 pub struct RegisterCategory {
     pub name: String,
@@ -167,7 +872,7 @@ pub struct Discounts {
     pub valid_until: Option<DateTimeWithTimeZone>,
     pub max_uses: Option<i32>,
     pub times_used: Option<i32>,
-    pub product_id: Option<i32>,
+    pub product_id: Option<ProductId>,
     pub category_id: Option<i32>,
     pub min_quantity: Option<i32>,
 }
@@ -184,7 +889,7 @@ impl From<DiscountsModel> for Discounts {
             valid_until: val.valid_until,
             max_uses: val.max_uses,
             times_used: val.times_used,
-            product_id: val.product_id,
+            product_id: val.product_id.map(ProductId),
             category_id: val.category_id,
             min_quantity: val.min_quantity,
         }
@@ -201,7 +906,7 @@ pub struct RegisterDiscount {
     pub valid_until: Option<DateTimeWithTimeZone>,
     pub max_uses: Option<i32>,
     pub times_used: Option<i32>,
-    pub product_id: i32,
+    pub product_id: ProductId,
     pub category_id: Option<i32>,
     pub min_quantity: Option<i32>,
 }
@@ -219,7 +924,7 @@ pub fn create_discount_model(
         valid_until: Set(input.valid_until),
         max_uses: Set(input.max_uses),
         times_used: Set(input.times_used),
-        product_id: Set(Some(input.product_id)),
+        product_id: Set(Some(input.product_id.into())),
         category_id: Set(input.category_id),
         min_quantity: Set(input.min_quantity),
         ..Default::default()
@@ -230,7 +935,7 @@ pub fn create_discount_model(
 pub struct Reviews {
     pub review_id: i32,
     pub customer_id: i32,
-    pub product_id: i32,
+    pub product_id: ProductId,
     pub rating: Option<i32>,
     pub review_text: Option<String>,
     pub review_date: Option<DateTimeWithTimeZone>,
@@ -242,7 +947,7 @@ impl From<ReviewsModel> for Reviews {
         Reviews {
             review_id: val.review_id,
             customer_id: val.customer_id,
-            product_id: val.product_id,
+            product_id: val.product_id.into(),
             rating: val.rating,
             review_text: val.review_text,
             review_date: val.review_date,
@@ -251,6 +956,89 @@ impl From<ReviewsModel> for Reviews {
     }
 }
 
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+pub enum ReviewIneligibleReason {
+    NotPurchased,
+    AlreadyReviewed,
+    WindowExpired,
+}
+
+impl ReviewIneligibleReason {
+    pub fn message(&self) -> &'static str {
+        match self {
+            ReviewIneligibleReason::NotPurchased => "Customer has not ordered the product",
+            ReviewIneligibleReason::AlreadyReviewed => "Customer has already reviewed the product",
+            ReviewIneligibleReason::WindowExpired => "Review window for the product has expired",
+        }
+    }
+
+    // as canReview reports it, registerReview rejects with it too
+    pub fn code(&self) -> &'static str {
+        match self {
+            ReviewIneligibleReason::NotPurchased => "NOT_PURCHASED",
+            ReviewIneligibleReason::AlreadyReviewed => "ALREADY_REVIEWED",
+            ReviewIneligibleReason::WindowExpired => "WINDOW_EXPIRED",
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct ReviewEligibility {
+    pub eligible: bool,
+    pub reason: Option<ReviewIneligibleReason>,
+}
+
+// the same rules back canReview and registerReview so the form never shows for a review that would be rejected
+pub async fn review_ineligible_reason<C: ConnectionTrait>(
+    db: &C,
+    customer_id: i32,
+    product_id: ProductId,
+    policy: &ReviewPolicy,
+) -> Result<Option<ReviewIneligibleReason>, DbErr> {
+    use crate::entity::{
+        order_items, orders,
+        prelude::{Orders as OrdersEntity, Reviews as ReviewsEntity},
+        reviews,
+    };
+
+    let last_order = OrdersEntity::find()
+        .inner_join(crate::entity::prelude::OrderItems)
+        .filter(orders::Column::CustomerId.eq(customer_id))
+        .filter(order_items::Column::ProductId.eq(product_id))
+        // only an order that was paid for, or bought on account, is a purchase
+        .filter(orders::Column::Status.is_in([
+            OrderStatus::Paid.as_str(),
+            OrderStatus::Processing.as_str(),
+            OrderStatus::Shipped.as_str(),
+            OrderStatus::OutForDelivery.as_str(),
+            OrderStatus::Delivered.as_str(),
+        ]))
+        .order_by_desc(orders::Column::OrderDate)
+        .one(db)
+        .await?;
+    let Some(last_order) = last_order else {
+        return Ok(Some(ReviewIneligibleReason::NotPurchased));
+    };
+
+    if ReviewsEntity::find()
+        .filter(reviews::Column::CustomerId.eq(customer_id))
+        .filter(reviews::Column::ProductId.eq(product_id))
+        .one(db)
+        .await?
+        .is_some()
+    {
+        return Ok(Some(ReviewIneligibleReason::AlreadyReviewed));
+    }
+
+    if let Some(order_date) = last_order.order_date {
+        if order_date + Duration::days(policy.window_days) < Utc::now() {
+            return Ok(Some(ReviewIneligibleReason::WindowExpired));
+        }
+    }
+
+    Ok(None)
+}
+
 #[derive(SimpleObject)]
 pub struct ReviewsPaginate {
     pub reviews: Vec<Reviews>,
@@ -259,7 +1047,7 @@ pub struct ReviewsPaginate {
 
 #[derive(InputObject)]
 pub struct RegisterReview {
-    pub product_id: i32,
+    pub product_id: ProductId,
     pub rating: Option<i32>,
     pub review_text: Option<String>,
     pub review_date: Option<DateTimeWithTimeZone>,
@@ -273,7 +1061,7 @@ pub fn create_review_model(
     use crate::entity::reviews;
     Ok(reviews::ActiveModel {
         customer_id: Set(customer_id),
-        product_id: Set(input.product_id),
+        product_id: Set(input.product_id.into()),
         rating: Set(input.rating),
         review_text: Set(input.review_text),
         review_date: Set(input.review_date),
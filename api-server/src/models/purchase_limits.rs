@@ -0,0 +1,79 @@
+use crate::{
+    entity::products::Model as ProductsModel, error::AppError, models::orders::OrderOwner,
+};
+use sea_orm::{ConnectionTrait, DbBackend, FromQueryResult, Statement};
+
+#[derive(FromQueryResult)]
+struct PurchasedQuantity {
+    quantity: i64,
+}
+
+// units of the product the buyer ordered within the window, cancelled orders don't count
+pub async fn purchased_quantity<C: ConnectionTrait>(
+    db: &C,
+    owner: &OrderOwner,
+    product_id: i32,
+    window_days: Option<i32>,
+) -> Result<i32, AppError> {
+    let (buyer_condition, buyer) = match owner {
+        OrderOwner::Customer(customer_id) => ("o.customer_id = $1", (*customer_id).into()),
+        OrderOwner::Guest(email) => ("lower(o.guest_email) = lower($1)", email.clone().into()),
+    };
+    let purchased = PurchasedQuantity::find_by_statement(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        format!(
+            r#"SELECT COALESCE(SUM(oi.quantity), 0)::bigint AS quantity
+                FROM order_items oi
+                JOIN orders o ON o.order_id = oi.order_id
+                WHERE {}
+                    AND oi.product_id = $2
+                    AND o.status <> 'CANCELLED'
+                    AND ($3::integer IS NULL OR o.order_date >= CURRENT_TIMESTAMP - INTERVAL '1 day' * $3)"#,
+            buyer_condition
+        ),
+        vec![buyer, product_id.into(), window_days.into()],
+    ))
+    .one(db)
+    .await?
+    .map(|purchased| purchased.quantity)
+    .unwrap_or(0);
+
+    Ok(purchased as i32)
+}
+
+// quantity is everything the buyer wants of the product right now, the whole cart line or order line,
+// buyers not known yet (guest carts before checkout) are only held to the limit for this purchase
+pub async fn check_purchase_quantity<C: ConnectionTrait>(
+    db: &C,
+    owner: Option<&OrderOwner>,
+    product: &ProductsModel,
+    quantity: i32,
+) -> Result<(), AppError> {
+    // date bookable products are limited by their availability calendar, checked when the order is placed
+    if !product.date_bookable && quantity > product.stock_quantity {
+        return Err(AppError::InsufficientStock {
+            product_id: product.product_id,
+            available: product.stock_quantity.max(0),
+        });
+    }
+
+    let Some(limit) = product.max_per_customer else {
+        return Ok(());
+    };
+    let already_purchased = match owner {
+        Some(owner) => {
+            purchased_quantity(db, owner, product.product_id, product.limit_window_days).await?
+        }
+        None => 0,
+    };
+    if already_purchased + quantity > limit {
+        return Err(AppError::PurchaseLimitExceeded {
+            product_id: product.product_id,
+            limit,
+            already_purchased,
+            window_days: product.limit_window_days,
+        });
+    }
+
+    Ok(())
+}
@@ -0,0 +1,47 @@
+use async_graphql::{Enum, SimpleObject};
+use sea_orm::prelude::DateTimeWithTimeZone;
+
+// who a request counts against, each tier has its own budget per caller
+#[derive(Enum, Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum RateLimitTier {
+    // keyed by client IP
+    Anonymous,
+    Customer,
+    Supplier,
+    // requests sent with an API key, keyed by the key
+    PartnerKey,
+}
+
+impl RateLimitTier {
+    pub const ALL: [RateLimitTier; 4] = [
+        RateLimitTier::Anonymous,
+        RateLimitTier::Customer,
+        RateLimitTier::Supplier,
+        RateLimitTier::PartnerKey,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RateLimitTier::Anonymous => "ANONYMOUS",
+            RateLimitTier::Customer => "CUSTOMER",
+            RateLimitTier::Supplier => "SUPPLIER",
+            RateLimitTier::PartnerKey => "PARTNER_KEY",
+        }
+    }
+
+    pub fn parse(tier: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|known| known.as_str() == tier)
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct RateLimitTierSetting {
+    pub tier: RateLimitTier,
+    // what the limiter enforces for this marketplace right now
+    pub requests_per_minute: i32,
+    pub default_requests_per_minute: i32,
+    // false while the tier runs on the default from the environment
+    pub overridden: bool,
+    pub updated_at: Option<DateTimeWithTimeZone>,
+    pub updated_by: Option<i32>,
+}
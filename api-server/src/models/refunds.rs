@@ -0,0 +1,458 @@
+use crate::{
+    entity::{
+        order_items, prelude::RefundItems as RefundItemsEntity, prelude::Refunds as RefundsEntity,
+        refund_items, refunds, refunds::Model as RefundsModel,
+    },
+    error::AppError,
+    ids::OrderId,
+    models::supplier_agreements::record_refund_clawback,
+    money::round_amount,
+    payment_gateway::{PaymentGateway, ProviderRefund, RefundStatus},
+};
+use async_graphql::{ComplexObject, Context, InputObject, SimpleObject};
+use chrono::{Duration, Utc};
+use sea_orm::{
+    prelude::{DateTimeWithTimeZone, Decimal},
+    ActiveModelTrait,
+    ActiveValue::Set,
+    ColumnTrait, ConnectionTrait, DatabaseConnection, EntityTrait, JoinType, QueryFilter,
+    QueryOrder, QuerySelect, RelationTrait, TransactionTrait,
+};
+use std::collections::HashMap;
+
+// a refund just stored is still being asked for, it is only retried once this has passed
+const UNSENT_REFUND_GRACE_MINUTES: i64 = 5;
+// the provider forgets idempotency keys after a day, a retry after that could pay out twice
+const UNSENT_REFUND_RETRY_HOURS: i64 = 23;
+
+#[derive(SimpleObject)]
+#[graphql(complex)]
+pub struct Refunds {
+    pub refund_id: i32,
+    pub order_id: OrderId,
+    // the lines' amounts plus extraAmount
+    pub amount: String,
+    // not tied to a line, e.g. shipping
+    pub extra_amount: String,
+    pub reason: Option<String>,
+    // PENDING until the provider confirms, SUCCEEDED or FAILED
+    pub status: String,
+    pub created_at: DateTimeWithTimeZone,
+    pub refunded_at: Option<DateTimeWithTimeZone>,
+}
+
+impl From<RefundsModel> for Refunds {
+    fn from(val: RefundsModel) -> Refunds {
+        Refunds {
+            refund_id: val.refund_id,
+            order_id: val.order_id.into(),
+            amount: val.amount.to_string(),
+            extra_amount: val.extra_amount.to_string(),
+            reason: val.reason,
+            status: val.status,
+            created_at: val.created_at,
+            refunded_at: val.refunded_at,
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct RefundItems {
+    pub order_item_id: i32,
+    pub quantity: i32,
+    pub amount: String,
+}
+
+#[ComplexObject]
+impl Refunds {
+    async fn items(&self, ctx: &Context<'_>) -> Result<Vec<RefundItems>, async_graphql::Error> {
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        Ok(RefundItemsEntity::find()
+            .filter(refund_items::Column::RefundId.eq(self.refund_id))
+            .order_by_asc(refund_items::Column::OrderItemId)
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|item| RefundItems {
+                order_item_id: item.order_item_id,
+                quantity: item.quantity,
+                amount: item.amount.to_string(),
+            })
+            .collect())
+    }
+}
+
+#[derive(InputObject)]
+pub struct RefundLineInput {
+    pub order_item_id: i32,
+    pub quantity: i32,
+}
+
+#[derive(InputObject)]
+pub struct RefundOrderInput {
+    // units of the order's lines to pay back, at their price after line discounts
+    #[graphql(default)]
+    pub lines: Vec<RefundLineInput>,
+    // paid back on top of the lines, e.g. shipping, admins only
+    pub extra_amount: Option<String>,
+    pub reason: Option<String>,
+}
+
+// a checked refund ready to be stored, amounts rounded to the currency
+pub struct RefundPlan {
+    pub lines: Vec<(i32, i32, Decimal)>,
+    pub extra_amount: Decimal,
+    pub amount: Decimal,
+}
+
+// units and amount of each of the order's lines already paid back or on their way, failed
+// refunds don't count
+async fn refunded_lines<C: ConnectionTrait>(
+    db: &C,
+    order_id: i32,
+) -> Result<HashMap<i32, (i32, Decimal)>, AppError> {
+    let mut lines = HashMap::new();
+    for item in RefundItemsEntity::find()
+        .join(JoinType::InnerJoin, refund_items::Relation::Refunds.def())
+        .filter(refunds::Column::OrderId.eq(order_id))
+        .filter(refunds::Column::Status.ne(RefundStatus::Failed.as_str()))
+        .all(db)
+        .await?
+    {
+        let (quantity, amount) = lines
+            .entry(item.order_item_id)
+            .or_insert((0, Decimal::ZERO));
+        *quantity += item.quantity;
+        *amount += item.amount;
+    }
+    Ok(lines)
+}
+
+// what the customer paid for the line, its share of the order's discounts off and of the VAT on
+pub fn line_paid(item: &order_items::Model) -> Decimal {
+    item.unit_price * Decimal::from(item.quantity) - item.discount_amount + item.tax_amount
+}
+
+// the units' part of what was paid for the line, the last units get what the earlier refunds
+// left so a fully refunded line adds up to what was paid for it
+fn refund_line_amount(
+    item: &order_items::Model,
+    quantity: i32,
+    refunded: (i32, Decimal),
+    currency: &str,
+) -> Decimal {
+    let paid = line_paid(item);
+    if refunded.0 + quantity >= item.quantity {
+        return paid - refunded.1;
+    }
+    round_amount(
+        paid * Decimal::from(quantity) / Decimal::from(item.quantity),
+        currency,
+    )
+}
+
+// what has been paid back on the order so far or is on its way
+pub async fn refunded_amount<C: ConnectionTrait>(
+    db: &C,
+    order_id: i32,
+) -> Result<Decimal, AppError> {
+    Ok(RefundsEntity::find()
+        .filter(refunds::Column::OrderId.eq(order_id))
+        .filter(refunds::Column::Status.ne(RefundStatus::Failed.as_str()))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|refund| refund.amount)
+        .sum())
+}
+
+// prices the requested lines and checks nothing is paid back twice, neither units of a line nor
+// more than the customer paid for the order
+pub async fn plan_refund<C: ConnectionTrait>(
+    db: &C,
+    order_id: i32,
+    paid: Decimal,
+    items: &[order_items::Model],
+    input: &RefundOrderInput,
+    currency: &str,
+) -> Result<RefundPlan, AppError> {
+    let refunded = refunded_lines(db, order_id).await?;
+    let mut failed_rules = Vec::new();
+    let mut lines = Vec::with_capacity(input.lines.len());
+    let mut requested: HashMap<i32, i32> = HashMap::new();
+
+    for line in &input.lines {
+        let Some(item) = items
+            .iter()
+            .find(|item| item.order_item_id == line.order_item_id)
+        else {
+            failed_rules.push(format!(
+                "Order item {} is not part of the order",
+                line.order_item_id
+            ));
+            continue;
+        };
+        if line.quantity <= 0 {
+            failed_rules.push(format!(
+                "Order item {}: quantity must be positive",
+                line.order_item_id
+            ));
+            continue;
+        }
+        let requested = requested.entry(item.order_item_id).or_insert(0);
+        *requested += line.quantity;
+        let already = refunded
+            .get(&item.order_item_id)
+            .copied()
+            .unwrap_or((0, Decimal::ZERO));
+        let left = item.quantity - already.0;
+        if *requested > left {
+            failed_rules.push(format!(
+                "Order item {}: only {} units are left to refund",
+                item.order_item_id,
+                left.max(0)
+            ));
+            continue;
+        }
+
+        // units of the line asked for earlier in the same request count as refunded already
+        let earlier: Vec<_> = lines
+            .iter()
+            .filter(|(order_item_id, _, _)| *order_item_id == item.order_item_id)
+            .collect();
+        let already = (
+            already.0
+                + earlier
+                    .iter()
+                    .map(|(_, quantity, _)| *quantity)
+                    .sum::<i32>(),
+            already.1
+                + earlier
+                    .iter()
+                    .map(|(_, _, amount)| *amount)
+                    .sum::<Decimal>(),
+        );
+        let amount = refund_line_amount(item, line.quantity, already, currency);
+        lines.push((item.order_item_id, line.quantity, amount));
+    }
+
+    let extra_amount = match input.extra_amount.as_deref().map(str::trim) {
+        Some(extra) => match Decimal::from_str_exact(extra) {
+            Ok(extra) if extra > Decimal::ZERO => round_amount(extra, currency),
+            _ => {
+                failed_rules.push("extraAmount must be a positive amount".to_string());
+                Decimal::ZERO
+            }
+        },
+        None => Decimal::ZERO,
+    };
+
+    if !failed_rules.is_empty() {
+        return Err(AppError::Validation {
+            message: "Refund is invalid".to_string(),
+            failed_rules,
+        });
+    }
+
+    let amount = lines.iter().map(|(_, _, amount)| *amount).sum::<Decimal>() + extra_amount;
+    if amount <= Decimal::ZERO {
+        return Err(AppError::invalid("Nothing to refund"));
+    }
+    let left = paid - refunded_amount(db, order_id).await?;
+    if amount > left {
+        return Err(AppError::Validation {
+            message: format!(
+                "Only {} of the order is left to refund",
+                left.max(Decimal::ZERO)
+            ),
+            failed_rules: vec!["refundExceedsPayment".to_string()],
+        });
+    }
+
+    Ok(RefundPlan {
+        lines,
+        extra_amount,
+        amount,
+    })
+}
+
+// the idempotency key a refund is requested under, every attempt for the refund uses the same one
+pub fn refund_key(refund_id: i32) -> String {
+    format!("refund-{}", refund_id)
+}
+
+fn refund_id_from_key(key: &str) -> Option<i32> {
+    key.strip_prefix("refund-")?.parse().ok()
+}
+
+// asks the provider for a stored refund. Only a decline fails it, when the provider can't be
+// reached or errors the refund stays PENDING and Err is returned, retry_unsent_refunds asks again
+pub async fn send_refund(
+    db: &DatabaseConnection,
+    gateway: &dyn PaymentGateway,
+    refund: RefundsModel,
+    provider_payment_id: &str,
+) -> Result<RefundsModel, AppError> {
+    use crate::entity::prelude::Orders as OrdersEntity;
+    // paid back in the currency the order was charged in
+    let order = OrdersEntity::find_by_id(refund.order_id)
+        .one(db)
+        .await?
+        .ok_or(AppError::NotFound("Order"))?;
+    let provider_refund = gateway
+        .refund(
+            provider_payment_id,
+            refund.amount,
+            &order.currency,
+            &refund_key(refund.refund_id),
+        )
+        .await?;
+
+    let txn = db.begin().await?;
+    let mut refund: refunds::ActiveModel = refund.into();
+    refund.provider_refund_id = Set(provider_refund.provider_refund_id);
+    refund.status = Set(provider_refund.status.as_str().to_string());
+    if provider_refund.status == RefundStatus::Succeeded {
+        refund.refunded_at = Set(Some(Utc::now().fixed_offset()));
+    }
+    let refund = refund.update(&txn).await?;
+    if provider_refund.status == RefundStatus::Succeeded {
+        record_refund_clawback(&txn, refund.refund_id).await?;
+    }
+    txn.commit().await?;
+    Ok(refund)
+}
+
+// pending refunds the provider never answered for, asked for again under their own key. Keys
+// are only honoured for a day, older ones are left for an admin to check with the provider
+pub async fn retry_unsent_refunds(
+    db: &DatabaseConnection,
+    gateway: &dyn PaymentGateway,
+) -> Result<(), AppError> {
+    use crate::entity::{bills, prelude::Bills as BillsEntity};
+    let now = Utc::now();
+    let unsent = RefundsEntity::find()
+        .filter(refunds::Column::Status.eq(RefundStatus::Pending.as_str()))
+        .filter(refunds::Column::ProviderRefundId.is_null())
+        .filter(refunds::Column::CreatedAt.lt(now - Duration::minutes(UNSENT_REFUND_GRACE_MINUTES)))
+        .filter(refunds::Column::CreatedAt.gt(now - Duration::hours(UNSENT_REFUND_RETRY_HOURS)))
+        .order_by_asc(refunds::Column::CreatedAt)
+        .all(db)
+        .await?;
+
+    for refund in unsent {
+        let provider_payment_id = BillsEntity::find()
+            .filter(bills::Column::OrderId.eq(refund.order_id))
+            .one(db)
+            .await?
+            .and_then(|bill| bill.provider_payment_id);
+        let Some(provider_payment_id) = provider_payment_id else {
+            continue;
+        };
+        let refund_id = refund.refund_id;
+        if let Err(e) = send_refund(db, gateway, refund, &provider_payment_id).await {
+            eprintln!("Refund {} is still unsent: {}", refund_id, e);
+        }
+    }
+
+    Ok(())
+}
+
+// the provider's word on a refund, a refund that already succeeded or failed isn't moved back.
+// A refund whose answer got lost has no provider id yet and is found by its idempotency key.
+// Returns the order of the refund
+pub async fn settle_refund<C: ConnectionTrait + TransactionTrait>(
+    db: &C,
+    provider_refund: &ProviderRefund,
+) -> Result<Option<i32>, AppError> {
+    let by_provider_id = match &provider_refund.provider_refund_id {
+        Some(provider_refund_id) => {
+            RefundsEntity::find()
+                .filter(refunds::Column::ProviderRefundId.eq(provider_refund_id.as_str()))
+                .one(db)
+                .await?
+        }
+        None => None,
+    };
+    let refund = match (by_provider_id, provider_refund.idempotency_key.as_deref()) {
+        (Some(refund), _) => Some(refund),
+        (None, Some(key)) => match refund_id_from_key(key) {
+            Some(refund_id) => RefundsEntity::find_by_id(refund_id).one(db).await?,
+            None => None,
+        },
+        (None, None) => None,
+    };
+    // refunds made in the provider's dashboard have no row
+    let Some(refund) = refund else {
+        return Ok(None);
+    };
+    let order_id = refund.order_id;
+    if refund.status != RefundStatus::Pending.as_str() {
+        return Ok(Some(order_id));
+    }
+
+    let txn = db.begin().await?;
+    let mut refund: refunds::ActiveModel = refund.into();
+    if let Some(provider_refund_id) = &provider_refund.provider_refund_id {
+        refund.provider_refund_id = Set(Some(provider_refund_id.clone()));
+    }
+    if provider_refund.status != RefundStatus::Pending {
+        refund.status = Set(provider_refund.status.as_str().to_string());
+    }
+    if provider_refund.status == RefundStatus::Succeeded {
+        refund.refunded_at = Set(Some(Utc::now().fixed_offset()));
+    }
+    let refund = refund.update(&txn).await?;
+    if provider_refund.status == RefundStatus::Succeeded {
+        record_refund_clawback(&txn, refund.refund_id).await?;
+    }
+    txn.commit().await?;
+
+    Ok(Some(order_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dec(amount: &str) -> Decimal {
+        Decimal::from_str_exact(amount).unwrap()
+    }
+
+    fn line(quantity: i32, unit_price: &str, discount: &str, tax: &str) -> order_items::Model {
+        order_items::Model {
+            order_item_id: 1,
+            order_id: 1,
+            product_id: 1,
+            quantity,
+            unit_price: dec(unit_price),
+            discount_amount: dec(discount),
+            tax_amount: dec(tax),
+            booking_date: None,
+            hs_code: None,
+            customs_value: None,
+            origin_country: None,
+        }
+    }
+
+    #[test]
+    fn pays_back_the_line_with_its_discount_off_and_vat_on() {
+        // 3 x 10.00, 3.00 of the order's coupon and 5.40 VAT on the rest
+        let item = line(3, "10.00", "3.00", "5.40");
+        assert_eq!(
+            refund_line_amount(&item, 3, (0, Decimal::ZERO), "EUR"),
+            dec("32.40")
+        );
+    }
+
+    #[test]
+    fn last_units_get_what_earlier_refunds_left() {
+        let item = line(3, "10.00", "0.00", "2.00");
+        let first = refund_line_amount(&item, 1, (0, Decimal::ZERO), "EUR");
+        let second = refund_line_amount(&item, 1, (1, first), "EUR");
+        let last = refund_line_amount(&item, 1, (2, first + second), "EUR");
+        assert_eq!(first, dec("10.67"));
+        assert_eq!(first + second + last, dec("32.00"));
+    }
+}
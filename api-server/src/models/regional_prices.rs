@@ -0,0 +1,126 @@
+use crate::{
+    config::RegionConfig,
+    entity::{prelude::ProductRegionPrices, product_region_prices},
+    error::AppError,
+};
+use async_graphql::{InputObject, SimpleObject};
+use lazy_regex::regex;
+use sea_orm::{prelude::Decimal, ConnectionTrait, DbErr, EntityTrait};
+use std::collections::HashSet;
+
+#[derive(SimpleObject)]
+pub struct RegionalPrice {
+    pub region: String,
+    pub currency: String,
+    pub price: String,
+}
+
+impl From<product_region_prices::Model> for RegionalPrice {
+    fn from(val: product_region_prices::Model) -> RegionalPrice {
+        RegionalPrice {
+            region: val.region,
+            currency: val.currency,
+            price: val.price.to_string(),
+        }
+    }
+}
+
+#[derive(InputObject)]
+pub struct RegionalPriceInput {
+    pub region: String,
+    // ISO 4217 code, e.g. EUR
+    pub currency: String,
+    pub price: String,
+}
+
+// a product either has no overrides or one per required region, so no required region falls back by accident
+pub fn validate_regional_prices(
+    region: &RegionConfig,
+    prices: &[RegionalPriceInput],
+) -> Result<Vec<(String, String, Decimal)>, AppError> {
+    let mut failed_rules = Vec::new();
+    let mut seen = HashSet::new();
+    let mut valid = Vec::with_capacity(prices.len());
+
+    for price in prices {
+        let region_code = price.region.trim().to_string();
+        if region_code.is_empty() || region_code.len() > 20 {
+            failed_rules.push(format!("{} is not a valid region", price.region));
+            continue;
+        }
+        if !seen.insert(region_code.clone()) {
+            failed_rules.push(format!("{} is priced more than once", region_code));
+            continue;
+        }
+        let currency = price.currency.trim().to_uppercase();
+        if !regex!(r"^[A-Z]{3}$").is_match(&currency) {
+            failed_rules.push(format!("{} is not a currency code", price.currency));
+            continue;
+        }
+        match Decimal::from_str_exact(price.price.trim()) {
+            Ok(amount) if amount > Decimal::ZERO && amount.scale() <= 2 => {
+                valid.push((region_code, currency, amount))
+            }
+            _ => failed_rules.push(format!(
+                "{} is not a valid price for {}",
+                price.price, region_code
+            )),
+        }
+    }
+
+    if !prices.is_empty() {
+        for required in &region.required_price_regions {
+            if !seen.contains(required) {
+                failed_rules.push(format!("missing a price for {}", required));
+            }
+        }
+    }
+
+    if !failed_rules.is_empty() {
+        return Err(AppError::Validation {
+            message: "Regional prices are invalid".to_string(),
+            failed_rules,
+        });
+    }
+    Ok(valid)
+}
+
+// the override for the region in its own currency when there is one, the base price in the store
+// currency everywhere else. Catalog prices and checkout both go through here so an order is
+// charged what was shown
+pub async fn price_in_region<C: ConnectionTrait>(
+    db: &C,
+    product_id: i32,
+    base_price: Decimal,
+    region: Option<&str>,
+    store_currency: &str,
+) -> Result<(Decimal, String), DbErr> {
+    let Some(region) = region else {
+        return Ok((base_price, store_currency.to_string()));
+    };
+    Ok(
+        ProductRegionPrices::find_by_id((product_id, region.to_string()))
+            .one(db)
+            .await?
+            .map(|price| (price.price, price.currency))
+            .unwrap_or_else(|| (base_price, store_currency.to_string())),
+    )
+}
+
+// adds a line's currency to the order's, every line of an order has to be paid in the same one
+pub fn check_same_currency(
+    order_currency: &mut Option<String>,
+    currency: String,
+) -> Result<(), AppError> {
+    match order_currency {
+        Some(order_currency) if *order_currency != currency => Err(AppError::invalid(format!(
+            "Products priced in {} and {} can't be ordered together",
+            order_currency, currency
+        ))),
+        Some(_) => Ok(()),
+        None => {
+            *order_currency = Some(currency);
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,38 @@
+use crate::entity::retention_runs::Model as RetentionRunsModel;
+use async_graphql::SimpleObject;
+use sea_orm::prelude::DateTimeWithTimeZone;
+
+#[derive(SimpleObject)]
+pub struct RetentionRuns {
+    pub retention_run_id: i32,
+    pub target: String,
+    pub dry_run: bool,
+    pub cutoff: DateTimeWithTimeZone,
+    // rows deleted, or the rows that would have been for dry runs
+    pub affected_rows: i64,
+    pub error: Option<String>,
+    pub started_at: DateTimeWithTimeZone,
+    pub finished_at: DateTimeWithTimeZone,
+}
+
+impl From<RetentionRunsModel> for RetentionRuns {
+    fn from(val: RetentionRunsModel) -> RetentionRuns {
+        RetentionRuns {
+            retention_run_id: val.retention_run_id,
+            target: val.target,
+            dry_run: val.dry_run,
+            cutoff: val.cutoff,
+            affected_rows: val.affected_rows,
+            error: val.error,
+            started_at: val.started_at,
+            finished_at: val.finished_at,
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct RetentionPolicies {
+    pub target: String,
+    pub retention_days: i64,
+    pub last_run: Option<RetentionRuns>,
+}
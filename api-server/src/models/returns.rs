@@ -0,0 +1,120 @@
+use crate::{
+    entity::{
+        prelude::ReturnPolicies as ReturnPoliciesEntity,
+        return_policies::Model as ReturnPoliciesModel,
+        return_requests::Model as ReturnRequestsModel,
+    },
+    error::AppError,
+    models::products::{category_breadcrumbs, category_tree},
+};
+use async_graphql::{Context, InputObject, SimpleObject};
+use chrono::Utc;
+use sea_orm::{
+    prelude::{DateTimeWithTimeZone, Decimal},
+    ConnectionTrait, DbErr, EntityTrait,
+};
+
+pub const DEFAULT_RETURN_WINDOW_DAYS: i32 = 30;
+
+#[derive(SimpleObject)]
+pub struct ReturnPolicies {
+    pub supplier_id: i32,
+    // counted from the order date
+    pub return_window_days: i32,
+    pub restocking_fee_percent: String,
+    pub non_returnable_category_ids: Vec<i32>,
+}
+
+impl From<ReturnPoliciesModel> for ReturnPolicies {
+    fn from(val: ReturnPoliciesModel) -> ReturnPolicies {
+        ReturnPolicies {
+            supplier_id: val.supplier_id,
+            return_window_days: val.return_window_days,
+            restocking_fee_percent: val.restocking_fee_percent.to_string(),
+            non_returnable_category_ids: val.non_returnable_category_ids,
+        }
+    }
+}
+
+#[derive(InputObject)]
+pub struct ReturnPolicyInput {
+    pub return_window_days: i32,
+    pub restocking_fee_percent: String,
+    pub non_returnable_category_ids: Vec<i32>,
+}
+
+// what a product page shows, the supplier's policy applied to the product's category
+#[derive(SimpleObject)]
+pub struct ProductReturnPolicy {
+    pub returnable: bool,
+    pub return_window_days: i32,
+    pub restocking_fee_percent: String,
+}
+
+#[derive(SimpleObject)]
+pub struct ReturnRequests {
+    pub return_request_id: i32,
+    pub order_item_id: i32,
+    pub quantity: i32,
+    pub reason: Option<String>,
+    pub status: String,
+    pub restocking_fee: String,
+    pub refund_amount: String,
+    pub requested_at: DateTimeWithTimeZone,
+    pub resolved_at: Option<DateTimeWithTimeZone>,
+}
+
+impl From<ReturnRequestsModel> for ReturnRequests {
+    fn from(val: ReturnRequestsModel) -> ReturnRequests {
+        ReturnRequests {
+            return_request_id: val.return_request_id,
+            order_item_id: val.order_item_id,
+            quantity: val.quantity,
+            reason: val.reason,
+            status: val.status,
+            restocking_fee: val.restocking_fee.to_string(),
+            refund_amount: val.refund_amount.to_string(),
+            requested_at: val.requested_at,
+            resolved_at: val.resolved_at,
+        }
+    }
+}
+
+// the stored policy, or the marketplace default for suppliers that never set one
+pub async fn effective_return_policy<C: ConnectionTrait>(
+    db: &C,
+    supplier_id: i32,
+) -> Result<ReturnPoliciesModel, DbErr> {
+    Ok(ReturnPoliciesEntity::find_by_id(supplier_id)
+        .one(db)
+        .await?
+        .unwrap_or_else(|| ReturnPoliciesModel {
+            supplier_id,
+            return_window_days: DEFAULT_RETURN_WINDOW_DAYS,
+            restocking_fee_percent: Decimal::ZERO,
+            non_returnable_category_ids: Vec::new(),
+            updated_at: Utc::now().fixed_offset(),
+        }))
+}
+
+// excluding a category excludes everything below it too, the tree comes from the hot cache
+pub async fn is_returnable(
+    ctx: &Context<'_>,
+    policy: &ReturnPoliciesModel,
+    category_id: Option<i32>,
+) -> Result<bool, AppError> {
+    let Some(category_id) = category_id else {
+        return Ok(true);
+    };
+    if policy.non_returnable_category_ids.is_empty() {
+        return Ok(true);
+    }
+
+    Ok(category_breadcrumbs(category_tree(ctx).await?, category_id)
+        .iter()
+        .all(|category| {
+            !policy
+                .non_returnable_category_ids
+                .contains(&category.category_id)
+        }))
+}
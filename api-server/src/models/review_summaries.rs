@@ -0,0 +1,168 @@
+use crate::entity::{
+    prelude::ReviewSummaries as ReviewSummariesEntity,
+    review_summaries::{self, Model as ReviewSummariesModel},
+    reviews::Model as ReviewsModel,
+};
+use crate::error::AppError;
+use async_graphql::{ErrorExtensions, SimpleObject};
+use sea_orm::{
+    sea_query::OnConflict, ActiveModelTrait, ActiveValue::Set, DatabaseTransaction, EntityTrait,
+    QuerySelect,
+};
+use serde_json::{Map, Value};
+
+const TOP_KEYWORDS: usize = 5;
+const STOP_WORDS: &[&str] = &[
+    "about", "after", "again", "also", "been", "before", "being", "but", "could", "does", "from",
+    "have", "just", "like", "more", "much", "only", "other", "really", "some", "than", "that",
+    "their", "them", "then", "there", "these", "they", "this", "very", "were", "what", "when",
+    "which", "while", "will", "with", "would", "your",
+];
+
+#[derive(SimpleObject)]
+pub struct RatingBucket {
+    pub stars: i32,
+    pub count: i32,
+}
+
+#[derive(SimpleObject)]
+pub struct KeywordCount {
+    pub keyword: String,
+    pub count: i64,
+}
+
+#[derive(SimpleObject)]
+pub struct ReviewSummary {
+    pub review_count: i32,
+    pub average_rating: Option<f64>,
+    pub rating_histogram: Vec<RatingBucket>,
+    pub top_keywords: Vec<KeywordCount>,
+}
+
+impl From<ReviewSummariesModel> for ReviewSummary {
+    fn from(val: ReviewSummariesModel) -> ReviewSummary {
+        let mut top_keywords: Vec<KeywordCount> = val
+            .keyword_counts
+            .as_object()
+            .map(|keywords| {
+                keywords
+                    .iter()
+                    .map(|(keyword, count)| KeywordCount {
+                        keyword: keyword.clone(),
+                        count: count.as_i64().unwrap_or(0),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        top_keywords.sort_by(|a, b| b.count.cmp(&a.count).then(a.keyword.cmp(&b.keyword)));
+        top_keywords.truncate(TOP_KEYWORDS);
+
+        ReviewSummary {
+            review_count: val.review_count,
+            average_rating: (val.rating_count > 0)
+                .then(|| val.rating_total as f64 / val.rating_count as f64),
+            rating_histogram: [
+                val.one_star,
+                val.two_star,
+                val.three_star,
+                val.four_star,
+                val.five_star,
+            ]
+            .into_iter()
+            .zip(1..)
+            .map(|(count, stars)| RatingBucket { stars, count })
+            .collect(),
+            top_keywords,
+        }
+    }
+}
+
+impl ReviewSummary {
+    pub fn empty() -> ReviewSummary {
+        ReviewSummary {
+            review_count: 0,
+            average_rating: None,
+            rating_histogram: (1..=5)
+                .map(|stars| RatingBucket { stars, count: 0 })
+                .collect(),
+            top_keywords: Vec::new(),
+        }
+    }
+}
+
+fn keywords(text: &str) -> Vec<String> {
+    let mut keywords: Vec<String> = text
+        .split(|c: char| !c.is_alphabetic())
+        .filter(|word| word.chars().count() >= 4)
+        .map(|word| word.to_lowercase())
+        .filter(|word| !STOP_WORDS.contains(&word.as_str()))
+        .collect();
+    // a word repeated inside one review only counts once
+    keywords.sort();
+    keywords.dedup();
+    keywords
+}
+
+// Adds (delta = 1) or removes (delta = -1) one review from its product's summary. The row is
+// locked for the rest of the transaction so concurrent review writes can't lose updates.
+pub async fn apply_review_to_summary(
+    txn: &DatabaseTransaction,
+    review: &ReviewsModel,
+    delta: i32,
+) -> Result<(), async_graphql::Error> {
+    ReviewSummariesEntity::insert(review_summaries::ActiveModel {
+        product_id: Set(review.product_id),
+        ..Default::default()
+    })
+    .on_conflict(
+        OnConflict::column(review_summaries::Column::ProductId)
+            .do_nothing()
+            .to_owned(),
+    )
+    .exec_without_returning(txn)
+    .await?;
+
+    let summary = ReviewSummariesEntity::find_by_id(review.product_id)
+        .lock_exclusive()
+        .one(txn)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Review summary").extend())?;
+
+    let mut keyword_counts: Map<String, Value> = summary
+        .keyword_counts
+        .as_object()
+        .cloned()
+        .unwrap_or_default();
+    for keyword in keywords(review.review_text.as_deref().unwrap_or_default()) {
+        let count = keyword_counts
+            .get(&keyword)
+            .and_then(Value::as_i64)
+            .unwrap_or(0)
+            + delta as i64;
+        if count > 0 {
+            keyword_counts.insert(keyword, count.into());
+        } else {
+            keyword_counts.remove(&keyword);
+        }
+    }
+
+    let mut updated: review_summaries::ActiveModel = summary.clone().into();
+    updated.review_count = Set(summary.review_count + delta);
+    if let Some(rating) = review.rating {
+        updated.rating_count = Set(summary.rating_count + delta);
+        updated.rating_total = Set(summary.rating_total + rating * delta);
+        match rating {
+            1 => updated.one_star = Set(summary.one_star + delta),
+            2 => updated.two_star = Set(summary.two_star + delta),
+            3 => updated.three_star = Set(summary.three_star + delta),
+            4 => updated.four_star = Set(summary.four_star + delta),
+            5 => updated.five_star = Set(summary.five_star + delta),
+            _ => return Err(AppError::invalid("Rating must be between 1 and 5").extend()),
+        }
+    }
+    updated.keyword_counts = Set(Value::Object(keyword_counts));
+    updated.updated_at = Set(Some(chrono::Utc::now().fixed_offset()));
+    updated.update(txn).await?;
+
+    Ok(())
+}
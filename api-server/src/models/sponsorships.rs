@@ -0,0 +1,109 @@
+use crate::{
+    entity::{
+        prelude::{Products as ProductsEntity, SponsoredCampaigns as SponsoredCampaignsEntity},
+        products,
+        sponsored_campaigns::Model as SponsoredCampaignsModel,
+    },
+    ids::{ProductId, TenantId},
+    models::products::{on_sale, Products},
+    tenancy::TenantScope,
+};
+use async_graphql::{InputObject, SimpleObject};
+use sea_orm::{
+    prelude::DateTimeWithTimeZone, ColumnTrait, DatabaseConnection, DbBackend, DbErr, EntityTrait,
+    QueryFilter, Statement,
+};
+
+#[derive(SimpleObject)]
+pub struct SponsoredCampaigns {
+    pub campaign_id: i32,
+    pub product_id: ProductId,
+    pub keyword: String,
+    pub bid_per_click: String,
+    pub budget: String,
+    pub spent: String,
+    pub remaining_budget: String,
+    pub active: bool,
+    pub created_at: Option<DateTimeWithTimeZone>,
+}
+
+impl From<SponsoredCampaignsModel> for SponsoredCampaigns {
+    fn from(val: SponsoredCampaignsModel) -> SponsoredCampaigns {
+        SponsoredCampaigns {
+            campaign_id: val.campaign_id,
+            product_id: val.product_id.into(),
+            keyword: val.keyword,
+            bid_per_click: val.bid_per_click.to_string(),
+            budget: val.budget.to_string(),
+            spent: val.spent.to_string(),
+            remaining_budget: (val.budget - val.spent).to_string(),
+            active: val.active,
+            created_at: val.created_at,
+        }
+    }
+}
+
+#[derive(InputObject)]
+pub struct RegisterSponsoredCampaign {
+    pub product_id: ProductId,
+    pub keyword: String,
+    pub bid_per_click: String,
+    pub budget: String,
+}
+
+// highest bidders in the marketplace whose keyword appears in the search and who can still pay for a click
+pub async fn sponsored_products(
+    db: &DatabaseConnection,
+    tenant: TenantId,
+    search: &str,
+    slots: u64,
+) -> Result<Vec<Products>, DbErr> {
+    let campaigns = SponsoredCampaignsEntity::find()
+        .from_raw_sql(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            r#"SELECT * FROM sponsored_campaigns
+                WHERE active
+                    AND spent + bid_per_click <= budget
+                    AND position(lower(keyword) in lower($1)) > 0
+                    AND product_id IN (SELECT product_id FROM products WHERE tenant_id = $2)
+                ORDER BY bid_per_click DESC, created_at"#,
+            vec![search.trim().into(), tenant.into()],
+        ))
+        .all(db)
+        .await?;
+
+    let mut placements: Vec<SponsoredCampaignsModel> = Vec::new();
+    for campaign in campaigns {
+        if placements.len() as u64 >= slots {
+            break;
+        }
+        if !placements
+            .iter()
+            .any(|placed| placed.product_id == campaign.product_id)
+        {
+            placements.push(campaign);
+        }
+    }
+
+    let product_models = on_sale(ProductsEntity::find())
+        .filter(
+            products::Column::ProductId
+                .is_in(placements.iter().map(|campaign| campaign.product_id)),
+        )
+        .for_tenant(tenant)
+        .all(db)
+        .await?;
+
+    Ok(placements
+        .into_iter()
+        .filter_map(|campaign| {
+            let product = product_models
+                .iter()
+                .find(|product| product.product_id == campaign.product_id)?;
+            let mut product: Products = product.clone().into();
+            product.is_sponsored = true;
+            product.sponsored_campaign_id = Some(campaign.campaign_id);
+            Some(product)
+        })
+        .collect())
+}
@@ -0,0 +1,428 @@
+use crate::{
+    entity::{
+        prelude::{
+            SupplierAgreements as SupplierAgreementsEntity,
+            SupplierPayoutAdjustments as SupplierPayoutAdjustmentsEntity,
+            SupplierPayouts as SupplierPayoutsEntity, Suppliers as SuppliersEntity,
+        },
+        supplier_agreements::{self, Model as SupplierAgreementsModel},
+        supplier_payout_adjustments,
+        supplier_payouts::{self, Model as SupplierPayoutsModel},
+        suppliers::Model as SuppliersModel,
+    },
+    error::AppError,
+    money::round_amount,
+    storage::Storage,
+};
+use async_graphql::{ComplexObject, Context, InputObject, SimpleObject};
+use chrono::{Days, Duration, NaiveDate};
+use sea_orm::{
+    prelude::{DateTimeWithTimeZone, Decimal},
+    sea_query::Expr,
+    ActiveValue::Set,
+    ColumnTrait, Condition, ConnectionTrait, DatabaseConnection, DbBackend, DbErr, EntityTrait,
+    FromQueryResult, PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, Statement,
+    TransactionTrait,
+};
+use std::{collections::BTreeMap, sync::Arc};
+
+// how long a link to a signed agreement keeps working
+const AGREEMENT_URL_MINUTES: i64 = 15;
+// the part of what was paid for a line that isn't VAT, refunds come off a supplier's gross in it
+const NET_SHARE: &str = "COALESCE((oi.unit_price * oi.quantity - oi.discount_amount) \
+    / NULLIF(oi.unit_price * oi.quantity - oi.discount_amount + oi.tax_amount, 0), 1)";
+
+#[derive(SimpleObject)]
+#[graphql(complex)]
+pub struct SupplierAgreements {
+    pub agreement_id: i32,
+    pub supplier_id: i32,
+    pub version: String,
+    // share of each sale the marketplace keeps
+    pub commission_percent: String,
+    #[graphql(skip)]
+    pub document_key: String,
+    pub effective_from: NaiveDate,
+    // null for agreements without an end date
+    pub effective_until: Option<NaiveDate>,
+    pub uploaded_by: Option<i32>,
+    pub uploaded_at: DateTimeWithTimeZone,
+}
+
+impl From<SupplierAgreementsModel> for SupplierAgreements {
+    fn from(val: SupplierAgreementsModel) -> SupplierAgreements {
+        SupplierAgreements {
+            agreement_id: val.agreement_id,
+            supplier_id: val.supplier_id,
+            version: val.version,
+            commission_percent: val.commission_percent.to_string(),
+            document_key: val.document_key,
+            effective_from: val.effective_from,
+            effective_until: val.effective_until,
+            uploaded_by: val.uploaded_by,
+            uploaded_at: val.uploaded_at,
+        }
+    }
+}
+
+#[ComplexObject]
+impl SupplierAgreements {
+    // the signed document, a link that expires after a few minutes
+    async fn document_url(&self, ctx: &Context<'_>) -> Result<String, async_graphql::Error> {
+        Ok(ctx
+            .data::<Arc<dyn Storage>>()?
+            .signed_url(&self.document_key, Duration::minutes(AGREEMENT_URL_MINUTES))?)
+    }
+}
+
+#[derive(InputObject)]
+pub struct RegisterSupplierAgreement {
+    pub supplier_id: i32,
+    // as printed on the document, unique per supplier
+    pub version: String,
+    pub commission_percent: String,
+    pub effective_from: NaiveDate,
+    pub effective_until: Option<NaiveDate>,
+}
+
+#[derive(SimpleObject)]
+pub struct PayoutEligibility {
+    pub supplier_id: i32,
+    pub releasable: bool,
+    // why the payouts are held, empty when they can be released
+    pub reasons: Vec<String>,
+    pub active_agreement: Option<SupplierAgreements>,
+}
+
+// the agreement in effect on the day, the latest one to take effect when several overlap
+pub async fn active_agreement<C: ConnectionTrait>(
+    db: &C,
+    supplier_id: i32,
+    on: NaiveDate,
+) -> Result<Option<SupplierAgreementsModel>, DbErr> {
+    SupplierAgreementsEntity::find()
+        .filter(supplier_agreements::Column::SupplierId.eq(supplier_id))
+        .filter(supplier_agreements::Column::EffectiveFrom.lte(on))
+        .filter(
+            Condition::any()
+                .add(supplier_agreements::Column::EffectiveUntil.is_null())
+                .add(supplier_agreements::Column::EffectiveUntil.gte(on)),
+        )
+        .order_by_desc(supplier_agreements::Column::EffectiveFrom)
+        .one(db)
+        .await
+}
+
+// payouts go out to the supplier's bank account under the agreement in effect today, without
+// either they are held
+pub async fn payout_eligibility<C: ConnectionTrait>(
+    db: &C,
+    supplier: &SuppliersModel,
+    today: NaiveDate,
+) -> Result<PayoutEligibility, DbErr> {
+    let agreement = active_agreement(db, supplier.supplier_id, today).await?;
+
+    let mut reasons = Vec::new();
+    if supplier.payout_iban.is_none() {
+        reasons.push("No payout details".to_string());
+    }
+    if agreement.is_none() {
+        reasons.push("No commission agreement in effect".to_string());
+    }
+
+    Ok(PayoutEligibility {
+        supplier_id: supplier.supplier_id,
+        releasable: reasons.is_empty(),
+        reasons,
+        active_agreement: agreement.map(|agreement| agreement.into()),
+    })
+}
+
+#[derive(SimpleObject)]
+pub struct SupplierPayouts {
+    pub payout_id: i32,
+    pub supplier_id: i32,
+    // the agreement the commission was taken at
+    pub agreement_id: i32,
+    pub period_start: NaiveDate,
+    pub period_end: NaiveDate,
+    pub currency: String,
+    // the supplier's lines of the paid orders placed in the period, less what was refunded
+    pub gross: String,
+    // refunds that succeeded after an earlier payout, taken off this one
+    pub adjustments: String,
+    pub commission: String,
+    // what the supplier is paid
+    pub amount: String,
+    pub released_by: Option<i32>,
+    pub released_at: DateTimeWithTimeZone,
+}
+
+impl From<SupplierPayoutsModel> for SupplierPayouts {
+    fn from(val: SupplierPayoutsModel) -> SupplierPayouts {
+        SupplierPayouts {
+            payout_id: val.payout_id,
+            supplier_id: val.supplier_id,
+            agreement_id: val.agreement_id,
+            period_start: val.period_start,
+            period_end: val.period_end,
+            currency: val.currency,
+            gross: val.gross.to_string(),
+            adjustments: val.adjustments.to_string(),
+            commission: val.commission.to_string(),
+            amount: val.amount.to_string(),
+            released_by: val.released_by,
+            released_at: val.released_at,
+        }
+    }
+}
+
+#[derive(FromQueryResult)]
+struct PayoutGross {
+    currency: String,
+    gross: Decimal,
+}
+
+// what a currency's payout is taken from, the period's gross less the pending claw-backs. Ones
+// the period can't cover stay pending for a later payout instead of taking it below zero
+fn payout_base(gross: Decimal, pending: Decimal) -> (Decimal, Decimal) {
+    if gross + pending > Decimal::ZERO {
+        (gross + pending, pending)
+    } else {
+        (gross, Decimal::ZERO)
+    }
+}
+
+// a refund that succeeds once its order was paid out can't come off that payout anymore, the
+// supplier's refunded lines are taken off their next payout instead. Must run in the transaction
+// that marks the refund SUCCEEDED, the supplier lock orders it against a release in progress
+pub async fn record_refund_clawback<C: ConnectionTrait>(
+    db: &C,
+    refund_id: i32,
+) -> Result<(), DbErr> {
+    db.execute(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        r#"SELECT s.supplier_id
+            FROM suppliers s
+            WHERE s.supplier_id IN (SELECT p.supplier_id
+                FROM refund_items ri
+                JOIN order_items oi ON oi.order_item_id = ri.order_item_id
+                JOIN products p ON p.product_id = oi.product_id
+                WHERE ri.refund_id = $1)
+            ORDER BY s.supplier_id
+            FOR UPDATE"#,
+        vec![refund_id.into()],
+    ))
+    .await?;
+    // orders held back by a dispute freeze were never paid out, nothing to claw back
+    db.execute(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        format!(
+            r#"INSERT INTO supplier_payout_adjustments (supplier_id, refund_id, currency, amount)
+            SELECT p.supplier_id, r.refund_id, o.currency, -round(sum(ri.amount * {NET_SHARE}), 2)
+            FROM refunds r
+            JOIN orders o ON o.order_id = r.order_id
+            JOIN refund_items ri ON ri.refund_id = r.refund_id
+            JOIN order_items oi ON oi.order_item_id = ri.order_item_id
+            JOIN products p ON p.product_id = oi.product_id
+            WHERE r.refund_id = $1
+                AND r.status = 'SUCCEEDED'
+                AND NOT o.payout_frozen
+                AND EXISTS (SELECT 1
+                    FROM supplier_payouts sp
+                    WHERE sp.supplier_id = p.supplier_id
+                        AND sp.currency = o.currency
+                        AND (o.order_date AT TIME ZONE 'UTC')::date
+                            BETWEEN sp.period_start AND sp.period_end)
+            GROUP BY p.supplier_id, r.refund_id, o.currency
+            HAVING round(sum(ri.amount * {NET_SHARE}), 2) > 0
+            ON CONFLICT (refund_id, supplier_id) DO NOTHING"#
+        ),
+        vec![refund_id.into()],
+    ))
+    .await?;
+    Ok(())
+}
+
+// releases what the supplier earned on the orders placed in the period, one payout per currency.
+// Held while payoutEligibility says so, and a period overlapping one already paid out is refused.
+// Orders whose payouts are frozen by a dispute are left out. Claw-backs of refunds that succeeded
+// after an earlier payout are taken off in their currency
+pub async fn release_payout(
+    db: &DatabaseConnection,
+    supplier_id: i32,
+    period_start: NaiveDate,
+    period_end: NaiveDate,
+    today: NaiveDate,
+    released_by: i32,
+) -> Result<Vec<SupplierPayoutsModel>, AppError> {
+    if period_end < period_start {
+        return Err(AppError::invalid("The period can't end before it starts"));
+    }
+    if period_end >= today {
+        return Err(AppError::invalid(
+            "Only periods that have ended can be paid out",
+        ));
+    }
+    let txn = db.begin().await?;
+
+    // locked so two releases for the supplier can't both pass the overlap check
+    let supplier = SuppliersEntity::find_by_id(supplier_id)
+        .lock_exclusive()
+        .one(&txn)
+        .await?
+        .ok_or(AppError::NotFound("Supplier"))?;
+    let eligibility = payout_eligibility(&txn, &supplier, today).await?;
+    let agreement = match (
+        eligibility.releasable,
+        active_agreement(&txn, supplier_id, today).await?,
+    ) {
+        (true, Some(agreement)) => agreement,
+        _ => {
+            return Err(AppError::Validation {
+                message: "Payouts for this supplier are held".to_string(),
+                failed_rules: eligibility.reasons,
+            })
+        }
+    };
+
+    let overlapping = SupplierPayoutsEntity::find()
+        .filter(supplier_payouts::Column::SupplierId.eq(supplier_id))
+        .filter(supplier_payouts::Column::PeriodStart.lte(period_end))
+        .filter(supplier_payouts::Column::PeriodEnd.gte(period_start))
+        .count(&txn)
+        .await?;
+    if overlapping > 0 {
+        return Err(AppError::invalid(
+            "Part of this period has already been paid out",
+        ));
+    }
+
+    let from = period_start.and_hms_opt(0, 0, 0).unwrap().and_utc();
+    let until = (period_end + Days::new(1))
+        .and_hms_opt(0, 0, 0)
+        .unwrap()
+        .and_utc();
+    let grosses = PayoutGross::find_by_statement(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        format!(
+            r#"SELECT o.currency,
+                sum(oi.unit_price * oi.quantity - oi.discount_amount
+                    - COALESCE((SELECT sum(ri.amount)
+                        FROM refund_items ri
+                        JOIN refunds r ON r.refund_id = ri.refund_id
+                        WHERE ri.order_item_id = oi.order_item_id
+                            AND r.status = 'SUCCEEDED'), 0) * {NET_SHARE}) AS gross
+            FROM orders o
+            JOIN bills b ON b.order_id = o.order_id
+            JOIN order_items oi ON oi.order_id = o.order_id
+            JOIN products p ON p.product_id = oi.product_id
+            WHERE p.supplier_id = $1
+                AND o.order_date >= $2 AND o.order_date < $3
+                AND b.payment_status = 'PAID'
+                AND NOT o.payout_frozen
+            GROUP BY o.currency
+            ORDER BY o.currency"#
+        ),
+        vec![supplier_id.into(), from.into(), until.into()],
+    ))
+    .all(&txn)
+    .await?;
+
+    let mut pending = BTreeMap::<String, Vec<i32>>::new();
+    let mut pending_sums = BTreeMap::<String, Decimal>::new();
+    for adjustment in SupplierPayoutAdjustmentsEntity::find()
+        .filter(supplier_payout_adjustments::Column::SupplierId.eq(supplier_id))
+        .filter(supplier_payout_adjustments::Column::PayoutId.is_null())
+        .all(&txn)
+        .await?
+    {
+        *pending_sums.entry(adjustment.currency.clone()).or_default() += adjustment.amount;
+        pending
+            .entry(adjustment.currency)
+            .or_default()
+            .push(adjustment.adjustment_id);
+    }
+
+    let mut payouts = Vec::new();
+    for PayoutGross { currency, gross } in grosses {
+        if gross <= Decimal::ZERO {
+            continue;
+        }
+        let gross = round_amount(gross, &currency);
+        let (base, adjustments) = payout_base(
+            gross,
+            round_amount(
+                pending_sums.get(&currency).copied().unwrap_or_default(),
+                &currency,
+            ),
+        );
+        let commission = round_amount(
+            base * agreement.commission_percent / Decimal::ONE_HUNDRED,
+            &currency,
+        );
+        let payout = SupplierPayoutsEntity::insert(supplier_payouts::ActiveModel {
+            supplier_id: Set(supplier_id),
+            agreement_id: Set(agreement.agreement_id),
+            period_start: Set(period_start),
+            period_end: Set(period_end),
+            currency: Set(currency.clone()),
+            gross: Set(gross),
+            adjustments: Set(adjustments),
+            commission: Set(commission),
+            amount: Set(base - commission),
+            released_by: Set(Some(released_by)),
+            ..Default::default()
+        })
+        .exec_with_returning(&txn)
+        .await?;
+        if adjustments < Decimal::ZERO {
+            SupplierPayoutAdjustmentsEntity::update_many()
+                .col_expr(
+                    supplier_payout_adjustments::Column::PayoutId,
+                    Expr::value(payout.payout_id),
+                )
+                .filter(
+                    supplier_payout_adjustments::Column::AdjustmentId
+                        .is_in(pending.remove(&currency).unwrap_or_default()),
+                )
+                .exec(&txn)
+                .await?;
+        }
+        payouts.push(payout);
+    }
+    if payouts.is_empty() {
+        return Err(AppError::invalid("Nothing to pay out for this period"));
+    }
+
+    txn.commit().await?;
+    Ok(payouts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refund_after_release_comes_off_the_next_payout() {
+        // 100 paid out, then a 30 refund succeeds: the next period's 80 pays out 50
+        let (base, adjustments) = payout_base(Decimal::from(80), Decimal::from(-30));
+        assert_eq!(base, Decimal::from(50));
+        assert_eq!(adjustments, Decimal::from(-30));
+    }
+
+    #[test]
+    fn refund_before_release_is_only_taken_off_the_gross() {
+        // already netted out of the period's gross, no claw-back was recorded for it
+        let (base, adjustments) = payout_base(Decimal::from(70), Decimal::ZERO);
+        assert_eq!(base, Decimal::from(70));
+        assert_eq!(adjustments, Decimal::ZERO);
+    }
+
+    #[test]
+    fn claw_back_larger_than_the_period_waits() {
+        let (base, adjustments) = payout_base(Decimal::from(20), Decimal::from(-30));
+        assert_eq!(base, Decimal::from(20));
+        assert_eq!(adjustments, Decimal::ZERO);
+    }
+}
@@ -0,0 +1,227 @@
+use crate::{
+    entity::{prelude::SyncTombstones, products::Model as ProductsModel, sync_tombstones},
+    error::AppError,
+    ids::{ProductId, TenantId},
+    models::products::{Categories, Products},
+};
+use async_graphql::{ErrorExtensions, SimpleObject};
+use chrono::DateTime;
+use sea_orm::{
+    prelude::DateTimeWithTimeZone, ColumnTrait, Condition, ConnectionTrait, DbBackend, DbErr,
+    EntityTrait, QueryFilter, QueryOrder, QuerySelect, Statement,
+};
+
+pub const MAX_SYNC_BATCH: u64 = 500;
+
+// cursors are "<updated_at as unix micros>:<row id>" so rows touched in the same instant keep a stable order
+pub fn encode_sync_cursor(updated_at: DateTimeWithTimeZone, id: i32) -> String {
+    format!("{}:{}", updated_at.timestamp_micros(), id)
+}
+
+pub fn decode_sync_cursor(
+    cursor: &str,
+) -> Result<(DateTimeWithTimeZone, i32), async_graphql::Error> {
+    let (micros, id) = cursor
+        .split_once(':')
+        .ok_or_else(|| AppError::invalid("Invalid sync cursor").extend())?;
+    let updated_at = DateTime::from_timestamp_micros(micros.parse::<i64>()?)
+        .ok_or_else(|| AppError::invalid("Invalid sync cursor").extend())?
+        .fixed_offset();
+
+    Ok((updated_at, id.parse::<i32>()?))
+}
+
+pub const SYNC_ENTITY_PRODUCT: &str = "PRODUCT";
+pub const SYNC_ENTITY_CATEGORY: &str = "CATEGORY";
+
+// the catalog feeds' cursors are "x<transaction id>:<row id>", they run in the order of the
+// transactions that wrote the rows. The prefix tells them apart from updated_at cursors
+pub fn encode_feed_cursor(sync_xid: i64, id: i32) -> String {
+    format!("x{}:{}", sync_xid, id)
+}
+
+pub fn decode_feed_cursor(cursor: &str) -> Result<(i64, i32), async_graphql::Error> {
+    let invalid = || AppError::invalid("Invalid sync cursor, start over without one").extend();
+    let (sync_xid, id) = cursor
+        .strip_prefix('x')
+        .and_then(|cursor| cursor.split_once(':'))
+        .ok_or_else(invalid)?;
+
+    Ok((
+        sync_xid.parse::<i64>().map_err(|_| invalid())?,
+        id.parse::<i32>().map_err(|_| invalid())?,
+    ))
+}
+
+// transaction ids are handed out on a transaction's first write, not when it commits, so a feed
+// stops short of the oldest transaction still running. Everything below it has committed or
+// rolled back, nothing can turn up behind a cursor later
+pub async fn sync_horizon<C: ConnectionTrait>(db: &C) -> Result<i64, DbErr> {
+    db.query_one(Statement::from_string(
+        DbBackend::Postgres,
+        "SELECT pg_snapshot_xmin(pg_current_snapshot())::text::bigint AS horizon",
+    ))
+    .await?
+    .ok_or_else(|| DbErr::RecordNotFound("sync horizon".to_string()))?
+    .try_get::<i64>("", "horizon")
+}
+
+// the rows of a feed past the cursor and below the horizon
+pub fn in_sync_window(
+    sync_xid: impl ColumnTrait,
+    id: impl ColumnTrait,
+    cursor: Option<(i64, i32)>,
+    horizon: i64,
+) -> Condition {
+    let window = Condition::all().add(sync_xid.lt(horizon));
+    match cursor {
+        Some((after_xid, after_id)) => window.add(
+            Condition::any().add(sync_xid.gt(after_xid)).add(
+                Condition::all()
+                    .add(sync_xid.eq(after_xid))
+                    .add(id.gt(after_id)),
+            ),
+        ),
+        None => window,
+    }
+}
+
+// ids deleted within the window, in feed order
+pub async fn deleted_in_sync_window<C: ConnectionTrait>(
+    db: &C,
+    entity: &str,
+    tenant: TenantId,
+    cursor: Option<(i64, i32)>,
+    horizon: i64,
+    limit: u64,
+) -> Result<Vec<(i64, i32)>, DbErr> {
+    SyncTombstones::find()
+        .select_only()
+        .column(sync_tombstones::Column::SyncXid)
+        .column(sync_tombstones::Column::EntityId)
+        .filter(sync_tombstones::Column::Entity.eq(entity))
+        .filter(sync_tombstones::Column::TenantId.eq(tenant.0))
+        .filter(in_sync_window(
+            sync_tombstones::Column::SyncXid,
+            sync_tombstones::Column::EntityId,
+            cursor,
+            horizon,
+        ))
+        .order_by_asc(sync_tombstones::Column::SyncXid)
+        .order_by_asc(sync_tombstones::Column::EntityId)
+        .limit(limit)
+        .into_tuple()
+        .all(db)
+        .await
+}
+
+pub struct SyncPage<T> {
+    pub changed: Vec<T>,
+    pub deleted: Vec<i32>,
+    pub next_cursor: Option<String>,
+    pub has_more: bool,
+}
+
+// changed rows and deletions, each read with limit + 1 in feed order, merged into one page
+pub fn sync_page<T>(
+    changed: Vec<(i64, i32, T)>,
+    deleted: Vec<(i64, i32)>,
+    limit: u64,
+) -> SyncPage<T> {
+    let mut changes: Vec<(i64, i32, Option<T>)> = changed
+        .into_iter()
+        .map(|(sync_xid, id, row)| (sync_xid, id, Some(row)))
+        .chain(
+            deleted
+                .into_iter()
+                .map(|(sync_xid, id)| (sync_xid, id, None)),
+        )
+        .collect();
+    changes.sort_by_key(|(sync_xid, id, _)| (*sync_xid, *id));
+
+    let has_more = changes.len() as u64 > limit;
+    changes.truncate(limit as usize);
+    let next_cursor = changes
+        .last()
+        .map(|(sync_xid, id, _)| encode_feed_cursor(*sync_xid, *id));
+
+    let mut page = SyncPage {
+        changed: Vec::new(),
+        deleted: Vec::new(),
+        next_cursor,
+        has_more,
+    };
+    for (_, id, row) in changes {
+        match row {
+            Some(row) => page.changed.push(row),
+            None => page.deleted.push(id),
+        }
+    }
+    page
+}
+
+#[derive(SimpleObject)]
+pub struct ProductsSync {
+    pub products: Vec<Products>,
+    pub deleted_product_ids: Vec<ProductId>,
+    pub next_cursor: Option<String>,
+    pub has_more: bool,
+}
+
+#[derive(SimpleObject)]
+pub struct CategoriesSync {
+    pub categories: Vec<Categories>,
+    pub deleted_category_ids: Vec<i32>,
+    pub next_cursor: Option<String>,
+    pub has_more: bool,
+}
+
+#[derive(SimpleObject)]
+pub struct ProductPrices {
+    pub product_id: ProductId,
+    pub base_price: String,
+    pub price_updated_at: DateTimeWithTimeZone,
+}
+
+impl From<ProductsModel> for ProductPrices {
+    fn from(val: ProductsModel) -> ProductPrices {
+        ProductPrices {
+            product_id: val.product_id.into(),
+            base_price: val.base_price.to_string(),
+            price_updated_at: val.price_updated_at,
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct PricesSync {
+    pub prices: Vec<ProductPrices>,
+    pub deleted_product_ids: Vec<ProductId>,
+    pub next_cursor: Option<String>,
+    pub has_more: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merges_changes_and_deletions_in_feed_order() {
+        let page = sync_page(
+            vec![(10, 4, "a"), (12, 1, "b"), (15, 2, "c")],
+            vec![(11, 7), (12, 3)],
+            4,
+        );
+
+        assert_eq!(page.changed, ["a", "b"]);
+        assert_eq!(page.deleted, [7, 3]);
+        assert_eq!(page.next_cursor.as_deref(), Some("x12:3"));
+        assert!(page.has_more);
+    }
+
+    #[test]
+    fn refuses_cursors_from_the_updated_at_feed() {
+        assert_eq!(decode_feed_cursor("x42:7").unwrap(), (42, 7));
+        assert!(decode_feed_cursor("1760000000000000:7").is_err());
+    }
+}
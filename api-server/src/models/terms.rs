@@ -0,0 +1,48 @@
+use crate::entity::policy_versions::Model as PolicyVersionsModel;
+use async_graphql::{Enum, InputObject, SimpleObject};
+use sea_orm::prelude::DateTimeWithTimeZone;
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+pub enum PolicyType {
+    Terms,
+    Privacy,
+}
+
+impl PolicyType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PolicyType::Terms => "TERMS",
+            PolicyType::Privacy => "PRIVACY",
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct PolicyVersions {
+    pub policy_version_id: i32,
+    pub policy_type: String,
+    pub version: String,
+    pub document_url: String,
+    pub published_at: Option<DateTimeWithTimeZone>,
+}
+
+impl From<PolicyVersionsModel> for PolicyVersions {
+    fn from(val: PolicyVersionsModel) -> PolicyVersions {
+        PolicyVersions {
+            policy_version_id: val.policy_version_id,
+            policy_type: val.policy_type,
+            version: val.version,
+            document_url: val.document_url,
+            published_at: val.published_at,
+        }
+    }
+}
+
+#[derive(InputObject)]
+pub struct RegisterPolicyVersion {
+    pub policy_type: PolicyType,
+    pub version: String,
+    pub document_url: String,
+    // leave empty to publish immediately, or schedule it for later
+    pub published_at: Option<DateTimeWithTimeZone>,
+}
@@ -1,65 +1,130 @@
 use crate::{
-    auth::Auth,
+    auth::{Auth, AuthenticatedUser, Claims},
+    config::{JwtPolicy, SessionPolicy},
     entity::{
         customers::Model as CustomersModel, suppliers::Model as SuppliersModel,
         users::Model as UsersModel,
     },
+    error::{AppError, AuthErrorCode},
+    graphql::complexity,
+    ids::{global_id, UserId},
+    models::addresses::Addresses,
+    pii::{pii, PiiKind},
+    retry::retry_db,
+    sessions::issue_refresh_token,
+    tenancy::current_tenant,
 };
-use async_graphql::{Error, InputObject, SimpleObject};
+use async_graphql::{
+    ComplexObject, Context, Error, ErrorExtensions, InputObject, SimpleObject, ID,
+};
+use chrono::Duration;
 use sea_orm::{
     prelude::DateTimeWithTimeZone, ActiveEnum, ColumnTrait, DatabaseConnection, EntityTrait,
-    QueryFilter,
+    JoinType, QueryFilter, QueryOrder, QuerySelect, RelationTrait,
 };
 
 #[derive(SimpleObject)]
+#[graphql(complex)]
 pub struct Users {
-    pub user_id: i32,
+    pub user_id: UserId,
+    #[graphql(directive = pii::apply(PiiKind::Email))]
     pub email: String,
+    #[graphql(directive = pii::apply(PiiKind::Secret))]
     pub password: String,
     pub role: String,
     pub created_at: Option<DateTimeWithTimeZone>,
-    pub email_verified: Option<bool>,
+    pub email_verified: bool,
+    pub locale: Option<String>,
 }
 
 impl From<UsersModel> for Users {
     fn from(val: UsersModel) -> Users {
         Users {
-            user_id: val.user_id,
+            user_id: val.user_id.into(),
             email: val.email,
             password: val.password,
             role: val.role.to_value(),
             created_at: val.created_at,
             email_verified: val.email_verified,
+            locale: val.locale,
         }
     }
 }
 
+#[ComplexObject]
+impl Users {
+    pub async fn id(&self) -> ID {
+        global_id("Users", self.user_id.0)
+    }
+}
+
 #[derive(InputObject)]
 pub struct LoginUser {
+    #[graphql(directive = pii::apply(PiiKind::Email))]
     pub email: String,
+    #[graphql(directive = pii::apply(PiiKind::Secret))]
     pub password: String,
 }
 
 #[derive(SimpleObject)]
 pub struct AuthUser {
+    // short lived, trade the refresh token in for a new pair before it runs out
     pub token: String,
+    // single use, the refreshToken mutation replaces it with a new one
+    #[graphql(directive = pii::apply(PiiKind::Secret))]
+    pub refresh_token: String,
     pub user_role: String,
+    pub roles: Vec<String>,
+}
+
+// an account as admins see it, without the password hash Users carries
+#[derive(SimpleObject)]
+pub struct UserAccounts {
+    pub user_id: UserId,
+    #[graphql(directive = pii::apply(PiiKind::Email))]
+    pub email: String,
+    pub role: String,
+    pub created_at: Option<DateTimeWithTimeZone>,
+    pub email_verified: bool,
+    // set while the account is suspended
+    pub disabled_at: Option<DateTimeWithTimeZone>,
+}
+
+impl From<UsersModel> for UserAccounts {
+    fn from(val: UsersModel) -> UserAccounts {
+        UserAccounts {
+            user_id: val.user_id.into(),
+            email: val.email,
+            role: val.role.to_value(),
+            created_at: val.created_at,
+            email_verified: val.email_verified,
+            disabled_at: val.disabled_at,
+        }
+    }
 }
 
 #[derive(InputObject)]
 pub struct RegisterUser {
+    #[graphql(directive = pii::apply(PiiKind::Email))]
     pub email: String,
+    #[graphql(directive = pii::apply(PiiKind::Secret))]
     pub password: String,
     pub role: String,
 }
 
 #[derive(SimpleObject)]
+#[graphql(complex)]
 pub struct Customers {
     pub customer_id: i32,
+    #[graphql(directive = pii::apply(PiiKind::Name))]
     pub first_name: String,
+    #[graphql(directive = pii::apply(PiiKind::Name))]
     pub last_name: String,
     pub registration_date: Option<DateTimeWithTimeZone>,
-    pub user_id: i32,
+    pub user_id: UserId,
+    pub vat_id: Option<String>,
+    // set once the VAT ID checked out with the validation service, orders are VAT exempt from then on
+    pub vat_id_validated_at: Option<DateTimeWithTimeZone>,
 }
 
 impl From<CustomersModel> for Customers {
@@ -69,14 +134,38 @@ impl From<CustomersModel> for Customers {
             first_name: val.first_name,
             last_name: val.last_name,
             registration_date: val.registration_date,
-            user_id: val.user_id,
+            user_id: val.user_id.into(),
+            vat_id: val.vat_id,
+            vat_id_validated_at: val.vat_id_validated_at,
         }
     }
 }
 
+#[ComplexObject]
+impl Customers {
+    // the address book, default address first
+    #[graphql(complexity = "complexity::unbounded(child_complexity)")]
+    async fn addresses(&self, ctx: &Context<'_>) -> Result<Vec<Addresses>, Error> {
+        use crate::entity::{addresses, prelude::Addresses as AddressesEntity};
+        let db = ctx.data::<DatabaseConnection>()?;
+
+        Ok(AddressesEntity::find()
+            .filter(addresses::Column::CustomerId.eq(self.customer_id))
+            .order_by_desc(addresses::Column::IsDefault)
+            .order_by_asc(addresses::Column::AddressId)
+            .all(db)
+            .await?
+            .into_iter()
+            .map(Addresses::from)
+            .collect())
+    }
+}
+
 #[derive(InputObject)]
 pub struct RegisterCustomer {
+    #[graphql(directive = pii::apply(PiiKind::Name))]
     pub first_name: String,
+    #[graphql(directive = pii::apply(PiiKind::Name))]
     pub last_name: String,
 }
 
@@ -84,8 +173,20 @@ pub struct RegisterCustomer {
 pub struct Suppliers {
     pub supplier_id: i32,
     pub name: String,
+    #[graphql(directive = pii::apply(PiiKind::Phone))]
     pub contact_phone: Option<String>,
-    pub user_id: i32,
+    pub user_id: UserId,
+    pub region: Option<String>,
+    #[graphql(directive = pii::apply(PiiKind::Name))]
+    pub payout_account_holder: Option<String>,
+    // the full IBAN is never sent back out
+    pub payout_iban_last4: Option<String>,
+    // opted in to the daily activity digest, sent in the morning of this IANA time zone
+    pub digest_enabled: bool,
+    pub timezone: String,
+    // null while the account waits for an admin, products can't be listed until then
+    pub approved_at: Option<DateTimeWithTimeZone>,
+    pub ship_from_country: Option<String>,
 }
 
 impl From<SuppliersModel> for Suppliers {
@@ -94,7 +195,16 @@ impl From<SuppliersModel> for Suppliers {
             supplier_id: val.supplier_id,
             name: val.name,
             contact_phone: val.contact_phone,
-            user_id: val.user_id,
+            user_id: val.user_id.into(),
+            region: val.region,
+            payout_account_holder: val.payout_account_holder,
+            payout_iban_last4: val
+                .payout_iban
+                .map(|iban| iban[iban.len().saturating_sub(4)..].to_string()),
+            digest_enabled: val.digest_enabled,
+            timezone: val.timezone,
+            approved_at: val.approved_at,
+            ship_from_country: val.ship_from_country,
         }
     }
 }
@@ -102,36 +212,139 @@ impl From<SuppliersModel> for Suppliers {
 #[derive(InputObject)]
 pub struct RegisterSupplier {
     pub name: String,
+    #[graphql(directive = pii::apply(PiiKind::Phone))]
     pub contact_phone: Option<String>,
+    pub region: Option<String>,
+    // where parcels leave from, orders to other countries then carry customs data
+    pub ship_from_country: Option<String>,
 }
 
+// the caller's customer or supplier id, for the role the resolver acts as
 pub async fn get_customer_supplier_id(
     db: &DatabaseConnection,
-    token: &str,
+    ctx: &Context<'_>,
+    role: &str,
+) -> Result<i32, Error> {
+    let claims = AuthenticatedUser::from_ctx(ctx).map_err(|e| e.extend())?;
+    customer_supplier_id_of(db, &claims, role).await
+}
+
+// the same for callers verified outside GraphQL
+pub async fn customer_supplier_id_of(
+    db: &DatabaseConnection,
+    claims: &Claims,
     role: &str,
 ) -> Result<i32, Error> {
     use crate::entity::{customers, suppliers};
 
+    let user_id = claims.user_id.parse::<i32>()?;
     match role {
-        "supplier" => {
-            let user_id = Auth::verify_token(token)?.user_id.parse::<i32>()?;
+        "supplier" => retry_db("supplier_lookup", || {
             suppliers::Entity::find()
                 .filter(suppliers::Column::UserId.eq(user_id))
                 .one(db)
-                .await?
-                .map(|supplier| supplier.supplier_id)
-                .ok_or_else(|| Error::new("Supplier not found"))
-        }
+        })
+        .await?
+        .map(|supplier| supplier.supplier_id)
+        .ok_or_else(|| AppError::NotFound("Supplier").extend()),
 
-        "customer" => {
-            let user_id = Auth::verify_token(token)?.user_id.parse::<i32>()?;
+        "customer" => retry_db("customer_lookup", || {
             customers::Entity::find()
                 .filter(customers::Column::UserId.eq(user_id))
                 .one(db)
-                .await?
-                .map(|customer| customer.customer_id)
-                .ok_or_else(|| Error::new("Customer not found"))
+        })
+        .await?
+        .map(|customer| customer.customer_id)
+        .ok_or_else(|| AppError::NotFound("Customer").extend()),
+        _ => Err(AppError::invalid("Invalid role").extend()),
+    }
+}
+
+// the supplier, as long as it signed up under the caller's tenant
+pub async fn tenant_supplier(ctx: &Context<'_>, supplier_id: i32) -> Result<SuppliersModel, Error> {
+    use crate::entity::{suppliers, users};
+    let db = ctx.data::<DatabaseConnection>()?;
+
+    suppliers::Entity::find_by_id(supplier_id)
+        .join(JoinType::InnerJoin, suppliers::Relation::Users.def())
+        .filter(users::Column::TenantId.eq(current_tenant(ctx).0))
+        .one(db)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Supplier").extend())
+}
+
+// products are only listed by suppliers an admin has approved
+pub async fn check_supplier_approved(
+    db: &DatabaseConnection,
+    supplier_id: i32,
+) -> Result<(), Error> {
+    use crate::entity::prelude::Suppliers as SuppliersEntity;
+    let supplier = SuppliersEntity::find_by_id(supplier_id)
+        .one(db)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Supplier").extend())?;
+    if supplier.approved_at.is_none() {
+        return Err(AppError::Auth {
+            message: "Supplier account is awaiting approval".to_string(),
+            code: AuthErrorCode::InsufficientPermissions,
+            user_id: None,
+        }
+        .extend());
+    }
+    Ok(())
+}
+
+// the role the account signed up with first, then the ones added through addRole
+pub async fn held_roles(db: &DatabaseConnection, user: &UsersModel) -> Result<Vec<String>, Error> {
+    use crate::entity::{prelude::UserRoles as UserRolesEntity, user_roles};
+
+    let mut roles = vec![user.role.to_value()];
+    for added in UserRolesEntity::find()
+        .filter(user_roles::Column::UserId.eq(user.user_id))
+        .all(db)
+        .await?
+    {
+        let role = added.role.to_value();
+        if !roles.contains(&role) {
+            roles.push(role);
+        }
+    }
+
+    Ok(roles)
+}
+
+pub async fn issue_auth_user(
+    db: &DatabaseConnection,
+    redis: &redis::Client,
+    policy: &SessionPolicy,
+    jwt_policy: &JwtPolicy,
+    user: &UsersModel,
+) -> Result<AuthUser, Error> {
+    // every way into a session ends here, so a disabled account gets no new tokens
+    if user.disabled_at.is_some() {
+        return Err(AppError::Auth {
+            message: "Account is disabled".to_string(),
+            code: AuthErrorCode::InsufficientPermissions,
+            user_id: None,
         }
-        _ => Err(Error::new("Invalid role")),
+        .extend());
     }
+    let user_role = user.role.to_value();
+    let roles = held_roles(db, user).await?;
+    Ok(AuthUser {
+        token: Auth::create_token_with_roles(
+            jwt_policy,
+            user.user_id.into(),
+            user.tenant_id.into(),
+            user_role.clone(),
+            roles.clone(),
+            Duration::minutes(policy.access_token_minutes),
+        )
+        .map_err(|e| e.extend())?,
+        refresh_token: issue_refresh_token(redis, user.user_id, policy.refresh_token_days)
+            .await
+            .map_err(|e| e.extend())?,
+        user_role,
+        roles,
+    })
 }
@@ -0,0 +1,39 @@
+use crate::{entity::product_videos::Model as ProductVideosModel, ids::ProductId};
+use async_graphql::SimpleObject;
+use sea_orm::prelude::DateTimeWithTimeZone;
+
+#[derive(SimpleObject)]
+pub struct ProductVideos {
+    pub video_id: i32,
+    pub product_id: ProductId,
+    // UPLOAD or EXTERNAL
+    pub source: String,
+    pub provider: Option<String>,
+    pub url: String,
+    // filled in from the provider for external videos
+    pub title: Option<String>,
+    pub thumbnail_url: Option<String>,
+    // PENDING, PROCESSING, READY or FAILED
+    pub status: String,
+    pub error: Option<String>,
+    pub created_at: DateTimeWithTimeZone,
+    pub processed_at: Option<DateTimeWithTimeZone>,
+}
+
+impl From<ProductVideosModel> for ProductVideos {
+    fn from(val: ProductVideosModel) -> ProductVideos {
+        ProductVideos {
+            video_id: val.video_id,
+            product_id: ProductId(val.product_id),
+            source: val.source,
+            provider: val.provider,
+            url: val.url,
+            title: val.title,
+            thumbnail_url: val.thumbnail_url,
+            status: val.status,
+            error: val.error,
+            created_at: val.created_at,
+            processed_at: val.processed_at,
+        }
+    }
+}
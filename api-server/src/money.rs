@@ -0,0 +1,182 @@
+use sea_orm::prelude::Decimal;
+
+// how amounts in a currency are rounded, ISO 4217 minor units plus the smallest step a total is
+// paid in where that differs, like the 5 Rappen Swiss totals are rounded to
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CurrencyRule {
+    pub decimals: u32,
+    pub total_increment: Decimal,
+}
+
+impl CurrencyRule {
+    fn minor_units(decimals: u32) -> Self {
+        Self {
+            decimals,
+            total_increment: Decimal::new(1, decimals),
+        }
+    }
+}
+
+// currencies not listed here have two decimals and round totals to the cent
+pub fn currency_rule(currency: &str) -> CurrencyRule {
+    match currency.trim().to_uppercase().as_str() {
+        "BIF" | "CLP" | "DJF" | "GNF" | "ISK" | "JPY" | "KMF" | "KRW" | "PYG" | "RWF" | "UGX"
+        | "VND" | "VUV" | "XAF" | "XOF" | "XPF" => CurrencyRule::minor_units(0),
+        "BHD" | "IQD" | "JOD" | "KWD" | "LYD" | "OMR" | "TND" => CurrencyRule::minor_units(3),
+        "CHF" => CurrencyRule {
+            decimals: 2,
+            total_increment: Decimal::new(5, 2),
+        },
+        _ => CurrencyRule::minor_units(2),
+    }
+}
+
+// halves round away from zero, the way receipts and invoices do, rather than to the even step
+fn round_to_step(amount: Decimal, step: Decimal) -> Decimal {
+    let steps = (amount.abs() / step + Decimal::new(5, 1)).floor();
+    let rounded = steps * step;
+    if amount.is_sign_negative() {
+        -rounded
+    } else {
+        rounded
+    }
+}
+
+// unit prices, discounts and tax lines, to the currency's minor unit
+pub fn round_amount(amount: Decimal, currency: &str) -> Decimal {
+    let rule = currency_rule(currency);
+    round_to_step(amount, Decimal::new(1, rule.decimals)).round_dp(rule.decimals)
+}
+
+// what is actually charged or invoiced, to the currency's smallest payable step
+pub fn round_total(amount: Decimal, currency: &str) -> Decimal {
+    let rule = currency_rule(currency);
+    round_to_step(amount, rule.total_increment).round_dp(rule.decimals)
+}
+
+// an order level amount spread over the lines in proportion to their weights, each share rounded
+// to the currency and the rounding left on the last line so the shares add up to the amount
+pub fn split_amount(amount: Decimal, weights: &[Decimal], currency: &str) -> Vec<Decimal> {
+    let total: Decimal = weights.iter().copied().sum();
+    let mut left = amount;
+    let mut shares = Vec::with_capacity(weights.len());
+    for (index, weight) in weights.iter().enumerate() {
+        let share = if index + 1 == weights.len() {
+            left
+        } else if total.is_zero() {
+            Decimal::ZERO
+        } else {
+            round_amount(amount * weight / total, currency)
+        };
+        left -= share;
+        shares.push(share);
+    }
+    shares
+}
+
+// digit grouping and decimal mark of a locale, "de-CH" before "de"
+fn separators(locale: &str) -> (&'static str, &'static str) {
+    match locale {
+        "de-CH" | "it-CH" | "fr-CH" | "de-LI" => ("'", "."),
+        _ => match locale.split('-').next().unwrap_or_default() {
+            "de" | "es" | "it" | "nl" | "pt" | "da" | "id" | "tr" => (".", ","),
+            "fr" | "pl" | "sv" | "cs" | "fi" | "nb" | "ru" | "uk" => ("\u{a0}", ","),
+            _ => (",", "."),
+        },
+    }
+}
+
+// the amount with the currency's decimals and the locale's separators, the code first for
+// English and after the number elsewhere, e.g. "CHF 1,234.50" or "1.234,50 EUR"
+pub fn format_money(amount: Decimal, currency: &str, locale: &str) -> String {
+    let currency = currency.trim().to_uppercase();
+    let rule = currency_rule(&currency);
+    let amount = round_amount(amount, &currency);
+    let (group, decimal_mark) = separators(locale);
+
+    let digits = format!("{:.*}", rule.decimals as usize, amount.abs());
+    let (whole, fraction) = digits.split_once('.').unwrap_or((&digits, ""));
+    let mut grouped = String::new();
+    for (index, digit) in whole.chars().enumerate() {
+        if index > 0 && (whole.len() - index) % 3 == 0 {
+            grouped.push_str(group);
+        }
+        grouped.push(digit);
+    }
+    if !fraction.is_empty() {
+        grouped.push_str(decimal_mark);
+        grouped.push_str(fraction);
+    }
+    let sign = if amount.is_sign_negative() && !amount.is_zero() {
+        "-"
+    } else {
+        ""
+    };
+
+    if locale == "en" || locale.starts_with("en-") {
+        format!("{}{} {}", sign, currency, grouped)
+    } else {
+        format!("{}{} {}", sign, grouped, currency)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dec(amount: &str) -> Decimal {
+        Decimal::from_str_exact(amount).unwrap()
+    }
+
+    #[test]
+    fn rounds_to_the_minor_unit_of_the_currency() {
+        assert_eq!(round_amount(dec("1234.5"), "JPY"), dec("1235"));
+        assert_eq!(round_amount(dec("1234.4999"), "jpy"), dec("1234"));
+        assert_eq!(round_amount(dec("10.0005"), "KWD"), dec("10.001"));
+        assert_eq!(round_amount(dec("10.005"), "EUR"), dec("10.01"));
+        assert_eq!(round_amount(dec("-10.005"), "EUR"), dec("-10.01"));
+        // unit prices in francs keep their cents
+        assert_eq!(round_amount(dec("10.03"), "CHF"), dec("10.03"));
+    }
+
+    #[test]
+    fn rounds_swiss_totals_to_five_rappen() {
+        assert_eq!(round_total(dec("10.02"), "CHF"), dec("10.00"));
+        assert_eq!(round_total(dec("10.025"), "CHF"), dec("10.05"));
+        assert_eq!(round_total(dec("10.074"), "CHF"), dec("10.05"));
+        assert_eq!(round_total(dec("10.075"), "CHF"), dec("10.10"));
+        assert_eq!(round_total(dec("-0.03"), "CHF"), dec("-0.05"));
+        assert_eq!(round_total(dec("10.02"), "EUR"), dec("10.02"));
+        assert_eq!(round_total(dec("999.5"), "JPY"), dec("1000"));
+    }
+
+    #[test]
+    fn splits_an_order_amount_over_its_lines() {
+        let shares = split_amount(dec("10.00"), &[dec("30"), dec("30"), dec("30")], "EUR");
+        assert_eq!(shares, [dec("3.33"), dec("3.33"), dec("3.34")]);
+        let shares = split_amount(dec("7.50"), &[dec("100"), dec("50")], "EUR");
+        assert_eq!(shares, [dec("5.00"), dec("2.50")]);
+        assert_eq!(
+            split_amount(dec("1"), &[dec("0"), dec("0")], "JPY"),
+            [dec("0"), dec("1")]
+        );
+        assert!(split_amount(dec("1"), &[], "EUR").is_empty());
+    }
+
+    #[test]
+    fn formats_with_the_separators_of_the_locale() {
+        assert_eq!(format_money(dec("1234.5"), "EUR", "en"), "EUR 1,234.50");
+        assert_eq!(format_money(dec("1234.5"), "EUR", "de-AT"), "1.234,50 EUR");
+        assert_eq!(
+            format_money(dec("1234567.5"), "CHF", "de-CH"),
+            "1'234'567.50 CHF"
+        );
+        assert_eq!(
+            format_money(dec("1234567.5"), "JPY", "en-US"),
+            "JPY 1,234,568"
+        );
+        assert_eq!(format_money(dec("-5.1234"), "BHD", "fr"), "-5,123 BHD");
+        assert_eq!(format_money(dec("999"), "EUR", "en"), "EUR 999.00");
+        assert_eq!(format_money(dec("-0.001"), "EUR", "en"), "EUR 0.00");
+    }
+}
@@ -0,0 +1,201 @@
+use crate::{
+    auth::Auth,
+    config::NewsletterPolicy,
+    entity::{newsletter_subscriptions, prelude::NewsletterSubscriptions},
+    error::{AppError, AuthErrorCode},
+    ids::TenantId,
+    notifications::{normalize_email, send_templated, TemplateKey},
+};
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use axum::{extract::Path, Extension};
+use chrono::{Duration, Utc};
+use hmac::{Hmac, Mac};
+use sea_orm::{
+    prelude::DateTimeWithTimeZone, ActiveValue::Set, ColumnTrait, DatabaseConnection, EntityTrait,
+    QueryFilter,
+};
+use sha2::Sha256;
+use std::env;
+
+// how long the link in the confirmation email keeps working
+const CONFIRMATION_TTL_HOURS: i64 = 48;
+
+fn unsubscribe_mac(subscription_id: i32) -> Result<Hmac<Sha256>, AppError> {
+    let secret = env::var("TOKEN_SECRET")
+        .map_err(|_| AppError::Internal("TOKEN_SECRET must be set".to_string()))?;
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .map_err(|e| AppError::Internal(format!("Invalid token secret: {}", e)))?;
+    mac.update(format!("newsletter:{}", subscription_id).as_bytes());
+    Ok(mac)
+}
+
+// "<subscription id>.<hex hmac>", never expires so unsubscribe links in old newsletters keep working
+pub fn sign_unsubscribe_token(subscription_id: i32) -> Result<String, AppError> {
+    let signature: String = unsubscribe_mac(subscription_id)?
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    Ok(format!("{}.{}", subscription_id, signature))
+}
+
+pub fn verify_unsubscribe_token(token: &str) -> Result<i32, AppError> {
+    let invalid = || AppError::Auth {
+        message: "Invalid unsubscribe link".to_string(),
+        code: AuthErrorCode::InvalidCredentials,
+        user_id: None,
+    };
+
+    let (subscription_id, signature) = token.split_once('.').ok_or_else(invalid)?;
+    let subscription_id = subscription_id.parse::<i32>().map_err(|_| invalid())?;
+    let signature = (0..signature.len())
+        .step_by(2)
+        .map(|index| {
+            signature
+                .get(index..index + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+        })
+        .collect::<Option<Vec<u8>>>()
+        .ok_or_else(invalid)?;
+    unsubscribe_mac(subscription_id)?
+        .verify_slice(&signature)
+        .map_err(|_| invalid())?;
+    Ok(subscription_id)
+}
+
+fn confirmation_url(policy: &NewsletterPolicy, token: &str) -> String {
+    format!(
+        "{}/{}",
+        policy.confirmation_url.trim_end_matches('/'),
+        token
+    )
+}
+
+// starts (or restarts) the double opt-in, confirmed subscriptions are left alone so the answer never
+// tells a caller whether an address is already on the list
+pub async fn request_subscription(
+    db: &DatabaseConnection,
+    policy: &NewsletterPolicy,
+    tenant: TenantId,
+    email: &str,
+    locale: Option<String>,
+) -> Result<(), AppError> {
+    let email = normalize_email(email);
+    Auth::check_email(&email).map_err(|message| AppError::Validation {
+        message: message.to_string(),
+        failed_rules: vec!["EMAIL".to_string()],
+    })?;
+
+    let existing = NewsletterSubscriptions::find()
+        .filter(newsletter_subscriptions::Column::TenantId.eq(tenant.0))
+        .filter(newsletter_subscriptions::Column::Email.eq(&email))
+        .one(db)
+        .await?;
+    if existing
+        .as_ref()
+        .is_some_and(|subscription| subscription.status == "SUBSCRIBED")
+    {
+        return Ok(());
+    }
+
+    let mut bytes = [0u8; 24];
+    OsRng.fill_bytes(&mut bytes);
+    let token: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+
+    let pending = newsletter_subscriptions::ActiveModel {
+        status: Set("PENDING".to_string()),
+        locale: Set(locale.clone()),
+        confirm_token_hash: Set(Some(Auth::hash_secret(&token))),
+        confirmation_sent_at: Set(Some(Utc::now().fixed_offset())),
+        ..Default::default()
+    };
+    match existing {
+        Some(subscription) => {
+            NewsletterSubscriptions::update(newsletter_subscriptions::ActiveModel {
+                subscription_id: Set(subscription.subscription_id),
+                ..pending
+            })
+            .exec(db)
+            .await?;
+        }
+        None => {
+            NewsletterSubscriptions::insert(newsletter_subscriptions::ActiveModel {
+                email: Set(email.clone()),
+                tenant_id: Set(tenant.0),
+                ..pending
+            })
+            .exec_without_returning(db)
+            .await?;
+        }
+    }
+
+    send_templated(
+        db,
+        tenant,
+        TemplateKey::NewsletterConfirmation,
+        email,
+        locale.as_deref(),
+        &[("confirmation_url", confirmation_url(policy, &token))],
+    )
+    .await
+}
+
+pub async fn unsubscribe(db: &DatabaseConnection, token: &str) -> Result<(), AppError> {
+    let subscription_id = verify_unsubscribe_token(token)?;
+    NewsletterSubscriptions::update_many()
+        .col_expr(
+            newsletter_subscriptions::Column::Status,
+            "UNSUBSCRIBED".into(),
+        )
+        .col_expr(
+            newsletter_subscriptions::Column::UnsubscribedAt,
+            Utc::now().fixed_offset().into(),
+        )
+        .col_expr(
+            newsletter_subscriptions::Column::ConfirmTokenHash,
+            Option::<String>::None.into(),
+        )
+        .filter(newsletter_subscriptions::Column::SubscriptionId.eq(subscription_id))
+        .filter(newsletter_subscriptions::Column::Status.ne("UNSUBSCRIBED"))
+        .exec(db)
+        .await?;
+    Ok(())
+}
+
+// GET /newsletter/confirm/:token, the link in the confirmation email
+pub async fn confirm_newsletter(
+    Path(token): Path<String>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> String {
+    let confirmed = NewsletterSubscriptions::update_many()
+        .col_expr(
+            newsletter_subscriptions::Column::Status,
+            "SUBSCRIBED".into(),
+        )
+        .col_expr(
+            newsletter_subscriptions::Column::ConfirmedAt,
+            Utc::now().fixed_offset().into(),
+        )
+        .col_expr(
+            newsletter_subscriptions::Column::UnsubscribedAt,
+            Option::<DateTimeWithTimeZone>::None.into(),
+        )
+        .col_expr(
+            newsletter_subscriptions::Column::ConfirmTokenHash,
+            Option::<String>::None.into(),
+        )
+        .filter(newsletter_subscriptions::Column::ConfirmTokenHash.eq(Auth::hash_secret(&token)))
+        .filter(
+            newsletter_subscriptions::Column::ConfirmationSentAt
+                .gt(Utc::now() - Duration::hours(CONFIRMATION_TTL_HOURS)),
+        )
+        .exec(&db)
+        .await;
+
+    match confirmed {
+        Ok(result) if result.rows_affected > 0 => "Newsletter subscription confirmed".to_string(),
+        Ok(_) => "Invalid or expired confirmation link".to_string(),
+        Err(e) => AppError::from(e).to_string(),
+    }
+}
@@ -0,0 +1,336 @@
+use crate::{
+    entity::{
+        email_templates,
+        prelude::{EmailSuppressions, EmailTemplates},
+    },
+    error::AppError,
+    ids::TenantId,
+    mailer::send_mail,
+    models::email_templates::RenderedEmail,
+    tenancy::TenantScope,
+};
+use async_graphql::Enum;
+use lazy_regex::regex;
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
+
+// last link of every fallback chain, the built in copy is written in it
+pub const DEFAULT_LOCALE: &str = "en";
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+pub enum TemplateKey {
+    DisputeOpened,
+    EmailVerification,
+    GuestOrderConfirmation,
+    LoginCode,
+    NewsletterConfirmation,
+    OrderShipped,
+    PasswordReset,
+    PasswordResetByAdmin,
+    ShippingAddressChanged,
+    SupplierDigest,
+}
+
+impl TemplateKey {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TemplateKey::DisputeOpened => "DISPUTE_OPENED",
+            TemplateKey::EmailVerification => "EMAIL_VERIFICATION",
+            TemplateKey::GuestOrderConfirmation => "GUEST_ORDER_CONFIRMATION",
+            TemplateKey::LoginCode => "LOGIN_CODE",
+            TemplateKey::NewsletterConfirmation => "NEWSLETTER_CONFIRMATION",
+            TemplateKey::OrderShipped => "ORDER_SHIPPED",
+            TemplateKey::PasswordReset => "PASSWORD_RESET",
+            TemplateKey::PasswordResetByAdmin => "PASSWORD_RESET_BY_ADMIN",
+            TemplateKey::ShippingAddressChanged => "SHIPPING_ADDRESS_CHANGED",
+            TemplateKey::SupplierDigest => "SUPPLIER_DIGEST",
+        }
+    }
+
+    fn sender_name(&self) -> &'static str {
+        match self {
+            TemplateKey::DisputeOpened => "Nine11 Payments",
+            TemplateKey::EmailVerification => "Verify Mail Id Bitte",
+            TemplateKey::GuestOrderConfirmation => "Nine11 Orders",
+            TemplateKey::LoginCode => "Nine11 Security",
+            TemplateKey::NewsletterConfirmation => "Nine11 Newsletter",
+            TemplateKey::OrderShipped => "Nine11 Orders",
+            TemplateKey::PasswordReset => "Nine11 Security",
+            TemplateKey::PasswordResetByAdmin => "Nine11 Security",
+            TemplateKey::ShippingAddressChanged => "Nine11 Sellers",
+            TemplateKey::SupplierDigest => "Nine11 Sellers",
+        }
+    }
+
+    // (subject, html body) used when no stored template matches
+    fn builtin(&self) -> (&'static str, &'static str) {
+        match self {
+            TemplateKey::DisputeOpened => (
+                "Payment dispute on order #{{order_id}}",
+                "<p>The customer's bank opened a dispute over {{amount}} on order #{{order_id}} (reason: {{reason}}).</p><p>Payouts for this order are on hold until the dispute is resolved.</p>",
+            ),
+            TemplateKey::EmailVerification => (
+                "Nine11 email verification",
+                "<a href=\"{{verification_url}}\">Click here to verify your email</a>",
+            ),
+            TemplateKey::GuestOrderConfirmation => (
+                "Your Nine11 order #{{order_id}}",
+                "<p>Thanks for your order of {{total_amount}}.</p><p><a href=\"{{lookup_url}}\">Track your order</a></p><p>Create an account with this email address to keep all your orders in one place.</p>",
+            ),
+            TemplateKey::LoginCode => (
+                "Nine11 login code",
+                "<p>We noticed unusual sign in activity. Your login code is <b>{{code}}</b>.</p><p>It expires in {{ttl_minutes}} minutes.</p>",
+            ),
+            TemplateKey::NewsletterConfirmation => (
+                "Confirm your Nine11 newsletter subscription",
+                "<p>Please confirm that you want to receive the Nine11 newsletter.</p><p><a href=\"{{confirmation_url}}\">Yes, subscribe me</a></p><p>If you didn't sign up, ignore this email and you won't hear from us.</p>",
+            ),
+            TemplateKey::OrderShipped => (
+                "Your Nine11 order #{{order_id}} is on its way",
+                "<p>Your order #{{order_id}} was handed to {{carrier}}, tracking number {{tracking_number}}.</p><p><a href=\"{{tracking_url}}\">Track your parcel</a></p>",
+            ),
+            TemplateKey::PasswordReset => (
+                "Reset your Nine11 password",
+                "<p>Someone asked to reset the password of your Nine11 account.</p><p><a href=\"{{reset_url}}\">Choose a new password</a></p><p>The link works once and expires in {{ttl_minutes}} minutes. If it wasn't you, ignore this email and your password stays as it is.</p>",
+            ),
+            TemplateKey::PasswordResetByAdmin => (
+                "Your Nine11 password was reset",
+                "<p>For your security an administrator reset the password of your Nine11 account and signed you out on every device.</p><p>Please contact our support team to regain access.</p>",
+            ),
+            TemplateKey::ShippingAddressChanged => (
+                "Order #{{order_id}} ships to a new address",
+                "<p>The customer changed where order #{{order_id}} is shipped to. It now goes to {{city}}, {{country}}.</p><p>Please use the new address on the order when you prepare the shipment.</p>",
+            ),
+            TemplateKey::SupplierDigest => (
+                "{{supplier_name}}: your Nine11 activity for {{date}}",
+                "<p>Here is what happened in your store since the last digest.</p><ul><li>New orders: {{new_orders}}</li><li>Customer questions: {{questions}}</li><li>New reviews: {{reviews}}</li><li>Products low on stock: {{low_stock}}</li></ul>",
+            ),
+        }
+    }
+
+    // mail the recipient asked for right now still goes out to suppressed addresses
+    fn bypasses_suppression(&self) -> bool {
+        matches!(
+            self,
+            TemplateKey::EmailVerification | TemplateKey::LoginCode | TemplateKey::PasswordReset
+        )
+    }
+
+    pub fn placeholders(&self) -> &'static [&'static str] {
+        match self {
+            TemplateKey::DisputeOpened => &["order_id", "amount", "reason"],
+            TemplateKey::EmailVerification => &["verification_url"],
+            TemplateKey::GuestOrderConfirmation => &["order_id", "total_amount", "lookup_url"],
+            TemplateKey::LoginCode => &["code", "ttl_minutes"],
+            TemplateKey::NewsletterConfirmation => &["confirmation_url"],
+            TemplateKey::OrderShipped => {
+                &["order_id", "carrier", "tracking_number", "tracking_url"]
+            }
+            TemplateKey::PasswordReset => &["reset_url", "ttl_minutes"],
+            TemplateKey::PasswordResetByAdmin => &[],
+            TemplateKey::ShippingAddressChanged => &["order_id", "city", "country"],
+            TemplateKey::SupplierDigest => &[
+                "supplier_name",
+                "date",
+                "new_orders",
+                "questions",
+                "reviews",
+                "low_stock",
+            ],
+        }
+    }
+
+    // values the admin preview renders with
+    pub fn sample_data(&self) -> Vec<(&'static str, String)> {
+        match self {
+            TemplateKey::DisputeOpened => vec![
+                ("order_id", "1042".to_string()),
+                ("amount", "49.90".to_string()),
+                ("reason", "fraudulent".to_string()),
+            ],
+            TemplateKey::EmailVerification => vec![(
+                "verification_url",
+                "http://localhost:8000/verify/sample-token".to_string(),
+            )],
+            TemplateKey::GuestOrderConfirmation => vec![
+                ("order_id", "1042".to_string()),
+                ("total_amount", "49.90".to_string()),
+                (
+                    "lookup_url",
+                    "http://localhost:8000/guest-orders/sample-token".to_string(),
+                ),
+            ],
+            TemplateKey::LoginCode => vec![
+                ("code", "123456".to_string()),
+                ("ttl_minutes", "10".to_string()),
+            ],
+            TemplateKey::NewsletterConfirmation => vec![(
+                "confirmation_url",
+                "http://localhost:8000/newsletter/confirm/sample-token".to_string(),
+            )],
+            TemplateKey::OrderShipped => vec![
+                ("order_id", "1042".to_string()),
+                ("carrier", "UPS".to_string()),
+                ("tracking_number", "1Z999AA10123456784".to_string()),
+                (
+                    "tracking_url",
+                    "https://www.ups.com/track?tracknum=1Z999AA10123456784".to_string(),
+                ),
+            ],
+            TemplateKey::PasswordReset => vec![
+                (
+                    "reset_url",
+                    "http://localhost:8000/reset-password/sample-token".to_string(),
+                ),
+                ("ttl_minutes", "30".to_string()),
+            ],
+            TemplateKey::PasswordResetByAdmin => Vec::new(),
+            TemplateKey::ShippingAddressChanged => vec![
+                ("order_id", "1042".to_string()),
+                ("city", "Vienna".to_string()),
+                ("country", "AT".to_string()),
+            ],
+            TemplateKey::SupplierDigest => vec![
+                ("supplier_name", "Acme Supplies".to_string()),
+                ("date", "2024-12-01".to_string()),
+                ("new_orders", "12".to_string()),
+                ("questions", "3".to_string()),
+                ("reviews", "5".to_string()),
+                ("low_stock", "2".to_string()),
+            ],
+        }
+    }
+}
+
+pub fn is_valid_locale(locale: &str) -> bool {
+    regex!(r"^[a-z]{2,3}(-[A-Z]{2})?$").is_match(locale)
+}
+
+// "de-AT" falls back to "de" and then to the default locale
+pub fn fallback_chain(locale: Option<&str>) -> Vec<String> {
+    let mut chain = Vec::new();
+    if let Some(locale) = locale.filter(|locale| is_valid_locale(locale)) {
+        chain.push(locale.to_string());
+        if let Some((language, _)) = locale.split_once('-') {
+            chain.push(language.to_string());
+        }
+    }
+    if !chain.iter().any(|locale| locale == DEFAULT_LOCALE) {
+        chain.push(DEFAULT_LOCALE.to_string());
+    }
+    chain
+}
+
+// placeholders used in a text that aren't among the known ones
+pub fn unknown_placeholder_names(known: &[&str], text: &str) -> Vec<String> {
+    regex!(r"\{\{\s*(\w+)\s*\}\}")
+        .captures_iter(text)
+        .map(|captures| captures[1].to_string())
+        .filter(|name| !known.contains(&name.as_str()))
+        .collect()
+}
+
+// placeholders used in a template that the key doesn't provide
+pub fn unknown_placeholders(key: TemplateKey, text: &str) -> Vec<String> {
+    unknown_placeholder_names(key.placeholders(), text)
+}
+
+fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+pub fn substitute(text: &str, vars: &[(&str, String)], escape: bool) -> String {
+    regex!(r"\{\{\s*(\w+)\s*\}\}")
+        .replace_all(text, |captures: &lazy_regex::Captures| {
+            match vars.iter().find(|(name, _)| *name == &captures[1]) {
+                Some((_, value)) if escape => escape_html(value),
+                Some((_, value)) => value.clone(),
+                None => captures[0].to_string(),
+            }
+        })
+        .into_owned()
+}
+
+// picks the first variant the tenant stored along the recipient's fallback chain, the built in
+// copy otherwise
+pub async fn render_template(
+    db: &DatabaseConnection,
+    tenant: TenantId,
+    key: TemplateKey,
+    locale: Option<&str>,
+    vars: &[(&str, String)],
+) -> Result<RenderedEmail, AppError> {
+    let chain = fallback_chain(locale);
+
+    let stored = EmailTemplates::find()
+        .for_tenant(tenant)
+        .filter(email_templates::Column::TemplateKey.eq(key.as_str()))
+        .filter(email_templates::Column::Locale.is_in(chain.clone()))
+        .all(db)
+        .await?;
+
+    let (locale, subject, html_body) = chain
+        .iter()
+        .find_map(|locale| stored.iter().find(|template| &template.locale == locale))
+        .map(|template| {
+            (
+                template.locale.clone(),
+                template.subject.clone(),
+                template.html_body.clone(),
+            )
+        })
+        .unwrap_or_else(|| {
+            let (subject, html_body) = key.builtin();
+            (
+                DEFAULT_LOCALE.to_string(),
+                subject.to_string(),
+                html_body.to_string(),
+            )
+        });
+
+    Ok(RenderedEmail {
+        template_key: key.as_str().to_string(),
+        locale,
+        subject: substitute(&subject, vars, false),
+        html_body: substitute(&html_body, vars, true),
+    })
+}
+
+// addresses are stored the way normalize_email leaves them
+pub fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+// suppression lists are per marketplace, the tenant is the one the recipient belongs to
+pub async fn is_suppressed(
+    db: &DatabaseConnection,
+    tenant: TenantId,
+    email: &str,
+) -> Result<bool, AppError> {
+    Ok(
+        EmailSuppressions::find_by_id((tenant.0, normalize_email(email)))
+            .one(db)
+            .await?
+            .is_some(),
+    )
+}
+
+pub async fn send_templated(
+    db: &DatabaseConnection,
+    tenant: TenantId,
+    key: TemplateKey,
+    to: String,
+    locale: Option<&str>,
+    vars: &[(&str, String)],
+) -> Result<(), AppError> {
+    if !key.bypasses_suppression() && is_suppressed(db, tenant, &to).await? {
+        eprintln!("Not sending {} to suppressed address", key.as_str());
+        return Ok(());
+    }
+    let email = render_template(db, tenant, key, locale, vars).await?;
+    send_mail(key.sender_name(), to, &email.subject, email.html_body).await
+}
@@ -0,0 +1,266 @@
+use crate::{
+    broker::Broker,
+    error::AppError,
+    models::orders::OrderStatusChanged,
+    pubsub::{publish, spawn_subscriber},
+};
+use sea_orm::prelude::DateTimeWithTimeZone;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+const STATUS_CHANNEL: &str = "order_status_changed";
+// how long a change that arrived ahead of its predecessor waits for it before going out anyway
+const GAP_WAIT: Duration = Duration::from_secs(2);
+// orders without changes for this long are forgotten, a later change starts them over
+const SEQUENCE_RETENTION: Duration = Duration::from_secs(600);
+
+// what goes over Redis, the subscription itself is served from each instance's own broker
+#[derive(Serialize, Deserialize)]
+struct StatusChangeMessage {
+    order_id: i32,
+    previous_status: Option<String>,
+    status: String,
+    changed_at: DateTimeWithTimeZone,
+    // missing from instances that predate sequencing, those changes are relayed as they come
+    #[serde(default)]
+    sequence: Option<i32>,
+    #[serde(default)]
+    previous_sequence: Option<i32>,
+}
+
+// tells every instance, this one included, once the change is committed
+pub async fn publish_status_change(
+    redis: &redis::Client,
+    event: &OrderStatusChanged,
+) -> Result<(), AppError> {
+    let message = serde_json::to_string(&StatusChangeMessage {
+        order_id: event.order_id.into(),
+        previous_status: event.previous_status.clone(),
+        status: event.status.clone(),
+        changed_at: event.changed_at,
+        sequence: Some(event.sequence),
+        previous_sequence: event.previous_sequence,
+    })
+    .map_err(|e| AppError::Internal(format!("Failed to encode status change: {}", e)))?;
+    publish(redis, STATUS_CHANNEL, &message).await
+}
+
+struct OrderSequence {
+    last_relayed: i32,
+    // changes waiting for the one before them, by sequence, with when they arrived
+    held: BTreeMap<i32, (OrderStatusChanged, Instant)>,
+    touched: Instant,
+}
+
+// Redis can hand a change over twice (a publish retried after it went through) and changes
+// published by different instances can overtake each other. Changes are relayed once each, in
+// the order of the status history: repeats and changes older than one already relayed are
+// dropped, a change whose predecessor hasn't arrived yet is held for up to GAP_WAIT
+#[derive(Default)]
+struct StatusSequencer {
+    orders: HashMap<i32, OrderSequence>,
+}
+
+impl StatusSequencer {
+    // the changes that can go out now, oldest first
+    fn accept(&mut self, change: OrderStatusChanged, now: Instant) -> Vec<OrderStatusChanged> {
+        let order_id: i32 = change.order_id.into();
+        let Some(order) = self.orders.get_mut(&order_id) else {
+            // the first change of the order this instance sees has nothing to wait for
+            self.orders.insert(
+                order_id,
+                OrderSequence {
+                    last_relayed: change.sequence,
+                    held: BTreeMap::new(),
+                    touched: now,
+                },
+            );
+            return vec![change];
+        };
+        order.touched = now;
+
+        if change.sequence <= order.last_relayed || order.held.contains_key(&change.sequence) {
+            return Vec::new();
+        }
+        if change
+            .previous_sequence
+            .is_some_and(|previous| previous > order.last_relayed)
+        {
+            order.held.insert(change.sequence, (change, now));
+            return Vec::new();
+        }
+
+        order.last_relayed = change.sequence;
+        let mut ready = vec![change];
+        // whatever was waiting on this change can follow it
+        while let Some(entry) = order.held.first_entry() {
+            if entry
+                .get()
+                .0
+                .previous_sequence
+                .is_some_and(|previous| previous > order.last_relayed)
+            {
+                break;
+            }
+            let (change, _) = entry.remove();
+            order.last_relayed = change.sequence;
+            ready.push(change);
+        }
+        ready
+    }
+
+    // held changes whose predecessor never came, the gap is given up on and they go out in order
+    fn expire(&mut self, now: Instant) -> Vec<OrderStatusChanged> {
+        let mut ready = Vec::new();
+        for order in self.orders.values_mut() {
+            let waited_out = order
+                .held
+                .values()
+                .next()
+                .is_some_and(|(_, arrived)| now.duration_since(*arrived) >= GAP_WAIT);
+            if waited_out {
+                for (_, (change, _)) in std::mem::take(&mut order.held) {
+                    order.last_relayed = change.sequence;
+                    ready.push(change);
+                }
+            }
+        }
+        self.orders.retain(|_, order| {
+            !order.held.is_empty() || now.duration_since(order.touched) < SEQUENCE_RETENTION
+        });
+        ready
+    }
+}
+
+// feeds status changes from any instance to the subscribers connected to this one, each change
+// once and in order
+pub fn spawn_status_relay(redis: redis::Client, broker: Broker<OrderStatusChanged>) {
+    let sequencer = Arc::new(Mutex::new(StatusSequencer::default()));
+
+    let (expiring, expiry_broker) = (sequencer.clone(), broker.clone());
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(GAP_WAIT / 4);
+        loop {
+            interval.tick().await;
+            let ready = expiring.lock().unwrap().expire(Instant::now());
+            for change in ready {
+                expiry_broker.publish(change);
+            }
+        }
+    });
+
+    spawn_subscriber(redis, STATUS_CHANNEL, move |payload| {
+        match serde_json::from_str::<StatusChangeMessage>(&payload) {
+            Ok(message) => {
+                let change = OrderStatusChanged {
+                    order_id: message.order_id.into(),
+                    previous_status: message.previous_status,
+                    status: message.status,
+                    changed_at: message.changed_at,
+                    sequence: message.sequence.unwrap_or_default(),
+                    previous_sequence: message.previous_sequence,
+                };
+                let ready = match message.sequence {
+                    Some(_) => sequencer.lock().unwrap().accept(change, Instant::now()),
+                    None => vec![change],
+                };
+                for change in ready {
+                    broker.publish(change);
+                }
+            }
+            Err(e) => eprintln!("Dropped malformed status change: {}", e),
+        }
+        async {}
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(order_id: i32, sequence: i32, previous_sequence: Option<i32>) -> OrderStatusChanged {
+        OrderStatusChanged {
+            order_id: order_id.into(),
+            previous_status: None,
+            status: format!("STATUS_{}", sequence),
+            changed_at: chrono::Utc::now().fixed_offset(),
+            sequence,
+            previous_sequence,
+        }
+    }
+
+    fn sequences(changes: &[OrderStatusChanged]) -> Vec<i32> {
+        changes.iter().map(|change| change.sequence).collect()
+    }
+
+    #[test]
+    fn relays_each_change_once() {
+        let mut sequencer = StatusSequencer::default();
+        let now = Instant::now();
+
+        assert_eq!(sequences(&sequencer.accept(change(1, 10, None), now)), [10]);
+        assert!(sequencer.accept(change(1, 10, None), now).is_empty());
+        assert_eq!(
+            sequences(&sequencer.accept(change(1, 11, Some(10)), now)),
+            [11]
+        );
+        assert!(sequencer.accept(change(1, 11, Some(10)), now).is_empty());
+        // older than what already went out
+        assert!(sequencer.accept(change(1, 9, None), now).is_empty());
+    }
+
+    #[test]
+    fn holds_a_change_until_its_predecessor_arrives() {
+        let mut sequencer = StatusSequencer::default();
+        let now = Instant::now();
+
+        sequencer.accept(change(1, 10, None), now);
+        assert!(sequencer.accept(change(1, 13, Some(12)), now).is_empty());
+        assert!(sequencer.accept(change(1, 12, Some(11)), now).is_empty());
+        // a repeat of a held change stays held once
+        assert!(sequencer.accept(change(1, 13, Some(12)), now).is_empty());
+        assert_eq!(
+            sequences(&sequencer.accept(change(1, 11, Some(10)), now)),
+            [11, 12, 13]
+        );
+        // orders are sequenced apart from each other
+        assert_eq!(
+            sequences(&sequencer.accept(change(2, 5, Some(4)), now)),
+            [5]
+        );
+    }
+
+    #[test]
+    fn gives_up_on_a_gap_after_waiting() {
+        let mut sequencer = StatusSequencer::default();
+        let now = Instant::now();
+
+        sequencer.accept(change(1, 10, None), now);
+        sequencer.accept(change(1, 13, Some(12)), now);
+        sequencer.accept(change(1, 12, Some(11)), now);
+        assert!(sequencer.expire(now + GAP_WAIT / 2).is_empty());
+        assert_eq!(sequences(&sequencer.expire(now + GAP_WAIT)), [12, 13]);
+        // the change that never came is too old once it turns up
+        assert!(sequencer
+            .accept(change(1, 11, Some(10)), now + GAP_WAIT)
+            .is_empty());
+    }
+
+    #[test]
+    fn forgets_quiet_orders() {
+        let mut sequencer = StatusSequencer::default();
+        let now = Instant::now();
+
+        sequencer.accept(change(1, 10, None), now);
+        sequencer.expire(now + SEQUENCE_RETENTION);
+        // a change after the order was forgotten starts it over
+        assert_eq!(
+            sequences(&sequencer.accept(change(1, 9, None), now + SEQUENCE_RETENTION)),
+            [9]
+        );
+    }
+}
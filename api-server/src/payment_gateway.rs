@@ -0,0 +1,694 @@
+use crate::{
+    error::{AppError, AuthErrorCode},
+    money::currency_rule,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
+use hmac::{Hmac, Mac};
+use sea_orm::prelude::Decimal;
+use serde_json::Value;
+use sha2::Sha256;
+
+// webhooks signed longer ago than this are rejected as replays
+const WEBHOOK_TOLERANCE_SECONDS: i64 = 300;
+
+// what the provider tells us about a stored token, the card itself stays with the provider
+pub struct TokenizedMethod {
+    pub brand: Option<String>,
+    pub last4: Option<String>,
+    pub expiration: Option<NaiveDate>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ChargeStatus {
+    Succeeded,
+    RequiresAction,
+    Processing,
+    Failed,
+}
+
+impl ChargeStatus {
+    // stored as the bill's payment_status
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChargeStatus::Succeeded => "PAID",
+            ChargeStatus::RequiresAction => "REQUIRES_ACTION",
+            ChargeStatus::Processing => "PROCESSING",
+            ChargeStatus::Failed => "FAILED",
+        }
+    }
+}
+
+impl ChargeStatus {
+    fn from_stripe(status: Option<&str>) -> Self {
+        match status {
+            Some("succeeded") => ChargeStatus::Succeeded,
+            Some("requires_action") | Some("requires_confirmation") => ChargeStatus::RequiresAction,
+            Some("processing") => ChargeStatus::Processing,
+            _ => ChargeStatus::Failed,
+        }
+    }
+}
+
+pub struct Charge {
+    pub provider_payment_id: Option<String>,
+    pub status: ChargeStatus,
+    // handed to the client SDK when the provider needs the customer to confirm (3-D Secure)
+    pub client_secret: Option<String>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum DisputeStatus {
+    Open,
+    UnderReview,
+    Won,
+    Lost,
+}
+
+impl DisputeStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DisputeStatus::Open => "OPEN",
+            DisputeStatus::UnderReview => "UNDER_REVIEW",
+            DisputeStatus::Won => "WON",
+            DisputeStatus::Lost => "LOST",
+        }
+    }
+
+    // payment_status the disputed bill is moved to
+    pub fn bill_status(&self) -> &'static str {
+        match self {
+            DisputeStatus::Open | DisputeStatus::UnderReview => "DISPUTED",
+            DisputeStatus::Won => "PAID",
+            DisputeStatus::Lost => "CHARGED_BACK",
+        }
+    }
+}
+
+// bill statuses meaning the money was taken. A late processing or failure event doesn't move
+// them back, and only a dispute's outcome moves a disputed bill
+pub const SETTLED_PAYMENT_STATUSES: [&str; 3] = ["PAID", "DISPUTED", "CHARGED_BACK"];
+
+// envelope of a verified webhook, the payload itself is kept for the worker
+pub struct WebhookEvent {
+    pub id: String,
+    pub event_type: String,
+}
+
+pub struct ProviderDispute {
+    pub id: String,
+    pub payment_id: String,
+    pub amount: Decimal,
+    pub reason: Option<String>,
+    pub status: DisputeStatus,
+}
+
+pub struct ProviderPayment {
+    pub provider_payment_id: String,
+    pub amount: Decimal,
+    pub status: ChargeStatus,
+    // the idempotency key the charge was asked for under, payment events carry it in metadata
+    pub idempotency_key: Option<String>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum RefundStatus {
+    Pending,
+    Succeeded,
+    Failed,
+}
+
+impl RefundStatus {
+    // stored as the refund's status
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RefundStatus::Pending => "PENDING",
+            RefundStatus::Succeeded => "SUCCEEDED",
+            RefundStatus::Failed => "FAILED",
+        }
+    }
+
+    fn from_stripe(status: Option<&str>) -> Self {
+        match status {
+            Some("succeeded") => RefundStatus::Succeeded,
+            Some("failed") | Some("canceled") => RefundStatus::Failed,
+            _ => RefundStatus::Pending,
+        }
+    }
+}
+
+pub struct ProviderRefund {
+    // None when the provider declined the refund outright
+    pub provider_refund_id: Option<String>,
+    pub status: RefundStatus,
+    // the idempotency key the refund was requested under, refund events carry it in metadata
+    pub idempotency_key: Option<String>,
+}
+
+#[async_trait]
+pub trait PaymentGateway: Send + Sync {
+    fn provider(&self) -> &'static str;
+
+    // token was created client side with the provider's SDK
+    async fn describe_method(&self, token: &str) -> Result<TokenizedMethod, AppError>;
+
+    // a saved token has to belong to a provider customer to be charged more than once, the
+    // customer is created on the first method saved. Returns the provider customer id
+    async fn attach_method(
+        &self,
+        token: &str,
+        provider_customer_id: Option<&str>,
+    ) -> Result<String, AppError>;
+
+    // customer is the provider customer a saved method is attached to, None for one-off tokens.
+    // A declined charge comes back FAILED, Err means the outcome is unknown and the payment is
+    // matched to its order by the idempotency key once the provider reports it
+    async fn charge(
+        &self,
+        token: &str,
+        customer: Option<&str>,
+        amount: Decimal,
+        currency: &str,
+        idempotency_key: &str,
+    ) -> Result<Charge, AppError>;
+
+    // left unconfirmed for the client to confirm with the provider's SDK, the outcome arrives by
+    // webhook
+    async fn create_payment_intent(
+        &self,
+        amount: Decimal,
+        currency: &str,
+        order_id: i32,
+        idempotency_key: &str,
+    ) -> Result<Charge, AppError>;
+
+    // request header carrying the webhook signature
+    fn signature_header(&self) -> &'static str;
+
+    fn verify_webhook(&self, payload: &[u8], signature: &str) -> Result<WebhookEvent, AppError>;
+
+    // None for events that aren't about disputes
+    fn dispute_from_event(&self, payload: &str) -> Result<Option<ProviderDispute>, AppError>;
+
+    // None for events that aren't about a payment's outcome
+    fn payment_from_event(&self, payload: &str) -> Result<Option<ProviderPayment>, AppError>;
+
+    // None for events that aren't about a refund
+    fn refund_from_event(&self, payload: &str) -> Result<Option<ProviderRefund>, AppError>;
+
+    // pays part or all of a settled payment back, pending refunds are settled by webhook. A
+    // declined refund comes back FAILED, Err means the outcome is unknown and the refund can be
+    // asked for again under the same idempotency key
+    async fn refund(
+        &self,
+        provider_payment_id: &str,
+        amount: Decimal,
+        currency: &str,
+        idempotency_key: &str,
+    ) -> Result<ProviderRefund, AppError>;
+
+    async fn list_payments(&self, since: DateTime<Utc>) -> Result<Vec<ProviderPayment>, AppError>;
+}
+
+pub struct StripeGateway {
+    client: reqwest::Client,
+    secret_key: String,
+    webhook_secret: String,
+}
+
+impl StripeGateway {
+    // both secrets are required, an empty webhook secret would accept signatures anyone can forge
+    pub fn from_env() -> Result<Self, AppError> {
+        let required = |key: &str| {
+            std::env::var(key)
+                .ok()
+                .filter(|value| !value.is_empty())
+                .ok_or_else(|| AppError::Internal(format!("{} must be set", key)))
+        };
+        Ok(Self {
+            client: reqwest::Client::new(),
+            secret_key: required("STRIPE_SECRET_KEY")?,
+            webhook_secret: required("STRIPE_WEBHOOK_SECRET")?,
+        })
+    }
+
+    // Stripe counts in the currency's minor unit, yen have none
+    fn minor_units(amount: Decimal, currency: &str) -> Result<i64, AppError> {
+        let decimals = currency_rule(currency).decimals;
+        i64::try_from((amount * Decimal::from(10i64.pow(decimals))).round())
+            .map_err(|e| AppError::Internal(format!("Invalid charge amount: {}", e)))
+    }
+
+    // amounts Stripe sends come with the lowercase code of the currency they're in
+    fn decimal_amount(amount: i64, currency: &str) -> Decimal {
+        Decimal::new(amount, currency_rule(currency).decimals)
+    }
+}
+
+#[async_trait]
+impl PaymentGateway for StripeGateway {
+    fn provider(&self) -> &'static str {
+        "stripe"
+    }
+
+    async fn describe_method(&self, token: &str) -> Result<TokenizedMethod, AppError> {
+        let response: serde_json::Value = self
+            .client
+            .get(format!(
+                "https://api.stripe.com/v1/payment_methods/{}",
+                token
+            ))
+            .bearer_auth(&self.secret_key)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| AppError::Internal(format!("Payment method lookup failed: {}", e)))?
+            .json()
+            .await
+            .map_err(|e| AppError::Internal(format!("Payment method lookup failed: {}", e)))?;
+
+        let card = &response["card"];
+        Ok(TokenizedMethod {
+            brand: card["brand"].as_str().map(String::from),
+            last4: card["last4"].as_str().map(String::from),
+            expiration: match (card["exp_year"].as_i64(), card["exp_month"].as_u64()) {
+                (Some(year), Some(month)) => NaiveDate::from_ymd_opt(year as i32, month as u32, 1),
+                _ => None,
+            },
+        })
+    }
+
+    async fn attach_method(
+        &self,
+        token: &str,
+        provider_customer_id: Option<&str>,
+    ) -> Result<String, AppError> {
+        let customer = match provider_customer_id {
+            Some(customer) => customer.to_string(),
+            None => {
+                let response: Value = self
+                    .client
+                    .post("https://api.stripe.com/v1/customers")
+                    .bearer_auth(&self.secret_key)
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status())
+                    .map_err(|e| AppError::Internal(format!("Creating customer failed: {}", e)))?
+                    .json()
+                    .await
+                    .map_err(|e| AppError::Internal(format!("Creating customer failed: {}", e)))?;
+                response["id"]
+                    .as_str()
+                    .map(String::from)
+                    .ok_or_else(|| AppError::Internal("Customer response has no id".to_string()))?
+            }
+        };
+
+        self.client
+            .post(format!(
+                "https://api.stripe.com/v1/payment_methods/{}/attach",
+                token
+            ))
+            .bearer_auth(&self.secret_key)
+            .form(&[("customer", customer.as_str())])
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| AppError::Internal(format!("Attaching payment method failed: {}", e)))?;
+
+        Ok(customer)
+    }
+
+    async fn charge(
+        &self,
+        token: &str,
+        customer: Option<&str>,
+        amount: Decimal,
+        currency: &str,
+        idempotency_key: &str,
+    ) -> Result<Charge, AppError> {
+        let minor_units = Self::minor_units(amount, currency)?;
+        let mut form = vec![
+            ("amount", minor_units.to_string()),
+            ("currency", currency.to_lowercase()),
+            ("payment_method", token.to_string()),
+            ("confirm", "true".to_string()),
+            ("automatic_payment_methods[enabled]", "true".to_string()),
+            (
+                "automatic_payment_methods[allow_redirects]",
+                "never".to_string(),
+            ),
+        ];
+        if let Some(customer) = customer {
+            form.push(("customer", customer.to_string()));
+        }
+        // a charge whose answer got lost is matched to its order when the provider's event arrives
+        form.push(("metadata[idempotency_key]", idempotency_key.to_string()));
+
+        // declines come back as 402 with the payment intent inside the error, so the body is read either way
+        let response = self
+            .client
+            .post("https://api.stripe.com/v1/payment_intents")
+            .bearer_auth(&self.secret_key)
+            .header("Idempotency-Key", idempotency_key)
+            .form(&form)
+            .send()
+            .await
+            .map_err(|e| AppError::Internal(format!("Charge failed: {}", e)))?;
+        let status = response.status();
+        let response: Value = response
+            .json()
+            .await
+            .map_err(|e| AppError::Internal(format!("Charge failed: {}", e)))?;
+
+        let intent = match response.get("error") {
+            Some(error) if error["payment_intent"].is_object() => &error["payment_intent"],
+            // rejected before a payment intent was made, 4xx other than rate limits and
+            // idempotency conflicts means nothing was charged
+            Some(_)
+                if status.is_client_error()
+                    && status != reqwest::StatusCode::TOO_MANY_REQUESTS
+                    && status != reqwest::StatusCode::CONFLICT =>
+            {
+                return Ok(Charge {
+                    provider_payment_id: None,
+                    status: ChargeStatus::Failed,
+                    client_secret: None,
+                });
+            }
+            Some(error) => {
+                return Err(AppError::Internal(format!(
+                    "Charge failed: {}",
+                    error["message"]
+                        .as_str()
+                        .unwrap_or("unknown provider error")
+                )));
+            }
+            None => &response,
+        };
+
+        Ok(Charge {
+            provider_payment_id: intent["id"].as_str().map(String::from),
+            status: ChargeStatus::from_stripe(intent["status"].as_str()),
+            client_secret: intent["client_secret"].as_str().map(String::from),
+        })
+    }
+
+    async fn create_payment_intent(
+        &self,
+        amount: Decimal,
+        currency: &str,
+        order_id: i32,
+        idempotency_key: &str,
+    ) -> Result<Charge, AppError> {
+        let response: serde_json::Value = self
+            .client
+            .post("https://api.stripe.com/v1/payment_intents")
+            .bearer_auth(&self.secret_key)
+            .header("Idempotency-Key", idempotency_key)
+            .form(&[
+                ("amount", Self::minor_units(amount, currency)?.to_string()),
+                ("currency", currency.to_lowercase()),
+                ("metadata[order_id]", order_id.to_string()),
+                ("automatic_payment_methods[enabled]", "true".to_string()),
+            ])
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| AppError::Internal(format!("Creating payment intent failed: {}", e)))?
+            .json()
+            .await
+            .map_err(|e| AppError::Internal(format!("Creating payment intent failed: {}", e)))?;
+
+        Ok(Charge {
+            provider_payment_id: response["id"].as_str().map(String::from),
+            // requires_payment_method until the client confirms it
+            status: ChargeStatus::RequiresAction,
+            client_secret: response["client_secret"].as_str().map(String::from),
+        })
+    }
+
+    fn signature_header(&self) -> &'static str {
+        "stripe-signature"
+    }
+
+    // Stripe-Signature is "t=<unix time>,v1=<hex hmac of '<t>.<payload>'>", v1 may repeat while secrets roll
+    fn verify_webhook(&self, payload: &[u8], signature: &str) -> Result<WebhookEvent, AppError> {
+        let invalid = || AppError::Auth {
+            message: "Invalid webhook signature".to_string(),
+            code: AuthErrorCode::InvalidCredentials,
+            user_id: None,
+        };
+
+        let mut timestamp = None;
+        let mut signatures = Vec::new();
+        for part in signature.split(',') {
+            match part.split_once('=') {
+                Some(("t", value)) => timestamp = value.parse::<i64>().ok(),
+                Some(("v1", value)) => signatures.push(decode_hex(value).ok_or_else(invalid)?),
+                _ => {}
+            }
+        }
+        let timestamp = timestamp.ok_or_else(invalid)?;
+        if (Utc::now().timestamp() - timestamp).abs() > WEBHOOK_TOLERANCE_SECONDS {
+            return Err(invalid());
+        }
+
+        let verified = signatures.iter().any(|expected| {
+            let mut mac = Hmac::<Sha256>::new_from_slice(self.webhook_secret.as_bytes())
+                .expect("HMAC accepts keys of any length");
+            mac.update(timestamp.to_string().as_bytes());
+            mac.update(b".");
+            mac.update(payload);
+            mac.verify_slice(expected).is_ok()
+        });
+        if !verified {
+            return Err(invalid());
+        }
+
+        let event: Value = serde_json::from_slice(payload)
+            .map_err(|e| AppError::Internal(format!("Malformed webhook payload: {}", e)))?;
+        match (event["id"].as_str(), event["type"].as_str()) {
+            (Some(id), Some(event_type)) => Ok(WebhookEvent {
+                id: id.to_string(),
+                event_type: event_type.to_string(),
+            }),
+            _ => Err(AppError::Internal(
+                "Webhook payload has no event id or type".to_string(),
+            )),
+        }
+    }
+
+    fn dispute_from_event(&self, payload: &str) -> Result<Option<ProviderDispute>, AppError> {
+        let event: Value = serde_json::from_str(payload)
+            .map_err(|e| AppError::Internal(format!("Malformed webhook payload: {}", e)))?;
+        if !event["type"]
+            .as_str()
+            .is_some_and(|event_type| event_type.starts_with("charge.dispute."))
+        {
+            return Ok(None);
+        }
+
+        let dispute = &event["data"]["object"];
+        let (Some(id), Some(payment_id), Some(amount), Some(currency)) = (
+            dispute["id"].as_str(),
+            dispute["payment_intent"].as_str(),
+            dispute["amount"].as_i64(),
+            dispute["currency"].as_str(),
+        ) else {
+            return Err(AppError::Internal(
+                "Dispute event is missing its id, payment, amount or currency".to_string(),
+            ));
+        };
+
+        Ok(Some(ProviderDispute {
+            id: id.to_string(),
+            payment_id: payment_id.to_string(),
+            amount: Self::decimal_amount(amount, currency),
+            reason: dispute["reason"].as_str().map(String::from),
+            status: match dispute["status"].as_str() {
+                Some("won") | Some("warning_closed") => DisputeStatus::Won,
+                Some("lost") => DisputeStatus::Lost,
+                Some("under_review") | Some("warning_under_review") => DisputeStatus::UnderReview,
+                _ => DisputeStatus::Open,
+            },
+        }))
+    }
+
+    fn payment_from_event(&self, payload: &str) -> Result<Option<ProviderPayment>, AppError> {
+        let event: Value = serde_json::from_str(payload)
+            .map_err(|e| AppError::Internal(format!("Malformed webhook payload: {}", e)))?;
+        if !matches!(
+            event["type"].as_str(),
+            Some("payment_intent.succeeded")
+                | Some("payment_intent.processing")
+                | Some("payment_intent.payment_failed")
+        ) {
+            return Ok(None);
+        }
+
+        let intent = &event["data"]["object"];
+        let (Some(id), Some(amount), Some(currency)) = (
+            intent["id"].as_str(),
+            intent["amount"].as_i64(),
+            intent["currency"].as_str(),
+        ) else {
+            return Err(AppError::Internal(
+                "Payment event is missing its id, amount or currency".to_string(),
+            ));
+        };
+
+        Ok(Some(ProviderPayment {
+            provider_payment_id: id.to_string(),
+            amount: Self::decimal_amount(amount, currency),
+            status: ChargeStatus::from_stripe(intent["status"].as_str()),
+            idempotency_key: intent["metadata"]["idempotency_key"]
+                .as_str()
+                .map(String::from),
+        }))
+    }
+
+    fn refund_from_event(&self, payload: &str) -> Result<Option<ProviderRefund>, AppError> {
+        let event: Value = serde_json::from_str(payload)
+            .map_err(|e| AppError::Internal(format!("Malformed webhook payload: {}", e)))?;
+        if !matches!(
+            event["type"].as_str(),
+            Some("refund.created") | Some("refund.updated") | Some("refund.failed")
+        ) {
+            return Ok(None);
+        }
+
+        let refund = &event["data"]["object"];
+        let Some(id) = refund["id"].as_str() else {
+            return Err(AppError::Internal(
+                "Refund event is missing its id".to_string(),
+            ));
+        };
+
+        Ok(Some(ProviderRefund {
+            provider_refund_id: Some(id.to_string()),
+            status: RefundStatus::from_stripe(refund["status"].as_str()),
+            idempotency_key: refund["metadata"]["idempotency_key"]
+                .as_str()
+                .map(String::from),
+        }))
+    }
+
+    async fn refund(
+        &self,
+        provider_payment_id: &str,
+        amount: Decimal,
+        currency: &str,
+        idempotency_key: &str,
+    ) -> Result<ProviderRefund, AppError> {
+        // the key goes along as metadata too, a refund whose answer got lost is matched to its
+        // row when the provider's event arrives
+        let response = self
+            .client
+            .post("https://api.stripe.com/v1/refunds")
+            .bearer_auth(&self.secret_key)
+            .header("Idempotency-Key", idempotency_key)
+            .form(&[
+                ("payment_intent", provider_payment_id.to_string()),
+                ("amount", Self::minor_units(amount, currency)?.to_string()),
+                ("metadata[idempotency_key]", idempotency_key.to_string()),
+            ])
+            .send()
+            .await
+            .map_err(|e| AppError::Internal(format!("Refund failed: {}", e)))?;
+        // 4xx other than rate limits and idempotency conflicts is a decline, asking again gets
+        // the same answer
+        let status = response.status();
+        if status.is_client_error()
+            && status != reqwest::StatusCode::TOO_MANY_REQUESTS
+            && status != reqwest::StatusCode::CONFLICT
+        {
+            return Ok(ProviderRefund {
+                provider_refund_id: None,
+                status: RefundStatus::Failed,
+                idempotency_key: Some(idempotency_key.to_string()),
+            });
+        }
+        let response: Value = response
+            .error_for_status()
+            .map_err(|e| AppError::Internal(format!("Refund failed: {}", e)))?
+            .json()
+            .await
+            .map_err(|e| AppError::Internal(format!("Refund failed: {}", e)))?;
+
+        let Some(id) = response["id"].as_str() else {
+            return Err(AppError::Internal("Refund response has no id".to_string()));
+        };
+        Ok(ProviderRefund {
+            provider_refund_id: Some(id.to_string()),
+            status: RefundStatus::from_stripe(response["status"].as_str()),
+            idempotency_key: Some(idempotency_key.to_string()),
+        })
+    }
+
+    async fn list_payments(&self, since: DateTime<Utc>) -> Result<Vec<ProviderPayment>, AppError> {
+        let mut payments = Vec::new();
+        let mut starting_after: Option<String> = None;
+
+        loop {
+            let mut query = vec![
+                ("limit", "100".to_string()),
+                ("created[gte]", since.timestamp().to_string()),
+            ];
+            if let Some(cursor) = &starting_after {
+                query.push(("starting_after", cursor.clone()));
+            }
+
+            let page: Value = self
+                .client
+                .get("https://api.stripe.com/v1/payment_intents")
+                .bearer_auth(&self.secret_key)
+                .query(&query)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status())
+                .map_err(|e| AppError::Internal(format!("Payment listing failed: {}", e)))?
+                .json()
+                .await
+                .map_err(|e| AppError::Internal(format!("Payment listing failed: {}", e)))?;
+
+            let intents = page["data"].as_array().cloned().unwrap_or_default();
+            for intent in &intents {
+                if let (Some(id), Some(amount), Some(currency)) = (
+                    intent["id"].as_str(),
+                    intent["amount"].as_i64(),
+                    intent["currency"].as_str(),
+                ) {
+                    payments.push(ProviderPayment {
+                        provider_payment_id: id.to_string(),
+                        amount: Self::decimal_amount(amount, currency),
+                        status: ChargeStatus::from_stripe(intent["status"].as_str()),
+                        idempotency_key: intent["metadata"]["idempotency_key"]
+                            .as_str()
+                            .map(String::from),
+                    });
+                }
+            }
+
+            starting_after = intents
+                .last()
+                .and_then(|intent| intent["id"].as_str())
+                .map(String::from);
+            if !page["has_more"].as_bool().unwrap_or(false) || starting_after.is_none() {
+                break;
+            }
+        }
+
+        Ok(payments)
+    }
+}
+
+fn decode_hex(value: &str) -> Option<Vec<u8>> {
+    if !value.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..value.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(value.get(index..index + 2)?, 16).ok())
+        .collect()
+}
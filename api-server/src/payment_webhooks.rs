@@ -0,0 +1,446 @@
+use crate::{
+    entity::{
+        bills, disputes, order_items, orders, payment_events,
+        prelude::{Bills, Disputes, OrderItems, Orders, PaymentEvents, Products, Suppliers, Users},
+        products,
+        sea_orm_active_enums::UserRole,
+        suppliers, user_roles, users,
+    },
+    error::AppError,
+    ids::TenantId,
+    models::{
+        orders::{order_id_from_payment_key, record_status_change, void_unpaid_order},
+        refunds::{retry_unsent_refunds, settle_refund},
+    },
+    notifications::{send_templated, TemplateKey},
+    order_events::publish_status_change,
+    payment_gateway::{
+        ChargeStatus, DisputeStatus, PaymentGateway, ProviderPayment, SETTLED_PAYMENT_STATUSES,
+    },
+};
+use axum::{body::Bytes, http::HeaderMap, http::StatusCode, Extension};
+use chrono::{Duration, Utc};
+use sea_orm::{
+    sea_query::{OnConflict, Query},
+    ActiveModelTrait,
+    ActiveValue::Set,
+    ColumnTrait, Condition, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, QuerySelect,
+    TransactionTrait,
+};
+use std::{collections::HashMap, sync::Arc};
+
+// events still failing after this many attempts are left for an admin to look at
+const MAX_EVENT_ATTEMPTS: i32 = 5;
+const EVENT_BATCH_SIZE: u64 = 50;
+const EVENT_POLL_SECONDS: u64 = 15;
+// a charge with an unknown outcome is looked up once its event had this long to arrive, and
+// voided when the provider still knows nothing of it after the longer wait
+const UNKNOWN_CHARGE_GRACE_MINUTES: i64 = 10;
+const UNKNOWN_CHARGE_VOID_MINUTES: i64 = 60;
+
+// POST /webhooks/payments and /webhooks/stripe, events are only stored here and handled by the worker below
+pub async fn receive_payment_webhook(
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(gateway): Extension<Arc<dyn PaymentGateway>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> StatusCode {
+    let Some(signature) = headers
+        .get(gateway.signature_header())
+        .and_then(|value| value.to_str().ok())
+    else {
+        return StatusCode::BAD_REQUEST;
+    };
+
+    let event = match gateway.verify_webhook(&body, signature) {
+        Ok(event) => event,
+        Err(_) => return StatusCode::BAD_REQUEST,
+    };
+
+    // providers deliver at least once, redeliveries hit the unique key and are acknowledged again
+    let stored = PaymentEvents::insert(payment_events::ActiveModel {
+        provider: Set(gateway.provider().to_string()),
+        provider_event_id: Set(event.id),
+        event_type: Set(event.event_type),
+        payload: Set(String::from_utf8_lossy(&body).into_owned()),
+        ..Default::default()
+    })
+    .on_conflict(
+        OnConflict::columns([
+            payment_events::Column::Provider,
+            payment_events::Column::ProviderEventId,
+        ])
+        .do_nothing()
+        .to_owned(),
+    )
+    .exec_without_returning(&db)
+    .await;
+
+    match stored {
+        Ok(_) => StatusCode::OK,
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+pub fn spawn_payment_event_worker(
+    db: DatabaseConnection,
+    gateway: Arc<dyn PaymentGateway>,
+    redis: redis::Client,
+) {
+    tokio::spawn(async move {
+        let mut interval =
+            tokio::time::interval(std::time::Duration::from_secs(EVENT_POLL_SECONDS));
+        loop {
+            interval.tick().await;
+            if let Err(e) = process_pending_events(&db, gateway.as_ref(), &redis).await {
+                eprintln!("Payment event processing failed: {}", e);
+            }
+            if let Err(e) = retry_unsent_refunds(&db, gateway.as_ref()).await {
+                eprintln!("Retrying unsent refunds failed: {}", e);
+            }
+            if let Err(e) = reconcile_unknown_charges(&db, gateway.as_ref(), &redis).await {
+                eprintln!("Reconciling unknown charges failed: {}", e);
+            }
+        }
+    });
+}
+
+async fn process_pending_events(
+    db: &DatabaseConnection,
+    gateway: &dyn PaymentGateway,
+    redis: &redis::Client,
+) -> Result<(), AppError> {
+    let pending = PaymentEvents::find()
+        .filter(payment_events::Column::ProcessedAt.is_null())
+        .filter(payment_events::Column::Attempts.lt(MAX_EVENT_ATTEMPTS))
+        .order_by_asc(payment_events::Column::ReceivedAt)
+        .limit(EVENT_BATCH_SIZE)
+        .all(db)
+        .await?;
+
+    for event in pending {
+        let result = handle_event(db, gateway, redis, &event.payload).await;
+
+        let attempts = event.attempts + 1;
+        let mut event: payment_events::ActiveModel = event.into();
+        event.attempts = Set(attempts);
+        match result {
+            Ok(order_id) => {
+                event.processed_at = Set(Some(Utc::now().fixed_offset()));
+                event.last_error = Set(None);
+                event.order_id = Set(order_id);
+            }
+            Err(e) => event.last_error = Set(Some(e.to_string())),
+        }
+        event.update(db).await?;
+    }
+
+    Ok(())
+}
+
+// returns the order the event was about, so it shows up in the order's timeline
+async fn handle_event(
+    db: &DatabaseConnection,
+    gateway: &dyn PaymentGateway,
+    redis: &redis::Client,
+    payload: &str,
+) -> Result<Option<i32>, AppError> {
+    if let Some(payment) = gateway.payment_from_event(payload)? {
+        return settle_payment(db, redis, &payment).await;
+    }
+    if let Some(refund) = gateway.refund_from_event(payload)? {
+        return settle_refund(db, &refund).await;
+    }
+    handle_dispute(db, gateway, payload).await
+}
+
+// charges whose answer got lost, looked up with the provider by the order's key once their
+// event had time to arrive. One still unknown to the provider after an hour never went through
+// and its order is voided
+async fn reconcile_unknown_charges(
+    db: &DatabaseConnection,
+    gateway: &dyn PaymentGateway,
+    redis: &redis::Client,
+) -> Result<(), AppError> {
+    let now = Utc::now();
+    let unknown = Bills::find()
+        .filter(bills::Column::PaymentStatus.eq(ChargeStatus::Processing.as_str()))
+        .filter(bills::Column::ProviderPaymentId.is_null())
+        .filter(bills::Column::BillDate.lt(now - Duration::minutes(UNKNOWN_CHARGE_GRACE_MINUTES)))
+        .order_by_asc(bills::Column::BillDate)
+        .all(db)
+        .await?;
+    let Some(since) = unknown.first().and_then(|bill| bill.bill_date) else {
+        return Ok(());
+    };
+
+    let mut payments: HashMap<i32, ProviderPayment> = gateway
+        .list_payments(since.with_timezone(&Utc) - Duration::hours(1))
+        .await?
+        .into_iter()
+        .filter_map(|payment| {
+            let order_id = order_id_from_payment_key(payment.idempotency_key.as_deref()?)?;
+            Some((order_id, payment))
+        })
+        .collect();
+    for bill in unknown {
+        match payments.remove(&bill.order_id) {
+            Some(payment) if payment.status == ChargeStatus::Failed => {
+                void_unpaid_order(db, bill.order_id)
+                    .await
+                    .map_err(|e| AppError::Internal(e.message))?;
+            }
+            Some(payment) => {
+                settle_payment(db, redis, &payment).await?;
+            }
+            None if bill.bill_date.is_some_and(|date| {
+                date < now - Duration::minutes(UNKNOWN_CHARGE_VOID_MINUTES)
+            }) =>
+            {
+                void_unpaid_order(db, bill.order_id)
+                    .await
+                    .map_err(|e| AppError::Internal(e.message))?;
+            }
+            None => {}
+        }
+    }
+
+    Ok(())
+}
+
+// moves the bill to the payment's outcome, a pending order whose payment succeeded becomes PAID.
+// Returns the order of the bill
+pub async fn settle_payment(
+    db: &DatabaseConnection,
+    redis: &redis::Client,
+    payment: &ProviderPayment,
+) -> Result<Option<i32>, AppError> {
+    let txn = db.begin().await?;
+
+    // payments started outside this marketplace have no bill. Locked so a dispute or charge
+    // landing at the same time can't be overwritten
+    let mut bill = Bills::find()
+        .filter(bills::Column::ProviderPaymentId.eq(payment.provider_payment_id.as_str()))
+        .lock_exclusive()
+        .one(&txn)
+        .await?;
+    // a charge whose answer got lost has no payment id on its bill yet, found by its key instead
+    if let (None, Some(order_id)) = (
+        &bill,
+        payment
+            .idempotency_key
+            .as_deref()
+            .and_then(order_id_from_payment_key),
+    ) {
+        bill = Bills::find()
+            .filter(bills::Column::OrderId.eq(order_id))
+            .filter(bills::Column::ProviderPaymentId.is_null())
+            .lock_exclusive()
+            .one(&txn)
+            .await?;
+    }
+    let Some(bill) = bill else {
+        return Ok(None);
+    };
+    // a settled bill stays as it is, a disputed or charged back one isn't paid again by a late
+    // success either
+    if SETTLED_PAYMENT_STATUSES.contains(&bill.payment_status.as_str()) {
+        return Ok(Some(bill.order_id));
+    }
+
+    let order_id = bill.order_id;
+    let mut bill: bills::ActiveModel = bill.into();
+    bill.payment_status = Set(payment.status.as_str().to_string());
+    bill.provider_payment_id = Set(Some(payment.provider_payment_id.clone()));
+    bill.update(&txn).await?;
+
+    let change = match Orders::find_by_id(order_id).one(&txn).await? {
+        Some(order) if payment.status == ChargeStatus::Succeeded && order.status == "PENDING" => {
+            let mut order: orders::ActiveModel = order.into();
+            order.status = Set("PAID".to_string());
+            order.update(&txn).await?;
+            Some(record_status_change(&txn, order_id, Some("PENDING"), "PAID", None).await?)
+        }
+        _ => None,
+    };
+
+    txn.commit().await?;
+
+    if let Some(change) = change {
+        if let Err(e) = publish_status_change(redis, &change).await {
+            eprintln!("Status change of order {} not published: {}", order_id, e);
+        }
+    }
+
+    Ok(Some(order_id))
+}
+
+// records the dispute against its order, moves the bill and holds the order's payouts until it is won
+async fn handle_dispute(
+    db: &DatabaseConnection,
+    gateway: &dyn PaymentGateway,
+    payload: &str,
+) -> Result<Option<i32>, AppError> {
+    let Some(dispute) = gateway.dispute_from_event(payload)? else {
+        return Ok(None);
+    };
+
+    let txn = db.begin().await?;
+
+    // locked like in settle_payment, so a settlement or refund landing at the same time can't
+    // overwrite the dispute's status
+    let bill = Bills::find()
+        .filter(bills::Column::ProviderPaymentId.eq(dispute.payment_id.as_str()))
+        .lock_exclusive()
+        .one(&txn)
+        .await?;
+
+    let existing = Disputes::find()
+        .filter(disputes::Column::Provider.eq(gateway.provider()))
+        .filter(disputes::Column::ProviderDisputeId.eq(dispute.id.as_str()))
+        .one(&txn)
+        .await?;
+    let is_new = existing.is_none();
+
+    let stored = match existing {
+        Some(existing) => {
+            let mut existing: disputes::ActiveModel = existing.into();
+            existing.amount = Set(dispute.amount);
+            existing.reason = Set(dispute.reason.clone());
+            existing.status = Set(dispute.status.as_str().to_string());
+            existing.update(&txn).await?
+        }
+        None => {
+            Disputes::insert(disputes::ActiveModel {
+                provider: Set(gateway.provider().to_string()),
+                provider_dispute_id: Set(dispute.id.clone()),
+                provider_payment_id: Set(dispute.payment_id.clone()),
+                order_id: Set(bill.as_ref().map(|bill| bill.order_id)),
+                amount: Set(dispute.amount),
+                reason: Set(dispute.reason.clone()),
+                status: Set(dispute.status.as_str().to_string()),
+                ..Default::default()
+            })
+            .exec_with_returning(&txn)
+            .await?
+        }
+    };
+
+    if let Some(bill) = bill {
+        let order_id = bill.order_id;
+        let mut bill: bills::ActiveModel = bill.into();
+        bill.payment_status = Set(dispute.status.bill_status().to_string());
+        bill.update(&txn).await?;
+
+        Orders::update_many()
+            .col_expr(
+                orders::Column::PayoutFrozen,
+                (dispute.status != DisputeStatus::Won).into(),
+            )
+            .filter(orders::Column::OrderId.eq(order_id))
+            .exec(&txn)
+            .await?;
+    }
+
+    txn.commit().await?;
+
+    // mail failures shouldn't replay the event, the dispute is already stored
+    if is_new {
+        if let Err(e) = notify_dispute(db, &stored).await {
+            eprintln!(
+                "Dispute notification failed for {}: {}",
+                stored.provider_dispute_id, e
+            );
+        }
+    }
+
+    Ok(stored.order_id)
+}
+
+// the order's marketplace admins and every supplier with a product in the disputed order. A
+// dispute on a payment without an order has no marketplace to tell, it is listed for admins only
+async fn notify_dispute(
+    db: &DatabaseConnection,
+    dispute: &disputes::Model,
+) -> Result<(), AppError> {
+    let Some(order_id) = dispute.order_id else {
+        return Ok(());
+    };
+    let Some(order) = Orders::find_by_id(order_id).one(db).await? else {
+        return Ok(());
+    };
+
+    // admins by sign up and admins through an added role alike
+    let mut recipients = Users::find()
+        .filter(users::Column::TenantId.eq(order.tenant_id))
+        .filter(
+            Condition::any()
+                .add(users::Column::Role.eq(UserRole::Admin))
+                .add(
+                    users::Column::UserId.in_subquery(
+                        Query::select()
+                            .column(user_roles::Column::UserId)
+                            .from(user_roles::Entity)
+                            .and_where(user_roles::Column::Role.eq(UserRole::Admin))
+                            .to_owned(),
+                    ),
+                ),
+        )
+        .all(db)
+        .await?;
+
+    let product_ids: Vec<i32> = OrderItems::find()
+        .filter(order_items::Column::OrderId.eq(order.order_id))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|item| item.product_id)
+        .collect();
+    let supplier_ids: Vec<i32> = Products::find()
+        .filter(products::Column::ProductId.is_in(product_ids))
+        .all(db)
+        .await?
+        .into_iter()
+        .filter_map(|product| product.supplier_id)
+        .collect();
+    let supplier_user_ids: Vec<i32> = Suppliers::find()
+        .filter(suppliers::Column::SupplierId.is_in(supplier_ids))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|supplier| supplier.user_id)
+        .collect();
+    recipients.extend(
+        Users::find()
+            .filter(users::Column::UserId.is_in(supplier_user_ids))
+            .all(db)
+            .await?,
+    );
+
+    // an admin who also supplies the order is told once
+    recipients.sort_by_key(|user| user.user_id);
+    recipients.dedup_by_key(|user| user.user_id);
+    for user in recipients {
+        send_templated(
+            db,
+            TenantId(user.tenant_id),
+            TemplateKey::DisputeOpened,
+            user.email,
+            user.locale.as_deref(),
+            &[
+                ("order_id", order.order_id.to_string()),
+                ("amount", dispute.amount.to_string()),
+                (
+                    "reason",
+                    dispute
+                        .reason
+                        .clone()
+                        .unwrap_or_else(|| "unknown".to_string()),
+                ),
+            ],
+        )
+        .await?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,120 @@
+// A4 in points
+const PAGE_WIDTH: usize = 595;
+const PAGE_HEIGHT: usize = 842;
+const MARGIN: usize = 50;
+const FONT_SIZE: usize = 10;
+const LEADING: usize = 14;
+const LINES_PER_PAGE: usize = (PAGE_HEIGHT - 2 * MARGIN) / LEADING;
+
+// plain text documents in the standard Helvetica font, all the printing paperwork needs without
+// pulling in a layout engine
+#[derive(Default)]
+pub struct TextPdf {
+    pages: Vec<Vec<String>>,
+}
+
+impl TextPdf {
+    // starts a new page, lines that don't fit continue on the pages after it
+    pub fn add_page(&mut self, lines: Vec<String>) {
+        if lines.is_empty() {
+            self.pages.push(Vec::new());
+            return;
+        }
+        self.pages
+            .extend(lines.chunks(LINES_PER_PAGE).map(|chunk| chunk.to_vec()));
+    }
+
+    pub fn finish(mut self) -> Vec<u8> {
+        if self.pages.is_empty() {
+            self.pages.push(Vec::new());
+        }
+
+        // 1 is the catalog, 2 the page tree and 3 the font, each page is followed by its content
+        let kids: Vec<String> = (0..self.pages.len())
+            .map(|index| format!("{} 0 R", 4 + 2 * index))
+            .collect();
+        let mut objects = vec![
+            b"<< /Type /Catalog /Pages 2 0 R >>".to_vec(),
+            format!(
+                "<< /Type /Pages /Kids [{}] /Count {} >>",
+                kids.join(" "),
+                self.pages.len()
+            )
+            .into_bytes(),
+            b"<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>"
+                .to_vec(),
+        ];
+        for (index, lines) in self.pages.iter().enumerate() {
+            objects.push(
+                format!(
+                    "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /Resources << /Font << /F1 3 0 R >> >> /Contents {} 0 R >>",
+                    PAGE_WIDTH,
+                    PAGE_HEIGHT,
+                    5 + 2 * index
+                )
+                .into_bytes(),
+            );
+
+            let mut content = format!(
+                "BT\n/F1 {} Tf\n{} TL\n{} {} Td\n",
+                FONT_SIZE,
+                LEADING,
+                MARGIN,
+                PAGE_HEIGHT - MARGIN
+            )
+            .into_bytes();
+            for line in lines {
+                content.push(b'(');
+                content.extend(encode_text(line));
+                content.extend(b") Tj T*\n");
+            }
+            content.extend(b"ET");
+
+            let mut stream = format!("<< /Length {} >>\nstream\n", content.len()).into_bytes();
+            stream.extend(content);
+            stream.extend(b"\nendstream");
+            objects.push(stream);
+        }
+
+        let mut pdf = b"%PDF-1.4\n".to_vec();
+        let mut offsets = Vec::with_capacity(objects.len());
+        for (index, object) in objects.iter().enumerate() {
+            offsets.push(pdf.len());
+            pdf.extend(format!("{} 0 obj\n", index + 1).into_bytes());
+            pdf.extend(object);
+            pdf.extend(b"\nendobj\n");
+        }
+
+        let xref_offset = pdf.len();
+        pdf.extend(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).into_bytes());
+        for offset in offsets {
+            pdf.extend(format!("{:010} 00000 n \n", offset).into_bytes());
+        }
+        pdf.extend(
+            format!(
+                "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+                objects.len() + 1,
+                xref_offset
+            )
+            .into_bytes(),
+        );
+        pdf
+    }
+}
+
+// a PDF string literal in WinAnsi, Latin-1 letters survive and anything the font can't show prints as ?
+fn encode_text(line: &str) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(line.len());
+    for c in line.chars() {
+        match c {
+            '(' | ')' | '\\' => {
+                encoded.push(b'\\');
+                encoded.push(c as u8);
+            }
+            ' '..='~' => encoded.push(c as u8),
+            '\u{a0}'..='\u{ff}' => encoded.extend(format!("\\{:03o}", c as u32).into_bytes()),
+            _ => encoded.push(b'?'),
+        }
+    }
+    encoded
+}
@@ -0,0 +1,106 @@
+use crate::{
+    auth::AuthenticatedUser,
+    entity::{policy_versions, prelude::Users as UsersEntity},
+    models::user::held_roles,
+    tenancy::current_tenant,
+    terms::pending_policy_versions,
+};
+use async_graphql::{Context, Error, ErrorExtensions};
+use sea_orm::{DatabaseConnection, EntityTrait};
+use tokio::sync::OnceCell;
+
+// the caller's account as far as authorization is concerned
+#[derive(Clone)]
+pub struct CallerPermissions {
+    pub user_id: i32,
+    // read from the database rather than the token, so a removed role or a disabled account
+    // stops counting right away. Disabled accounts hold none
+    pub roles: Vec<String>,
+    pub email_verified: bool,
+}
+
+impl CallerPermissions {
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.iter().any(|held| held == role)
+    }
+}
+
+// put in every request's data by the handler, each part is loaded the first time a guard asks for
+// it and shared by the guards after that, however many fields of the operation they protect. A
+// mutation changing roles or accepting policies is seen by the guards of the next request
+#[derive(Default)]
+pub struct PermissionCache {
+    permissions: OnceCell<CallerPermissions>,
+    pending_policies: OnceCell<Vec<policy_versions::Model>>,
+}
+
+async fn load_permissions(ctx: &Context<'_>) -> Result<CallerPermissions, Error> {
+    let claims = AuthenticatedUser::from_ctx(ctx).map_err(|e| e.extend())?;
+    let db = ctx.data::<DatabaseConnection>()?;
+    load_user_permissions(db, claims.user_id.parse::<i32>()?).await
+}
+
+async fn load_user_permissions(
+    db: &DatabaseConnection,
+    user_id: i32,
+) -> Result<CallerPermissions, Error> {
+    let user = UsersEntity::find_by_id(user_id).one(db).await?;
+    let (roles, email_verified) = match user {
+        Some(user) if user.disabled_at.is_none() => {
+            (held_roles(db, &user).await?, user.email_verified)
+        }
+        Some(user) => (Vec::new(), user.email_verified),
+        None => (Vec::new(), false),
+    };
+
+    Ok(CallerPermissions {
+        user_id,
+        roles,
+        email_verified,
+    })
+}
+
+// subscriptions have no request of their own, their guards load everything each time
+impl PermissionCache {
+    // for the handler, which picks the caller's rate limit before the schema runs. The request's
+    // guards get the same answer without looking again
+    pub async fn load(
+        &self,
+        db: &DatabaseConnection,
+        user_id: i32,
+    ) -> Result<CallerPermissions, Error> {
+        self.permissions
+            .get_or_try_init(|| load_user_permissions(db, user_id))
+            .await
+            .cloned()
+    }
+}
+
+pub async fn caller_permissions(ctx: &Context<'_>) -> Result<CallerPermissions, Error> {
+    match ctx.data_opt::<PermissionCache>() {
+        Some(cache) => cache
+            .permissions
+            .get_or_try_init(|| load_permissions(ctx))
+            .await
+            .cloned(),
+        None => load_permissions(ctx).await,
+    }
+}
+
+// current policy versions the caller hasn't accepted yet
+pub async fn caller_pending_policies(
+    ctx: &Context<'_>,
+) -> Result<Vec<policy_versions::Model>, Error> {
+    let load = || async {
+        let user_id = AuthenticatedUser::from_ctx(ctx)
+            .map_err(|e| e.extend())?
+            .user_id
+            .parse::<i32>()?;
+        let db = ctx.data::<DatabaseConnection>()?;
+        Ok::<_, Error>(pending_policy_versions(db, current_tenant(ctx), user_id.into()).await?)
+    };
+    match ctx.data_opt::<PermissionCache>() {
+        Some(cache) => cache.pending_policies.get_or_try_init(load).await.cloned(),
+        None => load().await,
+    }
+}
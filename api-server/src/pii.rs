@@ -0,0 +1,58 @@
+use crate::config::PiiPolicy;
+use async_graphql::{registry::MetaDirectiveInvocation, Enum, InputType, TypeDirective};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+pub const REDACTED: &str = "[REDACTED]";
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+pub enum PiiKind {
+    Email,
+    IpAddress,
+    Name,
+    Phone,
+    Address,
+    BankAccount,
+    // passwords, codes and bearer tokens
+    Secret,
+}
+
+impl PiiKind {
+    // identifiers stay groupable as a keyed hash, everything else is dropped
+    fn is_hashed(&self) -> bool {
+        matches!(self, PiiKind::Email | PiiKind::IpAddress | PiiKind::Name)
+    }
+}
+
+/// Personal data, redacted or hashed wherever the value is logged.
+#[TypeDirective(
+    location = "FieldDefinition",
+    location = "InputFieldDefinition",
+    location = "ArgumentDefinition"
+)]
+pub fn pii(kind: PiiKind) {}
+
+// the kind a field or argument was marked with through @pii
+pub fn marked_kind(directives: &[MetaDirectiveInvocation]) -> Option<PiiKind> {
+    directives
+        .iter()
+        .find(|directive| directive.name == "pii")
+        .and_then(|directive| directive.args.get("kind"))
+        .and_then(|kind| PiiKind::parse(Some(kind.clone())).ok())
+}
+
+// the same input always gives the same hash, so hashed values can still be compared and joined
+pub fn protect(policy: &PiiPolicy, kind: PiiKind, value: &str) -> String {
+    if !kind.is_hashed() {
+        return REDACTED.to_string();
+    }
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(policy.hash_secret.as_bytes())
+        .expect("HMAC takes keys of any size");
+    mac.update(value.trim().to_lowercase().as_bytes());
+    let digest: String = mac.finalize().into_bytes()[..16]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    format!("pii:{}", digest)
+}
@@ -0,0 +1,44 @@
+use crate::{config::env_or, error::AppError, retry::retry_redis};
+use futures_util::StreamExt;
+use redis::AsyncCommands;
+use std::{future::Future, time::Duration};
+
+pub fn redis_client() -> Result<redis::Client, AppError> {
+    redis::Client::open(env_or("REDIS_URL", "redis://127.0.0.1:6379/".to_string()))
+        .map_err(|e| AppError::Internal(format!("Invalid REDIS_URL: {}", e)))
+}
+
+pub async fn publish(client: &redis::Client, channel: &str, message: &str) -> Result<(), AppError> {
+    retry_redis("redis_publish", || async {
+        let mut connection = client.get_multiplexed_async_connection().await?;
+        connection.publish::<_, _, ()>(channel, message).await
+    })
+    .await
+    .map_err(|e| AppError::Internal(format!("Redis publish failed: {}", e)))
+}
+
+// runs the handler for every message on the channel, reconnecting whenever redis goes away
+pub fn spawn_subscriber<F, Fut>(client: redis::Client, channel: &'static str, handler: F)
+where
+    F: Fn(String) -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send,
+{
+    tokio::spawn(async move {
+        loop {
+            match client.get_async_pubsub().await {
+                Ok(mut pubsub) => {
+                    if let Err(e) = pubsub.subscribe(channel).await {
+                        eprintln!("Redis subscribe to {} failed: {}", channel, e);
+                    } else {
+                        let mut messages = pubsub.on_message();
+                        while let Some(message) = messages.next().await {
+                            handler(message.get_payload().unwrap_or_default()).await;
+                        }
+                    }
+                }
+                Err(e) => eprintln!("Redis connection for {} failed: {}", channel, e),
+            }
+            tokio::time::sleep(Duration::from_secs(5)).await;
+        }
+    });
+}
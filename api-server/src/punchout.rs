@@ -0,0 +1,453 @@
+use crate::{
+    api_keys::{record_usage, resolve_api_key, ApiKeyContext, UsageOutcome, API_KEY_HEADER},
+    auth::{is_email_verified, Auth, ROLE_CUSTOMER},
+    config::{CurrencyPolicy, EmailVerificationPolicy, JwtPolicy, RegionConfig, TaxPolicy},
+    entity::{
+        addresses, orders, payment_methods,
+        prelude::{
+            Addresses as AddressesEntity, Orders as OrdersEntity,
+            PaymentMethods as PaymentMethodsEntity, Products as ProductsEntity,
+        },
+        products,
+    },
+    error::AppError,
+    ids::{ProductId, TenantId},
+    models::{
+        orders::{place_order, OrderOwner, RegisterOrder, RegisterOrderItem},
+        products::on_sale,
+        rate_limits::RateLimitTier,
+        user::customer_supplier_id_of,
+    },
+    rate_limit::RateLimiter,
+    sandbox::SandboxDb,
+    tenancy::TenantScope,
+    terms::pending_policy_versions,
+};
+use axum::{
+    body::Bytes,
+    extract::Query,
+    http::{header::CONTENT_TYPE, HeaderMap, StatusCode},
+    Extension, Json,
+};
+use sea_orm::{
+    ColumnTrait, DatabaseConnection, DbErr, EntityTrait, PaginatorTrait, QueryFilter, SqlErr,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+
+// JSON body of a punchout order
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PunchoutOrder {
+    pub po_number: String,
+    pub shipping_address_id: i32,
+    pub payment_method_id: i32,
+    pub items: Vec<PunchoutLine>,
+}
+
+// one ordered line, also the row layout of the CSV body (sku,quantity)
+#[derive(Deserialize)]
+pub struct PunchoutLine {
+    pub sku: String,
+    pub quantity: i32,
+}
+
+// CSV bodies only carry the lines, the order header comes in the query string
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PunchoutParams {
+    pub po_number: Option<String>,
+    pub shipping_address_id: Option<i32>,
+    pub payment_method_id: Option<i32>,
+}
+
+type PunchoutResponse = (StatusCode, Json<Value>);
+
+fn reject(
+    status: StatusCode,
+    message: impl Into<String>,
+    failed_rules: Vec<String>,
+) -> PunchoutResponse {
+    (
+        status,
+        Json(json!({ "message": message.into(), "failedRules": failed_rules })),
+    )
+}
+
+// POST /punchout/orders, authenticated with a customer API key in x-api-key
+#[allow(clippy::too_many_arguments)]
+pub async fn submit_punchout_order(
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(sandbox): Extension<SandboxDb>,
+    Extension(rate_limiter): Extension<RateLimiter>,
+    Extension(region): Extension<RegionConfig>,
+    Extension(currency): Extension<CurrencyPolicy>,
+    Extension(tax_policy): Extension<TaxPolicy>,
+    Extension(verification): Extension<EmailVerificationPolicy>,
+    Extension(jwt_policy): Extension<JwtPolicy>,
+    Query(params): Query<PunchoutParams>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> PunchoutResponse {
+    let Some(api_key) = headers
+        .get(API_KEY_HEADER)
+        .and_then(|value| value.to_str().ok())
+    else {
+        return reject(StatusCode::UNAUTHORIZED, "Missing API key", vec![]);
+    };
+
+    let api_key = match resolve_api_key(&db, &jwt_policy, api_key).await {
+        Ok(api_key) => api_key,
+        Err(e) => return reject(StatusCode::UNAUTHORIZED, e.to_string(), vec![]),
+    };
+
+    if !rate_limiter.check(
+        api_key.tenant_id,
+        RateLimitTier::PartnerKey,
+        &format!("api_key:{}", api_key.api_key_id),
+    ) {
+        let _ = record_usage(&db, api_key.api_key_id, UsageOutcome::RateLimited).await;
+        return reject(
+            StatusCode::TOO_MANY_REQUESTS,
+            "API key rate limit exceeded",
+            vec![],
+        );
+    }
+
+    let response = ingest(
+        if api_key.sandbox { &sandbox.0 } else { &db },
+        &api_key,
+        &params,
+        &headers,
+        &body,
+        &region,
+        &currency,
+        &tax_policy,
+        &verification,
+        &jwt_policy,
+    )
+    .await;
+
+    let outcome = if response.0.is_success() {
+        UsageOutcome::Success
+    } else {
+        UsageOutcome::Error
+    };
+    let _ = record_usage(&db, api_key.api_key_id, outcome).await;
+
+    response
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn ingest(
+    db: &DatabaseConnection,
+    api_key: &ApiKeyContext,
+    params: &PunchoutParams,
+    headers: &HeaderMap,
+    body: &[u8],
+    region: &RegionConfig,
+    currency: &CurrencyPolicy,
+    tax_policy: &TaxPolicy,
+    verification: &EmailVerificationPolicy,
+    jwt_policy: &JwtPolicy,
+) -> PunchoutResponse {
+    let (token, tenant) = (api_key.token.as_str(), api_key.tenant_id);
+    let claims = match Auth::verify_token(jwt_policy, token) {
+        Ok(claims) => claims,
+        Err(e) => return reject(StatusCode::UNAUTHORIZED, e.to_string(), vec![]),
+    };
+    let customer_id = match customer_supplier_id_of(db, &claims, ROLE_CUSTOMER).await {
+        Ok(customer_id) => customer_id,
+        Err(_) => {
+            return reject(
+                StatusCode::FORBIDDEN,
+                "Punchout orders need a customer API key",
+                vec![],
+            )
+        }
+    };
+
+    // same rule as the TermsGuard on registerOrder
+    let user_id = claims.user_id.parse::<i32>().unwrap_or_default();
+    match pending_policy_versions(db, tenant, user_id.into()).await {
+        Ok(pending) if pending.is_empty() => {}
+        Ok(_) => {
+            return reject(
+                StatusCode::FORBIDDEN,
+                "Accept the latest policies before ordering",
+                vec![],
+            )
+        }
+        Err(e) => return reject(StatusCode::INTERNAL_SERVER_ERROR, e.to_string(), vec![]),
+    }
+    // and the VerifiedEmailGuard
+    if verification.required_for_purchases {
+        match is_email_verified(db, user_id).await {
+            Ok(true) => {}
+            Ok(false) => {
+                return reject(
+                    StatusCode::FORBIDDEN,
+                    "Verify your email address before ordering",
+                    vec![],
+                )
+            }
+            Err(e) => return reject(StatusCode::INTERNAL_SERVER_ERROR, e.to_string(), vec![]),
+        }
+    }
+
+    let is_csv = headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|content_type| content_type.starts_with("text/csv"));
+    let order = if is_csv {
+        parse_csv_order(params, body)
+    } else {
+        serde_json::from_slice::<PunchoutOrder>(body).map_err(|e| AppError::Validation {
+            message: "Malformed punchout order".to_string(),
+            failed_rules: vec![e.to_string()],
+        })
+    };
+    let order = match order {
+        Ok(order) => order,
+        Err(AppError::Validation {
+            message,
+            failed_rules,
+        }) => return reject(StatusCode::UNPROCESSABLE_ENTITY, message, failed_rules),
+        Err(e) => return reject(StatusCode::BAD_REQUEST, e.to_string(), vec![]),
+    };
+
+    let input = match resolve_lines(db, tenant, order).await {
+        Ok(input) => input,
+        Err(AppError::Validation {
+            message,
+            failed_rules,
+        }) => return reject(StatusCode::UNPROCESSABLE_ENTITY, message, failed_rules),
+        Err(e) => return reject(StatusCode::INTERNAL_SERVER_ERROR, e.to_string(), vec![]),
+    };
+    match check_customer_ids(db, customer_id, &input).await {
+        Ok(()) => {}
+        Err(AppError::Validation {
+            message,
+            failed_rules,
+        }) => return reject(StatusCode::UNPROCESSABLE_ENTITY, message, failed_rules),
+        Err(e) => return reject(StatusCode::INTERNAL_SERVER_ERROR, e.to_string(), vec![]),
+    }
+
+    // a PO is only turned into an order once, resubmissions are reported instead of duplicated.
+    // Two submissions racing past this check are caught by the unique index below
+    let duplicates = OrdersEntity::find()
+        .filter(orders::Column::CustomerId.eq(customer_id))
+        .filter(orders::Column::PoNumber.eq(input.po_number.clone()))
+        .count(db)
+        .await;
+    match duplicates {
+        Ok(0) => {}
+        Ok(_) => {
+            return reject(
+                StatusCode::CONFLICT,
+                "An order with this PO number already exists",
+                vec![],
+            )
+        }
+        Err(e) => return reject(StatusCode::INTERNAL_SERVER_ERROR, e.to_string(), vec![]),
+    }
+
+    match place_order(
+        db,
+        OrderOwner::Customer(customer_id),
+        tenant,
+        input,
+        None,
+        region,
+        currency,
+        tax_policy,
+    )
+    .await
+    {
+        Ok(order) => (
+            StatusCode::CREATED,
+            Json(json!({
+                "orderId": order.order_id,
+                "poNumber": order.po_number,
+                "status": order.status,
+                "totalAmount": order.total_amount.to_string(),
+                "taxAmount": order.tax_amount.to_string(),
+            })),
+        ),
+        Err(e) if is_duplicate_po(&e) => reject(
+            StatusCode::CONFLICT,
+            "An order with this PO number already exists",
+            vec![],
+        ),
+        Err(e) => reject(StatusCode::UNPROCESSABLE_ENTITY, e.message, vec![]),
+    }
+}
+
+// the unique index on customer and PO number turned the order down, the sandbox copy of the
+// index has a generated name that also mentions po_number
+fn is_duplicate_po(err: &async_graphql::Error) -> bool {
+    err.source
+        .as_ref()
+        .and_then(|source| source.downcast_ref::<DbErr>())
+        .and_then(DbErr::sql_err)
+        .is_some_and(|err| {
+            matches!(err, SqlErr::UniqueConstraintViolation(message)
+                if message.contains("po_number"))
+        })
+}
+
+fn parse_csv_order(params: &PunchoutParams, body: &[u8]) -> Result<PunchoutOrder, AppError> {
+    let mut failed_rules = Vec::new();
+    let mut items = Vec::new();
+
+    // line 1 is the header
+    for (line, record) in csv::Reader::from_reader(body)
+        .deserialize::<PunchoutLine>()
+        .enumerate()
+        .map(|(index, record)| (index + 2, record))
+    {
+        match record {
+            Ok(item) => items.push(item),
+            Err(e) => failed_rules.push(format!("line {}: {}", line, e)),
+        }
+    }
+
+    if params.po_number.is_none() {
+        failed_rules.push("poNumber query parameter is required".to_string());
+    }
+    if params.shipping_address_id.is_none() {
+        failed_rules.push("shippingAddressId query parameter is required".to_string());
+    }
+    if params.payment_method_id.is_none() {
+        failed_rules.push("paymentMethodId query parameter is required".to_string());
+    }
+
+    match (
+        &params.po_number,
+        params.shipping_address_id,
+        params.payment_method_id,
+    ) {
+        (Some(po_number), Some(shipping_address_id), Some(payment_method_id))
+            if failed_rules.is_empty() =>
+        {
+            Ok(PunchoutOrder {
+                po_number: po_number.clone(),
+                shipping_address_id,
+                payment_method_id,
+                items,
+            })
+        }
+        _ => Err(AppError::Validation {
+            message: "Punchout CSV has invalid rows".to_string(),
+            failed_rules,
+        }),
+    }
+}
+
+// the address and payment method have to be the key owner's own, the same way
+// checkoutWithSavedMethod only picks from the customer's
+async fn check_customer_ids(
+    db: &DatabaseConnection,
+    customer_id: i32,
+    order: &RegisterOrder,
+) -> Result<(), AppError> {
+    let mut failed_rules = Vec::new();
+
+    let address_count = AddressesEntity::find_by_id(order.shipping_address_id)
+        .filter(addresses::Column::CustomerId.eq(customer_id))
+        .count(db)
+        .await?;
+    if address_count == 0 {
+        failed_rules.push("shippingAddressId is not one of the customer's addresses".to_string());
+    }
+    let payment_method_count = PaymentMethodsEntity::find()
+        .filter(payment_methods::Column::PaymentMethodId.eq(order.payment_method_id))
+        .filter(payment_methods::Column::CustomerId.eq(customer_id))
+        .count(db)
+        .await?;
+    if payment_method_count == 0 {
+        failed_rules
+            .push("paymentMethodId is not one of the customer's payment methods".to_string());
+    }
+
+    if !failed_rules.is_empty() {
+        return Err(AppError::Validation {
+            message: "Punchout order refers to details the customer doesn't own".to_string(),
+            failed_rules,
+        });
+    }
+    Ok(())
+}
+
+// maps SKUs onto catalog products, every problem is reported at once
+async fn resolve_lines(
+    db: &DatabaseConnection,
+    tenant: TenantId,
+    order: PunchoutOrder,
+) -> Result<RegisterOrder, AppError> {
+    let mut failed_rules = Vec::new();
+
+    let po_number = order.po_number.trim().to_string();
+    if po_number.is_empty() || po_number.len() > 50 {
+        failed_rules.push("poNumber must be between 1 and 50 characters".to_string());
+    }
+    if order.items.is_empty() {
+        failed_rules.push("order has no items".to_string());
+    }
+
+    // repeated SKUs are merged into one line
+    let mut quantities: BTreeMap<String, i32> = BTreeMap::new();
+    for item in &order.items {
+        let sku = item.sku.trim().to_string();
+        if item.quantity <= 0 {
+            failed_rules.push(format!("{}: quantity must be positive", sku));
+            continue;
+        }
+        *quantities.entry(sku).or_default() += item.quantity;
+    }
+
+    let products = on_sale(ProductsEntity::find())
+        .filter(products::Column::Sku.is_in(quantities.keys().cloned()))
+        .for_tenant(tenant)
+        .all(db)
+        .await?;
+
+    let mut order_items = Vec::with_capacity(quantities.len());
+    for (sku, quantity) in quantities {
+        match products
+            .iter()
+            .find(|product| product.sku.as_deref() == Some(sku.as_str()))
+        {
+            Some(product) if product.stock_quantity < quantity => {
+                failed_rules.push(format!("{}: only {} in stock", sku, product.stock_quantity))
+            }
+            // catalog orders carry no dates to book
+            Some(product) if product.date_bookable => {
+                failed_rules.push(format!("{}: needs a booking date", sku))
+            }
+            Some(product) => order_items.push(RegisterOrderItem {
+                product_id: ProductId(product.product_id),
+                quantity,
+                booking_date: None,
+            }),
+            None => failed_rules.push(format!("{}: unknown SKU", sku)),
+        }
+    }
+
+    if !failed_rules.is_empty() {
+        return Err(AppError::Validation {
+            message: "Punchout order failed catalog validation".to_string(),
+            failed_rules,
+        });
+    }
+
+    Ok(RegisterOrder {
+        shipping_address_id: order.shipping_address_id,
+        payment_method_id: Some(order.payment_method_id),
+        discount_code: None,
+        order_items,
+        po_number: Some(po_number),
+        region: None,
+    })
+}
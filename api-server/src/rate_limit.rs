@@ -0,0 +1,168 @@
+use crate::{
+    config::env_or,
+    entity::prelude::RateLimitTiers,
+    error::AppError,
+    ids::TenantId,
+    models::rate_limits::RateLimitTier,
+    pubsub::{publish, spawn_subscriber},
+    tenancy::tenant_key,
+};
+use sea_orm::{DatabaseConnection, EntityTrait};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant},
+};
+
+pub const RATE_LIMIT_TIERS_CHANNEL: &str = "rate_limit_tiers:reload";
+
+// Fixed window limiter kept in process memory, keyed by whatever identifies the caller. The
+// budget of each tier comes from the environment unless a marketplace's admins overrode it in
+// rate_limit_tiers, every instance reloads those when a change is announced on
+// RATE_LIMIT_TIERS_CHANNEL
+#[derive(Clone)]
+pub struct RateLimiter {
+    defaults: HashMap<RateLimitTier, u32>,
+    overrides: Arc<RwLock<HashMap<(TenantId, RateLimitTier), u32>>>,
+    window: Duration,
+    windows: Arc<Mutex<HashMap<String, (Instant, u32)>>>,
+}
+
+impl RateLimiter {
+    pub fn new(defaults: HashMap<RateLimitTier, u32>, window: Duration) -> Self {
+        Self {
+            defaults,
+            overrides: Arc::new(RwLock::new(HashMap::new())),
+            window,
+            windows: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let defaults = HashMap::from([
+            (
+                RateLimitTier::Anonymous,
+                env_or("RATE_LIMIT_ANONYMOUS_PER_MINUTE", 60),
+            ),
+            (
+                RateLimitTier::Customer,
+                env_or("RATE_LIMIT_CUSTOMER_PER_MINUTE", 300),
+            ),
+            (
+                RateLimitTier::Supplier,
+                env_or("RATE_LIMIT_SUPPLIER_PER_MINUTE", 600),
+            ),
+            (
+                RateLimitTier::PartnerKey,
+                env_or("API_RATE_LIMIT_PER_MINUTE", 120),
+            ),
+        ]);
+        Self::new(defaults, Duration::from_secs(60))
+    }
+
+    pub fn default_limit(&self, tier: RateLimitTier) -> u32 {
+        self.defaults.get(&tier).copied().unwrap_or(u32::MAX)
+    }
+
+    // requests per window the tenant's callers in this tier get
+    pub fn limit(&self, tenant: TenantId, tier: RateLimitTier) -> u32 {
+        self.overrides
+            .read()
+            .unwrap()
+            .get(&(tenant, tier))
+            .copied()
+            .unwrap_or_else(|| self.default_limit(tier))
+    }
+
+    // returns false once the caller has used up the current window
+    pub fn check(&self, tenant: TenantId, tier: RateLimitTier, key: &str) -> bool {
+        let limit = self.limit(tenant, tier);
+        let now = Instant::now();
+        let mut windows = self.windows.lock().unwrap();
+        let (started, count) = windows
+            .entry(tenant_key(tenant, &format!("{}:{}", tier.as_str(), key)))
+            .or_insert((now, 0));
+
+        if now.duration_since(*started) >= self.window {
+            *started = now;
+            *count = 0;
+        }
+
+        if *count >= limit {
+            return false;
+        }
+
+        *count += 1;
+        true
+    }
+
+    // drops the windows that ran out, a caller seen once would otherwise be kept forever
+    pub fn prune(&self) {
+        let now = Instant::now();
+        self.windows
+            .lock()
+            .unwrap()
+            .retain(|_, (started, _)| now.duration_since(*started) < self.window);
+    }
+
+    pub fn spawn_pruner(&self) {
+        let limiter = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(limiter.window);
+            loop {
+                interval.tick().await;
+                limiter.prune();
+            }
+        });
+    }
+
+    pub async fn reload(&self, db: &DatabaseConnection) -> Result<(), AppError> {
+        let overrides = RateLimitTiers::find()
+            .all(db)
+            .await?
+            .into_iter()
+            .filter_map(|row| {
+                let tier = RateLimitTier::parse(&row.tier)?;
+                Some((
+                    (row.tenant_id.into(), tier),
+                    row.requests_per_minute.max(1) as u32,
+                ))
+            })
+            .collect();
+        *self.overrides.write().unwrap() = overrides;
+        Ok(())
+    }
+
+    // initial load plus a reload whenever another instance announces a change
+    pub fn spawn_reloader(&self, db: DatabaseConnection, redis: redis::Client) {
+        let (initial, initial_db) = (self.clone(), db.clone());
+        tokio::spawn(async move {
+            if let Err(e) = initial.reload(&initial_db).await {
+                eprintln!("Loading rate limit tiers failed: {}", e);
+            }
+        });
+
+        let limiter = self.clone();
+        spawn_subscriber(redis, RATE_LIMIT_TIERS_CHANNEL, move |_| {
+            let (limiter, db) = (limiter.clone(), db.clone());
+            async move {
+                if let Err(e) = limiter.reload(&db).await {
+                    eprintln!("Reloading rate limit tiers failed: {}", e);
+                }
+            }
+        });
+    }
+
+    // reloads here right away and tells the other instances to do the same
+    pub async fn announce_change(
+        &self,
+        db: &DatabaseConnection,
+        redis: &redis::Client,
+    ) -> Result<(), AppError> {
+        self.reload(db).await?;
+        if let Err(e) = publish(redis, RATE_LIMIT_TIERS_CHANNEL, "reload").await {
+            eprintln!("Announcing rate limit tier change failed: {}", e);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,186 @@
+use crate::{
+    catalog_cache::bump_catalog_version,
+    config::env_or,
+    entity::{
+        categories,
+        category_attributes::Model as CategoryAttributesModel,
+        category_reassignments::{self, Model as CategoryReassignmentsModel},
+        prelude::{Categories as CategoriesEntity, CategoryReassignments, Products},
+        products,
+    },
+    error::AppError,
+    ids::TenantId,
+    jobs::JobTable,
+    models::{
+        category_attributes::{
+            attribute_template, lacks_required_attribute, required_attribute_rules,
+        },
+        category_counts::adjust_category_count,
+        products::Categories,
+    },
+    search::like_contains,
+};
+use sea_orm::{
+    sea_query::Expr, ColumnTrait, DatabaseConnection, EntityTrait, PaginatorTrait, QueryFilter,
+    QueryOrder, QuerySelect, QueryTrait, Select, TransactionTrait,
+};
+
+const REASSIGNMENT_POLL_SECONDS: u64 = 10;
+
+// the products of the source category a reassignment moves
+pub fn reassigned_products(
+    tenant: TenantId,
+    from_category_id: i32,
+    name_contains: Option<String>,
+    supplier_id: Option<i32>,
+) -> Select<Products> {
+    Products::find()
+        .filter(products::Column::CategoryId.eq(from_category_id))
+        .filter(products::Column::TenantId.eq(tenant.0))
+        .apply_if(name_contains, |query, name| {
+            query.filter(like_contains(products::Column::Name, &name))
+        })
+        .apply_if(supplier_id, |query, supplier_id| {
+            query.filter(products::Column::SupplierId.eq(supplier_id))
+        })
+}
+
+// the products of the source category the job still has to move
+fn remaining_products(job: &CategoryReassignmentsModel) -> Select<Products> {
+    reassigned_products(
+        job.tenant_id.into(),
+        job.from_category_id,
+        job.name_contains.clone(),
+        job.supplier_id,
+    )
+}
+
+// one batch per transaction, so the counts and the reported progress always agree with the products.
+// The job stops at a batch with products lacking an attribute the target category requires, they
+// were checked when the job was queued but may have changed since
+async fn move_batch(
+    db: &DatabaseConnection,
+    job: &CategoryReassignmentsModel,
+    template: &[CategoryAttributesModel],
+    batch_size: u64,
+) -> Result<i32, AppError> {
+    let txn = db.begin().await?;
+    let batch: Vec<(i32, bool)> = remaining_products(job)
+        .select_only()
+        .column(products::Column::ProductId)
+        .column_as(products::Column::ArchivedAt.is_null(), "on_sale")
+        .order_by_asc(products::Column::ProductId)
+        .limit(batch_size)
+        .lock_exclusive()
+        .into_tuple()
+        .all(&txn)
+        .await?;
+    if batch.is_empty() {
+        return Ok(0);
+    }
+    let moved = batch.len() as i32;
+    // archived products move along but aren't counted in either category
+    let counted = batch.iter().filter(|(_, on_sale)| *on_sale).count() as i32;
+    let product_ids: Vec<i32> = batch
+        .into_iter()
+        .map(|(product_id, _)| product_id)
+        .collect();
+    if let Some(lacking) = lacks_required_attribute(template) {
+        let missing = Products::find()
+            .filter(products::Column::ProductId.is_in(product_ids.clone()))
+            .filter(lacking)
+            .count(&txn)
+            .await?;
+        if missing > 0 {
+            return Err(AppError::Validation {
+                message: format!(
+                    "{} products lack attributes the target category requires",
+                    missing
+                ),
+                failed_rules: required_attribute_rules(template),
+            });
+        }
+    }
+
+    Products::update_many()
+        .col_expr(
+            products::Column::CategoryId,
+            Expr::value(job.to_category_id),
+        )
+        .filter(products::Column::ProductId.is_in(product_ids))
+        .exec(&txn)
+        .await?;
+    adjust_category_count(&txn, Some(job.from_category_id), -counted).await?;
+    adjust_category_count(&txn, Some(job.to_category_id), counted).await?;
+    CategoryReassignments::update_many()
+        .col_expr(
+            category_reassignments::Column::MovedProducts,
+            Expr::col(category_reassignments::Column::MovedProducts).add(moved),
+        )
+        .filter(category_reassignments::Column::ReassignmentId.eq(job.reassignment_id))
+        .exec(&txn)
+        .await?;
+
+    txn.commit().await?;
+    Ok(moved)
+}
+
+async fn run_reassignment(
+    db: &DatabaseConnection,
+    redis: &redis::Client,
+    reassignment_id: i32,
+    batch_size: u64,
+) -> Result<(), AppError> {
+    let job = CategoryReassignments::find_by_id(reassignment_id)
+        .one(db)
+        .await?
+        .ok_or(AppError::NotFound("Category reassignment"))?;
+
+    // a job picked up again after its worker stopped keeps the total it started with
+    if job.total_products.is_none() {
+        let total = remaining_products(&job).count(db).await?;
+        CategoryReassignments::update_many()
+            .col_expr(
+                category_reassignments::Column::TotalProducts,
+                Expr::value(total as i32),
+            )
+            .filter(category_reassignments::Column::ReassignmentId.eq(job.reassignment_id))
+            .exec(db)
+            .await?;
+    }
+
+    let tree: Vec<Categories> = CategoriesEntity::find()
+        .filter(categories::Column::TenantId.eq(job.tenant_id))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(Categories::from)
+        .collect();
+    let template = attribute_template(db, &tree, Some(job.to_category_id)).await?;
+
+    let result = loop {
+        match move_batch(db, &job, &template, batch_size).await {
+            Ok(0) => break Ok(()),
+            Ok(_) => continue,
+            Err(e) => break Err(e),
+        }
+    };
+    // a failed job may still have moved some batches
+    bump_catalog_version(redis, job.tenant_id.into()).await;
+    result
+}
+
+const REASSIGNMENT_JOBS: JobTable = JobTable {
+    table: "category_reassignments",
+    id_column: "reassignment_id",
+    label: "Category reassignment",
+};
+
+pub fn spawn_category_reassignment_worker(db: DatabaseConnection, redis: redis::Client) {
+    let batch_size = env_or("REASSIGNMENT_BATCH_SIZE", 500u64).max(1);
+    let worker_db = db.clone();
+    REASSIGNMENT_JOBS.spawn_worker(db, REASSIGNMENT_POLL_SECONDS, move |reassignment_id| {
+        let (db, redis) = (worker_db.clone(), redis.clone());
+        async move { run_reassignment(&db, &redis, reassignment_id, batch_size).await }
+    });
+}
@@ -0,0 +1,152 @@
+use crate::{
+    config::PiiPolicy,
+    pii::{marked_kind, protect, PiiKind, REDACTED},
+};
+use async_graphql::{
+    async_trait,
+    extensions::{Extension, ExtensionContext, ExtensionFactory, NextParseQuery},
+    parser::types::{ExecutableDocument, OperationType, Selection},
+    registry::{MetaInputValue, MetaType, Registry},
+    ServerResult, Value, Variables,
+};
+use std::sync::Arc;
+
+// one line per operation with the root fields and their arguments, @pii values never show up
+pub struct RequestLog(pub PiiPolicy);
+
+impl ExtensionFactory for RequestLog {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(RequestLogExtension(self.0.clone()))
+    }
+}
+
+struct RequestLogExtension(PiiPolicy);
+
+#[async_trait::async_trait]
+impl Extension for RequestLogExtension {
+    async fn parse_query(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        query: &str,
+        variables: &Variables,
+        next: NextParseQuery<'_>,
+    ) -> ServerResult<ExecutableDocument> {
+        let document = next.run(ctx, query, variables).await?;
+        let registry = &ctx.schema_env.registry;
+
+        for (name, operation) in document.operations.iter() {
+            let root = match operation.node.ty {
+                OperationType::Query => Some(registry.query_type.as_str()),
+                OperationType::Mutation => registry.mutation_type.as_deref(),
+                OperationType::Subscription => registry.subscription_type.as_deref(),
+            }
+            .and_then(|root| registry.types.get(root));
+
+            let fields: Vec<String> = operation
+                .node
+                .selection_set
+                .node
+                .items
+                .iter()
+                .filter_map(|selection| match &selection.node {
+                    Selection::Field(field) => Some(&field.node),
+                    _ => None,
+                })
+                .map(|field| {
+                    let meta_field =
+                        root.and_then(|root| root.field_by_name(field.name.node.as_str()));
+                    let arguments: Vec<String> = field
+                        .arguments
+                        .iter()
+                        .map(|(argument, value)| {
+                            let value = value
+                                .node
+                                .clone()
+                                .into_const_with(|variable| {
+                                    variables.get(&variable).cloned().ok_or(())
+                                })
+                                .unwrap_or(Value::Null);
+                            let meta_argument = meta_field
+                                .and_then(|meta_field| meta_field.args.get(argument.node.as_str()));
+                            format!(
+                                "{}: {}",
+                                argument.node,
+                                redact_input(&self.0, registry, meta_argument, value)
+                            )
+                        })
+                        .collect();
+                    format!("{}({})", field.name.node, arguments.join(", "))
+                })
+                .collect();
+
+            println!(
+                "graphql {} {} {}",
+                operation.node.ty,
+                name.map(|name| name.as_str()).unwrap_or("anonymous"),
+                fields.join(" ")
+            );
+        }
+
+        Ok(document)
+    }
+}
+
+// follows the registered input types so marked fields nested in input objects are caught too
+fn redact_input(
+    policy: &PiiPolicy,
+    registry: &Registry,
+    meta: Option<&MetaInputValue>,
+    value: Value,
+) -> Value {
+    let Some(meta) = meta else {
+        return value;
+    };
+    if let Some(kind) = marked_kind(&meta.directive_invocations) {
+        return protect_value(policy, kind, value);
+    }
+
+    redact_typed(policy, registry, &meta.ty, value)
+}
+
+fn redact_typed(policy: &PiiPolicy, registry: &Registry, ty: &str, value: Value) -> Value {
+    match value {
+        Value::List(items) => Value::List(
+            items
+                .into_iter()
+                .map(|item| redact_typed(policy, registry, ty, item))
+                .collect(),
+        ),
+        Value::Object(fields) => {
+            let type_name = ty.trim_matches(|c| c == '[' || c == ']' || c == '!');
+            let Some(MetaType::InputObject { input_fields, .. }) = registry.types.get(type_name)
+            else {
+                return Value::Object(fields);
+            };
+            Value::Object(
+                fields
+                    .into_iter()
+                    .map(|(name, value)| {
+                        let value =
+                            redact_input(policy, registry, input_fields.get(name.as_str()), value);
+                        (name, value)
+                    })
+                    .collect(),
+            )
+        }
+        value => value,
+    }
+}
+
+fn protect_value(policy: &PiiPolicy, kind: PiiKind, value: Value) -> Value {
+    match value {
+        Value::Null => Value::Null,
+        Value::String(value) => Value::String(protect(policy, kind, &value)),
+        Value::List(items) => Value::List(
+            items
+                .into_iter()
+                .map(|item| protect_value(policy, kind, item))
+                .collect(),
+        ),
+        _ => Value::String(REDACTED.to_string()),
+    }
+}
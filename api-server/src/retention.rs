@@ -0,0 +1,168 @@
+use crate::{
+    config::RetentionPolicy,
+    entity::{prelude::RetentionRuns, retention_runs},
+    error::AppError,
+};
+use async_graphql::Enum;
+use chrono::{Duration, Utc};
+use sea_orm::{
+    prelude::DateTimeWithTimeZone, ActiveValue::Set, ConnectionTrait, DatabaseConnection,
+    DbBackend, EntityTrait, Statement, TransactionTrait,
+};
+
+// arbitrary key for pg advisory locks, offset by target so instances don't purge the same table twice
+const RETENTION_LOCK_KEY: i64 = 0x5245_5445_4E54;
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+pub enum RetentionTarget {
+    AnalyticsEvents,
+    AuditLog,
+    ExpiredCarts,
+}
+
+impl RetentionTarget {
+    pub const ALL: [RetentionTarget; 3] = [
+        RetentionTarget::AnalyticsEvents,
+        RetentionTarget::AuditLog,
+        RetentionTarget::ExpiredCarts,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RetentionTarget::AnalyticsEvents => "ANALYTICS_EVENTS",
+            RetentionTarget::AuditLog => "AUDIT_LOG",
+            RetentionTarget::ExpiredCarts => "EXPIRED_CARTS",
+        }
+    }
+
+    pub fn retention_days(&self, policy: &RetentionPolicy) -> i64 {
+        match self {
+            RetentionTarget::AnalyticsEvents => policy.analytics_days,
+            RetentionTarget::AuditLog => policy.audit_log_days,
+            RetentionTarget::ExpiredCarts => policy.cart_days,
+        }
+    }
+
+    // (table, timestamp column) pairs purged for the target
+    fn tables(&self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            RetentionTarget::AnalyticsEvents => &[
+                ("analytics_events", "occurred_at"),
+                ("api_key_usage", "usage_date"),
+                ("sponsored_clicks", "clicked_at"),
+            ],
+            RetentionTarget::AuditLog => &[("security_events", "created_at")],
+            // the items go with their cart through the cascade
+            RetentionTarget::ExpiredCarts => &[("expired_carts", "expired_at")],
+        }
+    }
+
+    fn lock_key(&self) -> i64 {
+        RETENTION_LOCK_KEY + *self as i64
+    }
+}
+
+// counts or deletes the expired rows of one target, None when another instance holds the lock
+async fn purge(
+    db: &DatabaseConnection,
+    target: RetentionTarget,
+    cutoff: DateTimeWithTimeZone,
+    dry_run: bool,
+) -> Result<Option<i64>, AppError> {
+    let txn = db.begin().await?;
+
+    let locked = txn
+        .query_one(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            "SELECT pg_try_advisory_xact_lock($1) AS locked",
+            vec![target.lock_key().into()],
+        ))
+        .await?
+        .map(|row| row.try_get::<bool>("", "locked"))
+        .transpose()?
+        .unwrap_or(false);
+    if !locked {
+        return Ok(None);
+    }
+
+    let mut affected_rows = 0;
+    for (table, column) in target.tables() {
+        affected_rows += if dry_run {
+            txn.query_one(Statement::from_sql_and_values(
+                DbBackend::Postgres,
+                format!(
+                    "SELECT count(*) AS expired FROM {} WHERE {} < $1",
+                    table, column
+                ),
+                vec![cutoff.into()],
+            ))
+            .await?
+            .map(|row| row.try_get::<i64>("", "expired"))
+            .transpose()?
+            .unwrap_or(0)
+        } else {
+            txn.execute(Statement::from_sql_and_values(
+                DbBackend::Postgres,
+                format!("DELETE FROM {} WHERE {} < $1", table, column),
+                vec![cutoff.into()],
+            ))
+            .await?
+            .rows_affected() as i64
+        };
+    }
+
+    txn.commit().await?;
+
+    Ok(Some(affected_rows))
+}
+
+// applies every retention policy once, each target is recorded in retention_runs even when it fails
+pub async fn run_retention(
+    db: &DatabaseConnection,
+    policy: &RetentionPolicy,
+    dry_run: bool,
+) -> Result<Vec<retention_runs::Model>, AppError> {
+    let mut runs = Vec::with_capacity(RetentionTarget::ALL.len());
+
+    for target in RetentionTarget::ALL {
+        let started_at = Utc::now().fixed_offset();
+        let cutoff = started_at - Duration::days(target.retention_days(policy));
+
+        let (affected_rows, error) = match purge(db, target, cutoff, dry_run).await {
+            Ok(Some(affected_rows)) => (affected_rows, None),
+            Ok(None) => continue,
+            Err(e) => (0, Some(e.to_string())),
+        };
+
+        runs.push(
+            RetentionRuns::insert(retention_runs::ActiveModel {
+                target: Set(target.as_str().to_string()),
+                dry_run: Set(dry_run),
+                cutoff: Set(cutoff),
+                affected_rows: Set(affected_rows),
+                error: Set(error),
+                started_at: Set(started_at),
+                finished_at: Set(Utc::now().fixed_offset()),
+                ..Default::default()
+            })
+            .exec_with_returning(db)
+            .await?,
+        );
+    }
+
+    Ok(runs)
+}
+
+pub fn spawn_retention_scheduler(db: DatabaseConnection, policy: RetentionPolicy) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(
+            policy.interval_hours.max(1) * 60 * 60,
+        ));
+        loop {
+            interval.tick().await;
+            if let Err(e) = run_retention(&db, &policy, policy.dry_run).await {
+                eprintln!("Retention run failed: {}", e);
+            }
+        }
+    });
+}
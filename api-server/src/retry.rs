@@ -0,0 +1,153 @@
+use crate::config::RetryPolicy;
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use sea_orm::{sqlx, DbErr, RuntimeErr};
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    sync::{LazyLock, Mutex},
+    time::Duration,
+};
+
+static POLICY: LazyLock<RetryPolicy> = LazyLock::new(RetryPolicy::from_env);
+
+// per operation since startup, read by the retryMetrics admin query
+static COUNTS: LazyLock<Mutex<BTreeMap<&'static str, RetryCounts>>> =
+    LazyLock::new(Default::default);
+
+#[derive(Clone, Copy, Default)]
+pub struct RetryCounts {
+    pub retries: u64,
+    // succeeded after at least one retry
+    pub recovered: u64,
+    // still failing transiently when the attempts ran out
+    pub exhausted: u64,
+}
+
+pub fn retry_counts() -> Vec<(&'static str, RetryCounts)> {
+    COUNTS
+        .lock()
+        .map(|counts| {
+            counts
+                .iter()
+                .map(|(operation, counts)| (*operation, *counts))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn count(operation: &'static str, update: impl FnOnce(&mut RetryCounts)) {
+    if let Ok(mut counts) = COUNTS.lock() {
+        update(counts.entry(operation).or_default());
+    }
+}
+
+// serialization failures and deadlocks can simply run again, so can lost or unavailable connections
+pub fn is_transient_db(err: &DbErr) -> bool {
+    match err {
+        DbErr::ConnectionAcquire(_) => true,
+        DbErr::Conn(RuntimeErr::SqlxError(err))
+        | DbErr::Exec(RuntimeErr::SqlxError(err))
+        | DbErr::Query(RuntimeErr::SqlxError(err)) => match err {
+            sqlx::Error::Database(err) => matches!(err.code().as_deref(), Some("40001" | "40P01")),
+            sqlx::Error::Io(_)
+            | sqlx::Error::PoolTimedOut
+            | sqlx::Error::PoolClosed
+            | sqlx::Error::WorkerCrashed => true,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+// errors converted with `?` keep the DbErr, AppError's `.extend()` marks it instead
+fn is_transient_graphql(err: &async_graphql::Error) -> bool {
+    err.source
+        .as_ref()
+        .and_then(|source| source.downcast_ref::<DbErr>())
+        .is_some_and(is_transient_db)
+        || err.extensions.as_ref().and_then(|e| e.get("retryable"))
+            == Some(&async_graphql::Value::Boolean(true))
+}
+
+pub fn is_transient_redis(err: &redis::RedisError) -> bool {
+    err.is_io_error()
+        || err.is_connection_dropped()
+        || err.is_connection_refusal()
+        || err.is_timeout()
+}
+
+// exponential backoff with full jitter, so callers failing together don't come back together
+fn backoff(policy: &RetryPolicy, attempt: u32) -> Duration {
+    let ceiling = policy
+        .base_delay_ms
+        .saturating_mul(1 << attempt.min(16))
+        .min(policy.max_delay_ms);
+    Duration::from_millis(OsRng.next_u64() % (ceiling + 1))
+}
+
+// only for operations that are safe to run twice, the whole closure is called again on every attempt
+pub async fn retry<T, E, F, Fut>(
+    operation: &'static str,
+    is_transient: fn(&E) -> bool,
+    mut attempt: F,
+) -> Result<T, E>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let policy = &*POLICY;
+    let mut attempts = 1;
+    loop {
+        match attempt().await {
+            Ok(value) => {
+                if attempts > 1 {
+                    count(operation, |counts| counts.recovered += 1);
+                }
+                return Ok(value);
+            }
+            Err(err) if is_transient(&err) && attempts < policy.max_attempts => {
+                count(operation, |counts| counts.retries += 1);
+                tokio::time::sleep(backoff(policy, attempts)).await;
+                attempts += 1;
+            }
+            Err(err) => {
+                if is_transient(&err) {
+                    count(operation, |counts| counts.exhausted += 1);
+                }
+                return Err(err);
+            }
+        }
+    }
+}
+
+pub async fn retry_db<T, F, Fut>(operation: &'static str, attempt: F) -> Result<T, DbErr>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, DbErr>>,
+{
+    retry(operation, is_transient_db, attempt).await
+}
+
+// a serialization failure or deadlock aborts the whole transaction, so it only helps to run it
+// again from begin to commit. attempt has to be the whole transaction and do nothing outside it
+pub async fn retry_transaction<T, F, Fut>(
+    operation: &'static str,
+    attempt: F,
+) -> Result<T, async_graphql::Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, async_graphql::Error>>,
+{
+    retry(operation, is_transient_graphql, attempt).await
+}
+
+pub async fn retry_redis<T, F, Fut>(
+    operation: &'static str,
+    attempt: F,
+) -> Result<T, redis::RedisError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, redis::RedisError>>,
+{
+    retry(operation, is_transient_redis, attempt).await
+}
@@ -0,0 +1,356 @@
+use crate::{
+    error::AppError,
+    payment_gateway::{
+        Charge, ChargeStatus, PaymentGateway, ProviderDispute, ProviderPayment, ProviderRefund,
+        RefundStatus, TokenizedMethod, WebhookEvent,
+    },
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sea_orm::{
+    prelude::Decimal, ConnectOptions, ConnectionTrait, Database, DatabaseConnection,
+    DatabaseTransaction, DbBackend, DbErr, FromQueryResult, Statement,
+};
+
+// sandbox tables hanging off an order, see schema.sql
+const SANDBOX_ORDER_TABLES: [&str; 8] = [
+    "refunds",
+    "coupon_redemptions",
+    "order_items",
+    "bills",
+    "order_status_history",
+    "order_messages",
+    "order_address_changes",
+    "order_shipments",
+];
+
+// sandbox tables hanging off a product, cleared when a supplier's sandbox is reset
+const SANDBOX_PRODUCT_TABLES: [&str; 9] = [
+    "product_availability",
+    "product_region_prices",
+    "product_price_history",
+    "product_attributes",
+    "product_images",
+    "product_image_variants",
+    "product_videos",
+    "reviews",
+    "review_summaries",
+];
+
+// the part of the above copied over from the live catalog on a reset, column for column
+const COPIED_PRODUCT_TABLES: [&str; 6] = [
+    "product_availability",
+    "product_region_prices",
+    "product_attributes",
+    "product_images",
+    "product_image_variants",
+    "product_videos",
+];
+
+// sandbox tables of a customer, cleared when the customer's sandbox is reset
+const SANDBOX_CUSTOMER_TABLES: [&str; 4] = [
+    "payment_methods",
+    "shopping_carts",
+    "expired_carts",
+    "wishlist_items",
+];
+
+// the role sandbox connections run as, it can read production tables but write only the sandbox copies
+const SANDBOX_ROLE: &str = "sandbox_api";
+
+// a second pool whose search path puts the sandbox schema first, so the sandbox copies shadow
+// their production tables and everything without a copy still resolves to public. Writes to a
+// table without a copy fail, the role has no write access to public
+#[derive(Clone)]
+pub struct SandboxDb(pub DatabaseConnection);
+
+impl SandboxDb {
+    pub async fn connect(database_url: &str) -> Result<Self, AppError> {
+        let separator = if database_url.contains('?') { '&' } else { '?' };
+        let mut options = ConnectOptions::new(format!(
+            "{}{}options[role]={}",
+            database_url, separator, SANDBOX_ROLE
+        ));
+        options.set_schema_search_path("sandbox,public");
+        Database::connect(options)
+            .await
+            .map(SandboxDb)
+            .map_err(|e| AppError::Database {
+                message: "Failed to connect to the sandbox schema".to_string(),
+                source: e,
+                context: None,
+            })
+    }
+}
+
+// stands in for the payment provider on sandbox requests, every charge succeeds without money moving
+pub struct SandboxGateway;
+
+#[async_trait]
+impl PaymentGateway for SandboxGateway {
+    fn provider(&self) -> &'static str {
+        "sandbox"
+    }
+
+    async fn describe_method(&self, _token: &str) -> Result<TokenizedMethod, AppError> {
+        Ok(TokenizedMethod {
+            brand: Some("SANDBOX".to_string()),
+            last4: Some("4242".to_string()),
+            expiration: None,
+        })
+    }
+
+    async fn attach_method(
+        &self,
+        _token: &str,
+        provider_customer_id: Option<&str>,
+    ) -> Result<String, AppError> {
+        Ok(provider_customer_id
+            .unwrap_or("sandbox_customer")
+            .to_string())
+    }
+
+    async fn charge(
+        &self,
+        _token: &str,
+        _customer: Option<&str>,
+        _amount: Decimal,
+        _currency: &str,
+        idempotency_key: &str,
+    ) -> Result<Charge, AppError> {
+        Ok(Charge {
+            provider_payment_id: Some(format!("sandbox_{}", idempotency_key)),
+            status: ChargeStatus::Succeeded,
+            client_secret: None,
+        })
+    }
+
+    // already succeeded, there is nothing for the client to confirm
+    async fn create_payment_intent(
+        &self,
+        _amount: Decimal,
+        _currency: &str,
+        _order_id: i32,
+        idempotency_key: &str,
+    ) -> Result<Charge, AppError> {
+        Ok(Charge {
+            provider_payment_id: Some(format!("sandbox_{}", idempotency_key)),
+            status: ChargeStatus::Succeeded,
+            client_secret: None,
+        })
+    }
+
+    fn signature_header(&self) -> &'static str {
+        "x-sandbox-signature"
+    }
+
+    fn verify_webhook(&self, _payload: &[u8], _signature: &str) -> Result<WebhookEvent, AppError> {
+        Err(AppError::Internal(
+            "The sandbox gateway does not send webhooks".to_string(),
+        ))
+    }
+
+    fn dispute_from_event(&self, _payload: &str) -> Result<Option<ProviderDispute>, AppError> {
+        Ok(None)
+    }
+
+    fn payment_from_event(&self, _payload: &str) -> Result<Option<ProviderPayment>, AppError> {
+        Ok(None)
+    }
+
+    fn refund_from_event(&self, _payload: &str) -> Result<Option<ProviderRefund>, AppError> {
+        Ok(None)
+    }
+
+    async fn refund(
+        &self,
+        _provider_payment_id: &str,
+        _amount: Decimal,
+        _currency: &str,
+        idempotency_key: &str,
+    ) -> Result<ProviderRefund, AppError> {
+        Ok(ProviderRefund {
+            provider_refund_id: Some(format!("sandbox_{}", idempotency_key)),
+            status: RefundStatus::Succeeded,
+            idempotency_key: Some(idempotency_key.to_string()),
+        })
+    }
+
+    async fn list_payments(&self, _since: DateTime<Utc>) -> Result<Vec<ProviderPayment>, AppError> {
+        Ok(Vec::new())
+    }
+}
+
+#[derive(FromQueryResult)]
+struct SandboxOrder {
+    order_id: i32,
+}
+
+async fn purge_sandbox_orders(txn: &DatabaseTransaction, order_ids: Vec<i32>) -> Result<(), DbErr> {
+    if order_ids.is_empty() {
+        return Ok(());
+    }
+
+    txn.execute(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        r#"DELETE FROM sandbox.refund_items
+            WHERE refund_id IN (SELECT refund_id FROM sandbox.refunds WHERE order_id = ANY($1))"#,
+        vec![order_ids.clone().into()],
+    ))
+    .await?;
+    txn.execute(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        r#"DELETE FROM sandbox.return_requests
+            WHERE order_item_id IN (SELECT order_item_id FROM sandbox.order_items WHERE order_id = ANY($1))"#,
+        vec![order_ids.clone().into()],
+    ))
+    .await?;
+    for table in SANDBOX_ORDER_TABLES {
+        txn.execute(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            format!("DELETE FROM sandbox.{} WHERE order_id = ANY($1)", table),
+            vec![order_ids.clone().into()],
+        ))
+        .await?;
+    }
+    txn.execute(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        "DELETE FROM sandbox.orders WHERE order_id = ANY($1)",
+        vec![order_ids.into()],
+    ))
+    .await?;
+    Ok(())
+}
+
+// drops the supplier's sandbox orders, reviews and sponsored campaigns and replaces their sandbox catalog with a
+// copy of the live one, category counts have to be rebuilt on the sandbox connection afterwards
+pub async fn reset_supplier_sandbox(
+    txn: &DatabaseTransaction,
+    supplier_id: i32,
+) -> Result<(), DbErr> {
+    let order_ids = SandboxOrder::find_by_statement(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        r#"SELECT DISTINCT oi.order_id
+            FROM sandbox.order_items oi
+            JOIN sandbox.products p ON p.product_id = oi.product_id
+            WHERE p.supplier_id = $1"#,
+        vec![supplier_id.into()],
+    ))
+    .all(txn)
+    .await?
+    .into_iter()
+    .map(|order| order.order_id)
+    .collect();
+    purge_sandbox_orders(txn, order_ids).await?;
+
+    txn.execute(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        r#"DELETE FROM sandbox.sponsored_clicks
+            WHERE campaign_id IN (SELECT campaign_id FROM sandbox.sponsored_campaigns WHERE supplier_id = $1)"#,
+        vec![supplier_id.into()],
+    ))
+    .await?;
+    txn.execute(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        "DELETE FROM sandbox.sponsored_campaigns WHERE supplier_id = $1",
+        vec![supplier_id.into()],
+    ))
+    .await?;
+    for table in SANDBOX_PRODUCT_TABLES {
+        txn.execute(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            format!(
+                r#"DELETE FROM sandbox.{}
+                    WHERE product_id IN (SELECT product_id FROM sandbox.products WHERE supplier_id = $1)"#,
+                table
+            ),
+            vec![supplier_id.into()],
+        ))
+        .await?;
+    }
+    txn.execute(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        "DELETE FROM sandbox.products WHERE supplier_id = $1",
+        vec![supplier_id.into()],
+    ))
+    .await?;
+    txn.execute(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        "INSERT INTO sandbox.products SELECT * FROM public.products WHERE supplier_id = $1",
+        vec![supplier_id.into()],
+    ))
+    .await?;
+    for table in COPIED_PRODUCT_TABLES {
+        txn.execute(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            format!(
+                r#"INSERT INTO sandbox.{0}
+                    SELECT t.* FROM public.{0} t
+                    JOIN public.products p ON p.product_id = t.product_id
+                    WHERE p.supplier_id = $1"#,
+                table
+            ),
+            vec![supplier_id.into()],
+        ))
+        .await?;
+    }
+    // the insert trigger has just recorded today's price, the live history is copied next to it
+    txn.execute(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        r#"INSERT INTO sandbox.product_price_history (product_id, price, recorded_at)
+            SELECT h.product_id, h.price, h.recorded_at FROM public.product_price_history h
+            JOIN public.products p ON p.product_id = h.product_id
+            WHERE p.supplier_id = $1"#,
+        vec![supplier_id.into()],
+    ))
+    .await?;
+    Ok(())
+}
+
+// drops the customer's sandbox orders, carts, wishlist, coupon redemptions and payment methods
+pub async fn reset_customer_sandbox(
+    txn: &DatabaseTransaction,
+    customer_id: i32,
+) -> Result<(), DbErr> {
+    let order_ids = SandboxOrder::find_by_statement(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        "SELECT order_id FROM sandbox.orders WHERE customer_id = $1",
+        vec![customer_id.into()],
+    ))
+    .all(txn)
+    .await?
+    .into_iter()
+    .map(|order| order.order_id)
+    .collect();
+    purge_sandbox_orders(txn, order_ids).await?;
+
+    // the copies have no foreign keys, so nothing cascades
+    for table in ["cart_items", "cart_coupons"] {
+        txn.execute(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            format!(
+                r#"DELETE FROM sandbox.{}
+                    WHERE cart_id IN (SELECT cart_id FROM sandbox.shopping_carts WHERE customer_id = $1)"#,
+                table
+            ),
+            vec![customer_id.into()],
+        ))
+        .await?;
+    }
+    txn.execute(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        r#"DELETE FROM sandbox.expired_cart_items
+            WHERE expired_cart_id IN (SELECT expired_cart_id FROM sandbox.expired_carts WHERE customer_id = $1)"#,
+        vec![customer_id.into()],
+    ))
+    .await?;
+    for table in SANDBOX_CUSTOMER_TABLES {
+        txn.execute(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            format!("DELETE FROM sandbox.{} WHERE customer_id = $1", table),
+            vec![customer_id.into()],
+        ))
+        .await?;
+    }
+    Ok(())
+}
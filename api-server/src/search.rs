@@ -0,0 +1,75 @@
+use crate::{
+    entity::{prelude::Products, products},
+    error::AppError,
+};
+use sea_orm::{
+    sea_query::{Expr, LikeExpr, SimpleExpr},
+    ColumnTrait, Order, QueryFilter, QueryOrder, Select,
+};
+
+// products.search_vector is built with the simple configuration, no stemming or stop words, since
+// the catalog isn't in one language
+const SEARCH_MATCH: &str = "products.search_vector @@ to_tsquery('simple', $1)";
+const SEARCH_RANK: &str = "ts_rank_cd(products.search_vector, to_tsquery('simple', $1))";
+
+// product names are varchar(100), nothing longer can ever match
+pub const MAX_SEARCH_CHARS: usize = 100;
+
+// punctuation that shows up in product names, everything else outside letters and digits becomes a space
+const ALLOWED_PUNCTUATION: &[char] = &['-', '\'', '.', '&', '/', '+', '#', ',', '(', ')'];
+
+// every free text search goes through here before it reaches a query, an empty result matches everything
+pub fn sanitize_search(input: &str) -> Result<String, AppError> {
+    if input.chars().count() > MAX_SEARCH_CHARS {
+        return Err(AppError::Validation {
+            message: format!("Search is limited to {} characters", MAX_SEARCH_CHARS),
+            failed_rules: vec!["SEARCH_TOO_LONG".to_string()],
+        });
+    }
+
+    let cleaned: String = input
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || ALLOWED_PUNCTUATION.contains(&c) {
+                c
+            } else {
+                ' '
+            }
+        })
+        .collect();
+    Ok(cleaned.split_whitespace().collect::<Vec<_>>().join(" "))
+}
+
+// column LIKE '%term%' with the term's own % and _ taken literally, unlike ColumnTrait::contains
+pub fn like_contains<C: ColumnTrait>(column: C, term: &str) -> SimpleExpr {
+    let mut pattern = String::with_capacity(term.len() + 2);
+    pattern.push('%');
+    for c in term.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('%');
+    Expr::col((column.entity_name(), column)).like(LikeExpr::new(pattern).escape('\\'))
+}
+
+// the words of a sanitized search as a tsquery, each one matching as a prefix and all of them
+// required. None when nothing searchable is left
+pub fn prefix_tsquery(search: &str) -> Option<String> {
+    let words: Vec<String> = search
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| format!("{}:*", word.to_lowercase()))
+        .collect();
+    (!words.is_empty()).then(|| words.join(" & "))
+}
+
+// keeps the products matching the tsquery, best matches first. Name hits weigh more than
+// description hits and ties go to the older product
+pub fn full_text_search(query: Select<Products>, tsquery: &str) -> Select<Products> {
+    query
+        .filter(Expr::cust_with_values(SEARCH_MATCH, [tsquery]))
+        .order_by(Expr::cust_with_values(SEARCH_RANK, [tsquery]), Order::Desc)
+        .order_by_asc(products::Column::ProductId)
+}
@@ -0,0 +1,321 @@
+use crate::{
+    auth::{Auth, Claims},
+    config::SessionPolicy,
+    error::{AppError, AuthErrorCode},
+    retry::retry_redis,
+};
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use chrono::Utc;
+use redis::AsyncCommands;
+
+// refresh_token:<sha256 of the token> holds the user id until the token is used, revoked or expires
+const REFRESH_TOKEN_PREFIX: &str = "refresh_token:";
+// revoked_token:<sha256 of the access token> is kept until the access token would have expired anyway
+const REVOKED_TOKEN_PREFIX: &str = "revoked_token:";
+// user_refresh_tokens:<user id> is the set of the user's refresh token hashes, so all of them can be revoked
+const USER_REFRESH_TOKENS_PREFIX: &str = "user_refresh_tokens:";
+// sessions_revoked:<user id> holds the time access tokens issued up to then stopped being honoured
+const SESSIONS_REVOKED_PREFIX: &str = "sessions_revoked:";
+// password_reset:<sha256 of the token> holds the user id until the token is used or expires
+const PASSWORD_RESET_PREFIX: &str = "password_reset:";
+// user_password_reset:<user id> is the hash of the user's latest reset token, asking again voids the one before
+const USER_PASSWORD_RESET_PREFIX: &str = "user_password_reset:";
+// email_verification:<sha256 of the token> and user_email_verification:<user id>, the same for verification links
+const EMAIL_VERIFICATION_PREFIX: &str = "email_verification:";
+const USER_EMAIL_VERIFICATION_PREFIX: &str = "user_email_verification:";
+
+fn redis_error(e: redis::RedisError) -> AppError {
+    AppError::Internal(format!("Session store unavailable: {}", e))
+}
+
+fn invalid_refresh_token() -> AppError {
+    AppError::Auth {
+        message: "Refresh token is invalid, expired or already used".to_string(),
+        code: AuthErrorCode::InvalidCredentials,
+        user_id: None,
+    }
+}
+
+// only the hash is stored, the token itself goes to the client once
+pub async fn issue_refresh_token(
+    redis: &redis::Client,
+    user_id: i32,
+    ttl_days: i64,
+) -> Result<String, AppError> {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    let token: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    let hash = Auth::hash_secret(&token);
+    let key = format!("{}{}", REFRESH_TOKEN_PREFIX, hash);
+    let user_key = format!("{}{}", USER_REFRESH_TOKENS_PREFIX, user_id);
+    let ttl = ttl_days * 24 * 60 * 60;
+
+    retry_redis("refresh_token_issue", || async {
+        let mut connection = redis.get_multiplexed_async_connection().await?;
+        redis::pipe()
+            .atomic()
+            .set_ex(&key, user_id, ttl as u64)
+            .ignore()
+            .sadd(&user_key, &hash)
+            .ignore()
+            .expire(&user_key, ttl)
+            .ignore()
+            .query_async::<()>(&mut connection)
+            .await
+    })
+    .await
+    .map_err(redis_error)?;
+    Ok(token)
+}
+
+// takes the token out in one step, so a refresh token can be traded in exactly once
+pub async fn consume_refresh_token(
+    redis: &redis::Client,
+    refresh_token: &str,
+) -> Result<i32, AppError> {
+    let key = format!(
+        "{}{}",
+        REFRESH_TOKEN_PREFIX,
+        Auth::hash_secret(refresh_token)
+    );
+    let user_id: Option<i32> = retry_redis("refresh_token_consume", || async {
+        let mut connection = redis.get_multiplexed_async_connection().await?;
+        connection.get_del(&key).await
+    })
+    .await
+    .map_err(redis_error)?;
+    let user_id = user_id.ok_or_else(invalid_refresh_token)?;
+    forget_refresh_token(redis, user_id, refresh_token).await?;
+    Ok(user_id)
+}
+
+async fn forget_refresh_token(
+    redis: &redis::Client,
+    user_id: i32,
+    refresh_token: &str,
+) -> Result<(), AppError> {
+    let user_key = format!("{}{}", USER_REFRESH_TOKENS_PREFIX, user_id);
+    retry_redis("refresh_token_forget", || async {
+        let mut connection = redis.get_multiplexed_async_connection().await?;
+        connection
+            .srem::<_, _, ()>(&user_key, Auth::hash_secret(refresh_token))
+            .await
+    })
+    .await
+    .map_err(redis_error)
+}
+
+pub async fn revoke_refresh_token(
+    redis: &redis::Client,
+    refresh_token: &str,
+) -> Result<(), AppError> {
+    let key = format!(
+        "{}{}",
+        REFRESH_TOKEN_PREFIX,
+        Auth::hash_secret(refresh_token)
+    );
+    let user_id: Option<i32> = retry_redis("refresh_token_revoke", || async {
+        let mut connection = redis.get_multiplexed_async_connection().await?;
+        connection.get_del(&key).await
+    })
+    .await
+    .map_err(redis_error)?;
+    match user_id {
+        Some(user_id) => forget_refresh_token(redis, user_id, refresh_token).await,
+        None => Ok(()),
+    }
+}
+
+// signs the user out everywhere, every refresh token goes and access tokens issued until now are refused
+pub async fn revoke_user_sessions(
+    redis: &redis::Client,
+    policy: &SessionPolicy,
+    user_id: i32,
+) -> Result<(), AppError> {
+    let user_key = format!("{}{}", USER_REFRESH_TOKENS_PREFIX, user_id);
+    let revoked_key = format!("{}{}", SESSIONS_REVOKED_PREFIX, user_id);
+    retry_redis("user_sessions_revoke", || async {
+        let mut connection = redis.get_multiplexed_async_connection().await?;
+        let hashes: Vec<String> = connection.smembers(&user_key).await?;
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        for hash in &hashes {
+            pipe.del(format!("{}{}", REFRESH_TOKEN_PREFIX, hash))
+                .ignore();
+        }
+        pipe.del(&user_key)
+            .ignore()
+            .set_ex(
+                &revoked_key,
+                Utc::now().timestamp(),
+                (policy.access_token_minutes * 60) as u64,
+            )
+            .ignore()
+            .query_async::<()>(&mut connection)
+            .await
+    })
+    .await
+    .map_err(redis_error)
+}
+
+pub async fn revoke_access_token(
+    redis: &redis::Client,
+    token: &str,
+    expires_at: i64,
+) -> Result<(), AppError> {
+    let remaining = expires_at - Utc::now().timestamp();
+    if remaining <= 0 {
+        return Ok(());
+    }
+    let key = format!("{}{}", REVOKED_TOKEN_PREFIX, Auth::hash_secret(token));
+    retry_redis("access_token_revoke", || async {
+        let mut connection = redis.get_multiplexed_async_connection().await?;
+        connection
+            .set_ex::<_, _, ()>(&key, 1, remaining as u64)
+            .await
+    })
+    .await
+    .map_err(redis_error)
+}
+
+// logged out on its own, or issued before all of the user's sessions were revoked
+pub async fn is_access_token_revoked(
+    redis: &redis::Client,
+    token: &str,
+    claims: &Claims,
+) -> Result<bool, AppError> {
+    let key = format!("{}{}", REVOKED_TOKEN_PREFIX, Auth::hash_secret(token));
+    let revoked_key = format!("{}{}", SESSIONS_REVOKED_PREFIX, claims.user_id);
+    let (revoked, sessions_revoked_at): (bool, Option<i64>) =
+        retry_redis("access_token_check", || async {
+            let mut connection = redis.get_multiplexed_async_connection().await?;
+            redis::pipe()
+                .exists(&key)
+                .get(&revoked_key)
+                .query_async(&mut connection)
+                .await
+        })
+        .await
+        .map_err(redis_error)?;
+    Ok(revoked || sessions_revoked_at.is_some_and(|revoked_at| claims.iat <= revoked_at))
+}
+
+// one kind of emailed single use token, keyed by the hash of the token with a second key per user
+// pointing at the latest one so issuing a new token voids the one before
+struct EmailedToken {
+    prefix: &'static str,
+    user_prefix: &'static str,
+    invalid_message: &'static str,
+}
+
+const PASSWORD_RESET_TOKEN: EmailedToken = EmailedToken {
+    prefix: PASSWORD_RESET_PREFIX,
+    user_prefix: USER_PASSWORD_RESET_PREFIX,
+    invalid_message: "Reset link is invalid, expired or already used",
+};
+
+const EMAIL_VERIFICATION_TOKEN: EmailedToken = EmailedToken {
+    prefix: EMAIL_VERIFICATION_PREFIX,
+    user_prefix: USER_EMAIL_VERIFICATION_PREFIX,
+    invalid_message: "Verification link is invalid, expired or already used",
+};
+
+// only the hash is stored, the token itself goes out by email
+async fn issue_emailed_token(
+    redis: &redis::Client,
+    kind: &EmailedToken,
+    user_id: i32,
+    ttl_seconds: i64,
+) -> Result<String, AppError> {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    let token: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    let hash = Auth::hash_secret(&token);
+    let key = format!("{}{}", kind.prefix, hash);
+    let user_key = format!("{}{}", kind.user_prefix, user_id);
+    let ttl = ttl_seconds as u64;
+
+    retry_redis("emailed_token_issue", || async {
+        let mut connection = redis.get_multiplexed_async_connection().await?;
+        let previous: Option<String> = connection.get(&user_key).await?;
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        if let Some(previous) = previous {
+            pipe.del(format!("{}{}", kind.prefix, previous)).ignore();
+        }
+        pipe.set_ex(&key, user_id, ttl)
+            .ignore()
+            .set_ex(&user_key, &hash, ttl)
+            .ignore()
+            .query_async::<()>(&mut connection)
+            .await
+    })
+    .await
+    .map_err(redis_error)?;
+    Ok(token)
+}
+
+// takes the token out in one step, so a link works exactly once
+async fn consume_emailed_token(
+    redis: &redis::Client,
+    kind: &EmailedToken,
+    token: &str,
+) -> Result<i32, AppError> {
+    let key = format!("{}{}", kind.prefix, Auth::hash_secret(token));
+    let user_id: Option<i32> = retry_redis("emailed_token_consume", || async {
+        let mut connection = redis.get_multiplexed_async_connection().await?;
+        connection.get_del(&key).await
+    })
+    .await
+    .map_err(redis_error)?;
+    let user_id = user_id.ok_or_else(|| AppError::Auth {
+        message: kind.invalid_message.to_string(),
+        code: AuthErrorCode::InvalidCredentials,
+        user_id: None,
+    })?;
+
+    let user_key = format!("{}{}", kind.user_prefix, user_id);
+    retry_redis("emailed_token_forget", || async {
+        let mut connection = redis.get_multiplexed_async_connection().await?;
+        connection.del::<_, ()>(&user_key).await
+    })
+    .await
+    .map_err(redis_error)?;
+    Ok(user_id)
+}
+
+pub async fn issue_password_reset_token(
+    redis: &redis::Client,
+    user_id: i32,
+    ttl_minutes: i64,
+) -> Result<String, AppError> {
+    issue_emailed_token(redis, &PASSWORD_RESET_TOKEN, user_id, ttl_minutes * 60).await
+}
+
+pub async fn consume_password_reset_token(
+    redis: &redis::Client,
+    token: &str,
+) -> Result<i32, AppError> {
+    consume_emailed_token(redis, &PASSWORD_RESET_TOKEN, token).await
+}
+
+pub async fn issue_email_verification_token(
+    redis: &redis::Client,
+    user_id: i32,
+    ttl_hours: i64,
+) -> Result<String, AppError> {
+    issue_emailed_token(
+        redis,
+        &EMAIL_VERIFICATION_TOKEN,
+        user_id,
+        ttl_hours * 60 * 60,
+    )
+    .await
+}
+
+pub async fn consume_email_verification_token(
+    redis: &redis::Client,
+    token: &str,
+) -> Result<i32, AppError> {
+    consume_emailed_token(redis, &EMAIL_VERIFICATION_TOKEN, token).await
+}
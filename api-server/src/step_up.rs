@@ -0,0 +1,191 @@
+use crate::{
+    auth::Auth,
+    config::StepUpPolicy,
+    entity::{
+        login_challenges,
+        prelude::{
+            LoginChallenges as LoginChallengesEntity, SecurityEvents as SecurityEventsEntity,
+            Users as UsersEntity,
+        },
+        security_events, users,
+    },
+    error::{AppError, AuthErrorCode},
+    ids::TenantId,
+    notifications::{send_templated, TemplateKey},
+};
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use chrono::{Duration, Utc};
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ActiveValue::Set, ColumnTrait, Condition, ConnectionTrait,
+    DatabaseConnection, DbBackend, EntityTrait, QueryFilter, Statement,
+};
+
+pub const EVENT_LOGIN_SUCCEEDED: &str = "LOGIN_SUCCEEDED";
+pub const EVENT_LOGIN_FAILED: &str = "LOGIN_FAILED";
+pub const EVENT_STEP_UP_CHALLENGED: &str = "STEP_UP_CHALLENGED";
+pub const EVENT_STEP_UP_PASSED: &str = "STEP_UP_PASSED";
+pub const EVENT_ROLE_ADDED: &str = "ROLE_ADDED";
+pub const EVENT_PASSWORD_RESET: &str = "PASSWORD_RESET";
+pub const EVENT_PASSWORD_CHANGED: &str = "PASSWORD_CHANGED";
+pub const EVENT_PROFILE_UPDATED: &str = "PROFILE_UPDATED";
+
+// emails and IP addresses are kept as given, suspicious IPs are matched and investigated by them
+pub async fn record_security_event(
+    db: &DatabaseConnection,
+    user_id: Option<i32>,
+    email: Option<String>,
+    ip_address: Option<String>,
+    event_type: &str,
+) -> Result<(), AppError> {
+    let event = security_events::ActiveModel {
+        user_id: Set(user_id),
+        email: Set(email),
+        ip_address: Set(ip_address),
+        event_type: Set(event_type.to_string()),
+        ..Default::default()
+    };
+    SecurityEventsEntity::insert(event).exec(db).await?;
+
+    Ok(())
+}
+
+// When one IP has been trying many different emails, every account it touched in the window
+// has to pass an emailed one time code on its next logins until the flag runs out.
+// Returns whether the IP is currently considered suspicious.
+pub async fn flag_accounts_from_suspicious_ip(
+    db: &DatabaseConnection,
+    ip_address: &str,
+    policy: &StepUpPolicy,
+) -> Result<bool, AppError> {
+    let window_start = Utc::now() - Duration::minutes(policy.window_minutes);
+
+    let distinct_emails = db
+        .query_one(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            "SELECT COUNT(DISTINCT lower(email)) AS emails FROM security_events
+                WHERE ip_address = $1 AND event_type IN ($2, $3) AND created_at > $4;
+                ",
+            vec![
+                ip_address.into(),
+                EVENT_LOGIN_SUCCEEDED.into(),
+                EVENT_LOGIN_FAILED.into(),
+                window_start.into(),
+            ],
+        ))
+        .await?
+        .map(|row| row.try_get::<i64>("", "emails"))
+        .transpose()?
+        .unwrap_or(0);
+
+    if distinct_emails < policy.distinct_emails {
+        return Ok(false);
+    }
+
+    db.execute(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        "UPDATE users SET step_up_until = $1
+            WHERE user_id IN (
+                SELECT DISTINCT user_id FROM security_events
+                WHERE ip_address = $2 AND created_at > $3
+            );
+            ",
+        vec![
+            (Utc::now() + Duration::hours(policy.flag_hours)).into(),
+            ip_address.into(),
+            window_start.into(),
+        ],
+    ))
+    .await?;
+
+    Ok(true)
+}
+
+pub fn requires_step_up(user: &users::Model) -> bool {
+    user.step_up_until
+        .is_some_and(|step_up_until| step_up_until > Utc::now())
+}
+
+pub async fn issue_login_challenge(
+    db: &DatabaseConnection,
+    user: &users::Model,
+    policy: &StepUpPolicy,
+) -> Result<i32, AppError> {
+    let code = format!("{:06}", OsRng.next_u32() % 1_000_000);
+
+    let challenge = login_challenges::ActiveModel {
+        user_id: Set(user.user_id),
+        code_hash: Set(Auth::hash_secret(&code)),
+        expires_at: Set((Utc::now() + Duration::minutes(policy.otp_ttl_minutes)).fixed_offset()),
+        ..Default::default()
+    }
+    .insert(db)
+    .await?;
+
+    send_templated(
+        db,
+        TenantId(user.tenant_id),
+        TemplateKey::LoginCode,
+        user.email.clone(),
+        user.locale.as_deref(),
+        &[
+            ("code", code),
+            ("ttl_minutes", policy.otp_ttl_minutes.to_string()),
+        ],
+    )
+    .await?;
+
+    Ok(challenge.challenge_id)
+}
+
+pub async fn verify_login_challenge(
+    db: &DatabaseConnection,
+    challenge_id: i32,
+    code: &str,
+    policy: &StepUpPolicy,
+) -> Result<users::Model, AppError> {
+    let invalid_code = |user_id: Option<i32>| AppError::Auth {
+        message: "Invalid or expired login code".to_string(),
+        code: AuthErrorCode::InvalidCredentials,
+        user_id: user_id.map(|id| id.to_string()),
+    };
+
+    let (_, user) = LoginChallengesEntity::find_by_id(challenge_id)
+        .find_also_related(UsersEntity)
+        .one(db)
+        .await?
+        .ok_or_else(|| invalid_code(None))?;
+    let user = user.ok_or_else(|| invalid_code(None))?;
+
+    // every guess counts as an attempt. The checks and the count happen in the update itself,
+    // so guesses sent in parallel can't get past the limit or use a code twice
+    let usable = || {
+        Condition::all()
+            .add(login_challenges::Column::ChallengeId.eq(challenge_id))
+            .add(login_challenges::Column::ConsumedAt.is_null())
+            .add(login_challenges::Column::ExpiresAt.gt(Utc::now()))
+            .add(login_challenges::Column::Attempts.lt(policy.otp_max_attempts))
+    };
+    let attempt = || {
+        LoginChallengesEntity::update_many().col_expr(
+            login_challenges::Column::Attempts,
+            Expr::col(login_challenges::Column::Attempts).add(1),
+        )
+    };
+
+    let consumed = attempt()
+        .col_expr(
+            login_challenges::Column::ConsumedAt,
+            Expr::current_timestamp().into(),
+        )
+        .filter(usable())
+        .filter(login_challenges::Column::CodeHash.eq(Auth::hash_secret(code)))
+        .exec(db)
+        .await?
+        .rows_affected;
+    if consumed == 0 {
+        attempt().filter(usable()).exec(db).await?;
+        return Err(invalid_code(Some(user.user_id)));
+    }
+
+    Ok(user)
+}
@@ -0,0 +1,36 @@
+use crate::{config::TelemetryPolicy, error::AppError};
+use opentelemetry::{trace::TracerProvider as _, KeyValue};
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{runtime, trace::TracerProvider, Resource};
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+
+// sends spans to the collector in OTEL_EXPORTER_OTLP_ENDPOINT: one per HTTP request, one per
+// resolver under it, and the SQL statements sqlx logs as events on whichever span ran them.
+// RUST_LOG narrows what is recorded, everything at info and above by default. Without an
+// endpoint no subscriber is installed and the spans cost next to nothing
+pub fn init_tracing(policy: &TelemetryPolicy) -> Result<Option<TracerProvider>, AppError> {
+    let Some(endpoint) = &policy.otlp_endpoint else {
+        return Ok(None);
+    };
+
+    let exporter = SpanExporter::builder()
+        .with_tonic()
+        .with_endpoint(endpoint)
+        .build()
+        .map_err(|e| AppError::Internal(format!("Failed to set up the OTLP exporter: {}", e)))?;
+    let provider = TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::Tokio)
+        .with_resource(Resource::new([KeyValue::new(
+            "service.name",
+            policy.service_name.clone(),
+        )]))
+        .build();
+
+    tracing_subscriber::registry()
+        .with(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")))
+        .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("api-server")))
+        .try_init()
+        .map_err(|e| AppError::Internal(format!("Failed to install tracing: {}", e)))?;
+
+    Ok(Some(provider))
+}
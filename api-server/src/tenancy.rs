@@ -0,0 +1,155 @@
+use crate::{
+    config::env_or,
+    entity::{
+        accounting_exports, announcements, boost_rules, canned_responses, categories, discounts,
+        email_suppressions, email_templates, orders, policy_versions,
+        prelude::{
+            AccountingExports, Announcements, BoostRules, CannedResponses, Categories, Discounts,
+            EmailSuppressions, EmailTemplates, Orders, PolicyVersions, Products,
+            Tenants as TenantsEntity, Users,
+        },
+        products, users,
+    },
+    error::AppError,
+    ids::TenantId,
+};
+use axum::http::{header::HOST, HeaderMap};
+use sea_orm::{ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, Select};
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+pub const TENANT_HEADER: &str = "x-tenant";
+
+// seeded by schema.sql, owns everything created before tenants existed
+pub const DEFAULT_TENANT: TenantId = TenantId(1);
+
+// prefixes a cache or rate limit key so two marketplaces never share an entry
+pub fn tenant_key(tenant: TenantId, key: &str) -> String {
+    format!("tenant:{}:{}", tenant, key)
+}
+
+// tokens issued before tenants existed carry no tenant and belong to the default one
+pub fn default_tenant_id() -> i32 {
+    DEFAULT_TENANT.0
+}
+
+#[derive(Default)]
+struct TenantMap {
+    by_slug: HashMap<String, TenantId>,
+    by_host: HashMap<String, TenantId>,
+}
+
+// slugs and hostnames of every tenant, resolved on each request so kept in memory
+// and refreshed every TENANT_REFRESH_SECS
+#[derive(Clone, Default)]
+pub struct TenantDirectory {
+    tenants: Arc<RwLock<TenantMap>>,
+}
+
+impl TenantDirectory {
+    pub async fn reload(&self, db: &DatabaseConnection) -> Result<(), DbErr> {
+        let mut map = TenantMap::default();
+        for tenant in TenantsEntity::find().all(db).await? {
+            let tenant_id = TenantId(tenant.tenant_id);
+            for hostname in tenant.hostnames {
+                map.by_host.insert(hostname.to_lowercase(), tenant_id);
+            }
+            map.by_slug.insert(tenant.slug.to_lowercase(), tenant_id);
+        }
+        *self.tenants.write().unwrap() = map;
+        Ok(())
+    }
+
+    pub fn spawn_refresher(&self, db: DatabaseConnection) {
+        let directory = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(
+                env_or("TENANT_REFRESH_SECS", 60u64).max(1),
+            ));
+            loop {
+                interval.tick().await;
+                if let Err(e) = directory.reload(&db).await {
+                    eprintln!("Loading tenants failed: {}", e);
+                }
+            }
+        });
+    }
+
+    // an explicit X-Tenant slug wins over the Host header, a host nobody claimed is the default marketplace
+    pub fn resolve(&self, headers: &HeaderMap) -> Result<TenantId, AppError> {
+        let tenants = self.tenants.read().unwrap();
+
+        if let Some(slug) = headers.get(TENANT_HEADER) {
+            let slug = slug.to_str().unwrap_or_default().trim().to_lowercase();
+            return tenants
+                .by_slug
+                .get(&slug)
+                .copied()
+                .ok_or_else(|| AppError::Validation {
+                    message: format!("Unknown tenant {}", slug),
+                    failed_rules: vec![TENANT_HEADER.to_string()],
+                });
+        }
+
+        let host = headers
+            .get(HOST)
+            .and_then(|value| value.to_str().ok())
+            .map(|host| host.split(':').next().unwrap_or(host).to_lowercase());
+        Ok(host
+            .and_then(|host| tenants.by_host.get(&host).copied())
+            .unwrap_or(DEFAULT_TENANT))
+    }
+}
+
+// entities that belong to a single marketplace
+pub trait TenantOwned: EntityTrait {
+    fn tenant_column() -> Self::Column;
+}
+
+macro_rules! tenant_owned {
+    ($($entity:ty => $column:path),* $(,)?) => {
+        $(
+            impl TenantOwned for $entity {
+                fn tenant_column() -> Self::Column {
+                    $column
+                }
+            }
+        )*
+    };
+}
+
+tenant_owned!(
+    AccountingExports => accounting_exports::Column::TenantId,
+    Announcements => announcements::Column::TenantId,
+    BoostRules => boost_rules::Column::TenantId,
+    CannedResponses => canned_responses::Column::TenantId,
+    Categories => categories::Column::TenantId,
+    Discounts => discounts::Column::TenantId,
+    EmailSuppressions => email_suppressions::Column::TenantId,
+    EmailTemplates => email_templates::Column::TenantId,
+    Orders => orders::Column::TenantId,
+    PolicyVersions => policy_versions::Column::TenantId,
+    Products => products::Column::TenantId,
+    Users => users::Column::TenantId,
+);
+
+// every read of a tenant owned table goes through this, so one marketplace never sees another's rows
+pub trait TenantScope {
+    fn for_tenant(self, tenant: TenantId) -> Self;
+}
+
+impl<E: TenantOwned> TenantScope for Select<E> {
+    fn for_tenant(self, tenant: TenantId) -> Self {
+        self.filter(E::tenant_column().eq(tenant.0))
+    }
+}
+
+// the marketplace the request was resolved to, background work without a request runs as the default one
+pub fn current_tenant(ctx: &async_graphql::Context<'_>) -> TenantId {
+    ctx.data_opt::<TenantId>()
+        .copied()
+        .unwrap_or(DEFAULT_TENANT)
+}
@@ -0,0 +1,85 @@
+use crate::{
+    auth::AuthenticatedUser,
+    entity::{policy_versions, prelude::PolicyVersions as PolicyVersionsEntity},
+    error::{AppError, AuthErrorCode},
+    ids::{TenantId, UserId},
+    permissions::caller_pending_policies,
+};
+use async_graphql::{Context, ErrorExtensions, Guard, Result};
+use sea_orm::{DatabaseConnection, DbBackend, DbErr, EntityTrait, Statement};
+
+// takes the tenant as $1
+const CURRENT_POLICY_VERSIONS: &str = "SELECT DISTINCT ON (policy_type) * FROM policy_versions
+    WHERE tenant_id = $1 AND published_at <= CURRENT_TIMESTAMP
+    ORDER BY policy_type, published_at DESC";
+
+// newest version of every policy type the tenant published
+pub async fn current_policy_versions(
+    db: &DatabaseConnection,
+    tenant: TenantId,
+) -> Result<Vec<policy_versions::Model>, DbErr> {
+    PolicyVersionsEntity::find()
+        .from_raw_sql(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            CURRENT_POLICY_VERSIONS,
+            vec![tenant.into()],
+        ))
+        .all(db)
+        .await
+}
+
+// current versions the user has not accepted yet
+pub async fn pending_policy_versions(
+    db: &DatabaseConnection,
+    tenant: TenantId,
+    user_id: UserId,
+) -> Result<Vec<policy_versions::Model>, DbErr> {
+    PolicyVersionsEntity::find()
+        .from_raw_sql(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            format!(
+                "SELECT current.* FROM ({}) current
+                    WHERE NOT EXISTS (
+                        SELECT 1 FROM policy_acceptances
+                        WHERE policy_acceptances.policy_version_id = current.policy_version_id
+                            AND policy_acceptances.user_id = $2
+                    );
+                    ",
+                CURRENT_POLICY_VERSIONS
+            ),
+            vec![tenant.into(), user_id.into()],
+        ))
+        .all(db)
+        .await
+}
+
+// Put in front of sensitive operations (orders, payment details, listings) together with the
+// role guard, so they fail until the caller accepted the latest terms and privacy policy
+pub struct TermsGuard;
+
+impl Guard for TermsGuard {
+    async fn check(&self, ctx: &Context<'_>) -> Result<()> {
+        let user_id = AuthenticatedUser::from_ctx(ctx)
+            .map_err(|e| e.extend())?
+            .user_id;
+        let pending = caller_pending_policies(ctx).await?;
+
+        if pending.is_empty() {
+            return Ok(());
+        }
+
+        Err(AppError::Auth {
+            message: format!(
+                "Please accept the latest policies first: {}",
+                pending
+                    .iter()
+                    .map(|policy| format!("{} {}", policy.policy_type, policy.version))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+            code: AuthErrorCode::TermsNotAccepted,
+            user_id: Some(user_id),
+        }
+        .extend())
+    }
+}
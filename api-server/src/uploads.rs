@@ -0,0 +1,328 @@
+use crate::{
+    config::UploadPolicy,
+    error::{AppError, UploadRejection},
+};
+use async_graphql::{Context, Upload};
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{header::CONTENT_LENGTH, header::CONTENT_TYPE, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use http_body_util::Limited;
+use sha2::{Digest, Sha256};
+use std::io::Read;
+
+#[derive(Clone, Copy)]
+pub struct BodyLimits {
+    pub query: usize,
+    pub upload: usize,
+}
+
+// only multipart requests carry files, every other body keeps the query limit. The GraphQL
+// extractor streams the body itself and never sees DefaultBodyLimit, so the body is capped here
+pub async fn limit_body(
+    State(limits): State<BodyLimits>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let multipart = request
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("multipart/form-data"));
+    let limit = if multipart {
+        limits.upload
+    } else {
+        limits.query
+    };
+    let declared = request
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<usize>().ok());
+    if declared.is_some_and(|length| length > limit) {
+        return StatusCode::PAYLOAD_TOO_LARGE.into_response();
+    }
+
+    let (parts, body) = request.into_parts();
+    next.run(Request::from_parts(
+        parts,
+        Body::new(Limited::new(body, limit)),
+    ))
+    .await
+}
+
+#[derive(Clone, Copy)]
+pub enum UploadKind {
+    Image,
+    Csv,
+    Video,
+    Zip,
+    Pdf,
+}
+
+pub struct ValidatedUpload {
+    // sniffed from the file contents, the client supplied content type is never trusted
+    pub content_type: &'static str,
+    pub extension: &'static str,
+    pub bytes: Vec<u8>,
+}
+
+impl ValidatedUpload {
+    // content addressed name so re-uploading the same file doesn't pile up copies
+    pub fn storage_name(&self) -> String {
+        let hash: String = Sha256::digest(&self.bytes)
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        format!("{}.{}", hash, self.extension)
+    }
+}
+
+fn reject(reason: UploadRejection, message: impl Into<String>) -> AppError {
+    AppError::UploadRejected {
+        message: message.into(),
+        reason,
+    }
+}
+
+// every upload mutation goes through here before anything touches storage or the database
+pub fn validate_upload(
+    ctx: &Context<'_>,
+    file: Upload,
+    kind: UploadKind,
+    policy: &UploadPolicy,
+) -> Result<ValidatedUpload, AppError> {
+    let value = file
+        .value(ctx)
+        .map_err(|e| reject(UploadRejection::Malformed, e.to_string()))?;
+    let filename = value.filename.clone();
+
+    // read one byte past the limit so oversized files are caught without buffering them whole
+    let mut bytes = Vec::new();
+    value
+        .into_read()
+        .take(max_bytes(kind, policy) as u64 + 1)
+        .read_to_end(&mut bytes)
+        .map_err(|e| reject(UploadRejection::Malformed, e.to_string()))?;
+
+    validate_bytes(&filename, bytes, kind, policy)
+}
+
+fn max_bytes(kind: UploadKind, policy: &UploadPolicy) -> usize {
+    match kind {
+        UploadKind::Image => policy.max_image_bytes,
+        UploadKind::Csv => policy.max_csv_bytes,
+        UploadKind::Video => policy.max_video_bytes,
+        UploadKind::Zip => policy.max_zip_bytes,
+        UploadKind::Pdf => policy.max_pdf_bytes,
+    }
+}
+
+// the checks on the contents, files unpacked from an archive go through here as well
+pub fn validate_bytes(
+    filename: &str,
+    bytes: Vec<u8>,
+    kind: UploadKind,
+    policy: &UploadPolicy,
+) -> Result<ValidatedUpload, AppError> {
+    let max_bytes = max_bytes(kind, policy);
+    if bytes.len() > max_bytes {
+        return Err(reject(
+            UploadRejection::TooLarge,
+            format!("{} exceeds the {} byte limit", filename, max_bytes),
+        ));
+    }
+
+    match kind {
+        UploadKind::Image => {
+            let (content_type, extension) = sniff_image(&bytes).ok_or_else(|| {
+                reject(
+                    UploadRejection::UnsupportedType,
+                    format!("{} is not a recognised image", filename),
+                )
+            })?;
+
+            if !policy
+                .allowed_image_types
+                .iter()
+                .any(|allowed| allowed == content_type)
+            {
+                return Err(reject(
+                    UploadRejection::UnsupportedType,
+                    format!("{} images are not accepted", content_type),
+                ));
+            }
+
+            let (width, height) = image_dimensions(content_type, &bytes)
+                .filter(|(width, height)| *width > 0 && *height > 0)
+                .ok_or_else(|| {
+                    reject(
+                        UploadRejection::Malformed,
+                        format!("Could not read the dimensions of {}", filename),
+                    )
+                })?;
+
+            if width.max(height) > policy.max_image_dimension {
+                return Err(reject(
+                    UploadRejection::DimensionsExceeded,
+                    format!(
+                        "{} is {}x{}, images may be at most {} pixels on either side",
+                        filename, width, height, policy.max_image_dimension
+                    ),
+                ));
+            }
+
+            Ok(ValidatedUpload {
+                content_type,
+                extension,
+                bytes,
+            })
+        }
+        UploadKind::Csv => {
+            if sniff_image(&bytes).is_some() || sniff_video(&bytes).is_some() || bytes.contains(&0)
+            {
+                return Err(reject(
+                    UploadRejection::UnsupportedType,
+                    format!("{} is not a CSV document", filename),
+                ));
+            }
+            if std::str::from_utf8(&bytes).is_err() {
+                return Err(reject(
+                    UploadRejection::Malformed,
+                    format!("{} must be UTF-8 encoded", filename),
+                ));
+            }
+
+            Ok(ValidatedUpload {
+                content_type: "text/csv",
+                extension: "csv",
+                bytes,
+            })
+        }
+        // only the local file header is checked here, the entries are looked at when unpacked
+        UploadKind::Zip => {
+            if !bytes.starts_with(b"PK\x03\x04") {
+                return Err(reject(
+                    UploadRejection::UnsupportedType,
+                    format!("{} is not a ZIP archive", filename),
+                ));
+            }
+
+            Ok(ValidatedUpload {
+                content_type: "application/zip",
+                extension: "zip",
+                bytes,
+            })
+        }
+        // only the header is checked, the document is stored as it was signed
+        UploadKind::Pdf => {
+            if !bytes.starts_with(b"%PDF-") {
+                return Err(reject(
+                    UploadRejection::UnsupportedType,
+                    format!("{} is not a PDF document", filename),
+                ));
+            }
+
+            Ok(ValidatedUpload {
+                content_type: "application/pdf",
+                extension: "pdf",
+                bytes,
+            })
+        }
+        // only the container is checked here, the video worker looks at the rest
+        UploadKind::Video => {
+            let (content_type, extension) = sniff_video(&bytes).ok_or_else(|| {
+                reject(
+                    UploadRejection::UnsupportedType,
+                    format!("{} is not an MP4 or WebM video", filename),
+                )
+            })?;
+
+            Ok(ValidatedUpload {
+                content_type,
+                extension,
+                bytes,
+            })
+        }
+    }
+}
+
+// MP4 opens with an ftyp box, WebM with an EBML header declaring the webm doctype
+fn sniff_video(bytes: &[u8]) -> Option<(&'static str, &'static str)> {
+    if bytes.len() >= 12 && &bytes[4..8] == b"ftyp" {
+        Some(("video/mp4", "mp4"))
+    } else if bytes.starts_with(&[0x1A, 0x45, 0xDF, 0xA3])
+        && bytes[..bytes.len().min(64)]
+            .windows(4)
+            .any(|w| w == b"webm")
+    {
+        Some(("video/webm", "webm"))
+    } else {
+        None
+    }
+}
+
+// magic bytes of the image formats we know how to measure
+fn sniff_image(bytes: &[u8]) -> Option<(&'static str, &'static str)> {
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some(("image/png", "png"))
+    } else if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some(("image/jpeg", "jpg"))
+    } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        Some(("image/gif", "gif"))
+    } else if bytes.len() >= 12 && &bytes[0..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        Some(("image/webp", "webp"))
+    } else {
+        None
+    }
+}
+
+fn image_dimensions(content_type: &str, bytes: &[u8]) -> Option<(u32, u32)> {
+    let be_u16 =
+        |at: usize| Some(u16::from_be_bytes(bytes.get(at..at + 2)?.try_into().ok()?) as u32);
+    let le_u16 =
+        |at: usize| Some(u16::from_le_bytes(bytes.get(at..at + 2)?.try_into().ok()?) as u32);
+    let le_u24 = |at: usize| {
+        let b = bytes.get(at..at + 3)?;
+        Some(b[0] as u32 | (b[1] as u32) << 8 | (b[2] as u32) << 16)
+    };
+
+    match content_type {
+        // IHDR is always the first chunk
+        "image/png" => Some((
+            u32::from_be_bytes(bytes.get(16..20)?.try_into().ok()?),
+            u32::from_be_bytes(bytes.get(20..24)?.try_into().ok()?),
+        )),
+        "image/gif" => Some((le_u16(6)?, le_u16(8)?)),
+        "image/webp" => match bytes.get(12..16)? {
+            b"VP8 " => Some((le_u16(26)? & 0x3FFF, le_u16(28)? & 0x3FFF)),
+            b"VP8L" => {
+                let bits = u32::from_le_bytes(bytes.get(21..25)?.try_into().ok()?);
+                Some(((bits & 0x3FFF) + 1, ((bits >> 14) & 0x3FFF) + 1))
+            }
+            b"VP8X" => Some((le_u24(24)? + 1, le_u24(27)? + 1)),
+            _ => None,
+        },
+        // walk the marker segments until the start-of-frame header
+        "image/jpeg" => {
+            let mut at = 2;
+            loop {
+                if *bytes.get(at)? != 0xFF {
+                    return None;
+                }
+                let marker = *bytes.get(at + 1)?;
+                match marker {
+                    0xFF => at += 1,
+                    0xC0..=0xCF if !matches!(marker, 0xC4 | 0xC8 | 0xCC) => {
+                        return Some((be_u16(at + 7)?, be_u16(at + 5)?));
+                    }
+                    _ => at += 2 + be_u16(at + 2)? as usize,
+                }
+            }
+        }
+        _ => None,
+    }
+}
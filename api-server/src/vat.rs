@@ -0,0 +1,53 @@
+use crate::error::AppError;
+use async_trait::async_trait;
+use lazy_regex::regex;
+
+#[async_trait]
+pub trait VatIdValidator: Send + Sync {
+    async fn is_valid(&self, country_code: &str, number: &str) -> Result<bool, AppError>;
+}
+
+// strips the usual separators and splits "DE 123.456.789" into ("DE", "123456789")
+pub fn normalize_vat_id(vat_id: &str) -> Option<(String, String)> {
+    let vat_id: String = vat_id
+        .chars()
+        .filter(|c| !matches!(c, ' ' | '.' | '-'))
+        .collect::<String>()
+        .to_uppercase();
+
+    if !regex!(r"^[A-Z]{2}[0-9A-Z]{2,12}$").is_match(&vat_id) {
+        return None;
+    }
+    let (country_code, number) = vat_id.split_at(2);
+    Some((country_code.to_string(), number.to_string()))
+}
+
+// EU VIES REST service
+#[derive(Default)]
+pub struct ViesValidator {
+    client: reqwest::Client,
+}
+
+#[async_trait]
+impl VatIdValidator for ViesValidator {
+    async fn is_valid(&self, country_code: &str, number: &str) -> Result<bool, AppError> {
+        let response: serde_json::Value = self
+            .client
+            .get(format!(
+                "https://ec.europa.eu/taxation_customs/vies/rest-api/ms/{}/vat/{}",
+                country_code, number
+            ))
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| AppError::Internal(format!("VIES lookup failed: {}", e)))?
+            .json()
+            .await
+            .map_err(|e| AppError::Internal(format!("VIES lookup failed: {}", e)))?;
+
+        Ok(response
+            .get("isValid")
+            .and_then(|valid| valid.as_bool())
+            .unwrap_or(false))
+    }
+}
@@ -1,38 +1,41 @@
-use crate::auth::Auth;
 use crate::entity::prelude::Users;
 use crate::entity::users::ActiveModel;
-use crate::error::{AppError, AuthErrorCode};
+use crate::error::AppError;
+use crate::sessions::consume_email_verification_token;
 use axum::extract::Path;
 use axum::response::IntoResponse;
 use axum::Extension;
 use sea_orm::ActiveValue::Set;
 use sea_orm::{ActiveModelTrait, DatabaseConnection, EntityTrait};
 
+// spends the emailed token and marks the address it was sent to as verified, returns the user id
+pub async fn mark_email_verified(
+    db: &DatabaseConnection,
+    redis: &redis::Client,
+    token: &str,
+) -> Result<i32, AppError> {
+    let user_id = consume_email_verification_token(redis, token).await?;
+    let user = Users::find_by_id(user_id)
+        .one(db)
+        .await?
+        .ok_or_else(|| AppError::Internal(format!("User {} not found", user_id)))?;
+
+    if !user.email_verified {
+        let mut user: ActiveModel = user.into();
+        user.email_verified = Set(true);
+        user.update(db).await?;
+    }
+    Ok(user_id)
+}
+
+// GET /verify/:token, the link in the verification email
 pub async fn verify_mail(
     Path(token): Path<String>,
     Extension(postgres): Extension<DatabaseConnection>,
+    Extension(redis): Extension<redis::Client>,
 ) -> impl IntoResponse {
-    let claims = Auth::verify_token(&token.clone()).map_err(|_| AppError::Auth {
-        message: "Invalid token".to_string(),
-        code: AuthErrorCode::InvalidCredentials,
-        user_id: None,
-    });
-
-    let claims = match claims {
-        Ok(c) => c,
-        Err(e) => return e.to_string(),
-    };
-
-    let user_id = claims.user_id.parse::<i32>().unwrap();
-    let user = Users::find_by_id(user_id)
-        .one(&postgres)
-        .await
-        .unwrap()
-        .unwrap();
-
-    let mut user: ActiveModel = user.into();
-    user.email_verified = Set(Some(true));
-    user.update(&postgres).await.unwrap();
-
-    "Email verified successfully".to_string()
+    match mark_email_verified(&postgres, &redis, &token).await {
+        Ok(_) => "Email verified successfully".to_string(),
+        Err(e) => e.to_string(),
+    }
 }
@@ -0,0 +1,236 @@
+use crate::{
+    entity::{prelude::ProductVideos, product_videos},
+    error::AppError,
+};
+use chrono::Utc;
+use lazy_regex::regex;
+use sea_orm::{ActiveModelTrait, ActiveValue::Set, DatabaseConnection, EntityTrait};
+use serde::Deserialize;
+use tokio::sync::mpsc::{self, error::TrySendError};
+
+// jobs waiting for the worker, an uploaded video is held in memory until it's checked
+const QUEUE_CAPACITY: usize = 4;
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub enum VideoProvider {
+    Youtube,
+    Vimeo,
+}
+
+impl VideoProvider {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            VideoProvider::Youtube => "YOUTUBE",
+            VideoProvider::Vimeo => "VIMEO",
+        }
+    }
+
+    fn oembed_endpoint(&self) -> &'static str {
+        match self {
+            VideoProvider::Youtube => "https://www.youtube.com/oembed",
+            VideoProvider::Vimeo => "https://vimeo.com/api/oembed.json",
+        }
+    }
+}
+
+// only links to a single video on a provider we can embed are accepted,
+// the canonical watch URL is stored whatever form was pasted
+pub fn parse_video_url(url: &str) -> Option<(VideoProvider, String)> {
+    let url = url.trim();
+    if let Some(captures) = regex!(
+        r"^https?://(?:www\.|m\.)?(?:youtube\.com/(?:watch\?(?:[^#]*&)?v=|embed/|shorts/)|youtu\.be/)([A-Za-z0-9_-]{11})(?:[?&#/].*)?$"
+    )
+    .captures(url)
+    {
+        return Some((
+            VideoProvider::Youtube,
+            format!("https://www.youtube.com/watch?v={}", &captures[1]),
+        ));
+    }
+    if let Some(captures) =
+        regex!(r"^https?://(?:www\.|player\.)?vimeo\.com/(?:video/)?(\d+)(?:[?#/].*)?$")
+            .captures(url)
+    {
+        return Some((
+            VideoProvider::Vimeo,
+            format!("https://vimeo.com/{}", &captures[1]),
+        ));
+    }
+    None
+}
+
+pub enum VideoJob {
+    Upload {
+        video_id: i32,
+        bytes: Vec<u8>,
+    },
+    External {
+        video_id: i32,
+        provider: VideoProvider,
+        url: String,
+    },
+}
+
+impl VideoJob {
+    fn video_id(&self) -> i32 {
+        match self {
+            VideoJob::Upload { video_id, .. } | VideoJob::External { video_id, .. } => *video_id,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct VideoQueue {
+    sender: mpsc::Sender<(DatabaseConnection, VideoJob)>,
+}
+
+impl VideoQueue {
+    // a place in the queue, taken before the video is stored so a full queue leaves nothing behind
+    pub fn reserve(&self) -> Result<VideoSlot<'_>, AppError> {
+        self.sender
+            .try_reserve()
+            .map(VideoSlot)
+            .map_err(|e| match e {
+                TrySendError::Full(()) => AppError::invalid(
+                    "Too many videos are being processed, try again in a few minutes",
+                ),
+                TrySendError::Closed(()) => {
+                    AppError::Internal("Video worker is not running".to_string())
+                }
+            })
+    }
+}
+
+pub struct VideoSlot<'a>(mpsc::Permit<'a, (DatabaseConnection, VideoJob)>);
+
+impl VideoSlot<'_> {
+    // the video is processed on the connection it was stored with, sandbox videos stay in the sandbox
+    pub fn enqueue(self, db: &DatabaseConnection, job: VideoJob) {
+        self.0.send((db.clone(), job));
+    }
+}
+
+// videos are stored as PENDING and moved to READY or FAILED here, products only list the READY ones
+pub fn spawn_video_worker() -> VideoQueue {
+    let (sender, mut receiver) = mpsc::channel::<(DatabaseConnection, VideoJob)>(QUEUE_CAPACITY);
+    let client = reqwest::Client::new();
+
+    tokio::spawn(async move {
+        while let Some((db, job)) = receiver.recv().await {
+            let video_id = job.video_id();
+            if let Err(e) = process_video(&db, &client, job).await {
+                eprintln!("Video processing failed for video {}: {}", video_id, e);
+            }
+        }
+    });
+
+    VideoQueue { sender }
+}
+
+#[derive(Default, Deserialize)]
+struct OEmbed {
+    title: Option<String>,
+    thumbnail_url: Option<String>,
+}
+
+async fn process_video(
+    db: &DatabaseConnection,
+    client: &reqwest::Client,
+    job: VideoJob,
+) -> Result<(), AppError> {
+    let Some(video) = ProductVideos::find_by_id(job.video_id()).one(db).await? else {
+        // removed before the worker got to it
+        return Ok(());
+    };
+    let mut video: product_videos::ActiveModel = video.into();
+    video.status = Set("PROCESSING".to_string());
+    let video = video.update(db).await?;
+
+    let outcome = match job {
+        VideoJob::Upload { bytes, .. } => check_container(&bytes).map(|_| OEmbed::default()),
+        VideoJob::External { provider, url, .. } => fetch_oembed(client, provider, &url).await,
+    };
+
+    let mut video: product_videos::ActiveModel = video.into();
+    match outcome {
+        Ok(oembed) => {
+            video.status = Set("READY".to_string());
+            video.title = Set(oembed.title);
+            video.thumbnail_url = Set(oembed.thumbnail_url);
+            video.error = Set(None);
+        }
+        Err(reason) => {
+            video.status = Set("FAILED".to_string());
+            video.error = Set(Some(reason));
+        }
+    }
+    video.processed_at = Set(Some(Utc::now().fixed_offset()));
+    video.update(db).await?;
+
+    Ok(())
+}
+
+// the upload was sniffed as MP4 or WebM, this makes sure it is more than a header
+fn check_container(bytes: &[u8]) -> Result<(), String> {
+    if bytes.get(4..8) == Some(b"ftyp") {
+        // top level boxes are a 32 bit size and a fourcc, the movie metadata lives in moov
+        let mut at = 0usize;
+        while let Some(header) = bytes.get(at..at + 8) {
+            let size = u32::from_be_bytes(header[0..4].try_into().unwrap_or_default()) as usize;
+            if &header[4..8] == b"moov" {
+                return Ok(());
+            }
+            let size = match size {
+                // extends to the end of the file
+                0 => break,
+                // 64 bit size follows the fourcc
+                1 => bytes
+                    .get(at + 8..at + 16)
+                    .and_then(|large| large.try_into().ok())
+                    .map(u64::from_be_bytes)
+                    .and_then(|large| usize::try_from(large).ok())
+                    .ok_or("Truncated MP4 box")?,
+                size => size,
+            };
+            if size < 8 {
+                return Err("Malformed MP4 box".to_string());
+            }
+            at = at.saturating_add(size);
+        }
+        Err("The MP4 file has no movie metadata".to_string())
+    } else {
+        // the Segment element holds the tracks and clusters
+        if bytes.windows(4).any(|w| w == [0x18, 0x53, 0x80, 0x67]) {
+            Ok(())
+        } else {
+            Err("The WebM file has no segment".to_string())
+        }
+    }
+}
+
+// the provider answers its oEmbed endpoint only for public, embeddable videos
+async fn fetch_oembed(
+    client: &reqwest::Client,
+    provider: VideoProvider,
+    url: &str,
+) -> Result<OEmbed, String> {
+    let response = client
+        .get(provider.oembed_endpoint())
+        .query(&[("url", url), ("format", "json")])
+        .send()
+        .await
+        .map_err(|e| format!("Could not reach {}: {}", provider.as_str(), e))?;
+
+    let status = response.status();
+    if status.is_client_error() {
+        return Err("The video does not exist or cannot be embedded".to_string());
+    }
+    if !status.is_success() {
+        return Err(format!("{} answered with {}", provider.as_str(), status));
+    }
+
+    response
+        .json::<OEmbed>()
+        .await
+        .map_err(|e| format!("Unreadable response from {}: {}", provider.as_str(), e))
+}
@@ -1,6 +1,23 @@
+create extension if not exists pg_trgm;
+
 create type payment_method_type as enum ('netbanking', 'card', 'iban', 'upi');
 
-create type user_role as enum ('customer', 'supplier');
+create type user_role as enum ('customer', 'supplier', 'admin');
+
+create table tenants
+(
+    tenant_id  serial
+        primary key,
+    slug       varchar(50)  not null
+        unique,
+    name       varchar(100) not null,
+    hostnames  text[]                   default '{}'::text[] not null,
+    created_at timestamp with time zone default CURRENT_TIMESTAMP not null
+);
+
+-- rows created before tenants existed, and every single-marketplace deployment, belong to tenant 1
+insert into tenants (slug, name)
+values ('default', 'Default marketplace');
 
 create table categories
 (
@@ -10,7 +27,13 @@ create table categories
     parent_category_id integer
         constraint fk_parent_category
             references categories
-            on delete set null
+            on delete set null,
+    updated_at         timestamp with time zone default CURRENT_TIMESTAMP not null,
+    tenant_id          integer default 1 not null
+        constraint fk_category_tenant
+            references tenants,
+    -- id of the transaction that last wrote the row, the position of the change in the sync feed
+    sync_xid           bigint default pg_current_xact_id()::text::bigint not null
 );
 
 create table card_types
@@ -33,15 +56,24 @@ create table users
 (
     user_id        serial
         primary key,
-    email          varchar(100) not null
-        unique,
+    email          varchar(100) not null,
     password       varchar(255) not null,
     role           user_role    not null
         constraint users_role_check
             check ((role)::text = ANY
-                   (ARRAY [('customer'::character varying)::text, ('supplier'::character varying)::text])),
+                   (ARRAY [('customer'::character varying)::text, ('supplier'::character varying)::text, ('admin'::character varying)::text])),
     created_at     timestamp with time zone default CURRENT_TIMESTAMP,
-    email_verified boolean                  default false
+    email_verified boolean                  default false not null,
+    step_up_until  timestamp with time zone,
+    locale         varchar(15),
+    tenant_id      integer default 1 not null
+        constraint fk_user_tenant
+            references tenants,
+    -- set by an admin, the account can't sign in or refresh its session until it is cleared
+    disabled_at    timestamp with time zone,
+    -- the same address can hold an account on every marketplace
+    constraint users_tenant_email_key
+        unique (tenant_id, email)
 );
 
 create table customers
@@ -51,18 +83,21 @@ create table customers
     first_name        varchar(50) not null,
     last_name         varchar(50) not null,
     registration_date timestamp with time zone default CURRENT_TIMESTAMP,
-    user_id           integer     not null
+    user_id             integer     not null
         unique
         constraint fk_user_customer
             references users
-            on delete cascade
+            on delete cascade,
+    vat_id              varchar(20),
+    vat_id_validated_at timestamp with time zone
 );
 
 create table addresses
 (
     address_id      serial
         primary key,
-    customer_id     integer      not null
+    -- null for addresses entered at guest checkout
+    customer_id     integer
         constraint fk_customer
             references customers
             on delete cascade,
@@ -74,11 +109,14 @@ create table addresses
     is_default      boolean default false,
     address_type_id integer
         constraint fk_address_type
-            references address_types,
-    constraint unique_default_address
-        unique (customer_id, is_default)
+            references address_types
 );
 
+-- one default per customer, any number of other addresses
+create unique index unique_default_address
+    on addresses (customer_id)
+    where is_default;
+
 create index idx_addresses_customer_default
     on addresses (customer_id, is_default);
 
@@ -102,7 +140,14 @@ create table payment_methods
     ifsc_code            varchar(11),
     card_type_id         integer
         constraint fk_card_type
-            references card_types
+            references card_types,
+    provider             varchar(20),
+    provider_token       varchar(255)
+        unique,
+    card_brand           varchar(20),
+    card_last4           char(4),
+    -- the provider's customer the token is attached to, saved tokens can only be charged again through it
+    provider_customer_id varchar(255)
 );
 
 create index idx_payment_methods_customer_default
@@ -122,9 +167,24 @@ create table suppliers
         unique
         constraint fk_user_supplier
             references users
-            on delete cascade
+            on delete cascade,
+    region        varchar(20),
+    payout_account_holder varchar(100),
+    payout_iban           varchar(34),
+    digest_enabled        boolean     default false not null,
+    timezone              varchar(50) default 'UTC' not null,
+    last_digest_sent_at   timestamp with time zone,
+    -- held by the instance sending the digest, cleared once it went out or failed
+    digest_claimed_until  timestamp with time zone,
+    -- set by an admin, products can only be listed once the supplier is approved
+    approved_at           timestamp with time zone,
+    -- where parcels leave from, orders to addresses in another country carry customs data
+    ship_from_country     varchar(3)
 );
 
+create index idx_supplier_region
+    on suppliers (region);
+
 create table products
 (
     product_id      serial
@@ -140,15 +200,57 @@ create table products
         constraint fk_supplier
             references suppliers
             on delete set null,
-    stock_quantity  integer default 0 not null,
+    stock_quantity  integer default 0 not null
+        constraint products_stock_quantity_check
+            check (stock_quantity >= 0),
     base_product_id integer
         constraint fk_base_product
             references products
             on delete set null,
-    media_paths     text[],
-    created_at      timestamp with time zone
+    media_paths      text[],
+    created_at       timestamp with time zone,
+    updated_at       timestamp with time zone default CURRENT_TIMESTAMP not null,
+    price_updated_at timestamp with time zone default CURRENT_TIMESTAMP not null,
+    cost_price       numeric(10, 2),
+    sku              varchar(64),
+    tenant_id        integer default 1 not null
+        constraint fk_product_tenant
+            references tenants,
+    -- units one customer may buy, across all their orders within limit_window_days (ever, when null)
+    max_per_customer  integer
+        constraint products_max_per_customer_check
+            check (max_per_customer > 0),
+    limit_window_days integer
+        constraint products_limit_window_days_check
+            check (limit_window_days > 0),
+    -- rentals and slot limited services, sold per date from product_availability instead of from stock
+    date_bookable     boolean default false not null,
+    -- taken off sale by the supplier, kept so past orders still point at it
+    archived_at       timestamp with time zone,
+    -- set when an admin took the product down, it stays archived until the reason is cleared
+    removed_at        timestamp with time zone,
+    removal_reason    text,
+    -- needed to ship the product across a border
+    hs_code           varchar(10),
+    -- value per unit declared to customs, the price paid when not set
+    customs_value     numeric(10, 2)
+        constraint products_customs_value_check
+            check (customs_value >= 0),
+    -- ids of the transactions that last wrote the row and its price, positions in the sync feeds
+    sync_xid          bigint default pg_current_xact_id()::text::bigint not null,
+    price_sync_xid    bigint default pg_current_xact_id()::text::bigint not null,
+    -- what searchProducts matches against, left out of the entity since nothing reads it back
+    search_vector     tsvector generated always as (
+        setweight(to_tsvector('simple', coalesce(name, '')), 'A') ||
+        setweight(to_tsvector('simple', coalesce(description, '')), 'B')
+    ) stored,
+    constraint products_tenant_sku_key
+        unique (tenant_id, sku)
 );
 
+create index idx_product_tenant
+    on products (tenant_id);
+
 create index idx_product_category
     on products (category_id);
 
@@ -158,17 +260,37 @@ create index idx_product_supplier
 create index idx_product_name
     on products (name);
 
+create index idx_product_name_trgm
+    on products using gin (lower(name) gin_trgm_ops);
+
+create index idx_product_search_vector
+    on products using gin (search_vector);
+
+create index idx_product_sync_xid
+    on products (sync_xid, product_id);
+
+create index idx_product_price_sync_xid
+    on products (price_sync_xid, product_id);
+
 create table shopping_carts
 (
-    cart_id     serial
+    cart_id          serial
         primary key,
-    customer_id integer not null
+    customer_id      integer
         constraint fk_customer
             references customers
             on delete cascade,
-    created_at  timestamp with time zone default CURRENT_TIMESTAMP
+    created_at       timestamp with time zone default CURRENT_TIMESTAMP,
+    updated_at       timestamp with time zone default CURRENT_TIMESTAMP not null,
+    guest_token_hash varchar(64)
+        unique,
+    constraint shopping_carts_owner_check
+        check (customer_id is not null or guest_token_hash is not null)
 );
 
+create index idx_shopping_carts_updated_at
+    on shopping_carts (updated_at);
+
 create table cart_items
 (
     cart_item_id serial
@@ -243,14 +365,17 @@ create table discounts
         constraint fk_category
             references categories
             on delete set null,
-    min_quantity   integer
+    min_quantity   integer,
+    tenant_id      integer default 1 not null
+        constraint fk_discount_tenant
+            references tenants
 );
 
 create table orders
 (
     order_id            serial
         primary key,
-    customer_id         integer        not null
+    customer_id         integer
         constraint fk_customer
             references customers
             on delete restrict,
@@ -261,19 +386,57 @@ create table orders
         constraint fk_shipping_address
             references addresses
             on delete restrict,
-    payment_method_id   integer        not null
+    payment_method_id   integer
         constraint fk_payment_method
             references payment_methods
             on delete restrict,
     discount_id         integer
         constraint fk_discount
             references discounts
-            on delete set null
+            on delete set null,
+    region              varchar(20),
+    tax_amount          numeric(10, 2) default 0 not null,
+    po_number           varchar(50),
+    payout_frozen       boolean        default false not null,
+    guest_email         varchar(100),
+    tenant_id           integer        default 1 not null
+        constraint fk_order_tenant
+            references tenants,
+    carrier             varchar(10)
+        constraint orders_carrier_check
+            check ((carrier)::text = ANY
+                   ((ARRAY ['DHL'::character varying, 'DPD'::character varying, 'FEDEX'::character varying, 'GLS'::character varying, 'UPS'::character varying, 'USPS'::character varying])::text[])),
+    tracking_number     varchar(40),
+    shipped_at          timestamp with time zone,
+    first_order         boolean        default false not null,
+    welcome_discount    numeric(10, 2) default 0 not null,
+    -- the store currency, or the currency of the region's price overrides the order was priced with
+    currency            varchar(3)     not null
+        constraint orders_currency_check
+            check ((currency)::text ~ '^[A-Z]{3}$'::text),
+    constraint orders_owner_check
+        check (customer_id is not null or guest_email is not null),
+    -- which status may follow which is checked by the API
+    constraint orders_status_check
+        check ((status)::text = ANY
+               ((ARRAY ['PENDING'::character varying, 'PAID'::character varying, 'PROCESSING'::character varying, 'SHIPPED'::character varying, 'OUT_FOR_DELIVERY'::character varying, 'DELIVERED'::character varying, 'CANCELLED'::character varying])::text[]))
 );
 
+create index idx_orders_tenant
+    on orders (tenant_id);
+
 create index idx_orders_customer_date
     on orders (customer_id, order_date);
 
+create index idx_orders_guest_email
+    on orders (lower(guest_email::text))
+    where customer_id is null;
+
+-- a customer's PO number is only ever turned into one order
+create unique index orders_customer_po_number_key
+    on orders (customer_id, po_number)
+    where po_number is not null;
+
 create table order_items
 (
     order_item_id   serial
@@ -288,7 +451,14 @@ create table order_items
             on delete restrict,
     quantity        integer                  not null,
     unit_price      numeric(10, 2)           not null,
-    discount_amount numeric(10, 2) default 0 not null
+    -- the line's share of the order's discounts and VAT, so the lines add up to what was charged
+    discount_amount numeric(10, 2) default 0 not null,
+    tax_amount      numeric(10, 2) default 0 not null,
+    booking_date    date,
+    -- customs data taken at checkout, only for lines shipped to another country
+    hs_code         varchar(10),
+    customs_value   numeric(10, 2),
+    origin_country  varchar(3)
 );
 
 create index idx_order_items_order
@@ -308,7 +478,11 @@ create table bills
             on delete restrict,
     bill_date      timestamp with time zone default CURRENT_TIMESTAMP,
     total_amount   numeric(10, 2) not null,
-    payment_status varchar(20)    not null
+    payment_status varchar(20)    not null,
+    tax_amount     numeric(10, 2) default 0 not null,
+    vat_id         varchar(20),
+    po_number      varchar(50),
+    provider_payment_id varchar(255)
 );
 
 create index idx_discounts_code
@@ -323,3 +497,1584 @@ create index idx_discounts_category
 create index idx_discounts_validity
     on discounts (valid_from, valid_until);
 
+
+create table api_keys
+(
+    api_key_id  serial
+        primary key,
+    supplier_id integer
+        constraint fk_supplier_api_key
+            references suppliers
+            on delete cascade,
+    customer_id integer
+        constraint fk_customer_api_key
+            references customers
+            on delete cascade,
+    name        varchar(50) not null,
+    key_prefix  varchar(12) not null,
+    key_hash    varchar(64) not null
+        unique,
+    created_at  timestamp with time zone default CURRENT_TIMESTAMP,
+    revoked_at  timestamp with time zone,
+    -- requests made with the key read and write the sandbox schema instead of production data
+    sandbox     boolean     default false not null,
+    constraint api_keys_owner_check
+        check (num_nonnulls(supplier_id, customer_id) = 1)
+);
+
+create index idx_api_keys_supplier
+    on api_keys (supplier_id);
+
+create index idx_api_keys_customer
+    on api_keys (customer_id);
+
+create table api_key_usage
+(
+    api_key_id         integer           not null
+        constraint fk_api_key_usage
+            references api_keys
+            on delete cascade,
+    usage_date         date              not null,
+    request_count      integer default 0 not null,
+    error_count        integer default 0 not null,
+    rate_limited_count integer default 0 not null,
+    primary key (api_key_id, usage_date)
+);
+
+create table security_events
+(
+    security_event_id serial
+        primary key,
+    user_id           integer
+        constraint fk_user_security_event
+            references users
+            on delete cascade,
+    email             varchar(100),
+    ip_address        varchar(45),
+    event_type        varchar(30) not null,
+    created_at        timestamp with time zone default CURRENT_TIMESTAMP
+);
+
+create index idx_security_events_ip_created
+    on security_events (ip_address, created_at);
+
+create index idx_security_events_user
+    on security_events (user_id);
+
+create index idx_security_events_created
+    on security_events (created_at);
+
+create table login_challenges
+(
+    challenge_id serial
+        primary key,
+    user_id      integer                  not null
+        constraint fk_user_login_challenge
+            references users
+            on delete cascade,
+    code_hash    varchar(64)              not null,
+    attempts     integer default 0        not null,
+    expires_at   timestamp with time zone not null,
+    consumed_at  timestamp with time zone
+);
+
+create table review_summaries
+(
+    product_id     integer                 not null
+        primary key
+        constraint fk_product_review_summary
+            references products
+            on delete cascade,
+    review_count   integer default 0       not null,
+    rating_count   integer default 0       not null,
+    rating_total   integer default 0       not null,
+    one_star       integer default 0       not null,
+    two_star       integer default 0       not null,
+    three_star     integer default 0       not null,
+    four_star      integer default 0       not null,
+    five_star      integer default 0       not null,
+    keyword_counts jsonb   default '{}'::jsonb not null,
+    updated_at     timestamp with time zone default CURRENT_TIMESTAMP
+);
+
+create table policy_versions
+(
+    policy_version_id serial
+        primary key,
+    policy_type       varchar(20) not null
+        constraint policy_versions_policy_type_check
+            check ((policy_type)::text = ANY
+                   ((ARRAY ['TERMS'::character varying, 'PRIVACY'::character varying])::text[])),
+    version           varchar(20) not null,
+    document_url      text        not null,
+    published_at      timestamp with time zone default CURRENT_TIMESTAMP,
+    tenant_id         integer default 1 not null
+        constraint fk_policy_version_tenant
+            references tenants,
+    constraint unique_policy_version
+        unique (tenant_id, policy_type, version)
+);
+
+create table policy_acceptances
+(
+    user_id           integer not null
+        constraint fk_user_policy_acceptance
+            references users
+            on delete cascade,
+    policy_version_id integer not null
+        constraint fk_policy_version_acceptance
+            references policy_versions
+            on delete cascade,
+    accepted_at       timestamp with time zone default CURRENT_TIMESTAMP,
+    primary key (user_id, policy_version_id)
+);
+
+create function touch_updated_at() returns trigger as
+$$
+begin
+    new.updated_at = CURRENT_TIMESTAMP;
+    return new;
+end;
+$$ language plpgsql;
+
+create function touch_price_updated_at() returns trigger as
+$$
+begin
+    if new.base_price is distinct from old.base_price then
+        new.price_updated_at = CURRENT_TIMESTAMP;
+        new.price_sync_xid = pg_current_xact_id()::text::bigint;
+    end if;
+    return new;
+end;
+$$ language plpgsql;
+
+create function touch_sync_xid() returns trigger as
+$$
+begin
+    new.sync_xid = pg_current_xact_id()::text::bigint;
+    return new;
+end;
+$$ language plpgsql;
+
+-- deleted rows the sync feeds still have to report, kept without foreign keys since they outlive
+-- the rows they stand for
+create table sync_tombstones
+(
+    entity     varchar(20) not null
+        constraint sync_tombstones_entity_check
+            check (entity in ('PRODUCT', 'CATEGORY')),
+    entity_id  integer     not null,
+    tenant_id  integer     not null,
+    deleted_at timestamp with time zone default CURRENT_TIMESTAMP not null,
+    sync_xid   bigint default pg_current_xact_id()::text::bigint not null,
+    primary key (entity, entity_id)
+);
+
+create index idx_sync_tombstones_sync_xid
+    on sync_tombstones (entity, tenant_id, sync_xid, entity_id);
+
+-- arguments are the entity and the id column. The tombstone goes to the schema of the deleted row,
+-- so sandbox deletions stay in the sandbox
+create function record_sync_tombstone() returns trigger as
+$$
+begin
+    execute format('insert into %I.sync_tombstones (entity, entity_id, tenant_id) values ($1, $2, $3)',
+                   tg_table_schema)
+        using tg_argv[0], (to_jsonb(old) ->> tg_argv[1])::integer, old.tenant_id;
+    return null;
+end;
+$$ language plpgsql;
+
+-- the cart is updated in the schema of the cart_items table that changed, so sandbox carts stay in the sandbox
+create function touch_cart_updated_at() returns trigger as
+$$
+begin
+    execute format('update %I.shopping_carts set updated_at = CURRENT_TIMESTAMP where cart_id = $1',
+                   tg_table_schema)
+        using coalesce(new.cart_id, old.cart_id);
+    return null;
+end;
+$$ language plpgsql;
+
+create trigger categories_touch_updated_at
+    before update
+    on categories
+    for each row
+execute function touch_updated_at();
+
+create trigger categories_touch_sync_xid
+    before update
+    on categories
+    for each row
+execute function touch_sync_xid();
+
+create trigger categories_record_sync_tombstone
+    after delete
+    on categories
+    for each row
+execute function record_sync_tombstone('CATEGORY', 'category_id');
+
+create index idx_category_sync_xid
+    on categories (sync_xid, category_id);
+
+create trigger products_touch_updated_at
+    before update
+    on products
+    for each row
+execute function touch_updated_at();
+
+create trigger products_touch_price_updated_at
+    before update
+    on products
+    for each row
+execute function touch_price_updated_at();
+
+create trigger products_touch_sync_xid
+    before update
+    on products
+    for each row
+execute function touch_sync_xid();
+
+create trigger products_record_sync_tombstone
+    after delete
+    on products
+    for each row
+execute function record_sync_tombstone('PRODUCT', 'product_id');
+
+create trigger cart_items_touch_cart
+    after insert or update or delete
+    on cart_items
+    for each row
+execute function touch_cart_updated_at();
+
+-- originals uploaded for a product, media_paths keeps listing them for existing clients
+create table product_images
+(
+    image_id     serial
+        primary key,
+    product_id   integer                                            not null
+        constraint fk_product_image
+            references products
+            on delete cascade,
+    url          text                                               not null,
+    storage_key  text                                               not null,
+    content_type varchar(50)                                        not null,
+    size_bytes   integer                                            not null,
+    uploaded_at  timestamp with time zone default CURRENT_TIMESTAMP not null,
+    constraint uq_product_image
+        unique (product_id, url)
+);
+
+create table product_image_variants
+(
+    image_variant_id serial
+        primary key,
+    product_id       integer     not null
+        constraint fk_product_image_variant
+            references products
+            on delete cascade,
+    source_url       text        not null,
+    size             varchar(20) not null,
+    format           varchar(10) not null,
+    url              text        not null,
+    width            integer     not null,
+    height           integer     not null,
+    created_at       timestamp with time zone default CURRENT_TIMESTAMP,
+    constraint uq_image_variant
+        unique (source_url, size, format)
+);
+
+create index idx_image_variant_product
+    on product_image_variants (product_id);
+
+create table order_messages
+(
+    message_id      serial
+        primary key,
+    order_id        integer     not null
+        constraint fk_order_message_order
+            references orders
+            on delete cascade,
+    sender_user_id  integer     not null
+        constraint fk_order_message_sender
+            references users
+            on delete cascade,
+    sender_role     varchar(20) not null,
+    body            text        not null,
+    -- private storage keys, readers get short lived signed URLs
+    attachment_keys text[]      default '{}'::text[] not null,
+    created_at      timestamp with time zone default CURRENT_TIMESTAMP
+);
+
+create index idx_order_message_order
+    on order_messages (order_id, created_at);
+
+create table boost_rules
+(
+    boost_rule_id      serial
+        primary key,
+    name               varchar(100)   not null,
+    rule_type          varchar(20)    not null
+        constraint boost_rules_rule_type_check
+            check ((rule_type)::text = ANY
+                   ((ARRAY ['NEW_ARRIVAL'::character varying, 'SUPPLIER'::character varying, 'MARGIN'::character varying])::text[])),
+    weight             numeric(10, 2) not null,
+    supplier_id        integer
+        constraint fk_boost_rule_supplier
+            references suppliers
+            on delete cascade,
+    max_age_days       integer,
+    min_margin_percent numeric(5, 2),
+    active             boolean                  default true not null,
+    created_at         timestamp with time zone default CURRENT_TIMESTAMP,
+    tenant_id          integer default 1 not null
+        constraint fk_boost_rule_tenant
+            references tenants
+);
+
+create table sponsored_campaigns
+(
+    campaign_id   serial
+        primary key,
+    supplier_id   integer        not null
+        constraint fk_sponsored_campaign_supplier
+            references suppliers
+            on delete cascade,
+    product_id    integer        not null
+        constraint fk_sponsored_campaign_product
+            references products
+            on delete cascade,
+    keyword       varchar(100)   not null,
+    bid_per_click numeric(10, 2) not null
+        constraint sponsored_campaigns_bid_check
+            check (bid_per_click > (0)::numeric),
+    budget        numeric(10, 2) not null,
+    spent         numeric(10, 2)           default 0 not null,
+    active        boolean                  default true not null,
+    created_at    timestamp with time zone default CURRENT_TIMESTAMP
+);
+
+create index idx_sponsored_campaign_keyword
+    on sponsored_campaigns (lower(keyword::text))
+    where active;
+
+create table sponsored_clicks
+(
+    click_id    serial
+        primary key,
+    campaign_id integer        not null
+        constraint fk_sponsored_click_campaign
+            references sponsored_campaigns
+            on delete cascade,
+    ip_address  varchar(45)    not null,
+    cost        numeric(10, 2) not null,
+    clicked_at  timestamp with time zone default CURRENT_TIMESTAMP
+);
+
+create index idx_sponsored_click_campaign
+    on sponsored_clicks (campaign_id, ip_address, clicked_at);
+
+create table retention_runs
+(
+    retention_run_id serial
+        primary key,
+    target           varchar(30)                                        not null
+        constraint retention_runs_target_check
+            check ((target)::text = ANY
+                   ((ARRAY ['ANALYTICS_EVENTS'::character varying, 'AUDIT_LOG'::character varying, 'EXPIRED_CARTS'::character varying])::text[])),
+    dry_run          boolean                                            not null,
+    cutoff           timestamp with time zone                           not null,
+    affected_rows    bigint                   default 0                 not null,
+    error            text,
+    started_at       timestamp with time zone default CURRENT_TIMESTAMP not null,
+    finished_at      timestamp with time zone default CURRENT_TIMESTAMP not null
+);
+
+create index idx_retention_runs_target_started
+    on retention_runs (target, started_at);
+
+create table email_templates
+(
+    email_template_id serial
+        primary key,
+    template_key      varchar(30)                                        not null,
+    locale            varchar(15)                                        not null,
+    subject           varchar(200)                                       not null,
+    html_body         text                                               not null,
+    updated_at        timestamp with time zone default CURRENT_TIMESTAMP not null,
+    tenant_id         integer                  default 1                 not null
+        constraint fk_email_template_tenant
+            references tenants,
+    constraint email_templates_key_locale_unique
+        unique (tenant_id, template_key, locale)
+);
+
+create trigger email_templates_touch_updated_at
+    before update
+    on email_templates
+    for each row
+execute function touch_updated_at();
+
+create table payment_events
+(
+    payment_event_id  serial
+        primary key,
+    provider          varchar(20)                                        not null,
+    provider_event_id varchar(255)                                       not null,
+    event_type        varchar(100)                                       not null,
+    payload           text                                               not null,
+    attempts          integer                  default 0                 not null,
+    last_error        text,
+    received_at       timestamp with time zone default CURRENT_TIMESTAMP not null,
+    processed_at      timestamp with time zone,
+    -- the order the event turned out to be about, set once it was handled
+    order_id          integer
+        constraint fk_payment_event_order
+            references orders
+            on delete set null,
+    constraint payment_events_provider_event_unique
+        unique (provider, provider_event_id)
+);
+
+create index idx_payment_events_pending
+    on payment_events (received_at)
+    where processed_at is null;
+
+create index idx_payment_events_order
+    on payment_events (order_id);
+
+create table disputes
+(
+    dispute_id          serial
+        primary key,
+    provider            varchar(20)                                        not null,
+    provider_dispute_id varchar(255)                                       not null,
+    provider_payment_id varchar(255)                                       not null,
+    order_id            integer
+        constraint fk_dispute_order
+            references orders
+            on delete set null,
+    amount              numeric(10, 2)                                     not null,
+    reason              varchar(100),
+    status              varchar(30)                                        not null,
+    opened_at           timestamp with time zone default CURRENT_TIMESTAMP not null,
+    updated_at          timestamp with time zone default CURRENT_TIMESTAMP not null,
+    constraint disputes_provider_dispute_unique
+        unique (provider, provider_dispute_id)
+);
+
+create index idx_disputes_order
+    on disputes (order_id);
+
+create trigger disputes_touch_updated_at
+    before update
+    on disputes
+    for each row
+execute function touch_updated_at();
+
+create table accounting_exports
+(
+    accounting_export_id serial
+        primary key,
+    requested_by         integer                                            not null
+        constraint fk_accounting_export_user
+            references users
+            on delete cascade,
+    -- null for marketplace wide exports requested by admins
+    supplier_id          integer
+        constraint fk_accounting_export_supplier
+            references suppliers
+            on delete cascade,
+    format               varchar(20)                                        not null
+        constraint accounting_exports_format_check
+            check ((format)::text = ANY
+                   ((ARRAY ['DATEV'::character varying, 'QUICKBOOKS'::character varying])::text[])),
+    period_start         date                                               not null,
+    period_end           date                                               not null,
+    status               varchar(20)              default 'PENDING'         not null
+        constraint accounting_exports_status_check
+            check ((status)::text = ANY
+                   ((ARRAY ['PENDING'::character varying, 'RUNNING'::character varying, 'DONE'::character varying, 'FAILED'::character varying])::text[])),
+    -- private storage key, downloads go through short lived signed URLs
+    file_key             varchar(500),
+    row_count            integer,
+    error                text,
+    requested_at         timestamp with time zone default CURRENT_TIMESTAMP not null,
+    finished_at          timestamp with time zone,
+    -- a RUNNING job whose worker stopped renewing this is picked up again
+    lease_expires_at     timestamp with time zone,
+    tenant_id            integer                                            not null
+        constraint fk_accounting_export_tenant
+            references tenants,
+    constraint accounting_exports_period_check
+        check (period_start <= period_end)
+);
+
+create index idx_accounting_exports_pending
+    on accounting_exports (requested_at)
+    where status IN ('PENDING', 'RUNNING');
+
+-- roles an account picked up after registering, users.role stays the role it signed up with
+create table user_roles
+(
+    user_id    integer                                            not null
+        constraint fk_user_user_role
+            references users
+            on delete cascade,
+    role       user_role                                          not null,
+    granted_at timestamp with time zone default CURRENT_TIMESTAMP not null,
+    primary key (user_id, role)
+);
+
+-- suppliers without a row fall back to a 30 day window, no fee and every category returnable
+create table return_policies
+(
+    supplier_id                 integer                                            not null
+        primary key
+        constraint fk_supplier_return_policy
+            references suppliers
+            on delete cascade,
+    return_window_days          integer                  default 30                not null
+        constraint return_policies_return_window_days_check
+            check (return_window_days >= 0),
+    restocking_fee_percent      numeric(5, 2)            default 0                 not null
+        constraint return_policies_restocking_fee_percent_check
+            check (restocking_fee_percent >= 0 and restocking_fee_percent <= 100),
+    -- subcategories of these are excluded as well
+    non_returnable_category_ids integer[]                default '{}'::integer[]   not null,
+    updated_at                  timestamp with time zone default CURRENT_TIMESTAMP not null
+);
+
+create trigger return_policies_touch_updated_at
+    before update
+    on return_policies
+    for each row
+execute function touch_updated_at();
+
+create table return_requests
+(
+    return_request_id serial
+        primary key,
+    order_item_id     integer                                            not null
+        constraint fk_order_item_return_request
+            references order_items
+            on delete cascade,
+    quantity          integer                                            not null
+        constraint return_requests_quantity_check
+            check (quantity > 0),
+    reason            text,
+    status            varchar(20)              default 'REQUESTED'       not null
+        constraint return_requests_status_check
+            check ((status)::text = ANY
+                   ((ARRAY ['REQUESTED'::character varying, 'APPROVED'::character varying, 'REJECTED'::character varying])::text[])),
+    -- both fixed when the return is requested, from the policy in force at that time
+    restocking_fee    numeric(10, 2)                                     not null,
+    refund_amount     numeric(10, 2)                                     not null,
+    requested_at      timestamp with time zone default CURRENT_TIMESTAMP not null,
+    resolved_at       timestamp with time zone
+);
+
+create index idx_return_requests_order_item
+    on return_requests (order_item_id);
+
+-- money paid back on an order, for some of its lines, an amount not tied to a line (e.g. shipping) or both.
+-- refunds that didn't fail count against what is still refundable
+create table refunds
+(
+    refund_id          serial
+        primary key,
+    order_id           integer                                            not null
+        constraint fk_order_refund
+            references orders
+            on delete restrict,
+    -- the lines' amounts plus extra_amount
+    amount             numeric(10, 2)                                     not null
+        constraint refunds_amount_check
+            check (amount > (0)::numeric),
+    extra_amount       numeric(10, 2)           default 0                 not null
+        constraint refunds_extra_amount_check
+            check (extra_amount >= (0)::numeric),
+    reason             text,
+    status             varchar(20)              default 'PENDING'         not null
+        constraint refunds_status_check
+            check ((status)::text = ANY
+                   ((ARRAY ['PENDING'::character varying, 'SUCCEEDED'::character varying, 'FAILED'::character varying])::text[])),
+    provider_refund_id varchar(255),
+    requested_by       integer
+        constraint fk_refund_requested_by
+            references users
+            on delete set null,
+    created_at         timestamp with time zone default CURRENT_TIMESTAMP not null,
+    refunded_at        timestamp with time zone
+);
+
+create index idx_refunds_order
+    on refunds (order_id);
+
+create index idx_refunds_provider_refund
+    on refunds (provider_refund_id);
+
+create table refund_items
+(
+    refund_id     integer        not null
+        constraint fk_refund_item_refund
+            references refunds
+            on delete cascade,
+    order_item_id integer        not null
+        constraint fk_refund_item_order_item
+            references order_items
+            on delete restrict,
+    quantity      integer        not null
+        constraint refund_items_quantity_check
+            check (quantity > 0),
+    amount        numeric(10, 2) not null
+        constraint refund_items_amount_check
+            check (amount >= (0)::numeric),
+    constraint refund_items_pkey
+        primary key (refund_id, order_item_id)
+);
+
+create index idx_refund_items_order_item
+    on refund_items (order_item_id);
+
+create table product_videos
+(
+    video_id      serial
+        primary key,
+    product_id    integer                                            not null
+        constraint fk_product_video
+            references products
+            on delete cascade,
+    source        varchar(20)                                        not null
+        constraint product_videos_source_check
+            check ((source)::text = ANY
+                   ((ARRAY ['UPLOAD'::character varying, 'EXTERNAL'::character varying])::text[])),
+    -- only set for external videos
+    provider      varchar(20)
+        constraint product_videos_provider_check
+            check ((provider)::text = ANY
+                   ((ARRAY ['YOUTUBE'::character varying, 'VIMEO'::character varying])::text[])),
+    url           text                                               not null,
+    title         text,
+    thumbnail_url text,
+    status        varchar(20)              default 'PENDING'         not null
+        constraint product_videos_status_check
+            check ((status)::text = ANY
+                   ((ARRAY ['PENDING'::character varying, 'PROCESSING'::character varying, 'READY'::character varying, 'FAILED'::character varying])::text[])),
+    error         text,
+    created_at    timestamp with time zone default CURRENT_TIMESTAMP not null,
+    processed_at  timestamp with time zone
+);
+
+create index idx_product_videos_product
+    on product_videos (product_id);
+
+-- banners shown to everyone or one side of the marketplace between starts_at and ends_at
+create table announcements
+(
+    announcement_id serial
+        primary key,
+    title           varchar(200)                                       not null,
+    body            text                                               not null,
+    kind            varchar(20)              default 'INFO'            not null
+        constraint announcements_kind_check
+            check ((kind)::text = ANY
+                   ((ARRAY ['INFO'::character varying, 'MAINTENANCE'::character varying, 'SALE'::character varying])::text[])),
+    audience        varchar(20)              default 'ALL'             not null
+        constraint announcements_audience_check
+            check ((audience)::text = ANY
+                   ((ARRAY ['ALL'::character varying, 'CUSTOMERS'::character varying, 'SUPPLIERS'::character varying])::text[])),
+    starts_at       timestamp with time zone default CURRENT_TIMESTAMP not null,
+    -- open ended when null
+    ends_at         timestamp with time zone,
+    created_by      integer
+        constraint fk_announcement_creator
+            references users
+            on delete set null,
+    created_at      timestamp with time zone default CURRENT_TIMESTAMP not null,
+    updated_at      timestamp with time zone default CURRENT_TIMESTAMP not null,
+    tenant_id       integer                  default 1                 not null
+        constraint fk_announcement_tenant
+            references tenants,
+    constraint announcements_ends_at_check
+        check (ends_at is null or ends_at > starts_at)
+);
+
+create trigger announcements_touch_updated_at
+    before update
+    on announcements
+    for each row
+execute function touch_updated_at();
+
+create index idx_announcements_window
+    on announcements (tenant_id, starts_at, ends_at);
+
+-- products in a category and all of its subcategories, kept up to date as products are added, moved or removed
+create table category_product_counts
+(
+    category_id   integer           not null
+        primary key
+        constraint fk_category_product_count
+            references categories
+            on delete cascade,
+    product_count integer default 0 not null
+);
+
+-- every status an order went through, written alongside the status change itself
+create table order_status_history
+(
+    history_id      serial
+        primary key,
+    order_id        integer                                            not null
+        constraint fk_order_status_history
+            references orders
+            on delete cascade,
+    previous_status varchar(20),
+    status          varchar(20)                                        not null,
+    -- null for changes made by the system or by a guest
+    changed_by      integer
+        constraint fk_order_status_changed_by
+            references users
+            on delete set null,
+    changed_at      timestamp with time zone default CURRENT_TIMESTAMP not null
+);
+
+create index idx_order_status_history_order
+    on order_status_history (order_id, changed_at);
+
+-- every time a customer moved an order to another of their addresses before it shipped
+create table order_address_changes
+(
+    change_id           serial
+        primary key,
+    order_id            integer                                            not null
+        constraint fk_order_address_change_order
+            references orders
+            on delete cascade,
+    -- null once the customer deleted that address
+    previous_address_id integer
+        constraint fk_order_address_change_previous
+            references addresses
+            on delete set null,
+    new_address_id      integer
+        constraint fk_order_address_change_new
+            references addresses
+            on delete set null,
+    changed_by          integer
+        constraint fk_order_address_changed_by
+            references users
+            on delete set null,
+    changed_at          timestamp with time zone default CURRENT_TIMESTAMP not null
+);
+
+create index idx_order_address_changes_order
+    on order_address_changes (order_id, changed_at);
+
+-- each supplier ships their own lines of an order, the order is SHIPPED once every supplier with
+-- a line in it has
+create table order_shipments
+(
+    shipment_id     serial
+        primary key,
+    order_id        integer                                            not null
+        constraint fk_order_shipment_order
+            references orders
+            on delete cascade,
+    supplier_id     integer                                            not null
+        constraint fk_order_shipment_supplier
+            references suppliers
+            on delete cascade,
+    carrier         varchar(10)                                        not null
+        constraint order_shipments_carrier_check
+            check ((carrier)::text = ANY
+                   ((ARRAY ['DHL'::character varying, 'DPD'::character varying, 'FEDEX'::character varying, 'GLS'::character varying, 'UPS'::character varying, 'USPS'::character varying])::text[])),
+    tracking_number varchar(40)                                        not null,
+    shipped_by      integer
+        constraint fk_order_shipment_shipped_by
+            references users
+            on delete set null,
+    shipped_at      timestamp with time zone default CURRENT_TIMESTAMP not null,
+    constraint order_shipments_order_supplier_key
+        unique (order_id, supplier_id)
+);
+
+-- admin moves of every product from one category to another, worked off in batches by the reassignment worker
+create table category_reassignments
+(
+    reassignment_id  serial
+        primary key,
+    requested_by     integer                                            not null
+        constraint fk_category_reassignment_user
+            references users
+            on delete cascade,
+    from_category_id integer                                            not null
+        constraint fk_category_reassignment_from
+            references categories
+            on delete cascade,
+    to_category_id   integer                                            not null
+        constraint fk_category_reassignment_to
+            references categories
+            on delete cascade,
+    -- optional filter, only products whose name contains it are moved
+    name_contains    varchar(100),
+    -- optional filter, only this supplier's products are moved
+    supplier_id      integer
+        constraint fk_category_reassignment_supplier
+            references suppliers
+            on delete cascade,
+    status           varchar(20)              default 'PENDING'         not null
+        constraint category_reassignments_status_check
+            check ((status)::text = ANY
+                   ((ARRAY ['PENDING'::character varying, 'RUNNING'::character varying, 'DONE'::character varying, 'FAILED'::character varying])::text[])),
+    total_products   integer,
+    moved_products   integer                  default 0                 not null,
+    error            text,
+    requested_at     timestamp with time zone default CURRENT_TIMESTAMP not null,
+    finished_at      timestamp with time zone,
+    lease_expires_at timestamp with time zone,
+    tenant_id        integer                  default 1                 not null
+        constraint fk_category_reassignment_tenant
+            references tenants,
+    constraint category_reassignments_categories_check
+        check (from_category_id <> to_category_id)
+);
+
+create index idx_category_reassignments_pending
+    on category_reassignments (requested_at)
+    where status IN ('PENDING', 'RUNNING');
+
+-- units of a date bookable product that can be booked per day, days without a row can't be booked
+create table product_availability
+(
+    product_id   integer           not null
+        constraint fk_product_availability_product
+            references products
+            on delete cascade,
+    available_on date              not null,
+    capacity     integer           not null
+        constraint product_availability_capacity_check
+            check (capacity >= 0),
+    booked       integer default 0 not null,
+    constraint product_availability_pkey
+        primary key (product_id, available_on),
+    constraint product_availability_booked_check
+        check ((booked >= 0) AND (booked <= capacity))
+);
+
+-- what a product costs in a region instead of its base price, products without a row use the base price
+create table product_region_prices
+(
+    product_id integer        not null
+        constraint fk_product_region_price_product
+            references products
+            on delete cascade,
+    region     varchar(20)    not null,
+    currency   varchar(3)     not null
+        constraint product_region_prices_currency_check
+            check ((currency)::text ~ '^[A-Z]{3}$'::text),
+    price      numeric(10, 2) not null
+        constraint product_region_prices_price_check
+            check (price > (0)::numeric),
+    constraint product_region_prices_pkey
+        primary key (product_id, region)
+);
+
+-- every base price a product has had, written by the products trigger below. Price drop badges compare against the
+-- highest price of the last 30 days from here, not against whatever the supplier claims the old price was
+create table product_price_history
+(
+    price_history_id serial
+        primary key,
+    product_id       integer                                            not null
+        constraint fk_product_price_history_product
+            references products
+            on delete cascade,
+    price            numeric(10, 2)                                     not null,
+    recorded_at      timestamp with time zone default CURRENT_TIMESTAMP not null
+);
+
+create index idx_product_price_history_product
+    on product_price_history (product_id, recorded_at);
+
+-- the history table is looked up in the schema of the products table that changed, so sandbox prices stay in the
+-- sandbox whatever the search path
+create function record_price_history() returns trigger as
+$$
+begin
+    if tg_op = 'INSERT' or new.base_price is distinct from old.base_price then
+        execute format('insert into %I.product_price_history (product_id, price) values ($1, $2)', tg_table_schema)
+            using new.product_id, new.base_price;
+    end if;
+    return null;
+end;
+$$ language plpgsql;
+
+create trigger products_record_price_history
+    after insert or update
+    on products
+    for each row
+execute function record_price_history();
+
+-- replies suppliers and admins drop into order chats, {{placeholders}} are filled in from the order
+create table canned_responses
+(
+    canned_response_id serial
+        primary key,
+    -- null for the marketplace wide responses admins manage
+    supplier_id        integer
+        constraint fk_canned_response_supplier
+            references suppliers
+            on delete cascade,
+    title              varchar(100)                                       not null,
+    body               text                                               not null,
+    created_at         timestamp with time zone default CURRENT_TIMESTAMP not null,
+    updated_at         timestamp with time zone default CURRENT_TIMESTAMP not null,
+    tenant_id          integer                  default 1                 not null
+        constraint fk_canned_response_tenant
+            references tenants
+);
+
+create index idx_canned_responses_supplier
+    on canned_responses (supplier_id);
+
+create trigger canned_responses_touch_updated_at
+    before update
+    on canned_responses
+    for each row
+execute function touch_updated_at();
+
+-- newsletter sign ups, PENDING until the emailed confirmation link is opened (double opt-in)
+create table newsletter_subscriptions
+(
+    subscription_id      serial
+        primary key,
+    email                varchar(255)                                       not null,
+    status               varchar(20)              default 'PENDING'         not null
+        constraint newsletter_subscriptions_status_check
+            check ((status)::text = ANY
+                   ((ARRAY ['PENDING'::character varying, 'SUBSCRIBED'::character varying, 'UNSUBSCRIBED'::character varying])::text[])),
+    locale               varchar(10),
+    -- sha256 of the token in the confirmation link, cleared once it was used
+    confirm_token_hash   varchar(64),
+    confirmation_sent_at timestamp with time zone,
+    confirmed_at         timestamp with time zone,
+    unsubscribed_at      timestamp with time zone,
+    created_at           timestamp with time zone default CURRENT_TIMESTAMP not null,
+    updated_at           timestamp with time zone default CURRENT_TIMESTAMP not null,
+    tenant_id            integer                  default 1                 not null
+        constraint fk_newsletter_subscription_tenant
+            references tenants,
+    constraint newsletter_subscriptions_tenant_email_key
+        unique (tenant_id, email)
+);
+
+create index idx_newsletter_subscriptions_updated
+    on newsletter_subscriptions (updated_at, subscription_id);
+
+create trigger newsletter_subscriptions_touch_updated_at
+    before update
+    on newsletter_subscriptions
+    for each row
+execute function touch_updated_at();
+
+-- addresses no templated mail goes to (bounces and complaints the ESP reports back), login codes and verification excepted
+create table email_suppressions
+(
+    tenant_id  integer                                            not null
+        constraint fk_email_suppression_tenant
+            references tenants,
+    email      varchar(255)                                       not null,
+    reason     varchar(20)                                        not null
+        constraint email_suppressions_reason_check
+            check ((reason)::text = ANY
+                   ((ARRAY ['BOUNCED'::character varying, 'COMPLAINED'::character varying, 'MANUAL'::character varying])::text[])),
+    created_at timestamp with time zone default CURRENT_TIMESTAMP not null,
+    -- each marketplace keeps its own list, a bounce reported to one doesn't silence the others
+    constraint email_suppressions_pkey
+        primary key (tenant_id, email)
+);
+
+-- admin actions over many accounts at once, carried out by the bulk user worker
+create table bulk_user_jobs
+(
+    job_id          serial
+        primary key,
+    action          varchar(20)                                        not null
+        constraint bulk_user_jobs_action_check
+            check ((action)::text = ANY
+                   ((ARRAY ['DISABLE'::character varying, 'RESET_PASSWORD'::character varying, 'ASSIGN_ROLE'::character varying])::text[])),
+    -- only for ASSIGN_ROLE
+    role            user_role,
+    requested_by    integer                                            not null
+        constraint fk_bulk_user_job_user
+            references users
+            on delete cascade,
+    status          varchar(20)              default 'PENDING'         not null
+        constraint bulk_user_jobs_status_check
+            check ((status)::text = ANY
+                   ((ARRAY ['PENDING'::character varying, 'RUNNING'::character varying, 'DONE'::character varying, 'FAILED'::character varying])::text[])),
+    total_users     integer                                            not null,
+    processed_users integer                  default 0                 not null,
+    failed_users    integer                  default 0                 not null,
+    error           text,
+    requested_at    timestamp with time zone default CURRENT_TIMESTAMP not null,
+    finished_at     timestamp with time zone,
+    lease_expires_at timestamp with time zone,
+    tenant_id       integer                  default 1                 not null
+        constraint fk_bulk_user_job_tenant
+            references tenants,
+    constraint bulk_user_jobs_role_check
+        check ((action)::text <> 'ASSIGN_ROLE' OR role IS NOT NULL)
+);
+
+create index idx_bulk_user_jobs_pending
+    on bulk_user_jobs (requested_at)
+    where status IN ('PENDING', 'RUNNING');
+
+-- one row per targeted account, the report of what the job did to it
+create table bulk_user_job_results
+(
+    job_id       integer                                not null
+        constraint fk_bulk_user_job_result_job
+            references bulk_user_jobs
+            on delete cascade,
+    user_id      integer                                not null,
+    outcome      varchar(20) default 'PENDING'          not null
+        constraint bulk_user_job_results_outcome_check
+            check ((outcome)::text = ANY
+                   ((ARRAY ['PENDING'::character varying, 'DONE'::character varying, 'SKIPPED'::character varying, 'FAILED'::character varying])::text[])),
+    message      text,
+    processed_at timestamp with time zone,
+    primary key (job_id, user_id)
+);
+
+-- attributes products of a category carry, subcategories inherit them unless they define the same name
+create table category_attributes
+(
+    attribute_id   serial
+        primary key,
+    category_id    integer               not null
+        constraint fk_category_attribute_category
+            references categories
+            on delete cascade,
+    name           varchar(50)           not null,
+    required       boolean default false not null,
+    -- null for free text
+    allowed_values text[],
+    constraint uq_category_attribute_name
+        unique (category_id, name)
+);
+
+create table product_attributes
+(
+    product_id integer      not null
+        constraint fk_product_attribute_product
+            references products
+            on delete cascade,
+    name       varchar(50)  not null,
+    value      varchar(100) not null,
+    primary key (product_id, name)
+);
+
+create index idx_product_attributes_facet
+    on product_attributes (name, value);
+
+create table domain_events
+(
+    event_id     serial
+        primary key,
+    event_type   varchar(50)                                        not null,
+    payload      jsonb                                              not null,
+    tenant_id    integer                  default 1                 not null
+        constraint fk_domain_event_tenant
+            references tenants,
+    occurred_at  timestamp with time zone default CURRENT_TIMESTAMP not null,
+    attempts     integer                  default 0                 not null,
+    last_error   text,
+    processed_at timestamp with time zone
+);
+
+create index idx_domain_events_pending
+    on domain_events (occurred_at)
+    where processed_at is null;
+
+-- myActivity looks up a customer's events by the customer they were recorded for
+create index idx_domain_events_customer
+    on domain_events (tenant_id, (payload ->> 'customerId'), occurred_at)
+    where event_type in ('ORDER_PLACED', 'REVIEW_POSTED');
+
+create table analytics_events
+(
+    analytics_event_id serial
+        primary key,
+    event_type         varchar(50)                                        not null,
+    customer_id        integer,
+    order_id           integer,
+    properties         jsonb                                              not null,
+    tenant_id          integer                  default 1                 not null
+        constraint fk_analytics_event_tenant
+            references tenants,
+    occurred_at        timestamp with time zone default CURRENT_TIMESTAMP not null,
+    -- the domain event it was recorded from, redeliveries don't count twice
+    domain_event_id    integer
+        constraint analytics_events_domain_event_unique
+            unique
+);
+
+create index idx_analytics_events_type_date
+    on analytics_events (tenant_id, event_type, occurred_at);
+
+-- productPerformance looks events up by the product they were recorded for
+create index idx_analytics_events_product
+    on analytics_events (tenant_id, (properties ->> 'productId'), occurred_at)
+    where event_type in ('PRODUCT_VIEWED', 'PRODUCT_ADDED_TO_CART');
+
+-- a ZIP of product images named by SKU, unpacked by the image ZIP worker
+create table image_zip_jobs
+(
+    job_id         serial
+        primary key,
+    supplier_id    integer                                            not null
+        constraint fk_image_zip_job_supplier
+            references suppliers
+            on delete cascade,
+    file_name      varchar(255)                                       not null,
+    status         varchar(20)              default 'PENDING'         not null
+        constraint image_zip_jobs_status_check
+            check ((status)::text = ANY
+                   ((ARRAY ['PENDING'::character varying, 'RUNNING'::character varying, 'DONE'::character varying, 'FAILED'::character varying])::text[])),
+    total_files    integer                  default 0                 not null,
+    attached_files integer                  default 0                 not null,
+    error          text,
+    requested_at   timestamp with time zone default CURRENT_TIMESTAMP not null,
+    finished_at    timestamp with time zone,
+    lease_expires_at timestamp with time zone,
+    tenant_id      integer                  default 1                 not null
+        constraint fk_image_zip_job_tenant
+            references tenants
+);
+
+create index idx_image_zip_jobs_supplier
+    on image_zip_jobs (supplier_id, requested_at);
+
+-- one row per file in the archive, the report of what became of it
+create table image_zip_job_results
+(
+    job_id     integer     not null
+        constraint fk_image_zip_job_result_job
+            references image_zip_jobs
+            on delete cascade,
+    entry_name varchar(255) not null,
+    sku        varchar(64),
+    -- the product the image was attached to, null unless ATTACHED
+    product_id integer
+        constraint fk_image_zip_job_result_product
+            references products
+            on delete set null,
+    outcome    varchar(20) not null
+        constraint image_zip_job_results_outcome_check
+            check ((outcome)::text = ANY
+                   ((ARRAY ['ATTACHED'::character varying, 'NO_MATCHING_SKU'::character varying, 'REJECTED'::character varying, 'FAILED'::character varying])::text[])),
+    message    text,
+    primary key (job_id, entry_name)
+);
+
+-- what a customer's cart held when it expired, restoreExpiredCart puts it back until retention drops it
+create table expired_carts
+(
+    expired_cart_id serial
+        primary key,
+    customer_id     integer                                            not null
+        constraint fk_expired_cart_customer
+            references customers
+            on delete cascade,
+    item_count      integer                                            not null,
+    -- at the base prices of the moment it expired
+    total_value     numeric(12, 2)                                     not null,
+    last_active_at  timestamp with time zone                           not null,
+    expired_at      timestamp with time zone default CURRENT_TIMESTAMP not null,
+    restored_at     timestamp with time zone
+);
+
+create index idx_expired_carts_customer
+    on expired_carts (customer_id, expired_at);
+
+create index idx_expired_carts_expired_at
+    on expired_carts (expired_at);
+
+create table expired_cart_items
+(
+    expired_cart_id integer        not null
+        constraint fk_expired_cart_item_cart
+            references expired_carts
+            on delete cascade,
+    product_id      integer        not null
+        constraint fk_expired_cart_item_product
+            references products
+            on delete cascade,
+    quantity        integer        not null,
+    unit_price      numeric(10, 2) not null,
+    primary key (expired_cart_id, product_id)
+);
+
+-- per marketplace overrides of the request budgets, tiers without a row use the defaults from the environment
+create table rate_limit_tiers
+(
+    tenant_id           integer                                            not null
+        constraint fk_rate_limit_tier_tenant
+            references tenants,
+    tier                varchar(20)                                        not null
+        constraint rate_limit_tiers_tier_check
+            check ((tier)::text = ANY
+                   ((ARRAY ['ANONYMOUS'::character varying, 'CUSTOMER'::character varying, 'SUPPLIER'::character varying, 'PARTNER_KEY'::character varying])::text[])),
+    requests_per_minute integer                                            not null
+        constraint rate_limit_tiers_requests_check
+            check (requests_per_minute > 0),
+    updated_at          timestamp with time zone default CURRENT_TIMESTAMP not null,
+    updated_by          integer
+        constraint fk_rate_limit_tier_user
+            references users
+            on delete set null,
+    primary key (tenant_id, tier)
+);
+
+-- products a customer saved for later, a product is on a wishlist at most once
+create table wishlist_items
+(
+    customer_id integer                                            not null
+        constraint fk_wishlist_item_customer
+            references customers
+            on delete cascade,
+    product_id  integer                                            not null
+        constraint fk_wishlist_item_product
+            references products
+            on delete cascade,
+    added_at    timestamp with time zone default CURRENT_TIMESTAMP not null,
+    primary key (customer_id, product_id)
+);
+
+create index idx_wishlist_items_product
+    on wishlist_items (product_id);
+
+-- consistency scans requested by admins, worked off by the integrity check worker
+create table integrity_checks
+(
+    integrity_check_id serial
+        primary key,
+    requested_by       integer
+        constraint fk_integrity_check_user
+            references users
+            on delete set null,
+    tenant_id          integer                                            not null
+        constraint fk_integrity_check_tenant
+            references tenants,
+    status             varchar(20)              default 'PENDING'         not null
+        constraint integrity_checks_status_check
+            check ((status)::text = ANY
+                   ((ARRAY ['PENDING'::character varying, 'RUNNING'::character varying, 'DONE'::character varying, 'FAILED'::character varying])::text[])),
+    finding_count      integer,
+    error              text,
+    requested_at       timestamp with time zone default CURRENT_TIMESTAMP not null,
+    finished_at        timestamp with time zone,
+    lease_expires_at   timestamp with time zone
+);
+
+create index idx_integrity_checks_pending
+    on integrity_checks (requested_at)
+    where status IN ('PENDING', 'RUNNING');
+
+-- what a check found, nothing is repaired automatically
+create table integrity_findings
+(
+    integrity_finding_id serial
+        primary key,
+    integrity_check_id   integer      not null
+        constraint fk_integrity_finding_check
+            references integrity_checks
+            on delete cascade,
+    kind                 varchar(30)  not null,
+    table_name           varchar(60)  not null,
+    -- primary key of the row, "product_id:date" style for composite keys
+    row_ref              varchar(100) not null,
+    detail               text         not null
+);
+
+create index idx_integrity_findings_check
+    on integrity_findings (integrity_check_id, kind);
+
+-- signed commission agreements, a supplier's payouts are only released while one is in effect
+create table supplier_agreements
+(
+    agreement_id       serial
+        primary key,
+    supplier_id        integer                                            not null
+        constraint fk_agreement_supplier
+            references suppliers
+            on delete cascade,
+    version            varchar(50)                                        not null,
+    commission_percent numeric(5, 2)                                      not null,
+    -- private storage key of the signed PDF, handed out as a signed URL
+    document_key       text                                               not null,
+    effective_from     date                                               not null,
+    -- null for agreements without an end date
+    effective_until    date,
+    uploaded_by        integer
+        constraint fk_agreement_user
+            references users
+            on delete set null,
+    uploaded_at        timestamp with time zone default CURRENT_TIMESTAMP not null,
+    constraint uq_supplier_agreement_version
+        unique (supplier_id, version),
+    constraint supplier_agreements_dates_check
+        check (effective_until is null or effective_until >= effective_from)
+);
+
+create index idx_supplier_agreements_until
+    on supplier_agreements (effective_until)
+    where effective_until is not null;
+
+-- what a supplier is paid for the orders placed in a period, one row per currency. Released by an
+-- admin once payoutEligibility allows it, commission is taken at the agreement in effect then
+create table supplier_payouts
+(
+    payout_id    serial
+        primary key,
+    supplier_id  integer                                            not null
+        constraint fk_payout_supplier
+            references suppliers
+            on delete restrict,
+    agreement_id integer                                            not null
+        constraint fk_payout_agreement
+            references supplier_agreements
+            on delete restrict,
+    period_start date                                               not null,
+    period_end   date                                               not null,
+    currency     varchar(3)                                         not null,
+    gross        numeric(12, 2)                                     not null,
+    -- claw-backs of refunds that succeeded after an earlier payout, zero or negative
+    adjustments  numeric(12, 2)           default 0                 not null,
+    commission   numeric(12, 2)                                     not null,
+    amount       numeric(12, 2)                                     not null,
+    released_by  integer
+        constraint fk_payout_user
+            references users
+            on delete set null,
+    released_at  timestamp with time zone default CURRENT_TIMESTAMP not null,
+    constraint supplier_payouts_period_check
+        check (period_end >= period_start)
+);
+
+create index idx_supplier_payouts_supplier
+    on supplier_payouts (supplier_id, period_start);
+
+-- a refund that succeeds after the order was paid out, taken off the supplier's next payout in
+-- the currency. payout_id is set once a payout has absorbed it
+create table supplier_payout_adjustments
+(
+    adjustment_id serial
+        primary key,
+    supplier_id   integer                                            not null
+        constraint fk_adjustment_supplier
+            references suppliers
+            on delete restrict,
+    refund_id     integer                                            not null
+        constraint fk_adjustment_refund
+            references refunds
+            on delete restrict,
+    currency      varchar(3)                                         not null,
+    amount        numeric(12, 2)                                     not null
+        constraint supplier_payout_adjustments_amount_check
+            check (amount < (0)::numeric),
+    payout_id     integer
+        constraint fk_adjustment_payout
+            references supplier_payouts
+            on delete restrict,
+    created_at    timestamp with time zone default CURRENT_TIMESTAMP not null,
+    constraint uq_adjustment_refund_supplier
+        unique (refund_id, supplier_id)
+);
+
+create index idx_supplier_payout_adjustments_pending
+    on supplier_payout_adjustments (supplier_id)
+    where payout_id is null;
+
+-- codes customers apply to their cart, optionally limited to one category's or one supplier's products
+create table coupons
+(
+    coupon_id             serial
+        primary key,
+    tenant_id             integer                                            not null
+        constraint fk_coupon_tenant
+            references tenants,
+    code                  varchar(50)                                        not null,
+    description           text,
+    coupon_type           varchar(10)                                        not null
+        constraint coupons_type_check
+            check ((coupon_type)::text = ANY
+                   ((ARRAY ['PERCENTAGE'::character varying, 'FIXED'::character varying])::text[])),
+    -- percent off for PERCENTAGE, an amount in the store currency for FIXED
+    value                 numeric(10, 2)                                     not null,
+    -- null for no limit
+    max_uses              integer,
+    max_uses_per_customer integer,
+    times_used            integer                  default 0                 not null,
+    valid_from            timestamp with time zone,
+    valid_until           timestamp with time zone,
+    category_id           integer
+        constraint fk_coupon_category
+            references categories
+            on delete cascade,
+    supplier_id           integer
+        constraint fk_coupon_supplier
+            references suppliers
+            on delete cascade,
+    active                boolean                  default true              not null,
+    created_by            integer
+        constraint fk_coupon_user
+            references users
+            on delete set null,
+    created_at            timestamp with time zone default CURRENT_TIMESTAMP not null,
+    constraint coupons_value_check
+        check (value > 0 and (coupon_type = 'FIXED' or value <= 100)),
+    constraint coupons_limits_check
+        check ((max_uses is null or max_uses > 0) and
+               (max_uses_per_customer is null or max_uses_per_customer > 0)),
+    constraint coupons_dates_check
+        check (valid_until is null or valid_from is null or valid_until > valid_from)
+);
+
+create unique index uq_coupons_tenant_code
+    on coupons (tenant_id, upper(code::text));
+
+-- the coupon on a cart, nothing is counted against its limits until the cart is ordered
+create table cart_coupons
+(
+    cart_id    integer                                            not null
+        primary key
+        constraint fk_cart_coupon_cart
+            references shopping_carts
+            on delete cascade,
+    coupon_id  integer                                            not null
+        constraint fk_cart_coupon_coupon
+            references coupons
+            on delete cascade,
+    applied_at timestamp with time zone default CURRENT_TIMESTAMP not null
+);
+
+-- a coupon used on an order, written in the order's transaction and counted against the coupon's
+-- limits. Voiding an unpaid order drops the row
+create table coupon_redemptions
+(
+    redemption_id   serial
+        primary key,
+    coupon_id       integer                                            not null
+        constraint fk_redemption_coupon
+            references coupons
+            on delete cascade,
+    -- null for guest orders
+    customer_id     integer
+        constraint fk_redemption_customer
+            references customers
+            on delete cascade,
+    order_id        integer                                            not null
+        constraint uq_redemption_order
+            unique
+        constraint fk_redemption_order
+            references orders
+            on delete cascade,
+    discount_amount numeric(10, 2)                                     not null,
+    redeemed_at     timestamp with time zone default CURRENT_TIMESTAMP not null
+);
+
+create index idx_coupon_redemptions_coupon_customer
+    on coupon_redemptions (coupon_id, customer_id);
+
+-- copies of the tables sandbox API keys write to, everything else (users, suppliers, categories, ...)
+-- is read from public through the search path. columns added to these tables later must be added
+-- to the sandbox copies as well, they are copied column for column when a sandbox is reset.
+-- sandbox connections run as sandbox_api, see the grants at the end
+create schema sandbox;
+
+create table sandbox.products (like public.products including all);
+create table sandbox.category_product_counts (like public.category_product_counts including all);
+create table sandbox.payment_methods (like public.payment_methods including all);
+create table sandbox.orders (like public.orders including all);
+create table sandbox.order_items (like public.order_items including all);
+create table sandbox.bills (like public.bills including all);
+create table sandbox.order_status_history (like public.order_status_history including all);
+create table sandbox.order_messages (like public.order_messages including all);
+create table sandbox.order_address_changes (like public.order_address_changes including all);
+create table sandbox.order_shipments (like public.order_shipments including all);
+create table sandbox.return_requests (like public.return_requests including all);
+create table sandbox.refunds (like public.refunds including all);
+create table sandbox.refund_items (like public.refund_items including all);
+create table sandbox.product_availability (like public.product_availability including all);
+create table sandbox.product_region_prices (like public.product_region_prices including all);
+create table sandbox.product_price_history (like public.product_price_history including all);
+create table sandbox.product_attributes (like public.product_attributes including all);
+create table sandbox.product_images (like public.product_images including all);
+create table sandbox.product_image_variants (like public.product_image_variants including all);
+create table sandbox.product_videos (like public.product_videos including all);
+create table sandbox.reviews (like public.reviews including all);
+create table sandbox.review_summaries (like public.review_summaries including all);
+create table sandbox.discounts (like public.discounts including all);
+create table sandbox.coupons (like public.coupons including all);
+create table sandbox.cart_coupons (like public.cart_coupons including all);
+create table sandbox.coupon_redemptions (like public.coupon_redemptions including all);
+create table sandbox.shopping_carts (like public.shopping_carts including all);
+create table sandbox.cart_items (like public.cart_items including all);
+create table sandbox.expired_carts (like public.expired_carts including all);
+create table sandbox.expired_cart_items (like public.expired_cart_items including all);
+create table sandbox.wishlist_items (like public.wishlist_items including all);
+create table sandbox.sponsored_campaigns (like public.sponsored_campaigns including all);
+create table sandbox.sponsored_clicks (like public.sponsored_clicks including all);
+-- nothing processes sandbox events, they never reach analytics
+create table sandbox.domain_events (like public.domain_events including all);
+create table sandbox.analytics_events (like public.analytics_events including all);
+create table sandbox.sync_tombstones (like public.sync_tombstones including all);
+
+create trigger products_touch_updated_at
+    before update
+    on sandbox.products
+    for each row
+execute function touch_updated_at();
+
+create trigger products_touch_price_updated_at
+    before update
+    on sandbox.products
+    for each row
+execute function touch_price_updated_at();
+
+create trigger products_touch_sync_xid
+    before update
+    on sandbox.products
+    for each row
+execute function touch_sync_xid();
+
+create trigger products_record_sync_tombstone
+    after delete
+    on sandbox.products
+    for each row
+execute function record_sync_tombstone('PRODUCT', 'product_id');
+
+create trigger products_record_price_history
+    after insert or update
+    on sandbox.products
+    for each row
+execute function record_price_history();
+
+create trigger cart_items_touch_cart
+    after insert or update or delete
+    on sandbox.cart_items
+    for each row
+execute function touch_cart_updated_at();
+
+-- sandbox connections switch to this role. it may only read production tables, so a write to a table
+-- that has no sandbox copy fails instead of landing in production data
+create role sandbox_api nologin;
+grant sandbox_api to current_user;
+grant usage on schema public, sandbox to sandbox_api;
+grant select on all tables in schema public to sandbox_api;
+-- the copies take their ids from the production sequences, so sandbox and live ids never collide
+grant usage, select on all sequences in schema public to sandbox_api;
+grant select, insert, update, delete on all tables in schema sandbox to sandbox_api;